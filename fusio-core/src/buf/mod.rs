@@ -0,0 +1,499 @@
+//! Buffer abstraction for I/O operations.
+
+mod slice;
+
+use alloc::vec::Vec;
+use core::ops::{Bound, RangeBounds};
+
+pub use slice::*;
+
+use crate::MaybeSend;
+
+#[cfg(not(feature = "completion-based"))]
+pub unsafe trait MaybeOwned {
+    //! A trait for determining whether the buffer is owned or borrowed.
+    //! Poll-based I/O operations require the buffer to be borrowed, while completion-based I/O
+    //! operations require the buffer to be owned. This trait provides a way to abstract over
+    //! the ownership of the buffer. Users could switch between poll-based and completion-based
+    //! I/O operations at compile-time by enabling or disabling the `completion-based` feature.
+    //!
+    //! # Safety
+    //! Do not implement this trait manually.
+}
+#[cfg(not(feature = "completion-based"))]
+unsafe impl<T> MaybeOwned for T {}
+
+/// # Safety
+/// Do not implement this trait manually.
+#[cfg(feature = "completion-based")]
+pub unsafe trait MaybeOwned: 'static {}
+
+#[cfg(feature = "completion-based")]
+unsafe impl<T: 'static> MaybeOwned for T {}
+
+pub trait IoBuf: Unpin + Sized + MaybeOwned + MaybeSend {
+    //! A poll-based I/O and completion-based I/O buffer compatible buffer.
+    //! The [`IoBuf`] trait is implemented by buffer types that can be used with [`crate::Read`].
+    //! Fusio has already implemented this trait for common buffer types
+    //! like `Vec<u8>`, `&[u8]`, `&mut [u8]`, `bytes::Bytes`, `bytes::BytesMut`, every buffer type
+    //! may be not be able to be used in all async runtimes, fusio provides compile-time safety to
+    //! ensure which buffer types are compatible with the async runtime.
+
+    fn as_ptr(&self) -> *const u8;
+
+    fn bytes_init(&self) -> usize;
+
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: The buffer is pinned and the bytes are initialized.
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.bytes_init()) }
+    }
+
+    #[cfg(feature = "bytes")]
+    fn as_bytes(&self) -> bytes::Bytes {
+        bytes::Bytes::copy_from_slice(self.as_slice())
+    }
+
+    /// Slices `range` of the initialized bytes, counted from the start of this buffer. Ranges
+    /// past its end are caught by a debug assertion.
+    ///
+    /// # Safety
+    /// The buffer must be recovered from the same type of buffer before it drops.
+    unsafe fn slice_unchecked(self, range: impl RangeBounds<usize>) -> Slice;
+
+    /// # Safety
+    /// The buffer must be recovered from the same type.
+    unsafe fn recover_from_slice(buf: Slice) -> Self;
+
+    fn calculate_bounds<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.bytes_init(),
+        };
+        debug_assert!(
+            start <= end && end <= self.bytes_init(),
+            "range {start}..{end} out of bounds of a buffer of {} bytes",
+            self.bytes_init()
+        );
+        (start, end)
+    }
+}
+
+pub trait IoBufMut: IoBuf {
+    //! Mutable version of [`IoBuf`] which is used with [`crate::Write`].
+
+    fn as_mut_ptr(&mut self) -> *mut u8;
+
+    fn as_slice_mut(&mut self) -> &mut [u8] {
+        // SAFETY: The buffer is pinned and the bytes are initialized.
+        unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.bytes_init()) }
+    }
+
+    /// Mutable version of [`IoBuf::slice_unchecked`].
+    ///
+    /// # Safety
+    /// The buffer must be recovered from the same type of buffer before it drops.
+    unsafe fn slice_mut_unchecked(self, range: impl RangeBounds<usize>) -> SliceMut;
+
+    /// # Safety
+    /// The buffer must be recovered from the same type.
+    unsafe fn recover_from_slice_mut(buf: SliceMut) -> Self;
+}
+
+impl IoBuf for Vec<u8> {
+    fn as_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    unsafe fn slice_unchecked(self, range: impl RangeBounds<usize>) -> Slice {
+        let (start, end) = self.calculate_bounds(range);
+        Slice {
+            layout: SliceLayout::Vec(self),
+            start,
+            end,
+        }
+    }
+
+    unsafe fn recover_from_slice(buf: Slice) -> Self {
+        match buf.layout {
+            SliceLayout::Vec(vec) => vec,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl IoBufMut for Vec<u8> {
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        Vec::as_mut_ptr(self)
+    }
+
+    unsafe fn slice_mut_unchecked(self, range: impl RangeBounds<usize>) -> SliceMut {
+        let (start, end) = self.calculate_bounds(range);
+        SliceMut {
+            layout: SliceMutLayout::Vec(self),
+            start,
+            end,
+        }
+    }
+
+    unsafe fn recover_from_slice_mut(buf: SliceMut) -> Self {
+        match buf.layout {
+            SliceMutLayout::Vec(vec) => vec,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(not(feature = "completion-based"))]
+impl IoBuf for &[u8] {
+    fn as_ptr(&self) -> *const u8 {
+        (*self).as_ptr()
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    unsafe fn slice_unchecked(self, range: impl RangeBounds<usize>) -> Slice {
+        let (start, end) = self.calculate_bounds(range);
+        Slice {
+            layout: SliceLayout::Slice {
+                ptr: self.as_ptr() as *mut u8,
+                len: self.len(),
+            },
+            start,
+            end,
+        }
+    }
+
+    unsafe fn recover_from_slice(buf: Slice) -> Self {
+        match buf.layout {
+            SliceLayout::Slice { ptr, len } => core::slice::from_raw_parts(ptr, len),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(not(feature = "completion-based"))]
+impl IoBuf for &mut [u8] {
+    fn as_ptr(&self) -> *const u8 {
+        <[u8]>::as_ptr(self)
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    unsafe fn slice_unchecked(self, range: impl RangeBounds<usize>) -> Slice {
+        let (start, end) = self.calculate_bounds(range);
+        Slice {
+            layout: SliceLayout::Slice {
+                ptr: self.as_ptr() as *mut u8,
+                len: self.len(),
+            },
+            start,
+            end,
+        }
+    }
+
+    unsafe fn recover_from_slice(buf: Slice) -> Self {
+        match buf.layout {
+            SliceLayout::Slice { ptr, len } => core::slice::from_raw_parts_mut(ptr as *mut u8, len),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(not(feature = "completion-based"))]
+impl IoBufMut for &mut [u8] {
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        <[u8]>::as_mut_ptr(self)
+    }
+
+    unsafe fn slice_mut_unchecked(self, range: impl RangeBounds<usize>) -> SliceMut {
+        let (start, end) = self.calculate_bounds(range);
+        SliceMut {
+            layout: SliceMutLayout::Slice {
+                ptr: self.as_mut_ptr(),
+                len: self.len(),
+            },
+            start,
+            end,
+        }
+    }
+
+    unsafe fn recover_from_slice_mut(buf: SliceMut) -> Self {
+        match buf.layout {
+            SliceMutLayout::Slice { ptr, len } => core::slice::from_raw_parts_mut(ptr, len),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "completion-based")]
+impl IoBuf for &'static [u8] {
+    fn as_ptr(&self) -> *const u8 {
+        (*self).as_ptr()
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    #[cfg(feature = "bytes")]
+    fn as_bytes(&self) -> bytes::Bytes {
+        bytes::Bytes::from_static(self)
+    }
+
+    unsafe fn slice_unchecked(self, range: impl RangeBounds<usize>) -> Slice {
+        let (start, end) = self.calculate_bounds(range);
+        Slice {
+            layout: SliceLayout::Slice {
+                ptr: self.as_ptr() as *mut u8,
+                len: self.len(),
+            },
+            start,
+            end,
+        }
+    }
+
+    unsafe fn recover_from_slice(buf: Slice) -> Self {
+        match buf.layout {
+            SliceLayout::Slice { ptr, len } => core::slice::from_raw_parts(ptr, len),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl IoBuf for bytes::Bytes {
+    fn as_ptr(&self) -> *const u8 {
+        <[u8]>::as_ptr(self)
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+    fn as_bytes(&self) -> bytes::Bytes {
+        self.clone()
+    }
+
+    unsafe fn slice_unchecked(self, range: impl RangeBounds<usize>) -> Slice {
+        let (start, end) = self.calculate_bounds(range);
+        Slice {
+            layout: SliceLayout::Bytes(self),
+            start,
+            end,
+        }
+    }
+
+    unsafe fn recover_from_slice(buf: Slice) -> Self {
+        match buf.layout {
+            SliceLayout::Bytes(bytes) => bytes,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl IoBuf for bytes::BytesMut {
+    fn as_ptr(&self) -> *const u8 {
+        <[u8]>::as_ptr(self)
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    fn as_bytes(&self) -> bytes::Bytes {
+        self.clone().freeze()
+    }
+
+    unsafe fn slice_unchecked(self, range: impl RangeBounds<usize>) -> Slice {
+        let (start, end) = self.calculate_bounds(range);
+        Slice {
+            layout: SliceLayout::BytesMut(self),
+            start,
+            end,
+        }
+    }
+
+    unsafe fn recover_from_slice(buf: Slice) -> Self {
+        match buf.layout {
+            SliceLayout::BytesMut(bytes) => bytes,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl IoBufMut for bytes::BytesMut {
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        <[u8]>::as_mut_ptr(self)
+    }
+
+    unsafe fn slice_mut_unchecked(self, range: impl RangeBounds<usize>) -> SliceMut {
+        let (start, end) = self.calculate_bounds(range);
+        SliceMut {
+            layout: SliceMutLayout::BytesMut(self),
+            start,
+            end,
+        }
+    }
+
+    unsafe fn recover_from_slice_mut(buf: SliceMut) -> Self {
+        match buf.layout {
+            SliceMutLayout::BytesMut(bytes) => bytes,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use alloc::{format, vec::Vec};
+    use core::ops::Range;
+
+    use proptest::{prelude::*, test_runner::Config};
+
+    use super::{IoBuf, IoBufMut, SliceMut};
+
+    /// Few cases under Miri, which is slow but checks the pointer arithmetic, and no failure
+    /// persistence since Miri isolates the file system.
+    fn config() -> Config {
+        Config {
+            cases: if cfg!(miri) { 8 } else { 256 },
+            failure_persistence: None,
+            ..Config::default()
+        }
+    }
+
+    /// A buffer and two nested ranges, the second relative to the first.
+    fn nested() -> impl Strategy<Value = (Vec<u8>, Range<usize>, Range<usize>)> {
+        prop::collection::vec(any::<u8>(), 0..64)
+            .prop_flat_map(|buf| {
+                let len = buf.len();
+                (Just(buf), 0..=len, 0..=len)
+            })
+            .prop_flat_map(|(buf, a, b)| {
+                let outer = a.min(b)..a.max(b);
+                let len = outer.len();
+                (Just(buf), Just(outer), 0..=len, 0..=len)
+            })
+            .prop_map(|(buf, outer, a, b)| (buf, outer, a.min(b)..a.max(b)))
+    }
+
+    fn expected(buf: &[u8], outer: &Range<usize>, inner: &Range<usize>) -> Vec<u8> {
+        buf[outer.clone()][inner.clone()].to_vec()
+    }
+
+    proptest! {
+        #![proptest_config(config())]
+
+        #[test]
+        fn vec_slices_round_trip((buf, outer, inner) in nested()) {
+            let owned = buf.clone();
+            let ptr = owned.as_ptr();
+            let slice = unsafe { owned.slice_unchecked(outer.clone()) };
+            prop_assert_eq!(slice.as_slice(), &buf[outer.clone()]);
+            let slice = unsafe { slice.slice_unchecked(inner.clone()) };
+            prop_assert_eq!(slice.as_slice(), &expected(&buf, &outer, &inner)[..]);
+
+            let recovered = unsafe { Vec::<u8>::recover_from_slice(slice) };
+            prop_assert_eq!(recovered.as_ptr(), ptr);
+            prop_assert_eq!(recovered, buf);
+        }
+
+        #[cfg(not(feature = "completion-based"))]
+        #[test]
+        fn borrowed_slices_round_trip((buf, outer, inner) in nested()) {
+            let slice = unsafe { buf.as_slice().slice_unchecked(outer.clone()) };
+            let slice = unsafe { slice.slice_unchecked(inner.clone()) };
+            prop_assert_eq!(slice.as_slice(), &expected(&buf, &outer, &inner)[..]);
+            let recovered = unsafe { <&[u8]>::recover_from_slice(slice) };
+            prop_assert_eq!(recovered.as_ptr(), buf.as_ptr());
+            prop_assert_eq!(recovered, &buf[..]);
+        }
+
+        #[test]
+        fn mutable_slices_write_in_place((buf, outer, inner) in nested()) {
+            let slice = unsafe { buf.clone().slice_mut_unchecked(outer.clone()) };
+            let mut slice = unsafe { slice.slice_mut_unchecked(inner.clone()) };
+            prop_assert_eq!(slice.bytes_init(), inner.len());
+            slice.as_slice_mut().fill(0xff);
+            let written = unsafe { Vec::<u8>::recover_from_slice_mut(slice) };
+
+            let mut expected = buf.clone();
+            expected[outer.start + inner.start..outer.start + inner.end].fill(0xff);
+            prop_assert_eq!(written, expected);
+        }
+
+        #[cfg(not(feature = "completion-based"))]
+        #[test]
+        fn borrowed_mutable_slices_write_in_place((buf, outer, inner) in nested()) {
+            let mut written = buf.clone();
+            let slice = unsafe { written.as_mut_slice().slice_mut_unchecked(outer.clone()) };
+            let mut slice = unsafe { slice.slice_mut_unchecked(inner.clone()) };
+            slice.as_slice_mut().fill(0xff);
+            let recovered = unsafe { <&mut [u8]>::recover_from_slice_mut(slice) };
+            prop_assert_eq!(recovered.len(), buf.len());
+
+            let mut expected = buf.clone();
+            expected[outer.start + inner.start..outer.start + inner.end].fill(0xff);
+            prop_assert_eq!(written, expected);
+        }
+
+        #[test]
+        fn mutable_slices_recover_through_immutable((buf, outer, inner) in nested()) {
+            let slice = unsafe { buf.clone().slice_mut_unchecked(outer.clone()) };
+            let slice = unsafe { slice.slice_unchecked(inner.clone()) };
+            prop_assert_eq!(slice.as_slice(), &expected(&buf, &outer, &inner)[..]);
+            let slice = unsafe { SliceMut::recover_from_slice(slice) };
+            prop_assert_eq!(slice.as_slice(), &expected(&buf, &outer, &inner)[..]);
+            prop_assert_eq!(unsafe { Vec::<u8>::recover_from_slice_mut(slice) }, buf);
+        }
+    }
+
+    #[cfg(feature = "bytes")]
+    proptest! {
+        #![proptest_config(config())]
+
+        #[test]
+        fn bytes_slices_round_trip((buf, outer, inner) in nested()) {
+            let bytes = bytes::Bytes::from(buf.clone());
+            let slice = unsafe { bytes.clone().slice_unchecked(outer.clone()) };
+            let slice = unsafe { slice.slice_unchecked(inner.clone()) };
+            prop_assert_eq!(slice.as_slice(), &expected(&buf, &outer, &inner)[..]);
+            prop_assert_eq!(&slice.as_bytes()[..], &expected(&buf, &outer, &inner)[..]);
+            prop_assert_eq!(unsafe { bytes::Bytes::recover_from_slice(slice) }, bytes);
+
+            let mut written = bytes::BytesMut::from(&buf[..]);
+            written = {
+                let slice = unsafe { written.slice_mut_unchecked(outer.clone()) };
+                let mut slice = unsafe { slice.slice_mut_unchecked(inner.clone()) };
+                slice.as_slice_mut().fill(0xff);
+                unsafe { bytes::BytesMut::recover_from_slice_mut(slice) }
+            };
+            let mut expected = buf.clone();
+            expected[outer.start + inner.start..outer.start + inner.end].fill(0xff);
+            prop_assert_eq!(&written[..], &expected[..]);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn slicing_past_the_end_panics_in_debug_builds() {
+        let slice = unsafe { alloc::vec![0u8; 4].slice_unchecked(1..3) };
+        let _ = unsafe { slice.slice_unchecked(1..3) };
+    }
+}
@@ -1,7 +1,28 @@
-use std::ops::RangeBounds;
+use alloc::vec::Vec;
+use core::ops::{Bound, RangeBounds};
 
 use crate::{IoBuf, IoBufMut};
 
+/// The bounds of `range`, relative to the slice `start..end`, within the underlying buffer.
+fn sub_bounds(start: usize, end: usize, range: impl RangeBounds<usize>) -> (usize, usize) {
+    let len = end - start;
+    let sub_start = match range.start_bound() {
+        Bound::Included(&sub_start) => sub_start,
+        Bound::Excluded(&sub_start) => sub_start + 1,
+        Bound::Unbounded => 0,
+    };
+    let sub_end = match range.end_bound() {
+        Bound::Included(&sub_end) => sub_end + 1,
+        Bound::Excluded(&sub_end) => sub_end,
+        Bound::Unbounded => len,
+    };
+    debug_assert!(
+        sub_start <= sub_end && sub_end <= len,
+        "range {sub_start}..{sub_end} out of bounds of a slice of {len} bytes"
+    );
+    (start + sub_start, start + sub_end)
+}
+
 pub struct Slice {
     pub(super) layout: SliceLayout,
     pub(super) start: usize,
@@ -36,21 +57,14 @@ impl IoBuf for Slice {
     }
 
     fn bytes_init(&self) -> usize {
-        match &self.layout {
-            SliceLayout::Slice { len, .. } => *len - self.start,
-            SliceLayout::Vec(vec) => vec.len() - self.start,
-            #[cfg(feature = "bytes")]
-            SliceLayout::Bytes(bytes) => bytes.len() - self.start,
-            #[cfg(feature = "bytes")]
-            SliceLayout::BytesMut(bytes) => bytes.len() - self.start,
-        }
+        self.end - self.start
     }
 
     #[cfg(feature = "bytes")]
     fn as_bytes(&self) -> bytes::Bytes {
         match &self.layout {
             SliceLayout::Slice { ptr, .. } => bytes::Bytes::copy_from_slice(unsafe {
-                std::slice::from_raw_parts((*ptr).add(self.start), self.end - self.start)
+                core::slice::from_raw_parts((*ptr).add(self.start), self.end - self.start)
             }),
             SliceLayout::Vec(vec) => bytes::Bytes::copy_from_slice(&vec[self.start..self.end]),
             #[cfg(feature = "bytes")]
@@ -61,16 +75,7 @@ impl IoBuf for Slice {
     }
 
     unsafe fn slice_unchecked(self, range: impl RangeBounds<usize>) -> Slice {
-        let start = match range.start_bound() {
-            std::ops::Bound::Included(&start) => start,
-            std::ops::Bound::Excluded(&start) => start + 1,
-            std::ops::Bound::Unbounded => self.start,
-        };
-        let end = match range.end_bound() {
-            std::ops::Bound::Included(&end) => end + 1,
-            std::ops::Bound::Excluded(&end) => end,
-            std::ops::Bound::Unbounded => self.end,
-        };
+        let (start, end) = sub_bounds(self.start, self.end, range);
         Slice {
             layout: self.layout,
             start,
@@ -114,19 +119,14 @@ impl IoBuf for SliceMut {
     }
 
     fn bytes_init(&self) -> usize {
-        match &self.layout {
-            SliceMutLayout::Slice { len, .. } => *len - self.start,
-            SliceMutLayout::Vec(vec) => vec.len() - self.start,
-            #[cfg(feature = "bytes")]
-            SliceMutLayout::BytesMut(bytes) => bytes.len() - self.start,
-        }
+        self.end - self.start
     }
 
     #[cfg(feature = "bytes")]
     fn as_bytes(&self) -> bytes::Bytes {
         match &self.layout {
             SliceMutLayout::Slice { ptr, .. } => bytes::Bytes::copy_from_slice(unsafe {
-                std::slice::from_raw_parts((*ptr).add(self.start), self.end - self.start)
+                core::slice::from_raw_parts((*ptr).add(self.start), self.end - self.start)
             }),
             SliceMutLayout::Vec(vec) => bytes::Bytes::copy_from_slice(&vec[self.start..self.end]),
             #[cfg(feature = "bytes")]
@@ -135,16 +135,7 @@ impl IoBuf for SliceMut {
     }
 
     unsafe fn slice_unchecked(self, range: impl RangeBounds<usize>) -> Slice {
-        let start = match range.start_bound() {
-            std::ops::Bound::Included(&start) => start,
-            std::ops::Bound::Excluded(&start) => start + 1,
-            std::ops::Bound::Unbounded => self.start,
-        };
-        let end = match range.end_bound() {
-            std::ops::Bound::Included(&end) => end + 1,
-            std::ops::Bound::Excluded(&end) => end,
-            std::ops::Bound::Unbounded => self.end,
-        };
+        let (start, end) = sub_bounds(self.start, self.end, range);
         match self.layout {
             SliceMutLayout::Slice { ptr, len } => Slice {
                 layout: SliceLayout::Slice {
@@ -206,16 +197,7 @@ impl IoBufMut for SliceMut {
     }
 
     unsafe fn slice_mut_unchecked(self, range: impl RangeBounds<usize>) -> SliceMut {
-        let start = match range.start_bound() {
-            std::ops::Bound::Included(&start) => start,
-            std::ops::Bound::Excluded(&start) => start + 1,
-            std::ops::Bound::Unbounded => self.start,
-        };
-        let end = match range.end_bound() {
-            std::ops::Bound::Included(&end) => end + 1,
-            std::ops::Bound::Excluded(&end) => end,
-            std::ops::Bound::Unbounded => self.end,
-        };
+        let (start, end) = sub_bounds(self.start, self.end, range);
         SliceMut {
             layout: self.layout,
             start,
@@ -0,0 +1,102 @@
+use alloc::{boxed::Box, string::String};
+use core::fmt;
+
+use crate::path;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    PathError(path::Error),
+    Unsupported {
+        message: String,
+    },
+    UnexpectedEof {
+        requested: u64,
+        available: u64,
+    },
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// The object is archived, e.g. in S3 Glacier, and has to be restored before it can be read.
+    NotRestored {
+        path: path::Path,
+    },
+    /// The file was opened with `create_new` but already exists, or was created by someone else
+    /// before the object was stored.
+    AlreadyExists {
+        path: path::Path,
+    },
+    /// A precondition on the ETag of the object did not hold: it changed since the ETag given to
+    /// `If-Match` was taken, or it still has the ETag given to `If-None-Match`.
+    PreconditionFailed {
+        path: path::Path,
+    },
+    Other(BoxedError),
+}
+
+pub type BoxedError = Box<dyn core::error::Error + Send + Sync + 'static>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            Error::Io(e) => e.fmt(f),
+            Error::PathError(e) => e.fmt(f),
+            Error::Unsupported { message } => write!(f, "unsupported operation: {message}"),
+            Error::UnexpectedEof {
+                requested,
+                available,
+            } => write!(
+                f,
+                "unexpected end of file: requested {requested} bytes, {available} available"
+            ),
+            Error::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {expected:#010x}, found {actual:#010x}"
+            ),
+            Error::NotRestored { path } => {
+                write!(f, "object {path} is archived and has not been restored")
+            }
+            Error::AlreadyExists { path } => write!(f, "{path} already exists"),
+            Error::PreconditionFailed { path } => {
+                write!(f, "precondition on the ETag of {path} failed")
+            }
+            Error::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        // wrapped errors are transparent, they are displayed in place of this error
+        match self {
+            #[cfg(feature = "std")]
+            Error::Io(e) => e.source(),
+            Error::PathError(e) => e.source(),
+            Error::Other(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<path::Error> for Error {
+    fn from(e: path::Error) -> Self {
+        Error::PathError(e)
+    }
+}
+
+impl From<BoxedError> for Error {
+    fn from(e: BoxedError) -> Self {
+        Error::Other(e)
+    }
+}
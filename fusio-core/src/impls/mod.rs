@@ -0,0 +1,64 @@
+//! Implementations of the core traits for types of `alloc`, `std` and, behind their features,
+//! other crates whose types cannot be implemented for outside of this crate.
+
+#[cfg(feature = "tokio")]
+mod tokio;
+
+use alloc::vec::Vec;
+
+use crate::{Error, IoBufMut, Read};
+#[cfg(feature = "std")]
+use crate::{IoBuf, Write};
+
+impl Read for &mut Vec<u8> {
+    async fn read_exact_at<B: IoBufMut>(&mut self, mut buf: B, pos: u64) -> (Result<(), Error>, B) {
+        let len = buf.bytes_init();
+        let range = usize::try_from(pos)
+            .ok()
+            .and_then(|pos| Some(pos..pos.checked_add(len)?));
+        let Some(range) = range.filter(|range| range.end <= self.len()) else {
+            return (
+                Err(Error::UnexpectedEof {
+                    requested: len as u64,
+                    available: (self.len() as u64).saturating_sub(pos),
+                }),
+                buf,
+            );
+        };
+        buf.as_slice_mut().copy_from_slice(&self[range]);
+        (Ok(()), buf)
+    }
+
+    async fn read_to_end_at(&mut self, mut buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        let pos = usize::try_from(pos).map_or(self.len(), |pos| pos.min(self.len()));
+        buf.extend_from_slice(&self[pos..]);
+        (Ok(()), buf)
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        Ok(self.len() as u64)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for std::io::Cursor<&mut Vec<u8>> {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        (
+            std::io::Write::write_all(self, buf.as_slice()).map_err(Error::Io),
+            buf,
+        )
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Succeeds, writes to memory are ordered already.
+    async fn barrier(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
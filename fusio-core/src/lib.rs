@@ -0,0 +1,288 @@
+//! The core traits of [fusio](https://docs.rs/fusio): [`Read`], [`Write`], the buffers they
+//! take ([`IoBuf`], [`IoBufMut`]), [`path::Path`] and [`Error`].
+//!
+//! This crate is `no_std` and only needs `alloc`, so storage on embedded or kernel-adjacent
+//! targets can implement the traits without pulling in an async runtime or HTTP client.
+//! The `std` feature, enabled by default, adds `std::io::Error` to [`Error`] and conversions
+//! between [`path::Path`] and local filesystem paths.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod buf;
+mod error;
+mod impls;
+pub mod path;
+
+use alloc::{vec, vec::Vec};
+use core::future::Future;
+
+pub use buf::{IoBuf, IoBufMut};
+pub use error::{BoxedError, Error};
+
+#[cfg(not(feature = "no-send"))]
+pub unsafe trait MaybeSend: Send {
+    //! Considering lots of runtimes does not require [`core::marker::Send`] for
+    //! [`core::future::Future`] and `futures_core::Stream`, we provide a trait to
+    //! represent the future or stream that may not require [`core::marker::Send`]. Users could
+    //! switch the feature `no-send` at compile-time to disable the [`core::marker::Send`] bound
+    //! for [`core::future::Future`] and `futures_core::Stream`.
+    //!
+    //! # Safety
+    //! Do not implement it directly.
+}
+
+/// # Safety
+/// Do not implement it directly
+#[cfg(feature = "no-send")]
+pub unsafe trait MaybeSend {}
+
+#[cfg(not(feature = "no-send"))]
+unsafe impl<T: Send> MaybeSend for T {}
+#[cfg(feature = "no-send")]
+unsafe impl<T> MaybeSend for T {}
+
+#[cfg(not(feature = "no-send"))]
+pub unsafe trait MaybeSync: Sync {
+    //! Same as [`MaybeSend`], but for [`core::marker::Sync`].
+    //!
+    //! # Safety
+    //! Do not implement it directly.
+}
+
+/// # Safety
+/// Do not implement it directly
+#[cfg(feature = "no-send")]
+pub unsafe trait MaybeSync {}
+
+#[cfg(not(feature = "no-send"))]
+unsafe impl<T: Sync> MaybeSync for T {}
+#[cfg(feature = "no-send")]
+unsafe impl<T> MaybeSync for T {}
+
+pub trait Write: MaybeSend {
+    //! The core trait for writing data,
+    //! it is similar to `std::io::Write`, but it takes the ownership of the buffer,
+    //! because completion-based IO requires the buffer to be pinned and should be safe to
+    //! cancellation.
+    //!
+    //! [`Write`] represents "sequential write all and overwrite" semantics,
+    //! which means each buffer will be written to the file sequentially and overwrite the previous
+    //! file when closed.
+    //!
+    //! Contents are not be garanteed to be written to the file until the [`Write::close`] method is
+    //! called, [`Write::flush`] may be used to flush the data to the file in some
+    //! implementations, but not all implementations will do so.
+    //!
+    //! Whether the operation is successful or not, the buffer will be returned,
+    //! fusio promises that the returned buffer will be the same as the input buffer.
+    //!
+    //! # Dyn Compatibility
+    //! This trait is not dyn compatible.
+    //! If you want to use [`Write`] trait in a dynamic way, you could use `fusio::DynWrite`.
+
+    fn write_all<B: IoBuf>(
+        &mut self,
+        buf: B,
+    ) -> impl Future<Output = (Result<(), Error>, B)> + MaybeSend;
+
+    fn flush(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend;
+
+    fn close(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend;
+
+    /// Writes every buffer of `bufs` in order, like calling [`Write::write_all`] for each of them.
+    /// Backends that can submit several writes at once, like io_uring, override it to save a
+    /// round trip per buffer. Object store writers keep the default, as they already collect
+    /// writes into parts that are uploaded concurrently.
+    ///
+    /// On error, the file only advances past the buffers before the failed one. Backends that
+    /// submit the writes together may have written later buffers as well.
+    fn write_batch<B: IoBuf>(
+        &mut self,
+        bufs: Vec<B>,
+    ) -> impl Future<Output = (Result<(), Error>, Vec<B>)> + MaybeSend {
+        async move {
+            let mut written = Vec::with_capacity(bufs.len());
+            let mut bufs = bufs.into_iter();
+            while let Some(buf) = bufs.next() {
+                let (result, buf) = self.write_all(buf).await;
+                written.push(buf);
+                if let Err(e) = result {
+                    written.extend(bufs);
+                    return (Err(e), written);
+                }
+            }
+            (Ok(()), written)
+        }
+    }
+
+    /// Makes every write submitted before the barrier durable before any write after it is
+    /// issued, so a journal can order its writes, e.g. a commit record after the entries it
+    /// commits, without closing the file or waiting for each write.
+    ///
+    /// Local files sync their data like `fdatasync`. Backends that cannot make writes durable
+    /// before the file is closed, like object stores, fail with [`Error::Unsupported`].
+    ///
+    /// **The default implementation fails with [`Error::Unsupported`] as well**, since a flush
+    /// does not make writes durable on most backends. Writers that can order their writes must
+    /// override it, those where a flush suffices by returning [`Write::flush`].
+    fn barrier(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend {
+        async {
+            Err(Error::Unsupported {
+                message: "this writer does not implement barrier".into(),
+            })
+        }
+    }
+}
+
+pub trait Read: MaybeSend + MaybeSync {
+    //! The core trait for reading data,
+    //! it is similar to `std::io::Read`,
+    //! but it takes the ownership of the buffer,
+    //! because completion-based IO requires the buffer to be pinned and should be safe to
+    //! cancellation.
+    //!
+    //! [`Read`] represents "random exactly read" semantics,
+    //! which means the read operation will start at the specified position, and the buffer will be
+    //! exactly filled with the data read.
+    //!
+    //! The buffer will be returned with the result, whether the operation is successful or not,
+    //! fusio promises that the returned buffer will be the same as the input buffer.
+    //!
+    //! If you want sequential reading, try `fusio::SeqRead`.
+    //!
+    //! # Dyn Compatibility
+    //! This trait is not dyn compatible.
+    //! If you want to use [`Read`] trait in a dynamic way, you could use `fusio::DynRead`.
+
+    fn read_exact_at<B: IoBufMut>(
+        &mut self,
+        buf: B,
+        pos: u64,
+    ) -> impl Future<Output = (Result<(), Error>, B)> + MaybeSend;
+
+    fn read_to_end_at(
+        &mut self,
+        buf: Vec<u8>,
+        pos: u64,
+    ) -> impl Future<Output = (Result<(), Error>, Vec<u8>)> + MaybeSend;
+
+    fn size(&self) -> impl Future<Output = Result<u64, Error>> + MaybeSend;
+
+    /// Reads up to `buf.bytes_init()` bytes at `pos` and returns how many were read, which is
+    /// only less than requested at the end of the file. Reading at or past the end returns `0`.
+    ///
+    /// [`Read::read_exact_at`] fails with [`Error::UnexpectedEof`] in the same situation.
+    fn read_at<B: IoBufMut>(
+        &mut self,
+        buf: B,
+        pos: u64,
+    ) -> impl Future<Output = (Result<usize, Error>, B)> + MaybeSend {
+        async move {
+            let size = match self.size().await {
+                Ok(size) => size,
+                Err(e) => return (Err(e), buf),
+            };
+            let len = (buf.bytes_init() as u64).min(size.saturating_sub(pos)) as usize;
+            if len == 0 {
+                return (Ok(0), buf);
+            }
+
+            // SAFETY: the slice is recovered into the same buffer type right below
+            let (result, slice) = self
+                .read_exact_at(unsafe { buf.slice_mut_unchecked(..len) }, pos)
+                .await;
+            (result.map(|_| len), unsafe {
+                B::recover_from_slice_mut(slice)
+            })
+        }
+    }
+
+    /// Reads every `(pos, len)` range exactly and returns their content in the same order. Reading
+    /// past the end fails with [`Error::UnexpectedEof`] like [`Read::read_exact_at`].
+    ///
+    /// Backends override it to issue the reads together, e.g. as concurrent range requests, which
+    /// saves the latency of one round trip per range for workloads reading many small pieces.
+    fn read_batch(
+        &mut self,
+        ranges: Vec<(u64, usize)>,
+    ) -> impl Future<Output = Result<Vec<Vec<u8>>, Error>> + MaybeSend {
+        async move {
+            let mut bufs = Vec::with_capacity(ranges.len());
+            for (pos, len) in ranges {
+                let (result, buf) = self.read_exact_at(vec![0; len], pos).await;
+                result?;
+                bufs.push(buf);
+            }
+            Ok(bufs)
+        }
+    }
+}
+
+impl<R: Read> Read for &mut R {
+    fn read_exact_at<B: IoBufMut>(
+        &mut self,
+        buf: B,
+        pos: u64,
+    ) -> impl Future<Output = (Result<(), Error>, B)> + MaybeSend {
+        R::read_exact_at(self, buf, pos)
+    }
+
+    fn read_to_end_at(
+        &mut self,
+        buf: Vec<u8>,
+        pos: u64,
+    ) -> impl Future<Output = (Result<(), Error>, Vec<u8>)> + MaybeSend {
+        R::read_to_end_at(self, buf, pos)
+    }
+
+    fn size(&self) -> impl Future<Output = Result<u64, Error>> + MaybeSend {
+        R::size(self)
+    }
+
+    fn read_at<B: IoBufMut>(
+        &mut self,
+        buf: B,
+        pos: u64,
+    ) -> impl Future<Output = (Result<usize, Error>, B)> + MaybeSend {
+        R::read_at(self, buf, pos)
+    }
+
+    fn read_batch(
+        &mut self,
+        ranges: Vec<(u64, usize)>,
+    ) -> impl Future<Output = Result<Vec<Vec<u8>>, Error>> + MaybeSend {
+        R::read_batch(self, ranges)
+    }
+}
+
+impl<W: Write> Write for &mut W {
+    fn write_all<B: IoBuf>(
+        &mut self,
+        buf: B,
+    ) -> impl Future<Output = (Result<(), Error>, B)> + MaybeSend {
+        W::write_all(self, buf)
+    }
+
+    fn flush(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend {
+        W::flush(self)
+    }
+
+    fn close(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend {
+        W::close(self)
+    }
+
+    fn write_batch<B: IoBuf>(
+        &mut self,
+        bufs: Vec<B>,
+    ) -> impl Future<Output = (Result<(), Error>, Vec<B>)> + MaybeSend {
+        W::write_batch(self, bufs)
+    }
+
+    fn barrier(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend {
+        W::barrier(self)
+    }
+}
@@ -1,10 +1,16 @@
 //! A path abstraction that can be used to represent paths in a cloud-agnostic way.
 
-use std::{fmt::Formatter, path::PathBuf};
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use core::fmt::{self, Formatter};
+#[cfg(feature = "std")]
+use std::path::PathBuf;
 
 use itertools::Itertools;
 use percent_encoding::percent_decode;
-use thiserror::Error;
+#[cfg(feature = "std")]
 use url::Url;
 
 /// The delimiter to separate object namespaces, creating a directory structure.
@@ -13,33 +19,96 @@ pub const DELIMITER: &str = "/";
 /// The path delimiter as a single byte
 pub const DELIMITER_BYTE: u8 = DELIMITER.as_bytes()[0];
 
+/// The leading segment marking a Windows UNC path (`\\server\share\...`), which is represented
+/// as `UNC/server/share/...`.
+#[cfg(target_os = "windows")]
+const UNC_PREFIX: &str = "UNC";
+
 mod parts;
 
 pub use parts::{InvalidPart, PathPart};
 
-#[derive(Debug, Error)]
-#[error(transparent)]
+#[derive(Debug)]
 pub enum Error {
-    #[error("Path \"{path}\" contained empty path segment")]
-    EmptySegment { path: String },
-    #[error("Error parsing Path \"{path}\": {source}")]
-    BadSegment { path: String, source: InvalidPart },
-    #[error("Failed to canonicalize path \"{path}\": {source}")]
+    EmptySegment {
+        path: String,
+    },
+    BadSegment {
+        path: String,
+        source: InvalidPart,
+    },
+    #[cfg(feature = "std")]
     Canonicalize {
         path: std::path::PathBuf,
         source: std::io::Error,
     },
-    #[error("Unable to convert path \"{path}\" to URL")]
-    InvalidPath { path: PathBuf },
-    #[error("Unable to convert url \"{url}\" to Path")]
-    InvalidUrl { url: Url },
-    #[error("Path \"{path}\" contained non-unicode characters: {source}")]
+    #[cfg(feature = "std")]
+    InvalidPath {
+        path: PathBuf,
+    },
+    #[cfg(feature = "std")]
+    InvalidUrl {
+        url: Url,
+    },
     NonUnicode {
         path: String,
-        source: std::str::Utf8Error,
+        source: core::str::Utf8Error,
     },
-    #[error("Path {path} does not start with prefix {prefix}")]
-    PrefixMismatch { path: String, prefix: String },
+    PrefixMismatch {
+        path: String,
+        prefix: String,
+    },
+    PolicyViolation {
+        path: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::EmptySegment { path } => {
+                write!(f, "Path \"{path}\" contained empty path segment")
+            }
+            Error::BadSegment { path, source } => {
+                write!(f, "Error parsing Path \"{path}\": {source}")
+            }
+            #[cfg(feature = "std")]
+            Error::Canonicalize { path, source } => write!(
+                f,
+                "Failed to canonicalize path \"{}\": {source}",
+                path.display()
+            ),
+            #[cfg(feature = "std")]
+            Error::InvalidPath { path } => {
+                write!(f, "Unable to convert path \"{}\" to URL", path.display())
+            }
+            #[cfg(feature = "std")]
+            Error::InvalidUrl { url } => write!(f, "Unable to convert url \"{url}\" to Path"),
+            Error::NonUnicode { path, source } => write!(
+                f,
+                "Path \"{path}\" contained non-unicode characters: {source}"
+            ),
+            Error::PrefixMismatch { path, prefix } => {
+                write!(f, "Path {path} does not start with prefix {prefix}")
+            }
+            Error::PolicyViolation { path, reason } => {
+                write!(f, "Path \"{path}\" violates the path policy: {reason}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::BadSegment { source, .. } => Some(source),
+            #[cfg(feature = "std")]
+            Error::Canonicalize { source, .. } => Some(source),
+            Error::NonUnicode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Ord, PartialOrd)]
@@ -48,6 +117,12 @@ pub struct Path {
 }
 
 impl Path {
+    /// Parses an object key verbatim.
+    ///
+    /// Characters such as spaces, `+`, `#`, `?` and non-ASCII are kept as-is, so the result
+    /// refers to exactly the given key. Empty segments (e.g. `a//b`) and `.`/`..` segments are
+    /// rejected. Use [`Path::from`] to build a path from arbitrary strings instead, which
+    /// percent-encodes characters that are unsafe in object keys.
     pub fn parse(path: impl AsRef<str>) -> Result<Self, Error> {
         let path = path.as_ref();
 
@@ -75,6 +150,11 @@ impl Path {
         })
     }
 
+    #[cfg(feature = "std")]
+    /// Converts a local filesystem path into a [`Path`], resolving it to an absolute path first.
+    ///
+    /// On Windows both `\\` and `/` separators are accepted, drive letters are kept as the first
+    /// segment (`C:/dir/file`) and UNC paths are represented as `UNC/server/share/...`.
     pub fn from_filesystem_path(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
         let absolute = std::fs::canonicalize(&path).map_err(|err| Error::Canonicalize {
             path: path.as_ref().to_path_buf(),
@@ -84,15 +164,27 @@ impl Path {
         Self::from_absolute_path(absolute)
     }
 
+    #[cfg(feature = "std")]
     pub fn from_absolute_path(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
         Self::from_absolute_path_with_base(path, None)
     }
 
+    #[cfg(feature = "std")]
     pub(crate) fn from_absolute_path_with_base(
         path: impl AsRef<std::path::Path>,
-        base: Option<&url::Url>,
+        base: Option<&Url>,
     ) -> Result<Self, Error> {
         let url = absolute_path_to_url(path)?;
+
+        // `\\server\share\dir` is converted to `file://server/share/dir`, keep the server
+        #[cfg(target_os = "windows")]
+        if base.is_none() {
+            if let Some(host) = url.host_str().filter(|host| !host.is_empty()) {
+                let path = Self::from_url_path(url.path())?;
+                return Self::parse(format!("{UNC_PREFIX}{DELIMITER}{host}{DELIMITER}{path}"));
+            }
+        }
+
         let path = match base {
             Some(prefix) => {
                 url.path()
@@ -161,6 +253,12 @@ impl Path {
         self.prefix_match(prefix).is_some()
     }
 
+    #[cfg(feature = "std")]
+    /// Converts this path back into a local filesystem path, see [`path_to_local`].
+    pub fn to_filesystem_path(&self) -> Result<PathBuf, Error> {
+        path_to_local(self)
+    }
+
     pub fn child<'a>(&self, child: impl Into<PathPart<'a>>) -> Self {
         let raw = match self.raw.is_empty() {
             true => format!("{}", child.into().raw),
@@ -209,8 +307,8 @@ impl From<Path> for String {
     }
 }
 
-impl std::fmt::Display for Path {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         self.raw.fmt(f)
     }
 }
@@ -230,13 +328,35 @@ where
     }
 }
 
+#[cfg(feature = "std")]
 pub(crate) fn absolute_path_to_url(path: impl AsRef<std::path::Path>) -> Result<Url, Error> {
     Url::from_file_path(&path).map_err(|_| Error::InvalidPath {
         path: path.as_ref().into(),
     })
 }
 
+#[cfg(feature = "std")]
 pub fn path_to_local(location: &Path) -> Result<PathBuf, Error> {
+    #[cfg(target_os = "windows")]
+    {
+        let mut parts = location.parts();
+        if parts.next().as_ref().map(PathPart::as_ref) == Some(UNC_PREFIX) {
+            let host = parts.next().ok_or_else(|| Error::InvalidPath {
+                path: PathBuf::from(location.as_ref()),
+            })?;
+            let mut url = Url::parse(&format!("file://{}/", host.as_ref())).map_err(|_| {
+                Error::InvalidPath {
+                    path: PathBuf::from(location.as_ref()),
+                }
+            })?;
+            url.path_segments_mut()
+                .expect("url path")
+                .pop_if_empty()
+                .extend(parts);
+            return url.to_file_path().map_err(|_| Error::InvalidUrl { url });
+        }
+    }
+
     let mut url = Url::parse("file:///").unwrap();
     url.path_segments_mut()
         .expect("url path")
@@ -263,8 +383,9 @@ pub fn path_to_local(location: &Path) -> Result<PathBuf, Error> {
     Ok(path)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
+    use alloc::{vec, vec::Vec};
     use std::fs::canonicalize;
 
     use tempfile::NamedTempFile;
@@ -469,6 +590,17 @@ mod tests {
         assert_eq!(b.raw, c.raw);
     }
 
+    #[test]
+    fn parse_keeps_special_characters() {
+        for key in ["foo bar", "a+b", "a#b", "a?b=c", "dir/文件.txt", "emoji/🦀"] {
+            let path = Path::parse(key).unwrap();
+            assert_eq!(path.as_ref(), key);
+        }
+
+        let path = Path::from("a#b/c?d");
+        assert_eq!(path.as_ref(), "a%23b/c%3Fd");
+    }
+
     #[test]
     fn from_url_path() {
         let a = Path::from_url_path("foo%20bar").unwrap();
@@ -518,5 +650,28 @@ mod tests {
         let std_path = path_to_local(&this_path).unwrap();
 
         assert_eq!(std_path, canonicalize(temp_file.path()).unwrap());
+        assert_eq!(this_path.to_filesystem_path().unwrap(), std_path);
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn windows_drive_letter() {
+        let path = Path::from_absolute_path("C:\\data\\sub dir/file.txt").unwrap();
+        assert_eq!(path.as_ref(), "C:/data/sub dir/file.txt");
+        assert_eq!(
+            path_to_local(&path).unwrap(),
+            PathBuf::from("C:\\data\\sub dir\\file.txt")
+        );
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn windows_unc_path() {
+        let path = Path::from_absolute_path("\\\\server\\share\\dir\\file.txt").unwrap();
+        assert_eq!(path.as_ref(), "UNC/server/share/dir/file.txt");
+        assert_eq!(
+            path_to_local(&path).unwrap(),
+            PathBuf::from("\\\\server\\share\\dir\\file.txt")
+        );
     }
 }
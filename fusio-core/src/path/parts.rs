@@ -1,21 +1,32 @@
-use std::borrow::Cow;
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+};
+use core::fmt;
 
 use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
-use thiserror::Error;
 
 use crate::path::DELIMITER_BYTE;
 
-#[derive(Debug, Error)]
-#[error(
-    "Encountered illegal character sequence \"{segment}\" whilst parsing path segment \
-     \"{illegal}\""
-)]
+#[derive(Debug)]
 #[allow(missing_copy_implementations)]
 pub struct InvalidPart {
     segment: String,
     illegal: String,
 }
 
+impl fmt::Display for InvalidPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Encountered illegal character sequence \"{}\" whilst parsing path segment \"{}\"",
+            self.segment, self.illegal
+        )
+    }
+}
+
+impl core::error::Error for InvalidPart {}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default, Hash)]
 pub struct PathPart<'a> {
     pub(super) raw: Cow<'a, str>,
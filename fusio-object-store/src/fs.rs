@@ -33,14 +33,25 @@ impl<O: ObjectStore> Fs for S3Store<O> {
                 message: "append mode is not supported in Amazon S3".into(),
             });
         }
+        if options.create_new {
+            return Err(Error::Unsupported {
+                message: "conditional creates are not supported through object_store".into(),
+            });
+        }
+        if options.write && options.if_match.is_some() {
+            return Err(Error::Unsupported {
+                message: "conditional writes are not supported through object_store".into(),
+            });
+        }
         Ok(S3File {
             inner: self.inner.clone(),
             path: path.clone().into(),
             buf: None,
+            if_match: options.if_match,
         })
     }
 
-    async fn create_dir_all(_: &Path) -> Result<(), Error> {
+    async fn create_dir_all(&self, _: &Path) -> Result<(), Error> {
         Ok(())
     }
 
@@ -53,7 +64,9 @@ impl<O: ObjectStore> Fs for S3Store<O> {
 
         Ok(stream! {
             while let Some(meta) = stream.next().await.transpose().map_err(BoxedError::from)? {
-                yield Ok(FileMeta { path: meta.location.into(), size: meta.size as u64 });
+                yield Ok(FileMeta::new(meta.location.into(), meta.size as u64)
+                    .last_modified(Some(meta.last_modified.into()))
+                    .etag(meta.e_tag));
             }
         })
     }
@@ -64,4 +77,24 @@ impl<O: ObjectStore> Fs for S3Store<O> {
 
         Ok(())
     }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let from = from.clone().into();
+        let to = to.clone().into();
+        self.inner
+            .copy(&from, &to)
+            .await
+            .map_err(BoxedError::from)?;
+
+        Ok(())
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        let location = path.clone().into();
+        let meta = self.inner.head(&location).await.map_err(BoxedError::from)?;
+
+        Ok(FileMeta::new(path.clone(), meta.size as u64)
+            .last_modified(Some(meta.last_modified.into()))
+            .etag(meta.e_tag))
+    }
 }
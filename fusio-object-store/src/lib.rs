@@ -13,6 +13,19 @@ pub struct S3File<O: ObjectStore> {
     inner: Arc<O>,
     path: Path,
     buf: Option<Arc<Mutex<ParquetObjectWriter>>>,
+    /// Reads fail unless the object still has this ETag, see [`fusio::fs::OpenOptions::if_match`].
+    if_match: Option<String>,
+}
+
+impl<O: ObjectStore> S3File<O> {
+    fn get_error(&self, e: object_store::Error) -> Error {
+        match e {
+            object_store::Error::Precondition { .. } => Error::PreconditionFailed {
+                path: self.path.clone().into(),
+            },
+            e => BoxedError::from(e).into(),
+        }
+    }
 }
 
 impl<O: ObjectStore> S3File<O> {
@@ -23,16 +36,12 @@ impl<O: ObjectStore> S3File<O> {
     ) -> (Result<(), Error>, B) {
         let opts = GetOptions {
             range: Some(range),
+            if_match: self.if_match.clone(),
             ..Default::default()
         };
-        let result = match self
-            .inner
-            .get_opts(&self.path, opts)
-            .await
-            .map_err(BoxedError::from)
-        {
+        let result = match self.inner.get_opts(&self.path, opts).await {
             Ok(result) => result,
-            Err(e) => return (Err(e.into()), buf),
+            Err(e) => return (Err(self.get_error(e)), buf),
         };
 
         let bytes = match result.bytes().await.map_err(BoxedError::from) {
@@ -45,18 +54,30 @@ impl<O: ObjectStore> S3File<O> {
     }
 }
 
+/// `object_store` addresses ranges with `usize`, so offsets past 4GiB can not be read on 32-bit
+/// targets such as wasm32.
+fn offset(pos: u64) -> Result<usize, Error> {
+    usize::try_from(pos).map_err(|_| Error::Unsupported {
+        message: format!("offset {pos} does not fit in usize on this target"),
+    })
+}
+
 impl<O: ObjectStore> Read for S3File<O> {
     async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
-        let range = GetRange::Bounded(Range {
-            start: pos as usize,
-            end: pos as usize + buf.bytes_init(),
-        });
+        let end = pos.checked_add(buf.bytes_init() as u64).unwrap_or(u64::MAX);
+        let range = match (offset(pos), offset(end)) {
+            (Ok(start), Ok(end)) => GetRange::Bounded(Range { start, end }),
+            (Err(e), _) | (_, Err(e)) => return (Err(e), buf),
+        };
 
         self.read_with_range(range, buf).await
     }
 
     async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
-        let range = GetRange::Offset(pos as usize);
+        let range = match offset(pos) {
+            Ok(start) => GetRange::Offset(start),
+            Err(e) => return (Err(e), buf),
+        };
 
         let (result, buf) = self.read_with_range(range, buf).await;
         match result {
@@ -68,13 +89,14 @@ impl<O: ObjectStore> Read for S3File<O> {
     async fn size(&self) -> Result<u64, Error> {
         let options = GetOptions {
             head: true,
+            if_match: self.if_match.clone(),
             ..Default::default()
         };
         let response = self
             .inner
             .get_opts(&self.path, options)
             .await
-            .map_err(BoxedError::from)?;
+            .map_err(|e| self.get_error(e))?;
         Ok(response.meta.size as u64)
     }
 }
@@ -111,6 +133,12 @@ impl<O: ObjectStore> Write for S3File<O> {
         }
         Ok(())
     }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        Err(Error::Unsupported {
+            message: "object stores make writes durable only when the object is closed".into(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -153,6 +181,7 @@ mod tests {
                 inner: Arc::new(s3),
                 path,
                 buf: None,
+                if_match: None,
             };
             let (result, bytes) = store.write_all(Bytes::from("hello! Fusio!")).await;
             result.unwrap();
@@ -0,0 +1,317 @@
+#[cfg(not(fusio_loom))]
+use std::sync::Mutex;
+use std::{collections::HashMap, sync::Arc};
+
+use fusio::{
+    cache::{EvictionPolicy, Invalidate, Lru},
+    path::Path,
+};
+#[cfg(fusio_loom)]
+use loom::sync::Mutex;
+use parquet::file::metadata::ParquetMetaData;
+
+/// Identifies one version of a parquet object. The etag changes whenever the object is
+/// rewritten, so a cached footer is never served for different content under the same path.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub path: String,
+    pub etag: String,
+}
+
+impl CacheKey {
+    pub fn new(path: impl Into<String>, etag: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            etag: etag.into(),
+        }
+    }
+}
+
+struct Entries {
+    metadata: HashMap<CacheKey, Arc<ParquetMetaData>>,
+    policy: Box<dyn EvictionPolicy<CacheKey>>,
+    /// The sum of [`ParquetMetaData::memory_size`] over the cached footers.
+    bytes: usize,
+}
+
+impl Entries {
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(metadata) = self.metadata.remove(key) {
+            self.bytes -= metadata.memory_size();
+        }
+    }
+
+    /// Evicts entries until at most `capacity` of them holding at most `max_bytes` are left.
+    fn evict_to(&mut self, capacity: usize, max_bytes: usize) {
+        while self.metadata.len() > capacity || self.bytes > max_bytes {
+            let Some(victim) = self.policy.evict() else {
+                break;
+            };
+            self.remove(&victim);
+        }
+    }
+}
+
+/// Decoded parquet footers, including the page index if it was loaded, shared by every
+/// [`crate::reader::AsyncReader`] it is attached to.
+///
+/// Opening the same object again skips the footer reads, which dominate the cost of opening
+/// small files on object storage. Once `capacity` entries are cached, the entry picked by the
+/// [`EvictionPolicy`] is evicted, the least recently used one by default. The same happens once
+/// the footers hold more than [`MetadataCache::with_max_bytes`] of memory, and
+/// [`MetadataCache::shrink_to`] releases memory on demand, e.g. when the process is under memory
+/// pressure.
+///
+/// # Concurrency
+///
+/// The cache may be shared by any number of readers at once. Loom model tests, run with
+/// `RUSTFLAGS="--cfg fusio_loom" cargo test -p fusio-parquet --release --lib loom_tests`, check
+/// under every interleaving that it never holds more than `capacity` entries, that the eviction
+/// policy tracks exactly the cached keys, and that no version of a path is served once
+/// [`MetadataCache::invalidate`] returned, unless it was inserted again afterwards.
+pub struct MetadataCache {
+    capacity: usize,
+    max_bytes: usize,
+    entries: Mutex<Entries>,
+}
+
+impl MetadataCache {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_policy(capacity, Lru::new())
+    }
+
+    pub fn with_policy<P>(capacity: usize, policy: P) -> Self
+    where
+        P: EvictionPolicy<CacheKey> + 'static,
+    {
+        Self {
+            capacity,
+            max_bytes: usize::MAX,
+            entries: Mutex::new(Entries {
+                metadata: HashMap::new(),
+                policy: Box::new(policy),
+                bytes: 0,
+            }),
+        }
+    }
+
+    /// Caps the memory held by the cached footers, as estimated by
+    /// [`ParquetMetaData::memory_size`], in addition to the number of entries.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Arc<ParquetMetaData>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.policy.access(key);
+        entries.metadata.get(key).cloned()
+    }
+
+    pub fn insert(&self, key: CacheKey, metadata: Arc<ParquetMetaData>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let size = metadata.memory_size();
+        if size > self.max_bytes {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let entries = &mut *entries;
+        if let Some(cached) = entries.metadata.get_mut(&key) {
+            entries.bytes = entries.bytes - cached.memory_size() + size;
+            *cached = metadata;
+        } else {
+            if entries.metadata.len() >= self.capacity && !entries.policy.admit(&key) {
+                return;
+            }
+            entries.metadata.insert(key.clone(), metadata);
+            entries.policy.insert(key);
+            entries.bytes += size;
+        }
+        entries.evict_to(self.capacity, self.max_bytes);
+    }
+
+    /// Drops the cached footer of every version of `path`.
+    pub fn invalidate(&self, path: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        let keys = entries
+            .metadata
+            .keys()
+            .filter(|key| key.path == path)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys {
+            entries.remove(&key);
+            entries.policy.remove(&key);
+        }
+    }
+
+    /// Evicts footers in the order of the eviction policy until they hold at most `bytes` of
+    /// memory and returns how much was released. Later inserts are still bounded by
+    /// [`MetadataCache::with_max_bytes`] only.
+    pub fn shrink_to(&self, bytes: usize) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.bytes;
+        entries.evict_to(self.capacity, bytes);
+        before - entries.bytes
+    }
+
+    /// The memory held by the cached footers, as estimated by [`ParquetMetaData::memory_size`].
+    pub fn memory_size(&self) -> usize {
+        self.entries.lock().unwrap().bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().metadata.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Drops the footers of a path written or removed through a cache sharing the same
+/// [`fusio::cache::Invalidations`], given cache keys use the path as fusio formats it.
+impl Invalidate for MetadataCache {
+    fn invalidate(&self, path: &Path) {
+        MetadataCache::invalidate(self, path.as_ref());
+    }
+}
+
+#[cfg(all(test, not(fusio_loom)))]
+mod tests {
+    use std::sync::Arc;
+
+    use parquet::{
+        file::metadata::{FileMetaData, ParquetMetaData},
+        schema::types::{SchemaDescriptor, Type},
+    };
+
+    use super::{CacheKey, MetadataCache};
+
+    fn metadata() -> Arc<ParquetMetaData> {
+        let schema = Type::group_type_builder("schema").build().unwrap();
+        let schema = SchemaDescriptor::new(Arc::new(schema));
+        let file = FileMetaData::new(1, 0, None, None, Arc::new(schema), None);
+        Arc::new(ParquetMetaData::new(file, Vec::new()))
+    }
+
+    #[test]
+    fn shrinks_to_bytes() {
+        let size = metadata().memory_size();
+        let cache = MetadataCache::new(8).with_max_bytes(2 * size);
+        for path in ["a", "b", "c"] {
+            cache.insert(CacheKey::new(path, "v1"), metadata());
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.memory_size(), 2 * size);
+        assert!(cache.get(&CacheKey::new("a", "v1")).is_none());
+
+        assert_eq!(cache.shrink_to(size), size);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&CacheKey::new("c", "v1")).is_some());
+        assert_eq!(cache.shrink_to(0), size);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn invalidated_through_shared_invalidations() {
+        use fusio::{cache::Invalidations, path::Path};
+
+        let cache = Arc::new(MetadataCache::new(8));
+        let invalidations = Invalidations::new();
+        invalidations.register(&cache);
+        cache.insert(CacheKey::new("data/a.parquet", "v1"), metadata());
+        cache.insert(CacheKey::new("data/b.parquet", "v1"), metadata());
+
+        invalidations.invalidate(&Path::parse("data/a.parquet").unwrap());
+        assert!(cache.get(&CacheKey::new("data/a.parquet", "v1")).is_none());
+        assert!(cache.get(&CacheKey::new("data/b.parquet", "v1")).is_some());
+    }
+}
+
+#[cfg(all(test, fusio_loom))]
+mod loom_tests {
+    use std::sync::Arc as StdArc;
+
+    use loom::{sync::Arc, thread};
+    use parquet::{
+        file::metadata::{FileMetaData, ParquetMetaData},
+        schema::types::{SchemaDescriptor, Type},
+    };
+
+    use super::{CacheKey, MetadataCache};
+
+    fn metadata() -> StdArc<ParquetMetaData> {
+        let schema = Type::group_type_builder("schema").build().unwrap();
+        let schema = SchemaDescriptor::new(StdArc::new(schema));
+        let file = FileMetaData::new(1, 0, None, None, StdArc::new(schema), None);
+        StdArc::new(ParquetMetaData::new(file, Vec::new()))
+    }
+
+    fn assert_consistent(cache: &MetadataCache) {
+        let mut entries = cache.entries.lock().unwrap();
+        assert!(entries.metadata.len() <= cache.capacity);
+        let bytes = entries
+            .metadata
+            .values()
+            .map(|metadata| metadata.memory_size())
+            .sum::<usize>();
+        assert_eq!(entries.bytes, bytes);
+        let mut tracked = 0;
+        while let Some(key) = entries.policy.evict() {
+            assert!(entries.metadata.contains_key(&key));
+            tracked += 1;
+        }
+        assert_eq!(tracked, entries.metadata.len());
+    }
+
+    #[test]
+    fn loom_capacity_holds() {
+        loom::model(|| {
+            let cache = Arc::new(MetadataCache::new(1));
+            let metadata = metadata();
+            let threads = ["a", "b"]
+                .into_iter()
+                .map(|path| {
+                    let cache = cache.clone();
+                    let metadata = metadata.clone();
+                    thread::spawn(move || {
+                        let key = CacheKey::new(path, "v1");
+                        cache.insert(key.clone(), metadata);
+                        cache.get(&key);
+                    })
+                })
+                .collect::<Vec<_>>();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            assert_eq!(cache.len(), 1);
+            assert_consistent(&cache);
+        });
+    }
+
+    #[test]
+    fn loom_invalidate_is_not_lost() {
+        loom::model(|| {
+            let cache = Arc::new(MetadataCache::new(4));
+            let metadata = metadata();
+            cache.insert(CacheKey::new("a", "v1"), metadata.clone());
+
+            let insert = thread::spawn({
+                let cache = cache.clone();
+                move || cache.insert(CacheKey::new("a", "v2"), metadata)
+            });
+            cache.invalidate("a");
+            let v2 = cache.get(&CacheKey::new("a", "v2")).is_some();
+            insert.join().unwrap();
+
+            assert!(cache.get(&CacheKey::new("a", "v1")).is_none());
+            // v2 survives only when it was inserted after the invalidation
+            assert!(!v2 || cache.len() == 1);
+            assert_consistent(&cache);
+        });
+    }
+}
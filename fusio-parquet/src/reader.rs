@@ -12,6 +12,8 @@ use parquet::{
     },
 };
 
+use crate::cache::{CacheKey, MetadataCache};
+
 const PREFETCH_FOOTER_SIZE: usize = 512 * 1024;
 
 pub struct AsyncReader {
@@ -19,6 +21,8 @@ pub struct AsyncReader {
     content_length: u64,
     // The prefetch size for fetching file footer.
     prefetch_footer_size: usize,
+    page_index: bool,
+    cache: Option<(Arc<MetadataCache>, CacheKey)>,
 }
 
 fn set_prefetch_footer_size(footer_size: usize, content_size: u64) -> usize {
@@ -32,6 +36,8 @@ impl AsyncReader {
             inner: reader,
             content_length,
             prefetch_footer_size: set_prefetch_footer_size(PREFETCH_FOOTER_SIZE, content_length),
+            page_index: false,
+            cache: None,
         })
     }
 
@@ -39,6 +45,76 @@ impl AsyncReader {
         self.prefetch_footer_size = set_prefetch_footer_size(footer_size, self.content_length);
         self
     }
+
+    /// Loads the column and offset indexes together with the footer, so they are cached with it.
+    pub fn with_page_index(mut self, page_index: bool) -> Self {
+        self.page_index = page_index;
+        self
+    }
+
+    /// Looks up the metadata in `cache` before reading the footer and caches it after reading.
+    /// `key` must change whenever the object does, usually by including its etag.
+    pub fn with_metadata_cache(mut self, cache: Arc<MetadataCache>, key: CacheKey) -> Self {
+        self.cache = Some((cache, key));
+        self
+    }
+
+    async fn load_metadata(&mut self) -> parquet::errors::Result<ParquetMetaData> {
+        if self.content_length == 0 {
+            return Err(ParquetError::EOF("file empty".to_string()));
+        }
+        if self.page_index {
+            let file_size = self.content_length as usize;
+            return ParquetMetaDataReader::new()
+                .with_page_indexes(true)
+                .with_prefetch_hint(Some(self.prefetch_footer_size))
+                .load_and_finish(&mut *self, file_size)
+                .await;
+        }
+
+        let footer_size = self.prefetch_footer_size;
+        let mut buf = BytesMut::with_capacity(footer_size);
+        buf.resize(footer_size, 0);
+
+        let (result, prefetched_footer_content) = self
+            .inner
+            .read_exact_at(buf, self.content_length - footer_size as u64)
+            .await;
+        result.map_err(|err| ParquetError::External(Box::new(err)))?;
+        let prefetched_footer_slice = prefetched_footer_content.as_ref();
+        let prefetched_footer_length = prefetched_footer_slice.len();
+
+        // Decode the metadata length from the last 8 bytes of the file.
+        let metadata_length = {
+            let buf = &prefetched_footer_slice
+                [(prefetched_footer_length - FOOTER_SIZE)..prefetched_footer_length];
+            debug_assert!(buf.len() == FOOTER_SIZE);
+            ParquetMetaDataReader::decode_footer(buf.try_into().unwrap())?
+        };
+
+        // Try to read the metadata from the `prefetched_footer_content`.
+        // Otherwise, fetch exact metadata from the remote.
+        if prefetched_footer_length >= metadata_length + FOOTER_SIZE {
+            let buf =
+                &prefetched_footer_slice[(prefetched_footer_length - metadata_length - FOOTER_SIZE)
+                    ..(prefetched_footer_length - FOOTER_SIZE)];
+            ParquetMetaDataReader::decode_metadata(buf)
+        } else {
+            let mut buf = BytesMut::with_capacity(metadata_length);
+            buf.resize(metadata_length, 0);
+
+            let (result, bytes) = self
+                .inner
+                .read_exact_at(
+                    buf,
+                    self.content_length - metadata_length as u64 - FOOTER_SIZE as u64,
+                )
+                .await;
+            result.map_err(|err| ParquetError::External(Box::new(err)))?;
+
+            ParquetMetaDataReader::decode_metadata(&bytes)
+        }
+    }
 }
 
 impl AsyncFileReader for AsyncReader {
@@ -57,53 +133,15 @@ impl AsyncFileReader for AsyncReader {
 
     fn get_metadata(&mut self) -> BoxFuture<'_, parquet::errors::Result<Arc<ParquetMetaData>>> {
         async move {
-            if self.content_length == 0 {
-                return Err(ParquetError::EOF("file empty".to_string()));
+            if let Some(metadata) = self.cache.as_ref().and_then(|(cache, key)| cache.get(key)) {
+                return Ok(metadata);
             }
 
-            let footer_size = self.prefetch_footer_size;
-            let mut buf = BytesMut::with_capacity(footer_size);
-            buf.resize(footer_size, 0);
-
-            let (result, prefetched_footer_content) = self
-                .inner
-                .read_exact_at(buf, self.content_length - footer_size as u64)
-                .await;
-            result.map_err(|err| ParquetError::External(Box::new(err)))?;
-            let prefetched_footer_slice = prefetched_footer_content.as_ref();
-            let prefetched_footer_length = prefetched_footer_slice.len();
-
-            // Decode the metadata length from the last 8 bytes of the file.
-            let metadata_length = {
-                let buf = &prefetched_footer_slice
-                    [(prefetched_footer_length - FOOTER_SIZE)..prefetched_footer_length];
-                debug_assert!(buf.len() == FOOTER_SIZE);
-                ParquetMetaDataReader::decode_footer(buf.try_into().unwrap())?
-            };
-
-            // Try to read the metadata from the `prefetched_footer_content`.
-            // Otherwise, fetch exact metadata from the remote.
-            if prefetched_footer_length >= metadata_length + FOOTER_SIZE {
-                let buf = &prefetched_footer_slice[(prefetched_footer_length
-                    - metadata_length
-                    - FOOTER_SIZE)
-                    ..(prefetched_footer_length - FOOTER_SIZE)];
-                Ok(Arc::new(ParquetMetaDataReader::decode_metadata(buf)?))
-            } else {
-                let mut buf = BytesMut::with_capacity(metadata_length);
-                buf.resize(metadata_length, 0);
-
-                let (result, bytes) = self
-                    .inner
-                    .read_exact_at(
-                        buf,
-                        self.content_length - metadata_length as u64 - FOOTER_SIZE as u64,
-                    )
-                    .await;
-                result.map_err(|err| ParquetError::External(Box::new(err)))?;
-
-                Ok(Arc::new(ParquetMetaDataReader::decode_metadata(&bytes)?))
+            let metadata = Arc::new(self.load_metadata().await?);
+            if let Some((cache, key)) = &self.cache {
+                cache.insert(key.clone(), metadata.clone());
             }
+            Ok(metadata)
         }
         .boxed()
     }
@@ -119,7 +157,7 @@ mod tests {
     use arrow::array::{ArrayRef, Int64Array, RecordBatch};
     use futures::StreamExt;
     use parquet::{
-        arrow::{AsyncArrowWriter, ParquetRecordBatchStreamBuilder},
+        arrow::{async_reader::AsyncFileReader, AsyncArrowWriter, ParquetRecordBatchStreamBuilder},
         file::properties::WriterProperties,
         format::KeyValue,
     };
@@ -128,6 +166,7 @@ mod tests {
     use tokio::fs::File;
 
     use crate::{
+        cache::{CacheKey, MetadataCache},
         reader::{AsyncReader, PREFETCH_FOOTER_SIZE},
         writer::AsyncWriter,
     };
@@ -237,4 +276,52 @@ mod tests {
             assert_eq!(to_write, read);
         }
     }
+
+    #[tokio::test]
+    async fn test_async_reader_with_metadata_cache() {
+        let temp_file = tempfile().unwrap();
+        let mut temp_file_clone = temp_file.try_clone().unwrap();
+
+        let writer = AsyncWriter::new(Box::new(File::from_std(temp_file)));
+        let col = Arc::new(Int64Array::from_iter_values([1, 2, 3])) as ArrayRef;
+        let to_write = RecordBatch::try_from_iter([("col", col)]).unwrap();
+        let mut writer = AsyncArrowWriter::try_new(writer, to_write.schema(), None).unwrap();
+        writer.write(&to_write).await.unwrap();
+        writer.close().await.unwrap();
+
+        temp_file_clone.seek(SeekFrom::Start(0)).unwrap();
+        let content_len = temp_file_clone.metadata().unwrap().len();
+        let cache = Arc::new(MetadataCache::new(16));
+        let key = CacheKey::new("data.parquet", "etag-1");
+
+        let mut reader = AsyncReader::new(Box::new(File::from_std(temp_file_clone)), content_len)
+            .await
+            .unwrap()
+            .with_page_index(true)
+            .with_metadata_cache(cache.clone(), key.clone());
+        let metadata = reader.get_metadata().await.unwrap();
+        assert_eq!(metadata.file_metadata().num_rows(), 3);
+        assert!(metadata.column_index().is_some());
+        assert_eq!(cache.len(), 1);
+
+        // a cached footer is served without touching the file
+        let mut reader =
+            AsyncReader::new(Box::new(File::from_std(tempfile().unwrap())), content_len)
+                .await
+                .unwrap()
+                .with_metadata_cache(cache.clone(), key);
+        let cached = reader.get_metadata().await.unwrap();
+        assert!(Arc::ptr_eq(&metadata, &cached));
+
+        // a new etag misses the cache
+        let mut reader =
+            AsyncReader::new(Box::new(File::from_std(tempfile().unwrap())), content_len)
+                .await
+                .unwrap()
+                .with_metadata_cache(cache.clone(), CacheKey::new("data.parquet", "etag-2"));
+        assert!(reader.get_metadata().await.is_err());
+
+        cache.invalidate("data.parquet");
+        assert!(cache.is_empty());
+    }
 }
@@ -0,0 +1,300 @@
+//! Length-delimited frames over fusio's [`Write`] and [`SeqRead`], for streams of records such as
+//! logs, where each record has to be read back on its own.
+//!
+//! Every frame is prefixed with its length, encoded by a [`LengthCodec`]: [`FixedU32`] takes
+//! four bytes and is read with a single call, [`Varint`] takes one to ten bytes and is read one
+//! byte at a time, which suits small records on local files better than on object storage.
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), fusio::Error> {
+//! use std::io::Cursor;
+//!
+//! use fusio_serde::framed::{FramedRead, FramedWrite, Varint};
+//!
+//! let mut log = Vec::new();
+//! let mut writer = FramedWrite::<_, Varint>::new(Cursor::new(&mut log));
+//! writer.send(b"first".to_vec()).await?;
+//! writer.send_encoded(&42u64).await?;
+//!
+//! let mut reader = FramedRead::<_, Varint>::new(Cursor::new(&mut log));
+//! assert_eq!(reader.next().await?, Some(b"first".to_vec()));
+//! assert_eq!(reader.next_decoded::<u64>().await?, Some(42));
+//! assert_eq!(reader.next().await?, None);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{future::Future, io, marker::PhantomData};
+
+use fusio::{Error, IoBuf, MaybeSend, SeqRead, Write};
+
+use crate::{invalid, read_bytes, Decode, Encode};
+
+/// Frames larger than this are rejected by [`FramedRead`] unless configured otherwise, so a
+/// corrupted prefix does not allocate an arbitrary amount of memory.
+pub const DEFAULT_MAX_FRAME_SIZE: u64 = 64 << 20;
+
+/// How the length of a frame is written in front of it.
+pub trait LengthCodec: MaybeSend {
+    /// Appends the prefix of a frame of `len` bytes to `buf`.
+    fn encode(len: u64, buf: &mut Vec<u8>) -> Result<(), Error>;
+
+    /// Reads a prefix, or returns `None` if `reader` ended right before it.
+    fn decode<R: SeqRead>(
+        reader: &mut R,
+    ) -> impl Future<Output = Result<Option<u64>, Error>> + MaybeSend;
+}
+
+/// Reads `N` bytes, returning `None` if `reader` has no byte left at all.
+async fn read_prefix<R: SeqRead, const N: usize>(reader: &mut R) -> Result<Option<[u8; N]>, Error> {
+    let (result, buf) = reader.read_exact(vec![0; N]).await;
+    match result {
+        Ok(()) => Ok(Some(buf.try_into().unwrap())),
+        Err(Error::UnexpectedEof { available: 0, .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// A little-endian `u32` prefix, limiting frames to 4 GiB.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedU32;
+
+impl LengthCodec for FixedU32 {
+    fn encode(len: u64, buf: &mut Vec<u8>) -> Result<(), Error> {
+        let len = u32::try_from(len).map_err(|_| {
+            Error::from(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame does not fit a u32 length",
+            ))
+        })?;
+        buf.extend_from_slice(&len.to_le_bytes());
+        Ok(())
+    }
+
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Option<u64>, Error> {
+        Ok(read_prefix::<_, 4>(reader)
+            .await?
+            .map(|prefix| u32::from_le_bytes(prefix) as u64))
+    }
+}
+
+/// An unsigned LEB128 prefix: seven bits per byte, least significant first, with the high bit set
+/// on every byte but the last.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Varint;
+
+impl LengthCodec for Varint {
+    fn encode(mut len: u64, buf: &mut Vec<u8>) -> Result<(), Error> {
+        while len >= 0x80 {
+            buf.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        buf.push(len as u8);
+        Ok(())
+    }
+
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Option<u64>, Error> {
+        let mut len = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = match read_prefix::<_, 1>(reader).await? {
+                Some([byte]) => byte,
+                None if shift == 0 => return Ok(None),
+                None => {
+                    return Err(Error::UnexpectedEof {
+                        requested: 1,
+                        available: 0,
+                    })
+                }
+            };
+            len |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(Some(len));
+            }
+        }
+        Err(invalid("varint is longer than ten bytes"))
+    }
+}
+
+/// Writes length-prefixed frames to a [`Write`].
+pub struct FramedWrite<W, C> {
+    writer: W,
+    _codec: PhantomData<C>,
+}
+
+impl<W: Write, C: LengthCodec> FramedWrite<W, C> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            _codec: PhantomData,
+        }
+    }
+
+    /// Writes `frame` with its prefix in a single call.
+    pub async fn send<B: IoBuf>(&mut self, frame: B) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(frame.bytes_init() + 10);
+        C::encode(frame.bytes_init() as u64, &mut buf)?;
+        buf.extend_from_slice(frame.as_slice());
+        let (result, _) = self.writer.write_all(buf).await;
+        result
+    }
+
+    /// Writes `value` as one frame, encoding it into memory first.
+    pub async fn send_encoded<T: Encode>(&mut self, value: &T) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(value.size() + 10);
+        C::encode(value.size() as u64, &mut buf)?;
+        let mut cursor = io::Cursor::new(&mut buf);
+        cursor.set_position(cursor.get_ref().len() as u64);
+        value.encode(&mut cursor).await?;
+        let (result, _) = self.writer.write_all(buf).await;
+        result
+    }
+
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush().await
+    }
+
+    pub async fn close(&mut self) -> Result<(), Error> {
+        self.writer.close().await
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads length-prefixed frames written by [`FramedWrite`] from a [`SeqRead`].
+pub struct FramedRead<R, C> {
+    reader: R,
+    max_frame_size: u64,
+    _codec: PhantomData<C>,
+}
+
+impl<R: SeqRead, C: LengthCodec> FramedRead<R, C> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            _codec: PhantomData,
+        }
+    }
+
+    /// Frames with a larger prefix fail with an [`io::ErrorKind::InvalidData`] error.
+    pub fn with_max_frame_size(self, max_frame_size: u64) -> Self {
+        Self {
+            max_frame_size,
+            ..self
+        }
+    }
+
+    /// Returns the next frame, or `None` once the reader ends at a frame boundary. A reader that
+    /// ends within a frame fails with [`Error::UnexpectedEof`], e.g. after a torn write.
+    pub async fn next(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let Some(len) = C::decode(&mut self.reader).await? else {
+            return Ok(None);
+        };
+        if len > self.max_frame_size {
+            return Err(invalid("frame exceeds the maximum frame size"));
+        }
+        read_bytes(&mut self.reader, len).await.map(Some)
+    }
+
+    /// Decodes the next frame as a `T`, which has to span the whole frame.
+    pub async fn next_decoded<T: Decode>(&mut self) -> Result<Option<T>, Error> {
+        let Some(mut frame) = self.next().await? else {
+            return Ok(None);
+        };
+        let len = frame.len() as u64;
+        let mut cursor = io::Cursor::new(&mut frame);
+        let value = T::decode(&mut cursor).await?;
+        match cursor.position() == len {
+            true => Ok(Some(value)),
+            false => Err(invalid("frame has trailing bytes")),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use fusio::Error;
+
+    use super::{FixedU32, FramedRead, FramedWrite, LengthCodec, Varint};
+
+    async fn round_trip<C: LengthCodec>() {
+        let frames = [vec![], vec![7; 127], vec![8; 128], vec![9; 70_000]];
+
+        let mut buf = Vec::new();
+        let mut writer = FramedWrite::<_, C>::new(Cursor::new(&mut buf));
+        for frame in &frames {
+            writer.send(frame.clone()).await.unwrap();
+        }
+        writer.send_encoded(&"record".to_string()).await.unwrap();
+
+        let mut reader = FramedRead::<_, C>::new(Cursor::new(&mut buf));
+        for frame in &frames {
+            assert_eq!(reader.next().await.unwrap().as_ref(), Some(frame));
+        }
+        assert_eq!(
+            reader.next_decoded::<String>().await.unwrap().as_deref(),
+            Some("record")
+        );
+        assert_eq!(reader.next().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn fixed_u32_round_trip() {
+        round_trip::<FixedU32>().await;
+    }
+
+    #[tokio::test]
+    async fn varint_round_trip() {
+        round_trip::<Varint>().await;
+
+        let mut buf = Vec::new();
+        Varint::encode(300, &mut buf).unwrap();
+        assert_eq!(buf, [0xac, 0x02]);
+        Varint::encode(u64::MAX, &mut buf).unwrap();
+        let mut reader = Cursor::new(&mut buf);
+        assert_eq!(Varint::decode(&mut reader).await.unwrap(), Some(300));
+        assert_eq!(Varint::decode(&mut reader).await.unwrap(), Some(u64::MAX));
+    }
+
+    #[tokio::test]
+    async fn rejects_torn_and_oversized_frames() {
+        let mut buf = Vec::new();
+        let mut writer = FramedWrite::<_, Varint>::new(Cursor::new(&mut buf));
+        writer.send(vec![1; 200]).await.unwrap();
+
+        let mut torn = buf[..100].to_vec();
+        let mut reader = FramedRead::<_, Varint>::new(Cursor::new(&mut torn));
+        assert!(matches!(
+            reader.next().await,
+            Err(Error::UnexpectedEof { .. })
+        ));
+
+        let mut torn = buf[..1].to_vec();
+        let mut reader = FramedRead::<_, Varint>::new(Cursor::new(&mut torn));
+        assert!(matches!(
+            reader.next().await,
+            Err(Error::UnexpectedEof { .. })
+        ));
+
+        let mut reader =
+            FramedRead::<_, Varint>::new(Cursor::new(&mut buf)).with_max_frame_size(100);
+        assert!(reader.next().await.is_err());
+    }
+}
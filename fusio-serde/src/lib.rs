@@ -0,0 +1,264 @@
+//! Encoding and decoding of typed records through fusio's [`Write`] and [`SeqRead`], so small
+//! metadata such as manifests or checkpoints can be persisted on any backend without ad-hoc byte
+//! handling.
+//!
+//! Integers and floats are stored little-endian with their fixed size. Strings, byte vectors and
+//! vectors are prefixed with their encoded size as a `u64` and are written and read with a single
+//! call, so decoding them costs one request on object storage no matter how many items they hold.
+//!
+//! ```
+//! use fusio::{Error, SeqRead, Write};
+//! use fusio_serde::{Decode, Encode};
+//!
+//! struct Checkpoint {
+//!     sequence: u64,
+//!     files: Vec<String>,
+//! }
+//!
+//! impl Encode for Checkpoint {
+//!     async fn encode<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+//!         self.sequence.encode(writer).await?;
+//!         self.files.encode(writer).await
+//!     }
+//!
+//!     fn size(&self) -> usize {
+//!         self.sequence.size() + self.files.size()
+//!     }
+//! }
+//!
+//! impl Decode for Checkpoint {
+//!     async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, Error> {
+//!         Ok(Self {
+//!             sequence: u64::decode(reader).await?,
+//!             files: Vec::decode(reader).await?,
+//!         })
+//!     }
+//! }
+//! ```
+//!
+//! [`framed`] writes and reads records as length-delimited frames, for streams of them such as
+//! logs.
+
+pub mod framed;
+
+use std::{future::Future, io};
+
+use fusio::{Error, MaybeSend, MaybeSync, SeqRead, Write};
+
+/// A value that can be written to a [`Write`].
+pub trait Encode: MaybeSync {
+    /// Writes the value to `writer`, without flushing or closing it.
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> impl Future<Output = Result<(), Error>> + MaybeSend;
+
+    /// The number of bytes [`Encode::encode`] writes.
+    fn size(&self) -> usize;
+}
+
+/// A value that can be read back from a [`SeqRead`] after being written by [`Encode`].
+pub trait Decode: Sized + MaybeSend {
+    /// Reads the value at the current position of `reader`. Data that does not describe a value,
+    /// e.g. an invalid UTF-8 string, fails with an [`io::ErrorKind::InvalidData`] error.
+    fn decode<R: SeqRead>(reader: &mut R) -> impl Future<Output = Result<Self, Error>> + MaybeSend;
+}
+
+fn invalid(message: &str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+async fn read_bytes<R: SeqRead>(reader: &mut R, len: u64) -> Result<Vec<u8>, Error> {
+    let len = usize::try_from(len).map_err(|_| invalid("length does not fit in memory"))?;
+    let (result, buf) = reader.read_exact(vec![0; len]).await;
+    result?;
+    Ok(buf)
+}
+
+macro_rules! impl_number {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                async fn encode<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+                    let (result, _) = writer.write_all(self.to_le_bytes().to_vec()).await;
+                    result
+                }
+
+                fn size(&self) -> usize {
+                    size_of::<$ty>()
+                }
+            }
+
+            impl Decode for $ty {
+                async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, Error> {
+                    let buf = read_bytes(reader, size_of::<$ty>() as u64).await?;
+                    Ok(<$ty>::from_le_bytes(buf.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl Encode for bool {
+    async fn encode<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        u8::from(*self).encode(writer).await
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+}
+
+impl Decode for bool {
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, Error> {
+        match u8::decode(reader).await? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("invalid bool")),
+        }
+    }
+}
+
+impl Encode for String {
+    async fn encode<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(self.size());
+        buf.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        buf.extend_from_slice(self.as_bytes());
+        let (result, _) = writer.write_all(buf).await;
+        result
+    }
+
+    fn size(&self) -> usize {
+        size_of::<u64>() + self.len()
+    }
+}
+
+impl Decode for String {
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, Error> {
+        let len = u64::decode(reader).await?;
+        let buf = read_bytes(reader, len).await?;
+        String::from_utf8(buf).map_err(|_| invalid("invalid UTF-8 string"))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    async fn encode<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        match self {
+            None => 0u8.encode(writer).await,
+            Some(value) => {
+                1u8.encode(writer).await?;
+                value.encode(writer).await
+            }
+        }
+    }
+
+    fn size(&self) -> usize {
+        1 + self.as_ref().map_or(0, Encode::size)
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, Error> {
+        match u8::decode(reader).await? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(reader).await?)),
+            _ => Err(invalid("invalid option tag")),
+        }
+    }
+}
+
+/// Encoded as the size of the items in bytes followed by the items, which are encoded into memory
+/// first and written at once.
+impl<T: Encode> Encode for Vec<T> {
+    async fn encode<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(self.size());
+        let mut cursor = io::Cursor::new(&mut buf);
+        ((self.size() - size_of::<u64>()) as u64)
+            .encode(&mut cursor)
+            .await?;
+        for item in self {
+            item.encode(&mut cursor).await?;
+        }
+        let (result, _) = writer.write_all(buf).await;
+        result
+    }
+
+    fn size(&self) -> usize {
+        size_of::<u64>() + self.iter().map(Encode::size).sum::<usize>()
+    }
+}
+
+/// Reads the items with a single call and decodes them from memory.
+impl<T: Decode> Decode for Vec<T> {
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, Error> {
+        let len = u64::decode(reader).await?;
+        let mut buf = read_bytes(reader, len).await?;
+        let mut items = Vec::new();
+        let mut cursor = io::Cursor::new(&mut buf);
+        while cursor.position() < len {
+            items.push(T::decode(&mut cursor).await?);
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Decode, Encode};
+
+    #[tokio::test]
+    async fn round_trip() {
+        let values = (
+            42u64,
+            -7i16,
+            1.5f64,
+            true,
+            Some("manifest".to_string()),
+            vec![vec![1u8, 2, 3], vec![]],
+        );
+
+        let mut buf = Vec::new();
+        let mut writer = Cursor::new(&mut buf);
+        values.0.encode(&mut writer).await.unwrap();
+        values.1.encode(&mut writer).await.unwrap();
+        values.2.encode(&mut writer).await.unwrap();
+        values.3.encode(&mut writer).await.unwrap();
+        values.4.encode(&mut writer).await.unwrap();
+        values.5.encode(&mut writer).await.unwrap();
+        assert_eq!(
+            buf.len(),
+            values.0.size()
+                + values.1.size()
+                + values.2.size()
+                + values.3.size()
+                + values.4.size()
+                + values.5.size()
+        );
+
+        let mut reader = Cursor::new(&mut buf);
+        assert_eq!(u64::decode(&mut reader).await.unwrap(), values.0);
+        assert_eq!(i16::decode(&mut reader).await.unwrap(), values.1);
+        assert_eq!(f64::decode(&mut reader).await.unwrap(), values.2);
+        assert_eq!(bool::decode(&mut reader).await.unwrap(), values.3);
+        assert_eq!(
+            Option::<String>::decode(&mut reader).await.unwrap(),
+            values.4
+        );
+        assert_eq!(Vec::<Vec<u8>>::decode(&mut reader).await.unwrap(), values.5);
+        assert!(u8::decode(&mut reader).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_data() {
+        let mut buf = vec![2];
+        assert!(bool::decode(&mut Cursor::new(&mut buf)).await.is_err());
+
+        let mut buf = 2u64.to_le_bytes().to_vec();
+        buf.extend_from_slice(&[0xff, 0xfe]);
+        assert!(String::decode(&mut Cursor::new(&mut buf)).await.is_err());
+    }
+}
@@ -0,0 +1,407 @@
+use std::sync::OnceLock;
+#[cfg(not(fusio_loom))]
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+#[cfg(fusio_loom)]
+use loom::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+/// Settings of a [`BufferPool`].
+#[derive(Debug, Clone, Copy)]
+pub struct PoolOptions {
+    /// Buffers smaller than this are rounded up to it, so small requests share one size class.
+    pub min_size: usize,
+    /// Buffers larger than this are allocated and dropped without the pool.
+    pub max_size: usize,
+    /// Buffer capacities are rounded up to a multiple of this, e.g. the page size. Must be a power
+    /// of two.
+    ///
+    /// Only the capacity is rounded: buffers are plain `Vec<u8>`s whose address is only as
+    /// aligned as the allocator places byte arrays, so they cannot be handed to direct I/O, which
+    /// needs the address aligned to the logical block size as well.
+    pub granularity: usize,
+    /// Upper bound of the bytes kept for reuse, buffers returned beyond it are dropped.
+    pub max_retained: usize,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            min_size: 4 * 1024,
+            max_size: 8 * 1024 * 1024,
+            granularity: 4 * 1024,
+            max_retained: 64 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Retained {
+    // free lists indexed by the base two logarithm of the buffer size
+    classes: Vec<Vec<Vec<u8>>>,
+    bytes: usize,
+}
+
+struct Inner {
+    options: PoolOptions,
+    max_retained: AtomicUsize,
+    retained: Mutex<Retained>,
+}
+
+/// Reuses read buffers across operations, so reading in a steady state stops allocating a fresh
+/// `Vec` for every request.
+///
+/// Buffers are grouped in power of two size classes between [`PoolOptions::min_size`] and
+/// [`PoolOptions::max_size`]. The pool is cheap to clone and shared by its clones, e.g. by every
+/// [`crate::buffered::BufReader`] of one [`crate::fs::Fs`], or process wide through
+/// [`BufferPool::global`].
+///
+/// # Concurrency
+///
+/// Clones may be used from any number of threads at once. The following invariants hold under
+/// every interleaving and are checked with [loom](https://docs.rs/loom) model tests, run with
+/// `RUSTFLAGS="--cfg fusio_loom" cargo test -p fusio --release --lib loom_tests`:
+///
+/// - a buffer is handed out by [`BufferPool::get`] to at most one caller at a time,
+/// - [`BufferPool::retained`] is the total capacity of the buffers kept for reuse,
+/// - once [`BufferPool::set_max_retained`] returned, the pool never keeps more than the new limit,
+///   even if buffers are returned concurrently.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<Inner>,
+}
+
+impl BufferPool {
+    pub fn new(options: PoolOptions) -> Self {
+        assert!(
+            options.granularity.is_power_of_two(),
+            "granularity must be a power of two"
+        );
+        Self {
+            inner: Arc::new(Inner {
+                options,
+                max_retained: AtomicUsize::new(options.max_retained),
+                retained: Mutex::new(Retained::default()),
+            }),
+        }
+    }
+
+    /// A pool with the default [`PoolOptions`] shared by the whole process.
+    pub fn global() -> &'static BufferPool {
+        static GLOBAL: OnceLock<BufferPool> = OnceLock::new();
+        GLOBAL.get_or_init(|| BufferPool::new(PoolOptions::default()))
+    }
+
+    fn class(&self, len: usize) -> Option<(usize, usize)> {
+        let options = &self.inner.options;
+        if len > options.max_size {
+            return None;
+        }
+        let size = len
+            .max(options.min_size)
+            .next_power_of_two()
+            .next_multiple_of(options.granularity);
+        Some((size.trailing_zeros() as usize, size))
+    }
+
+    /// Returns a buffer of `len` bytes. Its content is unspecified, as it may have been used
+    /// before, so it is meant to be read into.
+    pub fn get(&self, len: usize) -> Vec<u8> {
+        let Some((class, size)) = self.class(len) else {
+            return vec![0; len];
+        };
+        let reused = {
+            let mut retained = self.inner.retained.lock().unwrap();
+            let buf = retained
+                .classes
+                .get_mut(class)
+                .and_then(|buffers| buffers.pop());
+            if let Some(buf) = &buf {
+                retained.bytes -= buf.capacity();
+            }
+            buf
+        };
+
+        let mut buf = reused.unwrap_or_else(|| Vec::with_capacity(size));
+        buf.resize(len, 0);
+        buf
+    }
+
+    /// Hands `buf` back for reuse. Buffers not allocated by [`BufferPool::get`] are accepted as
+    /// long as their capacity matches a size class.
+    pub fn put(&self, buf: Vec<u8>) {
+        let Some((class, size)) = self.class(buf.capacity()) else {
+            return;
+        };
+        if size != buf.capacity() {
+            return;
+        }
+
+        let mut retained = self.inner.retained.lock().unwrap();
+        if retained.bytes + size > self.inner.max_retained.load(Ordering::Relaxed) {
+            return;
+        }
+        if retained.classes.len() <= class {
+            retained.classes.resize_with(class + 1, Vec::new);
+        }
+        retained.bytes += size;
+        retained.classes[class].push(buf);
+    }
+
+    /// Bytes currently kept for reuse.
+    pub fn retained(&self) -> usize {
+        self.inner.retained.lock().unwrap().bytes
+    }
+
+    /// Drops retained buffers, largest first, until at most `bytes` are kept and returns how many
+    /// bytes were released. Call it when the host application comes under memory pressure.
+    ///
+    /// The memory goes back to the allocator, whether it is returned to the operating system
+    /// right away depends on the allocator, see [`trim_allocator`].
+    pub fn shrink_to(&self, bytes: usize) -> usize {
+        let released = {
+            let mut retained = self.inner.retained.lock().unwrap();
+            let mut released = Vec::new();
+            for class in (0..retained.classes.len()).rev() {
+                while retained.bytes > bytes {
+                    let Some(buf) = retained.classes[class].pop() else {
+                        break;
+                    };
+                    retained.bytes -= buf.capacity();
+                    released.push(buf);
+                }
+            }
+            released
+        };
+        // deallocate outside of the lock
+        released.iter().map(Vec::capacity).sum()
+    }
+
+    /// Changes [`PoolOptions::max_retained`] and shrinks the pool to the new limit right away.
+    pub fn set_max_retained(&self, bytes: usize) -> usize {
+        self.inner.max_retained.store(bytes, Ordering::Relaxed);
+        self.shrink_to(bytes)
+    }
+
+    /// Lets the operating system reclaim the pages of the retained buffers with
+    /// `madvise(MADV_DONTNEED)` while keeping the buffers for reuse, and returns how many bytes
+    /// were advised. Only whole pages inside a buffer are advised, they read as zeros once used
+    /// again. Unlike [`BufferPool::shrink_to`], this works whatever the allocator does with freed
+    /// memory.
+    #[cfg(target_os = "linux")]
+    pub fn advise_unused(&self) -> usize {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let retained = self.inner.retained.lock().unwrap();
+        let mut advised = 0;
+        for buf in retained.classes.iter().flatten() {
+            let start = (buf.as_ptr() as usize).next_multiple_of(page);
+            let end = (buf.as_ptr() as usize + buf.capacity()) / page * page;
+            if end <= start {
+                continue;
+            }
+            // SAFETY: the pages belong to the allocation of `buf` alone, which nobody reads while
+            // it is retained; heap memory is private and anonymous, so the pages stay mapped and
+            // read as zeros afterwards
+            let advice = unsafe {
+                libc::madvise(start as *mut libc::c_void, end - start, libc::MADV_DONTNEED)
+            };
+            if advice == 0 {
+                advised += end - start;
+            }
+        }
+        advised
+    }
+
+    /// Lets the operating system reclaim the pages of the retained buffers, which is only
+    /// supported on Linux. Nothing is advised here.
+    #[cfg(not(target_os = "linux"))]
+    pub fn advise_unused(&self) -> usize {
+        0
+    }
+}
+
+/// Asks the allocator to return freed memory to the operating system, e.g. after
+/// [`BufferPool::shrink_to`] or shrinking a cache under memory pressure, and returns whether any
+/// was returned.
+///
+/// Calls `malloc_trim` of glibc, so it only has an effect when the global allocator is the system
+/// allocator.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub fn trim_allocator() -> bool {
+    unsafe { libc::malloc_trim(0) == 1 }
+}
+
+/// Asks the allocator to return freed memory to the operating system, which is only supported
+/// with glibc. Nothing is returned here.
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+pub fn trim_allocator() -> bool {
+    false
+}
+
+#[cfg(all(test, not(fusio_loom)))]
+mod tests {
+    use super::{BufferPool, PoolOptions};
+
+    #[test]
+    fn reuses_buffers() {
+        let pool = BufferPool::new(PoolOptions {
+            min_size: 16,
+            max_size: 1024,
+            granularity: 16,
+            max_retained: 64,
+        });
+
+        let buf = pool.get(20);
+        assert_eq!(buf.len(), 20);
+        assert_eq!(buf.capacity(), 32);
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+        assert_eq!(pool.retained(), 32);
+
+        let buf = pool.get(30);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(pool.retained(), 0);
+        pool.put(buf);
+
+        // the cap is reached, the third buffer is dropped
+        let buffers = [pool.get(32), pool.get(32), pool.get(32)];
+        for buf in buffers {
+            pool.put(buf);
+        }
+        assert_eq!(pool.retained(), 64);
+
+        // too large or irregular buffers bypass the pool
+        assert_eq!(pool.get(2048).len(), 2048);
+        pool.put(Vec::with_capacity(48));
+        assert_eq!(pool.retained(), 64);
+    }
+
+    #[test]
+    fn shrinks_under_pressure() {
+        let pool = BufferPool::new(PoolOptions {
+            min_size: 16,
+            max_size: 1024,
+            granularity: 16,
+            max_retained: 1024,
+        });
+        let buffers = [pool.get(16), pool.get(16), pool.get(64), pool.get(256)];
+        for buf in buffers {
+            pool.put(buf);
+        }
+        assert_eq!(pool.retained(), 352);
+
+        // the largest buffers go first
+        assert_eq!(pool.shrink_to(100), 256);
+        assert_eq!(pool.retained(), 96);
+
+        assert_eq!(pool.set_max_retained(16), 80);
+        assert_eq!(pool.retained(), 16);
+        pool.put(pool.get(64));
+        assert_eq!(pool.retained(), 16);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn advises_retained_pages() {
+        let pool = BufferPool::new(PoolOptions {
+            min_size: 1 << 20,
+            max_size: 1 << 20,
+            granularity: 4096,
+            max_retained: 1 << 20,
+        });
+        let mut buf = pool.get(1 << 20);
+        buf.fill(7);
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        assert!(pool.advise_unused() >= (1 << 20) - 2 * page);
+        // the buffer is kept for reuse
+        assert_eq!(pool.retained(), 1 << 20);
+        let buf = pool.get(1 << 20);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(buf.len(), 1 << 20);
+    }
+}
+
+#[cfg(all(test, fusio_loom))]
+mod loom_tests {
+    use loom::thread;
+
+    use super::{BufferPool, PoolOptions};
+
+    fn pool(max_retained: usize) -> BufferPool {
+        BufferPool::new(PoolOptions {
+            min_size: 16,
+            max_size: 64,
+            granularity: 16,
+            max_retained,
+        })
+    }
+
+    #[test]
+    fn loom_buffers_are_not_shared() {
+        loom::model(|| {
+            let pool = pool(64);
+            pool.put(pool.get(16));
+
+            let threads = (0..2)
+                .map(|_| {
+                    let pool = pool.clone();
+                    thread::spawn(move || pool.get(16))
+                })
+                .collect::<Vec<_>>();
+            let bufs = threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>();
+
+            assert_ne!(bufs[0].as_ptr(), bufs[1].as_ptr());
+            assert_eq!(pool.retained(), 0);
+        });
+    }
+
+    #[test]
+    fn loom_retained_matches_buffers() {
+        loom::model(|| {
+            let pool = pool(1024);
+            let threads = [16, 32]
+                .into_iter()
+                .map(|len| {
+                    let pool = pool.clone();
+                    thread::spawn(move || pool.put(pool.get(len)))
+                })
+                .collect::<Vec<_>>();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            assert_eq!(pool.retained(), 48);
+            let (a, b) = (pool.get(16), pool.get(32));
+            assert_eq!(pool.retained(), 0);
+            assert_eq!((a.capacity(), b.capacity()), (16, 32));
+        });
+    }
+
+    #[test]
+    fn loom_limit_holds_after_lowering() {
+        loom::model(|| {
+            let pool = pool(1024);
+            let buf = pool.get(64);
+
+            let put = thread::spawn({
+                let pool = pool.clone();
+                move || pool.put(buf)
+            });
+            pool.set_max_retained(32);
+            put.join().unwrap();
+
+            assert!(pool.retained() <= 32);
+        });
+    }
+}
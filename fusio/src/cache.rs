@@ -0,0 +1,490 @@
+//! Eviction policies shared by the caches built on fusio, and the invalidations that keep caches
+//! stacked on the same storage coherent.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{BuildHasher, Hash},
+    sync::{Arc, Mutex, Weak},
+};
+
+use crate::{path::Path, MaybeSend, MaybeSync};
+
+/// Seeds hashes randomly, or the same way on every run in simulations.
+#[cfg(not(feature = "sim"))]
+type RandomState = std::collections::hash_map::RandomState;
+#[cfg(feature = "sim")]
+type RandomState = std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+pub trait EvictionPolicy<K>: MaybeSend {
+    //! Decides which entry a cache drops when it is full.
+    //!
+    //! The cache owns the entries and tells the policy about every lookup, insertion and removal.
+    //! Plain LRU suits most workloads, [`Slru`] protects entries read more than once from scans
+    //! and [`TinyLfu`] additionally refuses to cache entries that are rarely used.
+    //!
+    //! Policies are not synchronized themselves, caches keep them behind the lock of their
+    //! entries. Loom model tests check for every policy in this module that, shared that way by
+    //! concurrent callers, a key is evicted at most once and every key inserted and not removed
+    //! is evicted eventually.
+
+    /// Called on every lookup of `key`, whether it is cached or not.
+    fn access(&mut self, key: &K);
+
+    /// Called after `key` was added to the cache.
+    fn insert(&mut self, key: K);
+
+    /// Called when `key` left the cache other than by [`EvictionPolicy::evict`], e.g. when it was
+    /// invalidated.
+    fn remove(&mut self, key: &K);
+
+    /// Picks the entry to drop next and forgets it, `None` when the policy tracks no entries.
+    fn evict(&mut self) -> Option<K>;
+
+    /// Whether `candidate` is worth caching when it displaces the next victim.
+    fn admit(&mut self, candidate: &K) -> bool {
+        let _ = candidate;
+        true
+    }
+}
+
+/// Evicts the least recently used entry.
+#[derive(Debug)]
+pub struct Lru<K> {
+    tick: u64,
+    ticks: HashMap<K, u64>,
+    order: BTreeMap<u64, K>,
+}
+
+impl<K> Default for Lru<K> {
+    fn default() -> Self {
+        Self {
+            tick: 0,
+            ticks: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Lru<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.ticks.contains_key(key)
+    }
+
+    fn victim(&self) -> Option<&K> {
+        self.order.first_key_value().map(|(_, key)| key)
+    }
+}
+
+impl<K: Hash + Eq + Clone + MaybeSend> EvictionPolicy<K> for Lru<K> {
+    fn access(&mut self, key: &K) {
+        if let Some(tick) = self.ticks.get_mut(key) {
+            self.tick += 1;
+            let key = self
+                .order
+                .remove(tick)
+                .expect("ticks and order are in sync");
+            *tick = self.tick;
+            self.order.insert(self.tick, key);
+        }
+    }
+
+    fn insert(&mut self, key: K) {
+        self.tick += 1;
+        if let Some(tick) = self.ticks.insert(key.clone(), self.tick) {
+            self.order.remove(&tick);
+        }
+        self.order.insert(self.tick, key);
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(tick) = self.ticks.remove(key) {
+            self.order.remove(&tick);
+        }
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        let (_, key) = self.order.pop_first()?;
+        self.ticks.remove(&key);
+        Some(key)
+    }
+}
+
+/// Segmented LRU: new entries start in a probation segment and move to a protected segment on
+/// their second access. Entries read only once are evicted first, so a single large scan does
+/// not flush the entries that are read over and over.
+#[derive(Debug)]
+pub struct Slru<K> {
+    probation: Lru<K>,
+    protected: Lru<K>,
+    protected_capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone + MaybeSend> Slru<K> {
+    /// Keeps up to `protected_capacity` entries in the protected segment, usually about 80% of
+    /// the cache.
+    pub fn new(protected_capacity: usize) -> Self {
+        Self {
+            probation: Lru::new(),
+            protected: Lru::new(),
+            protected_capacity,
+        }
+    }
+
+    fn victim(&self) -> Option<&K> {
+        self.probation.victim().or_else(|| self.protected.victim())
+    }
+}
+
+impl<K: Hash + Eq + Clone + MaybeSend> EvictionPolicy<K> for Slru<K> {
+    fn access(&mut self, key: &K) {
+        if self.protected.contains(key) {
+            self.protected.access(key);
+        } else if self.probation.contains(key) {
+            self.probation.remove(key);
+            self.protected.insert(key.clone());
+            if self.protected.len() > self.protected_capacity {
+                if let Some(demoted) = self.protected.evict() {
+                    self.probation.insert(demoted);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, key: K) {
+        self.protected.remove(&key);
+        self.probation.insert(key);
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.probation.remove(key);
+        self.protected.remove(key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        self.probation.evict().or_else(|| self.protected.evict())
+    }
+}
+
+/// Approximate access counts of a bounded number of keys, halved periodically so old
+/// popularity fades.
+#[derive(Debug)]
+struct FrequencySketch {
+    hasher: RandomState,
+    counters: Vec<u8>,
+    samples: usize,
+    reset_after: usize,
+}
+
+impl FrequencySketch {
+    const ROWS: u64 = 4;
+    const MAX: u8 = 15;
+
+    fn new(capacity: usize) -> Self {
+        let width = capacity.max(16).next_power_of_two();
+        Self {
+            hasher: RandomState::default(),
+            counters: vec![0; width],
+            samples: 0,
+            reset_after: width * 10,
+        }
+    }
+
+    fn slots<K: Hash>(&self, key: &K) -> impl Iterator<Item = usize> {
+        let mask = self.counters.len() - 1;
+        let hash = self.hasher.hash_one(key);
+        (0..Self::ROWS).map(move |row| {
+            let hash = hash.rotate_left(16 * row as u32) ^ row.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            hash as usize & mask
+        })
+    }
+
+    fn increment<K: Hash>(&mut self, key: &K) {
+        for slot in self.slots(key) {
+            let counter = &mut self.counters[slot];
+            *counter = (*counter + 1).min(Self::MAX);
+        }
+        self.samples += 1;
+        if self.samples >= self.reset_after {
+            self.counters.iter_mut().for_each(|counter| *counter /= 2);
+            self.samples /= 2;
+        }
+    }
+
+    fn estimate<K: Hash>(&self, key: &K) -> u8 {
+        self.slots(key)
+            .map(|slot| self.counters[slot])
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// [`Slru`] with a TinyLFU admission filter: when the cache is full, a new entry only displaces
+/// the next victim if it was requested more often recently. Entries seen once, like the pages of
+/// a scan, never push out popular ones.
+#[derive(Debug)]
+pub struct TinyLfu<K> {
+    main: Slru<K>,
+    sketch: FrequencySketch,
+}
+
+impl<K: Hash + Eq + Clone + MaybeSend> TinyLfu<K> {
+    /// Sized for a cache holding about `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            main: Slru::new(capacity * 4 / 5),
+            sketch: FrequencySketch::new(capacity),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone + MaybeSend> EvictionPolicy<K> for TinyLfu<K> {
+    fn access(&mut self, key: &K) {
+        self.sketch.increment(key);
+        self.main.access(key);
+    }
+
+    fn insert(&mut self, key: K) {
+        self.main.insert(key);
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.main.remove(key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        self.main.evict()
+    }
+
+    fn admit(&mut self, candidate: &K) -> bool {
+        match self.main.victim() {
+            Some(victim) => self.sketch.estimate(candidate) > self.sketch.estimate(victim),
+            None => true,
+        }
+    }
+}
+
+/// A set that answers "definitely absent" or "maybe present" in a fixed amount of memory.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    hasher: RandomState,
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Sized for `items` entries with the given false positive rate, e.g. `0.01`.
+    pub fn new(items: usize, false_positive_rate: f64) -> Self {
+        let items = items.max(1) as f64;
+        let rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let bits = (-items * rate.ln() / std::f64::consts::LN_2.powi(2)).ceil() as usize;
+        let hashes = ((bits as f64 / items) * std::f64::consts::LN_2).round() as u32;
+        Self {
+            hasher: RandomState::default(),
+            bits: vec![0; bits.div_ceil(64).max(1)],
+            hashes: hashes.max(1),
+        }
+    }
+
+    fn positions<K: Hash + ?Sized>(&self, key: &K) -> impl Iterator<Item = usize> {
+        let hash = self.hasher.hash_one(key);
+        let (h1, h2) = (hash as u32 as u64, (hash >> 32) | 1);
+        let len = self.bits.len() as u64 * 64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    pub fn insert<K: Hash + ?Sized>(&mut self, key: &K) {
+        for position in self.positions(key) {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    /// `false` if `key` was never inserted, `true` if it probably was.
+    pub fn may_contain<K: Hash + ?Sized>(&self, key: &K) -> bool {
+        self.positions(key)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+/// A cache that holds something about paths, like remembered misses or parsed footers, which must
+/// be dropped when the path is written or removed.
+pub trait Invalidate: MaybeSend + MaybeSync {
+    /// Drops everything cached about `path`.
+    fn invalidate(&self, path: &Path);
+}
+
+/// The caches stacked on the same storage, so a write through any of them invalidates the path in
+/// all of them right away instead of when their entries expire.
+///
+/// Clones share the same caches. Caches are held weakly and dropped from the set once they are
+/// dropped themselves. Writes that bypass every registered cache are not seen, those caches still
+/// serve stale entries until they expire.
+#[derive(Clone, Default)]
+pub struct Invalidations {
+    caches: Arc<Mutex<Vec<Weak<dyn Invalidate>>>>,
+}
+
+impl Invalidations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Invalidates paths in `cache` whenever they are invalidated through this set.
+    pub fn register<C: Invalidate + 'static>(&self, cache: &Arc<C>) {
+        let cache: Weak<C> = Arc::downgrade(cache);
+        self.caches.lock().unwrap().push(cache);
+    }
+
+    /// Drops everything cached about `path` in every registered cache.
+    pub fn invalidate(&self, path: &Path) {
+        let caches = {
+            let mut caches = self.caches.lock().unwrap();
+            caches.retain(|cache| cache.strong_count() > 0);
+            caches.iter().filter_map(Weak::upgrade).collect::<Vec<_>>()
+        };
+        for cache in caches {
+            cache.invalidate(path);
+        }
+    }
+}
+
+#[cfg(all(test, not(fusio_loom)))]
+mod tests {
+    use super::{BloomFilter, EvictionPolicy, Lru, Slru, TinyLfu};
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut lru = Lru::new();
+        lru.insert(1);
+        lru.insert(2);
+        lru.insert(3);
+        lru.access(&1);
+        lru.remove(&3);
+
+        assert_eq!(lru.evict(), Some(2));
+        assert_eq!(lru.evict(), Some(1));
+        assert_eq!(lru.evict(), None);
+    }
+
+    #[test]
+    fn slru_survives_scans() {
+        let mut slru = Slru::new(2);
+        slru.insert("hot");
+        slru.access(&"hot");
+        for key in ["a", "b", "c"] {
+            slru.insert(key);
+        }
+
+        assert_eq!(slru.evict(), Some("a"));
+        assert_eq!(slru.evict(), Some("b"));
+        assert_eq!(slru.evict(), Some("c"));
+        assert_eq!(slru.evict(), Some("hot"));
+    }
+
+    #[test]
+    fn tiny_lfu_rejects_rare_entries() {
+        let mut lfu = TinyLfu::new(2);
+        for _ in 0..3 {
+            lfu.access(&"hot");
+        }
+        lfu.insert("hot");
+
+        lfu.access(&"once");
+        assert!(!lfu.admit(&"once"));
+        for _ in 0..5 {
+            lfu.access(&"popular");
+        }
+        assert!(lfu.admit(&"popular"));
+    }
+
+    #[test]
+    fn bloom_filter_has_no_false_negatives() {
+        let mut bloom = BloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            bloom.insert(&i);
+        }
+        assert!((0..1000).all(|i| bloom.may_contain(&i)));
+
+        let false_positives = (1000..11000).filter(|i| bloom.may_contain(i)).count();
+        assert!(false_positives < 500, "{false_positives} false positives");
+    }
+}
+
+#[cfg(all(test, fusio_loom))]
+mod loom_tests {
+    use loom::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::{EvictionPolicy, Lru, Slru, TinyLfu};
+
+    /// Inserts two keys from two threads while a third evicts one and removes the key that was
+    /// there before, then checks that no key was evicted twice and none got lost.
+    fn check<P>(policy: P)
+    where
+        P: EvictionPolicy<u32> + Send + 'static,
+    {
+        let policy = Arc::new(Mutex::new(policy));
+        policy.lock().unwrap().insert(0);
+
+        let inserts = [1, 2]
+            .into_iter()
+            .map(|key| {
+                let policy = policy.clone();
+                thread::spawn(move || {
+                    let mut policy = policy.lock().unwrap();
+                    policy.access(&key);
+                    policy.insert(key);
+                })
+            })
+            .collect::<Vec<_>>();
+        let evicted = {
+            let mut policy = policy.lock().unwrap();
+            let evicted = policy.evict();
+            policy.remove(&0);
+            evicted
+        };
+        for insert in inserts {
+            insert.join().unwrap();
+        }
+
+        let mut keys = evicted.into_iter().collect::<Vec<_>>();
+        let mut policy = policy.lock().unwrap();
+        while let Some(key) = policy.evict() {
+            keys.push(key);
+        }
+        keys.sort();
+        let expected = match evicted {
+            Some(0) => vec![0, 1, 2],
+            _ => vec![1, 2],
+        };
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn loom_lru_evicts_each_key_once() {
+        loom::model(|| check(Lru::new()));
+    }
+
+    #[test]
+    fn loom_slru_evicts_each_key_once() {
+        loom::model(|| check(Slru::new(1)));
+    }
+
+    #[test]
+    fn loom_tiny_lfu_evicts_each_key_once() {
+        loom::model(|| check(TinyLfu::new(2)));
+    }
+}
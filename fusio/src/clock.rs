@@ -0,0 +1,137 @@
+//! Time source abstraction used by signers and time-aware layers.
+
+use std::{
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use crate::{MaybeSend, MaybeSync};
+
+pub trait Clock: MaybeSend + MaybeSync {
+    //! A source of wall-clock time.
+    //!
+    //! Components that stamp requests with the current time (e.g. AWS SigV4) read it through this
+    //! trait instead of calling [`SystemTime::now`] directly, so tests can pin the time and hosts
+    //! with a skewed clock can apply a correction.
+
+    fn now(&self) -> SystemTime;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> SystemTime {
+        C::now(self)
+    }
+}
+
+/// The operating system clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that always returns the same instant, mostly useful in tests.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock {
+    now: SystemTime,
+}
+
+impl FixedClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self { now }
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.now
+    }
+}
+
+/// A clock that shifts another clock by a correction offset which can be updated at runtime.
+#[derive(Debug, Default)]
+pub struct OffsetClock<C> {
+    inner: C,
+    offset_millis: AtomicI64,
+}
+
+impl<C> OffsetClock<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            offset_millis: AtomicI64::new(0),
+        }
+    }
+
+    /// The current correction in milliseconds, positive values move the clock forward.
+    pub fn offset_millis(&self) -> i64 {
+        self.offset_millis.load(Ordering::Relaxed)
+    }
+
+    pub fn set_offset_millis(&self, offset_millis: i64) {
+        self.offset_millis.store(offset_millis, Ordering::Relaxed);
+    }
+}
+
+impl<C: Clock> OffsetClock<C> {
+    /// Adjusts the correction so that this clock agrees with `reference`, e.g. the time reported
+    /// by a remote server.
+    pub fn synchronize(&self, reference: SystemTime) {
+        let offset_millis = match reference.duration_since(self.inner.now()) {
+            Ok(ahead) => ahead.as_millis() as i64,
+            Err(behind) => -(behind.duration().as_millis() as i64),
+        };
+        self.set_offset_millis(offset_millis);
+    }
+}
+
+impl<C: Clock> Clock for OffsetClock<C> {
+    fn now(&self) -> SystemTime {
+        let now = self.inner.now();
+        let offset = self.offset_millis();
+        if offset >= 0 {
+            now + Duration::from_millis(offset as u64)
+        } else {
+            now - Duration::from_millis(offset.unsigned_abs())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{Clock, FixedClock, OffsetClock};
+
+    #[test]
+    fn offset_clock_applies_correction() {
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = OffsetClock::new(FixedClock::new(base));
+        assert_eq!(clock.now(), base);
+
+        clock.set_offset_millis(1_500);
+        assert_eq!(clock.now(), base + Duration::from_millis(1_500));
+
+        clock.set_offset_millis(-2_000);
+        assert_eq!(clock.now(), base - Duration::from_secs(2));
+    }
+
+    #[test]
+    fn synchronize_to_reference() {
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = OffsetClock::new(FixedClock::new(base));
+
+        clock.synchronize(base + Duration::from_secs(900));
+        assert_eq!(clock.offset_millis(), 900_000);
+        assert_eq!(clock.now(), base + Duration::from_secs(900));
+
+        clock.synchronize(base - Duration::from_secs(60));
+        assert_eq!(clock.offset_millis(), -60_000);
+    }
+}
@@ -0,0 +1,169 @@
+//! Per-operation context, like the deadline of the statement a read belongs to, propagated to
+//! layers without threading it through every call.
+//!
+//! A [`Context`] is attached to a future with [`Context::scope`] and is visible to everything the
+//! future polls through [`Context::current`], so query engines can hand statement deadlines down
+//! to storage and layers can honor them. The context does not cross into tasks spawned by the
+//! future, those are scoped separately.
+
+use std::{
+    cell::RefCell,
+    future::Future,
+    io,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures_util::future::poll_fn;
+
+use crate::Error;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Context>>> = const { RefCell::new(None) };
+}
+
+/// How urgent an operation is, for layers that schedule or shed work.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Background work like compaction or prefetching, the first to be delayed.
+    Low,
+    #[default]
+    Normal,
+    /// Work a user is waiting for.
+    High,
+}
+
+/// What an operation is part of: when it has to be done by, how urgent it is, and free-form
+/// tags like a query id for tracing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Context {
+    pub deadline: Option<SystemTime>,
+    pub priority: Priority,
+    pub tags: Vec<(String, String)>,
+}
+
+impl Context {
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// The value of the last tag named `key`.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .rev()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The time left until the deadline at `now`, zero once it passed and `None` without one.
+    pub fn remaining(&self, now: SystemTime) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.duration_since(now).unwrap_or(Duration::ZERO))
+    }
+
+    /// Fails with [`io::ErrorKind::TimedOut`] if the deadline passed at `now`.
+    pub fn check_deadline(&self, now: SystemTime) -> Result<(), Error> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "deadline exceeded").into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The context of the scope the caller runs in, if any.
+    pub fn current() -> Option<Arc<Context>> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Runs `future` with this context as [`Context::current`]. Scopes nest, the innermost one
+    /// is current.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        let context = Arc::new(self);
+        let mut future = std::pin::pin!(future);
+        poll_fn(|cx| {
+            let _guard = Guard::enter(context.clone());
+            future.as_mut().poll(cx)
+        })
+        .await
+    }
+}
+
+/// Makes a context current until it is dropped, restoring the previous one even if the scoped
+/// future panics.
+struct Guard {
+    previous: Option<Arc<Context>>,
+}
+
+impl Guard {
+    fn enter(context: Arc<Context>) -> Self {
+        let previous = CURRENT.with(|current| current.borrow_mut().replace(context));
+        Self { previous }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{Context, Priority};
+
+    #[tokio::test]
+    async fn scopes_nest() {
+        assert_eq!(Context::current(), None);
+
+        let outer = Context::default().with_tag("query", "1");
+        let inner = Context::default()
+            .with_priority(Priority::High)
+            .with_tag("query", "1")
+            .with_tag("query", "2");
+        outer
+            .scope(async {
+                assert_eq!(Context::current().unwrap().tag("query"), Some("1"));
+                inner
+                    .scope(async {
+                        tokio::task::yield_now().await;
+                        let current = Context::current().unwrap();
+                        assert_eq!(current.tag("query"), Some("2"));
+                        assert_eq!(current.priority, Priority::High);
+                    })
+                    .await;
+                assert_eq!(Context::current().unwrap().tag("query"), Some("1"));
+            })
+            .await;
+        assert_eq!(Context::current(), None);
+    }
+
+    #[test]
+    fn checks_deadline() {
+        let deadline = UNIX_EPOCH + Duration::from_secs(10);
+        let context = Context::default().with_deadline(deadline);
+        assert_eq!(
+            context.remaining(UNIX_EPOCH + Duration::from_secs(4)),
+            Some(Duration::from_secs(6))
+        );
+        assert_eq!(context.remaining(deadline), Some(Duration::ZERO));
+        assert!(context.check_deadline(UNIX_EPOCH).is_ok());
+        assert!(context.check_deadline(deadline).is_err());
+        assert!(Context::default().check_deadline(deadline).is_ok());
+    }
+}
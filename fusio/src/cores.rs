@@ -0,0 +1,396 @@
+//! One backend per core for thread-per-core runtimes.
+//!
+//! Completion-based runtimes like tokio-uring and monoio run one ring per thread, and an
+//! operation completes on the thread that submitted it. A [`PerCore`] starts one thread per core,
+//! pins it to its core and builds a backend on it, then runs the jobs dispatched to that core
+//! there, so a file is only ever touched by one core and its completions never bounce between
+//! cores or NUMA nodes.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    future::Future,
+    hash::{Hash, Hasher},
+    mem,
+    rc::Rc,
+    sync::{mpsc, Arc, Mutex},
+    task::{Poll, Waker},
+    thread::{self, JoinHandle},
+};
+
+use futures_util::{
+    future::{poll_fn, LocalBoxFuture},
+    stream::FuturesUnordered,
+    StreamExt,
+};
+
+use crate::{path::Path, Error};
+
+/// The cores the current process is allowed to run on.
+#[cfg(target_os = "linux")]
+pub fn available_cores() -> Result<Vec<usize>, Error> {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok((0..libc::CPU_SETSIZE as usize)
+        .filter(|core| unsafe { libc::CPU_ISSET(*core, &set) })
+        .collect())
+}
+
+/// The cores the current process is allowed to run on.
+#[cfg(not(target_os = "linux"))]
+pub fn available_cores() -> Result<Vec<usize>, Error> {
+    Ok((0..thread::available_parallelism()?.get()).collect())
+}
+
+/// Pins the current thread to `core`. Memory the thread allocates afterwards is placed on the
+/// NUMA node of the core.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(core: usize) -> Result<(), Error> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(Error::Unsupported {
+            message: format!("core {core} is out of range"),
+        });
+    }
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    unsafe { libc::CPU_SET(core, &mut set) };
+    if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Pins the current thread to `core`. Memory the thread allocates afterwards is placed on the
+/// NUMA node of the core.
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_core: usize) -> Result<(), Error> {
+    Err(Error::Unsupported {
+        message: "pinning threads to cores is only supported on Linux".into(),
+    })
+}
+
+type Job<F> = Box<dyn FnOnce(Rc<F>) -> LocalBoxFuture<'static, ()> + Send>;
+
+struct Queue<F> {
+    jobs: Vec<Job<F>>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+struct Worker<F> {
+    core: usize,
+    queue: Arc<Mutex<Queue<F>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Runs one backend per core, each on its own thread pinned to its core, and dispatches jobs to
+/// them.
+///
+/// Backends are built on their thread once it is pinned, so they can hold state that cannot leave
+/// the thread, like the ring of a completion-based runtime, and allocate on the local NUMA node.
+/// Jobs dispatched by path always run on the same core, which keeps the files of a path, and the
+/// caches in front of them, on one core.
+///
+/// Dropping a [`PerCore`] lets every thread finish the jobs it was given and exit without waiting
+/// for them, [`PerCore::shutdown`] waits.
+pub struct PerCore<F> {
+    workers: Vec<Worker<F>>,
+}
+
+impl<F: 'static> PerCore<F> {
+    /// Starts a thread for every core in `cores` that runs a runtime with `runtime`, which blocks
+    /// on the future it is given, e.g. `|future| tokio_uring::start(future)`, and serves jobs with
+    /// the backend `backend` builds for its core.
+    ///
+    /// Fails if `cores` is empty or a thread cannot be pinned to its core.
+    pub fn start<R, B>(cores: Vec<usize>, runtime: R, backend: B) -> Result<Self, Error>
+    where
+        R: Fn(LocalBoxFuture<'static, ()>) + Send + Sync + 'static,
+        B: Fn(usize) -> F + Send + Sync + 'static,
+    {
+        if cores.is_empty() {
+            return Err(Error::Unsupported {
+                message: "no cores to start backends on".into(),
+            });
+        }
+        let runtime = Arc::new(runtime);
+        let backend = Arc::new(backend);
+        let mut this = Self {
+            workers: Vec::with_capacity(cores.len()),
+        };
+        for core in cores {
+            let queue = Arc::new(Mutex::new(Queue {
+                jobs: Vec::new(),
+                closed: false,
+                waker: None,
+            }));
+            let (pinned, started) = mpsc::channel();
+            let thread = thread::Builder::new()
+                .name(format!("fusio-core-{core}"))
+                .spawn({
+                    let queue = queue.clone();
+                    let runtime = runtime.clone();
+                    let backend = backend.clone();
+                    move || {
+                        let _closing = Closing(queue.clone());
+                        let result = pin_current_thread(core);
+                        let failed = result.is_err();
+                        let _ = pinned.send(result);
+                        if !failed {
+                            runtime(Box::pin(async move {
+                                serve(&queue, Rc::new(backend(core))).await
+                            }));
+                        }
+                    }
+                })?;
+            this.workers.push(Worker {
+                core,
+                queue,
+                thread: Some(thread),
+            });
+            started
+                .recv()
+                .map_err(|_| Error::Other("core thread panicked".into()))??;
+        }
+        Ok(this)
+    }
+
+    /// The cores jobs are dispatched to, in the order they were given to [`PerCore::start`].
+    pub fn cores(&self) -> impl Iterator<Item = usize> + '_ {
+        self.workers.iter().map(|worker| worker.core)
+    }
+
+    /// The index of the core that jobs for `path` are dispatched to.
+    pub fn index_of(&self, path: &Path) -> usize {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        (hasher.finish() % self.workers.len() as u64) as usize
+    }
+
+    /// Runs `job` with the backend of the core `path` belongs to, see [`PerCore::index_of`].
+    pub async fn dispatch<T, J, Fut>(&self, path: &Path, job: J) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        J: FnOnce(Rc<F>) -> Fut + Send + 'static,
+        Fut: Future<Output = T> + 'static,
+    {
+        self.run_on(self.index_of(path), job).await
+    }
+
+    /// Runs `job` with the backend of the core at `index`, alongside the other jobs of that core.
+    ///
+    /// Panics if `index` is out of range. Fails if the thread of the core stopped, e.g. because a
+    /// job panicked.
+    pub async fn run_on<T, J, Fut>(&self, index: usize, job: J) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        J: FnOnce(Rc<F>) -> Fut + Send + 'static,
+        Fut: Future<Output = T> + 'static,
+    {
+        let reply = Arc::new(Mutex::new(Reply {
+            value: None,
+            sent: false,
+            waker: None,
+        }));
+        let sender = Sender(reply.clone());
+        let job: Job<F> = Box::new(move |fs| {
+            Box::pin(async move {
+                let value = job(fs).await;
+                sender.send(value);
+            })
+        });
+
+        let waker = {
+            let mut queue = self.workers[index].queue.lock().unwrap();
+            if queue.closed {
+                return Err(stopped());
+            }
+            queue.jobs.push(job);
+            queue.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        poll_fn(|cx| {
+            let mut reply = reply.lock().unwrap();
+            match (reply.value.take(), reply.sent) {
+                (Some(value), _) => Poll::Ready(Ok(value)),
+                (None, true) => Poll::Ready(Err(stopped())),
+                (None, false) => {
+                    reply.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Lets every thread finish the jobs it was given and waits until all of them exited.
+    pub fn shutdown(mut self) {
+        self.close();
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl<F> PerCore<F> {
+    fn close(&self) {
+        for worker in &self.workers {
+            let waker = {
+                let mut queue = worker.queue.lock().unwrap();
+                queue.closed = true;
+                queue.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<F> Drop for PerCore<F> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Closes the queue of a thread once it exits, also when a job panicked, and fails the jobs left
+/// in it.
+struct Closing<F>(Arc<Mutex<Queue<F>>>);
+
+impl<F> Drop for Closing<F> {
+    fn drop(&mut self) {
+        let jobs = {
+            let mut queue = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            queue.closed = true;
+            mem::take(&mut queue.jobs)
+        };
+        drop(jobs);
+    }
+}
+
+fn stopped() -> Error {
+    Error::Other("the thread of the core stopped before the job finished".into())
+}
+
+/// Runs the jobs of `queue` concurrently until it is closed and every job finished.
+async fn serve<F>(queue: &Mutex<Queue<F>>, fs: Rc<F>) {
+    let mut running = FuturesUnordered::new();
+    poll_fn(|cx| {
+        let (jobs, closed) = {
+            let mut queue = queue.lock().unwrap();
+            queue.waker = Some(cx.waker().clone());
+            (mem::take(&mut queue.jobs), queue.closed)
+        };
+        running.extend(jobs.into_iter().map(|job| job(fs.clone())));
+        while let Poll::Ready(Some(())) = running.poll_next_unpin(cx) {}
+        match closed && running.is_empty() {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    })
+    .await
+}
+
+struct Reply<T> {
+    value: Option<T>,
+    sent: bool,
+    waker: Option<Waker>,
+}
+
+/// Hands the output of a job back to [`PerCore::run_on`], or tells it the job was dropped.
+struct Sender<T>(Arc<Mutex<Reply<T>>>);
+
+impl<T> Sender<T> {
+    fn send(self, value: T) {
+        self.0.lock().unwrap().value = Some(value);
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut reply = self.0.lock().unwrap();
+            reply.sent = true;
+            reply.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(
+        feature = "tokio",
+        not(feature = "completion-based"),
+        target_os = "linux"
+    ))]
+    #[tokio::test]
+    async fn runs_jobs_on_their_core() {
+        use tempfile::TempDir;
+
+        use super::{available_cores, PerCore};
+        use crate::{
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+            Read, Write,
+        };
+
+        let cores = available_cores().unwrap();
+        let cores = cores[..cores.len().min(2)].to_vec();
+        let pool = PerCore::start(
+            cores.clone(),
+            |future| {
+                tokio::runtime::Builder::new_current_thread()
+                    .build()
+                    .unwrap()
+                    .block_on(future)
+            },
+            |_| TokioFs,
+        )
+        .unwrap();
+        assert_eq!(pool.cores().collect::<Vec<_>>(), cores);
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("file")).unwrap();
+        let index = pool.index_of(&path);
+        let core = pool
+            .dispatch(&path, {
+                let path = path.clone();
+                move |fs| async move {
+                    let mut file = fs
+                        .open_options(&path, OpenOptions::default().create(true))
+                        .await
+                        .unwrap();
+                    let (result, _) = file.write_all(&b"fusio"[..]).await;
+                    result.unwrap();
+                    file.close().await.unwrap();
+                    unsafe { libc::sched_getcpu() }
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(core as usize, cores[index]);
+
+        let read = pool
+            .run_on(cores.len() - 1, move |fs| async move {
+                let mut file = fs.open(&path).await.unwrap();
+                let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+                result.unwrap();
+                buf
+            })
+            .await
+            .unwrap();
+        assert_eq!(read, b"fusio");
+
+        assert!(pool.run_on(0, |_| async { panic!() }).await.is_err());
+        pool.shutdown();
+    }
+}
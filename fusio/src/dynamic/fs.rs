@@ -1,11 +1,11 @@
-use std::pin::Pin;
-
+use async_stream::stream;
 use futures_core::Stream;
+use futures_util::StreamExt;
 
-use super::MaybeSendFuture;
+use super::{BoxFuture, BoxStream};
 use crate::{
     buf::IoBufMut,
-    fs::{FileMeta, Fs, OpenOptions},
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
     path::Path,
     DynRead, DynWrite, Error, IoBuf, MaybeSend, MaybeSync, Read, Write,
 };
@@ -29,6 +29,16 @@ impl<'read> Read for Box<dyn DynFile + 'read> {
     async fn size(&self) -> Result<u64, Error> {
         DynRead::size(self.as_ref()).await
     }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        let (result, buf) =
+            DynRead::read_at(self.as_mut(), unsafe { buf.slice_mut_unchecked(..) }, pos).await;
+        (result, unsafe { B::recover_from_slice_mut(buf) })
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        DynRead::read_batch(self.as_mut(), ranges).await
+    }
 }
 
 impl<'write> Write for Box<dyn DynFile + 'write> {
@@ -45,13 +55,31 @@ impl<'write> Write for Box<dyn DynFile + 'write> {
     async fn close(&mut self) -> Result<(), Error> {
         DynWrite::close(self.as_mut()).await
     }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        let bufs = bufs
+            .into_iter()
+            .map(|buf| unsafe { buf.slice_unchecked(..) })
+            .collect();
+        let (result, bufs) = DynWrite::write_batch(self.as_mut(), bufs).await;
+        (
+            result,
+            bufs.into_iter()
+                .map(|buf| unsafe { B::recover_from_slice(buf) })
+                .collect(),
+        )
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        DynWrite::barrier(self.as_mut()).await
+    }
 }
 
 pub trait DynFs: MaybeSend + MaybeSync {
     fn open<'s, 'path: 's>(
         &'s self,
         path: &'path Path,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = Result<Box<dyn DynFile>, Error>> + 's>> {
+    ) -> BoxFuture<'s, Result<Box<dyn DynFile>, Error>> {
         self.open_options(path, OpenOptions::default())
     }
 
@@ -59,31 +87,54 @@ pub trait DynFs: MaybeSend + MaybeSync {
         &'s self,
         path: &'path Path,
         options: OpenOptions,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = Result<Box<dyn DynFile>, Error>> + 's>>;
+    ) -> BoxFuture<'s, Result<Box<dyn DynFile>, Error>>;
 
     fn create_dir_all<'s, 'path: 's>(
         &'s self,
         path: &'path Path,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = Result<(), Error>> + 's>>;
+    ) -> BoxFuture<'s, Result<(), Error>>;
 
     fn list<'s, 'path: 's>(
         &'s self,
         path: &'path Path,
-    ) -> Pin<
-        Box<
-            dyn MaybeSendFuture<
-                    Output = Result<
-                        Pin<Box<dyn Stream<Item = Result<FileMeta, Error>> + 's>>,
-                        Error,
-                    >,
-                > + 's,
-        >,
-    >;
-
-    fn remove<'s, 'path: 's>(
+    ) -> BoxFuture<'s, Result<BoxStream<'s, Result<FileMeta, Error>>, Error>>;
+
+    fn list_options<'s, 'path: 's>(
         &'s self,
         path: &'path Path,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = Result<(), Error>> + 's>>;
+        options: ListOptions,
+    ) -> BoxFuture<'s, Result<BoxStream<'s, Result<FileMeta, Error>>, Error>>;
+
+    fn remove<'s, 'path: 's>(&'s self, path: &'path Path) -> BoxFuture<'s, Result<(), Error>>;
+
+    fn remove_batch(&self, paths: Vec<Path>) -> BoxFuture<'_, Result<Vec<(Path, Error)>, Error>>;
+
+    fn copy<'s, 'path: 's>(
+        &'s self,
+        from: &'path Path,
+        to: &'path Path,
+    ) -> BoxFuture<'s, Result<(), Error>>;
+
+    fn rename_new<'s, 'path: 's>(
+        &'s self,
+        from: &'path Path,
+        to: &'path Path,
+    ) -> BoxFuture<'s, Result<(), Error>>;
+
+    fn stat<'s, 'path: 's>(&'s self, path: &'path Path) -> BoxFuture<'s, Result<FileMeta, Error>>;
+
+    fn create_temp<'s, 'path: 's>(
+        &'s self,
+        prefix: &'path Path,
+    ) -> BoxFuture<'s, Result<(Box<dyn DynFile>, TempGuard), Error>>;
+
+    fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> BoxFuture<'_, Result<BoxStream<'_, Result<WatchEvent, Error>>, Error>>;
+
+    fn shutdown(&self) -> BoxFuture<'_, Result<(), Error>>;
 }
 
 impl<F: Fs> DynFs for F {
@@ -91,7 +142,7 @@ impl<F: Fs> DynFs for F {
         &'s self,
         path: &'path Path,
         options: OpenOptions,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = Result<Box<dyn DynFile>, Error>> + 's>> {
+    ) -> BoxFuture<'s, Result<Box<dyn DynFile>, Error>> {
         Box::pin(async move {
             let file = F::open_options(self, path, options).await?;
             Ok(Box::new(file) as Box<dyn DynFile>)
@@ -101,40 +152,188 @@ impl<F: Fs> DynFs for F {
     fn create_dir_all<'s, 'path: 's>(
         &'s self,
         path: &'path Path,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = Result<(), Error>> + 's>> {
-        Box::pin(F::create_dir_all(path))
+    ) -> BoxFuture<'s, Result<(), Error>> {
+        Box::pin(F::create_dir_all(self, path))
     }
 
     fn list<'s, 'path: 's>(
         &'s self,
         path: &'path Path,
-    ) -> Pin<
-        Box<
-            dyn MaybeSendFuture<
-                    Output = Result<
-                        Pin<Box<dyn Stream<Item = Result<FileMeta, Error>> + 's>>,
-                        Error,
-                    >,
-                > + 's,
-        >,
-    > {
+    ) -> BoxFuture<'s, Result<BoxStream<'s, Result<FileMeta, Error>>, Error>> {
         Box::pin(async move {
             let stream = F::list(self, path).await?;
-            Ok(Box::pin(stream) as Pin<Box<dyn Stream<Item = Result<FileMeta, Error>>>>)
+            Ok(Box::pin(stream) as BoxStream<'s, Result<FileMeta, Error>>)
         })
     }
 
-    fn remove<'s, 'path: 's>(
+    fn list_options<'s, 'path: 's>(
         &'s self,
         path: &'path Path,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = Result<(), Error>> + 's>> {
+        options: ListOptions,
+    ) -> BoxFuture<'s, Result<BoxStream<'s, Result<FileMeta, Error>>, Error>> {
+        Box::pin(async move {
+            let stream = F::list_options(self, path, options).await?;
+            Ok(Box::pin(stream) as BoxStream<'s, Result<FileMeta, Error>>)
+        })
+    }
+
+    fn remove<'s, 'path: 's>(&'s self, path: &'path Path) -> BoxFuture<'s, Result<(), Error>> {
         Box::pin(F::remove(self, path))
     }
+
+    fn remove_batch(&self, paths: Vec<Path>) -> BoxFuture<'_, Result<Vec<(Path, Error)>, Error>> {
+        Box::pin(F::remove_batch(self, paths))
+    }
+
+    fn copy<'s, 'path: 's>(
+        &'s self,
+        from: &'path Path,
+        to: &'path Path,
+    ) -> BoxFuture<'s, Result<(), Error>> {
+        Box::pin(F::copy(self, from, to))
+    }
+
+    fn rename_new<'s, 'path: 's>(
+        &'s self,
+        from: &'path Path,
+        to: &'path Path,
+    ) -> BoxFuture<'s, Result<(), Error>> {
+        Box::pin(F::rename_new(self, from, to))
+    }
+
+    fn stat<'s, 'path: 's>(&'s self, path: &'path Path) -> BoxFuture<'s, Result<FileMeta, Error>> {
+        Box::pin(F::stat(self, path))
+    }
+
+    fn create_temp<'s, 'path: 's>(
+        &'s self,
+        prefix: &'path Path,
+    ) -> BoxFuture<'s, Result<(Box<dyn DynFile>, TempGuard), Error>> {
+        Box::pin(async move {
+            let (file, guard) = F::create_temp(self, prefix).await?;
+            Ok((Box::new(file) as Box<dyn DynFile>, guard))
+        })
+    }
+
+    fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> BoxFuture<'_, Result<BoxStream<'_, Result<WatchEvent, Error>>, Error>> {
+        Box::pin(async move {
+            let stream = F::watch(self, path, options).await?;
+            Ok(Box::pin(stream) as BoxStream<'_, Result<WatchEvent, Error>>)
+        })
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(F::shutdown(self))
+    }
+}
+
+/// Lets type erased backends be wrapped again, e.g. by layers written against [`Fs`].
+///
+/// Listings are opened lazily, so an error of opening one is reported as the first item of the
+/// stream.
+impl<'fs> Fs for Box<dyn DynFs + 'fs> {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        DynFs::open_options(self.as_ref(), path, options).await
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        DynFs::create_dir_all(self.as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Ok(stream! {
+            let mut listing = DynFs::list(self.as_ref(), path).await?;
+            while let Some(meta) = listing.next().await {
+                yield meta;
+            }
+        })
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Ok(stream! {
+            let mut listing = DynFs::list_options(self.as_ref(), path, options).await?;
+            while let Some(meta) = listing.next().await {
+                yield meta;
+            }
+        })
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        DynFs::remove(self.as_ref(), path).await
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        DynFs::remove_batch(self.as_ref(), paths).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::copy(self.as_ref(), from, to).await
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::rename_new(self.as_ref(), from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        DynFs::stat(self.as_ref(), path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        DynFs::create_temp(self.as_ref(), prefix).await
+    }
+
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.as_ref()).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn test_dyn_remove_batch() {
+        use tempfile::TempDir;
+
+        use super::DynFs;
+        use crate::{disk::TokioFs, fs::Fs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a"), b"a").unwrap();
+        std::fs::write(dir.path().join("b"), b"b").unwrap();
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+        let fs: Box<dyn DynFs> = Box::new(TokioFs);
+
+        let failed = Fs::remove_batch(&fs, vec![path("a"), path("missing"), path("b")])
+            .await
+            .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, path("missing"));
+        assert!(!dir.path().join("a").exists());
+        assert!(!dir.path().join("b").exists());
+    }
+
     #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
     #[tokio::test]
     async fn test_dyn_fs() {
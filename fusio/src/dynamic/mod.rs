@@ -1,4 +1,11 @@
 //! Dyn compatible(object safety) version of [`Read`], [`Write`] and others.
+//!
+//! The traits of this module return boxed futures instead of using `async fn` in traits, so they
+//! double as the stable facade of fusio: a backend can implement [`DynRead`], [`DynWrite`] and
+//! [`DynFs`] by hand with `Box::pin(async move { .. })`, e.g. in a library that does not want to
+//! rely on `async fn` in traits or needs trait objects. The boxed types (`Box<dyn DynRead>`,
+//! `Box<dyn DynWrite>`, `Box<dyn DynFile>` and `Box<dyn DynFs>`) implement [`Read`], [`Write`] and
+//! [`crate::fs::Fs`] again, so such backends work with every layer and utility of fusio.
 
 #[cfg(feature = "fs")]
 pub mod fs;
@@ -7,47 +14,67 @@ use std::{future::Future, pin::Pin};
 
 #[cfg(feature = "fs")]
 pub use fs::{DynFile, DynFs};
+use futures_core::Stream;
 
 use crate::{
     buf::{Slice, SliceMut},
-    Error, MaybeSend, MaybeSync, Read, Write,
+    Error, IoBuf, IoBufMut, MaybeSend, MaybeSync, Read, Write,
 };
 
 pub trait MaybeSendFuture: Future + MaybeSend {}
 
 impl<F> MaybeSendFuture for F where F: Future + MaybeSend {}
 
+/// The future returned by the methods of the dyn compatible traits.
+pub type BoxFuture<'a, T> = Pin<Box<dyn MaybeSendFuture<Output = T> + 'a>>;
+
+/// The stream returned by the listings of [`DynFs`].
+pub type BoxStream<'a, T> = Pin<Box<dyn Stream<Item = T> + 'a>>;
+
 pub trait DynWrite: MaybeSend {
     //! Dyn compatible(object safety) version of [`Write`].
     //! All implementations of [`Write`] has already implemented this trait.
-    //! Also, all implementations of [`DynWrite`] has already implemented [`Write`].
-    //! User should not use this trait directly.
+    //! Types that do not implement [`Write`] may implement this trait directly instead, and
+    //! `Box<dyn DynWrite>` implements [`Write`] again.
 
-    fn write_all(
-        &mut self,
-        buf: Slice,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = (Result<(), Error>, Slice)> + '_>>;
+    fn write_all(&mut self, buf: Slice) -> BoxFuture<'_, (Result<(), Error>, Slice)>;
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), Error>>;
 
-    fn flush(&mut self) -> Pin<Box<dyn MaybeSendFuture<Output = Result<(), Error>> + '_>>;
+    fn close(&mut self) -> BoxFuture<'_, Result<(), Error>>;
 
-    fn close(&mut self) -> Pin<Box<dyn MaybeSendFuture<Output = Result<(), Error>> + '_>>;
+    fn write_batch(&mut self, bufs: Vec<Slice>) -> BoxFuture<'_, (Result<(), Error>, Vec<Slice>)>;
+
+    /// See [`Write::barrier`], fails with [`Error::Unsupported`] unless implemented.
+    fn barrier(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async {
+            Err(Error::Unsupported {
+                message: "this writer does not implement barrier".into(),
+            })
+        })
+    }
 }
 
 impl<W: Write> DynWrite for W {
-    fn write_all(
-        &mut self,
-        buf: Slice,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = (Result<(), Error>, Slice)> + '_>> {
+    fn write_all(&mut self, buf: Slice) -> BoxFuture<'_, (Result<(), Error>, Slice)> {
         Box::pin(W::write_all(self, buf))
     }
 
-    fn flush(&mut self) -> Pin<Box<dyn MaybeSendFuture<Output = Result<(), Error>> + '_>> {
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), Error>> {
         Box::pin(W::flush(self))
     }
 
-    fn close(&mut self) -> Pin<Box<dyn MaybeSendFuture<Output = Result<(), Error>> + '_>> {
+    fn close(&mut self) -> BoxFuture<'_, Result<(), Error>> {
         Box::pin(W::close(self))
     }
+
+    fn write_batch(&mut self, bufs: Vec<Slice>) -> BoxFuture<'_, (Result<(), Error>, Vec<Slice>)> {
+        Box::pin(W::write_batch(self, bufs))
+    }
+
+    fn barrier(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(W::barrier(self))
+    }
 }
 
 pub trait DynRead: MaybeSend + MaybeSync {
@@ -58,15 +85,26 @@ pub trait DynRead: MaybeSend + MaybeSync {
         &mut self,
         buf: SliceMut,
         pos: u64,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = (Result<(), Error>, SliceMut)> + '_>>;
+    ) -> BoxFuture<'_, (Result<(), Error>, SliceMut)>;
 
     fn read_to_end_at(
         &mut self,
         buf: Vec<u8>,
         pos: u64,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = (Result<(), Error>, Vec<u8>)> + '_>>;
+    ) -> BoxFuture<'_, (Result<(), Error>, Vec<u8>)>;
+
+    fn size(&self) -> BoxFuture<'_, Result<u64, Error>>;
+
+    fn read_at(
+        &mut self,
+        buf: SliceMut,
+        pos: u64,
+    ) -> BoxFuture<'_, (Result<usize, Error>, SliceMut)>;
 
-    fn size(&self) -> Pin<Box<dyn MaybeSendFuture<Output = Result<u64, Error>> + '_>>;
+    fn read_batch(
+        &mut self,
+        ranges: Vec<(u64, usize)>,
+    ) -> BoxFuture<'_, Result<Vec<Vec<u8>>, Error>>;
 }
 
 impl<R> DynRead for R
@@ -77,7 +115,7 @@ where
         &mut self,
         buf: SliceMut,
         pos: u64,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = (Result<(), Error>, SliceMut)> + '_>> {
+    ) -> BoxFuture<'_, (Result<(), Error>, SliceMut)> {
         Box::pin(async move { R::read_exact_at(self, buf, pos).await })
     }
 
@@ -85,11 +123,194 @@ where
         &mut self,
         buf: Vec<u8>,
         pos: u64,
-    ) -> Pin<Box<dyn MaybeSendFuture<Output = (Result<(), Error>, Vec<u8>)> + '_>> {
+    ) -> BoxFuture<'_, (Result<(), Error>, Vec<u8>)> {
         Box::pin(async move { R::read_to_end_at(self, buf, pos).await })
     }
 
-    fn size(&self) -> Pin<Box<dyn MaybeSendFuture<Output = Result<u64, Error>> + '_>> {
+    fn size(&self) -> BoxFuture<'_, Result<u64, Error>> {
         Box::pin(R::size(self))
     }
+
+    fn read_at(
+        &mut self,
+        buf: SliceMut,
+        pos: u64,
+    ) -> BoxFuture<'_, (Result<usize, Error>, SliceMut)> {
+        Box::pin(async move { R::read_at(self, buf, pos).await })
+    }
+
+    fn read_batch(
+        &mut self,
+        ranges: Vec<(u64, usize)>,
+    ) -> BoxFuture<'_, Result<Vec<Vec<u8>>, Error>> {
+        Box::pin(R::read_batch(self, ranges))
+    }
+}
+
+impl<'write> Write for Box<dyn DynWrite + 'write> {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        let (result, buf) =
+            DynWrite::write_all(self.as_mut(), unsafe { buf.slice_unchecked(..) }).await;
+        (result, unsafe { B::recover_from_slice(buf) })
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        DynWrite::flush(self.as_mut()).await
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        DynWrite::close(self.as_mut()).await
+    }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        let bufs = bufs
+            .into_iter()
+            .map(|buf| unsafe { buf.slice_unchecked(..) })
+            .collect();
+        let (result, bufs) = DynWrite::write_batch(self.as_mut(), bufs).await;
+        (
+            result,
+            bufs.into_iter()
+                .map(|buf| unsafe { B::recover_from_slice(buf) })
+                .collect(),
+        )
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        DynWrite::barrier(self.as_mut()).await
+    }
+}
+
+impl<'read> Read for Box<dyn DynRead + 'read> {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        let (result, buf) =
+            DynRead::read_exact_at(self.as_mut(), unsafe { buf.slice_mut_unchecked(..) }, pos)
+                .await;
+        (result, unsafe { B::recover_from_slice_mut(buf) })
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        DynRead::read_to_end_at(self.as_mut(), buf, pos).await
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        DynRead::size(self.as_ref()).await
+    }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        let (result, buf) =
+            DynRead::read_at(self.as_mut(), unsafe { buf.slice_mut_unchecked(..) }, pos).await;
+        (result, unsafe { B::recover_from_slice_mut(buf) })
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        DynRead::read_batch(self.as_mut(), ranges).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoxFuture, DynRead, DynWrite};
+    use crate::{
+        buf::{Slice, SliceMut},
+        Error, IoBuf, IoBufMut, Read, Write,
+    };
+
+    /// Implements the boxed traits by hand, without `async fn` in traits.
+    struct Memory(Vec<u8>);
+
+    impl DynWrite for Memory {
+        fn write_all(&mut self, buf: Slice) -> BoxFuture<'_, (Result<(), Error>, Slice)> {
+            self.0.extend_from_slice(buf.as_slice());
+            Box::pin(async move { (Ok(()), buf) })
+        }
+
+        fn flush(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn close(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn write_batch(
+            &mut self,
+            bufs: Vec<Slice>,
+        ) -> BoxFuture<'_, (Result<(), Error>, Vec<Slice>)> {
+            for buf in &bufs {
+                self.0.extend_from_slice(buf.as_slice());
+            }
+            Box::pin(async move { (Ok(()), bufs) })
+        }
+    }
+
+    impl DynRead for Memory {
+        fn read_exact_at(
+            &mut self,
+            mut buf: SliceMut,
+            pos: u64,
+        ) -> BoxFuture<'_, (Result<(), Error>, SliceMut)> {
+            let (pos, len) = (pos as usize, buf.bytes_init());
+            let result = match self.0.get(pos..pos + len) {
+                Some(data) => {
+                    buf.as_slice_mut().copy_from_slice(data);
+                    Ok(())
+                }
+                None => Err(Error::UnexpectedEof {
+                    requested: len as u64,
+                    available: self.0.len().saturating_sub(pos) as u64,
+                }),
+            };
+            Box::pin(async move { (result, buf) })
+        }
+
+        fn read_to_end_at(
+            &mut self,
+            mut buf: Vec<u8>,
+            pos: u64,
+        ) -> BoxFuture<'_, (Result<(), Error>, Vec<u8>)> {
+            buf.extend_from_slice(self.0.get(pos as usize..).unwrap_or_default());
+            Box::pin(async move { (Ok(()), buf) })
+        }
+
+        fn size(&self) -> BoxFuture<'_, Result<u64, Error>> {
+            Box::pin(async { Ok(self.0.len() as u64) })
+        }
+
+        fn read_at(
+            &mut self,
+            mut buf: SliceMut,
+            pos: u64,
+        ) -> BoxFuture<'_, (Result<usize, Error>, SliceMut)> {
+            let data = self.0.get(pos as usize..).unwrap_or_default();
+            let len = buf.bytes_init().min(data.len());
+            buf.as_slice_mut()[..len].copy_from_slice(&data[..len]);
+            Box::pin(async move { (Ok(len), buf) })
+        }
+
+        fn read_batch(
+            &mut self,
+            ranges: Vec<(u64, usize)>,
+        ) -> BoxFuture<'_, Result<Vec<Vec<u8>>, Error>> {
+            let bufs = ranges
+                .into_iter()
+                .map(|(pos, len)| self.0[pos as usize..pos as usize + len].to_vec())
+                .collect();
+            Box::pin(async move { Ok(bufs) })
+        }
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn boxed_traits_without_afit() {
+        let mut writer: Box<dyn DynWrite> = Box::new(Memory(Vec::new()));
+        let (result, _) = Write::write_all(&mut writer, &b"hello"[..]).await;
+        result.unwrap();
+
+        let mut reader: Box<dyn DynRead> = Box::new(Memory(b"hello, fusio".to_vec()));
+        assert_eq!(Read::size(&reader).await.unwrap(), 12);
+        let (result, buf) = Read::read_exact_at(&mut reader, vec![0; 5], 7).await;
+        result.unwrap();
+        assert_eq!(buf, b"fusio");
+    }
 }
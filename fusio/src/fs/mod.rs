@@ -2,18 +2,136 @@
 //! different file systems.
 
 mod options;
+mod temp;
+mod watch;
 
-use std::future::Future;
+use std::{future::Future, time::SystemTime};
 
 use futures_core::Stream;
 pub use options::*;
+pub(crate) use temp::temp_path;
+pub use temp::TempGuard;
+pub use watch::*;
 
 use crate::{path::Path, Error, MaybeSend, MaybeSync, Read, Write};
 
-#[derive(Debug)]
+/// The metadata of a listed or stat'ed entry.
+///
+/// New attributes may be added in minor releases, so backends outside this crate build it with
+/// [`FileMeta::new`] and the setters for what they report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct FileMeta {
     pub path: Path,
     pub size: u64,
+    pub kind: EntryKind,
+    /// Whether the entry is a symbolic link. Always `false` on backends without link support.
+    pub is_symlink: bool,
+    /// Ownership and mode bits, only reported by local backends.
+    pub permissions: Option<Permissions>,
+    /// When the entry was last modified, if the backend reports it.
+    pub last_modified: Option<SystemTime>,
+    /// The entity tag of an object as the object store reports it, quotes included, e.g. for
+    /// conditional requests or to tell whether a cached copy is stale.
+    pub etag: Option<String>,
+    /// The media type of an object, only reported by object stores.
+    pub content_type: Option<String>,
+    /// The CRC-32C checksum of the whole content, only reported by object stores that keep one,
+    /// e.g. by [`Fs::stat`] on S3 for objects uploaded with the `CRC32C` checksum algorithm in a
+    /// single request.
+    pub crc32c: Option<u32>,
+}
+
+impl FileMeta {
+    /// A file of `size` bytes at `path` that reports none of the optional attributes.
+    pub fn new(path: Path, size: u64) -> Self {
+        Self {
+            path,
+            size,
+            kind: EntryKind::File,
+            is_symlink: false,
+            permissions: None,
+            last_modified: None,
+            etag: None,
+            content_type: None,
+            crc32c: None,
+        }
+    }
+
+    pub fn kind(mut self, kind: EntryKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn symlink(mut self, is_symlink: bool) -> Self {
+        self.is_symlink = is_symlink;
+        self
+    }
+
+    pub fn permissions(mut self, permissions: Option<Permissions>) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    pub fn last_modified(mut self, last_modified: Option<SystemTime>) -> Self {
+        self.last_modified = last_modified;
+        self
+    }
+
+    pub fn etag(mut self, etag: Option<String>) -> Self {
+        self.etag = etag;
+        self
+    }
+
+    pub fn content_type(mut self, content_type: Option<String>) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    pub fn crc32c(mut self, crc32c: Option<u32>) -> Self {
+        self.crc32c = crc32c;
+        self
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == EntryKind::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Directory
+    }
+}
+
+/// What a listed entry refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    /// A local directory, or on object stores a zero-length `key/` marker.
+    Directory,
+}
+
+/// Access permissions of a file.
+///
+/// `mode`, `uid` and `gid` are only available on Unix. `readonly` is available everywhere and is
+/// the only attribute Windows understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub readonly: bool,
+}
+
+impl Permissions {
+    /// Permissions that only carry POSIX mode bits, e.g. `0o644`.
+    pub fn from_mode(mode: u32) -> Self {
+        Self {
+            mode: Some(mode & 0o7777),
+            uid: None,
+            gid: None,
+            readonly: mode & 0o222 == 0,
+        }
+    }
 }
 
 pub trait Fs: MaybeSend + MaybeSync {
@@ -31,12 +149,164 @@ pub trait Fs: MaybeSend + MaybeSync {
         options: OpenOptions,
     ) -> impl Future<Output = Result<Self::File, Error>> + MaybeSend;
 
-    fn create_dir_all(path: &Path) -> impl Future<Output = Result<(), Error>> + MaybeSend;
+    fn create_dir_all(&self, path: &Path) -> impl Future<Output = Result<(), Error>> + MaybeSend;
 
+    /// Lists the entries under `path`.
+    ///
+    /// The local backends list the direct children of a directory sorted by file name, S3 lists
+    /// every key under the prefix in lexicographic order of their UTF-8 bytes. Other backends
+    /// should document their order if they cannot provide the same guarantee.
+    ///
+    /// Errors of opening the listing, like a missing local directory, are returned either here or
+    /// as the first item of the stream, e.g. by type erased backends. Callers that treat them
+    /// specially have to check both.
     fn list(
         &self,
         path: &Path,
     ) -> impl Future<Output = Result<impl Stream<Item = Result<FileMeta, Error>>, Error>> + MaybeSend;
 
+    /// Lists one page of the entries under `path`, see [`ListOptions`].
+    ///
+    /// The default implementation filters the full listing client side, backends that support
+    /// server side pagination should override it.
+    fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> impl Future<Output = Result<impl Stream<Item = Result<FileMeta, Error>>, Error>> + MaybeSend
+    {
+        async move { Ok(options.apply(self.list(path).await?)) }
+    }
+
     fn remove(&self, path: &Path) -> impl Future<Output = Result<(), Error>> + MaybeSend;
+
+    /// Removes every path in `paths` and returns the ones that could not be removed with the
+    /// reason, so one failure does not stop the rest of the batch. An error is only returned if
+    /// the batch could not be carried out at all.
+    ///
+    /// The default implementation removes the paths one by one, backends with a bulk delete API,
+    /// like S3 DeleteObjects, should override it.
+    fn remove_batch(
+        &self,
+        paths: Vec<Path>,
+    ) -> impl Future<Output = Result<Vec<(Path, Error)>, Error>> + MaybeSend {
+        async move {
+            let mut failed = Vec::new();
+            for path in paths {
+                if let Err(e) = self.remove(&path).await {
+                    failed.push((path, e));
+                }
+            }
+            Ok(failed)
+        }
+    }
+
+    /// Copies the file at `from` to `to`, replacing `to`.
+    ///
+    /// The default implementation reads the file through the client and writes it back, backends
+    /// that can copy without moving the data, like S3 CopyObject, should override it.
+    fn copy(&self, from: &Path, to: &Path) -> impl Future<Output = Result<(), Error>> + MaybeSend {
+        async move {
+            let mut reader = self.open_options(from, OpenOptions::default()).await?;
+            let (result, buf) = reader.read_to_end_at(Vec::new(), 0).await;
+            result?;
+
+            let mut writer = self
+                .open_options(to, OpenOptions::default().create(true).truncate(true))
+                .await?;
+            let (result, _) = writer.write_all(buf).await;
+            result?;
+            writer.close().await
+        }
+    }
+
+    /// Moves the file at `from` to `to`, failing with [`Error::AlreadyExists`] when `to` exists.
+    /// Readers of `to` see the whole file at once, so a file written under a temporary name can be
+    /// published without anyone reading it half written, and of several writers publishing the
+    /// same name only one succeeds.
+    ///
+    /// The default implementation writes the content to `to` with [`OpenOptions::create_new`] and
+    /// removes `from`, which is atomic on object stores that store a file when it is closed. Local
+    /// backends override it with a hard link.
+    fn rename_new(
+        &self,
+        from: &Path,
+        to: &Path,
+    ) -> impl Future<Output = Result<(), Error>> + MaybeSend {
+        rename_by_copy(self, from, to)
+    }
+
+    /// Returns the metadata of the file at `path`, including its modification time, entity tag and
+    /// media type where the backend reports them.
+    ///
+    /// The default implementation opens the file and only reports its size, backends should
+    /// override it.
+    fn stat(&self, path: &Path) -> impl Future<Output = Result<FileMeta, Error>> + MaybeSend {
+        async move {
+            let file = self.open_options(path, OpenOptions::default()).await?;
+            Ok(FileMeta::new(path.clone(), file.size().await?))
+        }
+    }
+
+    /// Watches the entries under `path` for changes. Dropping the stream stops watching.
+    ///
+    /// The default implementation lists `path` every [`WatchOptions::interval`] with a
+    /// [`PollWatcher`], taking the baseline when the stream is first polled, which works on
+    /// every backend including object stores. Local backends with change notifications, like
+    /// `TokioFs` with the `watch` feature, override it and ignore the options.
+    fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> impl Future<Output = Result<impl Stream<Item = Result<WatchEvent, Error>>, Error>> + MaybeSend
+    {
+        async move { watch::watch_by_polling(self, path, options) }
+    }
+
+    /// Creates a new file named `{prefix}-{uuid}` for spilling or staging writes. See
+    /// [`TempGuard`] for when the file is cleaned up, and for local files that only get their name
+    /// when linked.
+    fn create_temp(
+        &self,
+        prefix: &Path,
+    ) -> impl Future<Output = Result<(Self::File, TempGuard), Error>> + MaybeSend {
+        async move {
+            let path = temp_path(prefix)?;
+            let file = self
+                .open_options(&path, OpenOptions::default().create(true).truncate(true))
+                .await?;
+
+            Ok((file, TempGuard::new(path)))
+        }
+    }
+
+    /// Flushes everything the backend or its layers still buffer and stops their background
+    /// work, so an application can exit without losing data. The backend should not be used
+    /// afterwards.
+    ///
+    /// Files that are still open are not affected and must be closed by their owners first.
+    /// Backends without buffered state keep the default implementation, which does nothing.
+    fn shutdown(&self) -> impl Future<Output = Result<(), Error>> + MaybeSend {
+        async { Ok(()) }
+    }
+}
+
+/// The default implementation of [`Fs::rename_new`], also used by layers that move files between
+/// backends.
+pub(crate) async fn rename_by_copy<F: Fs + ?Sized>(
+    fs: &F,
+    from: &Path,
+    to: &Path,
+) -> Result<(), Error> {
+    let mut reader = fs.open_options(from, OpenOptions::default()).await?;
+    let (result, buf) = reader.read_to_end_at(Vec::new(), 0).await;
+    result?;
+
+    let mut writer = fs
+        .open_options(to, OpenOptions::default().create_new(true))
+        .await?;
+    let (result, _) = writer.write_all(buf).await;
+    result?;
+    writer.close().await?;
+    fs.remove(from).await
 }
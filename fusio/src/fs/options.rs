@@ -1,8 +1,18 @@
+use futures_core::Stream;
+use futures_util::{future::ready, StreamExt};
+
+use super::FileMeta;
+use crate::Error;
+
+#[derive(Debug, Clone)]
 pub struct OpenOptions {
     pub read: bool,
     pub write: bool,
     pub create: bool,
+    pub create_new: bool,
     pub truncate: bool,
+    /// See [`OpenOptions::if_match`].
+    pub if_match: Option<String>,
 }
 
 impl Default for OpenOptions {
@@ -11,7 +21,9 @@ impl Default for OpenOptions {
             read: true,
             write: false,
             create: false,
+            create_new: false,
             truncate: false,
+            if_match: None,
         }
     }
 }
@@ -33,8 +45,120 @@ impl OpenOptions {
         self
     }
 
+    /// Creates the file and fails with [`Error::AlreadyExists`] when it exists, e.g. to take a
+    /// lock file or publish a manifest only once.
+    ///
+    /// Local files are checked when opened. Objects in a remote store are checked when the file is
+    /// closed and stored, so the first of several writers to close wins.
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self = self.create(true);
+        self.create_new = create_new;
+        self
+    }
+
     pub fn truncate(mut self, truncate: bool) -> Self {
         self.truncate = truncate;
         self
     }
+
+    /// Pins the version of an object: reads and writes of the file fail with
+    /// [`Error::PreconditionFailed`] once the ETag of the object is no longer `etag`, e.g. so
+    /// ranged reads of a large object cannot mix two versions of it.
+    ///
+    /// Only object stores reporting an ETag in [`super::FileMeta::etag`] honor it, local backends
+    /// ignore it.
+    pub fn if_match(mut self, etag: impl Into<String>) -> Self {
+        self.if_match = Some(etag.into());
+        self
+    }
+}
+
+/// Pagination of [`super::Fs::list_options`].
+///
+/// Backends list in lexicographic (byte-wise) order of the entry paths, so pages are
+/// deterministic as long as the listed prefix does not change in between. To fetch the next page,
+/// pass the `path` of the last entry of the current page as `page_token`.
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    pub offset: usize,
+    pub page_token: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl ListOptions {
+    /// Skips the first `offset` entries after the page token.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Only returns entries whose path sorts after `page_token`.
+    pub fn page_token(mut self, page_token: impl Into<String>) -> Self {
+        self.page_token = Some(page_token.into());
+        self
+    }
+
+    /// Returns at most `limit` entries.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Applies the options to a listing that is already in lexicographic order.
+    pub(crate) fn apply<S>(self, stream: S) -> impl Stream<Item = Result<FileMeta, Error>>
+    where
+        S: Stream<Item = Result<FileMeta, Error>>,
+    {
+        let Self {
+            offset,
+            page_token,
+            limit,
+        } = self;
+
+        stream
+            .filter(move |meta| {
+                ready(match (meta, page_token.as_deref()) {
+                    (Ok(meta), Some(token)) => meta.path.as_ref() > token,
+                    _ => true,
+                })
+            })
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn list_options_paginate() {
+        use futures_util::{stream, StreamExt};
+
+        use super::ListOptions;
+        use crate::{fs::FileMeta, path::Path};
+
+        let listing = || {
+            stream::iter(
+                ["a", "b", "c", "d", "e"]
+                    .map(|name| Ok(FileMeta::new(Path::parse(name).unwrap(), 0))),
+            )
+        };
+        let page = |options: ListOptions| async move {
+            options
+                .apply(listing())
+                .map(|meta| meta.unwrap().path.to_string())
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        assert_eq!(page(ListOptions::default().limit(2)).await, vec!["a", "b"]);
+        assert_eq!(
+            page(ListOptions::default().page_token("b").limit(2)).await,
+            vec!["c", "d"]
+        );
+        assert_eq!(
+            page(ListOptions::default().page_token("b").offset(2)).await,
+            vec!["e"]
+        );
+    }
 }
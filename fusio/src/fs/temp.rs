@@ -0,0 +1,250 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::Fs;
+use crate::{path::Path, Error};
+
+/// Owns a file created by [`Fs::create_temp`] and deletes it when dropped.
+///
+/// Deleting on drop needs a synchronous remove, which only the local backends have. Files on
+/// other backends must be cleaned up with [`TempGuard::remove`], otherwise they are left behind.
+///
+/// On Linux, local files may be created without a name (`O_TMPFILE`), so they vanish once closed
+/// even if the process crashes. Such a file only appears at [`TempGuard::path`] after
+/// [`TempGuard::link`] or [`TempGuard::keep`], which callers have to call before opening,
+/// renaming or reading it by name.
+#[derive(Debug)]
+pub struct TempGuard {
+    path: Option<Path>,
+    on_drop: Option<fn(&Path)>,
+    /// A handle to a file that has no name yet, keeping it alive until it is linked.
+    #[cfg(target_os = "linux")]
+    unnamed: Option<std::fs::File>,
+}
+
+impl TempGuard {
+    /// A guard that does not remove `path` on drop.
+    pub fn new(path: Path) -> Self {
+        Self {
+            path: Some(path),
+            on_drop: None,
+            #[cfg(target_os = "linux")]
+            unnamed: None,
+        }
+    }
+
+    /// A guard that calls `on_drop` with `path` when dropped, unless it was kept or removed.
+    pub fn with_drop_hook(path: Path, on_drop: fn(&Path)) -> Self {
+        Self {
+            path: Some(path),
+            on_drop: Some(on_drop),
+            #[cfg(target_os = "linux")]
+            unnamed: None,
+        }
+    }
+
+    /// A guard of a local file opened with `O_TMPFILE`, which is linked to `path` by
+    /// [`TempGuard::link`]. `file` is a handle to the same file. `on_drop` is only called if the
+    /// file was linked.
+    #[cfg(all(feature = "fs", feature = "tokio", target_os = "linux"))]
+    pub(crate) fn unnamed(path: Path, file: std::fs::File, on_drop: fn(&Path)) -> Self {
+        Self {
+            path: Some(path),
+            on_drop: Some(on_drop),
+            unnamed: Some(file),
+        }
+    }
+
+    /// The name of the file, which a file created without one only gets by [`TempGuard::link`].
+    pub fn path(&self) -> &Path {
+        self.path.as_ref().expect("path is only taken on consume")
+    }
+
+    /// Gives a file created without a name its name at [`TempGuard::path`], e.g. to rename it
+    /// into place. Does nothing for files that have a name already.
+    pub fn link(&mut self) -> Result<(), Error> {
+        #[cfg(target_os = "linux")]
+        if let Some(file) = &self.unnamed {
+            use std::{
+                ffi::CString,
+                io,
+                os::{fd::AsRawFd, unix::ffi::OsStrExt},
+            };
+
+            let local = crate::path::path_to_local(self.path())?;
+            let to = CString::new(local.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let from = CString::new(format!("/proc/self/fd/{}", file.as_raw_fd()))
+                .expect("no interior nul");
+            // SAFETY: both paths are nul terminated and `file` is open for the duration of the
+            // call. Linking the `/proc` entry with `AT_SYMLINK_FOLLOW` names the open file, unlike
+            // `AT_EMPTY_PATH`, which needs `CAP_DAC_READ_SEARCH`.
+            let ret = unsafe {
+                libc::linkat(
+                    libc::AT_FDCWD,
+                    from.as_ptr(),
+                    libc::AT_FDCWD,
+                    to.as_ptr(),
+                    libc::AT_SYMLINK_FOLLOW,
+                )
+            };
+            if ret == -1 {
+                return Err(io::Error::last_os_error().into());
+            }
+            self.unnamed = None;
+        }
+        Ok(())
+    }
+
+    /// Disarms the guard and returns the path, e.g. after the file was renamed into place. A file
+    /// created without a name is linked first, see [`TempGuard::link`].
+    pub fn keep(mut self) -> Result<Path, Error> {
+        self.link()?;
+        Ok(self.path.take().expect("path is only taken on consume"))
+    }
+
+    /// Removes the file through `fs`, works on every backend. A file without a name is left to
+    /// vanish once closed.
+    pub async fn remove<F: Fs>(mut self, fs: &F) -> Result<(), Error> {
+        #[cfg(target_os = "linux")]
+        if self.unnamed.is_some() {
+            return Ok(());
+        }
+        let path = self.path.take().expect("path is only taken on consume");
+        fs.remove(&path).await
+    }
+}
+
+impl Drop for TempGuard {
+    fn drop(&mut self) {
+        // a file without a name is gone once its last handle is closed
+        #[cfg(target_os = "linux")]
+        if self.unnamed.is_some() {
+            return;
+        }
+        if let (Some(path), Some(on_drop)) = (self.path.take(), self.on_drop) {
+            on_drop(&path);
+        }
+    }
+}
+
+/// Builds `{prefix}-{uuid}` with a random (version 4) UUID.
+pub(crate) fn temp_path(prefix: &Path) -> Result<Path, Error> {
+    Ok(Path::parse(format!("{}-{}", prefix, uuid_v4()))?)
+}
+
+fn random_u64() -> u64 {
+    #[cfg(feature = "sim")]
+    if let Some(random) = crate::sim::seeded_u64() {
+        return random;
+    }
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.finish()
+}
+
+pub(crate) fn uuid_v4() -> String {
+    let high = (random_u64() & 0xffff_ffff_ffff_0fff) | 0x4000;
+    let low = (random_u64() & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000;
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{temp_path, uuid_v4};
+    use crate::path::Path;
+
+    #[test]
+    fn uuids_are_unique_and_well_formed() {
+        let uuids = (0..1024).map(|_| uuid_v4()).collect::<HashSet<_>>();
+        assert_eq!(uuids.len(), 1024);
+
+        for uuid in uuids {
+            let groups = uuid.split('-').map(str::len).collect::<Vec<_>>();
+            assert_eq!(groups, vec![8, 4, 4, 4, 12]);
+            assert_eq!(&uuid[14..15], "4");
+            assert!(matches!(&uuid[19..20], "8" | "9" | "a" | "b"));
+        }
+    }
+
+    #[test]
+    fn temp_path_keeps_prefix() {
+        let path = temp_path(&Path::parse("spill/sort").unwrap()).unwrap();
+        assert!(path.as_ref().starts_with("spill/sort-"));
+        assert_eq!(path.as_ref().len(), "spill/sort-".len() + 36);
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn local_temp_file_is_removed_on_drop() {
+        use tempfile::TempDir;
+
+        use crate::{disk::TokioFs, fs::Fs, path::path_to_local, Write};
+
+        let dir = TempDir::new().unwrap();
+        let prefix = Path::from_absolute_path(dir.path().join("spill")).unwrap();
+        let fs = TokioFs;
+
+        let (mut file, mut guard) = fs.create_temp(&prefix).await.unwrap();
+        file.write_all(&b"hello"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+        let local = path_to_local(guard.path()).unwrap();
+        guard.link().unwrap();
+        assert_eq!(std::fs::read(&local).unwrap(), b"hello");
+        drop(guard);
+        assert!(!local.exists());
+
+        let (_, guard) = fs.create_temp(&prefix).await.unwrap();
+        let kept = path_to_local(&guard.keep().unwrap()).unwrap();
+        assert!(kept.exists());
+    }
+
+    #[cfg(all(
+        feature = "tokio",
+        not(feature = "completion-based"),
+        target_os = "linux"
+    ))]
+    #[tokio::test]
+    async fn local_temp_file_has_no_name_until_linked() {
+        use tempfile::TempDir;
+
+        use crate::{disk::TokioFs, fs::Fs, path::path_to_local, Write};
+
+        let dir = TempDir::new().unwrap();
+        let prefix = Path::from_absolute_path(dir.path().join("spill")).unwrap();
+        let fs = TokioFs;
+
+        let (mut file, guard) = fs.create_temp(&prefix).await.unwrap();
+        file.write_all(&b"hello"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        let kept = path_to_local(&guard.keep().unwrap()).unwrap();
+        assert_eq!(std::fs::read(kept).unwrap(), b"hello");
+
+        let (file, guard) = fs.create_temp(&prefix).await.unwrap();
+        drop((file, guard));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
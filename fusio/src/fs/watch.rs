@@ -0,0 +1,272 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    pin::pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures_core::Stream;
+use futures_util::{stream, StreamExt};
+
+use super::{FileMeta, Fs};
+use crate::{path::Path, task::Timer, Error};
+
+/// A change observed under a watched path.
+#[derive(Debug)]
+pub enum WatchEvent {
+    Created(FileMeta),
+    Modified(FileMeta),
+    Removed(Path),
+}
+
+/// Options of [`Fs::watch`].
+#[derive(Clone)]
+pub struct WatchOptions {
+    /// How often backends without change notifications list the watched path, one second by
+    /// default.
+    pub interval: Duration,
+    /// Waits between the listings of backends without change notifications, which fail with
+    /// [`Error::Unsupported`] if it is not set.
+    pub timer: Option<Arc<dyn Timer>>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            timer: None,
+        }
+    }
+}
+
+impl WatchOptions {
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn timer(mut self, timer: Arc<dyn Timer>) -> Self {
+        self.timer = Some(timer);
+        self
+    }
+}
+
+/// Detects changes by diffing consecutive listings of a path.
+///
+/// Works on every [`Fs`], which makes it the watch implementation for object stores. The first
+/// [`PollWatcher::poll`] only records a baseline, later ones report what changed since the
+/// previous call. A file counts as modified when its size, modification time or ETag changed,
+/// as far as the backend reports them.
+#[derive(Debug)]
+pub struct PollWatcher {
+    path: Path,
+    snapshot: Option<BTreeMap<Path, Version>>,
+}
+
+/// What identifies the content of a listed file.
+#[derive(Debug, PartialEq, Eq)]
+struct Version {
+    size: u64,
+    last_modified: Option<SystemTime>,
+    etag: Option<String>,
+}
+
+impl Version {
+    fn of(meta: &FileMeta) -> Self {
+        Self {
+            size: meta.size,
+            last_modified: meta.last_modified,
+            etag: meta.etag.clone(),
+        }
+    }
+}
+
+impl PollWatcher {
+    pub fn new(path: Path) -> Self {
+        Self {
+            path,
+            snapshot: None,
+        }
+    }
+
+    pub async fn poll<F: Fs + ?Sized>(&mut self, fs: &F) -> Result<Vec<WatchEvent>, Error> {
+        let mut listing = pin!(fs.list(&self.path).await?);
+        let mut metas = Vec::new();
+        while let Some(meta) = listing.next().await {
+            metas.push(meta?);
+        }
+
+        let current = metas
+            .iter()
+            .map(|meta| (meta.path.clone(), Version::of(meta)))
+            .collect::<BTreeMap<_, _>>();
+        let Some(mut previous) = self.snapshot.replace(current) else {
+            return Ok(Vec::new());
+        };
+
+        let mut events = Vec::new();
+        for meta in metas {
+            match previous.remove(&meta.path) {
+                None => events.push(WatchEvent::Created(meta)),
+                Some(version) if version != Version::of(&meta) => {
+                    events.push(WatchEvent::Modified(meta))
+                }
+                Some(_) => {}
+            }
+        }
+        events.extend(previous.into_keys().map(WatchEvent::Removed));
+
+        Ok(events)
+    }
+}
+
+/// Watches `path` on any [`Fs`] by listing it every time `ticks` yields, e.g. an interval timer
+/// of the runtime in use, which controls the polling frequency. The stream ends with `ticks`.
+pub fn poll_watch<'fs, F, T>(
+    fs: &'fs F,
+    path: Path,
+    ticks: T,
+) -> impl Stream<Item = Result<WatchEvent, Error>> + 'fs
+where
+    F: Fs + ?Sized,
+    T: Stream + Unpin + 'fs,
+{
+    stream::unfold(
+        (PollWatcher::new(path), ticks, VecDeque::new()),
+        move |(mut watcher, mut ticks, mut pending)| async move {
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((Ok(event), (watcher, ticks, pending)));
+                }
+                ticks.next().await?;
+                match watcher.poll(fs).await {
+                    Ok(events) => pending.extend(events),
+                    Err(err) => return Some((Err(err), (watcher, ticks, pending))),
+                }
+            }
+        },
+    )
+}
+
+/// The default implementation of [`Fs::watch`].
+pub(crate) fn watch_by_polling<F: Fs + ?Sized>(
+    fs: &F,
+    path: Path,
+    options: WatchOptions,
+) -> Result<impl Stream<Item = Result<WatchEvent, Error>> + '_, Error> {
+    let timer = options.timer.ok_or_else(|| Error::Unsupported {
+        message: "watching without change notifications needs a timer".into(),
+    })?;
+    let interval = options.interval;
+    // the first listing is the baseline and is taken right away
+    let ticks = stream::unfold(false, move |started| {
+        let sleep = started.then(|| timer.sleep(interval));
+        async move {
+            if let Some(sleep) = sleep {
+                sleep.await;
+            }
+            Some(((), true))
+        }
+    });
+
+    Ok(poll_watch(fs, path, Box::pin(ticks)))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn poll_watcher_reports_changes() {
+        use tempfile::TempDir;
+
+        use super::{PollWatcher, WatchEvent};
+        use crate::{disk::TokioFs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        let fs = TokioFs;
+        let mut watcher = PollWatcher::new(Path::from_absolute_path(dir.path()).unwrap());
+
+        std::fs::write(dir.path().join("a"), b"a").unwrap();
+        assert!(watcher.poll(&fs).await.unwrap().is_empty());
+
+        std::fs::write(dir.path().join("b"), b"b").unwrap();
+        std::fs::write(dir.path().join("a"), b"aa").unwrap();
+        let mut events = watcher.poll(&fs).await.unwrap();
+        events.sort_by_key(|event| match event {
+            WatchEvent::Created(meta) | WatchEvent::Modified(meta) => meta.path.clone(),
+            WatchEvent::Removed(path) => path.clone(),
+        });
+        assert!(matches!(&events[..], [
+            WatchEvent::Modified(a),
+            WatchEvent::Created(b),
+        ] if a.path.filename() == Some("a") && b.path.filename() == Some("b")));
+
+        std::fs::remove_file(dir.path().join("a")).unwrap();
+        let events = watcher.poll(&fs).await.unwrap();
+        assert!(matches!(
+            &events[..],
+            [WatchEvent::Removed(a)] if a.filename() == Some("a")
+        ));
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn poll_watcher_compares_etags() {
+        use std::sync::Arc;
+
+        use super::{PollWatcher, WatchEvent};
+        use crate::{path::Path, remotes::aws::mock::MockS3};
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let mut watcher = PollWatcher::new(Path::from("data"));
+
+        server.put("data/a", "ab");
+        assert!(watcher.poll(&s3).await.unwrap().is_empty());
+
+        // same size, different content
+        server.put("data/a", "ba");
+        let events = watcher.poll(&s3).await.unwrap();
+        assert!(matches!(
+            &events[..],
+            [WatchEvent::Modified(a)] if a.path.as_ref() == "data/a"
+        ));
+        assert!(watcher.poll(&s3).await.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn watch_polls_through_dyn_fs() {
+        use std::{sync::Arc, time::Duration};
+
+        use futures_util::{future::join, StreamExt};
+
+        use super::{WatchEvent, WatchOptions};
+        use crate::{
+            fs::Fs, path::Path, remotes::aws::mock::MockS3, task::TokioTimer, DynFs, Error,
+        };
+
+        let server = Arc::new(MockS3::default());
+        let s3: Box<dyn DynFs> = Box::new(server.fs());
+        server.put("data/a", "a");
+
+        assert!(matches!(
+            Fs::watch(&s3, Path::from("data"), WatchOptions::default()).await,
+            Err(Error::Unsupported { .. })
+        ));
+
+        let options = WatchOptions::default()
+            .interval(Duration::from_millis(10))
+            .timer(Arc::new(TokioTimer));
+        let mut events = Fs::watch(&s3, Path::from("data"), options).await.unwrap();
+        let (event, ()) = join(events.next(), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            server.put("data/b", "b");
+        })
+        .await;
+        assert!(matches!(
+            event,
+            Some(Ok(WatchEvent::Created(b))) if b.path.as_ref() == "data/b"
+        ));
+    }
+}
@@ -0,0 +1,606 @@
+//! Listing and extracting the members of tar and zip archives through [`Read`], so archives
+//! stored on any backend are read in place.
+//!
+//! [`TarReader`] walks the headers from the start of the archive and skips the data of the
+//! members, reading one header per member; wrapping the file in a
+//! [`BufReader`](crate::buffered::BufReader) batches them for archives of small members.
+//! [`ZipReader`] reads the central directory from the end of the archive, so listing costs two or
+//! three ranged reads and extracting a member only reads that member. Zip members are either
+//! stored or deflated, ZIP64 archives are supported, encrypted members are not.
+
+use std::io;
+
+use crate::{Error, Read, Write};
+
+/// Members are copied in ranges of this size, so large ones are never held in memory at once.
+const RANGE_SIZE: u64 = 8 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    /// Hard links, devices, FIFOs and other members that are not extracted as files.
+    Other,
+}
+
+/// A member of an archive, as listed by [`TarReader::entries`] or [`ZipReader::entries`].
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: String,
+    pub kind: EntryKind,
+    /// The size of the extracted member in bytes.
+    pub size: u64,
+    /// The target of a tar symlink or hard link. The target of a zip symlink is its content.
+    pub link_target: Option<String>,
+    location: Location,
+}
+
+#[derive(Debug, Clone)]
+enum Location {
+    Tar {
+        data: u64,
+    },
+    Zip {
+        header: u64,
+        compressed_size: u64,
+        method: u16,
+        crc32: u32,
+        encrypted: bool,
+    },
+}
+
+fn invalid(message: &str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+async fn read_range<F: Read>(file: &mut F, pos: u64, len: u64) -> Result<Vec<u8>, Error> {
+    let len = usize::try_from(len).map_err(|_| invalid("range does not fit in memory"))?;
+    let (result, buf) = file.read_exact_at(vec![0; len], pos).await;
+    result?;
+    Ok(buf)
+}
+
+/// Copies `len` bytes at `pos` of `file` to `writer` range by range and returns their CRC-32.
+async fn copy_range<F: Read, W: Write>(
+    file: &mut F,
+    pos: u64,
+    len: u64,
+    writer: &mut W,
+) -> Result<u32, Error> {
+    let mut crc = 0;
+    for offset in (0..len).step_by(RANGE_SIZE as usize) {
+        let buf = read_range(file, pos + offset, RANGE_SIZE.min(len - offset)).await?;
+        crc = crc32(crc, &buf);
+        let (result, _) = writer.write_all(buf).await;
+        result?;
+    }
+    Ok(crc)
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Extends the CRC-32 (IEEE) `crc` of the preceding data with `buf`, as used by zip.
+fn crc32(crc: u32, buf: &[u8]) -> u32 {
+    !buf.iter().fold(!crc, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+const TAR_BLOCK: u64 = 512;
+
+/// Returns the bytes of a tar header field up to its first NUL.
+fn tar_str(field: &[u8]) -> &[u8] {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    &field[..end]
+}
+
+/// Parses a numeric tar header field, which is octal or, for values that do not fit, base-256
+/// with the high bit of the first byte set.
+fn tar_number(field: &[u8]) -> Result<u64, Error> {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .try_fold((field[0] & 0x7f) as u64, |n, b| {
+                n.checked_mul(256).map(|n| n + *b as u64)
+            })
+            .ok_or_else(|| invalid("tar number overflows"));
+    }
+    let digits = std::str::from_utf8(tar_str(field))
+        .map_err(|_| invalid("invalid tar number"))?
+        .trim_matches(' ');
+    match digits.is_empty() {
+        true => Ok(0),
+        false => u64::from_str_radix(digits, 8).map_err(|_| invalid("invalid tar number")),
+    }
+}
+
+/// Returns the `path` and `linkpath` records of a pax extended header.
+fn pax_records(mut data: &[u8]) -> Result<(Option<String>, Option<String>), Error> {
+    let (mut path, mut link) = (None, None);
+    while !data.is_empty() {
+        // every record is "{len} {key}={value}\n", where len counts the whole record
+        let space = data
+            .iter()
+            .position(|b| *b == b' ')
+            .ok_or_else(|| invalid("invalid pax record"))?;
+        let len = std::str::from_utf8(&data[..space])
+            .ok()
+            .and_then(|len| len.parse::<usize>().ok())
+            .filter(|len| *len > space && *len <= data.len())
+            .ok_or_else(|| invalid("invalid pax record"))?;
+        let record = &data[space + 1..len];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(eq) = record.iter().position(|b| *b == b'=') {
+            let value = String::from_utf8_lossy(&record[eq + 1..]).into_owned();
+            match &record[..eq] {
+                b"path" => path = Some(value),
+                b"linkpath" => link = Some(value),
+                _ => {}
+            }
+        }
+        data = &data[len..];
+    }
+    Ok((path, link))
+}
+
+/// Reads the members of a tar archive, see the [module docs](self).
+pub struct TarReader<F> {
+    file: F,
+}
+
+impl<F: Read> TarReader<F> {
+    pub fn new(file: F) -> Self {
+        Self { file }
+    }
+
+    /// Lists the members of the archive in order. GNU long names and the paths of pax extended
+    /// headers are applied to the members they describe.
+    pub async fn entries(&mut self) -> Result<Vec<Entry>, Error> {
+        let size = self.file.size().await?;
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        let (mut long_path, mut long_link) = (None, None);
+        while pos + TAR_BLOCK <= size {
+            let header = read_range(&mut self.file, pos, TAR_BLOCK).await?;
+            if header.iter().all(|b| *b == 0) {
+                break;
+            }
+            let expected = tar_number(&header[148..156])?;
+            let actual = header
+                .iter()
+                .enumerate()
+                .map(|(i, b)| match i {
+                    148..156 => b' ' as u64,
+                    _ => *b as u64,
+                })
+                .sum::<u64>();
+            if expected != actual {
+                return Err(invalid("tar header checksum mismatch"));
+            }
+
+            let len = tar_number(&header[124..136])?;
+            let data = pos + TAR_BLOCK;
+            pos = data
+                .checked_add(len.div_ceil(TAR_BLOCK) * TAR_BLOCK)
+                .ok_or_else(|| invalid("tar member overflows"))?;
+            let kind = match header[156] {
+                b'0' | b'\0' | b'7' => EntryKind::File,
+                b'5' => EntryKind::Directory,
+                b'2' => EntryKind::Symlink,
+                b'L' | b'K' => {
+                    let name = read_range(&mut self.file, data, len).await?;
+                    let name = String::from_utf8_lossy(tar_str(&name)).into_owned();
+                    match header[156] {
+                        b'L' => long_path = Some(name),
+                        _ => long_link = Some(name),
+                    }
+                    continue;
+                }
+                b'x' => {
+                    let records = read_range(&mut self.file, data, len).await?;
+                    let (path, link) = pax_records(&records)?;
+                    long_path = path.or(long_path);
+                    long_link = link.or(long_link);
+                    continue;
+                }
+                b'g' => continue,
+                _ => EntryKind::Other,
+            };
+
+            let path = long_path.take().unwrap_or_else(|| {
+                let name = String::from_utf8_lossy(tar_str(&header[0..100]));
+                let prefix = match &header[257..262] == b"ustar" {
+                    true => tar_str(&header[345..500]),
+                    false => &[],
+                };
+                match prefix.is_empty() {
+                    true => name.into_owned(),
+                    false => format!("{}/{name}", String::from_utf8_lossy(prefix)),
+                }
+            });
+            let link = long_link.take().or_else(|| {
+                let link = tar_str(&header[157..257]);
+                (!link.is_empty()).then(|| String::from_utf8_lossy(link).into_owned())
+            });
+            entries.push(Entry {
+                path,
+                kind,
+                size: if kind == EntryKind::File { len } else { 0 },
+                link_target: link,
+                location: Location::Tar { data },
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Writes the content of `entry` to `writer`, reading it in ranges.
+    pub async fn extract<W: Write>(&mut self, entry: &Entry, writer: &mut W) -> Result<(), Error> {
+        let Location::Tar { data } = entry.location else {
+            return Err(invalid("entry is not a member of a tar archive"));
+        };
+        copy_range(&mut self.file, data, entry.size, writer).await?;
+        Ok(())
+    }
+
+    /// Returns the content of `entry`.
+    pub async fn read(&mut self, entry: &Entry) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        self.extract(entry, &mut io::Cursor::new(&mut buf)).await?;
+        Ok(buf)
+    }
+}
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const EOCD_LEN: u64 = 22;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_LOCATOR_LEN: usize = 20;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x0606_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+
+fn u16_at(buf: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes(buf[pos..pos + 2].try_into().unwrap())
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap())
+}
+
+/// Reads the members of a zip archive, see the [module docs](self).
+pub struct ZipReader<F> {
+    file: F,
+    entries: Vec<Entry>,
+}
+
+impl<F: Read> ZipReader<F> {
+    /// Reads the central directory of the archive.
+    pub async fn open(mut file: F) -> Result<Self, Error> {
+        let size = file.size().await?;
+        // the end of central directory record is followed by a comment of up to 64 KiB
+        let suffix_len = size.min(EOCD_LEN + u16::MAX as u64);
+        let suffix = read_range(&mut file, size - suffix_len, suffix_len).await?;
+        let eocd = (0..=suffix.len().saturating_sub(EOCD_LEN as usize))
+            .rev()
+            .find(|i| {
+                u32_at(&suffix, *i) == EOCD_SIGNATURE
+                    && i + EOCD_LEN as usize + u16_at(&suffix, i + 20) as usize == suffix.len()
+            })
+            .ok_or_else(|| invalid("zip end of central directory not found"))?;
+
+        let mut count = u16_at(&suffix, eocd + 10) as u64;
+        let mut directory_len = u32_at(&suffix, eocd + 12) as u64;
+        let mut directory = u32_at(&suffix, eocd + 16) as u64;
+        if eocd >= ZIP64_LOCATOR_LEN
+            && u32_at(&suffix, eocd - ZIP64_LOCATOR_LEN) == ZIP64_LOCATOR_SIGNATURE
+        {
+            let record = u64_at(&suffix, eocd - ZIP64_LOCATOR_LEN + 8);
+            let record = read_range(&mut file, record, 56).await?;
+            if u32_at(&record, 0) != ZIP64_EOCD_SIGNATURE {
+                return Err(invalid("invalid zip64 end of central directory"));
+            }
+            count = u64_at(&record, 32);
+            directory_len = u64_at(&record, 40);
+            directory = u64_at(&record, 48);
+        }
+
+        let directory = read_range(&mut file, directory, directory_len).await?;
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        for _ in 0..count {
+            if directory.len() < pos + 46 || u32_at(&directory, pos) != CENTRAL_HEADER_SIGNATURE {
+                return Err(invalid("invalid zip central directory"));
+            }
+            let header = &directory[pos..];
+            let name_len = u16_at(header, 28) as usize;
+            let extra_len = u16_at(header, 30) as usize;
+            let comment_len = u16_at(header, 32) as usize;
+            if header.len() < 46 + name_len + extra_len + comment_len {
+                return Err(invalid("invalid zip central directory"));
+            }
+            let path = String::from_utf8_lossy(&header[46..46 + name_len]).into_owned();
+
+            let mut size = u32_at(header, 24) as u64;
+            let mut compressed_size = u32_at(header, 20) as u64;
+            let mut offset = u32_at(header, 42) as u64;
+            // the ZIP64 extra field holds the values that do not fit, in this order
+            let mut extra = &header[46 + name_len..46 + name_len + extra_len];
+            while extra.len() >= 4 {
+                let (id, len) = (u16_at(extra, 0), u16_at(extra, 2) as usize);
+                let field = extra.get(4..4 + len).unwrap_or(&[]);
+                if id == 0x0001 {
+                    let mut values = field.chunks_exact(8).map(|v| u64_at(v, 0));
+                    for value in [&mut size, &mut compressed_size, &mut offset] {
+                        if *value == u32::MAX as u64 {
+                            *value = values
+                                .next()
+                                .ok_or_else(|| invalid("invalid zip64 extra field"))?;
+                        }
+                    }
+                }
+                extra = extra.get(4 + len..).unwrap_or(&[]);
+            }
+
+            let unix_mode = match u16_at(header, 4) >> 8 {
+                3 => u32_at(header, 38) >> 16,
+                _ => 0,
+            };
+            let kind = if path.ends_with('/') {
+                EntryKind::Directory
+            } else if unix_mode & 0o170000 == 0o120000 {
+                EntryKind::Symlink
+            } else {
+                EntryKind::File
+            };
+            entries.push(Entry {
+                path,
+                kind,
+                size,
+                link_target: None,
+                location: Location::Zip {
+                    header: offset,
+                    compressed_size,
+                    method: u16_at(header, 10),
+                    crc32: u32_at(header, 16),
+                    encrypted: u16_at(header, 8) & 1 == 1,
+                },
+            });
+            pos += 46 + name_len + extra_len + comment_len;
+        }
+
+        Ok(Self { file, entries })
+    }
+
+    /// The members of the archive in the order of the central directory.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Writes the content of `entry` to `writer` and checks it against its CRC-32. Stored members
+    /// are copied in ranges, deflated ones are read and inflated at once.
+    pub async fn extract<W: Write>(&mut self, entry: &Entry, writer: &mut W) -> Result<(), Error> {
+        let Location::Zip {
+            header,
+            compressed_size,
+            method,
+            crc32: expected,
+            encrypted,
+        } = entry.location
+        else {
+            return Err(invalid("entry is not a member of a zip archive"));
+        };
+        if encrypted {
+            return Err(Error::Unsupported {
+                message: format!("encrypted zip member {}", entry.path),
+            });
+        }
+
+        let local = read_range(&mut self.file, header, 30).await?;
+        if u32_at(&local, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid("invalid zip local header"));
+        }
+        let data = header + 30 + u16_at(&local, 26) as u64 + u16_at(&local, 28) as u64;
+
+        let actual = match method {
+            0 => copy_range(&mut self.file, data, compressed_size, writer).await?,
+            8 => {
+                let compressed = read_range(&mut self.file, data, compressed_size).await?;
+                let size = usize::try_from(entry.size)
+                    .map_err(|_| invalid("zip member does not fit in memory"))?;
+                let buf = miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, size)
+                    .map_err(|_| invalid("invalid deflate stream"))?;
+                let crc = crc32(0, &buf);
+                let (result, _) = writer.write_all(buf).await;
+                result?;
+                crc
+            }
+            _ => {
+                return Err(Error::Unsupported {
+                    message: format!("zip compression method {method}"),
+                })
+            }
+        };
+        if actual != expected {
+            return Err(Error::ChecksumMismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Returns the content of `entry`.
+    pub async fn read(&mut self, entry: &Entry) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        self.extract(entry, &mut io::Cursor::new(&mut buf)).await?;
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, EntryKind, TarReader, ZipReader};
+
+    fn tar_header(name: &str, kind: u8, size: usize, link: &str) -> Vec<u8> {
+        let mut header = vec![0; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+        header[156] = kind;
+        header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].copy_from_slice(b"        ");
+        let sum = header.iter().map(|b| *b as u32).sum::<u32>();
+        header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+        header
+    }
+
+    fn tar_member(archive: &mut Vec<u8>, name: &str, kind: u8, data: &[u8], link: &str) {
+        archive.extend(tar_header(name, kind, data.len(), link));
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().div_ceil(512) * 512, 0);
+    }
+
+    #[tokio::test]
+    async fn reads_tar() {
+        let long = format!("{}/file", "d".repeat(120));
+        let content = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut archive = Vec::new();
+        tar_member(&mut archive, "dir/", b'5', &[], "");
+        tar_member(&mut archive, "dir/a", b'0', &content, "");
+        tar_member(&mut archive, "././@LongLink", b'L', long.as_bytes(), "");
+        tar_member(&mut archive, "truncated", b'0', b"long", "");
+        tar_member(&mut archive, "PaxHeader", b'x', b"17 path=pax/name\n", "");
+        tar_member(&mut archive, "ignored", b'0', b"pax", "");
+        tar_member(&mut archive, "link", b'2', &[], "dir/a");
+        archive.extend([0; 1024]);
+
+        let mut reader = TarReader::new(&mut archive);
+        let entries = reader.entries().await.unwrap();
+        let listed = entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.kind, e.size, e.link_target.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            listed,
+            [
+                ("dir/", EntryKind::Directory, 0, None),
+                ("dir/a", EntryKind::File, 1000, None),
+                (long.as_str(), EntryKind::File, 4, None),
+                ("pax/name", EntryKind::File, 3, None),
+                ("link", EntryKind::Symlink, 0, Some("dir/a")),
+            ]
+        );
+        assert_eq!(reader.read(&entries[1]).await.unwrap(), content);
+        assert_eq!(reader.read(&entries[2]).await.unwrap(), b"long");
+        assert_eq!(reader.read(&entries[3]).await.unwrap(), b"pax");
+
+        archive[600] ^= 1;
+        assert!(TarReader::new(&mut archive).entries().await.is_err());
+    }
+
+    /// Builds a zip archive of `(name, data, deflate)` members with a trailing comment.
+    fn zip(members: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let (mut archive, mut directory) = (Vec::new(), Vec::new());
+        for (name, data, deflate) in members {
+            let (method, stored) = match deflate {
+                true => (8u16, miniz_oxide::deflate::compress_to_vec(data, 6)),
+                false => (0, data.to_vec()),
+            };
+            let mut fields = Vec::new();
+            fields.extend(method.to_le_bytes());
+            fields.extend([0; 4]);
+            fields.extend(crc32(0, data).to_le_bytes());
+            fields.extend((stored.len() as u32).to_le_bytes());
+            fields.extend((data.len() as u32).to_le_bytes());
+            fields.extend((name.len() as u16).to_le_bytes());
+            fields.extend([0; 2]);
+
+            directory.extend(0x0201_4b50u32.to_le_bytes());
+            directory.extend([20, 3, 20, 0, 0, 0]);
+            directory.extend(&fields);
+            directory.extend([0; 6]);
+            directory.extend((0o100644u32 << 16).to_le_bytes());
+            directory.extend((archive.len() as u32).to_le_bytes());
+            directory.extend(name.as_bytes());
+
+            archive.extend(0x0403_4b50u32.to_le_bytes());
+            archive.extend([20, 0, 0, 0]);
+            archive.extend(&fields);
+            archive.extend(name.as_bytes());
+            archive.extend(stored);
+        }
+        let offset = archive.len() as u32;
+        archive.extend(&directory);
+        archive.extend(0x0605_4b50u32.to_le_bytes());
+        archive.extend([0; 4]);
+        archive.extend((members.len() as u16).to_le_bytes());
+        archive.extend((members.len() as u16).to_le_bytes());
+        archive.extend((directory.len() as u32).to_le_bytes());
+        archive.extend(offset.to_le_bytes());
+        archive.extend(7u16.to_le_bytes());
+        archive.extend(b"comment");
+        archive
+    }
+
+    #[tokio::test]
+    async fn reads_zip() {
+        let content = (0..100_000).map(|i| (i % 7) as u8).collect::<Vec<_>>();
+        let mut archive = zip(&[
+            ("dir/", b"", false),
+            ("dir/stored", b"hello", false),
+            ("dir/deflated", &content, true),
+        ]);
+
+        let mut reader = ZipReader::open(&mut archive).await.unwrap();
+        let entries = reader.entries().to_vec();
+        let listed = entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.kind, e.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            listed,
+            [
+                ("dir/", EntryKind::Directory, 0),
+                ("dir/stored", EntryKind::File, 5),
+                ("dir/deflated", EntryKind::File, 100_000),
+            ]
+        );
+        assert_eq!(reader.read(&entries[1]).await.unwrap(), b"hello");
+        assert!(reader.read(&entries[2]).await.unwrap() == content);
+
+        // corrupt the stored member
+        let pos = archive.windows(5).position(|w| w == b"hello").unwrap();
+        archive[pos] = b'j';
+        let mut reader = ZipReader::open(&mut archive).await.unwrap();
+        assert!(matches!(
+            reader.read(&entries[1]).await,
+            Err(crate::Error::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf4_3926);
+    }
+}
@@ -1,12 +1,17 @@
 use std::cmp;
 
-use crate::{Error, IoBuf, IoBufMut, Read, Write};
+use crate::{
+    buf::{buf_len, BufferPool},
+    Error, IoBuf, IoBufMut, Read, Write,
+};
 
 pub struct BufReader<F> {
     inner: F,
     capacity: usize,
-    buf: Option<(Vec<u8>, usize)>,
+    /// The buffered data, the position of its first byte and the offset of the next byte to read.
+    buf: Option<(Vec<u8>, u64, usize)>,
     size: u64,
+    pool: Option<BufferPool>,
 
     #[cfg(test)]
     filling_count: usize,
@@ -21,10 +26,25 @@ impl<F: Read> BufReader<F> {
             capacity,
             buf: None,
             size,
+            pool: None,
             #[cfg(test)]
             filling_count: 0,
         })
     }
+
+    /// Takes the buffers for refills from `pool` and hands replaced ones back.
+    pub fn with_pool(mut self, pool: BufferPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+}
+
+impl<F> Drop for BufReader<F> {
+    fn drop(&mut self) {
+        if let (Some(pool), Some((buf, _, _))) = (&self.pool, self.buf.take()) {
+            pool.put(buf);
+        }
+    }
 }
 
 impl<F: Read> Read for BufReader<F> {
@@ -40,7 +60,7 @@ impl<F: Read> Read for BufReader<F> {
             if let Err(err) = self.filling_buf(pos).await {
                 return (Err(err), buf);
             }
-            let (fill_buf, read_pos) = self.buf.as_mut().unwrap();
+            let (fill_buf, _, read_pos) = self.buf.as_mut().unwrap();
 
             let min_len = cmp::min(fill_buf.len() - *read_pos, buf_slice.len() - write_pos);
             let read_end = min_len + *read_pos;
@@ -55,7 +75,10 @@ impl<F: Read> Read for BufReader<F> {
     }
 
     async fn read_to_end_at(&mut self, mut buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
-        buf.resize((self.size - pos) as usize, 0u8);
+        match buf_len(self.size.saturating_sub(pos)) {
+            Ok(len) => buf.resize(len, 0u8),
+            Err(e) => return (Err(e), buf),
+        }
 
         self.read_exact_at(buf, pos).await
     }
@@ -66,17 +89,55 @@ impl<F: Read> Read for BufReader<F> {
 }
 
 impl<F: Read> BufReader<F> {
+    /// Appends the bytes from `pos` up to and including the next `delimiter` to `buf`, or up to
+    /// the end of the file if there is none, and returns how many were appended. Reading the
+    /// following bytes continues from the buffer.
+    pub async fn read_until(
+        &mut self,
+        delimiter: u8,
+        mut pos: u64,
+        buf: &mut Vec<u8>,
+    ) -> Result<usize, Error> {
+        let start = buf.len();
+        while pos < self.size {
+            self.filling_buf(pos).await?;
+            let (fill_buf, _, read_pos) = self.buf.as_mut().unwrap();
+
+            let available = &fill_buf[*read_pos..];
+            let (len, found) = match available.iter().position(|byte| *byte == delimiter) {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            buf.extend_from_slice(&available[..len]);
+            *read_pos += len;
+            pos += len as u64;
+            if found {
+                break;
+            }
+        }
+        Ok(buf.len() - start)
+    }
+
     async fn filling_buf(&mut self, pos: u64) -> Result<(), Error> {
         if self
             .buf
             .as_ref()
-            .map(|(buf, current)| buf.len() == *current || *current != pos as usize)
+            .map(|(buf, start, current)| buf.len() == *current || start + *current as u64 != pos)
             .unwrap_or(true)
         {
-            let fill_buf = vec![0u8; cmp::min(self.capacity, (self.size - pos) as usize)];
+            // the capacity bounds the length, so it always fits in `usize`
+            let len = cmp::min(self.capacity as u64, self.size.saturating_sub(pos)) as usize;
+            let fill_buf = match &self.pool {
+                Some(pool) => pool.get(len),
+                None => vec![0u8; len],
+            };
             let (result, fill_buf) = self.inner.read_exact_at(fill_buf, pos).await;
-            if result.is_ok() {
-                self.buf = Some((fill_buf, 0));
+            let unused = match result {
+                Ok(()) => self.buf.replace((fill_buf, pos, 0)).map(|(buf, _, _)| buf),
+                Err(_) => Some(fill_buf),
+            };
+            if let (Some(pool), Some(unused)) = (&self.pool, unused) {
+                pool.put(unused);
             }
             #[cfg(test)]
             {
@@ -166,6 +227,11 @@ impl<F: Write> Write for BufWriter<F> {
 
         Ok(())
     }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        self.flush().await?;
+        self.inner.barrier().await
+    }
 }
 
 #[cfg(test)]
@@ -223,6 +289,63 @@ pub(crate) mod tests {
         }
     }
 
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn test_buf_read_until() {
+        use tempfile::tempfile;
+
+        let mut file = tokio::fs::File::from_std(tempfile().unwrap());
+        file.write_all(b"ab\ncdef\ng").await.unwrap();
+
+        let mut reader = BufReader::new(file, 4).await.unwrap();
+        let mut lines = Vec::new();
+        let mut pos = 0;
+        loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', pos, &mut line).await.unwrap() {
+                0 => break,
+                len => pos += len as u64,
+            }
+            lines.push(line);
+        }
+        assert_eq!(lines, [&b"ab\n"[..], b"cdef\n", b"g"]);
+        // every byte was read from the file once
+        assert_eq!(reader.filling_count, 3);
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn test_buf_read_with_pool() {
+        use tempfile::tempfile;
+
+        use crate::buf::{BufferPool, PoolOptions};
+
+        let mut file = tokio::fs::File::from_std(tempfile().unwrap());
+        file.write_all(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15])
+            .await
+            .unwrap();
+
+        let pool = BufferPool::new(PoolOptions {
+            min_size: 8,
+            max_size: 64,
+            granularity: 8,
+            max_retained: 64,
+        });
+        let mut reader = BufReader::new(file, 8)
+            .await
+            .unwrap()
+            .with_pool(pool.clone());
+
+        let (result, buf) = reader.read_exact_at(vec![0u8; 12], 2).await;
+        result.unwrap();
+        assert_eq!(buf, vec![2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]);
+        // the first refill buffer was replaced by the second one
+        assert_eq!(pool.retained(), 8);
+
+        drop(reader);
+        assert_eq!(pool.retained(), 16);
+    }
+
     #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
     #[tokio::test]
     async fn test_buf_read_write() {
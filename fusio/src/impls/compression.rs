@@ -0,0 +1,261 @@
+//! Transparent decompression of gzip and zstd files on read, e.g. for consuming compressed logs
+//! from object storage without handling each format at the call site. Writers such as
+//! [`RollingWriter`](crate::util::RollingWriter) compress with the same [`Codec`]s.
+//!
+//! [`Decompressed::open`] detects the format of a file from its extension or, if that is not
+//! conclusive, its first bytes, and decodes the whole file into memory. Files in other formats
+//! and files opened with [`Decompression::Raw`] are read as they are.
+
+use std::io;
+
+use crate::{path::Path, Error, IoBufMut, Read};
+
+/// A compression format that [`Decompressed`] can decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Codec {
+    /// Detects the format by the extension of `path`: `.gz` and `.gzip` for gzip, `.zst` and
+    /// `.zstd` for zstd.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()? {
+            #[cfg(feature = "gzip")]
+            "gz" | "gzip" => Some(Codec::Gzip),
+            #[cfg(feature = "zstd")]
+            "zst" | "zstd" => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Detects the format by the magic bytes at the start of a file.
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            #[cfg(feature = "gzip")]
+            [0x1f, 0x8b, ..] => Some(Codec::Gzip),
+            #[cfg(feature = "zstd")]
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+
+    /// The extension of files in this format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Codec::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => "zst",
+        }
+    }
+
+    pub(crate) fn encoder(self) -> Result<Encoder, Error> {
+        Ok(match self {
+            #[cfg(feature = "gzip")]
+            Codec::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(Vec::new(), 0)?),
+        })
+    }
+
+    /// Decodes `data`, which may hold several concatenated gzip members or zstd frames.
+    pub fn decode(self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        match self {
+            #[cfg(feature = "gzip")]
+            Codec::Gzip => {
+                io::Read::read_to_end(&mut flate2::read::MultiGzDecoder::new(data), &mut buf)?;
+            }
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => {
+                io::copy(&mut zstd::stream::read::Decoder::new(data)?, &mut buf)?;
+            }
+        }
+        Ok(buf)
+    }
+}
+
+/// Compresses data as it is written, handing out the compressed output produced so far.
+pub(crate) enum Encoder {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    /// Compresses `data` and returns the output that is ready, which may be empty.
+    pub(crate) fn encode(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let output = match self {
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(encoder) => {
+                io::Write::write_all(encoder, data)?;
+                encoder.get_mut()
+            }
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => {
+                io::Write::write_all(encoder, data)?;
+                encoder.get_mut()
+            }
+        };
+        Ok(std::mem::take(output))
+    }
+
+    /// Returns the rest of the output, which completes the compressed file.
+    pub(crate) fn finish(self) -> Result<Vec<u8>, Error> {
+        Ok(match self {
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        })
+    }
+}
+
+/// Whether [`Decompressed::open`] decodes compressed files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Decompression {
+    /// Decodes files whose extension or magic bytes name a [`Codec`].
+    #[default]
+    Auto,
+    /// Reads every file as it is stored.
+    Raw,
+}
+
+/// A file that is read decompressed, see the [module docs](self).
+pub enum Decompressed<F> {
+    Raw(F),
+    Decoded(Vec<u8>),
+}
+
+impl<F: Read> Decompressed<F> {
+    /// Opens `file` stored at `path` according to `mode`. The extension of `path` is checked
+    /// first, the first bytes of `file` only if it has no known one.
+    pub async fn open(mut file: F, path: &Path, mode: Decompression) -> Result<Self, Error> {
+        if mode == Decompression::Raw {
+            return Ok(Decompressed::Raw(file));
+        }
+        let codec = match Codec::from_path(path) {
+            Some(codec) => Some(codec),
+            None => {
+                let len = file.size().await?.min(4) as usize;
+                let (result, magic) = file.read_exact_at(vec![0; len], 0).await;
+                result?;
+                Codec::from_magic(&magic)
+            }
+        };
+        let Some(codec) = codec else {
+            return Ok(Decompressed::Raw(file));
+        };
+
+        let (result, data) = file.read_to_end_at(Vec::new(), 0).await;
+        result?;
+        codec.decode(&data).map(Decompressed::Decoded)
+    }
+
+    /// Whether the file was decoded.
+    pub fn is_decoded(&self) -> bool {
+        matches!(self, Decompressed::Decoded(_))
+    }
+}
+
+impl<F: Read> Read for Decompressed<F> {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        match self {
+            Decompressed::Raw(file) => file.read_exact_at(buf, pos).await,
+            Decompressed::Decoded(data) => (&mut *data).read_exact_at(buf, pos).await,
+        }
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        match self {
+            Decompressed::Raw(file) => file.read_to_end_at(buf, pos).await,
+            Decompressed::Decoded(data) => (&mut *data).read_to_end_at(buf, pos).await,
+        }
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        match self {
+            Decompressed::Raw(file) => file.size().await,
+            Decompressed::Decoded(data) => Ok(data.len() as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decompressed, Decompression};
+    use crate::{path::Path, Read};
+
+    async fn read_all<F: Read>(mut file: Decompressed<F>) -> Vec<u8> {
+        let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+        result.unwrap();
+        buf
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn decodes_gzip() {
+        use std::io::Write;
+
+        use flate2::{write::GzEncoder, Compression};
+
+        let mut data = Vec::new();
+        for part in [&b"first\n"[..], b"second\n"] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(part).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+        let compressed = data.clone();
+
+        let path = Path::parse("logs/app.log.gz").unwrap();
+        let file = Decompressed::open(&mut data, &path, Decompression::Auto)
+            .await
+            .unwrap();
+        assert!(file.is_decoded());
+        assert_eq!(file.size().await.unwrap(), 13);
+        assert_eq!(read_all(file).await, b"first\nsecond\n");
+
+        // detected by its magic bytes, unless raw access is asked for
+        let path = Path::parse("logs/app.log").unwrap();
+        let file = Decompressed::open(&mut data, &path, Decompression::Auto)
+            .await
+            .unwrap();
+        assert_eq!(read_all(file).await, b"first\nsecond\n");
+        let file = Decompressed::open(&mut data, &path, Decompression::Raw)
+            .await
+            .unwrap();
+        assert_eq!(read_all(file).await, compressed);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn decodes_zstd() {
+        let mut data = zstd::encode_all(&b"hello, fusio"[..], 3).unwrap();
+
+        let path = Path::parse("data.bin").unwrap();
+        let file = Decompressed::open(&mut data, &path, Decompression::Auto)
+            .await
+            .unwrap();
+        assert_eq!(read_all(file).await, b"hello, fusio");
+
+        let mut plain = b"plain".to_vec();
+        let file = Decompressed::open(&mut plain, &path, Decompression::Auto)
+            .await
+            .unwrap();
+        assert!(!file.is_decoded());
+        assert_eq!(read_all(file).await, b"plain");
+
+        let path = Path::parse("data.zst").unwrap();
+        assert!(Decompressed::open(&mut plain, &path, Decompression::Auto)
+            .await
+            .is_err());
+    }
+}
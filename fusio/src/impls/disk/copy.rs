@@ -0,0 +1,119 @@
+//! Blocking helpers shared by the local [`crate::fs::Fs`] backends for copying files inside the
+//! kernel.
+
+#[cfg(not(target_os = "linux"))]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::{fs::File, io, os::fd::AsRawFd};
+
+use crate::Error;
+
+/// Whether a failed clone or in-kernel copy should be retried with a slower method, because the
+/// filesystem or kernel does not support it for these files.
+#[cfg(target_os = "linux")]
+fn is_unsupported(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::EOPNOTSUPP | libc::EXDEV | libc::EINVAL | libc::ENOSYS | libc::ENOTTY)
+    )
+}
+
+/// Shares the extents of `source` with `target` (`FICLONE`), which copies no data at all.
+#[cfg(target_os = "linux")]
+fn reflink(source: &File, target: &File) -> io::Result<()> {
+    // SAFETY: both files are open for the duration of the call.
+    match unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Copies all of `source` into `target` with `copy_file_range`, so the data never passes through
+/// userspace. Returns `Ok(false)` if nothing was copied because the call is not supported.
+#[cfg(target_os = "linux")]
+fn copy_file_range(source: &File, target: &File, size: u64) -> io::Result<bool> {
+    let mut copied = 0;
+    while copied < size {
+        let len = (size - copied).min(1 << 30) as usize;
+        // SAFETY: both files are open for the duration of the call, null offsets use and advance
+        // the file positions.
+        let ret = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                std::ptr::null_mut(),
+                target.as_raw_fd(),
+                std::ptr::null_mut(),
+                len,
+                0,
+            )
+        };
+        match ret {
+            -1 => {
+                let e = io::Error::last_os_error();
+                if copied == 0 && is_unsupported(&e) {
+                    return Ok(false);
+                }
+                return Err(e);
+            }
+            // The source shrank while copying.
+            0 => break,
+            n => copied += n as u64,
+        }
+    }
+    Ok(true)
+}
+
+/// Copies `from` to `to`, replacing `to`, and returns the size of the copy.
+///
+/// On Linux the file is reflinked when the filesystem supports it (btrfs, XFS), otherwise copied
+/// with `copy_file_range`, and only then through a userspace buffer. Elsewhere this is
+/// [`std::fs::copy`], which clones files on APFS.
+pub(crate) fn copy(from: &std::path::Path, to: &std::path::Path) -> Result<u64, Error> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut source = File::open(from)?;
+        let size = source.metadata()?.len();
+        let mut target = File::create(to)?;
+
+        match reflink(&source, &target) {
+            Ok(()) => return Ok(size),
+            Err(e) if is_unsupported(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        if copy_file_range(&source, &target, size)? {
+            return Ok(target.metadata()?.len());
+        }
+        Ok(io::copy(&mut source, &mut target)?)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Ok(fs::copy(from, to)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::copy;
+
+    #[test]
+    fn copy_replaces_target() {
+        let dir = TempDir::new().unwrap();
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+
+        let content = (0..1 << 20).map(|i| i as u8).collect::<Vec<_>>();
+        fs::write(&from, &content).unwrap();
+        fs::write(&to, b"stale content that is longer than nothing").unwrap();
+
+        assert_eq!(copy(&from, &to).unwrap(), 1 << 20);
+        assert!(fs::read(&to).unwrap() == content);
+
+        fs::write(&from, b"").unwrap();
+        assert_eq!(copy(&from, &to).unwrap(), 0);
+        assert!(fs::read(&to).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+//! Blocking helpers shared by the local [`crate::fs::Fs`] backends to make directory changes
+//! durable, also on network filesystems.
+
+use std::{fs, io};
+
+/// Flushes the entries of the directory at `path`, so files created, renamed or removed in it
+/// survive a crash. Directories can not be synced on Windows, where this does nothing.
+pub(crate) fn sync_dir(path: &std::path::Path) -> io::Result<()> {
+    #[cfg(unix)]
+    fs::File::open(path)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Syncs the directory containing `path`, see [`sync_dir`].
+pub(crate) fn sync_parent_dir(path: &std::path::Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync_dir(parent),
+        _ => Ok(()),
+    }
+}
+
+fn is_stale(e: &io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    const ESTALE: i32 = 116;
+    #[cfg(not(target_os = "linux"))]
+    const ESTALE: i32 = 70;
+
+    cfg!(unix) && e.raw_os_error() == Some(ESTALE)
+}
+
+/// Renames `from` to `to`, retrying up to `retries` times on stale NFS file handles.
+///
+/// NFS clients retransmit a rename whose reply got lost, and the retransmission fails with
+/// `NotFound` although the first attempt succeeded. That case is detected by `from` being gone
+/// and `to` existing, and reported as success.
+pub(crate) fn rename(from: &std::path::Path, to: &std::path::Path, retries: u32) -> io::Result<()> {
+    let mut attempt = 0;
+    loop {
+        match fs::rename(from, to) {
+            Ok(()) => return Ok(()),
+            Err(e)
+                if e.kind() == io::ErrorKind::NotFound
+                    && fs::symlink_metadata(from).is_err()
+                    && fs::symlink_metadata(to).is_ok() =>
+            {
+                return Ok(())
+            }
+            Err(e) if is_stale(&e) && attempt < retries => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::{rename, sync_parent_dir};
+
+    #[test]
+    fn rename_and_sync() {
+        let dir = TempDir::new().unwrap();
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+        fs::write(&from, b"fusio").unwrap();
+
+        rename(&from, &to, 3).unwrap();
+        sync_parent_dir(&to).unwrap();
+        assert_eq!(fs::read(&to).unwrap(), b"fusio");
+
+        // a repeated rename that already happened succeeds, like a retransmitted NFS request
+        rename(&from, &to, 3).unwrap();
+        assert!(rename(&dir.path().join("missing"), &dir.path().join("other"), 3).is_err());
+    }
+}
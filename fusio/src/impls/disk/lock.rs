@@ -0,0 +1,110 @@
+//! Blocking helpers shared by the local [`crate::fs::Fs`] backends for byte-range locks.
+//!
+//! Locks are open file description locks (`F_OFD_SETLK`): they belong to the opened file rather
+//! than to the process, so two handles in the same process conflict like two processes do, and
+//! all locks of a handle are released when it is closed.
+
+#[cfg(target_os = "linux")]
+use std::{io, os::fd::RawFd};
+
+use crate::Error;
+
+#[cfg(target_os = "linux")]
+fn set_lock(fd: RawFd, lock_type: libc::c_short, offset: u64, len: u64) -> Result<(), Error> {
+    let to_off = |value: u64| {
+        libc::off_t::try_from(value)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "lock range out of bounds"))
+    };
+
+    // SAFETY: `flock` is a plain C struct for which all zeroes is a valid value.
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = lock_type;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    lock.l_start = to_off(offset)?;
+    lock.l_len = to_off(len)?;
+    // `l_pid` must stay zero for open file description locks.
+
+    // SAFETY: `fd` is an open file descriptor and `lock` outlives the call.
+    if unsafe { libc::fcntl(fd, libc::F_OFD_SETLK, &lock) } == -1 {
+        let e = io::Error::last_os_error();
+        return Err(match e.raw_os_error() {
+            Some(libc::EAGAIN) | Some(libc::EACCES) => io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("range {offset}+{len} is locked by another handle"),
+            ),
+            _ => e,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Locks `len` bytes of `fd` starting at `offset` without waiting. An `exclusive` lock conflicts
+/// with every other lock on the range, a shared one only with exclusive locks. A `len` of zero
+/// extends the range to the end of the file, including bytes appended later.
+///
+/// A conflicting lock held through another handle fails with [`io::ErrorKind::WouldBlock`].
+/// Locking a range again through the same handle converts the lock to the new type.
+#[cfg(target_os = "linux")]
+pub(crate) fn lock_range(fd: RawFd, offset: u64, len: u64, exclusive: bool) -> Result<(), Error> {
+    let lock_type = match exclusive {
+        true => libc::F_WRLCK,
+        false => libc::F_RDLCK,
+    };
+    set_lock(fd, lock_type as libc::c_short, offset, len)
+}
+
+/// Releases the locks of `fd` on `len` bytes starting at `offset`, see [`lock_range`].
+#[cfg(target_os = "linux")]
+pub(crate) fn unlock_range(fd: RawFd, offset: u64, len: u64) -> Result<(), Error> {
+    set_lock(fd, libc::F_UNLCK as libc::c_short, offset, len)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn unsupported() -> Error {
+    Error::Unsupported {
+        message: "byte-range locks are only supported on linux".into(),
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::{fs::OpenOptions, io, os::fd::AsRawFd};
+
+    use tempfile::NamedTempFile;
+
+    use super::{lock_range, unlock_range};
+    use crate::Error;
+
+    fn is_conflict(result: Result<(), Error>) -> bool {
+        matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock)
+    }
+
+    #[test]
+    fn ranges_conflict_across_handles() {
+        let file = NamedTempFile::new().unwrap();
+        file.as_file().set_len(4096).unwrap();
+        let open = || {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(file.path())
+                .unwrap()
+        };
+        let (a, b) = (open(), open());
+
+        lock_range(a.as_raw_fd(), 0, 1024, true).unwrap();
+        assert!(is_conflict(lock_range(b.as_raw_fd(), 512, 1024, false)));
+        lock_range(b.as_raw_fd(), 1024, 1024, true).unwrap();
+
+        lock_range(a.as_raw_fd(), 0, 1024, false).unwrap();
+        lock_range(b.as_raw_fd(), 0, 512, false).unwrap();
+        assert!(is_conflict(lock_range(b.as_raw_fd(), 0, 512, true)));
+
+        unlock_range(a.as_raw_fd(), 0, 1024).unwrap();
+        lock_range(b.as_raw_fd(), 0, 512, true).unwrap();
+
+        drop(b);
+        lock_range(a.as_raw_fd(), 0, 0, true).unwrap();
+    }
+}
@@ -1,9 +1,46 @@
+#[cfg(all(feature = "fs", feature = "tokio"))]
+pub(crate) mod copy;
+#[cfg(all(feature = "fs", feature = "tokio"))]
+pub(crate) mod dir;
+#[cfg(all(feature = "fs", feature = "tokio"))]
+pub(crate) mod lock;
 #[cfg(feature = "monoio")]
 pub(crate) mod monoio;
+#[cfg(all(
+    feature = "fs",
+    any(
+        feature = "tokio",
+        feature = "monoio",
+        feature = "tokio-uring",
+        target_os = "wasi"
+    )
+))]
+pub(crate) mod permissions;
+#[cfg(all(
+    feature = "fs",
+    feature = "tokio",
+    feature = "http",
+    target_os = "linux"
+))]
+pub(crate) mod sendfile;
+#[cfg(all(feature = "fs", feature = "tokio"))]
+pub(crate) mod sparse;
+#[cfg(all(
+    feature = "fs",
+    any(
+        feature = "tokio",
+        feature = "monoio",
+        feature = "tokio-uring",
+        target_os = "wasi"
+    )
+))]
+pub(crate) mod symlink;
 #[cfg(feature = "tokio")]
 pub(crate) mod tokio;
 #[cfg(all(feature = "tokio-uring", target_os = "linux"))]
 pub(crate) mod tokio_uring;
+#[cfg(target_os = "wasi")]
+pub(crate) mod wasi;
 
 #[cfg(all(feature = "monoio", feature = "fs"))]
 #[allow(unused)]
@@ -19,11 +56,19 @@ pub use tokio::fs::*;
 pub use tokio_uring::fs::*;
 #[cfg(all(feature = "tokio-uring", target_os = "linux"))]
 #[allow(unused)]
-pub use tokio_uring::TokioUringFile;
+pub use tokio_uring::{TokioUringBuilder, TokioUringFile};
+#[cfg(all(target_os = "wasi", feature = "fs"))]
+#[allow(unused)]
+pub use wasi::fs::*;
+#[cfg(target_os = "wasi")]
+#[allow(unused)]
+pub use wasi::WasiFile;
 
 #[cfg(feature = "fs")]
 cfg_if::cfg_if! {
-    if #[cfg(feature = "tokio")] {
+    if #[cfg(target_os = "wasi")] {
+        pub type LocalFs = WasiFs;
+    } else if #[cfg(feature = "tokio")] {
         pub type LocalFs = TokioFs;
     } else if #[cfg(feature = "monoio")] {
         pub type LocalFs = MonoIoFs;
@@ -31,3 +76,78 @@ cfg_if::cfg_if! {
         pub type LocalFs = TokioUringFs;
     }
 }
+
+#[cfg(all(
+    feature = "fs",
+    any(
+        feature = "tokio",
+        feature = "monoio",
+        feature = "tokio-uring",
+        target_os = "wasi"
+    )
+))]
+pub(crate) fn remove_temp(path: &crate::path::Path) {
+    if let Ok(path) = crate::path::path_to_local(path) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Opens a file without a name (`O_TMPFILE`) in the directory `path` will be linked to, see
+/// [`crate::fs::TempGuard::link`]. Fails on filesystems without support, like NFS, and on kernels
+/// older than 3.11.
+#[cfg(all(feature = "fs", feature = "tokio", target_os = "linux"))]
+pub(crate) fn open_unnamed(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .mode(0o600)
+        .custom_flags(libc::O_TMPFILE)
+        .open(dir)
+}
+
+/// Reports opening an existing file with `create_new` as [`crate::Error::AlreadyExists`], like
+/// remote backends do.
+#[cfg(all(
+    feature = "fs",
+    any(
+        feature = "tokio",
+        feature = "monoio",
+        feature = "tokio-uring",
+        target_os = "wasi"
+    )
+))]
+pub(crate) fn open_error(path: &crate::path::Path, error: std::io::Error) -> crate::Error {
+    match error.kind() {
+        std::io::ErrorKind::AlreadyExists => crate::Error::AlreadyExists { path: path.clone() },
+        _ => error.into(),
+    }
+}
+
+/// Moves `from` to `to` for [`crate::fs::Fs::rename_new`] by hard linking `to` and removing
+/// `from`. Unlike a rename the link never replaces an existing `to`, and `to` appears with its
+/// whole content at once.
+#[cfg(all(
+    feature = "fs",
+    any(
+        feature = "tokio",
+        feature = "monoio",
+        feature = "tokio-uring",
+        target_os = "wasi"
+    )
+))]
+pub(crate) fn rename_new(
+    from: &crate::path::Path,
+    to: &crate::path::Path,
+) -> Result<(), crate::Error> {
+    let local_from = crate::path::path_to_local(from)?;
+    let local_to = crate::path::path_to_local(to)?;
+    std::fs::hard_link(&local_from, &local_to).map_err(|e| open_error(to, e))?;
+    std::fs::remove_file(&local_from)?;
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! Conversions between [`std::fs`] permissions and [`crate::fs::Permissions`].
+
+use std::fs::{self, Metadata};
+
+use crate::{fs::Permissions, Error};
+
+pub(crate) fn from_metadata(meta: &Metadata) -> Permissions {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        Permissions {
+            mode: Some(meta.mode() & 0o7777),
+            uid: Some(meta.uid()),
+            gid: Some(meta.gid()),
+            readonly: meta.permissions().readonly(),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        Permissions {
+            mode: None,
+            uid: None,
+            gid: None,
+            readonly: meta.permissions().readonly(),
+        }
+    }
+}
+
+/// Applies `permissions` to `path`. On Unix the mode bits win over `readonly` when both are set,
+/// and ownership is only changed when `uid` or `gid` is given.
+pub(crate) fn set_permissions(
+    path: &std::path::Path,
+    permissions: Permissions,
+) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if permissions.uid.is_some() || permissions.gid.is_some() {
+            std::os::unix::fs::chown(path, permissions.uid, permissions.gid)?;
+        }
+        let mut std_permissions = fs::metadata(path)?.permissions();
+        match permissions.mode {
+            Some(mode) => std_permissions.set_mode(mode),
+            None => std_permissions.set_readonly(permissions.readonly),
+        }
+        fs::set_permissions(path, std_permissions)?;
+    }
+    #[cfg(not(unix))]
+    {
+        let mut std_permissions = fs::metadata(path)?.permissions();
+        std_permissions.set_readonly(permissions.readonly);
+        fs::set_permissions(path, std_permissions)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    use tempfile::NamedTempFile;
+
+    use super::{from_metadata, set_permissions};
+    use crate::fs::Permissions;
+
+    #[test]
+    fn round_trip_mode() {
+        let file = NamedTempFile::new().unwrap();
+
+        set_permissions(file.path(), Permissions::from_mode(0o640)).unwrap();
+        let permissions = from_metadata(&fs::metadata(file.path()).unwrap());
+        assert_eq!(permissions.mode, Some(0o640));
+        assert!(!permissions.readonly);
+
+        set_permissions(file.path(), Permissions::from_mode(0o444)).unwrap();
+        let permissions = from_metadata(&fs::metadata(file.path()).unwrap());
+        assert_eq!(permissions.mode, Some(0o444));
+        assert!(permissions.readonly);
+    }
+}
@@ -0,0 +1,25 @@
+//! Blocking helpers shared by the local [`crate::fs::Fs`] backends for sending files to sockets
+//! without copying them through userspace.
+
+use std::{io, os::fd::RawFd};
+
+/// Sends up to `len` bytes of `file` starting at `offset` to `out` with `sendfile`, and returns
+/// the number of bytes sent. A non-blocking `out` that is full fails with
+/// [`io::ErrorKind::WouldBlock`].
+pub(crate) fn send(file: RawFd, offset: u64, len: u64, out: RawFd) -> io::Result<u64> {
+    let mut offset = libc::off_t::try_from(offset)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "offset out of bounds"))?;
+    let count = len.min(1 << 30) as usize;
+
+    // SAFETY: both descriptors are open for the duration of the call and `offset` outlives it.
+    match unsafe { libc::sendfile(out, file, &mut offset, count) } {
+        -1 => Err(io::Error::last_os_error()),
+        n => Ok(n as u64),
+    }
+}
+
+/// Whether a failed `sendfile` should be retried through a userspace buffer, because the kernel
+/// can not send from this file.
+pub(crate) fn is_unsupported(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS))
+}
@@ -0,0 +1,150 @@
+//! Blocking helpers shared by the local [`crate::fs::Fs`] backends for sparse files.
+
+#[cfg(not(target_os = "linux"))]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::{
+    fs::File,
+    io,
+    os::{fd::AsRawFd, unix::fs::FileExt},
+};
+
+use crate::Error;
+
+/// Deallocates `len` bytes of `file` starting at `offset`. The range reads back as zeroes and no
+/// longer occupies disk space, the size of the file is left unchanged.
+#[cfg(target_os = "linux")]
+pub(crate) fn punch_hole(file: &File, offset: u64, len: u64) -> Result<(), Error> {
+    let to_off = |value: u64| {
+        libc::off_t::try_from(value)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "hole out of bounds"))
+    };
+    let (offset, len) = (to_off(offset)?, to_off(len)?);
+
+    // SAFETY: `file` is open for the duration of the call.
+    let ret = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset,
+            len,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Seeks `file` to the next data (`SEEK_DATA`) or hole (`SEEK_HOLE`) at or after `offset`.
+/// Returns `None` when no data follows `offset`.
+#[cfg(target_os = "linux")]
+fn seek(file: &File, offset: u64, whence: libc::c_int) -> io::Result<Option<u64>> {
+    // SAFETY: `file` is open for the duration of the call.
+    let ret = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) };
+    if ret == -1 {
+        let e = io::Error::last_os_error();
+        return match e.raw_os_error() {
+            Some(libc::ENXIO) => Ok(None),
+            _ => Err(e),
+        };
+    }
+    Ok(Some(ret as u64))
+}
+
+/// Returns the ranges of `file` holding data as `(offset, len)`, in order. Filesystems without
+/// hole tracking report the whole file as a single range.
+#[cfg(target_os = "linux")]
+fn data_ranges(file: &File, size: u64) -> io::Result<Vec<(u64, u64)>> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    while offset < size {
+        let Some(start) = seek(file, offset, libc::SEEK_DATA)? else {
+            break;
+        };
+        let end = seek(file, start, libc::SEEK_HOLE)?
+            .unwrap_or(size)
+            .min(size);
+        ranges.push((start, end - start));
+        offset = end;
+    }
+    Ok(ranges)
+}
+
+/// Copies `from` to `to`, replacing `to`, and returns the number of data bytes copied. Holes in
+/// `from` are skipped, so they stay holes in `to` instead of being filled with zeroes.
+pub(crate) fn copy_sparse(from: &std::path::Path, to: &std::path::Path) -> Result<u64, Error> {
+    #[cfg(target_os = "linux")]
+    {
+        const CHUNK: usize = 1 << 20;
+
+        let source = File::open(from)?;
+        let size = source.metadata()?.len();
+        let target = File::create(to)?;
+
+        let mut buf = vec![0; CHUNK];
+        let mut copied = 0;
+        for (start, len) in data_ranges(&source, size)? {
+            let mut offset = start;
+            while offset < start + len {
+                let n = CHUNK.min((start + len - offset) as usize);
+                source.read_exact_at(&mut buf[..n], offset)?;
+                target.write_all_at(&buf[..n], offset)?;
+                offset += n as u64;
+            }
+            copied += len;
+        }
+        target.set_len(size)?;
+        target.sync_all()?;
+        Ok(copied)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Ok(fs::copy(from, to)?)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn unsupported() -> Error {
+    Error::Unsupported {
+        message: "hole punching is only supported on linux".into(),
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::{fs, os::unix::fs::FileExt};
+
+    use tempfile::TempDir;
+
+    use super::{copy_sparse, punch_hole};
+
+    #[test]
+    fn punch_and_copy() {
+        let dir = TempDir::new().unwrap();
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&from)
+            .unwrap();
+        file.write_all_at(&[1; 1 << 16], 0).unwrap();
+        file.write_all_at(&[2; 1 << 16], 1 << 20).unwrap();
+
+        punch_hole(&file, 0, 1 << 12).unwrap();
+        let mut buf = [0xff; 1 << 12];
+        file.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf.iter().all(|b| *b == 0));
+        assert_eq!(file.metadata().unwrap().len(), (1 << 20) + (1 << 16));
+
+        let copied = copy_sparse(&from, &to).unwrap();
+        assert!(copied < (1 << 20) + (1 << 16));
+
+        let (expected, actual) = (fs::read(&from).unwrap(), fs::read(&to).unwrap());
+        assert_eq!(expected.len(), actual.len());
+        assert!(expected == actual);
+    }
+}
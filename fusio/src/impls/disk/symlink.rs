@@ -0,0 +1,165 @@
+//! Blocking helpers shared by the local [`crate::fs::Fs`] backends for symlink-aware operations.
+
+use std::{
+    fs::{self, DirEntry, Metadata},
+    io,
+    path::PathBuf,
+};
+
+use super::permissions;
+use crate::{
+    fs::{EntryKind, FileMeta},
+    path::Path,
+    Error,
+};
+
+fn file_meta(
+    path: &std::path::Path,
+    link_meta: Metadata,
+    follow_symlinks: bool,
+) -> Result<FileMeta, Error> {
+    let is_symlink = link_meta.file_type().is_symlink();
+    let meta = if is_symlink && follow_symlinks {
+        fs::metadata(path)?
+    } else {
+        link_meta
+    };
+
+    let kind = if meta.is_dir() {
+        EntryKind::Directory
+    } else {
+        EntryKind::File
+    };
+    Ok(FileMeta::new(Path::from_absolute_path(path)?, meta.len())
+        .kind(kind)
+        .symlink(is_symlink)
+        .permissions(Some(permissions::from_metadata(&meta)))
+        .last_modified(meta.modified().ok()))
+}
+
+pub(crate) fn metadata(path: &std::path::Path, follow_symlinks: bool) -> Result<FileMeta, Error> {
+    file_meta(path, fs::symlink_metadata(path)?, follow_symlinks)
+}
+
+/// Reads the entries of the directory at `path` sorted by file name, so listings are stable.
+pub(crate) fn sorted_read_dir(path: &std::path::Path) -> Result<Vec<DirEntry>, Error> {
+    let mut entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(DirEntry::file_name);
+    Ok(entries)
+}
+
+pub(crate) fn entry_meta(entry: DirEntry, follow_symlinks: bool) -> Result<FileMeta, Error> {
+    file_meta(&entry.path(), entry.metadata()?, follow_symlinks)
+}
+
+/// Removes `path`. When `path` is a symlink, the link itself is removed unless `follow_symlinks`
+/// is set, in which case the file it points to is removed and the link is left dangling.
+pub(crate) fn remove(path: &std::path::Path, follow_symlinks: bool) -> Result<(), Error> {
+    if follow_symlinks && fs::symlink_metadata(path)?.file_type().is_symlink() {
+        fs::remove_file(fs::canonicalize(path)?)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_link(path: &std::path::Path) -> Result<PathBuf, Error> {
+    Ok(fs::read_link(path)?)
+}
+
+pub(crate) fn symlink(target: &std::path::Path, link: &std::path::Path) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link)?;
+        Ok(())
+    }
+    #[cfg(windows)]
+    {
+        let target_is_dir = link
+            .parent()
+            .map(|parent| parent.join(target))
+            .unwrap_or_else(|| target.to_path_buf())
+            .is_dir();
+        if target_is_dir {
+            std::os::windows::fs::symlink_dir(target, link)?;
+        } else {
+            std::os::windows::fs::symlink_file(target, link)?;
+        }
+        Ok(())
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (target, link);
+        Err(Error::Unsupported {
+            message: "symlinks are not supported on this platform".into(),
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::{entry_meta, metadata, read_link, remove, sorted_read_dir, symlink};
+
+    #[test]
+    fn symlink_metadata_and_removal() {
+        let dir = TempDir::new().unwrap();
+        let target = dir.path().join("target.file");
+        let link = dir.path().join("link.file");
+        fs::write(&target, b"hello, fusio symlinks").unwrap();
+
+        symlink(std::path::Path::new("target.file"), &link).unwrap();
+        assert_eq!(
+            read_link(&link).unwrap(),
+            std::path::Path::new("target.file")
+        );
+
+        let meta = metadata(&link, false).unwrap();
+        assert!(meta.is_symlink);
+        assert_eq!(meta.size, "target.file".len() as u64);
+        assert_eq!(meta.path.filename(), Some("link.file"));
+
+        let meta = metadata(&link, true).unwrap();
+        assert!(meta.is_symlink);
+        assert_eq!(meta.size, 21);
+
+        let entries = sorted_read_dir(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry_meta(entry, false).unwrap())
+            .map(|meta| (meta.path.filename().unwrap().to_string(), meta.is_symlink))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                ("link.file".to_string(), true),
+                ("target.file".to_string(), false)
+            ]
+        );
+
+        remove(&link, false).unwrap();
+        assert!(target.exists());
+        assert!(fs::symlink_metadata(&link).is_err());
+
+        symlink(&target, &link).unwrap();
+        remove(&link, true).unwrap();
+        assert!(!target.exists());
+        assert!(fs::symlink_metadata(&link).is_ok());
+    }
+
+    #[test]
+    fn entry_kind() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("file"), b"").unwrap();
+        symlink(&dir.path().join("sub"), &dir.path().join("link")).unwrap();
+
+        assert!(metadata(&dir.path().join("sub"), false).unwrap().is_dir());
+        assert!(metadata(&dir.path().join("file"), false).unwrap().is_file());
+        assert!(metadata(&dir.path().join("link"), false).unwrap().is_file());
+        assert!(metadata(&dir.path().join("link"), true).unwrap().is_dir());
+    }
+}
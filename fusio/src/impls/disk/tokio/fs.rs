@@ -1,18 +1,24 @@
-use std::io;
+use std::{io, path::PathBuf};
 
 use async_stream::stream;
 use futures_core::Stream;
+#[cfg(feature = "http")]
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio::{
-    fs::{create_dir_all, remove_file, File},
+    fs::{create_dir_all, File},
     task::spawn_blocking,
 };
 
+#[cfg(feature = "watch")]
+use crate::fs::{WatchEvent, WatchOptions};
 use crate::{
-    fs::{FileMeta, Fs, OpenOptions},
+    disk::{copy, dir, lock, open_error, permissions, remove_temp, rename_new, sparse, symlink},
+    fs::{temp_path, FileMeta, Fs, ListOptions, OpenOptions, Permissions, TempGuard},
     path::{path_to_local, Path},
-    Error,
+    Error, IoBuf, Write,
 };
 
+#[derive(Default)]
 pub struct TokioFs;
 
 impl Fs for TokioFs {
@@ -25,8 +31,10 @@ impl Fs for TokioFs {
             .read(options.read)
             .append(options.write)
             .create(options.create)
+            .create_new(options.create_new)
             .open(&local_path)
-            .await?;
+            .await
+            .map_err(|e| open_error(path, e))?;
 
         if options.truncate {
             file.set_len(0).await?;
@@ -35,7 +43,7 @@ impl Fs for TokioFs {
         Ok(file)
     }
 
-    async fn create_dir_all(path: &Path) -> Result<(), Error> {
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
         let path = path_to_local(path)?;
         create_dir_all(path).await?;
 
@@ -45,15 +53,96 @@ impl Fs for TokioFs {
     async fn list(
         &self,
         path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        self.list_with(path, false).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        self.remove_with(path, false).await
+    }
+
+    /// Copies with reflinks or inside the kernel, see [`TokioFs::copy`].
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        TokioFs::copy(self, from, to).await.map(|_| ())
+    }
+
+    /// Hard links `to` and removes `from`, a link never replaces an existing file.
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let (from, to) = (from.clone(), to.clone());
+
+        spawn_blocking(move || rename_new(&from, &to))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Follows symlinks, like opening the file does.
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        self.metadata(path, true).await
+    }
+
+    /// On Linux the file is created without a name where the filesystem supports it, see
+    /// [`TempGuard`], and under a random name otherwise.
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let path = temp_path(prefix)?;
+        #[cfg(target_os = "linux")]
+        {
+            let local = path_to_local(&path)?;
+            let unnamed = spawn_blocking(move || {
+                let file = crate::disk::open_unnamed(&local)?;
+                Ok::<_, io::Error>((file.try_clone()?, file))
+            })
+            .await
+            .map_err(io::Error::from)?;
+            if let Ok((file, handle)) = unnamed {
+                let guard = TempGuard::unnamed(path, handle, remove_temp);
+                return Ok((File::from_std(file), guard));
+            }
+        }
+        let file = self
+            .open_options(&path, OpenOptions::default().create(true).truncate(true))
+            .await?;
+
+        Ok((file, TempGuard::with_drop_hook(path, remove_temp)))
+    }
+
+    /// Watches the direct children of `path` using the native notification mechanism of the
+    /// platform (inotify, FSEvents, ...) instead of polling, so `options` are ignored.
+    #[cfg(feature = "watch")]
+    async fn watch(
+        &self,
+        path: Path,
+        _: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        super::watch::notify_watch(&path)
+    }
+}
+
+impl TokioFs {
+    /// Returns the metadata of `path`. With `follow_symlinks` the size is taken from the file a
+    /// symlink points to instead of the link itself.
+    pub async fn metadata(&self, path: &Path, follow_symlinks: bool) -> Result<FileMeta, Error> {
+        let path = path_to_local(path)?;
+
+        spawn_blocking(move || symlink::metadata(&path, follow_symlinks))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Lists `path` like [`Fs::list`], choosing whether symlinks are followed for entry metadata.
+    /// Entries are always reported at their own location, links are never resolved. Entries are
+    /// sorted by file name.
+    pub async fn list_with(
+        &self,
+        path: &Path,
+        follow_symlinks: bool,
     ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
         let path = path_to_local(path)?;
 
         spawn_blocking(move || {
-            let entries = path.read_dir()?;
+            let entries = symlink::sorted_read_dir(&path)?;
             Ok::<_, Error>(stream! {
                 for entry in entries {
-                    let entry = entry?;
-                    yield Ok(FileMeta { path: Path::from_filesystem_path(entry.path())?, size: entry.metadata()?.len() });
+                    yield symlink::entry_meta(entry, follow_symlinks);
                 }
             })
         })
@@ -61,10 +150,498 @@ impl Fs for TokioFs {
         .map_err(io::Error::from)?
     }
 
-    async fn remove(&self, path: &Path) -> Result<(), Error> {
+    /// Removes `path`. A symlink is removed itself unless `follow_symlinks` is set, in which case
+    /// the file it points to is removed instead.
+    pub async fn remove_with(&self, path: &Path, follow_symlinks: bool) -> Result<(), Error> {
+        let path = path_to_local(path)?;
+
+        spawn_blocking(move || symlink::remove(&path, follow_symlinks))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Returns the target of the symlink at `path` exactly as stored, which may be relative.
+    pub async fn read_link(&self, path: &Path) -> Result<PathBuf, Error> {
+        let path = path_to_local(path)?;
+
+        spawn_blocking(move || symlink::read_link(&path))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Creates a symlink at `link` pointing to `target`. A relative `target` is resolved against
+    /// the directory of `link` when the link is followed.
+    pub async fn symlink(
+        &self,
+        target: impl AsRef<std::path::Path>,
+        link: &Path,
+    ) -> Result<(), Error> {
+        let target = target.as_ref().to_path_buf();
+        let link = path_to_local(link)?;
+
+        spawn_blocking(move || symlink::symlink(&target, &link))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Syncs the directory containing `path`, so creating, renaming or removing `path` survives a
+    /// crash. Syncing the file itself does not persist its directory entry on ext4 or xfs.
+    pub async fn sync_parent_dir(&self, path: &Path) -> Result<(), Error> {
         let path = path_to_local(path)?;
 
-        remove_file(&path).await?;
+        spawn_blocking(move || dir::sync_parent_dir(&path))
+            .await
+            .map_err(io::Error::from)??;
         Ok(())
     }
+
+    /// Replaces `path` with `buf` atomically: readers see either the old or the new content, also
+    /// after a crash. The content is written to a temporary file next to `path`, synced, renamed
+    /// into place, and the directory is synced.
+    pub async fn atomic_write<B: IoBuf>(&self, path: &Path, buf: B) -> Result<(), Error> {
+        let (mut file, mut guard) = self.create_temp(path).await?;
+        let (result, _) = file.write_all(buf).await;
+        result?;
+        file.flush().await?;
+        file.sync_all().await?;
+        guard.link()?;
+
+        let from = path_to_local(guard.path())?;
+        let to = path_to_local(path)?;
+        spawn_blocking(move || std::fs::rename(from, to))
+            .await
+            .map_err(io::Error::from)??;
+        guard.keep()?;
+        self.sync_parent_dir(path).await
+    }
+
+    /// Changes the mode, ownership or read-only flag of `path`, see [`Permissions`] for what
+    /// each platform supports.
+    pub async fn set_permissions(
+        &self,
+        path: &Path,
+        permissions: Permissions,
+    ) -> Result<(), Error> {
+        let path = path_to_local(path)?;
+
+        spawn_blocking(move || permissions::set_permissions(&path, permissions))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Locks `len` bytes of `file` starting at `offset`, so cooperating processes can partition a
+    /// shared file. An `exclusive` lock conflicts with every other lock on the range, a shared one
+    /// only with exclusive locks, and a `len` of zero extends the range to the end of the file.
+    ///
+    /// The call does not wait: a range locked through another handle, also one of this process,
+    /// fails with [`io::ErrorKind::WouldBlock`]. Locks are released by
+    /// [`TokioFs::unlock_range`] or when `file` is closed. Only supported on Linux.
+    pub fn lock_range(
+        &self,
+        file: &File,
+        offset: u64,
+        len: u64,
+        exclusive: bool,
+    ) -> Result<(), Error> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+
+            lock::lock_range(file.as_raw_fd(), offset, len, exclusive)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (file, offset, len, exclusive);
+            Err(lock::unsupported())
+        }
+    }
+
+    /// Releases the locks taken through `file` on `len` bytes starting at `offset`, see
+    /// [`TokioFs::lock_range`].
+    pub fn unlock_range(&self, file: &File, offset: u64, len: u64) -> Result<(), Error> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+
+            lock::unlock_range(file.as_raw_fd(), offset, len)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (file, offset, len);
+            Err(lock::unsupported())
+        }
+    }
+
+    /// Deallocates `len` bytes of `file` starting at `offset`, so space inside a large
+    /// preallocated file can be reclaimed. The range reads back as zeroes and the size of the file
+    /// does not change. Only supported on Linux, on filesystems that support hole punching.
+    pub async fn punch_hole(&self, file: &File, offset: u64, len: u64) -> Result<(), Error> {
+        #[cfg(target_os = "linux")]
+        {
+            let file = file.try_clone().await?.into_std().await;
+
+            spawn_blocking(move || sparse::punch_hole(&file, offset, len))
+                .await
+                .map_err(io::Error::from)?
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (file, offset, len);
+            Err(sparse::unsupported())
+        }
+    }
+
+    /// Copies `from` to `to`, replacing `to`, and returns the size of the copy. The copy shares
+    /// the data of `from` on filesystems with reflinks (btrfs, XFS, APFS), which makes it nearly
+    /// free, and otherwise stays inside the kernel where possible.
+    pub async fn copy(&self, from: &Path, to: &Path) -> Result<u64, Error> {
+        let from = path_to_local(from)?;
+        let to = path_to_local(to)?;
+
+        spawn_blocking(move || copy::copy(&from, &to))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Copies `from` to `to`, replacing `to`, and returns the number of data bytes copied. Holes
+    /// in `from` are found with `SEEK_DATA` / `SEEK_HOLE` and stay holes in `to`. Outside Linux
+    /// this is a plain copy.
+    pub async fn copy_sparse(&self, from: &Path, to: &Path) -> Result<u64, Error> {
+        let from = path_to_local(from)?;
+        let to = path_to_local(to)?;
+
+        spawn_blocking(move || sparse::copy_sparse(&from, &to))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Sends `len` bytes of `file` starting at `offset` to `socket`, and returns the number of
+    /// bytes sent, which is less than `len` only when the file ends first. On Linux the kernel
+    /// hands the data to the socket with `sendfile`, so uploads never copy it through userspace,
+    /// elsewhere it is read and written in chunks. The position of `file` is unspecified
+    /// afterwards.
+    #[cfg(feature = "http")]
+    pub async fn send_file(
+        &self,
+        file: &mut File,
+        offset: u64,
+        len: u64,
+        socket: &mut tokio::net::TcpStream,
+    ) -> Result<u64, Error> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+
+            use tokio::io::Interest;
+
+            use crate::disk::sendfile;
+
+            let (in_fd, out_fd) = (file.as_raw_fd(), socket.as_raw_fd());
+            let mut sent = 0;
+            while sent < len {
+                socket.writable().await?;
+                match socket.try_io(Interest::WRITABLE, || {
+                    sendfile::send(in_fd, offset + sent, len - sent, out_fd)
+                }) {
+                    Ok(0) => return Ok(sent),
+                    Ok(n) => sent += n,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) if sent == 0 && sendfile::is_unsupported(&e) => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if sent == len {
+                return Ok(sent);
+            }
+        }
+
+        AsyncSeekExt::seek(file, std::io::SeekFrom::Start(offset)).await?;
+        Ok(tokio::io::copy(&mut AsyncReadExt::take(file, len), socket).await?)
+    }
+}
+
+/// Settings of [`TokioNfsFs`].
+#[derive(Debug, Clone, Copy)]
+pub struct NfsOptions {
+    /// Syncs the parent directory after creating, removing or renaming an entry, so the change
+    /// is durable once the call returns.
+    pub sync_dirs: bool,
+    /// How often a rename is retried after failing with a stale file handle.
+    pub rename_retries: u32,
+}
+
+impl Default for NfsOptions {
+    fn default() -> Self {
+        Self {
+            sync_dirs: true,
+            rename_retries: 3,
+        }
+    }
+}
+
+/// A [`TokioFs`] for directories hosted on NFS or other network filesystems.
+///
+/// Directory entries are synced after every change, as network filesystems may acknowledge
+/// metadata operations before they reached the server, and [`TokioNfsFs::rename`] tolerates the
+/// retransmission quirks of NFS. Like [`TokioFs`] it never opens files with `O_DIRECT` and never
+/// takes file locks, both of which are unreliable over NFS.
+#[derive(Default)]
+pub struct TokioNfsFs {
+    fs: TokioFs,
+    options: NfsOptions,
+}
+
+impl TokioNfsFs {
+    pub fn new(options: NfsOptions) -> Self {
+        Self {
+            fs: TokioFs,
+            options,
+        }
+    }
+
+    async fn sync_parent_dir(&self, path: &Path) -> Result<(), Error> {
+        match self.options.sync_dirs {
+            true => self.fs.sync_parent_dir(path).await,
+            false => Ok(()),
+        }
+    }
+
+    /// Moves `from` to `to`, replacing `to` if it exists.
+    pub async fn rename(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let local_from = path_to_local(from)?;
+        let local_to = path_to_local(to)?;
+        let retries = self.options.rename_retries;
+
+        spawn_blocking(move || dir::rename(&local_from, &local_to, retries))
+            .await
+            .map_err(io::Error::from)??;
+        self.sync_parent_dir(from).await?;
+        self.sync_parent_dir(to).await
+    }
+
+    /// Replaces `path` with `buf` atomically like [`TokioFs::atomic_write`], renaming with the
+    /// retries of [`TokioNfsFs::rename`].
+    pub async fn atomic_write<B: IoBuf>(&self, path: &Path, buf: B) -> Result<(), Error> {
+        let (mut file, mut guard) = self.fs.create_temp(path).await?;
+        let (result, _) = file.write_all(buf).await;
+        result?;
+        file.flush().await?;
+        file.sync_all().await?;
+        guard.link()?;
+
+        self.rename(guard.path(), path).await?;
+        guard.keep()?;
+        Ok(())
+    }
+}
+
+impl Fs for TokioNfsFs {
+    type File = File;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        let create = options.create;
+        let file = self.fs.open_options(path, options).await?;
+        if create {
+            self.sync_parent_dir(path).await?;
+        }
+        Ok(file)
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        self.fs.create_dir_all(path).await?;
+        self.sync_parent_dir(path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        self.fs.list(path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        self.fs.list_options(path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        self.fs.remove(path).await?;
+        self.sync_parent_dir(path).await
+    }
+
+    /// Copies with reflinks or inside the kernel like [`TokioFs`] and syncs the directory of the
+    /// copy.
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        Fs::copy(&self.fs, from, to).await?;
+        self.sync_parent_dir(to).await
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        self.fs.rename_new(from, to).await?;
+        self.sync_parent_dir(to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        self.fs.stat(path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        self.fs.create_temp(prefix).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "completion-based"))]
+    #[tokio::test]
+    async fn nfs_create_and_rename() {
+        use futures_util::StreamExt;
+        use tempfile::TempDir;
+
+        use super::TokioNfsFs;
+        use crate::{
+            fs::{Fs, ListOptions, OpenOptions},
+            path::Path,
+            Write,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let fs = TokioNfsFs::default();
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+
+        fs.create_dir_all(&path("sub")).await.unwrap();
+        let mut file = fs
+            .open_options(&path("sub/tmp"), OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        file.write_all(&b"fusio"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+
+        fs.rename(&path("sub/tmp"), &path("manifest"))
+            .await
+            .unwrap();
+        assert!(!dir.path().join("sub/tmp").exists());
+        assert_eq!(
+            std::fs::read(dir.path().join("manifest")).unwrap(),
+            b"fusio"
+        );
+
+        fs.copy(&path("manifest"), &path("sub/copy")).await.unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("sub/copy")).unwrap(),
+            b"fusio"
+        );
+        let root = Path::from_absolute_path(dir.path()).unwrap();
+        let listing = fs
+            .list_options(&root, ListOptions::default().limit(1))
+            .await
+            .unwrap();
+        assert_eq!(listing.collect::<Vec<_>>().await.len(), 1);
+
+        fs.remove(&path("manifest")).await.unwrap();
+        assert!(!dir.path().join("manifest").exists());
+    }
+
+    #[cfg(not(feature = "completion-based"))]
+    #[tokio::test]
+    async fn atomic_write_replaces_file() {
+        use tempfile::TempDir;
+
+        use super::TokioFs;
+        use crate::path::Path;
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("manifest")).unwrap();
+        std::fs::write(dir.path().join("manifest"), b"old").unwrap();
+
+        TokioFs.atomic_write(&path, &b"new"[..]).await.unwrap();
+        assert_eq!(std::fs::read(dir.path().join("manifest")).unwrap(), b"new");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(not(feature = "completion-based"))]
+    #[tokio::test]
+    async fn rename_new_never_replaces() {
+        use tempfile::TempDir;
+
+        use super::TokioFs;
+        use crate::{fs::Fs, path::Path, Error};
+
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+        std::fs::write(dir.path().join("staged"), b"new").unwrap();
+        std::fs::write(dir.path().join("taken"), b"old").unwrap();
+
+        let err = TokioFs.rename_new(&path("staged"), &path("taken")).await;
+        assert!(matches!(err, Err(Error::AlreadyExists { path: p }) if p == path("taken")));
+        assert_eq!(std::fs::read(dir.path().join("taken")).unwrap(), b"old");
+
+        TokioFs
+            .rename_new(&path("staged"), &path("published"))
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(dir.path().join("published")).unwrap(), b"new");
+        assert!(!dir.path().join("staged").exists());
+    }
+
+    #[cfg(not(feature = "completion-based"))]
+    #[tokio::test]
+    async fn create_new_fails_for_existing_files() {
+        use tempfile::TempDir;
+
+        use super::TokioFs;
+        use crate::{
+            fs::{Fs, OpenOptions},
+            path::Path,
+            Error,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("LOCK")).unwrap();
+        let options = OpenOptions::default().create_new(true);
+
+        TokioFs.open_options(&path, options.clone()).await.unwrap();
+        let err = TokioFs.open_options(&path, options).await.unwrap_err();
+        assert!(matches!(err, Error::AlreadyExists { path: p } if p == path));
+    }
+
+    #[cfg(all(feature = "http", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn send_file_to_socket() {
+        use tempfile::NamedTempFile;
+        use tokio::{
+            io::AsyncReadExt,
+            net::{TcpListener, TcpStream},
+        };
+
+        use super::TokioFs;
+
+        let content = (0..1 << 20).map(|i| i as u8).collect::<Vec<_>>();
+        let source = NamedTempFile::new().unwrap();
+        std::fs::write(source.path(), &content).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut sender = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut receiver, _) = listener.accept().await.unwrap();
+        let received = tokio::spawn(async move {
+            let mut buf = Vec::new();
+            receiver.read_to_end(&mut buf).await.unwrap();
+            buf
+        });
+
+        let mut file = tokio::fs::File::open(source.path()).await.unwrap();
+        let sent = TokioFs
+            .send_file(&mut file, 1024, 1 << 20, &mut sender)
+            .await
+            .unwrap();
+        assert_eq!(sent, (1 << 20) - 1024);
+        drop(sender);
+
+        assert!(received.await.unwrap() == content[1024..]);
+    }
 }
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+
+use futures_core::Stream;
+use futures_util::stream;
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind, RecursiveMode, Watcher,
+};
+use tokio::sync::mpsc::unbounded_channel;
+
+use crate::{
+    disk::symlink,
+    fs::WatchEvent,
+    path::{path_to_local, Path},
+    Error,
+};
+
+fn created(path: &std::path::Path) -> Option<Result<WatchEvent, Error>> {
+    // the file may already be gone again, its removal is reported by a later event
+    symlink::metadata(path, false)
+        .ok()
+        .map(WatchEvent::Created)
+        .map(Ok)
+}
+
+fn modified(path: &std::path::Path) -> Option<Result<WatchEvent, Error>> {
+    symlink::metadata(path, false)
+        .ok()
+        .map(WatchEvent::Modified)
+        .map(Ok)
+}
+
+fn removed(path: &std::path::Path) -> Option<Result<WatchEvent, Error>> {
+    Some(
+        Path::from_absolute_path(path)
+            .map(WatchEvent::Removed)
+            .map_err(Error::from),
+    )
+}
+
+fn translate(event: Event, pending: &mut VecDeque<Result<WatchEvent, Error>>) {
+    let paths = event.paths.iter();
+    match event.kind {
+        EventKind::Create(_) => pending.extend(paths.filter_map(|path| created(path))),
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            pending.extend(paths.filter_map(|path| removed(path)))
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            pending.extend(paths.filter_map(|path| created(path)))
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            if let [from, to] = &event.paths[..] {
+                pending.extend(removed(from));
+                pending.extend(created(to));
+            }
+        }
+        EventKind::Modify(_) => pending.extend(paths.filter_map(|path| modified(path))),
+        EventKind::Any | EventKind::Access(_) | EventKind::Other => {}
+    }
+}
+
+/// Watches the direct children of `path` for changes using the native notification mechanism
+/// of the platform (inotify, FSEvents, ...). Dropping the stream stops watching.
+pub(super) fn notify_watch(
+    path: &Path,
+) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+    let path = path_to_local(path)?;
+    let (sender, receiver) = unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    })
+    .map_err(|err| Error::Other(err.into()))?;
+    watcher
+        .watch(&path, RecursiveMode::NonRecursive)
+        .map_err(|err| Error::Other(err.into()))?;
+
+    Ok(stream::unfold(
+        (watcher, receiver, VecDeque::new()),
+        |(watcher, mut receiver, mut pending)| async move {
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((event, (watcher, receiver, pending)));
+                }
+                match receiver.recv().await? {
+                    Ok(event) => translate(event, &mut pending),
+                    Err(err) => pending.push_back(Err(Error::Other(err.into()))),
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use crate::{
+        disk::TokioFs,
+        fs::{Fs, WatchEvent, WatchOptions},
+        path::Path,
+    };
+
+    #[tokio::test]
+    async fn watch_reports_created_file() {
+        let dir = TempDir::new().unwrap();
+        let fs = TokioFs;
+        let path = Path::from_absolute_path(dir.path()).unwrap();
+        let mut events = Box::pin(fs.watch(path, WatchOptions::default()).await.unwrap());
+
+        std::fs::write(dir.path().join("manifest"), b"v1").unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let WatchEvent::Created(meta) = events.next().await.unwrap().unwrap() {
+                    return meta;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(event.path.filename(), Some("manifest"));
+    }
+}
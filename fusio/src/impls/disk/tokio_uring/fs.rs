@@ -1,10 +1,14 @@
+use std::path::PathBuf;
+
 use async_stream::stream;
 use futures_core::Stream;
-use tokio_uring::fs::{create_dir_all, remove_file};
+use tokio_uring::fs::create_dir_all;
 
 use crate::{
-    disk::tokio_uring::TokioUringFile,
-    fs::{FileMeta, Fs, OpenOptions},
+    disk::{
+        open_error, permissions, remove_temp, rename_new, symlink, tokio_uring::TokioUringFile,
+    },
+    fs::{temp_path, FileMeta, Fs, OpenOptions, Permissions, TempGuard},
     path::{path_to_local, Path},
     Error,
 };
@@ -21,9 +25,11 @@ impl Fs for TokioUringFs {
             .read(options.read)
             .write(options.write)
             .create(options.create)
+            .create_new(options.create_new)
             .truncate(options.truncate)
             .open(&local_path)
-            .await?;
+            .await
+            .map_err(|e| open_error(path, e))?;
 
         Ok(TokioUringFile {
             file: Some(file),
@@ -31,7 +37,7 @@ impl Fs for TokioUringFs {
         })
     }
 
-    async fn create_dir_all(path: &Path) -> Result<(), Error> {
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
         let path = path_to_local(path)?;
         create_dir_all(path).await?;
 
@@ -41,21 +47,97 @@ impl Fs for TokioUringFs {
     async fn list(
         &self,
         path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        self.list_with(path, false).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        self.remove_with(path, false).await
+    }
+
+    /// Follows symlinks, like opening the file does.
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        self.metadata(path, true).await
+    }
+
+    /// Hard links `to` and removes `from`, a link never replaces an existing file.
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        rename_new(from, to)
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let path = temp_path(prefix)?;
+        let file = self
+            .open_options(&path, OpenOptions::default().create(true).truncate(true))
+            .await?;
+
+        Ok((file, TempGuard::with_drop_hook(path, remove_temp)))
+    }
+}
+
+impl TokioUringFs {
+    /// Returns the metadata of `path`. With `follow_symlinks` the size is taken from the file a
+    /// symlink points to instead of the link itself.
+    pub async fn metadata(&self, path: &Path, follow_symlinks: bool) -> Result<FileMeta, Error> {
+        let path = path_to_local(path)?;
+
+        symlink::metadata(&path, follow_symlinks)
+    }
+
+    /// Lists `path` like [`Fs::list`], choosing whether symlinks are followed for entry metadata.
+    /// Entries are always reported at their own location, links are never resolved. Entries are
+    /// sorted by file name.
+    pub async fn list_with(
+        &self,
+        path: &Path,
+        follow_symlinks: bool,
     ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
         let path = path_to_local(path)?;
-        let dir = path.read_dir()?;
+        let dir = symlink::sorted_read_dir(&path)?;
 
         Ok(stream! {
             for entry in dir {
-                let entry = entry?;
-                yield Ok(FileMeta { path: Path::from_filesystem_path(entry.path())?, size: entry.metadata()?.len() });
+                yield symlink::entry_meta(entry, follow_symlinks);
             }
         })
     }
 
-    async fn remove(&self, path: &Path) -> Result<(), Error> {
+    /// Removes `path`. A symlink is removed itself unless `follow_symlinks` is set, in which case
+    /// the file it points to is removed instead.
+    pub async fn remove_with(&self, path: &Path, follow_symlinks: bool) -> Result<(), Error> {
+        let path = path_to_local(path)?;
+
+        symlink::remove(&path, follow_symlinks)
+    }
+
+    /// Returns the target of the symlink at `path` exactly as stored, which may be relative.
+    pub async fn read_link(&self, path: &Path) -> Result<PathBuf, Error> {
+        let path = path_to_local(path)?;
+
+        symlink::read_link(&path)
+    }
+
+    /// Creates a symlink at `link` pointing to `target`. A relative `target` is resolved against
+    /// the directory of `link` when the link is followed.
+    pub async fn symlink(
+        &self,
+        target: impl AsRef<std::path::Path>,
+        link: &Path,
+    ) -> Result<(), Error> {
+        let link = path_to_local(link)?;
+
+        symlink::symlink(target.as_ref(), &link)
+    }
+
+    /// Changes the mode, ownership or read-only flag of `path`, see [`Permissions`] for what
+    /// each platform supports.
+    pub async fn set_permissions(
+        &self,
+        path: &Path,
+        permissions: Permissions,
+    ) -> Result<(), Error> {
         let path = path_to_local(path)?;
 
-        Ok(remove_file(path).await?)
+        permissions::set_permissions(&path, permissions)
     }
 }
@@ -1,12 +1,17 @@
 #[cfg(feature = "fs")]
 pub mod fs;
+mod runtime;
 
 #[allow(unused)]
 #[cfg(feature = "fs")]
 pub use fs::TokioUringFs;
+pub use runtime::TokioUringBuilder;
+use std::io::ErrorKind;
+
+use futures_util::future::join_all;
 use tokio_uring::fs::File;
 
-use crate::{Error, IoBuf, IoBufMut, Read, Write};
+use crate::{buf::buf_len, Error, IoBuf, IoBufMut, Read, Write};
 
 #[repr(transparent)]
 struct TokioUringBuf<B> {
@@ -67,6 +72,34 @@ impl Write for TokioUringFile {
         (result.map_err(Error::from), buf.buf)
     }
 
+    /// Submits every write before awaiting any of them, so they reach the ring in one batch. On
+    /// error, buffers after the failed one may have been written as well.
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        let file = self.file.as_ref().expect("write file after closed");
+        let mut pos = self.pos;
+        let writes = bufs
+            .into_iter()
+            .map(|buf| {
+                let at = pos;
+                pos += buf.bytes_init() as u64;
+                file.write_all_at(TokioUringBuf { buf }, at)
+            })
+            .collect::<Vec<_>>();
+
+        let mut result = Ok(());
+        let mut written = Vec::with_capacity(writes.len());
+        for (outcome, buf) in join_all(writes).await {
+            if result.is_ok() {
+                match outcome {
+                    Ok(()) => self.pos += buf.buf.bytes_init() as u64,
+                    Err(e) => result = Err(Error::from(e)),
+                }
+            }
+            written.push(buf.buf);
+        }
+        (result, written)
+    }
+
     async fn flush(&mut self) -> Result<(), Error> {
         self.file
             .as_ref()
@@ -76,6 +109,15 @@ impl Write for TokioUringFile {
         Ok(())
     }
 
+    async fn barrier(&mut self) -> Result<(), Error> {
+        self.file
+            .as_ref()
+            .expect("barrier on closed file")
+            .sync_data()
+            .await?;
+        Ok(())
+    }
+
     async fn close(&mut self) -> Result<(), Error> {
         File::close(self.file.take().expect("close file twice")).await?;
         Ok(())
@@ -91,14 +133,32 @@ impl Read for TokioUringFile {
             .read_exact_at(TokioUringBuf { buf }, pos)
             .await;
 
-        (result.map_err(Error::from), buf.buf)
+        match result {
+            Ok(()) => (Ok(()), buf.buf),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                let requested = buf.buf.bytes_init() as u64;
+                match self.size().await {
+                    Ok(size) => (
+                        Err(Error::UnexpectedEof {
+                            requested,
+                            available: size.saturating_sub(pos),
+                        }),
+                        buf.buf,
+                    ),
+                    Err(e) => (Err(e), buf.buf),
+                }
+            }
+            Err(e) => (Err(Error::from(e)), buf.buf),
+        }
     }
 
     async fn read_to_end_at(&mut self, mut buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
-        match self.size().await {
-            Ok(size) => {
-                buf.resize((size - pos) as usize, 0);
-            }
+        match self
+            .size()
+            .await
+            .and_then(|size| buf_len(size.saturating_sub(pos)))
+        {
+            Ok(len) => buf.resize(len, 0),
             Err(e) => return (Err(e), buf),
         }
 
@@ -115,6 +175,29 @@ impl Read for TokioUringFile {
         }
     }
 
+    /// Submits every read before awaiting any of them, so they reach the ring in one batch.
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        let file = self.file.as_ref().expect("read file after closed");
+        let reads = ranges
+            .iter()
+            .map(|&(pos, len)| file.read_exact_at(TokioUringBuf { buf: vec![0u8; len] }, pos));
+
+        let mut bufs = Vec::with_capacity(ranges.len());
+        for (&(pos, len), (result, buf)) in ranges.iter().zip(join_all(reads).await) {
+            match result {
+                Ok(()) => bufs.push(buf.buf),
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    return Err(Error::UnexpectedEof {
+                        requested: len as u64,
+                        available: self.size().await?.saturating_sub(pos),
+                    });
+                }
+                Err(e) => return Err(Error::from(e)),
+            }
+        }
+        Ok(bufs)
+    }
+
     async fn size(&self) -> Result<u64, Error> {
         let stat = self
             .file
@@ -0,0 +1,108 @@
+use std::{future::Future, time::Duration};
+
+use crate::Error;
+
+const DEFAULT_ENTRIES: u32 = 256;
+
+/// Starts the io_uring runtime [`super::TokioUringFs`] runs on with tuned ring settings, in place
+/// of `tokio_uring::start`, for deployments where syscall overhead dominates.
+///
+/// Registered file descriptors are not offered, `tokio-uring` does not expose the ring to
+/// register them with.
+#[derive(Debug, Clone, Copy)]
+pub struct TokioUringBuilder {
+    entries: u32,
+    completion_entries: Option<u32>,
+    sqpoll_idle: Option<Duration>,
+    sqpoll_cpu: Option<u32>,
+}
+
+impl Default for TokioUringBuilder {
+    fn default() -> Self {
+        Self {
+            entries: DEFAULT_ENTRIES,
+            completion_entries: None,
+            sqpoll_idle: None,
+            sqpoll_cpu: None,
+        }
+    }
+}
+
+impl TokioUringBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The depth of the submission queue, 256 by default. The kernel rounds it up to a power of
+    /// two.
+    pub fn entries(mut self, entries: u32) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    /// The depth of the completion queue, twice the submission queue by default.
+    pub fn completion_entries(mut self, entries: u32) -> Self {
+        self.completion_entries = Some(entries);
+        self
+    }
+
+    /// Lets a kernel thread poll the submission queue, so submitting I/O takes no syscall while
+    /// the thread is awake. It goes to sleep after being idle for `idle`.
+    ///
+    /// Older kernels only allow it with `CAP_SYS_ADMIN`, [`TokioUringBuilder::start`] fails
+    /// otherwise.
+    pub fn sqpoll(mut self, idle: Duration) -> Self {
+        self.sqpoll_idle = Some(idle);
+        self
+    }
+
+    /// Pins the polling thread of [`TokioUringBuilder::sqpoll`] to `cpu`.
+    pub fn sqpoll_cpu(mut self, cpu: u32) -> Self {
+        self.sqpoll_cpu = Some(cpu);
+        self
+    }
+
+    /// Runs `future` to completion on a runtime with these settings. Fails if the kernel rejects
+    /// them.
+    pub fn start<F: Future>(&self, future: F) -> Result<F::Output, Error> {
+        let mut uring = tokio_uring::uring_builder();
+        if let Some(entries) = self.completion_entries {
+            uring.setup_cqsize(entries);
+        }
+        if let Some(idle) = self.sqpoll_idle {
+            uring.setup_sqpoll(idle.as_millis().try_into().unwrap_or(u32::MAX));
+            if let Some(cpu) = self.sqpoll_cpu {
+                uring.setup_sqpoll_cpu(cpu);
+            }
+        }
+
+        let mut builder = tokio_uring::builder();
+        builder.entries(self.entries).uring_builder(&uring);
+        let runtime = tokio_uring::Runtime::new(&builder)?;
+        Ok(runtime.block_on(future))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn starts_with_tuned_ring() {
+        use tempfile::tempfile;
+        use tokio_uring::fs::File;
+
+        use super::TokioUringBuilder;
+        use crate::{disk::tokio_uring::TokioUringFile, Read, Write};
+
+        let len = TokioUringBuilder::new()
+            .entries(64)
+            .completion_entries(512)
+            .start(async {
+                let mut file = TokioUringFile::from(File::from_std(tempfile().unwrap()));
+                let (result, _) = file.write_all(&b"fusio"[..]).await;
+                result.unwrap();
+                file.size().await.unwrap()
+            })
+            .unwrap();
+        assert_eq!(len, 5);
+    }
+}
@@ -0,0 +1,191 @@
+use std::{fs::create_dir_all, path::PathBuf};
+
+use futures_core::Stream;
+use futures_util::stream;
+
+use super::WasiFile;
+use crate::{
+    disk::{open_error, permissions, remove_temp, rename_new, symlink},
+    fs::{temp_path, FileMeta, Fs, OpenOptions, Permissions, TempGuard},
+    path::{path_to_local, Path},
+    Error,
+};
+
+/// A local [`Fs`] for WASI components, e.g. running in wasmtime, which reach the host filesystem
+/// through `wasi:filesystem` instead of a browser API.
+///
+/// Paths resolve against the directories preopened by the host, anything outside of them fails
+/// with a permission error. No async runtime is needed, every call completes synchronously.
+#[derive(Default)]
+pub struct WasiFs;
+
+impl Fs for WasiFs {
+    type File = WasiFile;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        let local_path = path_to_local(path)?;
+
+        Ok(WasiFile::from(
+            std::fs::OpenOptions::new()
+                .read(options.read)
+                .write(options.write)
+                .create(options.create)
+                .create_new(options.create_new)
+                .truncate(options.truncate)
+                .open(local_path)
+                .map_err(|e| open_error(path, e))?,
+        ))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        let path = path_to_local(path)?;
+        create_dir_all(path)?;
+
+        Ok(())
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        self.list_with(path, false).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        self.remove_with(path, false).await
+    }
+
+    /// Follows symlinks, like opening the file does.
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        self.metadata(path, true).await
+    }
+
+    /// Hard links `to` and removes `from`, a link never replaces an existing file.
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        rename_new(from, to)
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let path = temp_path(prefix)?;
+        let file = self
+            .open_options(&path, OpenOptions::default().create(true).truncate(true))
+            .await?;
+
+        Ok((file, TempGuard::with_drop_hook(path, remove_temp)))
+    }
+}
+
+impl WasiFs {
+    /// Returns the metadata of `path`. With `follow_symlinks` the size is taken from the file a
+    /// symlink points to instead of the link itself.
+    pub async fn metadata(&self, path: &Path, follow_symlinks: bool) -> Result<FileMeta, Error> {
+        let path = path_to_local(path)?;
+
+        symlink::metadata(&path, follow_symlinks)
+    }
+
+    /// Lists `path` like [`Fs::list`], choosing whether symlinks are followed for entry metadata.
+    /// Entries are always reported at their own location, links are never resolved. Entries are
+    /// sorted by file name.
+    pub async fn list_with(
+        &self,
+        path: &Path,
+        follow_symlinks: bool,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let path = path_to_local(path)?;
+        let dir = symlink::sorted_read_dir(&path)?;
+
+        Ok(stream::iter(dir.into_iter().map(move |entry| {
+            symlink::entry_meta(entry, follow_symlinks)
+        })))
+    }
+
+    /// Removes `path`. A symlink is removed itself unless `follow_symlinks` is set, in which case
+    /// the file it points to is removed instead.
+    pub async fn remove_with(&self, path: &Path, follow_symlinks: bool) -> Result<(), Error> {
+        let path = path_to_local(path)?;
+
+        symlink::remove(&path, follow_symlinks)
+    }
+
+    /// Returns the target of the symlink at `path` exactly as stored, which may be relative.
+    pub async fn read_link(&self, path: &Path) -> Result<PathBuf, Error> {
+        let path = path_to_local(path)?;
+
+        symlink::read_link(&path)
+    }
+
+    /// Creates a symlink at `link` pointing to `target`. Hosts may refuse symlinks, in which case
+    /// this fails with [`Error::Unsupported`].
+    pub async fn symlink(
+        &self,
+        target: impl AsRef<std::path::Path>,
+        link: &Path,
+    ) -> Result<(), Error> {
+        let link = path_to_local(link)?;
+
+        symlink::symlink(target.as_ref(), &link)
+    }
+
+    /// Changes the read-only flag of `path`, WASI has no mode bits or ownership.
+    pub async fn set_permissions(
+        &self,
+        path: &Path,
+        permissions: Permissions,
+    ) -> Result<(), Error> {
+        let path = path_to_local(path)?;
+
+        permissions::set_permissions(&path, permissions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{FutureExt, StreamExt};
+    use tempfile::TempDir;
+
+    use super::WasiFs;
+    use crate::{
+        fs::{Fs, OpenOptions},
+        path::Path,
+        Read, Write,
+    };
+
+    #[test]
+    fn write_read_and_list() {
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+        let fs = WasiFs;
+
+        // every call completes synchronously, no runtime needed
+        async {
+            let mut file = fs
+                .open_options(&path("file"), OpenOptions::default().create(true))
+                .await
+                .unwrap();
+            file.write_all(&b"hello, "[..]).await.0.unwrap();
+            file.write_all(&b"wasi"[..]).await.0.unwrap();
+            file.close().await.unwrap();
+
+            let mut file = fs.open(&path("file")).await.unwrap();
+            assert_eq!(file.size().await.unwrap(), 11);
+            let (result, buf) = file.read_exact_at(vec![0; 4], 7).await;
+            result.unwrap();
+            assert_eq!(buf, b"wasi");
+
+            let listed = fs
+                .list(&path(""))
+                .await
+                .unwrap()
+                .map(|meta| meta.unwrap().path)
+                .collect::<Vec<_>>()
+                .await;
+            assert_eq!(listed, vec![path("file")]);
+
+            fs.remove(&path("file")).await.unwrap();
+            assert!(fs.open(&path("file")).await.is_err());
+        }
+        .now_or_never()
+        .unwrap();
+    }
+}
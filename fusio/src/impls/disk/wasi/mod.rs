@@ -0,0 +1,88 @@
+#[cfg(feature = "fs")]
+pub mod fs;
+
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Seek, SeekFrom},
+};
+
+use crate::{buf::IoBufMut, Error, IoBuf, Read, Write};
+
+/// A file of [`fs::WasiFs`]. Every operation completes synchronously through `wasi:filesystem`,
+/// as WASI components have no threads to move blocking calls to.
+pub struct WasiFile {
+    file: File,
+    pos: u64,
+}
+
+impl From<File> for WasiFile {
+    fn from(file: File) -> Self {
+        Self { file, pos: 0 }
+    }
+}
+
+impl Write for WasiFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        let result = self
+            .file
+            .seek(SeekFrom::Start(self.pos))
+            .and_then(|_| io::Write::write_all(&mut self.file, buf.as_slice()));
+        if result.is_ok() {
+            self.pos += buf.bytes_init() as u64;
+        }
+        (result.map_err(Error::from), buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        io::Write::flush(&mut self.file).map_err(Error::from)
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        io::Write::flush(&mut self.file)?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        io::Write::flush(&mut self.file)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+impl Read for WasiFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, mut buf: B, pos: u64) -> (Result<(), Error>, B) {
+        if let Err(e) = self.file.seek(SeekFrom::Start(pos)) {
+            return (Err(Error::Io(e)), buf);
+        }
+        match io::Read::read_exact(&mut self.file, buf.as_slice_mut()) {
+            Ok(()) => (Ok(()), buf),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                let requested = buf.bytes_init() as u64;
+                match self.file.metadata() {
+                    Ok(metadata) => (
+                        Err(Error::UnexpectedEof {
+                            requested,
+                            available: metadata.len().saturating_sub(pos),
+                        }),
+                        buf,
+                    ),
+                    Err(e) => (Err(Error::Io(e)), buf),
+                }
+            }
+            Err(e) => (Err(Error::Io(e)), buf),
+        }
+    }
+
+    async fn read_to_end_at(&mut self, mut buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        let result = self
+            .file
+            .seek(SeekFrom::Start(pos))
+            .and_then(|_| io::Read::read_to_end(&mut self.file, &mut buf));
+        (result.map(|_| ()).map_err(Error::from), buf)
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        Ok(self.file.metadata()?.len())
+    }
+}
@@ -1,58 +1,18 @@
 //! Implementations of the traits in the `fusio` crate.
 
+#[cfg(feature = "archive")]
+pub mod archive;
 pub mod buffered;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub mod compression;
 pub mod disk;
+#[cfg(any(feature = "csv", feature = "ndjson"))]
+pub mod records;
 pub mod remotes;
 
-use std::{
-    future::Future,
-    io::{self, Cursor},
-};
+use std::{future::Future, io::Cursor};
 
-use crate::{Error, IoBuf, IoBufMut, MaybeSend, Read, Write};
-
-impl Read for &mut Vec<u8> {
-    async fn read_exact_at<B: IoBufMut>(&mut self, mut buf: B, pos: u64) -> (Result<(), Error>, B) {
-        let pos = pos as usize;
-        let len = buf.bytes_init();
-        let end = pos + len;
-        if end > self.len() {
-            return (
-                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "").into()),
-                buf,
-            );
-        }
-        buf.as_slice_mut().copy_from_slice(&self[pos..end]);
-        (Ok(()), buf)
-    }
-
-    async fn read_to_end_at(&mut self, mut buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
-        let pos = pos as usize;
-        buf.extend_from_slice(&self[pos..]);
-        (Ok(()), buf)
-    }
-
-    async fn size(&self) -> Result<u64, Error> {
-        Ok(self.len() as u64)
-    }
-}
-
-impl Write for Cursor<&mut Vec<u8>> {
-    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
-        (
-            std::io::Write::write_all(self, buf.as_slice()).map_err(Error::Io),
-            buf,
-        )
-    }
-
-    async fn flush(&mut self) -> Result<(), Error> {
-        Ok(())
-    }
-
-    async fn close(&mut self) -> Result<(), Error> {
-        Ok(())
-    }
-}
+use crate::{Error, IoBufMut, MaybeSend, Read};
 
 pub trait SeqRead: MaybeSend {
     fn read_exact<B: IoBufMut>(
@@ -0,0 +1,187 @@
+//! Streaming readers of text records on top of [`BufReader`], so newline-delimited JSON or CSV
+//! objects can be ingested from any [`Read`], e.g. a [`DynFile`](crate::dynamic::DynFile) on S3,
+//! without staging them to disk first. Only one buffer of the reader and one record are held in
+//! memory at a time.
+
+use serde::de::DeserializeOwned;
+
+use crate::{buffered::BufReader, Error, Read};
+
+/// Reads a file line by line through a [`BufReader`].
+struct Lines<F> {
+    reader: BufReader<F>,
+    pos: u64,
+}
+
+impl<F: Read> Lines<F> {
+    /// Appends the next line, including its newline, to `buf` and returns its length, which is
+    /// `0` at the end of the file.
+    async fn next(&mut self, buf: &mut Vec<u8>) -> Result<usize, Error> {
+        let len = self.reader.read_until(b'\n', self.pos, buf).await?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+/// Reads newline-delimited JSON (NDJSON, JSON Lines), one value per line. Blank lines are
+/// skipped.
+#[cfg(feature = "ndjson")]
+pub struct NdjsonReader<F> {
+    lines: Lines<F>,
+    line: Vec<u8>,
+}
+
+#[cfg(feature = "ndjson")]
+impl<F: Read> NdjsonReader<F> {
+    pub fn new(reader: BufReader<F>) -> Self {
+        Self {
+            lines: Lines { reader, pos: 0 },
+            line: Vec::new(),
+        }
+    }
+
+    /// Returns the next value, or `None` at the end of the file.
+    pub async fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+        loop {
+            self.line.clear();
+            if self.lines.next(&mut self.line).await? == 0 {
+                return Ok(None);
+            }
+            if self.line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            return serde_json::from_slice(&self.line)
+                .map(Some)
+                .map_err(|e| Error::Other(Box::new(e)));
+        }
+    }
+}
+
+/// Reads comma-separated values whose first record is the header, as described by RFC 4180.
+/// Quoted fields may span lines.
+#[cfg(feature = "csv")]
+pub struct CsvReader<F> {
+    lines: Lines<F>,
+    headers: csv::StringRecord,
+    record: Vec<u8>,
+}
+
+#[cfg(feature = "csv")]
+impl<F: Read> CsvReader<F> {
+    /// Reads the header of the file, which is empty if the file is.
+    pub async fn new(reader: BufReader<F>) -> Result<Self, Error> {
+        let mut reader = Self {
+            lines: Lines { reader, pos: 0 },
+            headers: csv::StringRecord::new(),
+            record: Vec::new(),
+        };
+        if let Some(headers) = reader.next_record().await? {
+            reader.headers = headers;
+        }
+        Ok(reader)
+    }
+
+    pub fn headers(&self) -> &csv::StringRecord {
+        &self.headers
+    }
+
+    /// Returns the next record as its fields, or `None` at the end of the file.
+    pub async fn next_record(&mut self) -> Result<Option<csv::StringRecord>, Error> {
+        self.record.clear();
+        // a record goes on while one of its fields is quoted, i.e. it has an odd number of quotes,
+        // as escaped quotes are doubled
+        loop {
+            if self.lines.next(&mut self.record).await? == 0 {
+                break;
+            }
+            if self.record.iter().filter(|byte| **byte == b'"').count() % 2 == 0 {
+                break;
+            }
+        }
+        if self.record.is_empty() {
+            return Ok(None);
+        }
+
+        let mut record = csv::StringRecord::new();
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(self.record.as_slice())
+            .read_record(&mut record)
+            .map_err(|e| Error::Other(Box::new(e)))?;
+        Ok(Some(record))
+    }
+
+    /// Returns the next record deserialized by the names in the header, or `None` at the end of
+    /// the file.
+    pub async fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+        let Some(record) = self.next_record().await? else {
+            return Ok(None);
+        };
+        record
+            .deserialize(Some(&self.headers))
+            .map(Some)
+            .map_err(|e| Error::Other(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::buffered::BufReader;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Event {
+        id: u64,
+        name: String,
+    }
+
+    #[cfg(feature = "ndjson")]
+    #[tokio::test]
+    async fn reads_ndjson() {
+        use super::NdjsonReader;
+
+        let mut content =
+            b"{\"id\":1,\"name\":\"open\"}\n\n{\"id\":2,\"name\":\"close\"}\r\n{\"id\":3".to_vec();
+        let reader = BufReader::new(&mut content, 8).await.unwrap();
+        let mut reader = NdjsonReader::new(reader);
+
+        let expected = [(1, "open"), (2, "close")];
+        for (id, name) in expected {
+            assert_eq!(
+                reader.next::<Event>().await.unwrap(),
+                Some(Event {
+                    id,
+                    name: name.to_string()
+                })
+            );
+        }
+        // the last line is torn
+        assert!(reader.next::<Event>().await.is_err());
+        assert_eq!(reader.next::<Event>().await.unwrap(), None);
+    }
+
+    #[cfg(feature = "csv")]
+    #[tokio::test]
+    async fn reads_csv() {
+        use super::CsvReader;
+
+        let mut content = b"id,name\n1,open\n2,\"multi\nline, \"\"quoted\"\"\"\n3,close".to_vec();
+        let reader = BufReader::new(&mut content, 8).await.unwrap();
+        let mut reader = CsvReader::new(reader).await.unwrap();
+        assert_eq!(reader.headers(), vec!["id", "name"]);
+
+        let expected = [(1, "open"), (2, "multi\nline, \"quoted\""), (3, "close")];
+        for (id, name) in expected {
+            assert_eq!(
+                reader.next::<Event>().await.unwrap(),
+                Some(Event {
+                    id,
+                    name: name.to_string()
+                })
+            );
+        }
+        assert_eq!(reader.next::<Event>().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,180 @@
+//! Addressing buckets by name, by S3 Access Point ARN or by Multi-Region Access Point, since many
+//! buckets are only exposed through access points.
+
+use percent_encoding::utf8_percent_encode;
+
+use super::STRICT_PATH_ENCODE_SET;
+use crate::path::Path;
+
+/// The signing region of Multi-Region Access Points, which are signed with SigV4A for every
+/// region at once.
+pub(crate) const MULTI_REGION: &str = "*";
+
+/// Where requests for the "bucket" given to [`AmazonS3Builder::new`] go.
+///
+/// [`AmazonS3Builder::new`]: super::fs::AmazonS3Builder::new
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum BucketAddress<'a> {
+    /// A bucket name, or an access point alias, which S3 accepts in place of one.
+    Bucket(&'a str),
+    /// `arn:{partition}:s3:{region}:{account}:accesspoint/{name}`
+    AccessPoint {
+        partition: &'a str,
+        region: &'a str,
+        account: &'a str,
+        name: &'a str,
+    },
+    /// `arn:{partition}:s3::{account}:accesspoint/{alias}`, or the alias ending in `.mrap` alone.
+    MultiRegion { alias: &'a str },
+}
+
+impl<'a> BucketAddress<'a> {
+    /// Parses `bucket`. Anything that is not an access point ARN or a Multi-Region Access Point
+    /// alias is taken as a bucket name.
+    pub(crate) fn parse(bucket: &'a str) -> Self {
+        if bucket.ends_with(".mrap") && !bucket.starts_with("arn:") {
+            return BucketAddress::MultiRegion { alias: bucket };
+        }
+        let mut fields = bucket.splitn(6, ':');
+        let (Some("arn"), Some(partition), Some("s3"), Some(region), Some(account), Some(resource)) = (
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+        ) else {
+            return BucketAddress::Bucket(bucket);
+        };
+        let Some(name) = resource
+            .strip_prefix("accesspoint/")
+            .or_else(|| resource.strip_prefix("accesspoint:"))
+        else {
+            return BucketAddress::Bucket(bucket);
+        };
+        match region {
+            "" => BucketAddress::MultiRegion { alias: name },
+            region => BucketAddress::AccessPoint {
+                partition,
+                region,
+                account,
+                name,
+            },
+        }
+    }
+
+    /// The endpoint objects are addressed under, with virtual-hosted style for buckets in
+    /// `region`.
+    pub(crate) fn endpoint(&self, region: &str) -> String {
+        match self {
+            BucketAddress::Bucket(bucket) => format!("https://{bucket}.s3.{region}.amazonaws.com"),
+            BucketAddress::AccessPoint {
+                partition,
+                region,
+                account,
+                name,
+            } => format!(
+                "https://{name}-{account}.s3-accesspoint.{region}.{}",
+                dns_suffix(partition)
+            ),
+            BucketAddress::MultiRegion { alias } => {
+                format!("https://{alias}.accesspoint.s3-global.amazonaws.com")
+            }
+        }
+    }
+
+    /// The region requests are signed for, access points are signed for their own region.
+    pub(crate) fn signing_region(&self, region: &'a str) -> &'a str {
+        match self {
+            BucketAddress::Bucket(_) => region,
+            BucketAddress::AccessPoint { region, .. } => region,
+            BucketAddress::MultiRegion { .. } => MULTI_REGION,
+        }
+    }
+}
+
+fn dns_suffix(partition: &str) -> &'static str {
+    match partition {
+        "aws-cn" => "amazonaws.com.cn",
+        _ => "amazonaws.com",
+    }
+}
+
+/// The `x-amz-copy-source` of the object at `path` in `bucket`, access points name objects as
+/// `{arn}/object/{key}`.
+pub(crate) fn copy_source(bucket: &str, path: &Path) -> String {
+    let key = utf8_percent_encode(path.as_ref(), &STRICT_PATH_ENCODE_SET);
+    match bucket.starts_with("arn:") {
+        true => format!("{bucket}/object/{key}"),
+        false => format!("{bucket}/{key}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_source, BucketAddress};
+    use crate::path::Path;
+
+    #[test]
+    fn addresses_buckets() {
+        let address = BucketAddress::parse("fusio-test");
+        assert_eq!(address, BucketAddress::Bucket("fusio-test"));
+        assert_eq!(
+            address.endpoint("eu-west-1"),
+            "https://fusio-test.s3.eu-west-1.amazonaws.com"
+        );
+        assert_eq!(address.signing_region("eu-west-1"), "eu-west-1");
+
+        // access point aliases are used like bucket names
+        let address = BucketAddress::parse("my-ap-hrzrlukc5m36ft7okagglf3gmwluquse1b-s3alias");
+        assert!(matches!(address, BucketAddress::Bucket(_)));
+    }
+
+    #[test]
+    fn addresses_access_points() {
+        let address = BucketAddress::parse("arn:aws:s3:us-west-2:123456789012:accesspoint/my-ap");
+        assert_eq!(
+            address.endpoint("us-east-1"),
+            "https://my-ap-123456789012.s3-accesspoint.us-west-2.amazonaws.com"
+        );
+        assert_eq!(address.signing_region("us-east-1"), "us-west-2");
+
+        let address =
+            BucketAddress::parse("arn:aws-cn:s3:cn-north-1:123456789012:accesspoint:my-ap");
+        assert_eq!(
+            address.endpoint("us-east-1"),
+            "https://my-ap-123456789012.s3-accesspoint.cn-north-1.amazonaws.com.cn"
+        );
+    }
+
+    #[test]
+    fn addresses_multi_region_access_points() {
+        for bucket in [
+            "arn:aws:s3::123456789012:accesspoint/mfzwi23gnjvgw.mrap",
+            "mfzwi23gnjvgw.mrap",
+        ] {
+            let address = BucketAddress::parse(bucket);
+            assert_eq!(
+                address,
+                BucketAddress::MultiRegion {
+                    alias: "mfzwi23gnjvgw.mrap"
+                }
+            );
+            assert_eq!(
+                address.endpoint("us-east-1"),
+                "https://mfzwi23gnjvgw.mrap.accesspoint.s3-global.amazonaws.com"
+            );
+            assert_eq!(address.signing_region("us-east-1"), "*");
+        }
+    }
+
+    #[test]
+    fn copies_through_access_points() {
+        let path = Path::parse("a b/c").unwrap();
+        assert_eq!(copy_source("bucket", &path), "bucket/a%20b/c");
+        assert_eq!(
+            copy_source("arn:aws:s3:us-west-2:123456789012:accesspoint/my-ap", &path),
+            "arn:aws:s3:us-west-2:123456789012:accesspoint/my-ap/object/a%20b/c"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Managing the bucket an [`AmazonS3`] is bound to, e.g. for integration tests and provisioning
+//! tools that bootstrap their buckets through fusio itself.
+
+use bytes::{Buf, Bytes};
+use http::{Method, Request, StatusCode};
+use http_body_util::{BodyExt, Empty, Full};
+use serde::{Deserialize, Serialize};
+
+use super::{fs::AmazonS3, response_error, unexpected_response, S3Error};
+use crate::{remotes::http::HttpError, Error};
+
+/// The region of buckets whose location constraint is empty.
+const DEFAULT_REGION: &str = "us-east-1";
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct CreateBucketConfiguration<'a> {
+    location_constraint: &'a str,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LocationConstraint {
+    #[serde(rename = "$text", default)]
+    region: String,
+}
+
+impl AmazonS3 {
+    fn bucket_url(&self) -> String {
+        format!("{}/", self.as_ref().options.endpoint)
+    }
+
+    /// Creates the bucket in the region of this client. Creating a bucket that already exists
+    /// and is owned by the caller succeeds, so bootstrapping can run repeatedly.
+    pub async fn create_bucket(&self) -> Result<(), Error> {
+        let region = &self.as_ref().options.region;
+        // buckets in us-east-1 are created without a configuration
+        let body = match region.as_str() {
+            DEFAULT_REGION => String::new(),
+            region => quick_xml::se::to_string(&CreateBucketConfiguration {
+                location_constraint: region,
+            })
+            .map_err(S3Error::from)?,
+        };
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(self.bucket_url())
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+
+        let (parts, content) = response.into_parts();
+        let content = content.collect().await.map_err(S3Error::from)?.to_bytes();
+        let error = response_error(parts.status, &parts.headers, &content);
+        match error.code() {
+            Some("BucketAlreadyOwnedByYou") => Ok(()),
+            _ => Err(error.into()),
+        }
+    }
+
+    /// Deletes the bucket, which S3 only allows once it is empty.
+    pub async fn delete_bucket(&self) -> Result<(), Error> {
+        let request = Request::builder()
+            .method(Method::DELETE)
+            .uri(self.bucket_url())
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        if !response.status().is_success() {
+            return Err(unexpected_response(response).await);
+        }
+        Ok(())
+    }
+
+    /// Whether the bucket exists, also if it lives in another region than this client. Buckets
+    /// the caller may not access are reported as an error.
+    pub async fn bucket_exists(&self) -> Result<bool, Error> {
+        let request = Request::builder()
+            .method(Method::HEAD)
+            .uri(self.bucket_url())
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            // S3 redirects to the region of the bucket
+            StatusCode::MOVED_PERMANENTLY => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            _ => Err(unexpected_response(response).await),
+        }
+    }
+
+    /// The region the bucket was created in.
+    pub async fn bucket_location(&self) -> Result<String, Error> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!("{}?location", self.bucket_url()))
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        if !response.status().is_success() {
+            return Err(unexpected_response(response).await);
+        }
+
+        let location: LocationConstraint = quick_xml::de::from_reader(
+            response
+                .collect()
+                .await
+                .map_err(S3Error::from)?
+                .aggregate()
+                .reader(),
+        )
+        .map_err(S3Error::from)?;
+        Ok(match location.region.as_str() {
+            "" => DEFAULT_REGION.to_string(),
+            // the legacy name of eu-west-1
+            "EU" => "eu-west-1".to_string(),
+            _ => location.region,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    async fn manages_the_bucket() {
+        use std::sync::Arc;
+
+        use crate::{fs::Fs, path::Path, remotes::aws::mock::MockS3};
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+
+        assert!(s3.bucket_exists().await.unwrap());
+        assert_eq!(s3.bucket_location().await.unwrap(), "us-east-1");
+
+        server.put("key", &b"value"[..]);
+        assert!(s3.delete_bucket().await.is_err());
+        s3.remove(&Path::parse("key").unwrap()).await.unwrap();
+        s3.delete_bucket().await.unwrap();
+        assert!(!s3.bucket_exists().await.unwrap());
+        assert!(s3.bucket_location().await.is_err());
+
+        s3.create_bucket().await.unwrap();
+        s3.create_bucket().await.unwrap();
+        assert!(s3.bucket_exists().await.unwrap());
+    }
+
+    #[test]
+    fn serializes_bucket_configuration() {
+        use super::CreateBucketConfiguration;
+
+        let configuration = CreateBucketConfiguration {
+            location_constraint: "eu-central-1",
+        };
+        assert_eq!(
+            quick_xml::se::to_string(&configuration).unwrap(),
+            concat!(
+                "<CreateBucketConfiguration>",
+                "<LocationConstraint>eu-central-1</LocationConstraint>",
+                "</CreateBucketConfiguration>"
+            )
+        );
+    }
+
+    #[test]
+    fn parses_location_constraint() {
+        use super::LocationConstraint;
+
+        let location: LocationConstraint = quick_xml::de::from_reader(
+            &br#"<LocationConstraint xmlns="http://s3.amazonaws.com/doc/2006-03-01/">eu-central-1</LocationConstraint>"#[..],
+        )
+        .unwrap();
+        assert_eq!(location.region, "eu-central-1");
+        let location: LocationConstraint = quick_xml::de::from_reader(
+            &br#"<LocationConstraint xmlns="http://s3.amazonaws.com/doc/2006-03-01/"/>"#[..],
+        )
+        .unwrap();
+        assert_eq!(location.region, "");
+    }
+}
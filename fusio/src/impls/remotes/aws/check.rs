@@ -0,0 +1,305 @@
+//! Pre-flight validation of an S3 configuration, telling misconfigurations apart so they can be
+//! fixed without digging through HTTP errors.
+
+use std::{error::Error as StdError, fmt, str::FromStr};
+
+use bytes::Bytes;
+use http::{HeaderMap, Method, Request, StatusCode};
+use http_body_util::{BodyExt, Empty};
+use url::Url;
+
+use super::{encode_query, fs::AmazonS3, S3Error, S3ResponseError};
+
+/// What kept a [`AmazonS3::check`] from succeeding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The endpoint host could not be resolved.
+    Dns,
+    /// The TLS handshake failed, e.g. because of an untrusted certificate.
+    Tls,
+    /// The endpoint could not be reached or the connection broke.
+    Connection,
+    /// The credential is missing, invalid or expired, or a request could not be signed.
+    Authentication,
+    /// The credential is valid but may not list the bucket.
+    Permission,
+    /// The clock of this machine is too far off the time of S3 for signatures to be accepted.
+    ClockSkew,
+    /// The bucket does not exist.
+    NoSuchBucket,
+    /// The bucket lives in another region than the configured one.
+    WrongRegion,
+    Other,
+}
+
+impl Problem {
+    fn describe(self) -> &'static str {
+        match self {
+            Problem::Dns => "DNS resolution failed",
+            Problem::Tls => "TLS handshake failed",
+            Problem::Connection => "connection failed",
+            Problem::Authentication => "authentication failed",
+            Problem::Permission => "permission denied",
+            Problem::ClockSkew => "clock skew",
+            Problem::NoSuchBucket => "no such bucket",
+            Problem::WrongRegion => "wrong region",
+            Problem::Other => "unexpected failure",
+        }
+    }
+}
+
+/// The outcome of a failed [`AmazonS3::check`]: the kind of problem, what S3 or the network
+/// reported, and a hint at how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub problem: Problem,
+    pub detail: String,
+}
+
+impl Diagnostic {
+    fn new(problem: Problem, detail: impl Into<String>) -> Self {
+        Self {
+            problem,
+            detail: detail.into(),
+        }
+    }
+
+    /// What to check or change to resolve the problem.
+    pub fn hint(&self) -> &'static str {
+        match self.problem {
+            Problem::Dns => "check the bucket name, the region and the DNS configuration",
+            Problem::Tls => {
+                "check the root certificates of this machine and proxies intercepting TLS"
+            }
+            Problem::Connection => "check network access to the endpoint, proxies and firewalls",
+            Problem::Authentication => {
+                "check the access key, the secret key and the session token, and whether they \
+                 expired"
+            }
+            Problem::Permission => {
+                "allow s3:ListBucket on the bucket for the credential, and check bucket policies"
+            }
+            Problem::ClockSkew => "synchronize the clock of this machine, e.g. with NTP",
+            Problem::NoSuchBucket => "check the bucket name, or create the bucket",
+            Problem::WrongRegion => "configure the region the bucket lives in",
+            Problem::Other => "see the detail reported by S3",
+        }
+    }
+
+    /// Classifies a response S3 rejected the probe with.
+    fn from_response(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Self {
+        let error = quick_xml::de::from_reader::<_, S3ResponseError>(body).unwrap_or_default();
+        let detail = match error.message.is_empty() {
+            true => format!("HTTP {status} {}", error.code),
+            false => format!("HTTP {status} {}: {}", error.code, error.message),
+        };
+        let problem = match error.code.as_str() {
+            "InvalidAccessKeyId"
+            | "SignatureDoesNotMatch"
+            | "ExpiredToken"
+            | "InvalidToken"
+            | "TokenRefreshRequired" => Problem::Authentication,
+            "AccessDenied" | "AllAccessDisabled" => Problem::Permission,
+            "RequestTimeTooSkewed" => Problem::ClockSkew,
+            "NoSuchBucket" => Problem::NoSuchBucket,
+            "PermanentRedirect"
+            | "AuthorizationHeaderMalformed"
+            | "IllegalLocationConstraintException" => Problem::WrongRegion,
+            _ => match status {
+                StatusCode::MOVED_PERMANENTLY => Problem::WrongRegion,
+                StatusCode::UNAUTHORIZED => Problem::Authentication,
+                StatusCode::FORBIDDEN => Problem::Permission,
+                StatusCode::NOT_FOUND => Problem::NoSuchBucket,
+                _ => Problem::Other,
+            },
+        };
+        // S3 names the region of the bucket when it is asked in the wrong one
+        let region = headers
+            .get("x-amz-bucket-region")
+            .and_then(|region| region.to_str().ok());
+        match (problem, region) {
+            (Problem::WrongRegion, Some(region)) => {
+                Self::new(problem, format!("{detail}, the bucket is in {region}"))
+            }
+            _ => Self::new(problem, detail),
+        }
+    }
+
+    /// Classifies an error raised before S3 answered.
+    fn from_error(error: S3Error) -> Self {
+        if let S3Error::AuthorizeError(e) = &error {
+            return Self::new(Problem::Authentication, e.to_string());
+        }
+        // transport errors only tell their cause apart in their messages
+        let mut messages = Vec::new();
+        let mut source = Some(&error as &dyn StdError);
+        while let Some(e) = source {
+            messages.push(e.to_string());
+            source = e.source();
+        }
+        let detail = messages.join(": ");
+        let lowercase = detail.to_lowercase();
+        let problem = if [
+            "dns error",
+            "failed to lookup address",
+            "name or service not known",
+        ]
+        .iter()
+        .any(|pattern| lowercase.contains(pattern))
+        {
+            Problem::Dns
+        } else if ["certificate", "tls", "ssl", "handshake"]
+            .iter()
+            .any(|pattern| lowercase.contains(pattern))
+        {
+            Problem::Tls
+        } else if matches!(error, S3Error::HttpError(_)) {
+            Problem::Connection
+        } else {
+            Problem::Other
+        };
+        Self::new(problem, detail)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}, {}",
+            self.problem.describe(),
+            self.detail,
+            self.hint()
+        )
+    }
+}
+
+impl StdError for Diagnostic {}
+
+impl AmazonS3 {
+    /// Probes the configuration with a single authenticated listing of at most one key and
+    /// classifies what went wrong, e.g. at startup or in a CLI command that validates settings.
+    ///
+    /// Unlike other requests, the probe is not retried with a corrected clock, so clock skew is
+    /// reported too.
+    pub async fn check(&self) -> Result<(), Diagnostic> {
+        let mut url = Url::from_str(&self.as_ref().options.endpoint)
+            .map_err(|e| Diagnostic::new(Problem::Other, e.to_string()))?;
+        url.set_query(Some(&encode_query(&[
+            ("list-type", "2"),
+            ("max-keys", "1"),
+        ])));
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(url.as_str())
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| Diagnostic::new(Problem::Other, e.to_string()))?;
+
+        let response = self
+            .as_ref()
+            .sign_and_send(request)
+            .await
+            .map_err(Diagnostic::from_error)?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let (parts, body) = response.into_parts();
+        let body = body
+            .collect()
+            .await
+            .map_err(|e| Diagnostic::from_error(e.into()))?
+            .to_bytes();
+        Err(Diagnostic::from_response(
+            parts.status,
+            &parts.headers,
+            &body,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{HeaderMap, StatusCode};
+
+    use super::{Diagnostic, Problem};
+    use crate::remotes::{aws::S3Error, http::HttpError};
+
+    fn rejected(status: StatusCode, code: &str) -> Diagnostic {
+        let body = format!("<Error><Code>{code}</Code><Message>message</Message></Error>");
+        Diagnostic::from_response(status, &HeaderMap::new(), body.as_bytes())
+    }
+
+    #[test]
+    fn classifies_rejections() {
+        for (status, code, problem) in [
+            (
+                StatusCode::FORBIDDEN,
+                "InvalidAccessKeyId",
+                Problem::Authentication,
+            ),
+            (
+                StatusCode::FORBIDDEN,
+                "SignatureDoesNotMatch",
+                Problem::Authentication,
+            ),
+            (StatusCode::FORBIDDEN, "AccessDenied", Problem::Permission),
+            (
+                StatusCode::FORBIDDEN,
+                "RequestTimeTooSkewed",
+                Problem::ClockSkew,
+            ),
+            (StatusCode::NOT_FOUND, "NoSuchBucket", Problem::NoSuchBucket),
+            (
+                StatusCode::BAD_REQUEST,
+                "AuthorizationHeaderMalformed",
+                Problem::WrongRegion,
+            ),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                Problem::Other,
+            ),
+        ] {
+            assert_eq!(rejected(status, code).problem, problem, "{code}");
+        }
+        assert_eq!(
+            rejected(StatusCode::FORBIDDEN, "RequestTimeTooSkewed").to_string(),
+            "clock skew: HTTP 403 Forbidden RequestTimeTooSkewed: message, synchronize the clock \
+             of this machine, e.g. with NTP"
+        );
+
+        // HEAD-like responses without a body are classified by their status
+        let mut headers = HeaderMap::new();
+        headers.insert("x-amz-bucket-region", "eu-west-1".parse().unwrap());
+        let diagnostic = Diagnostic::from_response(StatusCode::MOVED_PERMANENTLY, &headers, b"");
+        assert_eq!(diagnostic.problem, Problem::WrongRegion);
+        assert!(diagnostic.detail.ends_with("the bucket is in eu-west-1"));
+    }
+
+    #[test]
+    fn classifies_transport_errors() {
+        let transport = |message: &str| {
+            let error = std::io::Error::other(message.to_string());
+            Diagnostic::from_error(S3Error::from(HttpError::Other(Box::new(error)))).problem
+        };
+        assert_eq!(
+            transport("dns error: failed to lookup address information"),
+            Problem::Dns
+        );
+        assert_eq!(
+            transport("invalid peer certificate: UnknownIssuer"),
+            Problem::Tls
+        );
+        assert_eq!(transport("connection refused"), Problem::Connection);
+    }
+
+    #[tokio::test]
+    async fn checks_the_bucket() {
+        use std::sync::Arc;
+
+        use crate::remotes::aws::mock::MockS3;
+
+        let server = Arc::new(MockS3::default());
+        server.fs().check().await.unwrap();
+    }
+}
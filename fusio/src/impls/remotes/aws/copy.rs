@@ -0,0 +1,97 @@
+//! Copying objects inside S3 without downloading them, see [`Fs::copy`].
+//!
+//! [`Fs::copy`]: crate::fs::Fs::copy
+
+use bytes::Bytes;
+use http::{Method, Request};
+use http_body_util::{BodyExt, Empty};
+
+use super::{
+    arn::copy_source, fs::AmazonS3, multipart_upload::MultipartUpload, object_url, response_error,
+    S3Error, S3ResponseError,
+};
+use crate::{path::Path, remotes::http::HttpError, Error};
+
+/// The largest object CopyObject copies and the largest part UploadPartCopy copies.
+pub(crate) const MAX_COPY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+impl AmazonS3 {
+    /// Copies the object with one CopyObject request, which keeps the metadata of the source.
+    /// S3 refuses sources larger than [`MAX_COPY_SIZE`] with an `InvalidRequest` error.
+    pub(crate) async fn copy_object(&self, from: &Path, to: &Path) -> Result<(), S3Error> {
+        let options = &self.as_ref().options;
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(object_url(&options.endpoint, to))
+            .header("x-amz-copy-source", copy_source(&options.bucket, from))
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        let (parts, content) = response.into_parts();
+        let content = content.collect().await.map_err(S3Error::from)?.to_bytes();
+        // a copy can still fail after S3 answered 200, it reports the error in the body then
+        let failed = quick_xml::de::from_reader::<_, S3ResponseError>(content.as_ref())
+            .is_ok_and(|error| !error.code.is_empty());
+        if !parts.status.is_success() || failed {
+            return Err(response_error(parts.status, &parts.headers, &content));
+        }
+        Ok(())
+    }
+
+    /// Copies the object of `size` bytes in parts of `part_size` bytes with a multipart upload
+    /// of UploadPartCopy requests. The copy gets no metadata of the source.
+    pub(crate) async fn copy_parts(
+        &self,
+        from: &Path,
+        to: &Path,
+        size: u64,
+        part_size: u64,
+    ) -> Result<(), Error> {
+        let upload = MultipartUpload::new(self.clone(), to.clone());
+        let upload_id = upload.initiate().await?;
+        let source = copy_source(&self.as_ref().options.bucket, from);
+        let mut parts = Vec::new();
+        for (part_num, start) in (0..size).step_by(part_size as usize).enumerate() {
+            let end = (start + part_size).min(size) - 1;
+            let part = upload
+                .upload_part_copy(&upload_id, part_num, &source, start..=end)
+                .await?;
+            parts.push(part);
+        }
+        upload.complete_part(&upload_id, &parts).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    async fn copies_in_parts() {
+        use std::sync::Arc;
+
+        use http::Method;
+
+        use crate::{path::Path, remotes::aws::mock::MockS3};
+
+        let server = Arc::new(MockS3::default());
+        server.put("data/large", "0123456789");
+        let s3 = server.fs();
+
+        s3.copy_parts(&Path::from("data/large"), &Path::from("copy"), 10, 4)
+            .await
+            .unwrap();
+        assert_eq!(server.get("copy").unwrap(), "0123456789");
+        let copied = server
+            .requests()
+            .into_iter()
+            .filter(|(method, uri)| *method == Method::PUT && uri.contains("partNumber="))
+            .count();
+        assert_eq!(copied, 3);
+
+        assert!(s3
+            .copy_parts(&Path::from("missing"), &Path::from("gone"), 10, 4)
+            .await
+            .is_err());
+        assert!(server.get("gone").is_none());
+    }
+}
@@ -125,6 +125,12 @@ impl<'a> AwsAuthorizer<'a> {
         self
     }
 
+    /// Signs with the given time instead of reading the wall clock when authorizing
+    pub(crate) fn with_date(mut self, date: DateTime<Utc>) -> Self {
+        self.date = Some(date);
+        self
+    }
+
     // /// Overrides the header name for security tokens, defaults to `x-amz-security-token`
     // pub(crate) fn with_token_header(mut self, header: HeaderName) -> Self {
     //     self.token_header = Some(header);
@@ -227,7 +233,6 @@ impl<'a> AwsAuthorizer<'a> {
         Ok(())
     }
 
-    #[allow(unused)]
     pub(crate) fn sign(&self, method: Method, url: &mut Url, expires_in: Duration) {
         let date = self.date.unwrap_or_else(Utc::now);
         let scope = self.scope(date);
@@ -278,6 +283,22 @@ impl<'a> AwsAuthorizer<'a> {
             .append_pair("X-Amz-Signature", &signature);
     }
 
+    /// Signs the URI of `request` with query parameters valid for `expires_in`, see
+    /// [`Self::sign`], leaving its headers untouched.
+    pub(crate) fn authorize_query<B>(
+        &self,
+        request: &mut Request<B>,
+        expires_in: Duration,
+    ) -> Result<(), AuthorizeError> {
+        let mut url = Url::parse(&request.uri().to_string())?;
+        self.sign(request.method().clone(), &mut url, expires_in);
+        *request.uri_mut() = url
+            .as_str()
+            .parse()
+            .map_err(|e: http::uri::InvalidUri| AuthorizeError::SignHashFailed(Box::new(e)))?;
+        Ok(())
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn string_to_sign(
         &self,
@@ -425,6 +446,8 @@ pub enum AuthorizeError {
     SignHashFailed(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
     #[error("Body no frame")]
     BodyNoFrame,
+    #[error("Unsupported signature: {0}")]
+    Unsupported(&'static str),
 }
 
 /// <https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/iam-roles-for-amazon-ec2.html#instance-metadata-security-credentials>
@@ -0,0 +1,164 @@
+//! Removing many objects at once with the DeleteObjects API, see [`Fs::remove_batch`].
+//!
+//! [`Fs::remove_batch`]: crate::fs::Fs::remove_batch
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use bytes::{Buf, Bytes};
+use http::{Method, Request};
+use http_body_util::{BodyExt, Full};
+use serde::{Deserialize, Serialize};
+
+use super::{fs::AmazonS3, unexpected_response, S3Error};
+use crate::{path::Path, remotes::http::HttpError, util::crc32c, Error};
+
+/// The most keys S3 accepts in one DeleteObjects request.
+pub(crate) const MAX_KEYS: usize = 1000;
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Delete<'a> {
+    /// Only report the keys that could not be deleted.
+    quiet: bool,
+    object: Vec<ObjectIdentifier<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ObjectIdentifier<'a> {
+    key: &'a str,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+struct DeleteResult {
+    error: Vec<DeleteError>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+struct DeleteError {
+    key: String,
+    code: String,
+    message: String,
+}
+
+impl AmazonS3 {
+    /// Deletes up to [`MAX_KEYS`] objects in one request and returns the keys S3 refused to
+    /// delete. Missing keys count as deleted, like for a single delete.
+    pub(crate) async fn delete_objects(&self, paths: &[Path]) -> Result<Vec<(Path, Error)>, Error> {
+        let delete = Delete {
+            quiet: true,
+            object: paths
+                .iter()
+                .map(|path| ObjectIdentifier { key: path.as_ref() })
+                .collect(),
+        };
+        let body = quick_xml::se::to_string(&delete).map_err(S3Error::from)?;
+
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}/?delete", self.as_ref().options.endpoint));
+        // S3 requires an integrity checksum of the body, which the signer adds itself when
+        // checksums are enabled
+        if !self.as_ref().options.checksum {
+            let crc = crc32c(0, body.as_bytes()).to_be_bytes();
+            request = request.header("x-amz-checksum-crc32c", BASE64_STANDARD.encode(crc));
+        }
+        let request = request
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        if !response.status().is_success() {
+            return Err(unexpected_response(response).await);
+        }
+
+        let result: DeleteResult = quick_xml::de::from_reader(
+            response
+                .collect()
+                .await
+                .map_err(S3Error::from)?
+                .aggregate()
+                .reader(),
+        )
+        .map_err(S3Error::from)?;
+        result
+            .error
+            .into_iter()
+            .map(|error| {
+                let path = Path::parse(&error.key)?;
+                let message = format!("{}: {}", error.code, error.message);
+                Ok((path, Error::Other(message.into())))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    async fn removes_in_batches() {
+        use std::sync::Arc;
+
+        use http::Method;
+
+        use crate::{
+            fs::{Fs, OpenOptions},
+            path::Path,
+            remotes::aws::{mock::MockS3, ObjectLock},
+            Write,
+        };
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let paths = (0..2500)
+            .map(|i| Path::from(format!("data/{i:04}")))
+            .collect::<Vec<_>>();
+        for path in &paths[1..] {
+            server.put(path.as_ref(), "x");
+        }
+        let mut held = s3
+            .open_options(&paths[7], OpenOptions::default().create(true))
+            .await
+            .unwrap()
+            .object_lock(ObjectLock::default().legal_hold(true));
+        let (result, _) = held.write_all(&b"x"[..]).await;
+        result.unwrap();
+        held.close().await.unwrap();
+
+        let failed = s3.remove_batch(paths.clone()).await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, paths[7]);
+        assert!(failed[0].1.to_string().starts_with("AccessDenied"));
+        assert!(server.get(paths[7].as_ref()).is_some());
+        assert!(server.get(paths[8].as_ref()).is_none());
+
+        let deletes = server
+            .requests()
+            .into_iter()
+            .filter(|(method, uri)| *method == Method::POST && uri.ends_with("?delete"))
+            .count();
+        assert_eq!(deletes, 3);
+    }
+
+    #[test]
+    fn serializes_keys() {
+        use super::{Delete, ObjectIdentifier};
+
+        let delete = Delete {
+            quiet: true,
+            object: vec![
+                ObjectIdentifier { key: "a" },
+                ObjectIdentifier { key: "b&c" },
+            ],
+        };
+        assert_eq!(
+            quick_xml::se::to_string(&delete).unwrap(),
+            concat!(
+                "<Delete><Quiet>true</Quiet>",
+                "<Object><Key>a</Key></Object>",
+                "<Object><Key>b&amp;c</Key></Object>",
+                "</Delete>"
+            )
+        );
+    }
+}
@@ -1,3 +1,6 @@
+use std::io;
+
+use http::StatusCode;
 use thiserror::Error;
 
 use crate::remotes::{aws::credential::AuthorizeError, http::HttpError};
@@ -10,4 +13,42 @@ pub enum S3Error {
     AuthorizeError(#[from] AuthorizeError),
     #[error("xml parse error: {0}")]
     XmlParseError(#[from] quick_xml::DeError),
+    /// An error S3 answered a request with, parsed from the XML body of its response.
+    #[error("S3 error {code} (HTTP status: {status}, request id: {request_id}): {message}")]
+    Service {
+        status: StatusCode,
+        /// The S3 error code, e.g. `NoSuchKey`, `SlowDown` or `AccessDenied`.
+        code: String,
+        message: String,
+        /// The id S3 assigned to the request, to quote when asking AWS support about it.
+        request_id: String,
+    },
+}
+
+impl S3Error {
+    /// The S3 error code of a [`S3Error::Service`] error.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            S3Error::Service { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+impl From<S3Error> for crate::Error {
+    /// Reports objects or buckets S3 did not find as [`std::io::ErrorKind::NotFound`], like the
+    /// local backends do, keeping the [`S3Error`] as the source.
+    fn from(e: S3Error) -> Self {
+        let status = match &e {
+            S3Error::Service { status, .. } => Some(*status),
+            S3Error::HttpError(HttpError::HttpNotSuccess { status, .. }) => Some(*status),
+            _ => None,
+        };
+        match status {
+            Some(StatusCode::NOT_FOUND) => {
+                crate::Error::Io(io::Error::new(io::ErrorKind::NotFound, e))
+            }
+            _ => crate::Error::Other(Box::new(e)),
+        }
+    }
 }
@@ -1,23 +1,34 @@
-use std::{str::FromStr, sync::Arc};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use async_stream::stream;
+use base64::{prelude::BASE64_STANDARD, Engine};
 use bytes::{Buf, Bytes};
 use chrono::{DateTime, Utc};
 use futures_core::Stream;
-use http::{Method, Request};
-use http_body_util::{BodyExt, Empty};
-use serde::{Deserialize, Serialize};
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE, DATE, ETAG, LAST_MODIFIED},
+    Method, Request, Response, StatusCode,
+};
+use http_body::Body;
+use http_body_util::{BodyExt, Empty, Full};
+use serde::Deserialize;
 use url::Url;
 
-use super::{credential::AwsCredential, options::S3Options, S3Error, S3File};
+use super::{
+    arn::BucketAddress, copy::MAX_COPY_SIZE, credential::AwsCredential, delete::MAX_KEYS,
+    encode_query, object_url, options::S3Options, presign::presign, unexpected_response, S3Error,
+    S3File, S3ResponseError,
+};
 use crate::{
-    fs::{FileMeta, Fs, OpenOptions},
+    clock::{Clock, OffsetClock},
+    fs::{EntryKind, FileMeta, Fs, ListOptions, OpenOptions},
     path::Path,
-    remotes::{
-        aws::sign::Sign,
-        http::{DynHttpClient, HttpClient, HttpError},
-    },
-    Error,
+    remotes::http::{BoxBody, DynHttpClient, HttpClient, HttpError, RequestSigner},
+    Error, MaybeSync,
 };
 
 pub struct AmazonS3Builder {
@@ -26,10 +37,17 @@ pub struct AmazonS3Builder {
     credential: Option<AwsCredential>,
     sign_payload: bool,
     checksum: bool,
+    clock: Arc<dyn Clock>,
+    signing_endpoint: Option<String>,
     client: Box<dyn DynHttpClient>,
 }
 
 impl AmazonS3Builder {
+    /// Addresses `bucket`, which may also be an access point alias, an S3 Access Point ARN like
+    /// `arn:aws:s3:us-west-2:123456789012:accesspoint/my-ap`, whose region overrides
+    /// [`AmazonS3Builder::region`], or a Multi-Region Access Point ARN or alias ending in
+    /// `.mrap`. Multi-Region Access Points require SigV4A signatures, so they can only be reached
+    /// through a [`AmazonS3Builder::signing_endpoint`] or anonymously.
     #[allow(unused_variables)]
     pub fn new(bucket: String) -> Self {
         cfg_if::cfg_if! {
@@ -41,6 +59,8 @@ impl AmazonS3Builder {
                     credential: None,
                     sign_payload: false,
                     checksum: false,
+                    clock: Arc::new(crate::clock::SystemClock),
+                    signing_endpoint: None,
                     client,
                 }
             } else {
@@ -71,15 +91,45 @@ impl AmazonS3Builder {
         self
     }
 
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sends requests through `client` instead of the default client of the runtime, e.g. the
+    /// `SimClient` of the `sim` feature in simulations.
+    pub fn client<C: HttpClient + 'static>(mut self, client: C) -> Self {
+        self.client = Box::new(client);
+        self
+    }
+
+    /// Presigns every request through `endpoint` instead of signing it with a credential, so
+    /// browser apps can use S3 without embedding secrets.
+    ///
+    /// The request to sign is posted as JSON `{"method", "url", "headers"}`, and the endpoint
+    /// answers with `{"url", "headers"}`: the presigned URL, e.g. with SigV4 query parameters,
+    /// and the headers the signature covers. The request body is never sent to the endpoint, so
+    /// signatures must not cover the payload.
+    pub fn signing_endpoint(mut self, endpoint: String) -> Self {
+        self.signing_endpoint = Some(endpoint);
+        self
+    }
+
     pub fn build(self) -> AmazonS3 {
+        let address = BucketAddress::parse(&self.bucket);
+        let endpoint = address.endpoint(&self.region);
+        let region = address.signing_region(&self.region).to_string();
         AmazonS3 {
             inner: Arc::new(AmazonS3Inner {
                 options: S3Options {
-                    endpoint: format!("https://{}.s3.{}.amazonaws.com", self.bucket, self.region),
-                    region: self.region,
+                    endpoint,
+                    bucket: self.bucket,
+                    region,
                     credential: self.credential,
                     sign_payload: self.sign_payload,
                     checksum: self.checksum,
+                    clock: OffsetClock::new(self.clock),
+                    signing_endpoint: self.signing_endpoint,
                 },
                 client: self.client,
             }),
@@ -103,14 +153,110 @@ pub(super) struct AmazonS3Inner {
     pub(super) client: Box<dyn DynHttpClient>,
 }
 
+/// How far the signing time may be from the time of S3 before it rejects a request.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(15 * 60);
+
+impl AmazonS3Inner {
+    /// Signs and sends `request`.
+    ///
+    /// When S3 rejects the request with `RequestTimeTooSkewed`, or with an empty 403 whose `Date`
+    /// header is further than [`MAX_CLOCK_SKEW`] from the signing clock, the signing clock is
+    /// synchronized with the `Date` header of the response and the request is signed and sent once
+    /// more. The correction is kept for all subsequent requests.
+    pub(super) async fn send_request<B>(
+        &self,
+        request: Request<B>,
+    ) -> Result<Response<BoxBody>, S3Error>
+    where
+        B: Body<Data = Bytes> + Clone + Unpin + Send + MaybeSync + 'static,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let method = request.method().clone();
+        let uri = request.uri().clone();
+        let headers = request.headers().clone();
+        let body = request.body().clone();
+
+        let response = self.sign_and_send(request).await?;
+        if response.status() != StatusCode::FORBIDDEN {
+            return Ok(response);
+        }
+
+        let (parts, content) = response.into_parts();
+        let content = content.collect().await?.to_bytes();
+        let server_time = parts
+            .headers
+            .get(DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok());
+        let skewed = match quick_xml::de::from_reader::<_, S3ResponseError>(content.as_ref()) {
+            Ok(error) if !error.code.is_empty() => error.code == "RequestTimeTooSkewed",
+            // responses to HEAD requests have no body to tell the reason, only the time
+            _ => server_time.is_some_and(|server_time| {
+                let now = self.options.clock.now();
+                let skew = match now.duration_since(SystemTime::from(server_time)) {
+                    Ok(ahead) => ahead,
+                    Err(behind) => behind.duration(),
+                };
+                skew > MAX_CLOCK_SKEW
+            }),
+        };
+
+        match server_time {
+            Some(server_time) if skewed => {
+                self.options
+                    .clock
+                    .synchronize(SystemTime::from(server_time));
+
+                let mut request = Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .body(body)
+                    .map_err(HttpError::from)?;
+                *request.headers_mut() = headers;
+                self.sign_and_send(request).await
+            }
+            _ => Ok(Response::from_parts(
+                parts,
+                BoxBody::new(Full::new(content).map_err(|e| -> HttpError { match e {} })),
+            )),
+        }
+    }
+
+    pub(super) async fn sign_and_send<B>(
+        &self,
+        mut request: Request<B>,
+    ) -> Result<Response<BoxBody>, S3Error>
+    where
+        B: Body<Data = Bytes> + Clone + Unpin + Send + MaybeSync + 'static,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        match &self.options.signing_endpoint {
+            Some(endpoint) => presign(&self.client, endpoint, &mut request).await?,
+            None => self.options.sign(&mut request).await?,
+        }
+        Ok(self.client.send_request(request).await?)
+    }
+}
+
 impl Fs for AmazonS3 {
     type File = S3File;
 
-    async fn open_options(&self, path: &Path, _: OpenOptions) -> Result<Self::File, crate::Error> {
-        Ok(S3File::new(self.clone(), path.clone()))
+    async fn open_options(
+        &self,
+        path: &Path,
+        options: OpenOptions,
+    ) -> Result<Self::File, crate::Error> {
+        let file = S3File::new(self.clone(), path.clone())
+            .create(options.create)
+            .truncate(options.truncate)
+            .create_new(options.create_new);
+        Ok(match options.if_match {
+            Some(etag) => file.if_match(etag),
+            None => file,
+        })
     }
 
-    async fn create_dir_all(_path: &Path) -> Result<(), Error> {
+    async fn create_dir_all(&self, _path: &Path) -> Result<(), Error> {
         Ok(())
     }
 
@@ -118,38 +264,53 @@ impl Fs for AmazonS3 {
         &self,
         path: &Path,
     ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
-        Ok(stream! {
+        self.list_options(path, ListOptions::default()).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let start_after = options.page_token.clone();
+        let max_keys = options.limit.map(|limit| {
+            options
+                .offset
+                .saturating_add(limit)
+                .clamp(1, 1000)
+                .to_string()
+        });
+
+        // keys below `path` only, not the ones of siblings sharing its name as a prefix
+        let prefix = match path.as_ref() {
+            "" => String::new(),
+            path => format!("{path}/"),
+        };
+
+        Ok(options.apply(stream! {
             let mut next_token = None::<String>;
             loop {
-                let path = path.to_string();
-                let mut query = vec![("list-type", "2"), ("prefix", path.as_str())];
+                let mut query = vec![("list-type", "2"), ("prefix", prefix.as_str())];
                 if let Some(token) = next_token.as_ref() {
                     query.push(("continuation-token", token.as_str()));
+                } else if let Some(start_after) = start_after.as_ref() {
+                    query.push(("start-after", start_after.as_str()));
+                }
+                if let Some(max_keys) = max_keys.as_ref() {
+                    query.push(("max-keys", max_keys.as_str()));
                 }
 
                 let mut url = Url::from_str(self.as_ref().options.endpoint.as_str()).map_err(|e| S3Error::from(HttpError::from(e)))?;
-                {
-                    let mut pairs = url.query_pairs_mut();
-                    let serializer = serde_urlencoded::Serializer::new(&mut pairs);
-                    query
-                        .serialize(serializer)
-                        .map_err(|e| S3Error::from(HttpError::from(e)))?;
-                }
+                url.set_query(Some(&encode_query(&query)));
 
-                let mut request = Request::builder()
+                let request = Request::builder()
                     .method(Method::GET)
                     .uri(url.as_str())
                     .body(Empty::<Bytes>::new()).map_err(|e| S3Error::from(HttpError::from(e)))?;
-                request.sign(&self.as_ref().options).await.map_err(S3Error::from)?;
-                let response = self.as_ref().client.send_request(request).await.map_err(S3Error::from)?;
+                let response = self.as_ref().send_request(request).await?;
 
                 if !response.status().is_success() {
-                    yield Err(S3Error::from(HttpError::HttpNotSuccess { status: response.status(), body: String::from_utf8_lossy(
-                        &response
-                        .collect()
-                        .await
-                        .map_err(|e| Error::Other(e.into()))?.to_bytes()).to_string()
-                    }).into());
+                    yield Err(unexpected_response(response).await);
                     return;
                 }
 
@@ -164,70 +325,111 @@ impl Fs for AmazonS3 {
                 next_token = response.next_continuation_token.take();
 
                 for content in &response.contents {
-                    yield Ok(FileMeta {
-                        path: Path::parse(&content.key)?,
-                        size: content.size as u64
-                    });
+                    let kind = if content.key.ends_with('/') {
+                        EntryKind::Directory
+                    } else {
+                        EntryKind::File
+                    };
+                    yield Ok(FileMeta::new(Path::parse(&content.key)?, content.size)
+                        .kind(kind)
+                        .last_modified(Some(content.last_modified.into()))
+                        .etag(content.e_tag.clone()));
                 }
-
                 if next_token.is_none() {
                     break;
                 }
             }
-        })
+        }))
     }
 
-    async fn remove(&self, path: &Path) -> Result<(), Error> {
-        let mut url = Url::from_str(self.as_ref().options.endpoint.as_str())
+    /// Removes the paths with DeleteObjects requests of up to 1000 keys each. Keys that do not
+    /// exist count as removed. A request that fails as a whole fails the batch, the keys of the
+    /// earlier requests stay removed.
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let mut failed = Vec::new();
+        for chunk in paths.chunks(MAX_KEYS) {
+            failed.extend(self.delete_objects(chunk).await?);
+        }
+        Ok(failed)
+    }
+
+    /// Copies the object inside S3 without downloading it. CopyObject copies objects of up to
+    /// 5 GiB and keeps the metadata of the source, larger objects are copied in parts with
+    /// UploadPartCopy, without the metadata.
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        match self.copy_object(from, to).await {
+            Err(error) if error.code() == Some("InvalidRequest") => {
+                let size = Fs::stat(self, from).await?.size;
+                if size <= MAX_COPY_SIZE {
+                    return Err(error.into());
+                }
+                self.copy_parts(from, to, size, MAX_COPY_SIZE).await
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Reads the metadata from the headers of a HeadObject request. The CRC-32C checksum is
+    /// reported for objects stored with a full object checksum of that algorithm, not for the
+    /// composite checksums of multipart uploads.
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        let request = Request::builder()
+            .method(Method::HEAD)
+            .uri(object_url(&self.as_ref().options.endpoint, path))
+            .header("x-amz-checksum-mode", "ENABLED")
+            .body(Empty::<Bytes>::new())
             .map_err(|e| S3Error::from(HttpError::from(e)))?;
-        url.set_path(path.as_ref());
+        let response = self.as_ref().send_request(request).await?;
+        if !response.status().is_success() {
+            return Err(unexpected_response(response).await);
+        }
 
-        let mut request = Request::builder()
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let size = header(CONTENT_LENGTH.as_str())
+            .ok_or_else(|| Error::Other("missing content-length header".into()))?
+            .parse::<u64>()
+            .map_err(|e| Error::Other(e.into()))?;
+        let last_modified = header(LAST_MODIFIED.as_str())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+            .map(SystemTime::from);
+        let crc32c = header("x-amz-checksum-crc32c")
+            .and_then(|checksum| BASE64_STANDARD.decode(checksum).ok())
+            .and_then(|checksum| Some(u32::from_be_bytes(checksum.try_into().ok()?)));
+        Ok(FileMeta::new(path.clone(), size)
+            .last_modified(last_modified)
+            .etag(header(ETAG.as_str()).map(str::to_owned))
+            .content_type(header(CONTENT_TYPE.as_str()).map(str::to_owned))
+            .crc32c(crc32c))
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        let url = object_url(&self.as_ref().options.endpoint, path);
+
+        let request = Request::builder()
             .method(Method::DELETE)
-            .uri(url.as_str())
+            .uri(url)
             .body(Empty::<Bytes>::new())
             .map_err(|e| S3Error::from(HttpError::from(e)))?;
-        request
-            .sign(&self.as_ref().options)
-            .await
-            .map_err(S3Error::from)?;
-        let response = self
-            .as_ref()
-            .client
-            .send_request(request)
-            .await
-            .map_err(S3Error::from)?;
+        let response = self.as_ref().send_request(request).await?;
 
         if !response.status().is_success() {
-            return Err(S3Error::from(HttpError::HttpNotSuccess {
-                status: response.status(),
-                body: String::from_utf8_lossy(
-                    &response
-                        .collect()
-                        .await
-                        .map_err(|e| Error::Other(e.into()))?
-                        .to_bytes(),
-                )
-                .to_string(),
-            })
-            .into());
+            return Err(unexpected_response(response).await);
         }
 
         Ok(())
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-pub struct ListPrefix {
-    pub prefix: String,
-}
-
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ListContents {
     pub key: String,
-    pub size: usize,
+    pub size: u64,
     pub last_modified: DateTime<Utc>,
     #[serde(rename = "ETag")]
     pub e_tag: Option<String>,
@@ -239,13 +441,29 @@ pub struct ListResponse {
     #[serde(default)]
     pub contents: Vec<ListContents>,
     #[serde(default)]
-    pub common_prefixes: Vec<ListPrefix>,
-    #[serde(default)]
     pub next_continuation_token: Option<String>,
 }
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn listed_sizes_beyond_4_gib() {
+        use super::ListResponse;
+
+        let page: ListResponse = quick_xml::de::from_reader(
+            br#"<ListBucketResult>
+                <Contents>
+                    <Key>large</Key>
+                    <Size>6442450944</Size>
+                    <LastModified>2024-01-01T00:00:00.000Z</LastModified>
+                </Contents>
+            </ListBucketResult>"#
+                .as_ref(),
+        )
+        .unwrap();
+        assert_eq!(page.contents[0].size, 6 << 30);
+    }
+
     #[cfg(feature = "tokio-http")]
     #[tokio::test]
     async fn list_and_remove() {
@@ -279,4 +497,283 @@ mod tests {
             s3.remove(&meta.path).await.unwrap();
         }
     }
+
+    #[tokio::test]
+    async fn retry_on_request_time_too_skewed() {
+        use std::{
+            sync::{Arc, Mutex},
+            time::{Duration, SystemTime},
+        };
+
+        use bytes::Bytes;
+        use http::{header::DATE, Request, Response, StatusCode};
+        use http_body::Body;
+        use http_body_util::{Empty, Full};
+
+        use super::{AmazonS3Inner, S3Options};
+        use crate::{
+            clock::{FixedClock, OffsetClock},
+            error::BoxedError,
+            remotes::{
+                aws::AwsCredential,
+                http::{HttpClient, HttpError},
+            },
+            MaybeSync,
+        };
+
+        // Rejects the first request with `body` and `date`, then records the signing time of the
+        // retry.
+        struct SkewedServer {
+            body: &'static [u8],
+            date: &'static str,
+            signed_at: Mutex<Vec<String>>,
+        }
+
+        impl HttpClient for SkewedServer {
+            type RespBody = Full<Bytes>;
+
+            async fn send_request<B>(
+                &self,
+                request: Request<B>,
+            ) -> Result<Response<Self::RespBody>, HttpError>
+            where
+                B: Body + Send + MaybeSync + 'static,
+                B::Data: Into<Bytes>,
+                B::Error: Into<BoxedError>,
+            {
+                let mut signed_at = self.signed_at.lock().unwrap();
+                signed_at.push(
+                    request.headers()["x-amz-date"]
+                        .to_str()
+                        .unwrap()
+                        .to_string(),
+                );
+                if signed_at.len() == 1 {
+                    return Ok(Response::builder()
+                        .status(StatusCode::FORBIDDEN)
+                        .header(DATE, self.date)
+                        .body(Full::new(Bytes::from_static(self.body)))
+                        .unwrap());
+                }
+                Ok(Response::new(Full::new(Bytes::new())))
+            }
+        }
+
+        // the server is one hour ahead, except for the last case where it is one minute ahead
+        let cases: [(&[u8], _, _); 3] = [
+            (
+                b"<Error><Code>RequestTimeTooSkewed</Code></Error>",
+                "Sat, 06 Aug 2022 19:01:34 GMT",
+                Some("20220806T190134Z"),
+            ),
+            // a HEAD request, whose response has no body
+            (
+                b"",
+                "Sat, 06 Aug 2022 19:01:34 GMT",
+                Some("20220806T190134Z"),
+            ),
+            (b"", "Sat, 06 Aug 2022 18:02:34 GMT", None),
+        ];
+        for (body, date, retry) in cases {
+            let server = Arc::new(SkewedServer {
+                body,
+                date,
+                signed_at: Mutex::new(Vec::new()),
+            });
+            // 2022-08-06T18:01:34Z
+            let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_659_808_894);
+            let s3 = AmazonS3Inner {
+                options: S3Options {
+                    endpoint: "https://fusio-test.s3.us-east-1.amazonaws.com".into(),
+                    bucket: "fusio-test".into(),
+                    region: "us-east-1".into(),
+                    credential: Some(AwsCredential {
+                        key_id: "key".into(),
+                        secret_key: "secret".into(),
+                        token: None,
+                    }),
+                    sign_payload: false,
+                    checksum: false,
+                    clock: OffsetClock::new(Arc::new(FixedClock::new(now))),
+                    signing_endpoint: None,
+                },
+                client: Box::new(SharedClient(server.clone())),
+            };
+
+            let request = Request::get("https://fusio-test.s3.us-east-1.amazonaws.com/key")
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            let response = s3.send_request(request).await.unwrap();
+            let mut signed_at = vec!["20220806T180134Z"];
+            match retry {
+                Some(retried_at) => {
+                    assert_eq!(response.status(), StatusCode::OK);
+                    assert_eq!(s3.options.clock.offset_millis(), 3_600_000);
+                    signed_at.push(retried_at);
+                }
+                None => {
+                    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+                    assert_eq!(s3.options.clock.offset_millis(), 0);
+                }
+            }
+            assert_eq!(*server.signed_at.lock().unwrap(), signed_at);
+        }
+
+        struct SharedClient(Arc<SkewedServer>);
+
+        impl HttpClient for SharedClient {
+            type RespBody = Full<Bytes>;
+
+            async fn send_request<B>(
+                &self,
+                request: Request<B>,
+            ) -> Result<Response<Self::RespBody>, HttpError>
+            where
+                B: Body + Send + MaybeSync + 'static,
+                B::Data: Into<Bytes>,
+                B::Error: Into<BoxedError>,
+            {
+                self.0.send_request(request).await
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn list_follows_continuation_tokens_lazily() {
+        use std::{pin::pin, sync::Arc};
+
+        use futures_util::{StreamExt, TryStreamExt};
+
+        use crate::{fs::Fs, path::Path, remotes::aws::mock::MockS3};
+
+        let server = Arc::new(MockS3::default());
+        for i in 0..2500 {
+            server.put(&format!("data/{i:04}"), "x");
+        }
+        server.put("other/0000", "x");
+        let s3 = server.fs();
+        let listed = |server: &MockS3| {
+            server
+                .requests()
+                .iter()
+                .filter(|(_, uri)| uri.contains("list-type=2"))
+                .count()
+        };
+
+        let path = Path::from("data");
+        let mut stream = pin!(s3.list(&path).await.unwrap());
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first.path, Path::from("data/0000"));
+        assert_eq!(listed(&server), 1);
+
+        let rest = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(rest.len(), 2499);
+        assert_eq!(rest.last().unwrap().path, Path::from("data/2499"));
+        assert_eq!(listed(&server), 3);
+    }
+
+    #[tokio::test]
+    async fn list_skips_sibling_prefixes() {
+        use std::sync::Arc;
+
+        use futures_util::TryStreamExt;
+
+        use crate::{
+            fs::{Fs, ListOptions},
+            path::Path,
+            remotes::aws::mock::MockS3,
+        };
+
+        let server = Arc::new(MockS3::default());
+        for key in ["data/a", "data/b", "data2/c", "database"] {
+            server.put(key, "x");
+        }
+        let s3 = server.fs();
+        let keys = |listing: Vec<crate::fs::FileMeta>| {
+            listing
+                .into_iter()
+                .map(|meta| meta.path.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let (data, root) = (Path::from("data"), Path::default());
+        let listing = s3.list(&data).await.unwrap();
+        assert_eq!(
+            keys(listing.try_collect().await.unwrap()),
+            ["data/a", "data/b"]
+        );
+        let page = s3
+            .list_options(&data, ListOptions::default().page_token("data/a"))
+            .await
+            .unwrap();
+        assert_eq!(keys(page.try_collect().await.unwrap()), ["data/b"]);
+        let everything = s3.list(&root).await.unwrap();
+        assert_eq!(everything.try_collect::<Vec<_>>().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn copies_server_side() {
+        use std::sync::Arc;
+
+        use http::Method;
+
+        use crate::{fs::Fs, path::Path, remotes::aws::mock::MockS3};
+
+        let server = Arc::new(MockS3::default());
+        server.put("data/a b", "content");
+        let s3 = server.fs();
+
+        Fs::copy(&s3, &Path::from("data/a b"), &Path::from("backup/a b"))
+            .await
+            .unwrap();
+        assert_eq!(server.get("backup/a b").unwrap(), "content");
+        // nothing was downloaded
+        assert!(server
+            .requests()
+            .iter()
+            .all(|(method, _)| *method == Method::PUT));
+
+        assert!(Fs::copy(&s3, &Path::from("missing"), &Path::from("copy"))
+            .await
+            .is_err());
+        assert!(server.get("copy").is_none());
+    }
+
+    #[tokio::test]
+    async fn stats_from_head_object() {
+        use std::{
+            pin::pin,
+            sync::Arc,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        use futures_util::StreamExt;
+
+        use crate::{fs::Fs, path::Path, remotes::aws::mock::MockS3};
+
+        let server = Arc::new(MockS3::default());
+        server.put("data/a", "fusio");
+        let s3 = server.fs();
+
+        let meta = s3.stat(&Path::from("data/a")).await.unwrap();
+        assert_eq!(meta.path, Path::from("data/a"));
+        assert_eq!(meta.size, 5);
+        assert_eq!(
+            meta.last_modified,
+            Some(UNIX_EPOCH + Duration::from_secs(1_704_067_200))
+        );
+        assert!(meta.etag.as_ref().is_some_and(|etag| etag.starts_with('"')));
+        assert_eq!(meta.content_type.as_deref(), Some("binary/octet-stream"));
+
+        // listings report the same time and entity tag
+        let prefix = Path::from("data");
+        let mut listing = pin!(s3.list(&prefix).await.unwrap());
+        let listed = listing.next().await.unwrap().unwrap();
+        assert_eq!(
+            (listed.last_modified, listed.etag),
+            (meta.last_modified, meta.etag)
+        );
+
+        assert!(s3.stat(&Path::from("missing")).await.is_err());
+    }
 }
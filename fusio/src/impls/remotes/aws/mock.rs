@@ -0,0 +1,600 @@
+//! An in-memory S3 server for unit tests, speaking just enough of the REST API for the backend.
+
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    sync::{Arc, Mutex},
+};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use bytes::Bytes;
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED, RANGE},
+    HeaderMap, Method, Request, Response, StatusCode,
+};
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+
+use super::{
+    fs::{AmazonS3, AmazonS3Inner},
+    options::S3Options,
+};
+use crate::{
+    clock::{OffsetClock, SystemClock},
+    error::BoxedError,
+    remotes::http::{HttpClient, HttpError},
+    util::crc32c,
+    MaybeSync,
+};
+
+pub(crate) const ENDPOINT: &str = "http://mock.s3.local";
+pub(crate) const BUCKET: &str = "mock";
+const LAST_MODIFIED_DATE: &str = "Mon, 01 Jan 2024 00:00:00 GMT";
+const RESTORED: &str = "ongoing-request=\"false\", expiry-date=\"Fri, 21 Dec 2012 00:00:00 GMT\"";
+
+#[derive(Default)]
+pub(crate) struct MockS3 {
+    objects: Mutex<BTreeMap<String, Bytes>>,
+    /// Storage classes other than `STANDARD`.
+    classes: Mutex<BTreeMap<String, String>>,
+    /// Requested restores of archived objects, `true` once completed.
+    restores: Mutex<BTreeMap<String, bool>>,
+    /// The `x-amz-object-lock-*` headers objects were written with.
+    locks: Mutex<BTreeMap<String, HeaderMap>>,
+    /// The `x-amz-checksum-crc32c` objects were uploaded with.
+    checksums: Mutex<BTreeMap<String, String>>,
+    /// The parts of the ongoing multipart uploads by upload id.
+    uploads: Mutex<BTreeMap<String, BTreeMap<usize, Bytes>>>,
+    /// Whether the bucket was deleted, it exists from the start.
+    deleted: Mutex<bool>,
+    requests: Mutex<Vec<(Method, String)>>,
+}
+
+impl MockS3 {
+    pub(crate) fn fs(self: &Arc<Self>) -> AmazonS3 {
+        AmazonS3 {
+            inner: Arc::new(AmazonS3Inner {
+                options: S3Options {
+                    endpoint: ENDPOINT.into(),
+                    bucket: BUCKET.into(),
+                    region: "us-east-1".into(),
+                    credential: None,
+                    sign_payload: false,
+                    checksum: false,
+                    clock: OffsetClock::new(Arc::new(SystemClock)),
+                    signing_endpoint: None,
+                },
+                client: Box::new(MockClient(self.clone())),
+            }),
+        }
+    }
+
+    pub(crate) fn put(&self, key: &str, content: impl Into<Bytes>) {
+        self.objects
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), content.into());
+    }
+
+    /// Stores `content` like an upload with the `CRC32C` checksum algorithm. Replacing the object
+    /// with [`MockS3::put`] afterwards keeps the checksum, like bit rot would.
+    pub(crate) fn put_with_crc32c(&self, key: &str, content: impl Into<Bytes>) {
+        let content = content.into();
+        let checksum = BASE64_STANDARD.encode(crc32c(0, &content).to_be_bytes());
+        self.checksums
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), checksum);
+        self.put(key, content);
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<Bytes> {
+        self.objects.lock().unwrap().get(key).cloned()
+    }
+
+    /// The Object Lock headers `key` was written with.
+    pub(crate) fn lock(&self, key: &str) -> HeaderMap {
+        self.locks
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Completes the requested restore of `key`.
+    pub(crate) fn complete_restore(&self, key: &str) {
+        self.restores.lock().unwrap().insert(key.to_string(), true);
+    }
+
+    /// Method and path-and-query of every request received so far.
+    pub(crate) fn requests(&self) -> Vec<(Method, String)> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn handle_bucket(&self, method: &Method, query: &str) -> Response<Full<Bytes>> {
+        let mut deleted = self.deleted.lock().unwrap();
+        match (method, *deleted) {
+            (&Method::PUT, false) => error(StatusCode::CONFLICT, "BucketAlreadyOwnedByYou"),
+            (&Method::PUT, true) => {
+                *deleted = false;
+                response(StatusCode::OK, Bytes::new())
+            }
+            (_, true) => error(StatusCode::NOT_FOUND, "NoSuchBucket"),
+            (&Method::DELETE, false) => {
+                if !self.objects.lock().unwrap().is_empty() {
+                    return error(StatusCode::CONFLICT, "BucketNotEmpty");
+                }
+                *deleted = true;
+                response(StatusCode::NO_CONTENT, Bytes::new())
+            }
+            (&Method::HEAD, false) => response(StatusCode::OK, Bytes::new()),
+            (&Method::GET, false) if query == "location" => {
+                response(StatusCode::OK, "<LocationConstraint/>")
+            }
+            _ => error(StatusCode::NOT_IMPLEMENTED, "NotImplemented"),
+        }
+    }
+
+    /// DeleteObjects in quiet mode, refusing to delete objects under a legal hold.
+    fn delete_objects(&self, headers: &HeaderMap, body: &Bytes) -> Response<Full<Bytes>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Delete {
+            object: Vec<Object>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Object {
+            key: String,
+        }
+
+        let checksummed = headers
+            .keys()
+            .any(|name| name.as_str().starts_with("x-amz-checksum-"));
+        if !checksummed {
+            return error(StatusCode::BAD_REQUEST, "InvalidRequest");
+        }
+        let delete: Delete = quick_xml::de::from_reader(body.as_ref()).unwrap();
+        if delete.object.len() > 1000 {
+            return error(StatusCode::BAD_REQUEST, "MalformedXML");
+        }
+
+        let mut objects = self.objects.lock().unwrap();
+        let locks = self.locks.lock().unwrap();
+        let mut errors = String::new();
+        for Object { key } in delete.object {
+            let held = locks
+                .get(&key)
+                .and_then(|lock| lock.get("x-amz-object-lock-legal-hold"))
+                .is_some_and(|hold| hold == "ON");
+            if held {
+                errors.push_str(&format!(
+                    concat!(
+                        "<Error><Key>{}</Key><Code>AccessDenied</Code>",
+                        "<Message>Access Denied</Message></Error>"
+                    ),
+                    key
+                ));
+                continue;
+            }
+            objects.remove(&key);
+            self.checksums.lock().unwrap().remove(&key);
+        }
+        response(
+            StatusCode::OK,
+            format!("<DeleteResult>{errors}</DeleteResult>"),
+        )
+    }
+
+    /// Answers the requests of multipart uploads. Completing an upload stores the parts in the
+    /// order of their numbers, ignoring the part list of the request.
+    fn handle_upload(
+        &self,
+        method: &Method,
+        key: &str,
+        query: &str,
+        headers: &HeaderMap,
+        body: Bytes,
+    ) -> Response<Full<Bytes>> {
+        let mut uploads = self.uploads.lock().unwrap();
+        let param = |name: &str| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+                .map(|value| percent_decode_str(value).decode_utf8_lossy().to_string())
+        };
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+        match (method, param("uploadId")) {
+            (&Method::POST, None) => {
+                let upload_id = format!("upload-{}", uploads.len());
+                uploads.insert(upload_id.clone(), BTreeMap::new());
+                response(
+                    StatusCode::OK,
+                    format!(
+                        "<InitiateMultipartUploadResult><UploadId>{upload_id}</UploadId></\
+                         InitiateMultipartUploadResult>"
+                    ),
+                )
+            }
+            (&Method::PUT, Some(upload_id)) => {
+                let Some(parts) = uploads.get_mut(&upload_id) else {
+                    return error(StatusCode::NOT_FOUND, "NoSuchUpload");
+                };
+                let part_num = param("partNumber").and_then(|num| num.parse().ok());
+                let Some(part_num) = part_num else {
+                    return error(StatusCode::BAD_REQUEST, "InvalidArgument");
+                };
+                let Some(source) = header("x-amz-copy-source") else {
+                    let etag = format!("\"{}\"", etag(&body));
+                    parts.insert(part_num, body);
+                    let mut response = response(StatusCode::OK, Bytes::new());
+                    response.headers_mut().insert(ETAG, etag.parse().unwrap());
+                    return response;
+                };
+                let objects = self.objects.lock().unwrap();
+                let Some(content) = objects.get(&source_key(source)) else {
+                    return error(StatusCode::NOT_FOUND, "NoSuchKey");
+                };
+                let range = header("x-amz-copy-source-range")
+                    .and_then(|range| range.strip_prefix("bytes="))
+                    .and_then(|range| range.split_once('-'))
+                    .and_then(|(start, end)| {
+                        Some((start.parse::<usize>().ok()?, end.parse().ok()?))
+                    });
+                let part = match range {
+                    Some((start, end)) if start <= end && end < content.len() => {
+                        content.slice(start..=end)
+                    }
+                    Some(_) => return error(StatusCode::BAD_REQUEST, "InvalidRange"),
+                    None => content.clone(),
+                };
+                let etag = etag(&part);
+                parts.insert(part_num, part);
+                response(
+                    StatusCode::OK,
+                    format!("<CopyPartResult><ETag>\"{etag}\"</ETag></CopyPartResult>"),
+                )
+            }
+            (&Method::POST, Some(upload_id)) => {
+                let Some(parts) = uploads.remove(&upload_id) else {
+                    return error(StatusCode::NOT_FOUND, "NoSuchUpload");
+                };
+                let content = Bytes::from(parts.into_values().flatten().collect::<Vec<_>>());
+                let etag = etag(&content);
+                self.objects
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_string(), content);
+                self.checksums.lock().unwrap().remove(key);
+                self.classes.lock().unwrap().remove(key);
+                self.restores.lock().unwrap().remove(key);
+                response(
+                    StatusCode::OK,
+                    format!(
+                        "<CompleteMultipartUploadResult><ETag>\"{etag}\"</ETag></\
+                         CompleteMultipartUploadResult>"
+                    ),
+                )
+            }
+            (&Method::DELETE, Some(upload_id)) => {
+                uploads.remove(&upload_id);
+                response(StatusCode::NO_CONTENT, Bytes::new())
+            }
+            _ => error(StatusCode::NOT_IMPLEMENTED, "NotImplemented"),
+        }
+    }
+
+    fn handle(
+        &self,
+        method: &Method,
+        key: &str,
+        query: &str,
+        headers: &HeaderMap,
+        body: Bytes,
+    ) -> Response<Full<Bytes>> {
+        if key.is_empty() && *method == Method::POST && query == "delete" {
+            return self.delete_objects(headers, &body);
+        }
+        if key.is_empty() && !query.contains("list-type=2") {
+            return self.handle_bucket(method, query);
+        }
+        if query == "uploads" || query.contains("uploadId=") {
+            return self.handle_upload(method, key, query, headers, body);
+        }
+        let mut objects = self.objects.lock().unwrap();
+        let mut classes = self.classes.lock().unwrap();
+        let mut restores = self.restores.lock().unwrap();
+        let mut locks = self.locks.lock().unwrap();
+        let mut checksums = self.checksums.lock().unwrap();
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let range = header(RANGE.as_str());
+        let archived = classes
+            .get(key)
+            .is_some_and(|class| class == "GLACIER" || class == "DEEP_ARCHIVE");
+
+        match *method {
+            Method::GET if query.contains("list-type=2") => {
+                let param = |name: &str| {
+                    query
+                        .split('&')
+                        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+                        .map(|value| percent_decode_str(value).decode_utf8_lossy().into_owned())
+                };
+                let prefix = param("prefix").unwrap_or_default();
+                // continuation tokens are the last key of the previous page
+                let after = param("continuation-token")
+                    .or_else(|| param("start-after"))
+                    .unwrap_or_default();
+                let max_keys = param("max-keys").map_or(1000, |keys| keys.parse().unwrap());
+                let mut listed = objects
+                    .range::<str, _>((Bound::Excluded(after.as_str()), Bound::Unbounded))
+                    .filter(|(key, _)| key.starts_with(&prefix))
+                    .peekable();
+                let page = listed.by_ref().take(max_keys).collect::<Vec<_>>();
+                let next = match (listed.peek(), page.last()) {
+                    (Some(_), Some((last, _))) => format!(
+                        concat!(
+                            "<IsTruncated>true</IsTruncated>",
+                            "<NextContinuationToken>{}</NextContinuationToken>"
+                        ),
+                        last
+                    ),
+                    _ => String::new(),
+                };
+                let contents = page
+                    .into_iter()
+                    .map(|(key, content)| {
+                        format!(
+                            concat!(
+                                "<Contents><Key>{}</Key><Size>{}</Size>",
+                                "<LastModified>2024-01-01T00:00:00.000Z</LastModified>",
+                                "<ETag>\"{}\"</ETag></Contents>"
+                            ),
+                            key,
+                            content.len(),
+                            etag(content)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .concat();
+                response(
+                    StatusCode::OK,
+                    format!("<ListBucketResult>{contents}{next}</ListBucketResult>"),
+                )
+            }
+            Method::POST if query == "restore" => {
+                if !objects.contains_key(key) {
+                    return error(StatusCode::NOT_FOUND, "NoSuchKey");
+                }
+                match restores.get(key) {
+                    _ if !archived => error(StatusCode::FORBIDDEN, "InvalidObjectState"),
+                    Some(false) => error(StatusCode::CONFLICT, "RestoreAlreadyInProgress"),
+                    Some(true) => response(StatusCode::OK, Bytes::new()),
+                    None => {
+                        restores.insert(key.to_string(), false);
+                        response(StatusCode::ACCEPTED, Bytes::new())
+                    }
+                }
+            }
+            Method::GET | Method::HEAD => {
+                let Some(content) = objects.get(key) else {
+                    return error(StatusCode::NOT_FOUND, "NoSuchKey");
+                };
+                let current = format!("\"{}\"", etag(content));
+                if header("if-match").is_some_and(|etag| etag != current) {
+                    return error(StatusCode::PRECONDITION_FAILED, "PreconditionFailed");
+                }
+                if header("if-none-match").is_some_and(|etag| etag == current || etag == "*") {
+                    return response(StatusCode::NOT_MODIFIED, Bytes::new());
+                }
+                let restored = restores.get(key).copied();
+                if *method == Method::GET && archived && restored != Some(true) {
+                    return error(StatusCode::FORBIDDEN, "InvalidObjectState");
+                }
+                let status = match range {
+                    Some(_) => StatusCode::PARTIAL_CONTENT,
+                    None => StatusCode::OK,
+                };
+                let content = match range.and_then(|range| range.strip_prefix("bytes=")) {
+                    Some(range) => {
+                        let (start, end) = range.split_once('-').unwrap();
+                        let start = start.parse::<usize>().unwrap();
+                        let end = end
+                            .parse::<usize>()
+                            .map_or(content.len(), |end| (end + 1).min(content.len()));
+                        if start >= content.len() {
+                            return error(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange");
+                        }
+                        content.slice(start..end)
+                    }
+                    None => content.clone(),
+                };
+                let mut response = response(status, content.clone());
+                let headers = response.headers_mut();
+                headers.insert(ETAG, current.parse().unwrap());
+                // the time listings report, and the type S3 assigns when none was given
+                headers.insert(LAST_MODIFIED, LAST_MODIFIED_DATE.parse().unwrap());
+                headers.insert(CONTENT_TYPE, "binary/octet-stream".parse().unwrap());
+                let checksum = checksums
+                    .get(key)
+                    .filter(|_| header("x-amz-checksum-mode") == Some("ENABLED"));
+                if let Some(checksum) = checksum {
+                    headers.insert("x-amz-checksum-crc32c", checksum.parse().unwrap());
+                }
+                if *method == Method::HEAD {
+                    *response.body_mut() = Full::new(Bytes::new());
+                    response
+                        .headers_mut()
+                        .insert(CONTENT_LENGTH, content.len().into());
+                    if let Some(lock) = locks.get(key) {
+                        response.headers_mut().extend(lock.clone());
+                    }
+                    if let Some(class) = classes.get(key) {
+                        response
+                            .headers_mut()
+                            .insert("x-amz-storage-class", class.parse().unwrap());
+                    }
+                    let restore = match restored {
+                        Some(false) => Some("ongoing-request=\"true\""),
+                        Some(true) => Some(RESTORED),
+                        None => None,
+                    };
+                    if let Some(restore) = restore {
+                        response
+                            .headers_mut()
+                            .insert("x-amz-restore", restore.parse().unwrap());
+                    }
+                }
+                response
+            }
+            Method::PUT => {
+                let current = objects
+                    .get(key)
+                    .map(|content| format!("\"{}\"", etag(content)));
+                let precondition_failed = match (header("if-match"), &current) {
+                    (Some(expected), Some(current)) => expected != current,
+                    (Some(_), None) => true,
+                    (None, _) => header("if-none-match") == Some("*") && current.is_some(),
+                };
+                if precondition_failed {
+                    return error(StatusCode::PRECONDITION_FAILED, "PreconditionFailed");
+                }
+                let (body, checksum) = match header("x-amz-copy-source") {
+                    Some(source) => {
+                        let source = source_key(source);
+                        let Some(content) = objects.get(&source) else {
+                            return error(StatusCode::NOT_FOUND, "NoSuchKey");
+                        };
+                        (content.clone(), checksums.get(&source).cloned())
+                    }
+                    None => (body, header("x-amz-checksum-crc32c").map(str::to_owned)),
+                };
+                match checksum {
+                    Some(checksum) => checksums.insert(key.to_string(), checksum),
+                    None => checksums.remove(key),
+                };
+                let etag = format!("\"{}\"", etag(&body));
+                objects.insert(key.to_string(), body);
+                restores.remove(key);
+                let lock = headers
+                    .iter()
+                    .filter(|(name, _)| name.as_str().starts_with("x-amz-object-lock-"))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                locks.insert(key.to_string(), lock);
+                match header("x-amz-storage-class") {
+                    Some(class) if class != "STANDARD" => {
+                        classes.insert(key.to_string(), class.to_string());
+                    }
+                    _ => {
+                        classes.remove(key);
+                    }
+                }
+                let mut response = response(StatusCode::OK, Bytes::new());
+                response.headers_mut().insert(ETAG, etag.parse().unwrap());
+                response
+            }
+            Method::DELETE => {
+                objects.remove(key);
+                checksums.remove(key);
+                classes.remove(key);
+                restores.remove(key);
+                locks.remove(key);
+                response(StatusCode::NO_CONTENT, Bytes::new())
+            }
+            _ => error(StatusCode::NOT_IMPLEMENTED, "NotImplemented"),
+        }
+    }
+}
+
+/// The key of the object an `x-amz-copy-source` header names.
+fn source_key(source: &str) -> String {
+    let source = percent_decode_str(source).decode_utf8_lossy();
+    source
+        .trim_start_matches('/')
+        .strip_prefix(BUCKET)
+        .and_then(|source| source.strip_prefix('/'))
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn etag(content: &Bytes) -> String {
+    let hash = content.iter().fold(0u64, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(u64::from(*byte))
+    });
+    format!("{:x}-{}", hash, content.len())
+}
+
+fn response(status: StatusCode, body: impl Into<Bytes>) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .body(Full::new(body.into()))
+        .unwrap()
+}
+
+/// The request id of every error the mock answers with.
+pub(crate) const REQUEST_ID: &str = "MOCKREQUEST";
+
+fn error(status: StatusCode, code: &str) -> Response<Full<Bytes>> {
+    let mut response = response(
+        status,
+        format!(
+            "<Error><Code>{code}</Code><Message>{code}</Message><RequestId>{REQUEST_ID}</\
+             RequestId></Error>"
+        ),
+    );
+    response
+        .headers_mut()
+        .insert("x-amz-request-id", REQUEST_ID.parse().unwrap());
+    response
+}
+
+struct MockClient(Arc<MockS3>);
+
+impl HttpClient for MockClient {
+    type RespBody = Full<Bytes>;
+
+    async fn send_request<B>(
+        &self,
+        request: Request<B>,
+    ) -> Result<Response<Self::RespBody>, HttpError>
+    where
+        B: Body + Send + MaybeSync + 'static,
+        B::Data: Into<Bytes>,
+        B::Error: Into<BoxedError>,
+    {
+        let (parts, body) = request.into_parts();
+        let body = body
+            .map_frame(|frame| frame.map_data(Into::into))
+            .collect()
+            .await
+            .map_err(|e| HttpError::from(e.into() as BoxedError))?
+            .to_bytes();
+
+        let key = percent_decode_str(parts.uri.path().trim_start_matches('/'))
+            .decode_utf8_lossy()
+            .into_owned();
+        let query = parts.uri.query().unwrap_or_default();
+        self.0.requests.lock().unwrap().push((
+            parts.method.clone(),
+            parts
+                .uri
+                .path_and_query()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        ));
+        let mut response = self
+            .0
+            .handle(&parts.method, &key, query, &parts.headers, body);
+        // S3 answers HEAD requests without a body, errors included
+        if parts.method == Method::HEAD {
+            *response.body_mut() = Full::new(Bytes::new());
+        }
+        Ok(response)
+    }
+}
@@ -1,17 +1,48 @@
+pub(crate) mod arn;
+#[cfg(feature = "fs")]
+mod bucket;
+#[cfg(feature = "fs")]
+mod check;
+#[cfg(feature = "fs")]
+mod copy;
+#[cfg(feature = "fs")]
+mod delete;
 pub mod credential;
 mod error;
 #[cfg(feature = "fs")]
 pub mod fs;
+#[cfg(all(test, feature = "fs"))]
+pub(crate) mod mock;
 pub(crate) mod multipart_upload;
+#[cfg(feature = "fs")]
+mod object_lock;
 pub(crate) mod options;
+pub(crate) mod presign;
 mod s3;
 pub(crate) mod sign;
+#[cfg(feature = "fs")]
+mod storage_class;
 pub(crate) mod writer;
 
+#[cfg(feature = "fs")]
+pub use check::{Diagnostic, Problem};
 pub use credential::AwsCredential;
 pub use error::S3Error;
+use http::{HeaderMap, Response, StatusCode};
+use http_body_util::BodyExt;
+#[cfg(feature = "fs")]
+pub use object_lock::{ObjectLock, Retention, RetentionMode};
+use percent_encoding::utf8_percent_encode;
 pub use s3::S3File;
 use serde::Deserialize;
+#[cfg(feature = "fs")]
+pub use storage_class::{RestoreStatus, RestoreTier, StorageClass};
+
+use crate::{
+    path::Path,
+    remotes::http::{BoxBody, HttpError},
+    Error,
+};
 
 const STRICT_ENCODE_SET: percent_encoding::AsciiSet = percent_encoding::NON_ALPHANUMERIC
     .remove(b'-')
@@ -21,6 +52,78 @@ const STRICT_ENCODE_SET: percent_encoding::AsciiSet = percent_encoding::NON_ALPH
 const STRICT_PATH_ENCODE_SET: percent_encoding::AsciiSet = STRICT_ENCODE_SET.remove(b'/');
 const CHECKSUM_HEADER: &str = "x-amz-checksum-sha256";
 
+/// Builds the URL of the object stored at `path`.
+pub(crate) fn object_url(endpoint: &str, path: &Path) -> String {
+    key_url(endpoint, path.as_ref())
+}
+
+/// Builds the URL of the object stored under `key` verbatim.
+///
+/// Every byte of the key except unreserved characters and `/` is percent-encoded, which is also
+/// the canonical URI form SigV4 expects for S3, so the URL can be signed without re-encoding.
+/// Slashes are kept as they are, so keys with empty segments like `a//b`, which S3 allows but
+/// [`Path`] rejects, address their own object instead of `a/b`.
+pub(crate) fn key_url(endpoint: &str, key: &str) -> String {
+    format!(
+        "{}/{}",
+        endpoint,
+        utf8_percent_encode(key, &STRICT_PATH_ENCODE_SET)
+    )
+}
+
+/// Turns a response S3 answered with an unexpected status into an error holding its body.
+pub(crate) async fn unexpected_response(response: Response<BoxBody>) -> Error {
+    let (parts, body) = response.into_parts();
+    let body = body
+        .collect()
+        .await
+        .map(|body| body.to_bytes())
+        .unwrap_or_default();
+    response_error(parts.status, &parts.headers, &body).into()
+}
+
+/// Parses the XML error S3 answered with into a [`S3Error::Service`]. Responses without one, like
+/// those to HEAD requests, become [`HttpError::HttpNotSuccess`] holding the body.
+pub(crate) fn response_error(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> S3Error {
+    match quick_xml::de::from_reader::<_, S3ResponseError>(body) {
+        Ok(error) if !error.code.is_empty() => {
+            let request_id = match error.request_id.is_empty() {
+                true => headers
+                    .get("x-amz-request-id")
+                    .and_then(|id| id.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string(),
+                false => error.request_id,
+            };
+            S3Error::Service {
+                status,
+                code: error.code,
+                message: error.message,
+                request_id,
+            }
+        }
+        _ => S3Error::from(HttpError::HttpNotSuccess {
+            status,
+            body: String::from_utf8_lossy(body).to_string(),
+        }),
+    }
+}
+
+/// Encodes query parameters with the same strict encoding SigV4 uses for the canonical query.
+pub(crate) fn encode_query(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(key, &STRICT_ENCODE_SET),
+                utf8_percent_encode(value, &STRICT_ENCODE_SET)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
 #[derive(Default, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "PascalCase")]
 pub(crate) struct S3ResponseError {
@@ -29,3 +132,89 @@ pub(crate) struct S3ResponseError {
     pub resource: String,
     pub request_id: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use percent_encoding::percent_decode_str;
+    use proptest::prelude::*;
+    use url::Url;
+
+    use super::{encode_query, key_url, object_url};
+    use crate::path::Path;
+
+    const ENDPOINT: &str = "https://fusio-test.s3.us-east-1.amazonaws.com";
+
+    #[test]
+    fn object_url_special_characters() {
+        for (key, encoded) in [
+            ("foo bar", "foo%20bar"),
+            ("a+b", "a%2Bb"),
+            ("a#b", "a%23b"),
+            ("a?b", "a%3Fb"),
+            ("dir/文件.txt", "dir/%E6%96%87%E4%BB%B6.txt"),
+            ("100%/x", "100%25/x"),
+            ("~tilde-_.", "~tilde-_."),
+        ] {
+            let path = Path::parse(key).unwrap();
+            let url = object_url(ENDPOINT, &path);
+            assert_eq!(url, format!("{ENDPOINT}/{encoded}"));
+
+            let url = Url::parse(&url).unwrap();
+            assert_eq!(url.path(), format!("/{encoded}"));
+            assert_eq!(Path::from_url_path(url.path()).unwrap(), path);
+        }
+    }
+
+    /// The key an S3 request for `url` addresses.
+    fn url_key(url: &str) -> String {
+        let uri = url.parse::<http::Uri>().unwrap();
+        let path = uri.path().strip_prefix('/').unwrap();
+        percent_decode_str(path).decode_utf8().unwrap().into_owned()
+    }
+
+    #[test]
+    fn consecutive_slashes_are_kept() {
+        let url = key_url(ENDPOINT, "a//b c/");
+        assert_eq!(url, format!("{ENDPOINT}/a//b%20c/"));
+        assert_eq!(url_key(&url), "a//b c/");
+    }
+
+    #[test]
+    fn query_is_strictly_encoded() {
+        let query = encode_query(&[("list-type", "2"), ("prefix", "a b+c/d")]);
+        assert_eq!(query, "list-type=2&prefix=a%20b%2Bc%2Fd");
+
+        let mut url = Url::parse(ENDPOINT).unwrap();
+        url.set_query(Some(&query));
+        let pairs = url.query_pairs().collect::<Vec<_>>();
+        assert_eq!(pairs[1].1, "a b+c/d");
+    }
+
+    proptest! {
+        #[test]
+        fn object_url_round_trip(
+            segments in prop::collection::vec("[^/\\x00-\\x1f\\x7f]{1,12}", 1..4)
+        ) {
+            prop_assume!(segments.iter().all(|s| s != "." && s != ".."));
+
+            let path = Path::parse(segments.join("/")).unwrap();
+            let url = Url::parse(&object_url(ENDPOINT, &path)).unwrap();
+            prop_assert_eq!(Path::from_url_path(url.path()).unwrap(), path);
+        }
+
+        #[test]
+        fn key_url_round_trip(
+            segments in prop::collection::vec("[^/\\x00-\\x1f\\x7f]{0,12}", 1..5)
+        ) {
+            // URL parsers resolve dot segments
+            prop_assume!(segments.iter().all(|s| s != "." && s != ".."));
+
+            // empty segments make consecutive, leading or trailing slashes
+            let key = segments.join("/");
+            let url = key_url(ENDPOINT, &key);
+            prop_assert_eq!(url_key(&url), key.clone());
+            let parsed = Url::parse(&url).unwrap();
+            prop_assert_eq!(parsed.path(), format!("/{}", &url[ENDPOINT.len() + 1..]));
+        }
+    }
+}
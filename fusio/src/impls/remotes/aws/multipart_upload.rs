@@ -1,7 +1,10 @@
+use std::ops::RangeInclusive;
+
 use bytes::{Buf, Bytes};
 use http::{
-    header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG},
-    Method, Request, Response,
+    header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH},
+    request::Builder,
+    Method, Request, Response, StatusCode,
 };
 use http_body::Body;
 use http_body_util::{BodyExt, Empty, Full};
@@ -12,11 +15,15 @@ use super::fs::AmazonS3;
 use crate::{
     path::Path,
     remotes::{
-        aws::{sign::Sign, S3Error, S3ResponseError, STRICT_PATH_ENCODE_SET},
-        http::{BoxBody, HttpClient},
+        aws::{
+            object_url, response_error, unexpected_response, ObjectLock, S3Error, S3ResponseError,
+            STRICT_ENCODE_SET,
+        },
+        http::BoxBody,
         serde::{
             CompleteMultipartUploadRequest, CompleteMultipartUploadRequestPart,
-            InitiateMultipartUploadResult, MultipartPart,
+            CompleteMultipartUploadResult, CopyPartResult, InitiateMultipartUploadResult,
+            MultipartPart,
         },
     },
     Error,
@@ -25,76 +32,118 @@ use crate::{
 pub(crate) struct MultipartUpload {
     fs: AmazonS3,
     path: Path,
+    lock: ObjectLock,
+    create_new: bool,
+    if_match: Option<String>,
+    if_none_match: Option<String>,
 }
 
 impl MultipartUpload {
     pub fn new(fs: AmazonS3, path: Path) -> Self {
-        Self { fs, path }
+        Self {
+            fs,
+            path,
+            lock: ObjectLock::default(),
+            create_new: false,
+            if_match: None,
+            if_none_match: None,
+        }
+    }
+
+    /// Locks the uploaded object with `lock`.
+    pub(crate) fn object_lock(mut self, lock: ObjectLock) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Only stores the object when no object exists at its path, failing with
+    /// [`Error::AlreadyExists`] otherwise.
+    pub(crate) fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Only stores the object while its ETag is `if_match` and is not `if_none_match`, failing
+    /// with [`Error::PreconditionFailed`] otherwise.
+    pub(crate) fn preconditions(
+        mut self,
+        if_match: Option<String>,
+        if_none_match: Option<String>,
+    ) -> Self {
+        self.if_match = if_match;
+        self.if_none_match = if_none_match;
+        self
+    }
+
+    /// Adds the preconditions to the requests that store the object.
+    fn condition(&self, mut request: Builder) -> Builder {
+        if let Some(etag) = &self.if_match {
+            request = request.header(IF_MATCH, etag);
+        }
+        match (self.create_new, &self.if_none_match) {
+            (true, _) => request.header(IF_NONE_MATCH, "*"),
+            (false, Some(etag)) => request.header(IF_NONE_MATCH, etag),
+            (false, None) => request,
+        }
     }
 
-    async fn check_response(response: Response<BoxBody>) -> Result<Response<BoxBody>, Error> {
+    async fn check_response(
+        &self,
+        response: Response<BoxBody>,
+    ) -> Result<Response<BoxBody>, Error> {
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            let path = self.path.clone();
+            return Err(match self.create_new {
+                true => Error::AlreadyExists { path },
+                false => Error::PreconditionFailed { path },
+            });
+        }
         if !response.status().is_success() {
-            return Err(Error::Other(
-                format!(
-                    "failed to write to S3, HTTP status: {} content: {}",
-                    response.status(),
-                    String::from_utf8_lossy(
-                        &response.into_body().collect().await.unwrap().to_bytes()
-                    )
-                )
-                .into(),
-            ));
+            return Err(unexpected_response(response).await);
         }
         Ok(response)
     }
 
-    async fn send_request<B>(&self, mut request: Request<B>) -> Result<Response<BoxBody>, Error>
+    async fn send_request<B>(&self, request: Request<B>) -> Result<Response<BoxBody>, Error>
     where
         B: Body<Data = Bytes> + Clone + Unpin + Send + Sync + 'static,
         B::Error: std::error::Error + Send + Sync + 'static,
     {
-        request
-            .sign(&self.fs.as_ref().options)
-            .await
-            .map_err(|e| Error::S3Error(S3Error::from(e)))?;
-        let response = self
-            .fs
-            .as_ref()
-            .client
-            .send_request(request)
-            .await
-            .map_err(|e| Error::S3Error(S3Error::from(e)))?;
-        Self::check_response(response).await
+        let response = self.fs.as_ref().send_request(request).await?;
+        self.check_response(response).await
     }
 
-    pub(crate) async fn upload_once<B>(&self, size: usize, body: B) -> Result<(), Error>
+    /// Stores the object in one request and returns its ETag.
+    pub(crate) async fn upload_once<B>(&self, size: usize, body: B) -> Result<Option<String>, Error>
     where
         B: Body<Data = Bytes> + Clone + Unpin + Send + Sync + 'static,
         B::Error: std::error::Error + Send + Sync + 'static,
     {
-        let url = format!(
-            "{}/{}",
-            self.fs.as_ref().options.endpoint,
-            utf8_percent_encode(self.path.as_ref(), &STRICT_PATH_ENCODE_SET)
-        );
-        let request = Request::builder()
+        let url = object_url(&self.fs.as_ref().options.endpoint, &self.path);
+        let request = self
+            .condition(self.lock.apply(Request::builder()))
             .uri(url)
             .method(Method::PUT)
             .header(CONTENT_LENGTH, size)
             .body(body)
             .map_err(|e| Error::Other(e.into()))?;
-        let _ = self.send_request(request).await?;
+        let response = self.send_request(request).await?;
 
-        Ok(())
+        Ok(response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string))
     }
 
     pub(crate) async fn initiate(&self) -> Result<String, Error> {
         let url = format!(
-            "{}/{}?uploads",
-            self.fs.as_ref().options.endpoint,
-            utf8_percent_encode(self.path.as_ref(), &STRICT_PATH_ENCODE_SET)
+            "{}?uploads",
+            object_url(&self.fs.as_ref().options.endpoint, &self.path)
         );
-        let request = Request::builder()
+        let request = self
+            .lock
+            .apply(Request::builder())
             .uri(url)
             .method(Method::POST)
             .body(Empty::new())
@@ -125,11 +174,10 @@ impl MultipartUpload {
         B::Error: std::error::Error + Send + Sync + 'static,
     {
         let url = format!(
-            "{}/{}?partNumber={}&uploadId={}",
-            self.fs.as_ref().options.endpoint,
-            utf8_percent_encode(self.path.as_ref(), &STRICT_PATH_ENCODE_SET),
+            "{}?partNumber={}&uploadId={}",
+            object_url(&self.fs.as_ref().options.endpoint, &self.path),
             part_num + 1,
-            utf8_percent_encode(upload_id, &STRICT_PATH_ENCODE_SET),
+            utf8_percent_encode(upload_id, &STRICT_ENCODE_SET),
         );
         let request = Request::builder()
             .uri(url)
@@ -151,16 +199,59 @@ impl MultipartUpload {
         })
     }
 
+    /// Copies the bytes in `range` of the object at `source`, given like for
+    /// `x-amz-copy-source`, as a part of the upload with UploadPartCopy.
+    pub(crate) async fn upload_part_copy(
+        &self,
+        upload_id: &str,
+        part_num: usize,
+        source: &str,
+        range: RangeInclusive<u64>,
+    ) -> Result<MultipartPart, Error> {
+        let url = format!(
+            "{}?partNumber={}&uploadId={}",
+            object_url(&self.fs.as_ref().options.endpoint, &self.path),
+            part_num + 1,
+            utf8_percent_encode(upload_id, &STRICT_ENCODE_SET),
+        );
+        let request = Request::builder()
+            .uri(url)
+            .method(Method::PUT)
+            .header("x-amz-copy-source", source)
+            .header(
+                "x-amz-copy-source-range",
+                format!("bytes={}-{}", range.start(), range.end()),
+            )
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| Error::Other(e.into()))?;
+        let response = self.send_request(request).await?;
+        // like CompleteMultipartUpload, the copy can fail after S3 answered 200
+        let (parts, body) = response.into_parts();
+        let body = body.collect().await.map_err(S3Error::from)?.to_bytes();
+        let failed = quick_xml::de::from_reader::<_, S3ResponseError>(body.as_ref())
+            .is_ok_and(|error| !error.code.is_empty());
+        if failed {
+            return Err(response_error(parts.status, &parts.headers, &body).into());
+        }
+        let result: CopyPartResult =
+            quick_xml::de::from_reader(body.as_ref()).map_err(S3Error::from)?;
+
+        Ok(MultipartPart {
+            part_num,
+            etag: result.etag,
+        })
+    }
+
+    /// Completes the upload and returns the ETag of the object.
     pub(crate) async fn complete_part(
         &self,
         upload_id: &str,
         parts: &[MultipartPart],
-    ) -> Result<(), Error> {
+    ) -> Result<String, Error> {
         let url = format!(
-            "{}/{}?uploadId={}",
-            self.fs.as_ref().options.endpoint,
-            utf8_percent_encode(self.path.as_ref(), &STRICT_PATH_ENCODE_SET),
-            utf8_percent_encode(upload_id, &STRICT_PATH_ENCODE_SET),
+            "{}?uploadId={}",
+            object_url(&self.fs.as_ref().options.endpoint, &self.path),
+            utf8_percent_encode(upload_id, &STRICT_ENCODE_SET),
         );
         let content = quick_xml::se::to_string(&CompleteMultipartUploadRequest {
             part: parts
@@ -173,7 +264,8 @@ impl MultipartUpload {
         })
         .map_err(S3Error::from)?;
 
-        let request = Request::builder()
+        let request = self
+            .condition(Request::builder())
             .uri(url)
             .method(Method::POST)
             .header(CONTENT_LENGTH, content.len())
@@ -184,20 +276,15 @@ impl MultipartUpload {
         // still check if there is any error because S3 might return error for status code 200
         // https://docs.aws.amazon.com/AmazonS3/latest/API/API_CompleteMultipartUpload.html#API_CompleteMultipartUpload_Example_4
         let (parts, body) = response.into_parts();
-        let maybe_error: S3ResponseError = quick_xml::de::from_reader(
-            body.collect()
-                .await
-                .map_err(S3Error::from)?
-                .aggregate()
-                .reader(),
-        )
-        .map_err(S3Error::from)?;
+        let body = body.collect().await.map_err(S3Error::from)?.to_bytes();
+        let maybe_error: S3ResponseError =
+            quick_xml::de::from_reader(body.as_ref()).map_err(S3Error::from)?;
         if !maybe_error.code.is_empty() {
-            return Err(Error::Other(
-                format!("{:#?}, {:?}", parts, maybe_error).into(),
-            ));
+            return Err(response_error(parts.status, &parts.headers, &body).into());
         }
+        let result: CompleteMultipartUploadResult =
+            quick_xml::de::from_reader(body.as_ref()).map_err(S3Error::from)?;
 
-        Ok(())
+        Ok(result.etag)
     }
 }
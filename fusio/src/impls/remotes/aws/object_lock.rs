@@ -0,0 +1,181 @@
+//! S3 Object Lock, which keeps objects from being overwritten or deleted, e.g. for compliance
+//! archives.
+//!
+//! Writes set the lock of the object they create through [`S3File::object_lock`], and
+//! [`AmazonS3::object_lock`] reads it back. Object Lock has to be enabled on the bucket, and S3
+//! only accepts locked writes with a checksum, see [`AmazonS3Builder::checksum`].
+//!
+//! [`S3File::object_lock`]: super::S3File::object_lock
+//! [`AmazonS3Builder::checksum`]: super::fs::AmazonS3Builder::checksum
+
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use chrono::{DateTime, SecondsFormat, Utc};
+use http::{request::Builder, HeaderMap, Method, Request};
+use http_body_util::Empty;
+
+use super::{fs::AmazonS3, object_url, unexpected_response, S3Error};
+use crate::{path::Path, remotes::http::HttpError, Error};
+
+const MODE_HEADER: &str = "x-amz-object-lock-mode";
+const RETAIN_UNTIL_HEADER: &str = "x-amz-object-lock-retain-until-date";
+const LEGAL_HOLD_HEADER: &str = "x-amz-object-lock-legal-hold";
+
+/// Who may lift a [`Retention`] before it expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionMode {
+    /// Users with the `s3:BypassGovernanceRetention` permission.
+    Governance,
+    /// Nobody, not even the root user.
+    Compliance,
+}
+
+impl RetentionMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            RetentionMode::Governance => "GOVERNANCE",
+            RetentionMode::Compliance => "COMPLIANCE",
+        }
+    }
+}
+
+/// Protects an object version from being overwritten or deleted until `retain_until`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    pub mode: RetentionMode,
+    pub retain_until: SystemTime,
+}
+
+/// The Object Lock settings of an object. The default locks nothing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjectLock {
+    pub retention: Option<Retention>,
+    /// Protects the object until the hold is removed, independent of its retention.
+    pub legal_hold: bool,
+}
+
+impl ObjectLock {
+    pub fn retention(mut self, mode: RetentionMode, retain_until: SystemTime) -> Self {
+        self.retention = Some(Retention { mode, retain_until });
+        self
+    }
+
+    pub fn legal_hold(mut self, legal_hold: bool) -> Self {
+        self.legal_hold = legal_hold;
+        self
+    }
+
+    /// Adds the headers that lock the object created by `request`.
+    pub(crate) fn apply(&self, mut request: Builder) -> Builder {
+        if let Some(retention) = self.retention {
+            let retain_until = DateTime::<Utc>::from(retention.retain_until)
+                .to_rfc3339_opts(SecondsFormat::Millis, true);
+            request = request
+                .header(MODE_HEADER, retention.mode.as_str())
+                .header(RETAIN_UNTIL_HEADER, retain_until);
+        }
+        if self.legal_hold {
+            request = request.header(LEGAL_HOLD_HEADER, "ON");
+        }
+        request
+    }
+
+    /// Reads the lock from the headers of a `HEAD` or `GET` response.
+    fn from_headers(headers: &HeaderMap) -> Result<Self, Error> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let invalid = |what: &str| Error::Other(format!("invalid object lock {what}").into());
+
+        let mode = match header(MODE_HEADER) {
+            Some("GOVERNANCE") => Some(RetentionMode::Governance),
+            Some("COMPLIANCE") => Some(RetentionMode::Compliance),
+            Some(_) => return Err(invalid("mode")),
+            None => None,
+        };
+        let retain_until = header(RETAIN_UNTIL_HEADER)
+            .map(|date| {
+                DateTime::parse_from_rfc3339(date)
+                    .map(SystemTime::from)
+                    .map_err(|_| invalid("retain-until date"))
+            })
+            .transpose()?;
+        let retention = match (mode, retain_until) {
+            (Some(mode), Some(retain_until)) => Some(Retention { mode, retain_until }),
+            _ => None,
+        };
+
+        Ok(Self {
+            retention,
+            legal_hold: header(LEGAL_HOLD_HEADER) == Some("ON"),
+        })
+    }
+}
+
+impl AmazonS3 {
+    /// The Object Lock settings of the object at `path`. Reading them requires the
+    /// `s3:GetObjectRetention` and `s3:GetObjectLegalHold` permissions, without them S3 leaves
+    /// them out and the object appears unlocked.
+    pub async fn object_lock(&self, path: &Path) -> Result<ObjectLock, Error> {
+        let request = Request::builder()
+            .method(Method::HEAD)
+            .uri(object_url(&self.as_ref().options.endpoint, path))
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        if !response.status().is_success() {
+            return Err(unexpected_response(response).await);
+        }
+        ObjectLock::from_headers(response.headers())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    async fn writes_and_reads_object_lock() {
+        use std::{
+            sync::Arc,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        use super::{ObjectLock, RetentionMode};
+        use crate::{
+            fs::{Fs, OpenOptions},
+            path::Path,
+            remotes::aws::mock::MockS3,
+            Write,
+        };
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let path = Path::parse("audit/2024.log").unwrap();
+        let lock = ObjectLock::default()
+            .retention(
+                RetentionMode::Compliance,
+                UNIX_EPOCH + Duration::from_millis(1_893_456_000_123),
+            )
+            .legal_hold(true);
+
+        let mut file = s3
+            .open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap()
+            .object_lock(lock);
+        let (result, _) = file.write_all(&b"entry"[..]).await;
+        result.unwrap();
+        file.close().await.unwrap();
+
+        let headers = server.lock("audit/2024.log");
+        assert_eq!(headers["x-amz-object-lock-mode"], "COMPLIANCE");
+        assert_eq!(
+            headers["x-amz-object-lock-retain-until-date"],
+            "2030-01-01T00:00:00.123Z"
+        );
+        assert_eq!(headers["x-amz-object-lock-legal-hold"], "ON");
+        assert_eq!(s3.object_lock(&path).await.unwrap(), lock);
+
+        let path = Path::parse("audit/unlocked.log").unwrap();
+        server.put("audit/unlocked.log", &b""[..]);
+        assert_eq!(s3.object_lock(&path).await.unwrap(), ObjectLock::default());
+    }
+}
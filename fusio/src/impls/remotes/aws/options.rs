@@ -1,9 +1,17 @@
+use std::sync::Arc;
+
 use super::credential::AwsCredential;
+use crate::clock::{Clock, OffsetClock};
 
 pub(crate) struct S3Options {
     pub(crate) endpoint: String,
+    pub(crate) bucket: String,
     pub(crate) region: String,
     pub(crate) credential: Option<AwsCredential>,
     pub(crate) sign_payload: bool,
     pub(crate) checksum: bool,
+    pub(crate) clock: OffsetClock<Arc<dyn Clock>>,
+    /// Presigns requests through this URL instead of signing them with `credential`, see
+    /// [`super::presign`].
+    pub(crate) signing_endpoint: Option<String>,
 }
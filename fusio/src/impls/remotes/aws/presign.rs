@@ -0,0 +1,189 @@
+//! Delegates signing to a remote endpoint that returns presigned requests, so clients that must
+//! not hold credentials, like browser apps built for wasm, can still reach private buckets.
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use http::{HeaderName, HeaderValue, Method, Request, Uri};
+use http_body_util::{BodyExt, Full};
+use serde::{Deserialize, Serialize};
+
+use super::S3Error;
+use crate::{
+    error::BoxedError,
+    remotes::http::{HttpClient, HttpError},
+};
+
+/// What is posted to the signing endpoint as JSON: the request to sign, without its body.
+#[derive(Debug, Serialize)]
+struct SigningRequest<'a> {
+    method: &'a str,
+    url: String,
+    headers: BTreeMap<&'a str, &'a str>,
+}
+
+/// What the signing endpoint answers with: the presigned URL to send the request to, and the
+/// headers the signature covers that the request has to carry.
+#[derive(Debug, Deserialize)]
+struct SigningResponse {
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+fn other(e: impl Into<BoxedError>) -> S3Error {
+    S3Error::from(HttpError::Other(e.into()))
+}
+
+/// Signs `request` by posting it to `endpoint` through `client` and applying the presigned URL
+/// and headers from the response. The endpoint decides whether the request is allowed, the
+/// credentials never leave it.
+pub(crate) async fn presign<B, C: HttpClient>(
+    client: &C,
+    endpoint: &str,
+    request: &mut Request<B>,
+) -> Result<(), S3Error> {
+    let signing = SigningRequest {
+        method: request.method().as_str(),
+        url: request.uri().to_string(),
+        headers: request
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+            .collect(),
+    };
+    let body = serde_json::to_vec(&signing).map_err(other)?;
+    let signing = Request::builder()
+        .method(Method::POST)
+        .uri(endpoint)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .map_err(HttpError::from)?;
+
+    let response = client.send_request(signing).await?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| other(e.into()))?
+        .to_bytes();
+    if !status.is_success() {
+        return Err(HttpError::HttpNotSuccess {
+            status,
+            body: String::from_utf8_lossy(&body).to_string(),
+        }
+        .into());
+    }
+
+    let signed = serde_json::from_slice::<SigningResponse>(&body).map_err(other)?;
+    *request.uri_mut() = signed.url.parse::<Uri>().map_err(other)?;
+    for (name, value) in signed.headers {
+        request.headers_mut().insert(
+            HeaderName::try_from(name).map_err(other)?,
+            HeaderValue::try_from(value).map_err(other)?,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http::{header::RANGE, Method, Request, Response, StatusCode};
+    use http_body::Body;
+    use http_body_util::{BodyExt, Empty, Full};
+
+    use super::presign;
+    use crate::{
+        error::BoxedError,
+        remotes::http::{HttpClient, HttpError},
+        MaybeSync,
+    };
+
+    // presigns by appending a fake signature to the URL
+    struct Signer;
+
+    impl HttpClient for Signer {
+        type RespBody = Full<Bytes>;
+
+        async fn send_request<B>(
+            &self,
+            request: Request<B>,
+        ) -> Result<Response<Self::RespBody>, HttpError>
+        where
+            B: Body + Send + MaybeSync + 'static,
+            B::Data: Into<Bytes>,
+            B::Error: Into<BoxedError>,
+        {
+            assert_eq!(request.method(), Method::POST);
+            assert_eq!(request.uri(), "https://signer.local/sign");
+            let body = request
+                .into_body()
+                .map_frame(|frame| frame.map_data(Into::into))
+                .collect()
+                .await
+                .map_err(|e| HttpError::from(e.into() as BoxedError))?
+                .to_bytes();
+            let signing = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+            assert_eq!(signing["method"], "GET");
+            assert_eq!(signing["headers"]["range"], "bytes=0-9");
+
+            let url = format!("{}?X-Amz-Signature=fake", signing["url"].as_str().unwrap());
+            let body = serde_json::json!({
+                "url": url,
+                "headers": { "x-amz-content-sha256": "UNSIGNED-PAYLOAD" },
+            });
+            Ok(Response::new(Full::new(Bytes::from(body.to_string()))))
+        }
+    }
+
+    struct Rejecting;
+
+    impl HttpClient for Rejecting {
+        type RespBody = Full<Bytes>;
+
+        async fn send_request<B>(
+            &self,
+            _: Request<B>,
+        ) -> Result<Response<Self::RespBody>, HttpError>
+        where
+            B: Body + Send + MaybeSync + 'static,
+            B::Data: Into<Bytes>,
+            B::Error: Into<BoxedError>,
+        {
+            Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Full::new(Bytes::from_static(b"denied")))
+                .unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn applies_presigned_request() {
+        let request = || {
+            Request::get("https://bucket.s3.us-east-1.amazonaws.com/key")
+                .header(RANGE, "bytes=0-9")
+                .body(Empty::<Bytes>::new())
+                .unwrap()
+        };
+
+        let mut signed = request();
+        presign(&Signer, "https://signer.local/sign", &mut signed)
+            .await
+            .unwrap();
+        assert_eq!(
+            signed.uri(),
+            "https://bucket.s3.us-east-1.amazonaws.com/key?X-Amz-Signature=fake"
+        );
+        assert_eq!(signed.headers()["x-amz-content-sha256"], "UNSIGNED-PAYLOAD");
+        assert_eq!(signed.headers()[RANGE], "bytes=0-9");
+
+        let mut denied = request();
+        assert!(
+            presign(&Rejecting, "https://signer.local/sign", &mut denied)
+                .await
+                .is_err()
+        );
+    }
+}
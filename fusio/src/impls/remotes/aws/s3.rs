@@ -1,29 +1,39 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use bytes::Buf;
+use bytes::Bytes;
+use futures_util::{stream, StreamExt, TryStreamExt};
 use http::{
-    header::{CONTENT_LENGTH, RANGE},
+    header::{CONTENT_LENGTH, ETAG, IF_MATCH, IF_NONE_MATCH, RANGE},
     request::Builder,
-    Method, Request,
+    Method, Request, Response, StatusCode,
 };
-use http_body_util::{BodyExt, Empty};
-use percent_encoding::utf8_percent_encode;
+use http_body_util::{BodyExt, Empty, Full};
 
-use super::{fs::AmazonS3, sign::Sign, S3Error, STRICT_PATH_ENCODE_SET};
+use super::{fs::AmazonS3, object_url, response_error, unexpected_response, ObjectLock, S3Error};
 use crate::{
     buf::IoBufMut,
     path::Path,
     remotes::{
         aws::{multipart_upload::MultipartUpload, writer::S3Writer},
-        http::{HttpClient, HttpError},
+        http::{BoxBody, HttpError},
     },
     Error, IoBuf, Read, Write,
 };
 
+const MAX_CONCURRENT_RANGES: usize = 16;
+
 pub struct S3File {
     fs: AmazonS3,
     path: Path,
     writer: Option<S3Writer>,
+    create: bool,
+    truncate: bool,
+    create_new: bool,
+    lock: ObjectLock,
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    /// The ETag S3 reported last, see [`S3File::etag`].
+    etag: Mutex<Option<String>>,
 }
 
 impl S3File {
@@ -32,183 +42,238 @@ impl S3File {
             fs,
             path,
             writer: None,
+            create: false,
+            truncate: false,
+            create_new: false,
+            lock: ObjectLock::default(),
+            if_match: None,
+            if_none_match: None,
+            etag: Mutex::new(None),
         }
     }
 
+    /// Makes [`Write::close`] store an empty object when nothing was written and none exists,
+    /// like creating a local file does.
+    pub(crate) fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Makes [`Write::close`] replace an existing object with an empty one when nothing was
+    /// written, like truncating a local file does.
+    pub(crate) fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Makes [`Write::close`] fail with [`Error::AlreadyExists`] instead of overwriting an object
+    /// stored at the path in the meantime.
+    pub(crate) fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Locks the object created when the file is closed with `lock`, see [`ObjectLock`].
+    pub fn object_lock(mut self, lock: ObjectLock) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Makes reads and writes fail with [`Error::PreconditionFailed`] unless the ETag of the
+    /// object is `etag`, e.g. to only replace an object nobody changed since it was read, in a
+    /// compare-and-swap loop with [`S3File::etag`].
+    pub fn if_match(mut self, etag: impl Into<String>) -> Self {
+        self.if_match = Some(etag.into());
+        self
+    }
+
+    /// Makes reads and writes fail with [`Error::PreconditionFailed`] when the ETag of the object
+    /// is `etag`, e.g. to skip reading an object that did not change since it was cached. Writes
+    /// only support `*`, see [`OpenOptions::create_new`].
+    ///
+    /// [`OpenOptions::create_new`]: crate::fs::OpenOptions::create_new
+    pub fn if_none_match(mut self, etag: impl Into<String>) -> Self {
+        self.if_none_match = Some(etag.into());
+        self
+    }
+
+    /// The ETag of the object as of the last read, [`Read::size`] or completed write, quoted as
+    /// S3 reports it.
+    pub fn etag(&self) -> Option<String> {
+        self.etag.lock().unwrap().clone()
+    }
+
     fn build_request(&self, method: Method) -> Builder {
-        let url = format!(
-            "{}/{}",
-            self.fs.as_ref().options.endpoint,
-            utf8_percent_encode(self.path.as_ref(), &STRICT_PATH_ENCODE_SET)
-        );
+        let url = object_url(&self.fs.as_ref().options.endpoint, &self.path);
 
-        Request::builder().method(method).uri(url)
+        let mut request = Request::builder().method(method).uri(url);
+        if let Some(etag) = &self.if_match {
+            request = request.header(IF_MATCH, etag);
+        }
+        if let Some(etag) = &self.if_none_match {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        request
     }
-}
 
-impl Read for S3File {
-    async fn read_exact_at<B: IoBufMut>(&mut self, mut buf: B, pos: u64) -> (Result<(), Error>, B) {
+    /// Reads what S3 answered on the preconditions and the ETag of the object from a response
+    /// to a read.
+    fn check_read(&self, response: &Response<BoxBody>) -> Result<(), Error> {
+        if let StatusCode::PRECONDITION_FAILED | StatusCode::NOT_MODIFIED = response.status() {
+            return Err(Error::PreconditionFailed {
+                path: self.path.clone(),
+            });
+        }
+        if let Some(etag) = response.headers().get(ETAG) {
+            *self.etag.lock().unwrap() = etag.to_str().ok().map(str::to_string);
+        }
+        Ok(())
+    }
+
+    fn upload(&self) -> MultipartUpload {
+        MultipartUpload::new(self.fs.clone(), self.path.clone())
+            .object_lock(self.lock)
+            .create_new(self.create_new)
+            .preconditions(self.if_match.clone(), self.if_none_match.clone())
+    }
+
+    /// Fetches `len` bytes at `pos`, or everything from `pos` on when `len` is `None`. The body
+    /// is shorter than `len` when the object ends early.
+    ///
+    /// Returns `None` when `pos` is at or past the end of the object: S3 rejects such ranges,
+    /// which also covers every range of an empty object.
+    async fn get_range(&self, pos: u64, len: Option<u64>) -> Result<Option<Bytes>, Error> {
+        let range = match len {
+            Some(len) => format!("bytes={}-{}", pos, pos.saturating_add(len) - 1),
+            None => format!("bytes={}-", pos),
+        };
         let request = self
             .build_request(Method::GET)
-            .header(
-                RANGE,
-                format!("bytes={}-{}", pos, pos + buf.as_slice().len() as u64 - 1),
-            )
+            .header(RANGE, range)
             .body(Empty::new())
-            .map_err(|e| S3Error::from(HttpError::from(e)));
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
 
-        let mut request = match request {
-            Ok(request) => request,
-            Err(e) => return (Err(e.into()), buf),
-        };
+        let response = self.fs.as_ref().send_request(request).await?;
+        self.check_read(&response)?;
 
-        if let Err(e) = request
-            .sign(&self.fs.as_ref().options)
-            .await
-            .map_err(S3Error::from)
-        {
-            return (Err(e.into()), buf);
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(None);
         }
-
-        let response = match self
-            .fs
-            .as_ref()
-            .client
-            .send_request(request)
-            .await
-            .map_err(S3Error::from)
-        {
-            Ok(response) => response,
-            Err(e) => return (Err(e.into()), buf),
-        };
-
         if !response.status().is_success() {
-            return (
-                Err(S3Error::from(HttpError::HttpNotSuccess {
-                    status: response.status(),
-                    body: String::from_utf8_lossy(
-                        &response
-                            .into_body()
-                            .collect()
-                            .await
-                            .map(|b| b.to_bytes())
-                            .unwrap_or_default(),
-                    )
-                    .to_string(),
-                })
-                .into()),
-                buf,
-            );
-        } else {
-            match response.into_body().collect().await.map_err(S3Error::from) {
-                Ok(body) => {
-                    if let Err(e) = std::io::Read::read_exact(
-                        &mut body.aggregate().reader(),
-                        buf.as_slice_mut(),
-                    ) {
-                        return (Err(e.into()), buf);
-                    }
-                }
-                Err(e) => return (Err(e.into()), buf),
+            let (parts, body) = response.into_parts();
+            let body = body
+                .collect()
+                .await
+                .map(|b| b.to_bytes())
+                .unwrap_or_default();
+            let error = response_error(parts.status, &parts.headers, &body);
+            // archived objects are rejected until a copy is restored
+            if parts.status == StatusCode::FORBIDDEN && error.code() == Some("InvalidObjectState") {
+                return Err(Error::NotRestored {
+                    path: self.path.clone(),
+                });
             }
+            return Err(error.into());
+        }
 
-            (Ok(()), buf)
+        Ok(Some(
+            response
+                .into_body()
+                .collect()
+                .await
+                .map_err(S3Error::from)?
+                .to_bytes(),
+        ))
+    }
+
+    /// Returns exactly `len` bytes at `pos`, failing with [`Error::UnexpectedEof`] when the object
+    /// ends before.
+    async fn get_exact(&self, pos: u64, len: u64) -> Result<Bytes, Error> {
+        match self.get_range(pos, Some(len)).await? {
+            Some(body) if body.len() as u64 >= len => Ok(body.slice(..len as usize)),
+            Some(body) => Err(Error::UnexpectedEof {
+                requested: len,
+                available: body.len() as u64,
+            }),
+            // the range starts at or past the end, look up how much there actually is
+            None => Err(Error::UnexpectedEof {
+                requested: len,
+                available: self.size().await?.saturating_sub(pos),
+            }),
         }
     }
+}
 
-    async fn read_to_end_at(&mut self, mut buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
-        let mut request = match self
-            .build_request(Method::GET)
-            .header(RANGE, format!("bytes={}-", pos))
-            .body(Empty::new())
-            .map_err(|e| S3Error::from(HttpError::from(e)))
-        {
-            Err(e) => return (Err(e.into()), buf),
-            Ok(request) => request,
-        };
+impl Read for S3File {
+    async fn read_exact_at<B: IoBufMut>(&mut self, mut buf: B, pos: u64) -> (Result<(), Error>, B) {
+        let requested = buf.bytes_init() as u64;
+        if requested == 0 {
+            return (Ok(()), buf);
+        }
 
-        if let Err(e) = request
-            .sign(&self.fs.as_ref().options)
-            .await
-            .map_err(S3Error::from)
-        {
-            return (Err(e.into()), buf);
+        match self.get_exact(pos, requested).await {
+            Ok(body) => {
+                buf.as_slice_mut().copy_from_slice(&body);
+                (Ok(()), buf)
+            }
+            Err(e) => (Err(e), buf),
         }
+    }
 
-        let response = match self
-            .fs
-            .as_ref()
-            .client
-            .send_request(request)
-            .await
-            .map_err(S3Error::from)
-        {
-            Ok(response) => response,
-            Err(e) => return (Err(e.into()), buf),
-        };
+    async fn read_to_end_at(&mut self, mut buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        match self.get_range(pos, None).await {
+            Ok(Some(body)) => {
+                buf.extend_from_slice(&body);
+                (Ok(()), buf)
+            }
+            Ok(None) => (Ok(()), buf),
+            Err(e) => (Err(e), buf),
+        }
+    }
 
-        if !response.status().is_success() {
-            return (
-                Err(S3Error::from(HttpError::HttpNotSuccess {
-                    status: response.status(),
-                    body: String::from_utf8_lossy(
-                        &response
-                            .into_body()
-                            .collect()
-                            .await
-                            .map(|b| b.to_bytes())
-                            .unwrap_or_default(),
-                    )
-                    .to_string(),
-                })
-                .into()),
-                buf,
-            );
-        } else {
-            match response.into_body().collect().await.map_err(S3Error::from) {
-                Ok(body) => {
-                    let mut body = body.to_bytes();
-                    buf.resize(body.len(), 0);
-                    body.copy_to_slice(&mut buf[..]);
-                    (Ok(()), buf)
-                }
-                Err(e) => (Err(e.into()), buf),
+    async fn read_at<B: IoBufMut>(&mut self, mut buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        let requested = buf.bytes_init() as u64;
+        if requested == 0 {
+            return (Ok(0), buf);
+        }
+
+        match self.get_range(pos, Some(requested)).await {
+            Ok(Some(body)) => {
+                let len = body.len().min(requested as usize);
+                buf.as_slice_mut()[..len].copy_from_slice(&body[..len]);
+                (Ok(len), buf)
             }
+            Ok(None) => (Ok(0), buf),
+            Err(e) => (Err(e), buf),
         }
     }
 
+    /// Issues up to `MAX_CONCURRENT_RANGES` range requests at once.
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        let file = &*self;
+        stream::iter(ranges)
+            .map(|(pos, len)| async move {
+                match len {
+                    0 => Ok(Vec::new()),
+                    len => Ok(file.get_exact(pos, len as u64).await?.to_vec()),
+                }
+            })
+            .buffered(MAX_CONCURRENT_RANGES)
+            .try_collect()
+            .await
+    }
+
     async fn size(&self) -> Result<u64, Error> {
-        let mut request = self
+        let request = self
             .build_request(Method::HEAD)
             .body(Empty::new())
             .map_err(|e| S3Error::from(HttpError::from(e)))?;
-        request
-            .sign(&self.fs.as_ref().options)
-            .await
-            .map_err(S3Error::from)?;
 
-        let response = self
-            .fs
-            .as_ref()
-            .client
-            .send_request(request)
-            .await
-            .map_err(S3Error::from)?;
+        let response = self.fs.as_ref().send_request(request).await?;
+        self.check_read(&response)?;
 
         if !response.status().is_success() {
-            Err(S3Error::from(HttpError::HttpNotSuccess {
-                status: response.status(),
-                body: String::from_utf8_lossy(
-                    &response
-                        .into_body()
-                        .collect()
-                        .await
-                        .map_err(S3Error::from)?
-                        .to_bytes(),
-                )
-                .to_string(),
-            })
-            .into())
+            Err(unexpected_response(response).await)
         } else {
             let size = response
                 .headers()
@@ -225,15 +290,11 @@ impl Read for S3File {
 
 impl Write for S3File {
     async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
-        self.writer
-            .get_or_insert_with(|| {
-                S3Writer::new(Arc::new(MultipartUpload::new(
-                    self.fs.clone(),
-                    self.path.clone(),
-                )))
-            })
-            .write_all(buf)
-            .await
+        if self.writer.is_none() {
+            self.writer = Some(S3Writer::new(Arc::new(self.upload())));
+        }
+        let writer = self.writer.as_mut().expect("writer was created above");
+        writer.write_all(buf).await
     }
 
     async fn flush(&mut self) -> Result<(), Error> {
@@ -244,11 +305,38 @@ impl Write for S3File {
     }
 
     async fn close(&mut self) -> Result<(), Error> {
-        if let Some(mut writer) = self.writer.take() {
-            writer.close().await?;
-        }
+        let etag = match self.writer.take() {
+            Some(mut writer) => {
+                writer.close().await?;
+                writer.etag().map(str::to_string)
+            }
+            None if self.create => {
+                // without truncating, an existing object is kept as it is
+                let keep = !self.truncate && !self.create_new;
+                let stored = self
+                    .upload()
+                    .create_new(self.create_new || keep)
+                    .upload_once(0, Full::new(Bytes::new()))
+                    .await;
+                match stored {
+                    Err(Error::AlreadyExists { .. }) if keep => None,
+                    etag => etag?,
+                }
+            }
+            None => return Ok(()),
+        };
+        *self.etag.lock().unwrap() = etag;
+        self.create = false;
+        self.truncate = false;
         Ok(())
     }
+
+    /// Fails, an object only becomes durable once its upload completes on close.
+    async fn barrier(&mut self) -> Result<(), Error> {
+        Err(Error::Unsupported {
+            message: "Amazon S3 makes writes durable only when the object is closed".into(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -259,6 +347,7 @@ mod tests {
         use std::{env, sync::Arc};
 
         use crate::{
+            clock::{OffsetClock, SystemClock},
             remotes::{
                 aws::{
                     credential::AwsCredential,
@@ -266,7 +355,7 @@ mod tests {
                     options::S3Options,
                     s3::S3File,
                 },
-                http::{tokio::TokioClient, DynHttpClient, HttpClient},
+                http::{tokio::TokioClient, DynHttpClient},
             },
             Read, Write,
         };
@@ -282,6 +371,7 @@ mod tests {
         let region = "ap-southeast-1";
         let options = S3Options {
             endpoint: "https://fusio-test.s3.ap-southeast-1.amazonaws.com".into(),
+            bucket: "fusio-test".into(),
             credential: Some(AwsCredential {
                 key_id,
                 secret_key,
@@ -290,6 +380,8 @@ mod tests {
             region: region.into(),
             sign_payload: true,
             checksum: false,
+            clock: OffsetClock::new(Arc::new(SystemClock)),
+            signing_endpoint: None,
         };
 
         let s3 = AmazonS3 {
@@ -313,4 +405,279 @@ mod tests {
         result.unwrap();
         assert_eq!(buf, b"The answer of life, universe and everthing");
     }
+
+    #[tokio::test]
+    async fn zero_length_objects() {
+        use std::sync::Arc;
+
+        use crate::{
+            fs::{Fs, OpenOptions},
+            path::Path,
+            remotes::aws::mock::MockS3,
+            Read, Write,
+        };
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let path = Path::parse("empty").unwrap();
+
+        let mut file = s3
+            .open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        assert_eq!(server.get("empty").unwrap().len(), 0);
+
+        // creating an existing object keeps its content, truncating it empties it
+        server.put("kept", "kept");
+        let kept = Path::parse("kept").unwrap();
+        for (options, content) in [
+            (OpenOptions::default().create(true), &b"kept"[..]),
+            (OpenOptions::default().create(true).truncate(true), b""),
+        ] {
+            let mut file = s3.open_options(&kept, options).await.unwrap();
+            file.close().await.unwrap();
+            assert_eq!(server.get("kept").unwrap(), content);
+        }
+
+        let mut file = s3.open(&path).await.unwrap();
+        assert_eq!(file.size().await.unwrap(), 0);
+        let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+        result.unwrap();
+        assert!(buf.is_empty());
+        let (result, buf) = file.read_exact_at(Vec::new(), 0).await;
+        result.unwrap();
+        assert!(buf.is_empty());
+
+        server.put("abc", &b"abc"[..]);
+        let mut file = s3.open(&Path::parse("abc").unwrap()).await.unwrap();
+        let (result, buf) = file.read_to_end_at(b"xyz".to_vec(), 1).await;
+        result.unwrap();
+        assert_eq!(buf, b"xyzbc");
+        let (result, buf) = file.read_to_end_at(Vec::new(), 3).await;
+        result.unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn create_new_keeps_existing_objects() {
+        use std::sync::Arc;
+
+        use crate::{
+            fs::{Fs, OpenOptions},
+            path::Path,
+            remotes::aws::mock::MockS3,
+            Error, Write,
+        };
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let path = Path::parse("LOCK").unwrap();
+        let options = OpenOptions::default().create_new(true);
+
+        let mut first = s3.open_options(&path, options.clone()).await.unwrap();
+        let mut second = s3.open_options(&path, options.clone()).await.unwrap();
+        let (result, _) = first.write_all(&b"first"[..]).await;
+        result.unwrap();
+        first.close().await.unwrap();
+        let (result, _) = second.write_all(&b"second"[..]).await;
+        result.unwrap();
+        let err = second.close().await.unwrap_err();
+        assert!(matches!(err, Error::AlreadyExists { path: p } if p == path));
+        assert_eq!(server.get("LOCK").unwrap(), &b"first"[..]);
+
+        // closing without writing creates an empty object, also only when there is none
+        let mut empty = s3.open_options(&path, options).await.unwrap();
+        assert!(matches!(
+            empty.close().await,
+            Err(Error::AlreadyExists { .. })
+        ));
+        assert_eq!(server.get("LOCK").unwrap(), &b"first"[..]);
+    }
+
+    #[tokio::test]
+    async fn compare_and_swap_with_etags() {
+        use std::sync::Arc;
+
+        use crate::{
+            fs::{Fs, OpenOptions},
+            path::Path,
+            remotes::aws::mock::MockS3,
+            Error, Read, Write,
+        };
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let path = Path::parse("manifest").unwrap();
+        server.put("manifest", "v1");
+
+        let mut file = s3.open(&path).await.unwrap();
+        assert_eq!(file.etag(), None);
+        let (result, _) = file.read_to_end_at(Vec::new(), 0).await;
+        result.unwrap();
+        let v1 = file.etag().unwrap();
+
+        let options = OpenOptions::default().create(true).truncate(true);
+        let mut update = s3
+            .open_options(&path, options.clone())
+            .await
+            .unwrap()
+            .if_match(&v1);
+        let (result, _) = update.write_all(&b"v2"[..]).await;
+        result.unwrap();
+        update.close().await.unwrap();
+        let v2 = update.etag().unwrap();
+        assert_ne!(v1, v2);
+
+        // a writer that read v1 as well lost the race
+        let mut stale = s3.open_options(&path, options).await.unwrap().if_match(&v1);
+        let (result, _) = stale.write_all(&b"v3"[..]).await;
+        result.unwrap();
+        assert!(matches!(
+            stale.close().await,
+            Err(Error::PreconditionFailed { .. })
+        ));
+        assert_eq!(server.get("manifest").unwrap(), &b"v2"[..]);
+
+        let mut file = s3.open(&path).await.unwrap().if_match(&v1);
+        let (result, _) = file.read_to_end_at(Vec::new(), 0).await;
+        assert!(matches!(result, Err(Error::PreconditionFailed { .. })));
+        let file = s3.open(&path).await.unwrap().if_none_match(&v2);
+        assert!(matches!(
+            file.size().await,
+            Err(Error::PreconditionFailed { .. })
+        ));
+        let file = s3.open(&path).await.unwrap().if_match(&v2);
+        assert_eq!(file.size().await.unwrap(), 2);
+        assert_eq!(file.etag(), Some(v2));
+    }
+
+    #[tokio::test]
+    async fn reports_s3_error_codes() {
+        use std::{io, sync::Arc};
+
+        use http::StatusCode;
+
+        use crate::{
+            fs::Fs,
+            path::Path,
+            remotes::{
+                aws::{
+                    mock::{MockS3, REQUEST_ID},
+                    S3Error,
+                },
+                http::HttpError,
+            },
+            Error, Read,
+        };
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let path = Path::parse("missing").unwrap();
+        let mut file = s3.open(&path).await.unwrap();
+
+        // missing objects are reported like missing local files, with the S3 error as the source
+        let source = |error: Error| match error {
+            Error::Io(error) if error.kind() == io::ErrorKind::NotFound => {
+                error.into_inner().unwrap().downcast::<S3Error>().unwrap()
+            }
+            error => panic!("unexpected error: {error:?}"),
+        };
+        let (result, _) = file.read_to_end_at(Vec::new(), 0).await;
+        match *source(result.unwrap_err()) {
+            S3Error::Service {
+                status,
+                code,
+                request_id,
+                ..
+            } => {
+                assert_eq!(status, StatusCode::NOT_FOUND);
+                assert_eq!(code, "NoSuchKey");
+                assert_eq!(request_id, REQUEST_ID);
+            }
+            error => panic!("unexpected error: {error:?}"),
+        }
+        // HEAD responses carry no error body
+        for error in [
+            source(file.size().await.unwrap_err()),
+            source(s3.stat(&path).await.unwrap_err()),
+        ] {
+            assert!(matches!(
+                *error,
+                S3Error::HttpError(HttpError::HttpNotSuccess {
+                    status: StatusCode::NOT_FOUND,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_past_the_end() {
+        use std::sync::Arc;
+
+        use crate::{fs::Fs, path::Path, remotes::aws::mock::MockS3, Error, Read};
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        server.put("hello", &b"hello, world"[..]);
+        let mut file = s3.open(&Path::parse("hello").unwrap()).await.unwrap();
+
+        let (result, buf) = file.read_exact_at(vec![0u8; 5], 7).await;
+        result.unwrap();
+        assert_eq!(buf, b"world");
+        let (result, _) = file.read_exact_at(vec![0u8; 8], 7).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            Error::UnexpectedEof {
+                requested: 8,
+                available: 5
+            }
+        ));
+        let (result, _) = file.read_exact_at(vec![0u8; 8], 20).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            Error::UnexpectedEof {
+                requested: 8,
+                available: 0
+            }
+        ));
+
+        let (result, buf) = file.read_at(vec![0u8; 8], 7).await;
+        assert_eq!(result.unwrap(), 5);
+        assert_eq!(&buf[..5], b"world");
+        let (result, _) = file.read_at(vec![0u8; 8], 12).await;
+        assert_eq!(result.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn read_batch_issues_range_requests() {
+        use std::sync::Arc;
+
+        use http::Method;
+
+        use crate::{fs::Fs, path::Path, remotes::aws::mock::MockS3, Error, Read};
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        server.put("hello", &b"hello, world"[..]);
+        let mut file = s3.open(&Path::parse("hello").unwrap()).await.unwrap();
+
+        let bufs = file.read_batch(vec![(7, 5), (0, 5)]).await.unwrap();
+        assert_eq!(bufs, vec![b"world".to_vec(), b"hello".to_vec()]);
+        let gets = server
+            .requests()
+            .into_iter()
+            .filter(|(method, _)| method == Method::GET)
+            .count();
+        assert_eq!(gets, 2);
+
+        assert!(matches!(
+            file.read_batch(vec![(0, 5), (10, 5)]).await,
+            Err(Error::UnexpectedEof {
+                requested: 5,
+                available: 2
+            })
+        ));
+    }
 }
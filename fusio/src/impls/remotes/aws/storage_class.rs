@@ -0,0 +1,311 @@
+//! Storage classes of S3 objects, and restoring archived objects from the Glacier tiers.
+//!
+//! Objects in the `GLACIER` and `DEEP_ARCHIVE` classes, and objects that Intelligent-Tiering moved
+//! to an archive tier, cannot be read until a temporary copy is restored with
+//! [`AmazonS3::restore`], which takes minutes to hours. Reads of such objects fail with
+//! [`Error::NotRestored`], and [`AmazonS3::restore_status`] polls the progress of a restore.
+
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use chrono::DateTime;
+use http::{Method, Request, StatusCode};
+use http_body_util::{BodyExt, Empty, Full};
+use serde::Serialize;
+
+use super::{
+    arn::copy_source, fs::AmazonS3, object_url, response_error, unexpected_response, S3Error,
+};
+use crate::{path::Path, remotes::http::HttpError, Error};
+
+const STORAGE_CLASS_HEADER: &str = "x-amz-storage-class";
+const RESTORE_HEADER: &str = "x-amz-restore";
+const ARCHIVE_STATUS_HEADER: &str = "x-amz-archive-status";
+
+/// The storage class of an S3 object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageClass {
+    Standard,
+    ReducedRedundancy,
+    StandardIa,
+    OnezoneIa,
+    IntelligentTiering,
+    /// Glacier Instant Retrieval, readable without a restore.
+    GlacierIr,
+    /// Glacier Flexible Retrieval, has to be restored before it is read.
+    Glacier,
+    /// Glacier Deep Archive, has to be restored before it is read.
+    DeepArchive,
+}
+
+impl StorageClass {
+    /// The name S3 uses for the class, e.g. `STANDARD_IA`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StorageClass::Standard => "STANDARD",
+            StorageClass::ReducedRedundancy => "REDUCED_REDUNDANCY",
+            StorageClass::StandardIa => "STANDARD_IA",
+            StorageClass::OnezoneIa => "ONEZONE_IA",
+            StorageClass::IntelligentTiering => "INTELLIGENT_TIERING",
+            StorageClass::GlacierIr => "GLACIER_IR",
+            StorageClass::Glacier => "GLACIER",
+            StorageClass::DeepArchive => "DEEP_ARCHIVE",
+        }
+    }
+}
+
+/// How fast, and at which cost, an archived object is restored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RestoreTier {
+    /// Within minutes, not available for `DEEP_ARCHIVE`.
+    Expedited,
+    /// Within hours.
+    #[default]
+    Standard,
+    /// Within hours to a couple of days, the cheapest.
+    Bulk,
+}
+
+impl RestoreTier {
+    fn as_str(self) -> &'static str {
+        match self {
+            RestoreTier::Expedited => "Expedited",
+            RestoreTier::Standard => "Standard",
+            RestoreTier::Bulk => "Bulk",
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct RestoreRequest {
+    days: u32,
+    glacier_job_parameters: GlacierJobParameters,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct GlacierJobParameters {
+    tier: &'static str,
+}
+
+/// Whether an object can be read, see [`AmazonS3::restore_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreStatus {
+    /// The object is not archived.
+    Available,
+    /// The object is archived and no restored copy exists.
+    Archived,
+    /// A restore was requested and has not completed yet.
+    InProgress,
+    /// A restored copy can be read until `expiry`.
+    Restored { expiry: Option<SystemTime> },
+}
+
+impl RestoreStatus {
+    /// Whether the object can be read now.
+    pub fn is_readable(self) -> bool {
+        matches!(
+            self,
+            RestoreStatus::Available | RestoreStatus::Restored { .. }
+        )
+    }
+}
+
+/// Parses the `x-amz-restore` header, e.g.
+/// `ongoing-request="false", expiry-date="Fri, 21 Dec 2012 00:00:00 GMT"`.
+fn parse_restore(header: &str) -> Option<RestoreStatus> {
+    let field = |name: &str| {
+        let start = header.find(&format!("{name}=\""))? + name.len() + 2;
+        let len = header[start..].find('"')?;
+        Some(&header[start..start + len])
+    };
+    match field("ongoing-request")? {
+        "true" => Some(RestoreStatus::InProgress),
+        "false" => Some(RestoreStatus::Restored {
+            expiry: field("expiry-date")
+                .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                .map(SystemTime::from),
+        }),
+        _ => None,
+    }
+}
+
+impl AmazonS3 {
+    /// Moves the object at `path` to `class` by copying it onto itself, keeping its metadata.
+    ///
+    /// Archived objects have to be restored first, and S3 copies objects of up to 5 GiB in a
+    /// single request, larger ones are rejected.
+    pub async fn set_storage_class(&self, path: &Path, class: StorageClass) -> Result<(), Error> {
+        let options = &self.as_ref().options;
+        let source = copy_source(&options.bucket, path);
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(object_url(&options.endpoint, path))
+            .header("x-amz-copy-source", source)
+            .header("x-amz-metadata-directive", "COPY")
+            .header(STORAGE_CLASS_HEADER, class.as_str())
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        if !response.status().is_success() {
+            return Err(unexpected_response(response).await);
+        }
+        Ok(())
+    }
+
+    /// Requests a temporary copy of the archived object at `path` that can be read for `days`
+    /// days once the restore completed, see [`AmazonS3::restore_status`]. Requesting a restore
+    /// that is already in progress succeeds, requesting it for a restored copy extends it.
+    pub async fn restore(&self, path: &Path, days: u32, tier: RestoreTier) -> Result<(), Error> {
+        let url = format!(
+            "{}?restore",
+            object_url(&self.as_ref().options.endpoint, path)
+        );
+        let body = quick_xml::se::to_string(&RestoreRequest {
+            days,
+            glacier_job_parameters: GlacierJobParameters {
+                tier: tier.as_str(),
+            },
+        })
+        .map_err(S3Error::from)?;
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+
+        let (parts, content) = response.into_parts();
+        let content = content.collect().await.map_err(S3Error::from)?.to_bytes();
+        let error = response_error(parts.status, &parts.headers, &content);
+        if parts.status == StatusCode::CONFLICT && error.code() == Some("RestoreAlreadyInProgress")
+        {
+            return Ok(());
+        }
+        Err(error.into())
+    }
+
+    /// Whether the object at `path` is archived and how far its restore got.
+    pub async fn restore_status(&self, path: &Path) -> Result<RestoreStatus, Error> {
+        let request = Request::builder()
+            .method(Method::HEAD)
+            .uri(object_url(&self.as_ref().options.endpoint, path))
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| S3Error::from(HttpError::from(e)))?;
+        let response = self.as_ref().send_request(request).await?;
+        if !response.status().is_success() {
+            return Err(unexpected_response(response).await);
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        if let Some(status) = header(RESTORE_HEADER).and_then(parse_restore) {
+            return Ok(status);
+        }
+        let archived = matches!(
+            header(STORAGE_CLASS_HEADER),
+            Some("GLACIER" | "DEEP_ARCHIVE")
+        ) || header(ARCHIVE_STATUS_HEADER).is_some();
+        Ok(match archived {
+            true => RestoreStatus::Archived,
+            false => RestoreStatus::Available,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{parse_restore, RestoreStatus};
+
+    #[test]
+    fn parses_restore_header() {
+        assert_eq!(
+            parse_restore("ongoing-request=\"true\""),
+            Some(RestoreStatus::InProgress)
+        );
+        assert_eq!(
+            parse_restore(
+                "ongoing-request=\"false\", expiry-date=\"Fri, 21 Dec 2012 00:00:00 GMT\""
+            ),
+            Some(RestoreStatus::Restored {
+                expiry: Some(UNIX_EPOCH + Duration::from_secs(1_356_048_000))
+            })
+        );
+        assert_eq!(parse_restore("garbage"), None);
+    }
+
+    #[test]
+    fn serializes_restore_request() {
+        use super::{GlacierJobParameters, RestoreRequest};
+
+        let request = RestoreRequest {
+            days: 7,
+            glacier_job_parameters: GlacierJobParameters { tier: "Bulk" },
+        };
+        assert_eq!(
+            quick_xml::se::to_string(&request).unwrap(),
+            concat!(
+                "<RestoreRequest><Days>7</Days>",
+                "<GlacierJobParameters><Tier>Bulk</Tier></GlacierJobParameters>",
+                "</RestoreRequest>"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn restores_archived_objects() {
+        use std::sync::Arc;
+
+        use super::{RestoreTier, StorageClass};
+        use crate::{fs::Fs, path::Path, remotes::aws::mock::MockS3, Error, Read};
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let path = Path::parse("logs/2012.log").unwrap();
+        server.put("logs/2012.log", &b"archived"[..]);
+
+        s3.set_storage_class(&path, StorageClass::Glacier)
+            .await
+            .unwrap();
+        assert_eq!(server.get("logs/2012.log").unwrap(), &b"archived"[..]);
+        assert_eq!(
+            s3.restore_status(&path).await.unwrap(),
+            RestoreStatus::Archived
+        );
+        let mut file = s3.open(&path).await.unwrap();
+        let (result, _) = file.read_to_end_at(Vec::new(), 0).await;
+        assert!(matches!(result, Err(Error::NotRestored { path: p }) if p == path));
+
+        s3.restore(&path, 1, RestoreTier::Bulk).await.unwrap();
+        s3.restore(&path, 1, RestoreTier::Bulk).await.unwrap();
+        assert_eq!(
+            s3.restore_status(&path).await.unwrap(),
+            RestoreStatus::InProgress
+        );
+
+        server.complete_restore("logs/2012.log");
+        let status = s3.restore_status(&path).await.unwrap();
+        assert!(status.is_readable());
+        let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+        result.unwrap();
+        assert_eq!(buf, b"archived");
+
+        s3.set_storage_class(&path, StorageClass::Standard)
+            .await
+            .unwrap();
+        assert_eq!(
+            s3.restore_status(&path).await.unwrap(),
+            RestoreStatus::Available
+        );
+    }
+}
@@ -17,6 +17,8 @@ pub struct S3Writer {
     upload_id: Option<Arc<String>>,
     next_part_numer: usize,
     buf: BytesMut,
+    /// The ETag of the object once the upload completed.
+    etag: Option<String>,
 
     handlers: FuturesOrdered<Pin<Box<dyn MaybeSendFuture<Output = Result<MultipartPart, Error>>>>>,
 }
@@ -30,10 +32,16 @@ impl S3Writer {
             upload_id: None,
             next_part_numer: 0,
             buf: BytesMut::with_capacity(S3_PART_MINIMUM_SIZE),
+            etag: None,
             handlers: FuturesOrdered::new(),
         }
     }
 
+    /// The ETag of the uploaded object, once [`Write::close`] succeeded.
+    pub(crate) fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
     async fn upload_part<F>(&mut self, fn_bytes_init: F) -> Result<(), Error>
     where
         F: FnOnce() -> BytesMut,
@@ -86,13 +94,12 @@ impl Write for S3Writer {
 
     async fn close(&mut self) -> Result<(), Error> {
         let Some(upload_id) = self.upload_id.clone() else {
-            if !self.buf.is_empty() {
-                let bytes = mem::replace(&mut self.buf, BytesMut::new()).freeze();
-
-                self.inner
-                    .upload_once(bytes.len(), Full::new(bytes))
-                    .await?;
-            }
+            // also uploads an empty buffer, so that writing nothing creates an empty object
+            let bytes = mem::replace(&mut self.buf, BytesMut::new()).freeze();
+            self.etag = self
+                .inner
+                .upload_once(bytes.len(), Full::new(bytes))
+                .await?;
             return Ok(());
         };
         if !self.buf.is_empty() {
@@ -103,10 +110,19 @@ impl Write for S3Writer {
             parts.push(handle?);
         }
         assert_eq!(self.next_part_numer, parts.len());
-        self.inner.complete_part(&upload_id, &parts).await?;
+        self.etag = Some(self.inner.complete_part(&upload_id, &parts).await?);
 
         Ok(())
     }
+
+    /// Fails, like [`crate::remotes::aws::S3File`]: uploaded parts only become part of the
+    /// object when the upload completes on close, and data below the minimum part size cannot be
+    /// uploaded before.
+    async fn barrier(&mut self) -> Result<(), Error> {
+        Err(Error::Unsupported {
+            message: "Amazon S3 makes writes durable only when the object is closed".into(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -124,6 +140,7 @@ mod tests {
         use bytes::Bytes;
 
         use crate::{
+            clock::{OffsetClock, SystemClock},
             remotes::{
                 aws::{
                     fs::{AmazonS3, AmazonS3Inner},
@@ -140,6 +157,7 @@ mod tests {
         let region = "ap-southeast-2";
         let options = S3Options {
             endpoint: "endpoint".into(),
+            bucket: "bucket".into(),
             credential: Some(AwsCredential {
                 key_id: "key".to_string(),
                 secret_key: "secret_key".to_string(),
@@ -148,6 +166,8 @@ mod tests {
             region: region.into(),
             sign_payload: true,
             checksum: false,
+            clock: OffsetClock::new(Arc::new(SystemClock)),
+            signing_endpoint: None,
         };
         let client = crate::impls::remotes::http::tokio::TokioClient::new();
 
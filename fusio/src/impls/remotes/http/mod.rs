@@ -1,4 +1,5 @@
 mod error;
+mod signer;
 #[cfg(all(feature = "tokio-http", not(feature = "completion-based")))]
 pub mod tokio;
 
@@ -10,6 +11,7 @@ use futures_core::Stream;
 use http::{Request, Response};
 use http_body::Body;
 use http_body_util::BodyExt;
+pub use signer::{RequestSigner, SigningMode};
 
 use crate::{dynamic::MaybeSendFuture, error::BoxedError, MaybeSend, MaybeSync};
 
@@ -67,7 +69,7 @@ where
     }
 }
 
-impl HttpClient for Box<dyn DynHttpClient> {
+impl<'client> HttpClient for Box<dyn DynHttpClient + 'client> {
     type RespBody = BoxBody;
 
     async fn send_request<B>(
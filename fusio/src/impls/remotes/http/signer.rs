@@ -0,0 +1,67 @@
+use std::{future::Future, time::Duration};
+
+use bytes::Bytes;
+use http::Request;
+use http_body::Body;
+
+use crate::{MaybeSend, MaybeSync};
+
+/// Where a [`RequestSigner`] puts the signature of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningMode {
+    /// In the headers, e.g. `Authorization`, for requests sent right away.
+    Headers,
+    /// In the query string of the URI, e.g. `X-Amz-Signature`, valid for `expires_in`. The URI
+    /// can then be handed to clients that cannot set headers or must not hold credentials, like
+    /// browsers, CDNs or WebSocket clients.
+    Query { expires_in: Duration },
+}
+
+pub trait RequestSigner: MaybeSend + MaybeSync {
+    //! Authorizes outgoing HTTP requests for a remote storage service.
+    //!
+    //! Remote backends sign every request through this trait right before handing it to the
+    //! [`super::HttpClient`], so signing schemes (AWS SigV4, bearer tokens, shared keys, ...) can
+    //! be swapped without touching the code that builds requests. Implementations may need to
+    //! hash the payload, which is why the body must be cheaply [`Clone`].
+
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Signs `request` as `mode` asks, failing for modes the signing scheme does not support.
+    fn sign_with<B>(
+        &self,
+        request: &mut Request<B>,
+        mode: SigningMode,
+    ) -> impl Future<Output = Result<(), Self::Error>> + MaybeSend
+    where
+        B: Body<Data = Bytes> + Clone + Unpin + MaybeSend + MaybeSync,
+        B::Error: std::error::Error + Send + Sync + 'static;
+
+    /// Signs `request` in its headers.
+    fn sign<B>(
+        &self,
+        request: &mut Request<B>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + MaybeSend
+    where
+        B: Body<Data = Bytes> + Clone + Unpin + MaybeSend + MaybeSync,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        self.sign_with(request, SigningMode::Headers)
+    }
+}
+
+impl<S: RequestSigner> RequestSigner for &S {
+    type Error = S::Error;
+
+    fn sign_with<B>(
+        &self,
+        request: &mut Request<B>,
+        mode: SigningMode,
+    ) -> impl Future<Output = Result<(), Self::Error>> + MaybeSend
+    where
+        B: Body<Data = Bytes> + Clone + Unpin + MaybeSend + MaybeSync,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        S::sign_with(self, request, mode)
+    }
+}
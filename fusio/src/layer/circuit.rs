@@ -0,0 +1,623 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use async_stream::stream;
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use super::FsLayer;
+use crate::{
+    clock::{Clock, SystemClock},
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    DynFs, Error, IoBuf, IoBufMut, Read, Write,
+};
+
+const DEFAULT_THRESHOLD: u32 = 5;
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Whether a [`CircuitBreakerFs`] lets calls through, see [`CircuitBreakerFs::state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The backend is healthy and every call goes through.
+    Closed,
+    /// The backend failed too often, calls fail fast until `until`.
+    Open { until: SystemTime },
+    /// The cooldown passed and a single probe call is testing the backend.
+    HalfOpen,
+}
+
+enum Breaker {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: SystemTime,
+    },
+    /// A probe was let through, the next one is only allowed once `until` passed in case the
+    /// probe never finishes, e.g. because its future was dropped.
+    Probing {
+        until: SystemTime,
+    },
+}
+
+struct Circuit {
+    threshold: u32,
+    cooldown: Duration,
+    clock: Arc<dyn Clock>,
+    breaker: Mutex<Breaker>,
+}
+
+impl Circuit {
+    /// Lets a call through or fails it fast while the circuit is open.
+    fn admit(&self) -> Result<(), Error> {
+        let now = self.clock.now();
+        let mut breaker = self.breaker.lock().unwrap();
+        match *breaker {
+            Breaker::Closed { .. } => Ok(()),
+            Breaker::Open { until } | Breaker::Probing { until } if until <= now => {
+                *breaker = Breaker::Probing {
+                    until: now + self.cooldown,
+                };
+                Ok(())
+            }
+            Breaker::Open { .. } | Breaker::Probing { .. } => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!(
+                    "circuit breaker is open after {} consecutive backend failures",
+                    self.threshold
+                ),
+            )
+            .into()),
+        }
+    }
+
+    /// Counts the outcome of a call that was let through.
+    fn record<T>(&self, result: &Result<T, Error>) {
+        let failed = matches!(result, Err(e) if is_outage(e));
+        let mut breaker = self.breaker.lock().unwrap();
+        *breaker = match (failed, &*breaker) {
+            (false, _) => Breaker::Closed { failures: 0 },
+            (true, Breaker::Closed { failures }) if failures + 1 < self.threshold => {
+                Breaker::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (true, _) => Breaker::Open {
+                until: self.clock.now() + self.cooldown,
+            },
+        };
+    }
+
+    fn state(&self) -> CircuitState {
+        match *self.breaker.lock().unwrap() {
+            Breaker::Closed { .. } => CircuitState::Closed,
+            Breaker::Open { until } => CircuitState::Open { until },
+            Breaker::Probing { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+/// Whether `error` means the backend is unavailable rather than that it answered and rejected
+/// the call. Missing files, invalid input and the like show the backend is up.
+fn is_outage(error: &Error) -> bool {
+    match error {
+        Error::Io(e) => !matches!(
+            e.kind(),
+            io::ErrorKind::NotFound
+                | io::ErrorKind::AlreadyExists
+                | io::ErrorKind::PermissionDenied
+                | io::ErrorKind::InvalidInput
+                | io::ErrorKind::InvalidData
+                | io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::Unsupported
+        ),
+        #[cfg(feature = "aws")]
+        Error::Other(e) if e.is::<crate::remotes::aws::S3Error>() => {
+            is_s3_outage(e.downcast_ref().unwrap())
+        }
+        #[cfg(feature = "http")]
+        Error::Other(e) if e.is::<crate::remotes::http::HttpError>() => {
+            is_http_outage(e.downcast_ref().unwrap())
+        }
+        Error::Other(_) => true,
+        _ => false,
+    }
+}
+
+/// Server errors and failed requests are outages, other statuses are answers of the service.
+#[cfg(feature = "http")]
+fn is_http_outage(error: &crate::remotes::http::HttpError) -> bool {
+    match error {
+        crate::remotes::http::HttpError::HttpNotSuccess { status, .. } => status.is_server_error(),
+        _ => true,
+    }
+}
+
+/// S3 is unavailable when it answers with a server error, e.g. `SlowDown` or
+/// `InternalError`, or cannot be reached. Errors like `NoSuchKey` or `AccessDenied` are answers.
+#[cfg(feature = "aws")]
+fn is_s3_outage(error: &crate::remotes::aws::S3Error) -> bool {
+    use crate::remotes::aws::S3Error;
+
+    match error {
+        S3Error::Service { status, code, .. } => status.is_server_error() || code == "SlowDown",
+        S3Error::HttpError(e) => is_http_outage(e),
+        _ => false,
+    }
+}
+
+/// Stops sending calls to a backend that keeps failing, so callers get an error right away
+/// instead of waiting for timeouts while e.g. the region of a bucket is down.
+///
+/// After `threshold` consecutive failed calls the circuit opens and every call fails with
+/// [`io::ErrorKind::ConnectionRefused`] without reaching the backend. Once the cooldown passed,
+/// the next call is let through as a probe: if it succeeds the circuit closes again, otherwise it
+/// stays open for another cooldown. Calls on files opened through the layer count as well.
+///
+/// Only errors that suggest the backend is unavailable are counted, like I/O errors of the
+/// connection, requests that could not be sent and server errors of remote backends. A file that
+/// was not found or access that was denied is an answer of a healthy backend and resets the count
+/// like a success.
+pub struct CircuitBreakerFs {
+    inner: Box<dyn DynFs>,
+    circuit: Arc<Circuit>,
+}
+
+impl CircuitBreakerFs {
+    pub fn new<F>(fs: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            circuit: Arc::new(Circuit {
+                threshold: DEFAULT_THRESHOLD,
+                cooldown: DEFAULT_COOLDOWN,
+                clock: Arc::new(SystemClock),
+                breaker: Mutex::new(Breaker::Closed { failures: 0 }),
+            }),
+        }
+    }
+
+    /// Opens the circuit after `threshold` consecutive failures, at least one.
+    pub fn with_threshold(mut self, threshold: u32) -> Self {
+        self.configure(|circuit| circuit.threshold = threshold.max(1));
+        self
+    }
+
+    /// How long the circuit stays open before a probe is let through.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.configure(|circuit| circuit.cooldown = cooldown);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.configure(|circuit| circuit.clock = clock);
+        self
+    }
+
+    /// The current state of the circuit, e.g. for health endpoints.
+    pub fn state(&self) -> CircuitState {
+        self.circuit.state()
+    }
+
+    /// Replaces the circuit with a closed one configured by `configure`, files opened before
+    /// keep the old one.
+    fn configure(&mut self, configure: impl FnOnce(&mut Circuit)) {
+        let mut circuit = Circuit {
+            threshold: self.circuit.threshold,
+            cooldown: self.circuit.cooldown,
+            clock: self.circuit.clock.clone(),
+            breaker: Mutex::new(Breaker::Closed { failures: 0 }),
+        };
+        configure(&mut circuit);
+        self.circuit = Arc::new(circuit);
+    }
+}
+
+impl Fs for CircuitBreakerFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        self.circuit.admit()?;
+        let result = DynFs::open_options(self.inner.as_ref(), path, options).await;
+        self.circuit.record(&result);
+        Ok(Box::new(CircuitBreakerFile {
+            inner: result?,
+            circuit: self.circuit.clone(),
+        }))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        self.circuit.admit()?;
+        let result = DynFs::create_dir_all(self.inner.as_ref(), path).await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        self.circuit.admit()?;
+        let result = Fs::list(&self.inner, path).await;
+        self.circuit.record(&result);
+        Ok(self.recorded(result?))
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        self.circuit.admit()?;
+        let result = Fs::list_options(&self.inner, path, options).await;
+        self.circuit.record(&result);
+        Ok(self.recorded(result?))
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        self.circuit.admit()?;
+        let result = DynFs::remove(self.inner.as_ref(), path).await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        self.circuit.admit()?;
+        let result = DynFs::remove_batch(self.inner.as_ref(), paths).await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        self.circuit.admit()?;
+        let result = DynFs::copy(self.inner.as_ref(), from, to).await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        self.circuit.admit()?;
+        let result = DynFs::rename_new(self.inner.as_ref(), from, to).await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        self.circuit.admit()?;
+        let result = DynFs::stat(self.inner.as_ref(), path).await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        self.circuit.admit()?;
+        let result = DynFs::create_temp(self.inner.as_ref(), prefix).await;
+        self.circuit.record(&result);
+        let (file, guard) = result?;
+        let file: Self::File = Box::new(CircuitBreakerFile {
+            inner: file,
+            circuit: self.circuit.clone(),
+        });
+        Ok((file, guard))
+    }
+
+    /// Shuts the backend down even while the circuit is open.
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        self.circuit.admit()?;
+        let result = DynFs::watch(self.inner.as_ref(), path, options).await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+impl CircuitBreakerFs {
+    /// Counts failures of listings that break off after they started.
+    fn recorded<S>(&self, listing: S) -> impl Stream<Item = Result<FileMeta, Error>>
+    where
+        S: Stream<Item = Result<FileMeta, Error>>,
+    {
+        let circuit = self.circuit.clone();
+        stream! {
+            let mut listing = std::pin::pin!(listing);
+            while let Some(meta) = listing.next().await {
+                if meta.is_err() {
+                    circuit.record(&meta);
+                }
+                yield meta;
+            }
+        }
+    }
+}
+
+/// A file opened through a [`CircuitBreakerFs`], whose calls are admitted and counted by the
+/// circuit of the file system.
+struct CircuitBreakerFile {
+    inner: Box<dyn DynFile>,
+    circuit: Arc<Circuit>,
+}
+
+impl Read for CircuitBreakerFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        if let Err(e) = self.circuit.admit() {
+            return (Err(e), buf);
+        }
+        let (result, buf) = self.inner.read_exact_at(buf, pos).await;
+        self.circuit.record(&result);
+        (result, buf)
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        if let Err(e) = self.circuit.admit() {
+            return (Err(e), buf);
+        }
+        let (result, buf) = self.inner.read_to_end_at(buf, pos).await;
+        self.circuit.record(&result);
+        (result, buf)
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        self.circuit.admit()?;
+        let result = self.inner.size().await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        if let Err(e) = self.circuit.admit() {
+            return (Err(e), buf);
+        }
+        let (result, buf) = self.inner.read_at(buf, pos).await;
+        self.circuit.record(&result);
+        (result, buf)
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        self.circuit.admit()?;
+        let result = self.inner.read_batch(ranges).await;
+        self.circuit.record(&result);
+        result
+    }
+}
+
+impl Write for CircuitBreakerFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        if let Err(e) = self.circuit.admit() {
+            return (Err(e), buf);
+        }
+        let (result, buf) = self.inner.write_all(buf).await;
+        self.circuit.record(&result);
+        (result, buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.circuit.admit()?;
+        let result = self.inner.flush().await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        self.circuit.admit()?;
+        let result = self.inner.close().await;
+        self.circuit.record(&result);
+        result
+    }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        if let Err(e) = self.circuit.admit() {
+            return (Err(e), bufs);
+        }
+        let (result, bufs) = self.inner.write_batch(bufs).await;
+        self.circuit.record(&result);
+        (result, bufs)
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        self.circuit.admit()?;
+        let result = self.inner.barrier().await;
+        self.circuit.record(&result);
+        result
+    }
+}
+
+/// Wraps a stack in a [`CircuitBreakerFs`], see [`super::Stack::layer_dyn`].
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerLayer {
+    pub threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerLayer {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+}
+
+impl FsLayer for CircuitBreakerLayer {
+    fn layer(&self, inner: Box<dyn DynFs>) -> Box<dyn DynFs> {
+        Box::new(
+            CircuitBreakerFs::new(inner)
+                .with_threshold(self.threshold)
+                .with_cooldown(self.cooldown),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn fails_fast_while_the_backend_is_down() {
+        use std::{
+            io,
+            sync::{
+                atomic::{AtomicBool, AtomicUsize, Ordering},
+                Arc,
+            },
+            time::Duration,
+        };
+
+        use futures_core::Stream;
+        use tempfile::TempDir;
+
+        use super::{CircuitBreakerFs, CircuitState};
+        use crate::{
+            clock::{OffsetClock, SystemClock},
+            disk::TokioFs,
+            fs::{FileMeta, Fs, OpenOptions},
+            path::Path,
+            Error,
+        };
+
+        /// Refuses every call while `down` is set, and counts the calls that reached it.
+        #[derive(Clone, Default)]
+        struct Flaky {
+            down: Arc<AtomicBool>,
+            calls: Arc<AtomicUsize>,
+        }
+
+        impl Flaky {
+            fn call(&self) -> Result<(), Error> {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                match self.down.load(Ordering::SeqCst) {
+                    true => Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+                    false => Ok(()),
+                }
+            }
+        }
+
+        impl Fs for Flaky {
+            type File = <TokioFs as Fs>::File;
+
+            async fn open_options(
+                &self,
+                path: &Path,
+                options: OpenOptions,
+            ) -> Result<Self::File, Error> {
+                self.call()?;
+                TokioFs.open_options(path, options).await
+            }
+
+            async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+                self.call()?;
+                TokioFs.create_dir_all(path).await
+            }
+
+            async fn list(
+                &self,
+                path: &Path,
+            ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+                self.call()?;
+                TokioFs.list(path).await
+            }
+
+            async fn remove(&self, path: &Path) -> Result<(), Error> {
+                self.call()?;
+                TokioFs.remove(path).await
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("file")).unwrap();
+        let missing = Path::from_absolute_path(dir.path().join("missing")).unwrap();
+        let backend = Flaky::default();
+        let clock = Arc::new(OffsetClock::new(SystemClock));
+        let fs = CircuitBreakerFs::new(backend.clone())
+            .with_threshold(3)
+            .with_cooldown(Duration::from_secs(10))
+            .with_clock(clock.clone());
+
+        fs.open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        // missing files are answers of a healthy backend
+        for _ in 0..5 {
+            assert!(fs.open(&missing).await.is_err());
+        }
+        assert_eq!(fs.state(), CircuitState::Closed);
+
+        backend.down.store(true, Ordering::SeqCst);
+        for _ in 0..3 {
+            assert!(fs.open(&path).await.is_err());
+        }
+        assert!(matches!(fs.state(), CircuitState::Open { .. }));
+        let calls = backend.calls.load(Ordering::SeqCst);
+        let result = fs.open(&path).await;
+        assert!(
+            matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::ConnectionRefused)
+        );
+        assert_eq!(backend.calls.load(Ordering::SeqCst), calls);
+
+        // a failed probe keeps the circuit open for another cooldown
+        clock.set_offset_millis(10_000);
+        assert!(fs.open(&path).await.is_err());
+        assert_eq!(backend.calls.load(Ordering::SeqCst), calls + 1);
+        assert!(matches!(fs.state(), CircuitState::Open { .. }));
+
+        backend.down.store(false, Ordering::SeqCst);
+        assert!(fs.open(&path).await.is_err());
+        clock.set_offset_millis(20_000);
+        assert!(fs.open(&path).await.is_ok());
+        assert_eq!(fs.state(), CircuitState::Closed);
+    }
+
+    #[cfg(feature = "aws")]
+    #[test]
+    fn counts_only_s3_outages() {
+        use http::StatusCode;
+
+        use super::is_outage;
+        use crate::{
+            remotes::{aws::S3Error, http::HttpError},
+            Error,
+        };
+
+        let service = |status, code: &str| {
+            Error::from(S3Error::Service {
+                status,
+                code: code.into(),
+                message: String::new(),
+                request_id: String::new(),
+            })
+        };
+        assert!(!is_outage(&service(StatusCode::NOT_FOUND, "NoSuchKey")));
+        assert!(!is_outage(&service(StatusCode::FORBIDDEN, "AccessDenied")));
+        assert!(is_outage(&service(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "SlowDown"
+        )));
+        assert!(is_outage(&service(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError"
+        )));
+
+        let status = |status| {
+            Error::from(S3Error::from(HttpError::HttpNotSuccess {
+                status,
+                body: String::new(),
+            }))
+        };
+        assert!(!is_outage(&status(StatusCode::FORBIDDEN)));
+        assert!(is_outage(&status(StatusCode::BAD_GATEWAY)));
+        let transport = HttpError::Other("connection reset".into());
+        assert!(is_outage(&Error::from(S3Error::from(transport))));
+    }
+}
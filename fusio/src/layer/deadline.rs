@@ -0,0 +1,456 @@
+use std::{future::Future, io, pin::pin, sync::Arc};
+
+use futures_core::Stream;
+use futures_util::future::{select, Either};
+
+use super::FsLayer;
+use crate::{
+    clock::{Clock, SystemClock},
+    context::Context,
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    task::Timer,
+    DynFs, Error, IoBuf, IoBufMut, Read, Write,
+};
+
+/// Fails calls whose [`Context`] deadline passed, so work for a statement that was already given
+/// up on does not keep the backend busy.
+///
+/// The deadline is checked when a call starts. With a [`Timer`], see [`DeadlineFs::with_timer`],
+/// calls are also given up once the time left when they started runs out, which drops the call
+/// to the backend. Reads and writes that own a buffer, like [`Read::read_exact_at`] and
+/// [`Write::write_all`], are only checked when they start, as a call given up on could not hand
+/// its buffer back. Listings are bounded until the listing is opened. Calls outside of a
+/// [`Context::scope`], or in a context without a deadline, are forwarded as is. Expired calls fail
+/// with [`std::io::ErrorKind::TimedOut`].
+pub struct DeadlineFs {
+    inner: Box<dyn DynFs>,
+    clock: Arc<dyn Clock>,
+    timer: Option<Arc<dyn Timer>>,
+}
+
+impl DeadlineFs {
+    pub fn new<F>(fs: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            clock: Arc::new(SystemClock),
+            timer: None,
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Gives up on calls still running when the deadline passes, waiting with `timer`.
+    pub fn with_timer(mut self, timer: Arc<dyn Timer>) -> Self {
+        self.timer = Some(timer);
+        self
+    }
+
+    fn file(&self, inner: Box<dyn DynFile>) -> Box<dyn DynFile> {
+        Box::new(DeadlineFile {
+            inner,
+            clock: self.clock.clone(),
+            timer: self.timer.clone(),
+        })
+    }
+}
+
+fn check(clock: &dyn Clock) -> Result<(), Error> {
+    match Context::current() {
+        Some(context) => context.check_deadline(clock.now()),
+        None => Ok(()),
+    }
+}
+
+/// Runs `call` after checking the deadline and, with a timer, fails once the time left runs out.
+async fn bounded<T, C>(clock: &dyn Clock, timer: Option<&dyn Timer>, call: C) -> Result<T, Error>
+where
+    C: Future<Output = Result<T, Error>>,
+{
+    let remaining = match Context::current() {
+        Some(context) => {
+            let now = clock.now();
+            context.check_deadline(now)?;
+            context.remaining(now)
+        }
+        None => None,
+    };
+    let (Some(remaining), Some(timer)) = (remaining, timer) else {
+        return call.await;
+    };
+    match select(pin!(call), timer.sleep(remaining)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "deadline exceeded").into())
+        }
+    }
+}
+
+impl Fs for DeadlineFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        let file = bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            DynFs::open_options(self.inner.as_ref(), path, options),
+        )
+        .await?;
+        Ok(self.file(file))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            DynFs::create_dir_all(self.inner.as_ref(), path),
+        )
+        .await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            Fs::list(&self.inner, path),
+        )
+        .await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            Fs::list_options(&self.inner, path, options),
+        )
+        .await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            DynFs::remove(self.inner.as_ref(), path),
+        )
+        .await
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            DynFs::remove_batch(self.inner.as_ref(), paths),
+        )
+        .await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            DynFs::copy(self.inner.as_ref(), from, to),
+        )
+        .await
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            DynFs::rename_new(self.inner.as_ref(), from, to),
+        )
+        .await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            DynFs::stat(self.inner.as_ref(), path),
+        )
+        .await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let (file, guard) = bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            DynFs::create_temp(self.inner.as_ref(), prefix),
+        )
+        .await?;
+        Ok((self.file(file), guard))
+    }
+
+    /// Shuts the backend down regardless of any deadline.
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            DynFs::watch(self.inner.as_ref(), path, options),
+        )
+        .await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+/// A file opened through a [`DeadlineFs`], checking the deadline of the caller on every call.
+struct DeadlineFile {
+    inner: Box<dyn DynFile>,
+    clock: Arc<dyn Clock>,
+    timer: Option<Arc<dyn Timer>>,
+}
+
+impl Read for DeadlineFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        if let Err(e) = check(self.clock.as_ref()) {
+            return (Err(e), buf);
+        }
+        self.inner.read_exact_at(buf, pos).await
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        if let Err(e) = check(self.clock.as_ref()) {
+            return (Err(e), buf);
+        }
+        self.inner.read_to_end_at(buf, pos).await
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            self.inner.size(),
+        )
+        .await
+    }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        if let Err(e) = check(self.clock.as_ref()) {
+            return (Err(e), buf);
+        }
+        self.inner.read_at(buf, pos).await
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            self.inner.read_batch(ranges),
+        )
+        .await
+    }
+}
+
+impl Write for DeadlineFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        if let Err(e) = check(self.clock.as_ref()) {
+            return (Err(e), buf);
+        }
+        self.inner.write_all(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            self.inner.flush(),
+        )
+        .await
+    }
+
+    /// Closes the file even past the deadline, so buffered data is not lost and the file is not
+    /// left open.
+    async fn close(&mut self) -> Result<(), Error> {
+        self.inner.close().await
+    }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        if let Err(e) = check(self.clock.as_ref()) {
+            return (Err(e), bufs);
+        }
+        self.inner.write_batch(bufs).await
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        bounded(
+            self.clock.as_ref(),
+            self.timer.as_deref(),
+            self.inner.barrier(),
+        )
+        .await
+    }
+}
+
+/// Wraps a stack in a [`DeadlineFs`], see [`super::Stack::layer_dyn`].
+#[derive(Default, Clone)]
+pub struct DeadlineLayer {
+    timer: Option<Arc<dyn Timer>>,
+}
+
+impl DeadlineLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// See [`DeadlineFs::with_timer`].
+    pub fn with_timer(mut self, timer: Arc<dyn Timer>) -> Self {
+        self.timer = Some(timer);
+        self
+    }
+}
+
+impl FsLayer for DeadlineLayer {
+    fn layer(&self, inner: Box<dyn DynFs>) -> Box<dyn DynFs> {
+        let fs = DeadlineFs::new(inner);
+        Box::new(match &self.timer {
+            Some(timer) => fs.with_timer(timer.clone()),
+            None => fs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn rejects_calls_past_the_deadline() {
+        use std::{io, sync::Arc, time::Duration};
+
+        use tempfile::TempDir;
+
+        use super::DeadlineFs;
+        use crate::{
+            clock::{Clock, OffsetClock, SystemClock},
+            context::Context,
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+            Error, Read,
+        };
+
+        fn is_timed_out<T>(result: Result<T, Error>) -> bool {
+            matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::TimedOut)
+        }
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("file")).unwrap();
+        let clock = Arc::new(OffsetClock::new(SystemClock));
+        let fs = DeadlineFs::new(TokioFs).with_clock(clock.clone());
+        fs.open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+
+        let context = Context::default().with_deadline(clock.now() + Duration::from_secs(10));
+        context
+            .scope(async {
+                let mut file = fs.open(&path).await.unwrap();
+                let (result, _) = file.read_to_end_at(Vec::new(), 0).await;
+                result.unwrap();
+
+                clock.set_offset_millis(10_000);
+                assert!(is_timed_out(file.size().await));
+                assert!(is_timed_out(fs.open(&path).await));
+            })
+            .await;
+        // outside of the scope nothing expires
+        assert!(fs.open(&path).await.is_ok());
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn gives_up_on_slow_calls_at_the_deadline() {
+        use std::{
+            io,
+            sync::Arc,
+            time::{Duration, Instant},
+        };
+
+        use futures_core::Stream;
+        use tempfile::TempDir;
+
+        use super::DeadlineFs;
+        use crate::{
+            clock::{Clock, SystemClock},
+            context::Context,
+            disk::TokioFs,
+            fs::{FileMeta, Fs, OpenOptions},
+            path::Path,
+            task::TokioTimer,
+            Error,
+        };
+
+        /// Takes far longer than any deadline to stat a file.
+        struct Slow;
+
+        impl Fs for Slow {
+            type File = <TokioFs as Fs>::File;
+
+            async fn open_options(
+                &self,
+                path: &Path,
+                options: OpenOptions,
+            ) -> Result<Self::File, Error> {
+                TokioFs.open_options(path, options).await
+            }
+
+            async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+                TokioFs.create_dir_all(path).await
+            }
+
+            async fn list(
+                &self,
+                path: &Path,
+            ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+                TokioFs.list(path).await
+            }
+
+            async fn remove(&self, path: &Path) -> Result<(), Error> {
+                TokioFs.remove(path).await
+            }
+
+            async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                TokioFs.stat(path).await
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("file")).unwrap();
+        let fs = DeadlineFs::new(Slow).with_timer(Arc::new(TokioTimer));
+        fs.open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+
+        let context =
+            Context::default().with_deadline(SystemClock.now() + Duration::from_millis(50));
+        let started = Instant::now();
+        let result = context.scope(fs.stat(&path)).await;
+        assert!(matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // without a deadline the call runs to completion
+        let fs = DeadlineFs::new(TokioFs).with_timer(Arc::new(TokioTimer));
+        assert!(fs.stat(&path).await.is_ok());
+    }
+}
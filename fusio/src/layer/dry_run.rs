@@ -0,0 +1,262 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use futures_core::Stream;
+
+use crate::{
+    dynamic::DynFile,
+    fs::{temp_path, FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    DynFs, Error, IoBuf, IoBufMut, Read, Write,
+};
+
+/// A change a [`DryRunFs`] skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Remove {
+        path: Path,
+    },
+    CreateDirAll {
+        path: Path,
+    },
+    /// A file opened for writing was closed after `len` bytes were written to it.
+    Write {
+        path: Path,
+        len: u64,
+    },
+    Copy {
+        from: Path,
+        to: Path,
+    },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Remove { path } => write!(f, "remove {path}"),
+            Action::CreateDirAll { path } => write!(f, "create directory {path}"),
+            Action::Write { path, len } => write!(f, "write {len} bytes to {path}"),
+            Action::Copy { from, to } => write!(f, "copy {from} to {to}"),
+        }
+    }
+}
+
+/// Previews what a job would change without changing anything, e.g. to show which files a sync
+/// or a garbage collection would write or remove before running it for real.
+///
+/// Reads and listings go to the backend. Removals, directory creations, copies and files opened
+/// for writing succeed without reaching it and are recorded as [`Action`]s instead, in the order
+/// they happened. Writes are only counted, so files opened for writing cannot be read back, and
+/// temporary files are not created either.
+pub struct DryRunFs {
+    inner: Box<dyn DynFs>,
+    actions: Arc<Mutex<Vec<Action>>>,
+}
+
+impl DryRunFs {
+    pub fn new<F>(fs: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            actions: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// The changes skipped so far.
+    pub fn actions(&self) -> Vec<Action> {
+        self.actions.lock().unwrap().clone()
+    }
+
+    fn record(&self, action: Action) {
+        self.actions.lock().unwrap().push(action);
+    }
+}
+
+impl Fs for DryRunFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        if !options.write {
+            return DynFs::open_options(self.inner.as_ref(), path, options).await;
+        }
+        Ok(Box::new(DryRunFile {
+            path: path.clone(),
+            len: 0,
+            actions: self.actions.clone(),
+        }))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        self.record(Action::CreateDirAll { path: path.clone() });
+        Ok(())
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list(&self.inner, path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list_options(&self.inner, path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        self.record(Action::Remove { path: path.clone() });
+        Ok(())
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        for path in paths {
+            self.record(Action::Remove { path });
+        }
+        Ok(Vec::new())
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        self.record(Action::Copy {
+            from: from.clone(),
+            to: to.clone(),
+        });
+        Ok(())
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        DynFs::stat(self.inner.as_ref(), path).await
+    }
+
+    /// Hands out a file opened for writing under a fresh name instead of creating it on the
+    /// backend, so the guard has nothing to remove on drop.
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let path = temp_path(prefix)?;
+        let options = OpenOptions::default().create(true).truncate(true);
+        let file = Fs::open_options(self, &path, options).await?;
+        Ok((file, TempGuard::new(path)))
+    }
+
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.inner.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+/// A file opened for writing through a [`DryRunFs`], counting what is written to it.
+struct DryRunFile {
+    path: Path,
+    len: u64,
+    actions: Arc<Mutex<Vec<Action>>>,
+}
+
+fn unreadable() -> Error {
+    Error::Unsupported {
+        message: "files opened for writing in a dry run cannot be read".into(),
+    }
+}
+
+impl Read for DryRunFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, _pos: u64) -> (Result<(), Error>, B) {
+        (Err(unreadable()), buf)
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, _pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        (Err(unreadable()), buf)
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        Ok(self.len)
+    }
+}
+
+impl Write for DryRunFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        self.len += buf.bytes_init() as u64;
+        (Ok(()), buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        self.actions.lock().unwrap().push(Action::Write {
+            path: self.path.clone(),
+            len: self.len,
+        });
+        Ok(())
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn records_instead_of_changing() {
+        use tempfile::TempDir;
+
+        use super::{Action, DryRunFs};
+        use crate::{
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+            Read, Write,
+        };
+
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("old"), b"fusio").unwrap();
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+        let fs = DryRunFs::new(TokioFs);
+
+        fs.remove(&path("old")).await.unwrap();
+        let mut file = fs
+            .open_options(&path("new"), OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        let (result, _) = file.write_all(&b"hello"[..]).await;
+        result.unwrap();
+        file.close().await.unwrap();
+
+        assert_eq!(
+            fs.actions(),
+            [
+                Action::Remove { path: path("old") },
+                Action::Write {
+                    path: path("new"),
+                    len: 5
+                },
+            ]
+        );
+        assert_eq!(
+            fs.actions()[1].to_string(),
+            format!("write 5 bytes to {}", path("new"))
+        );
+        // nothing changed
+        let mut old = fs.open(&path("old")).await.unwrap();
+        let (result, buf) = old.read_to_end_at(Vec::new(), 0).await;
+        result.unwrap();
+        assert_eq!(buf, b"fusio");
+        assert!(!dir.path().join("new").exists());
+
+        fs.create_temp(&path("spill")).await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
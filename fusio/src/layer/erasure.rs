@@ -0,0 +1,629 @@
+use std::{collections::HashMap, io, sync::Arc};
+
+use async_stream::stream;
+use futures_core::Stream;
+use futures_util::{future::join_all, StreamExt};
+
+use crate::{
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions},
+    path::Path,
+    util::crc32c,
+    DynFs, Error, IoBuf, IoBufMut, Read, Write,
+};
+
+/// Every shard starts with the length of the object and the CRC-32C of the shard content, both
+/// little endian, so truncated or corrupt shards are treated like missing ones.
+const HEADER_LEN: usize = 12;
+
+/// Arithmetic in GF(2^8) with the polynomial x^8 + x^4 + x^3 + x^2 + 1.
+mod gf {
+    const TABLES: ([u8; 512], [u8; 256]) = {
+        let mut exp = [0; 512];
+        let mut log = [0; 256];
+        let mut x = 1u16;
+        let mut i = 0;
+        while i < 255 {
+            exp[i] = x as u8;
+            exp[i + 255] = x as u8;
+            log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= 0x11d;
+            }
+            i += 1;
+        }
+        (exp, log)
+    };
+
+    pub(super) fn mul(a: u8, b: u8) -> u8 {
+        match (a, b) {
+            (0, _) | (_, 0) => 0,
+            _ => TABLES.0[TABLES.1[a as usize] as usize + TABLES.1[b as usize] as usize],
+        }
+    }
+
+    pub(super) fn inv(a: u8) -> u8 {
+        debug_assert_ne!(a, 0);
+        TABLES.0[255 - TABLES.1[a as usize] as usize]
+    }
+}
+
+/// A systematic Reed-Solomon code: the first `data` shards hold the object, the parity shards
+/// are built from a Cauchy matrix, so any `data` of the shards recover the object.
+#[derive(Debug)]
+struct Codec {
+    data: usize,
+    parity: usize,
+}
+
+impl Codec {
+    fn row(&self, shard: usize) -> Vec<u8> {
+        (0..self.data)
+            .map(|column| match shard < self.data {
+                true => (shard == column) as u8,
+                false => gf::inv(shard as u8 ^ column as u8),
+            })
+            .collect()
+    }
+
+    fn encode(&self, content: &[u8]) -> Vec<Vec<u8>> {
+        let shard_len = content.len().div_ceil(self.data);
+        let mut shards = (0..self.data)
+            .map(|index| {
+                let start = (index * shard_len).min(content.len());
+                let end = (start + shard_len).min(content.len());
+                let mut shard = content[start..end].to_vec();
+                shard.resize(shard_len, 0);
+                shard
+            })
+            .collect::<Vec<_>>();
+
+        for parity in self.data..self.data + self.parity {
+            let shard = combine(&self.row(parity), &shards[..self.data], shard_len);
+            shards.push(shard);
+        }
+        shards
+    }
+
+    /// Recovers the object of `len` bytes from the shards that are available, `None` when fewer
+    /// than `data` shards are.
+    fn decode(&self, shards: &[Option<Vec<u8>>], len: usize) -> Option<Vec<u8>> {
+        let available = shards
+            .iter()
+            .enumerate()
+            .filter_map(|(index, shard)| Some((index, shard.as_ref()?)))
+            .take(self.data)
+            .collect::<Vec<_>>();
+        if available.len() < self.data {
+            return None;
+        }
+        let shard_len = available[0].1.len();
+
+        let rows = available
+            .iter()
+            .map(|(index, _)| self.row(*index))
+            .collect::<Vec<_>>();
+        let inverse = invert(rows);
+        let sources = available
+            .iter()
+            .map(|(_, shard)| shard.as_slice())
+            .collect::<Vec<_>>();
+
+        let mut content = Vec::with_capacity(shard_len * self.data);
+        for row in &inverse {
+            content.extend(combine(row, &sources, shard_len));
+        }
+        content.truncate(len);
+        Some(content)
+    }
+}
+
+/// The linear combination of `shards` with the `coefficients`.
+fn combine<S: AsRef<[u8]>>(coefficients: &[u8], shards: &[S], len: usize) -> Vec<u8> {
+    let mut combined = vec![0; len];
+    for (coefficient, shard) in coefficients.iter().zip(shards) {
+        match coefficient {
+            0 => {}
+            1 => combined
+                .iter_mut()
+                .zip(shard.as_ref())
+                .for_each(|(out, byte)| *out ^= byte),
+            _ => combined
+                .iter_mut()
+                .zip(shard.as_ref())
+                .for_each(|(out, byte)| *out ^= gf::mul(*coefficient, *byte)),
+        }
+    }
+    combined
+}
+
+/// Inverts a square matrix by Gauss-Jordan elimination. Any square selection of the rows of a
+/// [`Codec`] is invertible.
+fn invert(mut matrix: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    let n = matrix.len();
+    let mut inverse = (0..n)
+        .map(|row| (0..n).map(|column| (row == column) as u8).collect())
+        .collect::<Vec<Vec<u8>>>();
+
+    for column in 0..n {
+        let pivot = (column..n)
+            .find(|row| matrix[*row][column] != 0)
+            .expect("rows of the codec are linearly independent");
+        matrix.swap(column, pivot);
+        inverse.swap(column, pivot);
+
+        let scale = gf::inv(matrix[column][column]);
+        for value in matrix[column].iter_mut().chain(inverse[column].iter_mut()) {
+            *value = gf::mul(*value, scale);
+        }
+        for row in 0..n {
+            let factor = matrix[row][column];
+            if row == column || factor == 0 {
+                continue;
+            }
+            for index in 0..n {
+                matrix[row][index] ^= gf::mul(factor, matrix[column][index]);
+                inverse[row][index] ^= gf::mul(factor, inverse[column][index]);
+            }
+        }
+    }
+    inverse
+}
+
+fn parse_shard(mut shard: Vec<u8>) -> Option<(u64, Vec<u8>)> {
+    if shard.len() < HEADER_LEN {
+        return None;
+    }
+    let len = u64::from_le_bytes(shard[..8].try_into().unwrap());
+    let crc = u32::from_le_bytes(shard[8..HEADER_LEN].try_into().unwrap());
+    let content = shard.split_off(HEADER_LEN);
+    (crc32c(0, &content) == crc).then_some((len, content))
+}
+
+/// The length of the object read from the header of its shard on `fs`.
+async fn object_len(fs: &dyn DynFs, path: &Path) -> Result<u64, Error> {
+    let mut file = DynFs::open(fs, path).await?;
+    let (result, header) = file.read_exact_at(vec![0u8; 8], 0).await;
+    result?;
+    Ok(u64::from_le_bytes(header.try_into().unwrap()))
+}
+
+struct Inner {
+    codec: Codec,
+    backends: Vec<Box<dyn DynFs>>,
+}
+
+impl Inner {
+    async fn load(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        let reads = self.backends.iter().map(|fs| async move {
+            let mut file = DynFs::open(fs.as_ref(), path).await?;
+            let (result, shard) = file.read_to_end_at(Vec::new(), 0).await;
+            result.map(|_| shard)
+        });
+
+        let mut shards = Vec::with_capacity(self.backends.len());
+        let mut len = None;
+        let mut first_error = None;
+        for result in join_all(reads).await {
+            let shard = match result.map(parse_shard) {
+                Ok(Some((shard_len, shard))) if *len.get_or_insert(shard_len) == shard_len => {
+                    Some(shard)
+                }
+                Ok(_) => None,
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                    None
+                }
+            };
+            shards.push(shard);
+        }
+
+        let len = len.unwrap_or(0) as usize;
+        self.codec
+            .decode(&shards, len)
+            .ok_or_else(|| match first_error {
+                // also returned when the object does not exist on any backend
+                Some(e) if shards.iter().all(Option::is_none) => e,
+                _ => io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("not enough intact shards left to recover \"{path}\""),
+                )
+                .into(),
+            })
+    }
+
+    async fn store(&self, path: &Path, content: &[u8]) -> Result<(), Error> {
+        let writes = self
+            .codec
+            .encode(content)
+            .into_iter()
+            .zip(&self.backends)
+            .map(|(shard, fs)| async move {
+                let mut buf = Vec::with_capacity(HEADER_LEN + shard.len());
+                buf.extend_from_slice(&(content.len() as u64).to_le_bytes());
+                buf.extend_from_slice(&crc32c(0, &shard).to_le_bytes());
+                buf.extend_from_slice(&shard);
+
+                let options = OpenOptions::default().create(true).truncate(true);
+                let mut file = DynFs::open_options(fs.as_ref(), path, options).await?;
+                let (result, _) = file.write_all(buf).await;
+                result?;
+                file.close().await
+            });
+
+        join_all(writes).await.into_iter().collect()
+    }
+}
+
+/// Stripes every object over several backends as `data` shards plus parity shards, so it can
+/// still be read while as many backends as there are parity shards are unavailable, e.g. to
+/// survive the outage of a storage provider.
+///
+/// Experimental: objects are buffered in memory while they are written and read, writes replace
+/// the whole object like on object stores and only succeed once every shard was stored. Each
+/// backend holds one shard of an object under the path of the object, listings report the
+/// entries of the first backend that can be listed. [`Fs::create_temp`] keeps the default
+/// implementation, as a temporary object is only striped over the backends once it is closed,
+/// and it is not removed on drop.
+pub struct ErasureFs {
+    inner: Arc<Inner>,
+}
+
+impl ErasureFs {
+    /// Uses the first `data_shards` backends for data and the remaining ones for parity.
+    ///
+    /// # Panics
+    ///
+    /// If there are no data shards, no parity shards or more than 256 backends.
+    pub fn new(data_shards: usize, backends: Vec<Box<dyn DynFs>>) -> Self {
+        assert!(
+            data_shards > 0 && backends.len() > data_shards && backends.len() <= 256,
+            "{data_shards} data shards on {} backends",
+            backends.len()
+        );
+        Self {
+            inner: Arc::new(Inner {
+                codec: Codec {
+                    data: data_shards,
+                    parity: backends.len() - data_shards,
+                },
+                backends,
+            }),
+        }
+    }
+}
+
+impl Fs for ErasureFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        if !options.write {
+            let content = self.inner.load(path).await?;
+            return Ok(Box::new(ErasureFile {
+                content,
+                pending: None,
+            }));
+        }
+        Ok(Box::new(ErasureFile {
+            content: Vec::new(),
+            pending: Some((path.clone(), self.inner.clone())),
+        }))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        for fs in &self.inner.backends {
+            DynFs::create_dir_all(fs.as_ref(), path).await?;
+        }
+        Ok(())
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list_options(self, path, ListOptions::default()).await
+    }
+
+    /// Lists the first backend that can be listed. The sizes of files are read from their shard
+    /// headers.
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Ok(stream! {
+            let mut listing = None;
+            let mut first_error = None;
+            for fs in &self.inner.backends {
+                match DynFs::list_options(fs.as_ref(), path, options.clone()).await {
+                    Ok(stream) => {
+                        listing = Some((fs, stream));
+                        break;
+                    }
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+            let Some((fs, mut listing)) = listing else {
+                yield Err(first_error.expect("at least two backends"));
+                return;
+            };
+
+            while let Some(meta) = listing.next().await {
+                let mut meta = meta?;
+                if !meta.is_dir() {
+                    meta.size = object_len(fs.as_ref(), &meta.path).await?;
+                }
+                yield Ok(meta);
+            }
+        })
+    }
+
+    /// Removes the shards from every backend. Backends that do not hold a shard are skipped.
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        let removes = self
+            .inner
+            .backends
+            .iter()
+            .map(|fs| DynFs::remove(fs.as_ref(), path));
+
+        let mut not_found = None;
+        let mut removed = false;
+        for result in join_all(removes).await {
+            match result {
+                Ok(()) => removed = true,
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => not_found = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+        match not_found {
+            Some(e) if !removed => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the shards from every backend in one batch per backend. A path fails like in
+    /// [`Fs::remove`]: if a backend fails to remove its shard, or no backend holds one.
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let removes = self
+            .inner
+            .backends
+            .iter()
+            .map(|fs| DynFs::remove_batch(fs.as_ref(), paths.clone()));
+
+        let mut errors = HashMap::<Path, Vec<Error>>::new();
+        for result in join_all(removes).await {
+            for (path, error) in result? {
+                errors.entry(path).or_default().push(error);
+            }
+        }
+        let backends = self.inner.backends.len();
+        let failed = paths
+            .into_iter()
+            .filter_map(|path| {
+                let mut errors = errors.remove(&path)?;
+                let missing =
+                    |e: &Error| matches!(e, Error::Io(e) if e.kind() == io::ErrorKind::NotFound);
+                match errors.iter().position(|e| !missing(e)) {
+                    Some(index) => Some((path, errors.swap_remove(index))),
+                    None if errors.len() == backends => Some((path, errors.swap_remove(0))),
+                    None => None,
+                }
+            })
+            .collect();
+        Ok(failed)
+    }
+
+    /// Copies the shard on every backend. If a backend fails, e.g. because it lost its shard, the
+    /// object is recovered from the other shards and written to `to` again.
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let copies = self
+            .inner
+            .backends
+            .iter()
+            .map(|fs| DynFs::copy(fs.as_ref(), from, to));
+        if join_all(copies).await.iter().all(Result::is_ok) {
+            return Ok(());
+        }
+        let content = self.inner.load(from).await?;
+        self.inner.store(to, &content).await
+    }
+
+    /// Reports the shard of the first backend that has one, with the size of the object read from
+    /// the shard header. The ETag and checksum of a shard do not identify the object and are left
+    /// out.
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        let mut first_error = None;
+        for fs in &self.inner.backends {
+            let meta = async {
+                let meta = DynFs::stat(fs.as_ref(), path).await?;
+                Ok::<_, Error>(FileMeta {
+                    size: object_len(fs.as_ref(), path).await?,
+                    etag: None,
+                    crc32c: None,
+                    ..meta
+                })
+            };
+            match meta.await {
+                Ok(meta) => return Ok(meta),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.expect("at least two backends"))
+    }
+
+    /// Shuts down every backend, even if some of them fail, and returns the first error.
+    async fn shutdown(&self) -> Result<(), Error> {
+        let mut result = Ok(());
+        for fs in &self.inner.backends {
+            let shutdown = DynFs::shutdown(fs.as_ref()).await;
+            result = result.and(shutdown);
+        }
+        result
+    }
+}
+
+/// The content of an object, striped over the backends once it is closed after writing.
+struct ErasureFile {
+    content: Vec<u8>,
+    pending: Option<(Path, Arc<Inner>)>,
+}
+
+impl Read for ErasureFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        let mut content = &mut self.content;
+        content.read_exact_at(buf, pos).await
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        let mut content = &mut self.content;
+        content.read_to_end_at(buf, pos).await
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        Ok(self.content.len() as u64)
+    }
+}
+
+impl Write for ErasureFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        if self.pending.is_none() {
+            return (
+                Err(Error::Unsupported {
+                    message: "file is not open for writing".into(),
+                }),
+                buf,
+            );
+        }
+        self.content.extend_from_slice(buf.as_slice());
+        (Ok(()), buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        if let Some((path, inner)) = &self.pending {
+            inner.store(path, &self.content).await?;
+        }
+        self.pending = None;
+        Ok(())
+    }
+
+    /// Fails, the shards are only written when the file is closed.
+    async fn barrier(&mut self) -> Result<(), Error> {
+        Err(Error::Unsupported {
+            message: "erasure coded files are only written when closed".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn recovers_from_any_lost_shards() {
+        use super::Codec;
+
+        let codec = Codec { data: 4, parity: 2 };
+        let content = (0..1000).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+        let shards = codec.encode(&content);
+        assert_eq!(shards.len(), 6);
+
+        for lost in 0..6 {
+            for also_lost in lost..6 {
+                let available = shards
+                    .iter()
+                    .enumerate()
+                    .map(|(index, shard)| {
+                        (index != lost && index != also_lost).then(|| shard.clone())
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    codec.decode(&available, content.len()),
+                    Some(content.clone())
+                );
+            }
+        }
+
+        let available = shards
+            .iter()
+            .enumerate()
+            .map(|(index, shard)| (index > 2).then(|| shard.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(codec.decode(&available, content.len()), None);
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn reads_with_unavailable_backends() {
+        use std::{io, pin::pin};
+
+        use futures_util::StreamExt;
+        use tempfile::TempDir;
+
+        use super::ErasureFs;
+        use crate::{
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            layer::RouterFs,
+            path::Path,
+            Error, Read, Write,
+        };
+
+        let dirs = [(); 3].map(|_| TempDir::new().unwrap());
+        let backends = dirs
+            .iter()
+            .map(|dir| {
+                Box::new(RouterFs::new().mount(
+                    Path::default(),
+                    TokioFs,
+                    Path::from_absolute_path(dir.path()).unwrap(),
+                )) as Box<dyn crate::DynFs>
+            })
+            .collect();
+        let fs = ErasureFs::new(2, backends);
+        let path = Path::parse("object").unwrap();
+        let content = (0..=255u8).cycle().take(1001).collect::<Vec<_>>();
+
+        let mut file = fs
+            .open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        file.write_all(content.clone()).await.0.unwrap();
+        file.close().await.unwrap();
+        for dir in &dirs {
+            assert_eq!(
+                std::fs::metadata(dir.path().join("object")).unwrap().len(),
+                513
+            );
+        }
+        let meta = fs.stat(&path).await.unwrap();
+        assert_eq!(meta.path, path);
+        assert_eq!(meta.size, 1001);
+        assert!(meta.last_modified.is_some());
+
+        let root = Path::default();
+        let mut listing = pin!(fs.list(&root).await.unwrap());
+        assert_eq!(listing.next().await.unwrap().unwrap().size, 1001);
+
+        // a lost data shard and a corrupt parity shard
+        std::fs::remove_file(dirs[0].path().join("object")).unwrap();
+        let mut file = fs.open(&path).await.unwrap();
+        let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+        result.unwrap();
+        assert_eq!(buf, content);
+
+        std::fs::write(dirs[2].path().join("object"), b"corrupt").unwrap();
+        let err = fs.open(&path).await.err().unwrap();
+        assert!(matches!(err, Error::Io(e) if e.kind() == io::ErrorKind::InvalidData));
+
+        fs.remove(&path).await.unwrap();
+        let err = fs.open(&path).await.err().unwrap();
+        assert!(matches!(err, Error::Io(e) if e.kind() == io::ErrorKind::NotFound));
+    }
+}
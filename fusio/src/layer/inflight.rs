@@ -0,0 +1,434 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use futures_core::Stream;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    DynFs, Error, IoBuf, IoBufMut, Read, Write,
+};
+
+/// The kind of an [`Operation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpKind {
+    Open,
+    CreateDirAll,
+    List,
+    Remove,
+    /// Recorded under the path copied to.
+    Copy,
+    /// Recorded under the path renamed to.
+    Rename,
+    Stat,
+    CreateTemp,
+    Read,
+    Size,
+    Write,
+    Flush,
+    Close,
+}
+
+/// A call that has not returned yet, see [`InFlight::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    /// The name of the backend given to [`InFlightFs::with_tracker`], empty for the own tracker
+    /// of an [`InFlightFs`].
+    pub backend: Arc<str>,
+    pub kind: OpKind,
+    pub path: Path,
+    pub started: SystemTime,
+    pub elapsed: Duration,
+}
+
+struct Started {
+    backend: Arc<str>,
+    kind: OpKind,
+    path: Path,
+    started: SystemTime,
+}
+
+struct Tracker {
+    clock: Arc<dyn Clock>,
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, Started>>,
+}
+
+/// The calls in flight on one or more [`InFlightFs`], e.g. to show what storage is stuck on in
+/// an admin endpoint. Clones share the same calls.
+#[derive(Clone)]
+pub struct InFlight {
+    tracker: Arc<Tracker>,
+}
+
+impl Default for InFlight {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+impl InFlight {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            tracker: Arc::new(Tracker {
+                clock,
+                next_id: AtomicU64::new(0),
+                running: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Every call in flight, the longest running first.
+    pub fn snapshot(&self) -> Vec<Operation> {
+        self.filtered(|_| true)
+    }
+
+    fn filtered(&self, keep: impl Fn(&Started) -> bool) -> Vec<Operation> {
+        let now = self.tracker.clock.now();
+        let mut operations = self
+            .tracker
+            .running
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|started| keep(started))
+            .map(|started| Operation {
+                backend: started.backend.clone(),
+                kind: started.kind,
+                path: started.path.clone(),
+                started: started.started,
+                elapsed: now.duration_since(started.started).unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        operations.sort_by_key(|operation| operation.started);
+        operations
+    }
+
+    fn start(&self, backend: &Arc<str>, kind: OpKind, path: &Path) -> Running<'_> {
+        let id = self.tracker.next_id.fetch_add(1, Ordering::Relaxed);
+        let started = Started {
+            backend: backend.clone(),
+            kind,
+            path: path.clone(),
+            started: self.tracker.clock.now(),
+        };
+        self.tracker.running.lock().unwrap().insert(id, started);
+        Running { tracker: self, id }
+    }
+}
+
+/// Removes a call from the tracker once it returned or was dropped.
+struct Running<'a> {
+    tracker: &'a InFlight,
+    id: u64,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.tracker
+            .tracker
+            .running
+            .lock()
+            .unwrap()
+            .remove(&self.id);
+    }
+}
+
+/// Records the calls in flight on a backend, so operators can see which calls storage is stuck
+/// on and for how long with [`InFlightFs::stats`].
+///
+/// Calls on files opened through the layer are recorded with the path they were opened at.
+/// Several backends can share one [`InFlight`] with [`InFlightFs::with_tracker`] for a global
+/// view across all of them.
+pub struct InFlightFs {
+    inner: Box<dyn DynFs>,
+    tracker: InFlight,
+    backend: Arc<str>,
+}
+
+impl InFlightFs {
+    pub fn new<F>(fs: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            tracker: InFlight::default(),
+            backend: Arc::from(""),
+        }
+    }
+
+    /// Records calls in `tracker` under the name `backend` instead of an own tracker.
+    pub fn with_tracker(mut self, tracker: InFlight, backend: impl Into<Arc<str>>) -> Self {
+        self.tracker = tracker;
+        self.backend = backend.into();
+        self
+    }
+
+    /// The calls in flight on this backend, the longest running first.
+    pub fn stats(&self) -> Vec<Operation> {
+        self.tracker
+            .filtered(|started| Arc::ptr_eq(&started.backend, &self.backend))
+    }
+
+    fn start(&self, kind: OpKind, path: &Path) -> Running<'_> {
+        self.tracker.start(&self.backend, kind, path)
+    }
+
+    fn file(&self, file: Box<dyn DynFile>, path: Path) -> Box<dyn DynFile> {
+        Box::new(InFlightFile {
+            inner: file,
+            tracker: self.tracker.clone(),
+            backend: self.backend.clone(),
+            path,
+        })
+    }
+}
+
+impl Fs for InFlightFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        let _running = self.start(OpKind::Open, path);
+        let file = DynFs::open_options(self.inner.as_ref(), path, options).await?;
+        Ok(self.file(file, path.clone()))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        let _running = self.start(OpKind::CreateDirAll, path);
+        DynFs::create_dir_all(self.inner.as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let _running = self.start(OpKind::List, path);
+        Fs::list(&self.inner, path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let _running = self.start(OpKind::List, path);
+        Fs::list_options(&self.inner, path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        let _running = self.start(OpKind::Remove, path);
+        DynFs::remove(self.inner.as_ref(), path).await
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let _running = paths
+            .iter()
+            .map(|path| self.start(OpKind::Remove, path))
+            .collect::<Vec<_>>();
+        DynFs::remove_batch(self.inner.as_ref(), paths).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let _running = self.start(OpKind::Copy, to);
+        DynFs::copy(self.inner.as_ref(), from, to).await
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let _running = self.start(OpKind::Rename, to);
+        DynFs::rename_new(self.inner.as_ref(), from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        let _running = self.start(OpKind::Stat, path);
+        DynFs::stat(self.inner.as_ref(), path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let _running = self.start(OpKind::CreateTemp, prefix);
+        let (file, guard) = DynFs::create_temp(self.inner.as_ref(), prefix).await?;
+        let path = guard.path().clone();
+        Ok((self.file(file, path), guard))
+    }
+
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.inner.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+/// A file opened through an [`InFlightFs`], recording its calls under the path it was opened at.
+struct InFlightFile {
+    inner: Box<dyn DynFile>,
+    tracker: InFlight,
+    backend: Arc<str>,
+    path: Path,
+}
+
+impl Read for InFlightFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        let _running = self.tracker.start(&self.backend, OpKind::Read, &self.path);
+        self.inner.read_exact_at(buf, pos).await
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        let _running = self.tracker.start(&self.backend, OpKind::Read, &self.path);
+        self.inner.read_to_end_at(buf, pos).await
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        let _running = self.tracker.start(&self.backend, OpKind::Size, &self.path);
+        self.inner.size().await
+    }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        let _running = self.tracker.start(&self.backend, OpKind::Read, &self.path);
+        self.inner.read_at(buf, pos).await
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        let _running = self.tracker.start(&self.backend, OpKind::Read, &self.path);
+        self.inner.read_batch(ranges).await
+    }
+}
+
+impl Write for InFlightFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        let _running = self.tracker.start(&self.backend, OpKind::Write, &self.path);
+        self.inner.write_all(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        let _running = self.tracker.start(&self.backend, OpKind::Flush, &self.path);
+        self.inner.flush().await
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        let _running = self.tracker.start(&self.backend, OpKind::Close, &self.path);
+        self.inner.close().await
+    }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        let _running = self.tracker.start(&self.backend, OpKind::Write, &self.path);
+        self.inner.write_batch(bufs).await
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        let _running = self.tracker.start(&self.backend, OpKind::Flush, &self.path);
+        self.inner.barrier().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn lists_calls_in_flight() {
+        use std::{
+            sync::Arc,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        use futures_core::Stream;
+        use tempfile::TempDir;
+        use tokio::sync::Notify;
+
+        use super::{InFlight, InFlightFs, OpKind};
+        use crate::{
+            clock::{FixedClock, OffsetClock},
+            disk::TokioFs,
+            fs::{FileMeta, Fs, OpenOptions},
+            path::Path,
+            Error,
+        };
+
+        /// Holds every open until it is released.
+        struct Stuck {
+            release: Arc<Notify>,
+        }
+
+        impl Fs for Stuck {
+            type File = <TokioFs as Fs>::File;
+
+            async fn open_options(
+                &self,
+                path: &Path,
+                options: OpenOptions,
+            ) -> Result<Self::File, Error> {
+                self.release.notified().await;
+                TokioFs.open_options(path, options).await
+            }
+
+            async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+                TokioFs.create_dir_all(path).await
+            }
+
+            async fn list(
+                &self,
+                path: &Path,
+            ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+                TokioFs.list(path).await
+            }
+
+            async fn remove(&self, path: &Path) -> Result<(), Error> {
+                TokioFs.remove(path).await
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("file")).unwrap();
+        let clock = Arc::new(OffsetClock::new(FixedClock::new(UNIX_EPOCH)));
+        let tracker = InFlight::new(clock.clone());
+        let release = Arc::new(Notify::new());
+        let stuck = Arc::new(
+            InFlightFs::new(Stuck {
+                release: release.clone(),
+            })
+            .with_tracker(tracker.clone(), "stuck"),
+        );
+        let local = InFlightFs::new(TokioFs).with_tracker(tracker.clone(), "local");
+
+        let open = tokio::spawn({
+            let stuck = stuck.clone();
+            let path = path.clone();
+            async move {
+                stuck
+                    .open_options(&path, OpenOptions::default().create(true))
+                    .await
+                    .map(|_| ())
+            }
+        });
+        while stuck.stats().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        clock.set_offset_millis(5_000);
+
+        let stats = stuck.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(&*stats[0].backend, "stuck");
+        assert_eq!(stats[0].kind, OpKind::Open);
+        assert_eq!(stats[0].path, path);
+        assert_eq!(stats[0].elapsed, Duration::from_secs(5));
+        assert!(local.stats().is_empty());
+        assert_eq!(tracker.snapshot(), stats);
+
+        release.notify_one();
+        open.await.unwrap().unwrap();
+        assert!(tracker.snapshot().is_empty());
+    }
+}
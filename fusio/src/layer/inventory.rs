@@ -0,0 +1,227 @@
+use std::sync::{Arc, RwLock};
+
+use async_stream::stream;
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::{
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard},
+    path::Path,
+    util::ListSnapshot,
+    DynFs, Error,
+};
+
+/// Serves listings below the prefixes of loaded [`ListSnapshot`]s from the snapshots instead of
+/// the backend, e.g. from an S3 Inventory report loaded by
+/// [`load_s3_inventory`](crate::util::load_s3_inventory), so full scans of huge buckets do not
+/// send millions of LIST requests.
+///
+/// Listings served from a snapshot report every file below the listed path in lexicographic
+/// order, like S3 does, and reflect the bucket at [`ListSnapshot::taken`]: files written or
+/// removed since, also through this layer, are not reflected until a newer snapshot is loaded.
+/// Every other operation is forwarded to the backend.
+pub struct InventoryFs {
+    inner: Box<dyn DynFs>,
+    snapshots: RwLock<Vec<Arc<ListSnapshot>>>,
+}
+
+impl InventoryFs {
+    pub fn new<F>(fs: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            snapshots: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Serves listings below the prefix of `snapshot` from it, replacing the snapshot loaded for
+    /// the same prefix before.
+    pub fn load(&self, snapshot: ListSnapshot) {
+        let mut snapshots = self.snapshots.write().unwrap();
+        snapshots.retain(|loaded| loaded.prefix() != snapshot.prefix());
+        snapshots.push(Arc::new(snapshot));
+    }
+
+    /// Lists below `prefix` from the backend again.
+    pub fn unload(&self, prefix: &Path) {
+        self.snapshots
+            .write()
+            .unwrap()
+            .retain(|loaded| loaded.prefix() != prefix);
+    }
+
+    /// The snapshot with the longest prefix that contains `path`.
+    fn snapshot(&self, path: &Path) -> Option<Arc<ListSnapshot>> {
+        self.snapshots
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|snapshot| path.prefix_matches(snapshot.prefix()))
+            .max_by_key(|snapshot| snapshot.prefix().as_ref().len())
+            .cloned()
+    }
+}
+
+impl Fs for InventoryFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        DynFs::open_options(self.inner.as_ref(), path, options).await
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        DynFs::create_dir_all(self.inner.as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let snapshot = self.snapshot(path);
+        let path = path.clone();
+        Ok(stream! {
+            match snapshot {
+                Some(snapshot) => {
+                    for meta in snapshot.below(&path) {
+                        yield Ok(meta.clone());
+                    }
+                }
+                None => {
+                    let mut listing = match DynFs::list(self.inner.as_ref(), &path).await {
+                        Ok(listing) => listing,
+                        Err(e) => {
+                            yield Err(e);
+                            return;
+                        }
+                    };
+                    while let Some(meta) = listing.next().await {
+                        yield meta;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let snapshot = self.snapshot(path);
+        let path = path.clone();
+        Ok(stream! {
+            match snapshot {
+                Some(snapshot) => {
+                    let listing = futures_util::stream::iter(
+                        snapshot.below(&path).cloned().map(Ok).collect::<Vec<_>>(),
+                    );
+                    let mut listing = std::pin::pin!(options.apply(listing));
+                    while let Some(meta) = listing.next().await {
+                        yield meta;
+                    }
+                }
+                None => {
+                    let listing = match Fs::list_options(&self.inner, &path, options).await {
+                        Ok(listing) => listing,
+                        Err(e) => {
+                            yield Err(e);
+                            return;
+                        }
+                    };
+                    let mut listing = std::pin::pin!(listing);
+                    while let Some(meta) = listing.next().await {
+                        yield meta;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        DynFs::remove(self.inner.as_ref(), path).await
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        DynFs::remove_batch(self.inner.as_ref(), paths).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::copy(self.inner.as_ref(), from, to).await
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::rename_new(self.inner.as_ref(), from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        DynFs::stat(self.inner.as_ref(), path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        DynFs::create_temp(self.inner.as_ref(), prefix).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn lists_from_snapshot() {
+        use std::time::UNIX_EPOCH;
+
+        use futures_util::TryStreamExt;
+        use tempfile::TempDir;
+
+        use super::InventoryFs;
+        use crate::{
+            disk::TokioFs,
+            fs::{FileMeta, Fs, ListOptions},
+            path::Path,
+            util::ListSnapshot,
+        };
+
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("logs")).unwrap();
+        std::fs::write(dir.path().join("logs/live"), b"").unwrap();
+        let root = Path::from_absolute_path(dir.path()).unwrap();
+        let logs = root.child("logs");
+        let file = |name: &str, size| FileMeta::new(logs.child(name), size);
+
+        let fs = InventoryFs::new(TokioFs);
+        fs.load(ListSnapshot::from_files(
+            logs.clone(),
+            UNIX_EPOCH,
+            [file("b", 2), file("a", 1), file("c", 3)],
+        ));
+        let listed = fs.list(&logs).await.unwrap().try_collect::<Vec<_>>().await;
+        assert_eq!(
+            listed.unwrap(),
+            vec![file("a", 1), file("b", 2), file("c", 3)]
+        );
+
+        let page = fs
+            .list_options(
+                &logs,
+                ListOptions::default()
+                    .page_token(logs.child("a").as_ref())
+                    .limit(1),
+            )
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await;
+        assert_eq!(page.unwrap(), vec![file("b", 2)]);
+
+        fs.unload(&logs);
+        let listed = fs.list(&logs).await.unwrap().try_collect::<Vec<_>>().await;
+        let listed = listed.unwrap().into_iter().map(|meta| meta.path);
+        assert_eq!(listed.collect::<Vec<_>>(), vec![logs.child("live")]);
+    }
+}
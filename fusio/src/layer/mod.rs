@@ -0,0 +1,386 @@
+//! Composition of middleware that wraps an [`Fs`] in another [`Fs`].
+
+#[cfg(feature = "dyn")]
+mod circuit;
+#[cfg(feature = "dyn")]
+mod deadline;
+#[cfg(feature = "dyn")]
+mod dry_run;
+#[cfg(feature = "dyn")]
+mod erasure;
+#[cfg(feature = "dyn")]
+mod inflight;
+#[cfg(feature = "dyn")]
+mod inventory;
+#[cfg(feature = "dyn")]
+mod negative;
+#[cfg(feature = "dyn")]
+mod policy;
+#[cfg(feature = "dyn")]
+mod replication;
+#[cfg(feature = "dyn")]
+mod router;
+#[cfg(feature = "dyn")]
+mod scheduler;
+#[cfg(feature = "dyn")]
+mod sharded;
+#[cfg(feature = "dyn")]
+mod tiering;
+#[cfg(feature = "dyn")]
+mod trash;
+#[cfg(feature = "dyn")]
+mod ttl;
+#[cfg(feature = "dyn")]
+mod verify;
+#[cfg(feature = "dyn")]
+mod worm;
+
+#[cfg(feature = "dyn")]
+pub use circuit::{CircuitBreakerFs, CircuitBreakerLayer, CircuitState};
+#[cfg(feature = "dyn")]
+pub use deadline::{DeadlineFs, DeadlineLayer};
+#[cfg(feature = "dyn")]
+pub use dry_run::{Action, DryRunFs};
+#[cfg(feature = "dyn")]
+pub use erasure::ErasureFs;
+#[cfg(feature = "dyn")]
+pub use inflight::{InFlight, InFlightFs, OpKind, Operation};
+#[cfg(feature = "dyn")]
+pub use inventory::InventoryFs;
+#[cfg(feature = "dyn")]
+pub use negative::{NegativeCacheFs, NegativeCacheLayer};
+#[cfg(feature = "dyn")]
+pub use policy::{PathPolicy, PolicyFs, PolicyLayer};
+#[cfg(feature = "dyn")]
+pub use replication::{ReplicatedFs, ReplicationLag};
+#[cfg(feature = "dyn")]
+pub use router::RouterFs;
+#[cfg(feature = "dyn")]
+pub use scheduler::SchedulerFs;
+#[cfg(feature = "dyn")]
+pub use sharded::ShardedFs;
+#[cfg(feature = "dyn")]
+pub use tiering::*;
+#[cfg(feature = "dyn")]
+pub use trash::{TrashFs, Trashed};
+#[cfg(feature = "dyn")]
+pub use ttl::TtlFs;
+#[cfg(feature = "dyn")]
+pub use verify::{VerifyFs, VerifyLayer};
+#[cfg(feature = "dyn")]
+pub use worm::{WormFs, WormLayer};
+
+#[cfg(feature = "dyn")]
+use async_stream::stream;
+#[cfg(feature = "dyn")]
+use futures_core::Stream;
+#[cfg(feature = "dyn")]
+use futures_util::StreamExt;
+
+use crate::fs::Fs;
+#[cfg(feature = "dyn")]
+use crate::{
+    fs::{FileMeta, ListOptions},
+    DynFs, Error, MaybeSend, MaybeSync,
+};
+
+/// Assembles a backend and the layers wrapped around it.
+///
+/// Layers are applied in the order they are added, so the last one added is the outermost and
+/// sees every call first:
+///
+/// ```ignore
+/// let fs = Stack::new(s3)
+///     .layer(RetryLayer::new)
+///     .layer(MetricsLayer::new)
+///     .finish_dyn();
+/// ```
+#[derive(Debug)]
+pub struct Stack<F> {
+    fs: F,
+}
+
+impl<F: Fs> Stack<F> {
+    pub fn new(fs: F) -> Self {
+        Self { fs }
+    }
+
+    /// Wraps everything assembled so far with `layer`.
+    pub fn layer<G, L>(self, layer: L) -> Stack<G>
+    where
+        G: Fs,
+        L: FnOnce(F) -> G,
+    {
+        Stack { fs: layer(self.fs) }
+    }
+
+    pub fn finish(self) -> F {
+        self.fs
+    }
+
+    /// Erases the type of the stack, e.g. to pick backends at runtime.
+    #[cfg(feature = "dyn")]
+    pub fn finish_dyn(self) -> Box<dyn DynFs>
+    where
+        F: 'static,
+    {
+        Box::new(self.fs)
+    }
+
+    /// Wraps everything assembled so far with a type erased `layer`.
+    #[cfg(feature = "dyn")]
+    pub fn layer_dyn<L>(self, layer: &L) -> Stack<Box<dyn DynFs>>
+    where
+        F: 'static,
+        L: FsLayer + ?Sized,
+    {
+        Stack {
+            fs: layer.layer(self.finish_dyn()),
+        }
+    }
+}
+
+/// Middleware over type erased backends, the extension point for layers published outside of
+/// fusio.
+///
+/// A layer usually returns a struct holding `inner` that implements [`Fs`] by forwarding to it,
+/// `Box<dyn DynFs>` implements [`Fs`] as well. Implementations are expected to uphold these
+/// invariants so layers can be stacked in any order:
+///
+/// - Every operation the layer does not change is forwarded to `inner` as is, including
+///   [`Fs::list_options`], [`Fs::remove_batch`], [`Fs::copy`], [`Fs::stat`],
+///   [`Fs::create_temp`] and [`Fs::shutdown`]. Falling back to their default implementations
+///   silently drops the behavior of the backend, like server side pagination, batched deletes,
+///   server side copies, modification times and ETags, removing local temporary files on drop or
+///   flushing buffered state.
+/// - Layers that change removals apply the same rules to every path of [`Fs::remove_batch`] and
+///   report the paths they reject as failed instead of failing the whole batch. Layers that change
+///   writes treat the target of [`Fs::copy`] like a file opened for writing.
+/// - Listings are forwarded through the [`Fs`] implementation of `Box<dyn DynFs>`, e.g.
+///   `Fs::list(&self.inner, path)`, as the streams of [`DynFs::list`] cannot be returned from
+///   [`Fs::list`].
+/// - Once [`crate::Write::close`] returns successfully, the data has reached `inner`. Layers may
+///   buffer writes but never beyond closing the file.
+/// - Errors of `inner` are returned unchanged unless translating them is the purpose of the layer,
+///   so outer layers like retries can still classify them.
+/// - Paths keep their meaning: a layer that rewrites paths documents it, and the rewritten paths
+///   are the ones reported by [`Fs::list`].
+#[cfg(feature = "dyn")]
+pub trait FsLayer: MaybeSend + MaybeSync {
+    fn layer(&self, inner: Box<dyn DynFs>) -> Box<dyn DynFs>;
+}
+
+/// The options to list every backend of a layer with, so that merging the listings with
+/// [`merge_pages`] yields the page `options` asks for.
+#[cfg(feature = "dyn")]
+fn page_of_each(options: &ListOptions) -> ListOptions {
+    ListOptions {
+        offset: 0,
+        page_token: options.page_token.clone(),
+        limit: options
+            .limit
+            .map(|limit| limit.saturating_add(options.offset)),
+    }
+}
+
+/// Merges `listings`, each in lexicographic order and listed with [`page_of_each`], into the
+/// page `options` asks for. A path several listings report is taken from the first of them.
+#[cfg(feature = "dyn")]
+fn merge_pages<S>(
+    listings: Vec<S>,
+    options: ListOptions,
+) -> impl Stream<Item = Result<FileMeta, Error>>
+where
+    S: Stream<Item = Result<FileMeta, Error>>,
+{
+    let mut listings = listings.into_iter().map(Box::pin).collect::<Vec<_>>();
+    let merged = stream! {
+        let mut heads = Vec::with_capacity(listings.len());
+        for listing in &mut listings {
+            heads.push(listing.next().await);
+        }
+        loop {
+            // errors first, then the smallest path of the earliest listing
+            let mut next: Option<usize> = None;
+            for (index, head) in heads.iter().enumerate() {
+                let first = match (head, next.map(|next| &heads[next])) {
+                    (None, _) => false,
+                    (Some(_), None) => true,
+                    (Some(Err(_)), Some(Some(Ok(_)))) => true,
+                    (Some(Ok(meta)), Some(Some(Ok(next)))) => {
+                        meta.path.as_ref() < next.path.as_ref()
+                    }
+                    _ => false,
+                };
+                if first {
+                    next = Some(index);
+                }
+            }
+            let Some(index) = next else {
+                break;
+            };
+            let item = heads[index].take().expect("the listing has an entry");
+            heads[index] = listings[index].next().await;
+            if let Ok(meta) = &item {
+                for other in 0..heads.len() {
+                    while matches!(&heads[other], Some(Ok(head)) if head.path == meta.path) {
+                        heads[other] = listings[other].next().await;
+                    }
+                }
+            }
+            yield item;
+        }
+    };
+    ListOptions {
+        page_token: None,
+        ..options
+    }
+    .apply(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", feature = "dyn", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn layers_wrap_in_order() {
+        use std::sync::{Arc, Mutex};
+
+        use futures_core::Stream;
+        use tempfile::TempDir;
+
+        use super::Stack;
+        use crate::{
+            disk::TokioFs,
+            fs::{FileMeta, Fs, OpenOptions},
+            path::Path,
+            Error,
+        };
+
+        struct Tagged<F> {
+            inner: F,
+            tag: &'static str,
+            calls: Arc<Mutex<Vec<&'static str>>>,
+        }
+
+        impl<F: Fs> Fs for Tagged<F> {
+            type File = F::File;
+
+            async fn open_options(
+                &self,
+                path: &Path,
+                options: OpenOptions,
+            ) -> Result<Self::File, Error> {
+                self.calls.lock().unwrap().push(self.tag);
+                self.inner.open_options(path, options).await
+            }
+
+            async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+                self.inner.create_dir_all(path).await
+            }
+
+            async fn list(
+                &self,
+                path: &Path,
+            ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+                self.inner.list(path).await
+            }
+
+            async fn remove(&self, path: &Path) -> Result<(), Error> {
+                self.inner.remove(path).await
+            }
+        }
+
+        fn tagged<F>(
+            tag: &'static str,
+            calls: &Arc<Mutex<Vec<&'static str>>>,
+        ) -> impl FnOnce(F) -> Tagged<F> {
+            let calls = calls.clone();
+            move |inner| Tagged { inner, tag, calls }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("file")).unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        let fs = Stack::new(TokioFs)
+            .layer(tagged("inner", &calls))
+            .layer(tagged("outer", &calls))
+            .finish_dyn();
+        fs.open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+
+        assert_eq!(*calls.lock().unwrap(), vec!["outer", "inner"]);
+    }
+
+    #[cfg(all(feature = "tokio", feature = "dyn", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn dyn_layer() {
+        use futures_core::Stream;
+        use tempfile::TempDir;
+
+        use super::{FsLayer, Stack};
+        use crate::{
+            disk::TokioFs,
+            dynamic::DynFile,
+            fs::{FileMeta, Fs, OpenOptions},
+            path::Path,
+            Error,
+        };
+
+        struct NoRemove {
+            inner: Box<dyn crate::DynFs>,
+        }
+
+        impl Fs for NoRemove {
+            type File = Box<dyn DynFile>;
+
+            async fn open_options(
+                &self,
+                path: &Path,
+                options: OpenOptions,
+            ) -> Result<Self::File, Error> {
+                self.inner.open_options(path, options).await
+            }
+
+            async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+                self.inner.create_dir_all(path).await
+            }
+
+            async fn list(
+                &self,
+                path: &Path,
+            ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+                self.inner.list(path).await
+            }
+
+            async fn remove(&self, _: &Path) -> Result<(), Error> {
+                Err(Error::Unsupported {
+                    message: "remove is disabled".into(),
+                })
+            }
+        }
+
+        struct NoRemoveLayer;
+
+        impl FsLayer for NoRemoveLayer {
+            fn layer(&self, inner: Box<dyn crate::DynFs>) -> Box<dyn crate::DynFs> {
+                Box::new(NoRemove { inner })
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("file")).unwrap();
+        let fs = Stack::new(TokioFs).layer_dyn(&NoRemoveLayer).finish();
+
+        fs.open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        assert!(dir.path().join("file").exists());
+        assert!(matches!(
+            fs.remove(&path).await,
+            Err(Error::Unsupported { .. })
+        ));
+        assert!(dir.path().join("file").exists());
+    }
+}
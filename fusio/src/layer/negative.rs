@@ -0,0 +1,841 @@
+#[cfg(not(fusio_loom))]
+use std::sync::Mutex;
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Weak},
+    time::{Duration, SystemTime},
+};
+
+use futures_core::Stream;
+#[cfg(fusio_loom)]
+use loom::sync::Mutex;
+
+use super::FsLayer;
+use crate::{
+    cache::{BloomFilter, EvictionPolicy, Invalidate, Invalidations, Lru},
+    clock::{Clock, SystemClock},
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    util::walk::walk_entries,
+    DynFs, Error, IoBuf, IoBufMut, Read, Write,
+};
+
+const DEFAULT_CAPACITY: usize = 10_000;
+const HINT_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+struct Misses {
+    expires: HashMap<Path, SystemTime>,
+    policy: Lru<Path>,
+}
+
+/// The paths found missing, shared with the files opened through the layer.
+struct MissCache {
+    ttl: Duration,
+    capacity: usize,
+    clock: Arc<dyn Clock>,
+    misses: Mutex<Misses>,
+}
+
+impl MissCache {
+    fn is_missing(&self, path: &Path) -> bool {
+        let mut misses = self.misses.lock().unwrap();
+        let Some(expires) = misses.expires.get(path).copied() else {
+            return false;
+        };
+        if expires <= self.clock.now() {
+            misses.expires.remove(path);
+            misses.policy.remove(path);
+            return false;
+        }
+        misses.policy.access(path);
+        true
+    }
+
+    fn remember(&self, path: &Path) {
+        if self.capacity == 0 {
+            return;
+        }
+        let expires = self.clock.now() + self.ttl;
+        let mut misses = self.misses.lock().unwrap();
+        if misses.expires.insert(path.clone(), expires).is_none() {
+            misses.policy.insert(path.clone());
+        }
+        while misses.expires.len() > self.capacity {
+            let Some(evicted) = misses.policy.evict() else {
+                break;
+            };
+            misses.expires.remove(&evicted);
+        }
+    }
+
+    /// Remembers `path` when `result` reports it missing.
+    fn observe<T>(&self, path: &Path, result: &Result<T, Error>) {
+        if matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound) {
+            self.remember(path);
+        }
+    }
+
+    fn forget(&self, path: &Path) {
+        let mut misses = self.misses.lock().unwrap();
+        misses.expires.remove(path);
+        misses.policy.remove(path);
+    }
+
+    fn shrink_to(&self, paths: usize) -> usize {
+        let mut misses = self.misses.lock().unwrap();
+        let mut forgotten = 0;
+        while misses.expires.len() > paths {
+            let Some(evicted) = misses.policy.evict() else {
+                break;
+            };
+            misses.expires.remove(&evicted);
+            forgotten += 1;
+        }
+        forgotten
+    }
+}
+
+impl Invalidate for MissCache {
+    fn invalidate(&self, path: &Path) {
+        self.forget(path);
+    }
+}
+
+/// Remembers for a short time which paths did not exist, so probing for optional files, like
+/// an index that is only sometimes written, does not send the same failing request over and
+/// over.
+///
+/// Opens for reading, [`Fs::stat`] calls and the reads and [`Read::size`] calls of files opened
+/// for reading that fail with [`io::ErrorKind::NotFound`] are remembered. The latter matters on
+/// S3, where opening a file sends no request and a missing object is only noticed by its first
+/// request. Opening a path for writing or removing it through this layer forgets it right away,
+/// and closing a file opened for writing forgets it again, for object stores that only store it
+/// then. Files created behind the back of the layer become visible once the TTL passed, unless
+/// they are written through another cache sharing its [`Invalidations`], see
+/// [`NegativeCacheFs::with_invalidations`].
+///
+/// For prefixes with many files, [`NegativeCacheFs::load_hints`] additionally builds a bloom
+/// filter of the files and directories below the prefix, which answers most lookups of files that
+/// do not exist without any request, even for paths never probed before.
+///
+/// # Concurrency
+///
+/// The remembered paths are shared by the layer and every file opened through it. Loom model
+/// tests check under every interleaving that at most the configured capacity of paths is
+/// remembered, that the eviction order tracks exactly the remembered paths, and that a path
+/// forgotten by a write is not reported missing afterwards unless it is probed missing again.
+pub struct NegativeCacheFs {
+    inner: Box<dyn DynFs>,
+    cache: Arc<MissCache>,
+    hints: Mutex<Vec<(Path, BloomFilter)>>,
+    invalidations: Invalidations,
+}
+
+impl NegativeCacheFs {
+    pub fn new<F>(fs: F, ttl: Duration) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            cache: Arc::new(MissCache {
+                ttl,
+                capacity: DEFAULT_CAPACITY,
+                clock: Arc::new(SystemClock),
+                misses: Mutex::new(Misses {
+                    expires: HashMap::new(),
+                    policy: Lru::new(),
+                }),
+            }),
+            hints: Mutex::new(Vec::new()),
+            invalidations: Invalidations::new(),
+        }
+    }
+
+    /// Remembers at most `capacity` paths, dropping the least recently probed ones first.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.cache_mut().capacity = capacity;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.cache_mut().clock = clock;
+        self
+    }
+
+    /// Shares the paths written or removed through this layer with the other caches registered in
+    /// `invalidations`, and forgets the misses of paths written or removed through them, e.g.
+    /// another [`NegativeCacheFs`] stacked on the same bucket. Call it after the other `with_`
+    /// methods, which cannot change the cache once it is registered.
+    pub fn with_invalidations(mut self, invalidations: Invalidations) -> Self {
+        invalidations.register(&self.cache);
+        self.invalidations = invalidations;
+        self
+    }
+
+    fn cache_mut(&mut self) -> &mut MissCache {
+        Arc::get_mut(&mut self.cache).expect("configured before files are opened")
+    }
+
+    /// Forgets remembered paths, least recently probed first, until at most `paths` are left and
+    /// returns how many were forgotten. Call it when the host application comes under memory
+    /// pressure, with [`NegativeCacheFs::unload_hints`] for the larger bloom filters and
+    /// [`crate::buf::trim_allocator`] to return the memory to the operating system.
+    pub fn shrink_to(&self, paths: usize) -> usize {
+        self.cache.shrink_to(paths)
+    }
+
+    /// Drops the hints of every prefix, see [`NegativeCacheFs::load_hints`].
+    pub fn unload_hints(&self) {
+        self.hints.lock().unwrap().clear();
+    }
+
+    /// Lists every file and directory below `prefix` and remembers them in a bloom filter, with
+    /// the directories leading to them. Afterwards paths below `prefix` the filter rules out are
+    /// reported missing without asking the backend, until the hints are loaded again. Files and
+    /// directories created through this layer are added to the filter, the ones created behind
+    /// its back are hidden until the next load. Returns the number of files found.
+    pub async fn load_hints(&self, prefix: &Path) -> Result<usize, Error> {
+        let mut paths = Vec::new();
+        let mut files = 0;
+        walk_entries(&self.inner, prefix, |meta| {
+            files += usize::from(!meta.is_dir());
+            paths.push(meta.path);
+        })
+        .await?;
+
+        let mut bloom = BloomFilter::new(paths.len() + 1, HINT_FALSE_POSITIVE_RATE);
+        bloom.insert(prefix);
+        for path in &paths {
+            insert_with_ancestors(&mut bloom, prefix, path);
+        }
+        let mut hints = self.hints.lock().unwrap();
+        hints.retain(|(hinted, _)| hinted != prefix);
+        hints.push((prefix.clone(), bloom));
+        Ok(files)
+    }
+
+    fn ruled_out(&self, path: &Path) -> bool {
+        self.hints
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(prefix, bloom)| path.prefix_matches(prefix) && !bloom.may_contain(path))
+    }
+
+    /// Fails right away when `path` is known to be missing.
+    fn check(&self, path: &Path) -> Result<(), Error> {
+        if self.ruled_out(path) || self.cache.is_missing(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("\"{path}\" was not found recently"),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Forgets `path` here and in every cache sharing the invalidations, once it was written or
+    /// removed.
+    fn changed(&self, path: &Path) {
+        self.cache.forget(path);
+        self.invalidations.invalidate(path);
+    }
+
+    fn add_hint(&self, path: &Path) {
+        for (prefix, bloom) in self.hints.lock().unwrap().iter_mut() {
+            if path.prefix_matches(prefix) {
+                insert_with_ancestors(bloom, prefix, path);
+            }
+        }
+    }
+}
+
+/// Inserts `path` and the directories between `prefix` and `path` into `bloom`, so looking up
+/// a directory that holds files is never ruled out.
+fn insert_with_ancestors(bloom: &mut BloomFilter, prefix: &Path, path: &Path) {
+    let Some(parts) = path.prefix_match(prefix) else {
+        return;
+    };
+    let mut ancestor = prefix.clone();
+    for part in parts {
+        ancestor = ancestor.child(part);
+        bloom.insert(&ancestor);
+    }
+}
+
+impl Fs for NegativeCacheFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        if options.write {
+            self.cache.forget(path);
+            self.add_hint(path);
+            let file = DynFs::open_options(self.inner.as_ref(), path, options).await;
+            self.changed(path);
+            return Ok(Box::new(NegativeCacheFile {
+                inner: file?,
+                path: path.clone(),
+                cache: self.cache.clone(),
+                written: Some(self.invalidations.clone()),
+            }));
+        }
+
+        self.check(path)?;
+        let file = DynFs::open_options(self.inner.as_ref(), path, options).await;
+        self.cache.observe(path, &file);
+        Ok(Box::new(NegativeCacheFile {
+            inner: file?,
+            path: path.clone(),
+            cache: self.cache.clone(),
+            written: None,
+        }))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        self.cache.forget(path);
+        self.add_hint(path);
+        let result = DynFs::create_dir_all(self.inner.as_ref(), path).await;
+        self.changed(path);
+        result
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list(&self.inner, path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list_options(&self.inner, path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        let result = DynFs::remove(self.inner.as_ref(), path).await;
+        self.changed(path);
+        result
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let removed = paths.clone();
+        let result = DynFs::remove_batch(self.inner.as_ref(), paths).await;
+        for path in &removed {
+            self.changed(path);
+        }
+        result
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        self.check(from)?;
+        self.cache.forget(to);
+        self.add_hint(to);
+        let result = DynFs::copy(self.inner.as_ref(), from, to).await;
+        self.changed(to);
+        result
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        self.check(from)?;
+        self.cache.forget(to);
+        self.add_hint(to);
+        let result = DynFs::rename_new(self.inner.as_ref(), from, to).await;
+        self.changed(from);
+        self.changed(to);
+        result
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        self.check(path)?;
+        let meta = DynFs::stat(self.inner.as_ref(), path).await;
+        self.cache.observe(path, &meta);
+        meta
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let (file, guard) = DynFs::create_temp(self.inner.as_ref(), prefix).await?;
+        self.add_hint(guard.path());
+        Ok((file, guard))
+    }
+
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.inner.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+/// A file opened through a [`NegativeCacheFs`], remembering its path when the backend reports it
+/// missing, or forgetting it when it is closed after writing.
+struct NegativeCacheFile {
+    inner: Box<dyn DynFile>,
+    path: Path,
+    cache: Arc<MissCache>,
+    /// The invalidations to publish the path to on close, if the file was opened for writing.
+    written: Option<Invalidations>,
+}
+
+impl Read for NegativeCacheFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        let (result, buf) = self.inner.read_exact_at(buf, pos).await;
+        self.cache.observe(&self.path, &result);
+        (result, buf)
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        let (result, buf) = self.inner.read_to_end_at(buf, pos).await;
+        self.cache.observe(&self.path, &result);
+        (result, buf)
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        let size = self.inner.size().await;
+        self.cache.observe(&self.path, &size);
+        size
+    }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        let (result, buf) = self.inner.read_at(buf, pos).await;
+        self.cache.observe(&self.path, &result);
+        (result, buf)
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        let result = self.inner.read_batch(ranges).await;
+        self.cache.observe(&self.path, &result);
+        result
+    }
+}
+
+impl Write for NegativeCacheFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        self.inner.write_all(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush().await
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        let result = self.inner.close().await;
+        if let Some(invalidations) = &self.written {
+            self.cache.forget(&self.path);
+            invalidations.invalidate(&self.path);
+        }
+        result
+    }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        self.inner.write_batch(bufs).await
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        self.inner.barrier().await
+    }
+}
+
+/// Wraps a stack in a [`NegativeCacheFs`], see [`super::Stack::layer_dyn`].
+#[derive(Clone)]
+pub struct NegativeCacheLayer {
+    pub ttl: Duration,
+    pub capacity: usize,
+    /// The caches of the layers built so far, so they can be shrunk once they are type erased.
+    built: Arc<std::sync::Mutex<Vec<Weak<MissCache>>>>,
+    invalidations: Option<Invalidations>,
+}
+
+impl NegativeCacheLayer {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            capacity: DEFAULT_CAPACITY,
+            built: Arc::default(),
+            invalidations: None,
+        }
+    }
+
+    /// Builds every [`NegativeCacheFs`] with `invalidations`, see
+    /// [`NegativeCacheFs::with_invalidations`].
+    pub fn with_invalidations(mut self, invalidations: Invalidations) -> Self {
+        self.invalidations = Some(invalidations);
+        self
+    }
+
+    /// Shrinks every [`NegativeCacheFs`] built by this layer or its clones to at most `paths`
+    /// remembered paths, see [`NegativeCacheFs::shrink_to`]. Returns how many were forgotten.
+    pub fn shrink_to(&self, paths: usize) -> usize {
+        let mut built = self.built.lock().unwrap();
+        built.retain(|cache| cache.strong_count() > 0);
+        built
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|cache| cache.shrink_to(paths))
+            .sum()
+    }
+}
+
+impl FsLayer for NegativeCacheLayer {
+    fn layer(&self, inner: Box<dyn DynFs>) -> Box<dyn DynFs> {
+        let mut fs = NegativeCacheFs::new(inner, self.ttl).with_capacity(self.capacity);
+        if let Some(invalidations) = &self.invalidations {
+            fs = fs.with_invalidations(invalidations.clone());
+        }
+        self.built.lock().unwrap().push(Arc::downgrade(&fs.cache));
+        Box::new(fs)
+    }
+}
+
+#[cfg(all(test, not(fusio_loom)))]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn remembers_missing_paths() {
+        use std::{io, sync::Arc, time::Duration};
+
+        use tempfile::TempDir;
+
+        use super::NegativeCacheFs;
+        use crate::{
+            clock::{OffsetClock, SystemClock},
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+            Error,
+        };
+
+        fn is_not_found<T>(result: Result<T, Error>) -> bool {
+            matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound)
+        }
+
+        let dir = TempDir::new().unwrap();
+        let clock = Arc::new(OffsetClock::new(SystemClock));
+        let fs = NegativeCacheFs::new(TokioFs, Duration::from_secs(10)).with_clock(clock.clone());
+        let index = Path::from_absolute_path(dir.path().join("index")).unwrap();
+
+        assert!(is_not_found(fs.open(&index).await));
+        // created behind the back of the layer, still reported missing until the TTL passed
+        std::fs::write(dir.path().join("index"), b"fusio").unwrap();
+        assert!(is_not_found(fs.open(&index).await));
+        clock.set_offset_millis(10_000);
+        assert!(fs.open(&index).await.is_ok());
+
+        // writing through the layer forgets the miss right away
+        let data = Path::from_absolute_path(dir.path().join("data")).unwrap();
+        assert!(is_not_found(fs.open(&data).await));
+        fs.open_options(&data, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        assert!(fs.open(&data).await.is_ok());
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn shrinks_under_pressure() {
+        use std::time::Duration;
+
+        use tempfile::TempDir;
+
+        use super::NegativeCacheLayer;
+        use crate::{disk::TokioFs, layer::FsLayer, path::Path, DynFs};
+
+        let dir = TempDir::new().unwrap();
+        let layer = NegativeCacheLayer::new(Duration::from_secs(10));
+        let fs = layer.layer(Box::new(TokioFs));
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+        for name in ["a", "b", "c"] {
+            assert!(DynFs::open(fs.as_ref(), &path(name)).await.is_err());
+        }
+
+        // the least recently probed paths are forgotten first
+        assert!(DynFs::open(fs.as_ref(), &path("a")).await.is_err());
+        std::fs::write(dir.path().join("a"), b"").unwrap();
+        std::fs::write(dir.path().join("b"), b"").unwrap();
+        assert_eq!(layer.shrink_to(1), 2);
+        assert_eq!(layer.shrink_to(1), 0);
+        assert!(DynFs::open(fs.as_ref(), &path("a")).await.is_err());
+        assert!(DynFs::open(fs.as_ref(), &path("b")).await.is_ok());
+
+        drop(fs);
+        assert_eq!(layer.shrink_to(0), 0);
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn hints_rule_out_missing_files() {
+        use std::time::Duration;
+
+        use tempfile::TempDir;
+
+        use super::NegativeCacheFs;
+        use crate::{
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+        };
+
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("sst")).unwrap();
+        for i in 0..100 {
+            std::fs::write(dir.path().join(format!("sst/{i}")), b"").unwrap();
+        }
+        let fs = NegativeCacheFs::new(TokioFs, Duration::ZERO);
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+
+        assert_eq!(fs.load_hints(&path("sst")).await.unwrap(), 100);
+        assert!(fs.open(&path("sst/42")).await.is_ok());
+
+        // files created behind the back of the layer are mostly hidden until the next load, a
+        // few may pass as false positives of the filter
+        let mut hidden = 0;
+        for i in 0..20 {
+            std::fs::write(dir.path().join(format!("sst/late-{i}")), b"").unwrap();
+            if fs.open(&path(&format!("sst/late-{i}"))).await.is_err() {
+                hidden += 1;
+            }
+        }
+        assert!(hidden >= 15, "only {hidden} files hidden");
+        fs.load_hints(&path("sst")).await.unwrap();
+        for i in 0..20 {
+            assert!(fs.open(&path(&format!("sst/late-{i}"))).await.is_ok());
+        }
+
+        // files written through the layer are added to the filter
+        fs.open_options(&path("sst/new"), OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        assert!(fs.open(&path("sst/new")).await.is_ok());
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn hints_keep_directories() {
+        use std::time::Duration;
+
+        use tempfile::TempDir;
+
+        use super::NegativeCacheFs;
+        use crate::{disk::TokioFs, fs::Fs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("table/part=1")).unwrap();
+        std::fs::create_dir_all(dir.path().join("table/empty")).unwrap();
+        std::fs::write(dir.path().join("table/part=1/data"), b"").unwrap();
+        let fs = NegativeCacheFs::new(TokioFs, Duration::ZERO);
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+
+        assert_eq!(fs.load_hints(&path("table")).await.unwrap(), 1);
+        for directory in ["table", "table/part=1", "table/empty"] {
+            assert!(fs.stat(&path(directory)).await.unwrap().is_dir());
+        }
+
+        // directories created through the layer are added to the filter
+        fs.create_dir_all(&path("table/part=2/bucket"))
+            .await
+            .unwrap();
+        assert!(fs.stat(&path("table/part=2")).await.is_ok());
+        assert!(fs.stat(&path("table/part=2/bucket")).await.is_ok());
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn writes_invalidate_stacked_caches() {
+        use std::time::Duration;
+
+        use tempfile::TempDir;
+
+        use super::NegativeCacheFs;
+        use crate::{
+            cache::Invalidations,
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+            Write,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let invalidations = Invalidations::new();
+        let readers = NegativeCacheFs::new(TokioFs, Duration::from_secs(60))
+            .with_invalidations(invalidations.clone());
+        let writers = NegativeCacheFs::new(TokioFs, Duration::from_secs(60))
+            .with_invalidations(invalidations.clone());
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+
+        // a file written through one cache is visible through the other right away
+        assert!(readers.open(&path("index")).await.is_err());
+        let mut file = writers
+            .open_options(&path("index"), OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        assert!(readers.open(&path("index")).await.is_ok());
+
+        // as is a file renamed into place
+        assert!(readers.open(&path("data")).await.is_err());
+        writers
+            .rename_new(&path("index"), &path("data"))
+            .await
+            .unwrap();
+        assert!(readers.open(&path("data")).await.is_ok());
+
+        // caches not sharing the invalidations only see it once the TTL passed
+        let other = NegativeCacheFs::new(TokioFs, Duration::from_secs(60));
+        assert!(other.open(&path("stats")).await.is_err());
+        std::fs::write(dir.path().join("stats"), b"").unwrap();
+        invalidations.invalidate(&path("stats"));
+        assert!(other.open(&path("stats")).await.is_err());
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn remembers_missing_s3_objects() {
+        use std::{io, sync::Arc, time::Duration};
+
+        use super::NegativeCacheFs;
+        use crate::{
+            fs::{Fs, OpenOptions},
+            path::Path,
+            remotes::aws::mock::MockS3,
+            Error, Read, Write,
+        };
+
+        fn is_not_found<T>(result: Result<T, Error>) -> bool {
+            matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound)
+        }
+
+        let server = Arc::new(MockS3::default());
+        let fs = NegativeCacheFs::new(server.fs(), Duration::from_secs(10));
+        let index = Path::parse("index").unwrap();
+
+        // S3 opens lazily, the first request for the object finds it missing
+        let file = fs.open(&index).await.unwrap();
+        assert!(is_not_found(file.size().await));
+        assert_eq!(server.requests().len(), 1);
+        assert!(is_not_found(fs.open(&index).await));
+        assert!(is_not_found(fs.stat(&index).await));
+        assert_eq!(server.requests().len(), 1);
+
+        let stats = Path::parse("stats").unwrap();
+        assert!(is_not_found(fs.stat(&stats).await));
+        assert!(is_not_found(fs.stat(&stats).await));
+        assert!(is_not_found(fs.open(&stats).await));
+        assert_eq!(server.requests().len(), 2);
+
+        // the object is only stored on close, a probe while it is written is forgotten then
+        let mut writer = fs
+            .open_options(&stats, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        assert!(is_not_found(fs.stat(&stats).await));
+        let (result, _) = writer.write_all(&b"fusio"[..]).await;
+        result.unwrap();
+        writer.close().await.unwrap();
+        assert_eq!(fs.stat(&stats).await.unwrap().size, 5);
+    }
+}
+
+#[cfg(all(test, fusio_loom))]
+mod loom_tests {
+    use std::{
+        collections::HashMap,
+        sync::Arc as StdArc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use loom::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::{MissCache, Misses};
+    use crate::{cache::Lru, clock::FixedClock, path::Path};
+
+    fn cache(capacity: usize) -> Arc<MissCache> {
+        Arc::new(MissCache {
+            ttl: Duration::from_secs(60),
+            capacity,
+            clock: StdArc::new(FixedClock::new(UNIX_EPOCH)),
+            misses: Mutex::new(Misses {
+                expires: HashMap::new(),
+                policy: Lru::new(),
+            }),
+        })
+    }
+
+    fn assert_consistent(cache: &MissCache) {
+        let misses = cache.misses.lock().unwrap();
+        assert!(misses.expires.len() <= cache.capacity);
+        assert_eq!(misses.expires.len(), misses.policy.len());
+    }
+
+    #[test]
+    fn loom_capacity_holds() {
+        loom::model(|| {
+            let cache = cache(1);
+            let threads = ["a", "b"]
+                .into_iter()
+                .map(|name| {
+                    let cache = cache.clone();
+                    thread::spawn(move || cache.remember(&Path::from(name)))
+                })
+                .collect::<Vec<_>>();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            assert_consistent(&cache);
+            assert!(cache.is_missing(&Path::from("a")) ^ cache.is_missing(&Path::from("b")));
+        });
+    }
+
+    #[test]
+    fn loom_forget_is_not_lost() {
+        loom::model(|| {
+            let cache = cache(4);
+            let path = Path::from("a");
+            cache.remember(&path);
+
+            let probe = thread::spawn({
+                let cache = cache.clone();
+                let path = path.clone();
+                move || cache.is_missing(&path)
+            });
+            cache.forget(&path);
+            assert!(!cache.is_missing(&path));
+            probe.join().unwrap();
+
+            assert_consistent(&cache);
+            assert!(!cache.is_missing(&path));
+        });
+    }
+
+    #[test]
+    fn loom_remember_and_forget_stay_in_sync() {
+        loom::model(|| {
+            let cache = cache(4);
+            let path = Path::from("a");
+
+            let remember = thread::spawn({
+                let cache = cache.clone();
+                let path = path.clone();
+                move || cache.remember(&path)
+            });
+            cache.forget(&path);
+            remember.join().unwrap();
+
+            // either order is fine, but the eviction order must agree with the paths
+            assert_consistent(&cache);
+        });
+    }
+}
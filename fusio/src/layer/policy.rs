@@ -0,0 +1,245 @@
+use futures_core::Stream;
+
+use super::FsLayer;
+use crate::{
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::{self, Path},
+    DynFs, Error,
+};
+
+/// Rules every path passed to a [`PolicyFs`] has to follow. The default allows every path.
+#[derive(Debug, Clone, Default)]
+pub struct PathPolicy {
+    /// Maximum length of the whole path in bytes, e.g. 1024 for S3 keys.
+    pub max_len: Option<usize>,
+    /// Characters allowed in paths besides the `/` delimiter.
+    pub allowed_chars: Option<fn(char) -> bool>,
+    /// Paths have to be below one of these prefixes, unless the list is empty.
+    pub required_prefixes: Vec<Path>,
+    /// Rejects `.` and `..` segments, also percent-encoded, and segments containing `\`, which
+    /// some consumers of the keys interpret as path traversal.
+    pub deny_traversal: bool,
+}
+
+impl PathPolicy {
+    /// Allows only the characters that are safe in keys of every object store: ASCII letters,
+    /// digits and `!-_.*'()`, see the S3 object key naming guidelines. Rejects traversal
+    /// sequences and paths longer than 1024 bytes.
+    pub fn portable() -> Self {
+        Self {
+            max_len: Some(1024),
+            allowed_chars: Some(|c| c.is_ascii_alphanumeric() || "!-_.*'()".contains(c)),
+            required_prefixes: Vec::new(),
+            deny_traversal: true,
+        }
+    }
+
+    /// Checks `path` against every rule and describes the first one it violates.
+    pub fn validate(&self, path: &Path) -> Result<(), path::Error> {
+        let raw = path.as_ref();
+        let violation = |reason: String| path::Error::PolicyViolation {
+            path: raw.to_string(),
+            reason,
+        };
+
+        if let Some(max_len) = self.max_len.filter(|max_len| raw.len() > *max_len) {
+            return Err(violation(format!(
+                "{} bytes exceed the maximum of {max_len}",
+                raw.len()
+            )));
+        }
+        if let Some(allowed) = self.allowed_chars {
+            if let Some(c) = raw.chars().find(|c| *c != '/' && !allowed(*c)) {
+                return Err(violation(format!("character {c:?} is not allowed")));
+            }
+        }
+        if !self.required_prefixes.is_empty()
+            && !self
+                .required_prefixes
+                .iter()
+                .any(|prefix| path.prefix_matches(prefix))
+        {
+            return Err(violation("not below any of the allowed prefixes".into()));
+        }
+        if self.deny_traversal {
+            let traversal = path.parts().any(|part| {
+                let part = part.as_ref().to_ascii_lowercase().replace("%2e", ".");
+                part == "." || part == ".." || part.contains('\\') || part.contains("%5c")
+            });
+            if traversal {
+                return Err(violation("contains a path traversal sequence".into()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Validates every path against a [`PathPolicy`] before it reaches the backend, so invalid
+/// paths of one tenant are rejected early with [`path::Error::PolicyViolation`] instead of
+/// failing somewhere in the backend or escaping the space of the tenant.
+///
+/// Prefixes of listings and temporary files are validated like every other path.
+pub struct PolicyFs {
+    inner: Box<dyn DynFs>,
+    policy: PathPolicy,
+}
+
+impl PolicyFs {
+    pub fn new<F>(fs: F, policy: PathPolicy) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            policy,
+        }
+    }
+}
+
+impl Fs for PolicyFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        self.policy.validate(path)?;
+        DynFs::open_options(self.inner.as_ref(), path, options).await
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        self.policy.validate(path)?;
+        DynFs::create_dir_all(self.inner.as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        self.policy.validate(path)?;
+        Fs::list(&self.inner, path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        self.policy.validate(path)?;
+        Fs::list_options(&self.inner, path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        self.policy.validate(path)?;
+        DynFs::remove(self.inner.as_ref(), path).await
+    }
+
+    /// Removes the valid paths in one batch, the invalid ones are reported as failed.
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let mut failed = Vec::new();
+        let mut valid = Vec::with_capacity(paths.len());
+        for path in paths {
+            match self.policy.validate(&path) {
+                Ok(()) => valid.push(path),
+                Err(e) => failed.push((path, e.into())),
+            }
+        }
+        failed.extend(DynFs::remove_batch(self.inner.as_ref(), valid).await?);
+        Ok(failed)
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        self.policy.validate(from)?;
+        self.policy.validate(to)?;
+        DynFs::copy(self.inner.as_ref(), from, to).await
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        self.policy.validate(from)?;
+        self.policy.validate(to)?;
+        DynFs::rename_new(self.inner.as_ref(), from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        self.policy.validate(path)?;
+        DynFs::stat(self.inner.as_ref(), path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        self.policy.validate(prefix)?;
+        DynFs::create_temp(self.inner.as_ref(), prefix).await
+    }
+
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        self.policy.validate(&path)?;
+        DynFs::watch(self.inner.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+/// Wraps a stack in a [`PolicyFs`], see [`super::Stack::layer_dyn`].
+#[derive(Debug, Clone, Default)]
+pub struct PolicyLayer {
+    pub policy: PathPolicy,
+}
+
+impl FsLayer for PolicyLayer {
+    fn layer(&self, inner: Box<dyn DynFs>) -> Box<dyn DynFs> {
+        Box::new(PolicyFs::new(inner, self.policy.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PathPolicy;
+    use crate::path::{Error, Path};
+
+    #[test]
+    fn validates_paths() {
+        let policy = PathPolicy {
+            required_prefixes: vec![Path::parse("tenants/a").unwrap()],
+            ..PathPolicy::portable()
+        };
+        let reason = |path: Path| match policy.validate(&path) {
+            Ok(()) => None,
+            Err(Error::PolicyViolation { reason, .. }) => Some(reason),
+            Err(e) => panic!("unexpected error: {e}"),
+        };
+
+        assert_eq!(
+            reason(Path::parse("tenants/a/data-1.parquet").unwrap()),
+            None
+        );
+        assert_eq!(
+            reason(Path::parse("tenants/b/data").unwrap()).unwrap(),
+            "not below any of the allowed prefixes"
+        );
+        assert_eq!(
+            reason(Path::parse("tenants/a/da ta").unwrap()).unwrap(),
+            "character ' ' is not allowed"
+        );
+        assert_eq!(
+            reason(Path::parse("tenants/a/%2E%2E/b").unwrap()).unwrap(),
+            "character '%' is not allowed"
+        );
+        assert_eq!(
+            reason(Path::from_iter(["tenants", "a", "x".repeat(1024).as_str()])).unwrap(),
+            "1034 bytes exceed the maximum of 1024"
+        );
+
+        let policy = PathPolicy {
+            deny_traversal: true,
+            ..Default::default()
+        };
+        assert!(policy
+            .validate(&Path::parse("a/%2e%2E/b").unwrap())
+            .is_err());
+        assert!(policy.validate(&Path::parse("a\\..\\b").unwrap()).is_err());
+        assert!(policy.validate(&Path::parse("a/b..c").unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,660 @@
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    task::TaskGroup,
+    util::{bulk::copy, walk::walk},
+    DynFs, Error, IoBuf, IoBufMut, MaybeSend, Read, Write,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Put,
+    Remove,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Change {
+    op: Op,
+    /// When the oldest change not replicated yet was made.
+    since: SystemTime,
+    version: u64,
+    attempts: u32,
+}
+
+#[derive(Default)]
+struct Queue {
+    /// The changes every replica is behind, in the order of the replicas.
+    pending: Vec<HashMap<Path, Change>>,
+    version: u64,
+}
+
+impl Queue {
+    fn push(&mut self, path: &Path, op: Op, now: SystemTime) {
+        for index in 0..self.pending.len() {
+            self.push_to(index, path, op, now);
+        }
+    }
+
+    fn push_to(&mut self, index: usize, path: &Path, op: Op, now: SystemTime) {
+        self.version += 1;
+        let change = self.pending[index].entry(path.clone()).or_insert(Change {
+            op,
+            since: now,
+            version: 0,
+            attempts: 0,
+        });
+        change.op = op;
+        change.version = self.version;
+    }
+}
+
+/// How far a replica of a [`ReplicatedFs`] is behind the primary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationLag {
+    pub replica: String,
+    /// Paths whose latest change has not been applied to the replica yet.
+    pub pending: usize,
+    /// Time since the oldest pending change was made, `None` when the replica is up to date.
+    pub oldest: Option<Duration>,
+    /// Failed attempts of the pending change that failed most often.
+    pub max_attempts: u32,
+}
+
+struct Replica {
+    name: String,
+    fs: Box<dyn DynFs>,
+}
+
+/// Writes to a primary backend synchronously and replicates the changes to secondary backends
+/// in the background, e.g. to keep a copy of a bucket in another region for disaster recovery.
+///
+/// A file is queued for replication once it is closed after writing, and removals once the
+/// primary removed the file. Reads and listings are served by the primary only. Changes are
+/// applied by [`ReplicatedFs::replicate`], usually driven by [`ReplicatedFs::spawn_replication`];
+/// failed changes stay queued and are retried by the next run, and [`ReplicatedFs::lag`] reports
+/// how far every replica is behind. Replicas copy the content the primary has when the change is
+/// applied, so several writes of a file in between are replicated once.
+///
+/// The queue only lives in memory: changes still pending when the process crashes or is killed
+/// are lost, and so are changes made while a replica is not added yet. Run
+/// [`ReplicatedFs::reconcile`] after a restart to queue whatever the replicas miss.
+pub struct ReplicatedFs {
+    primary: Box<dyn DynFs>,
+    replicas: Vec<Replica>,
+    clock: Arc<dyn Clock>,
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl ReplicatedFs {
+    pub fn new<F>(primary: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            primary: Box::new(primary),
+            replicas: Vec::new(),
+            clock: Arc::new(SystemClock),
+            queue: Arc::new(Mutex::new(Queue::default())),
+        }
+    }
+
+    /// Adds `fs` as the replica `name`. Only changes made after adding it are replicated to it.
+    pub fn replica<F>(mut self, name: impl Into<String>, fs: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        self.replicas.push(Replica {
+            name: name.into(),
+            fs: Box::new(fs),
+        });
+        self.queue.lock().unwrap().pending.push(HashMap::new());
+        self
+    }
+
+    /// Measures the lag with `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// How far every replica is behind the primary, in the order the replicas were added.
+    pub fn lag(&self) -> Vec<ReplicationLag> {
+        let now = self.clock.now();
+        let queue = self.queue.lock().unwrap();
+        self.replicas
+            .iter()
+            .zip(&queue.pending)
+            .map(|(replica, pending)| ReplicationLag {
+                replica: replica.name.clone(),
+                pending: pending.len(),
+                oldest: pending
+                    .values()
+                    .map(|change| now.duration_since(change.since).unwrap_or_default())
+                    .max(),
+                max_attempts: pending
+                    .values()
+                    .map(|change| change.attempts)
+                    .max()
+                    .unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// Applies the pending changes to every replica and returns how many were applied.
+    ///
+    /// A failed change is kept for the next run and does not stop the other changes, the first
+    /// error is returned once every change was attempted.
+    pub async fn replicate(&self) -> Result<usize, Error> {
+        let mut applied = 0;
+        let mut first_error = None;
+        for (index, replica) in self.replicas.iter().enumerate() {
+            let changes = self.queue.lock().unwrap().pending[index]
+                .iter()
+                .map(|(path, change)| (path.clone(), *change))
+                .collect::<Vec<_>>();
+
+            for (path, change) in changes {
+                let result = self.apply(replica, &path, change.op).await;
+
+                let mut queue = self.queue.lock().unwrap();
+                let Some(pending) = queue.pending[index].get_mut(&path) else {
+                    continue;
+                };
+                // changed again while being applied, keep the newer change queued
+                if pending.version != change.version {
+                    continue;
+                }
+                match result {
+                    Ok(()) => {
+                        queue.pending[index].remove(&path);
+                        applied += 1;
+                    }
+                    Err(e) => {
+                        pending.attempts += 1;
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(applied),
+        }
+    }
+
+    async fn apply(&self, replica: &Replica, path: &Path, op: Op) -> Result<(), Error> {
+        match op {
+            // removed from the primary without the removal being queued, e.g. a temporary file
+            // removed on drop, so there is nothing left to replicate
+            Op::Put => match copy(&self.primary, &replica.fs, path, path).await {
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                    match DynFs::stat(self.primary.as_ref(), path).await {
+                        Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                        _ => Err(e.into()),
+                    }
+                }
+                result => result,
+            },
+            Op::Remove => match DynFs::remove(replica.fs.as_ref(), path).await {
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+        }
+    }
+
+    /// Compares every replica with the primary below `prefix` and queues the changes that make
+    /// them match, returning how many were queued. Apply them with [`ReplicatedFs::replicate`].
+    ///
+    /// A file is queued for copying when the replica misses it, has a different size, or was
+    /// last modified before the primary's file, and queued for removal when the primary does not
+    /// have it anymore. Files of the same size whose modification times cannot be compared are
+    /// considered up to date.
+    pub async fn reconcile(&self, prefix: &Path) -> Result<usize, Error> {
+        let mut primary = HashMap::new();
+        walk(&self.primary, prefix, |meta| {
+            primary.insert(meta.path.clone(), meta);
+        })
+        .await?;
+
+        let mut queued = 0;
+        for (index, replica) in self.replicas.iter().enumerate() {
+            let mut replicated = HashMap::new();
+            match walk(&replica.fs, prefix, |meta| {
+                replicated.insert(meta.path.clone(), meta);
+            })
+            .await
+            {
+                // nothing replicated below the prefix yet
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {}
+                result => result?,
+            }
+
+            let now = self.clock.now();
+            let mut queue = self.queue.lock().unwrap();
+            for (path, meta) in &primary {
+                let stale = replicated.get(path).is_none_or(|copy| {
+                    copy.size != meta.size
+                        || matches!(
+                            (copy.last_modified, meta.last_modified),
+                            (Some(copied), Some(modified)) if copied < modified
+                        )
+                });
+                if stale {
+                    queue.push_to(index, path, Op::Put, now);
+                    queued += 1;
+                }
+            }
+            for path in replicated
+                .keys()
+                .filter(|path| !primary.contains_key(*path))
+            {
+                queue.push_to(index, path, Op::Remove, now);
+                queued += 1;
+            }
+        }
+        Ok(queued)
+    }
+
+    /// Runs [`ReplicatedFs::replicate`] in the background of `tasks` every time `ticks` yields,
+    /// until `ticks` ends or the group is shut down. Failed changes are retried on the next tick.
+    pub fn spawn_replication<T>(self: &Arc<Self>, tasks: &TaskGroup, ticks: T)
+    where
+        T: Stream + Unpin + MaybeSend + 'static,
+    {
+        let fs = self.clone();
+        tasks.spawn(move |shutdown| async move {
+            let mut ticks = shutdown.take_until(ticks);
+            while ticks.next().await.is_some() {
+                let _ = fs.replicate().await;
+            }
+        });
+    }
+}
+
+impl Fs for ReplicatedFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        let write = options.write;
+        let file = DynFs::open_options(self.primary.as_ref(), path, options).await?;
+        if !write {
+            return Ok(file);
+        }
+        Ok(Box::new(ReplicatedFile {
+            inner: file,
+            path: path.clone(),
+            clock: self.clock.clone(),
+            queue: self.queue.clone(),
+        }))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        DynFs::create_dir_all(self.primary.as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list(&self.primary, path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list_options(&self.primary, path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        DynFs::remove(self.primary.as_ref(), path).await?;
+        let now = self.clock.now();
+        self.queue.lock().unwrap().push(path, Op::Remove, now);
+        Ok(())
+    }
+
+    /// Removes the paths from the primary in one batch and queues the removal of the ones that
+    /// were removed for the replicas.
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let failed = DynFs::remove_batch(self.primary.as_ref(), paths.clone()).await?;
+        let now = self.clock.now();
+        let mut queue = self.queue.lock().unwrap();
+        for path in paths
+            .iter()
+            .filter(|path| !failed.iter().any(|(failed, _)| failed == *path))
+        {
+            queue.push(path, Op::Remove, now);
+        }
+        drop(queue);
+        Ok(failed)
+    }
+
+    /// Copies on the primary and queues the copy like a file written to `to`.
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::copy(self.primary.as_ref(), from, to).await?;
+        let now = self.clock.now();
+        self.queue.lock().unwrap().push(to, Op::Put, now);
+        Ok(())
+    }
+
+    /// Moves on the primary and queues the move like a file written to `to` and a removal of
+    /// `from`.
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::rename_new(self.primary.as_ref(), from, to).await?;
+        let now = self.clock.now();
+        let mut queue = self.queue.lock().unwrap();
+        queue.push(to, Op::Put, now);
+        queue.push(from, Op::Remove, now);
+        Ok(())
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        DynFs::stat(self.primary.as_ref(), path).await
+    }
+
+    /// Creates the file on the primary, queued for replication once it is closed like any file
+    /// written. A file the guard removes on drop is not replicated anymore.
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let (file, guard) = DynFs::create_temp(self.primary.as_ref(), prefix).await?;
+        let file = Box::new(ReplicatedFile {
+            inner: file,
+            path: guard.path().clone(),
+            clock: self.clock.clone(),
+            queue: self.queue.clone(),
+        });
+        Ok((file, guard))
+    }
+
+    /// Applies the pending changes with [`ReplicatedFs::replicate`], then shuts down the primary
+    /// and every replica, even if some of them fail, and returns the first error. Changes that
+    /// still fail stay queued.
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.primary.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        let mut result = self.replicate().await.map(|_| ());
+        let shutdown = DynFs::shutdown(self.primary.as_ref()).await;
+        result = result.and(shutdown);
+        for replica in &self.replicas {
+            let shutdown = DynFs::shutdown(replica.fs.as_ref()).await;
+            result = result.and(shutdown);
+        }
+        result
+    }
+}
+
+/// A file of the primary opened for writing, queued for replication once it is closed.
+struct ReplicatedFile {
+    inner: Box<dyn DynFile>,
+    path: Path,
+    clock: Arc<dyn Clock>,
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl Read for ReplicatedFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        self.inner.read_exact_at(buf, pos).await
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        self.inner.read_to_end_at(buf, pos).await
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        self.inner.size().await
+    }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        self.inner.read_at(buf, pos).await
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        self.inner.read_batch(ranges).await
+    }
+}
+
+impl Write for ReplicatedFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        self.inner.write_all(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush().await
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        self.inner.close().await?;
+        let now = self.clock.now();
+        self.queue.lock().unwrap().push(&self.path, Op::Put, now);
+        Ok(())
+    }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        self.inner.write_batch(bufs).await
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        self.inner.barrier().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn replicates_in_the_background() {
+        use std::{sync::Arc, time::Duration};
+
+        use tempfile::TempDir;
+
+        use super::ReplicatedFs;
+        use crate::{
+            clock::{OffsetClock, SystemClock},
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            layer::RouterFs,
+            path::Path,
+            Write,
+        };
+
+        let root = |dir: &TempDir| {
+            RouterFs::new().mount(
+                Path::default(),
+                TokioFs,
+                Path::from_absolute_path(dir.path()).unwrap(),
+            )
+        };
+        let primary = TempDir::new().unwrap();
+        let secondary = TempDir::new().unwrap();
+        // the root of the broken replica is a file, so every change fails
+        let broken = TempDir::new().unwrap();
+        std::fs::write(broken.path().join("root"), b"").unwrap();
+        let clock = Arc::new(OffsetClock::new(SystemClock));
+        let fs = ReplicatedFs::new(root(&primary))
+            .replica("secondary", root(&secondary))
+            .replica(
+                "broken",
+                RouterFs::new().mount(
+                    Path::default(),
+                    TokioFs,
+                    Path::from_absolute_path(broken.path().join("root")).unwrap(),
+                ),
+            )
+            .with_clock(clock.clone());
+
+        let path = Path::parse("data/file").unwrap();
+        fs.create_dir_all(&Path::parse("data").unwrap())
+            .await
+            .unwrap();
+        let mut file = fs
+            .open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        file.write_all(&b"fusio"[..]).await.0.unwrap();
+        assert_eq!(fs.lag()[0].pending, 0);
+        file.close().await.unwrap();
+        assert!(primary.path().join("data/file").exists());
+        assert!(!secondary.path().join("data/file").exists());
+
+        clock.set_offset_millis(5_000);
+        let lag = fs.lag();
+        assert_eq!(lag[0].pending, 1);
+        assert!(lag[0].oldest.unwrap() >= Duration::from_secs(5));
+
+        assert!(fs.replicate().await.is_err());
+        assert_eq!(
+            std::fs::read(secondary.path().join("data/file")).unwrap(),
+            b"fusio"
+        );
+        let lag = fs.lag();
+        assert_eq!((lag[0].pending, lag[0].oldest), (0, None));
+        assert_eq!((lag[1].pending, lag[1].max_attempts), (1, 1));
+
+        fs.remove(&path).await.unwrap();
+        assert!(secondary.path().join("data/file").exists());
+        assert!(fs.replicate().await.is_err());
+        assert!(!secondary.path().join("data/file").exists());
+        assert_eq!((fs.lag()[1].pending, fs.lag()[1].max_attempts), (1, 2));
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn replicates_temporary_files_while_they_exist() {
+        use tempfile::TempDir;
+
+        use super::ReplicatedFs;
+        use crate::{disk::TokioFs, fs::Fs, layer::RouterFs, path::Path, Write};
+
+        let primary = TempDir::new().unwrap();
+        let secondary = TempDir::new().unwrap();
+        let fs = ReplicatedFs::new(TokioFs).replica(
+            "secondary",
+            RouterFs::new().mount(
+                Path::from_absolute_path(primary.path()).unwrap(),
+                TokioFs,
+                Path::from_absolute_path(secondary.path()).unwrap(),
+            ),
+        );
+        let prefix = Path::from_absolute_path(primary.path().join("spill")).unwrap();
+
+        let (mut file, kept) = fs.create_temp(&prefix).await.unwrap();
+        file.write_all(&b"fusio"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+        let kept = kept.keep().unwrap();
+        let (mut file, dropped) = fs.create_temp(&prefix).await.unwrap();
+        file.close().await.unwrap();
+        drop(dropped);
+        assert_eq!(fs.lag()[0].pending, 2);
+
+        assert_eq!(fs.replicate().await.unwrap(), 2);
+        let name = kept.filename().unwrap();
+        assert_eq!(
+            std::fs::read(secondary.path().join(name)).unwrap(),
+            b"fusio"
+        );
+        assert_eq!(std::fs::read_dir(secondary.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn shutdown_applies_pending_changes() {
+        use tempfile::TempDir;
+
+        use super::ReplicatedFs;
+        use crate::{
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            layer::RouterFs,
+            path::Path,
+            Write,
+        };
+
+        let primary = TempDir::new().unwrap();
+        let secondary = TempDir::new().unwrap();
+        let fs = ReplicatedFs::new(TokioFs).replica(
+            "secondary",
+            RouterFs::new().mount(
+                Path::from_absolute_path(primary.path()).unwrap(),
+                TokioFs,
+                Path::from_absolute_path(secondary.path()).unwrap(),
+            ),
+        );
+
+        let path = Path::from_absolute_path(primary.path().join("queued")).unwrap();
+        let mut file = fs
+            .open_options(&path, OpenOptions::default().write(true).create(true))
+            .await
+            .unwrap();
+        file.write_all(&b"fusio"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+        assert_eq!(fs.lag()[0].pending, 1);
+
+        fs.shutdown().await.unwrap();
+        assert_eq!(fs.lag()[0].pending, 0);
+        assert_eq!(
+            std::fs::read(secondary.path().join("queued")).unwrap(),
+            b"fusio"
+        );
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn reconcile_queues_what_replicas_miss() {
+        use tempfile::TempDir;
+
+        use super::ReplicatedFs;
+        use crate::{disk::TokioFs, layer::RouterFs, path::Path};
+
+        let primary = TempDir::new().unwrap();
+        let secondary = TempDir::new().unwrap();
+        // changes made before a restart, their queue is gone
+        std::fs::write(primary.path().join("new"), b"fusio").unwrap();
+        std::fs::write(primary.path().join("changed"), b"fusio").unwrap();
+        std::fs::write(primary.path().join("same"), b"fusio").unwrap();
+        std::fs::write(secondary.path().join("changed"), b"old").unwrap();
+        std::fs::write(secondary.path().join("same"), b"fusio").unwrap();
+        std::fs::write(secondary.path().join("removed"), b"old").unwrap();
+        let fs = ReplicatedFs::new(TokioFs).replica(
+            "secondary",
+            RouterFs::new().mount(
+                Path::from_absolute_path(primary.path()).unwrap(),
+                TokioFs,
+                Path::from_absolute_path(secondary.path()).unwrap(),
+            ),
+        );
+
+        let prefix = Path::from_absolute_path(primary.path()).unwrap();
+        assert_eq!(fs.reconcile(&prefix).await.unwrap(), 3);
+        assert_eq!(fs.replicate().await.unwrap(), 3);
+        let mut names = std::fs::read_dir(secondary.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["changed", "new", "same"]);
+        assert_eq!(
+            std::fs::read(secondary.path().join("changed")).unwrap(),
+            b"fusio"
+        );
+        assert_eq!(fs.reconcile(&prefix).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,485 @@
+use std::{io, pin::pin};
+
+use async_stream::stream;
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::{
+    dynamic::DynFile,
+    fs::{rename_by_copy, temp_path, FileMeta, Fs, ListOptions, OpenOptions, TempGuard},
+    path::Path,
+    util::bulk::copy,
+    DynFs, Error,
+};
+
+struct Mount {
+    prefix: Path,
+    root: Path,
+    fs: Box<dyn DynFs>,
+}
+
+impl Mount {
+    fn to_backend(&self, path: &Path) -> Option<Path> {
+        let rest = path.prefix_match(&self.prefix)?;
+        Some(Path::from_iter(self.root.parts().chain(rest)))
+    }
+
+    fn to_router(&self, path: Path) -> Path {
+        let routed = path
+            .prefix_match(&self.root)
+            .map(|rest| Path::from_iter(self.prefix.parts().chain(rest)));
+        routed.unwrap_or(path)
+    }
+}
+
+/// Presents several backends as a single namespace by dispatching every operation on the
+/// longest mounted prefix of its path, e.g. `hot/**` to a local disk and `cold/**` to S3.
+///
+/// The mount prefix is replaced by the root of the backend, so listings report paths below the
+/// prefix again. Listing a path above the mount points does not merge the backends, and files
+/// created by [`Fs::create_temp`] are not removed on drop.
+#[derive(Default)]
+pub struct RouterFs {
+    mounts: Vec<Mount>,
+}
+
+impl RouterFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes everything under `prefix` to `root` on `fs`, replacing an earlier mount of the same
+    /// prefix.
+    pub fn mount<F>(mut self, prefix: Path, fs: F, root: Path) -> Self
+    where
+        F: Fs + 'static,
+    {
+        self.mounts.retain(|mount| mount.prefix != prefix);
+        self.mounts.push(Mount {
+            prefix,
+            root,
+            fs: Box::new(fs),
+        });
+        self.mounts
+            .sort_by_key(|mount| std::cmp::Reverse(mount.prefix.parts().count()));
+        self
+    }
+
+    /// The index of the mount `path` is routed to and the path on its backend.
+    fn locate(&self, path: &Path) -> Option<(usize, Path)> {
+        self.mounts
+            .iter()
+            .enumerate()
+            .find_map(|(index, mount)| Some((index, mount.to_backend(path)?)))
+    }
+
+    fn route(&self, path: &Path) -> Result<(&Mount, Path), Error> {
+        let (index, routed) = self.locate(path).ok_or_else(|| not_mounted(path))?;
+        Ok((&self.mounts[index], routed))
+    }
+}
+
+fn not_mounted(path: &Path) -> Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no backend is mounted for \"{path}\""),
+    )
+    .into()
+}
+
+impl Fs for RouterFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        let (mount, path) = self.route(path)?;
+        DynFs::open_options(mount.fs.as_ref(), &path, options).await
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        let (mount, path) = self.route(path)?;
+        DynFs::create_dir_all(mount.fs.as_ref(), &path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let (mount, path) = self.route(path)?;
+
+        Ok(stream! {
+            let mut listing = DynFs::list(mount.fs.as_ref(), &path).await?;
+            while let Some(meta) = listing.next().await {
+                yield meta.map(|meta| FileMeta {
+                    path: mount.to_router(meta.path),
+                    ..meta
+                });
+            }
+        })
+    }
+
+    /// Pages on the backend of the mount, with the page token routed like the path. A token
+    /// outside the mount is applied to the full listing instead.
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let (mount, routed) = self.route(path)?;
+        let page_token = match &options.page_token {
+            Some(token) => Path::parse(token)
+                .ok()
+                .and_then(|token| mount.to_backend(&token))
+                .map(|token| Some(token.to_string())),
+            None => Some(None),
+        };
+        let path = path.clone();
+
+        Ok(stream! {
+            match page_token {
+                Some(page_token) => {
+                    let options = ListOptions { page_token, ..options };
+                    let mut listing =
+                        match DynFs::list_options(mount.fs.as_ref(), &routed, options).await {
+                            Ok(listing) => listing,
+                            Err(e) => {
+                                yield Err(e);
+                                return;
+                            }
+                        };
+                    while let Some(meta) = listing.next().await {
+                        yield meta.map(|meta| FileMeta {
+                            path: mount.to_router(meta.path),
+                            ..meta
+                        });
+                    }
+                }
+                None => {
+                    let listing = match Fs::list(self, &path).await {
+                        Ok(listing) => listing,
+                        Err(e) => {
+                            yield Err(e);
+                            return;
+                        }
+                    };
+                    let mut listing = pin!(options.apply(listing));
+                    while let Some(meta) = listing.next().await {
+                        yield meta;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        let (mount, path) = self.route(path)?;
+        DynFs::remove(mount.fs.as_ref(), &path).await
+    }
+
+    /// Removes the paths of every mount in one batch per mount.
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let mut failed = Vec::new();
+        let mut batches = vec![Vec::new(); self.mounts.len()];
+        for path in paths {
+            match self.locate(&path) {
+                Some((index, routed)) => batches[index].push(routed),
+                None => {
+                    let error = not_mounted(&path);
+                    failed.push((path, error));
+                }
+            }
+        }
+        for (mount, batch) in self.mounts.iter().zip(batches) {
+            if batch.is_empty() {
+                continue;
+            }
+            let removed = DynFs::remove_batch(mount.fs.as_ref(), batch).await?;
+            failed.extend(
+                removed
+                    .into_iter()
+                    .map(|(path, error)| (mount.to_router(path), error)),
+            );
+        }
+        Ok(failed)
+    }
+
+    /// Copies on the backend when both paths are on the same mount, and by reading and writing
+    /// the file otherwise.
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let (source, from) = self.locate(from).ok_or_else(|| not_mounted(from))?;
+        let (target, to) = self.locate(to).ok_or_else(|| not_mounted(to))?;
+        let (source_fs, target_fs) = (&self.mounts[source].fs, &self.mounts[target].fs);
+        if source == target {
+            return DynFs::copy(source_fs.as_ref(), &from, &to).await;
+        }
+        copy(source_fs, target_fs, &from, &to).await
+    }
+
+    /// Moves on the backend when both paths are on the same mount. Otherwise the file is written
+    /// to the target mount with `create_new` and removed from the source, which is only atomic
+    /// when the target mount is an object store.
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let (source, routed_from) = self.locate(from).ok_or_else(|| not_mounted(from))?;
+        let (target, routed_to) = self.locate(to).ok_or_else(|| not_mounted(to))?;
+        if source == target {
+            let fs = &self.mounts[source].fs;
+            return DynFs::rename_new(fs.as_ref(), &routed_from, &routed_to).await;
+        }
+        rename_by_copy(self, from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        let (mount, path) = self.route(path)?;
+        let meta = DynFs::stat(mount.fs.as_ref(), &path).await?;
+        Ok(FileMeta {
+            path: mount.to_router(meta.path),
+            ..meta
+        })
+    }
+
+    /// Creates the file on the mount of `prefix`. The guard reports the path on the router and
+    /// does not remove the file on drop.
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let (mount, routed) = self.route(prefix)?;
+        // the file is named `{prefix}-{uuid}`, so for a mount point it lies next to the mount
+        if mount.prefix == *prefix {
+            let path = temp_path(prefix)?;
+            let options = OpenOptions::default().create(true).truncate(true);
+            let file = Fs::open_options(self, &path, options).await?;
+            return Ok((file, TempGuard::new(path)));
+        }
+        let (file, guard) = DynFs::create_temp(mount.fs.as_ref(), &routed).await?;
+        Ok((file, TempGuard::new(mount.to_router(guard.keep()?))))
+    }
+
+    /// Shuts down every mounted backend, even if some of them fail, and returns the first error.
+    async fn shutdown(&self) -> Result<(), Error> {
+        let mut result = Ok(());
+        for mount in &self.mounts {
+            let shutdown = DynFs::shutdown(mount.fs.as_ref()).await;
+            result = result.and(shutdown);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn routes_by_longest_prefix() {
+        use std::{io, pin::pin};
+
+        use futures_util::StreamExt;
+        use tempfile::TempDir;
+
+        use super::RouterFs;
+        use crate::{
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+            Error, Write,
+        };
+
+        let hot = TempDir::new().unwrap();
+        let cold = TempDir::new().unwrap();
+        let fs = RouterFs::new()
+            .mount(
+                Path::parse("data").unwrap(),
+                TokioFs,
+                Path::from_absolute_path(cold.path()).unwrap(),
+            )
+            .mount(
+                Path::parse("data/hot").unwrap(),
+                TokioFs,
+                Path::from_absolute_path(hot.path()).unwrap(),
+            );
+
+        for path in ["data/hot/a", "data/b"] {
+            let mut file = fs
+                .open_options(
+                    &Path::parse(path).unwrap(),
+                    OpenOptions::default().create(true),
+                )
+                .await
+                .unwrap();
+            file.write_all(&b"fusio"[..]).await.0.unwrap();
+            file.close().await.unwrap();
+        }
+        assert!(hot.path().join("a").exists());
+        assert!(cold.path().join("b").exists());
+
+        let hot_dir = Path::parse("data/hot").unwrap();
+        let mut listing = pin!(fs.list(&hot_dir).await.unwrap());
+        let meta = listing.next().await.unwrap().unwrap();
+        assert_eq!(meta.path.as_ref(), "data/hot/a");
+        assert_eq!(meta.size, 5);
+        assert!(listing.next().await.is_none());
+
+        let err = fs.remove(&Path::parse("other/c").unwrap()).await;
+        assert!(matches!(err, Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound));
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn shutdown_reaches_every_mount() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        use futures_core::Stream;
+
+        use super::RouterFs;
+        use crate::{
+            disk::TokioFs,
+            fs::{FileMeta, Fs, OpenOptions},
+            path::Path,
+            Error,
+        };
+
+        struct Counting(TokioFs, Arc<AtomicUsize>);
+
+        impl Fs for Counting {
+            type File = <TokioFs as Fs>::File;
+
+            async fn open_options(
+                &self,
+                path: &Path,
+                options: OpenOptions,
+            ) -> Result<Self::File, Error> {
+                self.0.open_options(path, options).await
+            }
+
+            async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+                self.0.create_dir_all(path).await
+            }
+
+            async fn list(
+                &self,
+                path: &Path,
+            ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+                self.0.list(path).await
+            }
+
+            async fn remove(&self, path: &Path) -> Result<(), Error> {
+                self.0.remove(path).await
+            }
+
+            async fn shutdown(&self) -> Result<(), Error> {
+                self.1.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+
+        let shutdowns = Arc::new(AtomicUsize::new(0));
+        let fs = RouterFs::new()
+            .mount(
+                Path::parse("a").unwrap(),
+                Counting(TokioFs, shutdowns.clone()),
+                Path::default(),
+            )
+            .mount(
+                Path::parse("b").unwrap(),
+                Counting(TokioFs, shutdowns.clone()),
+                Path::default(),
+            );
+
+        fs.shutdown().await.unwrap();
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn removes_batches_per_mount() {
+        use std::{io, sync::Arc};
+
+        use http::Method;
+
+        use super::RouterFs;
+        use crate::{fs::Fs, path::Path, remotes::aws::mock::MockS3, Error};
+
+        let a = Arc::new(MockS3::default());
+        let b = Arc::new(MockS3::default());
+        let fs = RouterFs::new()
+            .mount(Path::parse("a").unwrap(), a.fs(), Path::default())
+            .mount(
+                Path::parse("b").unwrap(),
+                b.fs(),
+                Path::parse("root").unwrap(),
+            );
+        for key in ["1", "2"] {
+            a.put(key, "x");
+            b.put(&format!("root/{key}"), "x");
+        }
+
+        let paths = ["a/1", "b/1", "a/2", "b/2", "c/1"]
+            .into_iter()
+            .map(|path| Path::parse(path).unwrap())
+            .collect();
+        let failed = fs.remove_batch(paths).await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0.as_ref(), "c/1");
+        assert!(matches!(&failed[0].1, Error::Io(e) if e.kind() == io::ErrorKind::NotFound));
+
+        for server in [&a, &b] {
+            let deletes = server
+                .requests()
+                .into_iter()
+                .filter(|(method, uri)| *method == Method::POST && uri.ends_with("?delete"))
+                .count();
+            assert_eq!(deletes, 1);
+        }
+        assert!(a.get("1").is_none() && a.get("2").is_none());
+        assert!(b.get("root/1").is_none() && b.get("root/2").is_none());
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn pages_on_the_mount() {
+        use std::{pin::pin, sync::Arc};
+
+        use futures_util::StreamExt;
+        use http::Method;
+
+        use super::RouterFs;
+        use crate::{
+            fs::{Fs, ListOptions},
+            path::Path,
+            remotes::aws::mock::MockS3,
+        };
+
+        let server = Arc::new(MockS3::default());
+        let fs = RouterFs::new().mount(
+            Path::parse("b").unwrap(),
+            server.fs(),
+            Path::parse("root").unwrap(),
+        );
+        for key in ["1", "2", "3"] {
+            server.put(&format!("root/{key}"), "x");
+        }
+
+        let options = ListOptions::default().page_token("b/1").limit(1);
+        let dir = Path::parse("b").unwrap();
+        let mut listing = pin!(fs.list_options(&dir, options).await.unwrap());
+        let meta = listing.next().await.unwrap().unwrap();
+        assert_eq!(meta.path.as_ref(), "b/2");
+        assert!(listing.next().await.is_none());
+        assert!(server
+            .requests()
+            .iter()
+            .any(|(_, uri)| uri.contains("start-after=root%2F1")));
+
+        let (_, guard) = fs
+            .create_temp(&Path::parse("b/spill").unwrap())
+            .await
+            .unwrap();
+        assert!(guard.path().as_ref().starts_with("b/spill-"));
+        guard.remove(&fs).await.unwrap();
+        // next to the only mount point, where nothing is mounted
+        assert!(fs.create_temp(&Path::parse("b").unwrap()).await.is_err());
+        assert!(server
+            .requests()
+            .iter()
+            .any(|(method, uri)| *method == Method::DELETE && uri.contains("/root/spill-")));
+    }
+}
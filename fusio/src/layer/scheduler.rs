@@ -0,0 +1,470 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    task::{Poll, Waker},
+};
+
+use futures_core::Stream;
+use futures_util::future::poll_fn;
+
+use crate::{
+    context::{Context, Priority},
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    DynFs, Error, IoBuf, IoBufMut, Read, Write,
+};
+
+/// Calls of a class with weight `w` advance its virtual time by `STRIDE / w`.
+const STRIDE: u64 = 1 << 20;
+const CLASSES: usize = 3;
+const DEFAULT_WEIGHTS: [u64; CLASSES] = [1, 2, 4];
+
+fn class(priority: Priority) -> usize {
+    priority as usize
+}
+
+struct Queue {
+    limit: usize,
+    reserved: [usize; CLASSES],
+    weights: [u64; CLASSES],
+    running: [usize; CLASSES],
+    /// The virtual time of each class, the class furthest behind is served next.
+    pass: [u64; CLASSES],
+    waiting: [VecDeque<u64>; CLASSES],
+    wakers: HashMap<u64, Waker>,
+    granted: HashSet<u64>,
+    next_ticket: u64,
+}
+
+impl Queue {
+    fn can_start(&self, class: usize) -> bool {
+        if self.running.iter().sum::<usize>() >= self.limit {
+            return false;
+        }
+        if self.running[class] < self.reserved[class] {
+            return true;
+        }
+        let shared = self
+            .limit
+            .saturating_sub(self.reserved.iter().sum::<usize>());
+        let shared_used = (0..CLASSES)
+            .map(|class| self.running[class].saturating_sub(self.reserved[class]))
+            .sum::<usize>();
+        shared_used < shared
+    }
+
+    fn enqueue(&mut self, class: usize) -> u64 {
+        // a class that was idle starts at the virtual time of the busy ones instead of catching
+        // up on the time it did not use
+        if self.waiting[class].is_empty() && self.running[class] == 0 {
+            let busy = (0..CLASSES)
+                .filter(|&other| !self.waiting[other].is_empty() || self.running[other] > 0)
+                .map(|other| self.pass[other])
+                .min();
+            if let Some(busy) = busy {
+                self.pass[class] = self.pass[class].max(busy);
+            }
+        }
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.waiting[class].push_back(ticket);
+        ticket
+    }
+
+    /// Grants waiting tickets as long as permits are free, returns the wakers of the granted
+    /// calls to wake once the lock is released.
+    fn dispatch(&mut self) -> Vec<Waker> {
+        let mut wakers = Vec::new();
+        loop {
+            // ties go to the more urgent class
+            let next = (0..CLASSES)
+                .rev()
+                .filter(|&class| !self.waiting[class].is_empty() && self.can_start(class))
+                .min_by_key(|&class| self.pass[class]);
+            let Some(class) = next else {
+                return wakers;
+            };
+            let ticket = self.waiting[class].pop_front().unwrap();
+            self.running[class] += 1;
+            self.pass[class] += STRIDE / self.weights[class];
+            self.granted.insert(ticket);
+            wakers.extend(self.wakers.remove(&ticket));
+        }
+    }
+}
+
+struct Scheduler {
+    queue: Mutex<Queue>,
+}
+
+impl Scheduler {
+    /// Waits until a call of `priority` may start.
+    async fn acquire(self: &Arc<Self>, priority: Priority) -> Permit {
+        let class = class(priority);
+        let (ticket, wakers) = {
+            let mut queue = self.queue.lock().unwrap();
+            let ticket = queue.enqueue(class);
+            (ticket, queue.dispatch())
+        };
+        wakers.into_iter().for_each(Waker::wake);
+
+        let mut waiting = Waiting {
+            scheduler: self,
+            class,
+            ticket,
+            granted: false,
+        };
+        poll_fn(|cx| {
+            let mut queue = self.queue.lock().unwrap();
+            if queue.granted.remove(&ticket) {
+                return Poll::Ready(());
+            }
+            queue.wakers.insert(ticket, cx.waker().clone());
+            Poll::Pending
+        })
+        .await;
+        waiting.granted = true;
+
+        Permit {
+            scheduler: self.clone(),
+            class,
+        }
+    }
+
+    fn release(&self, class: usize) {
+        let wakers = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.running[class] -= 1;
+            queue.dispatch()
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Withdraws a ticket whose call was dropped while it waited.
+struct Waiting<'a> {
+    scheduler: &'a Scheduler,
+    class: usize,
+    ticket: u64,
+    granted: bool,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        let granted = {
+            let mut queue = self.scheduler.queue.lock().unwrap();
+            queue.wakers.remove(&self.ticket);
+            queue.waiting[self.class].retain(|&ticket| ticket != self.ticket);
+            queue.granted.remove(&self.ticket)
+        };
+        if granted {
+            self.scheduler.release(self.class);
+        }
+    }
+}
+
+/// Allows one call to run until it is dropped.
+struct Permit {
+    scheduler: Arc<Scheduler>,
+    class: usize,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.release(self.class);
+    }
+}
+
+fn priority() -> Priority {
+    Context::current()
+        .map(|context| context.priority)
+        .unwrap_or_default()
+}
+
+/// Limits how many calls run against the backend at once and shares the limit between the
+/// [`Priority`] classes of the [`Context`] of each call, so background work like compaction
+/// cannot starve reads a user is waiting for.
+///
+/// Waiting calls are served by weighted fair queuing: while several classes wait, each gets a
+/// share of the started calls proportional to its weight, 4 for [`Priority::High`], 2 for
+/// [`Priority::Normal`] and 1 for [`Priority::Low`] by default. Calls outside of a
+/// [`Context::scope`] run as [`Priority::Normal`]. Permits reserved for a class with
+/// [`SchedulerFs::with_reserved`] are only ever used by that class, so it can start calls even
+/// while the others fill the limit.
+///
+/// Every call on the file system and on files opened through it holds a permit until it
+/// returns, streams of listings do not.
+pub struct SchedulerFs {
+    inner: Box<dyn DynFs>,
+    scheduler: Arc<Scheduler>,
+}
+
+impl SchedulerFs {
+    /// Runs at most `concurrency` calls at once, at least one.
+    pub fn new<F>(fs: F, concurrency: usize) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            scheduler: Arc::new(Scheduler {
+                queue: Mutex::new(Queue {
+                    limit: concurrency.max(1),
+                    reserved: [0; CLASSES],
+                    weights: DEFAULT_WEIGHTS,
+                    running: [0; CLASSES],
+                    pass: [0; CLASSES],
+                    waiting: Default::default(),
+                    wakers: HashMap::new(),
+                    granted: HashSet::new(),
+                    next_ticket: 0,
+                }),
+            }),
+        }
+    }
+
+    /// Sets the share of `priority` while several classes wait, at least one.
+    pub fn with_weight(self, priority: Priority, weight: u32) -> Self {
+        self.scheduler.queue.lock().unwrap().weights[class(priority)] = u64::from(weight.max(1));
+        self
+    }
+
+    /// Keeps `permits` of the concurrency limit for calls of `priority`. Reservations count
+    /// towards the limit, the other classes share what is left.
+    pub fn with_reserved(self, priority: Priority, permits: usize) -> Self {
+        self.scheduler.queue.lock().unwrap().reserved[class(priority)] = permits;
+        self
+    }
+
+    fn file(&self, file: Box<dyn DynFile>) -> Box<dyn DynFile> {
+        Box::new(ScheduledFile {
+            inner: file,
+            scheduler: self.scheduler.clone(),
+        })
+    }
+}
+
+impl Fs for SchedulerFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        let file = DynFs::open_options(self.inner.as_ref(), path, options).await?;
+        Ok(self.file(file))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        DynFs::create_dir_all(self.inner.as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        Fs::list(&self.inner, path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        Fs::list_options(&self.inner, path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        DynFs::remove(self.inner.as_ref(), path).await
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        DynFs::remove_batch(self.inner.as_ref(), paths).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        DynFs::copy(self.inner.as_ref(), from, to).await
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        DynFs::rename_new(self.inner.as_ref(), from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        DynFs::stat(self.inner.as_ref(), path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        let (file, guard) = DynFs::create_temp(self.inner.as_ref(), prefix).await?;
+        Ok((self.file(file), guard))
+    }
+
+    /// Shuts the backend down without waiting for a permit.
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.inner.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+/// A file opened through a [`SchedulerFs`], whose calls wait for a permit of the priority of
+/// their caller.
+struct ScheduledFile {
+    inner: Box<dyn DynFile>,
+    scheduler: Arc<Scheduler>,
+}
+
+impl Read for ScheduledFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.read_exact_at(buf, pos).await
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.read_to_end_at(buf, pos).await
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.size().await
+    }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.read_at(buf, pos).await
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.read_batch(ranges).await
+    }
+}
+
+impl Write for ScheduledFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.write_all(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.flush().await
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.close().await
+    }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.write_batch(bufs).await
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        let _permit = self.scheduler.acquire(priority()).await;
+        self.inner.barrier().await
+    }
+}
+
+#[cfg(all(test, feature = "tokio", not(feature = "completion-based")))]
+mod tests {
+    use std::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures_util::task::noop_waker_ref;
+
+    use super::{Permit, SchedulerFs};
+    use crate::{context::Priority, disk::TokioFs};
+
+    type Acquire = Pin<Box<dyn Future<Output = Permit>>>;
+
+    fn acquire(fs: &SchedulerFs, priority: Priority) -> Acquire {
+        let scheduler = fs.scheduler.clone();
+        Box::pin(async move { scheduler.acquire(priority).await })
+    }
+
+    fn poll(acquire: &mut Acquire) -> Option<Permit> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        match acquire.as_mut().poll(&mut cx) {
+            Poll::Ready(permit) => Some(permit),
+            Poll::Pending => None,
+        }
+    }
+
+    #[test]
+    fn reserves_permits() {
+        let fs = SchedulerFs::new(TokioFs, 2).with_reserved(Priority::High, 1);
+
+        let low = poll(&mut acquire(&fs, Priority::Low)).unwrap();
+        let mut blocked = acquire(&fs, Priority::Low);
+        assert!(poll(&mut blocked).is_none());
+        let high = poll(&mut acquire(&fs, Priority::High)).unwrap();
+
+        // dropping a waiting call withdraws it, the next call takes the permit of the first one
+        drop(blocked);
+        drop(high);
+        let mut normal = acquire(&fs, Priority::Normal);
+        assert!(poll(&mut normal).is_none());
+        assert!(poll(&mut acquire(&fs, Priority::High)).is_some());
+        drop(low);
+        assert!(poll(&mut normal).is_some());
+    }
+
+    #[test]
+    fn shares_by_weight() {
+        let fs = SchedulerFs::new(TokioFs, 1);
+        let mut running = poll(&mut acquire(&fs, Priority::Normal)).unwrap();
+
+        let mut waiting = Vec::new();
+        for priority in [Priority::High; 4].into_iter().chain([Priority::Low; 2]) {
+            let mut acquire = acquire(&fs, priority);
+            assert!(poll(&mut acquire).is_none());
+            waiting.push((priority, acquire));
+        }
+
+        let mut order = Vec::new();
+        while !waiting.is_empty() {
+            drop(running);
+            let (granted, permit) = waiting
+                .iter_mut()
+                .enumerate()
+                .find_map(|(i, (_, acquire))| poll(acquire).map(|permit| (i, permit)))
+                .unwrap();
+            order.push(waiting.remove(granted).0);
+            running = permit;
+        }
+        assert_eq!(
+            order,
+            [
+                Priority::High,
+                Priority::Low,
+                Priority::High,
+                Priority::High,
+                Priority::High,
+                Priority::Low
+            ]
+        );
+    }
+}
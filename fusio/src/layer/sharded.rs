@@ -0,0 +1,342 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    pin::pin,
+};
+
+use async_stream::stream;
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use super::{merge_pages, page_of_each};
+use crate::{
+    dynamic::DynFile,
+    fs::{rename_by_copy, FileMeta, Fs, ListOptions, OpenOptions},
+    path::Path,
+    util::bulk::copy,
+    DynFs, Error,
+};
+
+const VIRTUAL_NODES: usize = 128;
+
+/// FNV-1a, finished with the splitmix64 mixer. Placement must not change between processes or
+/// Rust versions, which rules out the hashers of the standard library.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+struct Shard {
+    name: String,
+    fs: Box<dyn DynFs>,
+}
+
+/// Spreads files over several backends by consistent hashing of their paths while presenting a
+/// single namespace, e.g. to stay below the request rate limit of a single S3 bucket or prefix.
+///
+/// Every shard is identified by its name, so the placement of a path only depends on the names
+/// of the shards, not on the order they are added in. Adding or removing a shard moves about
+/// `1 / shards` of the files, which have to be copied over by the caller. Listings query every
+/// shard and report their entries one shard after another, while [`Fs::list_options`] merges
+/// the pages of the shards in path order. [`Fs::create_temp`] keeps the default implementation,
+/// as the shard of a temporary file depends on its random name rather than on the prefix.
+#[derive(Default)]
+pub struct ShardedFs {
+    shards: Vec<Shard>,
+    ring: BTreeMap<u64, usize>,
+}
+
+impl ShardedFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `fs` as the shard `name`, replacing an earlier shard of the same name.
+    pub fn shard<F>(mut self, name: impl Into<String>, fs: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        let name = name.into();
+        let shard = Shard {
+            name,
+            fs: Box::new(fs),
+        };
+        match self.shards.iter().position(|s| s.name == shard.name) {
+            Some(index) => self.shards[index] = shard,
+            None => self.shards.push(shard),
+        }
+
+        self.ring.clear();
+        for (index, shard) in self.shards.iter().enumerate() {
+            for node in 0..VIRTUAL_NODES {
+                let hash = stable_hash(format!("{}#{node}", shard.name).as_bytes());
+                self.ring.insert(hash, index);
+            }
+        }
+        self
+    }
+
+    /// The name of the shard `path` is placed on.
+    pub fn shard_of(&self, path: &Path) -> Option<&str> {
+        self.locate(path)
+            .map(|index| self.shards[index].name.as_str())
+    }
+
+    fn locate(&self, path: &Path) -> Option<usize> {
+        let hash = stable_hash(path.as_ref().as_bytes());
+        self.ring
+            .range(hash..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, index)| *index)
+    }
+
+    fn route(&self, path: &Path) -> Result<&dyn DynFs, Error> {
+        match self.locate(path) {
+            Some(index) => Ok(self.shards[index].fs.as_ref()),
+            None => Err(no_shards()),
+        }
+    }
+}
+
+fn no_shards() -> Error {
+    Error::Unsupported {
+        message: "sharded fs has no shards".into(),
+    }
+}
+
+impl Fs for ShardedFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        DynFs::open_options(self.route(path)?, path, options).await
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        for shard in &self.shards {
+            DynFs::create_dir_all(shard.fs.as_ref(), path).await?;
+        }
+        Ok(())
+    }
+
+    /// Lists every shard in turn. Directories that exist on several shards are reported once.
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Ok(stream! {
+            let mut directories = HashSet::new();
+            for shard in &self.shards {
+                let mut listing = DynFs::list(shard.fs.as_ref(), path).await?;
+                while let Some(meta) = listing.next().await {
+                    if let Ok(meta) = &meta {
+                        if meta.is_dir() && !directories.insert(meta.path.clone()) {
+                            continue;
+                        }
+                    }
+                    yield meta;
+                }
+            }
+        })
+    }
+
+    /// Pages through every shard on its backend and merges the pages in path order.
+    /// Directories that exist on several shards are reported once.
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let path = path.clone();
+        Ok(stream! {
+            let mut listings = Vec::with_capacity(self.shards.len());
+            for shard in &self.shards {
+                listings.push(Fs::list_options(&shard.fs, &path, page_of_each(&options)).await?);
+            }
+            let mut merged = pin!(merge_pages(listings, options));
+            while let Some(meta) = merged.next().await {
+                yield meta;
+            }
+        })
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        DynFs::remove(self.route(path)?, path).await
+    }
+
+    /// Removes the paths of every shard in one batch per shard.
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let mut batches = vec![Vec::new(); self.shards.len()];
+        for path in paths {
+            let index = self.locate(&path).ok_or_else(no_shards)?;
+            batches[index].push(path);
+        }
+        let mut failed = Vec::new();
+        for (shard, batch) in self.shards.iter().zip(batches) {
+            if !batch.is_empty() {
+                failed.extend(DynFs::remove_batch(shard.fs.as_ref(), batch).await?);
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Copies on the shard when both paths are placed on the same one, and by reading and writing
+    /// the file otherwise.
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let source = self.locate(from).ok_or_else(no_shards)?;
+        let target = self.locate(to).ok_or_else(no_shards)?;
+        let (source_fs, target_fs) = (&self.shards[source].fs, &self.shards[target].fs);
+        if source == target {
+            return DynFs::copy(source_fs.as_ref(), from, to).await;
+        }
+        copy(source_fs, target_fs, from, to).await
+    }
+
+    /// Moves on the shard when both paths are placed on the same one. Otherwise the file is
+    /// written to the target shard with `create_new` and removed from the source, which is only
+    /// atomic when the shards are object stores.
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let source = self.locate(from).ok_or_else(no_shards)?;
+        let target = self.locate(to).ok_or_else(no_shards)?;
+        if source == target {
+            return DynFs::rename_new(self.shards[source].fs.as_ref(), from, to).await;
+        }
+        rename_by_copy(self, from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        DynFs::stat(self.route(path)?, path).await
+    }
+
+    /// Shuts down every shard, even if some of them fail, and returns the first error.
+    async fn shutdown(&self) -> Result<(), Error> {
+        let mut result = Ok(());
+        for shard in &self.shards {
+            let shutdown = DynFs::shutdown(shard.fs.as_ref()).await;
+            result = result.and(shutdown);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn spreads_files_over_shards() {
+        use std::pin::pin;
+
+        use futures_util::StreamExt;
+        use tempfile::TempDir;
+
+        use super::ShardedFs;
+        use crate::{
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            layer::RouterFs,
+            path::Path,
+        };
+
+        let root = |dir: &TempDir| {
+            RouterFs::new().mount(
+                Path::default(),
+                TokioFs,
+                Path::from_absolute_path(dir.path()).unwrap(),
+            )
+        };
+        let dirs = [TempDir::new().unwrap(), TempDir::new().unwrap()];
+        let fs = ShardedFs::new()
+            .shard("a", root(&dirs[0]))
+            .shard("b", root(&dirs[1]));
+        let paths = (0..64)
+            .map(|i| Path::parse(format!("sst/{i}")).unwrap())
+            .collect::<Vec<_>>();
+
+        fs.create_dir_all(&Path::parse("sst").unwrap())
+            .await
+            .unwrap();
+        for path in &paths {
+            fs.open_options(path, OpenOptions::default().create(true))
+                .await
+                .unwrap();
+        }
+        let placed = |dir: &TempDir| std::fs::read_dir(dir.path().join("sst")).unwrap().count();
+        assert_eq!(placed(&dirs[0]) + placed(&dirs[1]), 64);
+        assert!(placed(&dirs[0]) > 0 && placed(&dirs[1]) > 0);
+
+        let sst = Path::parse("sst").unwrap();
+        let mut listing = pin!(fs.list(&sst).await.unwrap());
+        let mut listed = 0;
+        while let Some(meta) = listing.next().await {
+            meta.unwrap();
+            listed += 1;
+        }
+        assert_eq!(listed, 64);
+
+        // the order of the shards does not matter, and a new shard only takes over some files
+        let reordered = ShardedFs::new()
+            .shard("b", root(&dirs[1]))
+            .shard("a", root(&dirs[0]));
+        let extended = ShardedFs::new()
+            .shard("a", root(&dirs[0]))
+            .shard("b", root(&dirs[1]))
+            .shard("c", root(&dirs[1]));
+        let mut moved = 0;
+        for path in &paths {
+            assert_eq!(fs.shard_of(path), reordered.shard_of(path));
+            if extended.shard_of(path) != fs.shard_of(path) {
+                assert_eq!(extended.shard_of(path), Some("c"));
+                moved += 1;
+            }
+        }
+        assert!(moved > 0 && moved < 48, "{moved} files moved");
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn pages_in_path_order() {
+        use std::sync::Arc;
+
+        use futures_util::StreamExt;
+
+        use super::ShardedFs;
+        use crate::{
+            fs::{Fs, ListOptions},
+            path::Path,
+            remotes::aws::mock::MockS3,
+        };
+
+        let a = Arc::new(MockS3::default());
+        let b = Arc::new(MockS3::default());
+        let fs = ShardedFs::new().shard("a", a.fs()).shard("b", b.fs());
+        let paths = (0..6).map(|i| format!("data/{i}")).collect::<Vec<_>>();
+        for path in &paths {
+            match fs.shard_of(&Path::parse(path).unwrap()) {
+                Some("a") => a.put(path, "x"),
+                _ => b.put(path, "x"),
+            }
+        }
+        assert!(paths.iter().any(|path| a.get(path).is_some()));
+        assert!(paths.iter().any(|path| b.get(path).is_some()));
+
+        let dir = Path::parse("data").unwrap();
+        let page = |options| {
+            let fs = &fs;
+            let dir = &dir;
+            async move {
+                fs.list_options(dir, options)
+                    .await
+                    .unwrap()
+                    .map(|meta| meta.unwrap().path.to_string())
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+        assert_eq!(page(ListOptions::default().limit(3)).await, paths[..3]);
+        let rest = page(ListOptions::default().page_token("data/2")).await;
+        assert_eq!(rest, paths[3..]);
+    }
+}
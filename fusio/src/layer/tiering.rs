@@ -0,0 +1,704 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    io,
+    pin::pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use async_stream::stream;
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use super::{merge_pages, page_of_each, RouterFs};
+use crate::{
+    clock::{Clock, SystemClock},
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard},
+    path::Path,
+    task::TaskGroup,
+    Error, IoBuf, IoBufMut, MaybeSend, Read, Write,
+};
+
+/// The storage a file of a [`TieredFs`] is placed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Hot,
+    Cold,
+}
+
+/// Decides when a file moves from the hot to the cold tier: as soon as any of the configured
+/// thresholds is reached. Without thresholds files stay hot.
+#[derive(Debug, Clone, Default)]
+pub struct TieringPolicy {
+    /// Time since the file was last opened for writing.
+    pub min_age: Option<Duration>,
+    pub min_size: Option<u64>,
+}
+
+impl TieringPolicy {
+    fn should_migrate(&self, age: Duration, size: u64) -> bool {
+        self.min_age.is_some_and(|min_age| age >= min_age)
+            || self.min_size.is_some_and(|min_size| size >= min_size)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    tier: Tier,
+    /// When the file was last opened or closed for writing.
+    written: SystemTime,
+    /// Changes every time the file is written, so a migration notices a rewrite even when the
+    /// clock did not advance.
+    generation: u64,
+    /// Files opened for writing and not closed yet, which keep the file hot.
+    writers: usize,
+}
+
+type Placements = Arc<Mutex<HashMap<Path, Placement>>>;
+
+/// Writes new files to a hot tier and moves them to a cold tier according to a
+/// [`TieringPolicy`].
+///
+/// The tiers are two prefixes of a [`RouterFs`], e.g. one mounted on a local disk and one on a
+/// bucket, and a file at `path` is stored at `{hot}/{path}` or `{cold}/{path}`. Migration is
+/// driven by [`TieredFs::migrate`] and only considers files written through this instance, as
+/// their age is not known otherwise. Readers are routed to the tier a file currently lives on,
+/// files never seen before are looked up on the hot tier first.
+pub struct TieredFs {
+    router: RouterFs,
+    hot: Path,
+    cold: Path,
+    policy: TieringPolicy,
+    clock: Arc<dyn Clock>,
+    placements: Placements,
+    generations: AtomicU64,
+}
+
+impl TieredFs {
+    /// Places files below the `hot` and `cold` prefixes of `router`.
+    pub fn new(router: RouterFs, hot: Path, cold: Path, policy: TieringPolicy) -> Self {
+        Self {
+            router,
+            hot,
+            cold,
+            policy,
+            clock: Arc::new(SystemClock),
+            placements: Arc::new(Mutex::new(HashMap::new())),
+            generations: AtomicU64::new(0),
+        }
+    }
+
+    /// Measures the age of files with `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The tier `path` was last placed on, `None` for files not written or migrated through this
+    /// instance.
+    pub fn tier(&self, path: &Path) -> Option<Tier> {
+        self.placements
+            .lock()
+            .unwrap()
+            .get(path)
+            .map(|placement| placement.tier)
+    }
+
+    fn prefix(&self, tier: Tier) -> &Path {
+        match tier {
+            Tier::Hot => &self.hot,
+            Tier::Cold => &self.cold,
+        }
+    }
+
+    /// Where `path` is stored on the router when placed on `tier`.
+    fn location(&self, tier: Tier, path: &Path) -> Path {
+        Path::from_iter(self.prefix(tier).parts().chain(path.parts()))
+    }
+
+    /// The path of the file stored at `location` on `tier`.
+    fn to_tiered(&self, tier: Tier, location: Path) -> Path {
+        let path = location
+            .prefix_match(self.prefix(tier))
+            .map(Path::from_iter);
+        path.unwrap_or(location)
+    }
+
+    /// Reports an entry the router listed on `tier` under its path on the tiers.
+    fn listed(&self, tier: Tier, meta: FileMeta) -> FileMeta {
+        FileMeta {
+            path: self.to_tiered(tier, meta.path.clone()),
+            ..meta
+        }
+    }
+
+    /// Records that `path` was written on `tier`, with `writers` files still open for writing.
+    fn place(&self, path: &Path, tier: Tier, writers: usize) {
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        let mut placements = self.placements.lock().unwrap();
+        let placement = placements.entry(path.clone()).or_insert(Placement {
+            tier,
+            written: self.clock.now(),
+            generation,
+            writers: 0,
+        });
+        placement.tier = tier;
+        placement.written = self.clock.now();
+        placement.generation = generation;
+        placement.writers += writers;
+    }
+
+    /// Tracks `file`, opened for writing `path`, until it is closed.
+    fn writer(&self, path: &Path, file: Box<dyn DynFile>) -> Box<dyn DynFile> {
+        Box::new(TieredFile {
+            inner: file,
+            path: Some(path.clone()),
+            placements: self.placements.clone(),
+            clock: self.clock.clone(),
+        })
+    }
+
+    /// Moves every hot file the policy selects to the cold tier and returns how many were moved.
+    ///
+    /// Call it periodically, e.g. with [`TieredFs::spawn_migration`]. Files that are open for
+    /// writing are skipped. A file is streamed to the cold tier before it is removed from the hot
+    /// one, so it stays readable throughout, and it stays hot if it is written while being
+    /// copied.
+    pub async fn migrate(&self) -> Result<usize, Error> {
+        let now = self.clock.now();
+        let candidates = self
+            .placements
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, placement)| placement.tier == Tier::Hot && placement.writers == 0)
+            .map(|(path, placement)| {
+                let age = now.duration_since(placement.written).unwrap_or_default();
+                (path.clone(), age, placement.generation)
+            })
+            .collect::<Vec<_>>();
+
+        let mut migrated = 0;
+        for (path, age, generation) in candidates {
+            let (hot, cold) = (
+                self.location(Tier::Hot, &path),
+                self.location(Tier::Cold, &path),
+            );
+            let size = Fs::stat(&self.router, &hot).await?.size;
+            if !self.policy.should_migrate(age, size) {
+                continue;
+            }
+
+            Fs::copy(&self.router, &hot, &cold).await?;
+            let unchanged = {
+                let mut placements = self.placements.lock().unwrap();
+                match placements.get_mut(&path) {
+                    Some(placement)
+                        if placement.tier == Tier::Hot
+                            && placement.generation == generation
+                            && placement.writers == 0 =>
+                    {
+                        placement.tier = Tier::Cold;
+                        true
+                    }
+                    _ => false,
+                }
+            };
+            if !unchanged {
+                // written while being copied, the new content stays hot
+                let _ = Fs::remove(&self.router, &cold).await;
+                continue;
+            }
+            Fs::remove(&self.router, &hot).await?;
+            migrated += 1;
+        }
+
+        Ok(migrated)
+    }
+
+    /// Runs [`TieredFs::migrate`] every time `ticks` yields until `ticks` ends or a migration
+    /// fails.
+    pub async fn run<T>(&self, mut ticks: T) -> Result<(), Error>
+    where
+        T: Stream + Unpin,
+    {
+        while ticks.next().await.is_some() {
+            self.migrate().await?;
+        }
+        Ok(())
+    }
+
+    /// Runs migrations in the background of `tasks` every time `ticks` yields, until `ticks` ends
+    /// or the group is shut down. A failed migration is retried on the next tick.
+    pub fn spawn_migration<T>(self: &Arc<Self>, tasks: &TaskGroup, ticks: T)
+    where
+        T: Stream + Unpin + MaybeSend + 'static,
+    {
+        let fs = self.clone();
+        tasks.spawn(move |shutdown| async move {
+            let mut ticks = shutdown.take_until(ticks);
+            while ticks.next().await.is_some() {
+                let _ = fs.migrate().await;
+            }
+        });
+    }
+
+    /// Runs `op` with the location of `path` on the tier it is placed on, or on the hot and then
+    /// the cold tier when the placement is unknown.
+    async fn on_tier<T, Op, Fut>(&self, path: &Path, op: Op) -> Result<T, Error>
+    where
+        Op: Fn(Path) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        if let Some(tier) = self.tier(path) {
+            return op(self.location(tier, path)).await;
+        }
+        match op(self.location(Tier::Hot, path)).await {
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                op(self.location(Tier::Cold, path)).await
+            }
+            result => result,
+        }
+    }
+}
+
+impl Fs for TieredFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        if !options.write {
+            return self
+                .on_tier(path, |location| {
+                    let options = options.clone();
+                    async move { Fs::open_options(&self.router, &location, options).await }
+                })
+                .await;
+        }
+
+        // files already migrated are updated in place, new files start hot
+        let tier = self.tier(path).unwrap_or(Tier::Hot);
+        let file = Fs::open_options(&self.router, &self.location(tier, path), options).await?;
+        self.place(path, tier, 1);
+        Ok(self.writer(path, file))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        Fs::create_dir_all(&self.router, &self.location(Tier::Hot, path)).await?;
+        Fs::create_dir_all(&self.router, &self.location(Tier::Cold, path)).await
+    }
+
+    /// Lists the hot tier followed by the cold tier. Files that are being migrated are only
+    /// reported once.
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let (hot, cold) = (
+            self.location(Tier::Hot, path),
+            self.location(Tier::Cold, path),
+        );
+        Ok(stream! {
+            let mut seen = HashSet::new();
+            let listing = Fs::list(&self.router, &hot).await?;
+            let mut listing = pin!(listing);
+            while let Some(meta) = listing.next().await {
+                let meta = meta.map(|meta| self.listed(Tier::Hot, meta));
+                if let Ok(meta) = &meta {
+                    seen.insert(meta.path.clone());
+                }
+                yield meta;
+            }
+            let listing = Fs::list(&self.router, &cold).await?;
+            let mut listing = pin!(listing);
+            while let Some(meta) = listing.next().await {
+                let meta = meta.map(|meta| self.listed(Tier::Cold, meta));
+                if !matches!(&meta, Ok(meta) if seen.contains(&meta.path)) {
+                    yield meta;
+                }
+            }
+        })
+    }
+
+    /// Pages through both tiers on their backends and merges the pages in path order. Files that
+    /// are being migrated are only reported once.
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        let path = path.clone();
+        let page = |tier| {
+            let mut page = page_of_each(&options);
+            page.page_token = options
+                .page_token
+                .as_deref()
+                .map(|token| match Path::parse(token) {
+                    Ok(token) => self.location(tier, &token).to_string(),
+                    Err(_) => token.to_string(),
+                });
+            page
+        };
+        let (hot_page, cold_page) = (page(Tier::Hot), page(Tier::Cold));
+        Ok(stream! {
+            let (hot, cold) = (
+                self.location(Tier::Hot, &path),
+                self.location(Tier::Cold, &path),
+            );
+            let hot = Fs::list_options(&self.router, &hot, hot_page)
+                .await?
+                .map(|meta| meta.map(|meta| self.listed(Tier::Hot, meta)));
+            let cold = Fs::list_options(&self.router, &cold, cold_page)
+                .await?
+                .map(|meta| meta.map(|meta| self.listed(Tier::Cold, meta)));
+            let mut merged = pin!(merge_pages(vec![hot.left_stream(), cold.right_stream()], options));
+            while let Some(meta) = merged.next().await {
+                yield meta;
+            }
+        })
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        self.on_tier(path, |location| async move {
+            Fs::remove(&self.router, &location).await
+        })
+        .await?;
+        self.placements.lock().unwrap().remove(path);
+        Ok(())
+    }
+
+    /// Removes the paths in one batch. Paths of unknown placement are removed from the cold tier
+    /// if the hot tier does not have them.
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let located = |tier: Tier, paths: Vec<Path>| {
+            let locations = paths
+                .iter()
+                .map(|path| (self.location(tier, path), path.clone()))
+                .collect::<HashMap<_, _>>();
+            (locations.keys().cloned().collect::<Vec<_>>(), locations)
+        };
+        let (cold, hot): (Vec<_>, Vec<_>) = paths
+            .iter()
+            .cloned()
+            .partition(|path| self.tier(path) == Some(Tier::Cold));
+        let mut cold = cold;
+        let mut failed = Vec::new();
+        let (batch, locations) = located(Tier::Hot, hot);
+        for (location, error) in Fs::remove_batch(&self.router, batch).await? {
+            let path = locations[&location].clone();
+            match error {
+                Error::Io(e)
+                    if e.kind() == io::ErrorKind::NotFound && self.tier(&path).is_none() =>
+                {
+                    cold.push(path)
+                }
+                error => failed.push((path, error)),
+            }
+        }
+        let (batch, locations) = located(Tier::Cold, cold);
+        for (location, error) in Fs::remove_batch(&self.router, batch).await? {
+            failed.push((locations[&location].clone(), error));
+        }
+
+        let mut placements = self.placements.lock().unwrap();
+        for path in paths
+            .iter()
+            .filter(|path| !failed.iter().any(|(failed, _)| failed == *path))
+        {
+            placements.remove(path);
+        }
+        drop(placements);
+        Ok(failed)
+    }
+
+    /// Writes `to` like [`Fs::open_options`] does, on its current tier or on the hot tier for new
+    /// files, and reads `from` from the tier it is placed on. Copies within a tier stay on its
+    /// backend.
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let target = self.location(self.tier(to).unwrap_or(Tier::Hot), to);
+        self.on_tier(from, |source| {
+            let target = &target;
+            async move { Fs::copy(&self.router, &source, target).await }
+        })
+        .await?;
+        self.place(to, self.tier(to).unwrap_or(Tier::Hot), 0);
+        Ok(())
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        let meta = self
+            .on_tier(path, |location| async move {
+                Fs::stat(&self.router, &location).await
+            })
+            .await?;
+        Ok(FileMeta {
+            path: path.clone(),
+            ..meta
+        })
+    }
+
+    /// Creates the file on the hot tier, like every new file.
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let (file, guard) =
+            Fs::create_temp(&self.router, &self.location(Tier::Hot, prefix)).await?;
+        let path = self.to_tiered(Tier::Hot, guard.keep()?);
+        self.place(&path, Tier::Hot, 1);
+        Ok((self.writer(&path, file), TempGuard::new(path)))
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        Fs::shutdown(&self.router).await
+    }
+}
+
+/// A file opened for writing through a [`TieredFs`], which keeps it from being migrated until it
+/// is closed or dropped.
+struct TieredFile {
+    inner: Box<dyn DynFile>,
+    /// Taken once the file was released.
+    path: Option<Path>,
+    placements: Placements,
+    clock: Arc<dyn Clock>,
+}
+
+impl TieredFile {
+    fn release(&mut self) {
+        let Some(path) = self.path.take() else {
+            return;
+        };
+        if let Some(placement) = self.placements.lock().unwrap().get_mut(&path) {
+            placement.writers = placement.writers.saturating_sub(1);
+            placement.written = self.clock.now();
+        }
+    }
+}
+
+impl Drop for TieredFile {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl Read for TieredFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        self.inner.read_exact_at(buf, pos).await
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        self.inner.read_to_end_at(buf, pos).await
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        self.inner.size().await
+    }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        self.inner.read_at(buf, pos).await
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        self.inner.read_batch(ranges).await
+    }
+}
+
+impl Write for TieredFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        self.inner.write_all(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush().await
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        let result = self.inner.close().await;
+        self.release();
+        result
+    }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        self.inner.write_batch(bufs).await
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        self.inner.barrier().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn migrates_by_age_and_size() {
+        use std::{sync::Arc, time::Duration};
+
+        use tempfile::TempDir;
+
+        use super::{Tier, TieredFs, TieringPolicy};
+        use crate::{
+            clock::{OffsetClock, SystemClock},
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            layer::RouterFs,
+            path::Path,
+            Read, Write,
+        };
+
+        let root = |dir: &TempDir| Path::from_absolute_path(dir.path()).unwrap();
+        let hot = TempDir::new().unwrap();
+        let cold = TempDir::new().unwrap();
+        let router = RouterFs::new()
+            .mount(Path::from("hot"), TokioFs, root(&hot))
+            .mount(Path::from("cold"), TokioFs, root(&cold));
+        let clock = Arc::new(OffsetClock::new(SystemClock));
+        let fs = TieredFs::new(
+            router,
+            Path::from("hot"),
+            Path::from("cold"),
+            TieringPolicy {
+                min_age: Some(Duration::from_secs(60)),
+                min_size: Some(1024),
+            },
+        )
+        .with_clock(clock.clone());
+
+        for (name, size) in [("small", 10), ("large", 2048)] {
+            let mut file = fs
+                .open_options(
+                    &Path::parse(name).unwrap(),
+                    OpenOptions::default().create(true),
+                )
+                .await
+                .unwrap();
+            file.write_all(vec![1u8; size]).await.0.unwrap();
+            file.close().await.unwrap();
+        }
+
+        assert_eq!(fs.migrate().await.unwrap(), 1);
+        assert!(!hot.path().join("large").exists());
+        assert!(cold.path().join("large").exists());
+        assert_eq!(fs.tier(&Path::parse("large").unwrap()), Some(Tier::Cold));
+        assert_eq!(fs.tier(&Path::parse("small").unwrap()), Some(Tier::Hot));
+
+        clock.set_offset_millis(120_000);
+        assert_eq!(fs.migrate().await.unwrap(), 1);
+        assert!(cold.path().join("small").exists());
+
+        let mut file = fs.open(&Path::parse("small").unwrap()).await.unwrap();
+        let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+        result.unwrap();
+        assert_eq!(buf, vec![1u8; 10]);
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn keeps_files_open_for_writing_hot() {
+        use std::{
+            sync::Arc,
+            time::{Duration, SystemTime},
+        };
+
+        use tempfile::TempDir;
+
+        use super::{Tier, TieredFs, TieringPolicy};
+        use crate::{
+            clock::FixedClock,
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            layer::RouterFs,
+            path::Path,
+            Write,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let root = Path::from_absolute_path(dir.path()).unwrap();
+        std::fs::create_dir_all(dir.path().join("cold")).unwrap();
+        let fs = TieredFs::new(
+            RouterFs::new().mount(Path::default(), TokioFs, root),
+            Path::from("hot"),
+            Path::from("cold"),
+            TieringPolicy {
+                min_age: Some(Duration::ZERO),
+                min_size: None,
+            },
+        )
+        .with_clock(Arc::new(FixedClock::new(SystemTime::UNIX_EPOCH)));
+        let path = Path::parse("log").unwrap();
+        fs.create_dir_all(&Path::default()).await.unwrap();
+
+        let mut file = fs
+            .open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        file.write_all(&b"first"[..]).await.0.unwrap();
+        assert_eq!(fs.migrate().await.unwrap(), 0);
+        file.write_all(&b" second"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+        assert!(dir.path().join("hot/log").exists());
+
+        // the clock never advances, the migration still moves the closed file
+        assert_eq!(fs.migrate().await.unwrap(), 1);
+        assert_eq!(fs.tier(&path), Some(Tier::Cold));
+        assert!(!dir.path().join("hot/log").exists());
+        assert_eq!(
+            std::fs::read(dir.path().join("cold/log")).unwrap(),
+            b"first second"
+        );
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn pages_through_both_tiers() {
+        use std::sync::Arc;
+
+        use futures_util::StreamExt;
+
+        use super::{TieredFs, TieringPolicy};
+        use crate::{
+            fs::{Fs, ListOptions},
+            layer::RouterFs,
+            path::Path,
+            remotes::aws::mock::MockS3,
+        };
+
+        let hot = Arc::new(MockS3::default());
+        let cold = Arc::new(MockS3::default());
+        for key in ["data/a", "data/c"] {
+            hot.put(key, "x");
+        }
+        for key in ["data/b", "data/c", "data/d"] {
+            cold.put(key, "x");
+        }
+        let router = RouterFs::new()
+            .mount(Path::from("hot"), hot.fs(), Path::default())
+            .mount(Path::from("cold"), cold.fs(), Path::default());
+        let fs = TieredFs::new(
+            router,
+            Path::from("hot"),
+            Path::from("cold"),
+            TieringPolicy::default(),
+        );
+        let dir = Path::parse("data").unwrap();
+        let page = |options| {
+            let fs = &fs;
+            let dir = &dir;
+            async move {
+                fs.list_options(dir, options)
+                    .await
+                    .unwrap()
+                    .map(|meta| meta.unwrap().path.to_string())
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        let first = page(ListOptions::default().offset(1).limit(2)).await;
+        assert_eq!(first, ["data/b", "data/c"]);
+        let rest = page(ListOptions::default().page_token("data/b")).await;
+        assert_eq!(rest, ["data/c", "data/d"]);
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_core::Stream;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    util::walk::walk,
+    DynFs, Error,
+};
+
+/// A removed file kept in the trash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trashed {
+    /// Where the file was before it was removed.
+    pub path: Path,
+    /// Where the file is kept in the trash.
+    pub location: Path,
+    pub removed: SystemTime,
+}
+
+/// Moves removed files into a trash prefix instead of deleting them, so accidental removals can
+/// be undone with [`TrashFs::restore`] until they are purged.
+///
+/// A file removed at time `t` is kept at `{trash}/{t in milliseconds}/{path}`, so the trash
+/// survives restarts without an index and every removal of the same path is kept separately.
+/// Removing a path inside the trash deletes it for good. Moving a file copies it with
+/// [`Fs::copy`], which object stores do on the server, other backends read and write the file.
+/// Local backends keep the emptied directories of the trash.
+pub struct TrashFs {
+    inner: Box<dyn DynFs>,
+    trash: Path,
+    retention: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl TrashFs {
+    /// Keeps removed files below `trash` for `retention`, see [`TrashFs::purge_expired`].
+    pub fn new<F>(fs: F, trash: Path, retention: Duration) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            trash,
+            retention,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Where `path` is kept once it was removed at `removed`.
+    fn location(&self, removed: SystemTime, path: &Path) -> Path {
+        let millis = removed
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        Path::from_iter(
+            self.trash
+                .parts()
+                .chain([format!("{millis:020}").into()])
+                .chain(path.parts()),
+        )
+    }
+
+    /// Copies on the backend, creating the parent directories of `to` for local backends.
+    async fn copy_within(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        let parts = to.parts().collect::<Vec<_>>();
+        if let Some((_, parents)) = parts.split_last() {
+            let parent = Path::from_iter(parents.iter().cloned());
+            DynFs::create_dir_all(self.inner.as_ref(), &parent).await?;
+        }
+        DynFs::copy(self.inner.as_ref(), from, to).await
+    }
+
+    fn parse(&self, location: Path) -> Option<Trashed> {
+        let mut parts = location.prefix_match(&self.trash)?;
+        let millis = parts.next()?.as_ref().parse().ok()?;
+        let path = Path::from_iter(parts);
+        Some(Trashed {
+            path,
+            removed: UNIX_EPOCH + Duration::from_millis(millis),
+            location,
+        })
+    }
+
+    /// Every removed file still in the trash, in no particular order.
+    pub async fn trashed(&self) -> Result<Vec<Trashed>, Error> {
+        let mut trashed = Vec::new();
+        let result = walk(&self.inner, &self.trash, |meta| {
+            trashed.extend(self.parse(meta.path));
+        })
+        .await;
+        match result {
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            result => result.map(|_| trashed),
+        }
+    }
+
+    /// Moves the most recently removed version of `path` back into place, replacing the file
+    /// written there since. Fails with [`io::ErrorKind::NotFound`] if the trash holds no version
+    /// of `path`.
+    pub async fn restore(&self, path: &Path) -> Result<(), Error> {
+        let latest = self
+            .trashed()
+            .await?
+            .into_iter()
+            .filter(|trashed| &trashed.path == path)
+            .max_by_key(|trashed| trashed.removed)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("\"{path}\" is not in the trash"),
+                )
+            })?;
+
+        self.copy_within(&latest.location, path).await?;
+        DynFs::remove(self.inner.as_ref(), &latest.location).await
+    }
+
+    /// Deletes every file removed longer than `older_than` ago for good and returns how many were
+    /// deleted.
+    pub async fn purge(&self, older_than: Duration) -> Result<usize, Error> {
+        let now = self.clock.now();
+        let mut purged = 0;
+        for trashed in self.trashed().await? {
+            if now.duration_since(trashed.removed).unwrap_or_default() < older_than {
+                continue;
+            }
+            DynFs::remove(self.inner.as_ref(), &trashed.location).await?;
+            purged += 1;
+        }
+        Ok(purged)
+    }
+
+    /// Deletes every file that was kept for the retention period, see [`TrashFs::purge`].
+    pub async fn purge_expired(&self) -> Result<usize, Error> {
+        self.purge(self.retention).await
+    }
+}
+
+impl Fs for TrashFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        DynFs::open_options(self.inner.as_ref(), path, options).await
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        DynFs::create_dir_all(self.inner.as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list(&self.inner, path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list_options(&self.inner, path, options).await
+    }
+
+    /// Moves `path` into the trash.
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        if path.prefix_matches(&self.trash) {
+            return DynFs::remove(self.inner.as_ref(), path).await;
+        }
+
+        let location = self.location(self.clock.now(), path);
+        self.copy_within(path, &location).await?;
+        DynFs::remove(self.inner.as_ref(), path).await
+    }
+
+    /// Moves the paths into the trash under the same time and removes them in one batch, paths
+    /// that could not be moved are kept and reported as failed.
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let now = self.clock.now();
+        let mut failed = Vec::new();
+        let mut moved = Vec::with_capacity(paths.len());
+        for path in paths {
+            if !path.prefix_matches(&self.trash) {
+                let location = self.location(now, &path);
+                if let Err(e) = self.copy_within(&path, &location).await {
+                    failed.push((path, e));
+                    continue;
+                }
+            }
+            moved.push(path);
+        }
+        failed.extend(DynFs::remove_batch(self.inner.as_ref(), moved).await?);
+        Ok(failed)
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::copy(self.inner.as_ref(), from, to).await
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::rename_new(self.inner.as_ref(), from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        DynFs::stat(self.inner.as_ref(), path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        DynFs::create_temp(self.inner.as_ref(), prefix).await
+    }
+
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.inner.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn restores_and_purges() {
+        use std::{io, sync::Arc, time::Duration};
+
+        use tempfile::TempDir;
+
+        use super::TrashFs;
+        use crate::{
+            clock::{OffsetClock, SystemClock},
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            layer::RouterFs,
+            path::Path,
+            Error, Write,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let clock = Arc::new(OffsetClock::new(SystemClock));
+        let fs = TrashFs::new(
+            RouterFs::new().mount(
+                Path::default(),
+                TokioFs,
+                Path::from_absolute_path(dir.path()).unwrap(),
+            ),
+            Path::parse(".trash").unwrap(),
+            Duration::from_secs(3600),
+        )
+        .with_clock(clock.clone());
+        let path = Path::parse("data/file").unwrap();
+
+        fs.create_dir_all(&Path::parse("data").unwrap())
+            .await
+            .unwrap();
+        for (offset, content) in [(0, "first"), (1_000, "second")] {
+            clock.set_offset_millis(offset);
+            let mut file = fs
+                .open_options(&path, OpenOptions::default().create(true))
+                .await
+                .unwrap();
+            file.write_all(content.as_bytes()).await.0.unwrap();
+            file.close().await.unwrap();
+            fs.remove(&path).await.unwrap();
+            assert!(!dir.path().join("data/file").exists());
+        }
+        assert_eq!(fs.trashed().await.unwrap().len(), 2);
+
+        fs.restore(&path).await.unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("data/file")).unwrap(),
+            b"second"
+        );
+        let trashed = fs.trashed().await.unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].path, path);
+
+        clock.set_offset_millis(3_600_000);
+        assert_eq!(fs.purge_expired().await.unwrap(), 1);
+        assert!(fs.trashed().await.unwrap().is_empty());
+        let err = fs.restore(&path).await;
+        assert!(matches!(err, Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound));
+    }
+}
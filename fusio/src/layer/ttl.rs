@@ -0,0 +1,511 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_core::Stream;
+use futures_util::{lock::Mutex as AsyncMutex, StreamExt};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    dynamic::DynFile,
+    fs::{temp_path, FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    task::TaskGroup,
+    DynFs, Error, MaybeSend, Read, Write,
+};
+
+/// How many changes are logged before they are compacted into one snapshot.
+const COMPACT_AFTER: usize = 64;
+
+fn to_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+fn not_found(err: &Error) -> bool {
+    matches!(err, Error::Io(e) if e.kind() == io::ErrorKind::NotFound)
+}
+
+/// A file of the index directory: the sequence number and whether it is a snapshot or a logged
+/// change. Other files, like the temporary ones of a crashed write, are not part of the index.
+fn parse_entry(name: &str) -> Option<(u64, bool)> {
+    let (seq, kind) = name.split_once('.')?;
+    let snapshot = match kind {
+        "snapshot" => true,
+        "log" => false,
+        _ => return None,
+    };
+    Some((seq.parse().ok()?, snapshot))
+}
+
+/// Applies the lines of the index file `path` to `expires`.
+fn apply(
+    expires: &mut BTreeMap<Path, SystemTime>,
+    path: &Path,
+    content: Vec<u8>,
+) -> Result<(), Error> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("{path} is corrupt"));
+    let content = String::from_utf8(content).map_err(|_| invalid())?;
+    for line in content.lines() {
+        let (millis, file) = line.split_once('\t').ok_or_else(invalid)?;
+        let file = Path::parse(file)?;
+        if millis == "-" {
+            expires.remove(&file);
+        } else {
+            let millis = millis.parse().map_err(|_| invalid())?;
+            expires.insert(file, UNIX_EPOCH + Duration::from_millis(millis));
+        }
+    }
+    Ok(())
+}
+
+/// The files of the index directory, guarded so changes are logged one after another.
+struct Journal {
+    next: u64,
+    /// Changes logged since the latest snapshot.
+    logged: usize,
+    /// The files the next snapshot replaces.
+    replaced: Vec<Path>,
+}
+
+/// Removes files once their time to live passed, for scratch data on object stores that should
+/// not depend on lifecycle rules of the whole bucket.
+///
+/// Expiry times are kept in an index directory next to the data, which [`TtlFs::open`] loads
+/// again. Every change is logged as a small file `{seq}.log` of `{expires in milliseconds}\t{path}`
+/// and `-\t{path}` lines, and every 64 changes the logged ones are compacted into a
+/// `{seq}.snapshot` of all expiry times. Index files are written under a temporary name and
+/// published with [`Fs::rename_new`], so a crash never leaves one half written. Expired files
+/// stay readable until [`TtlFs::reap`] removes them, usually driven by [`TtlFs::spawn_reaper`].
+/// Files removed through this layer are dropped from the index.
+pub struct TtlFs {
+    inner: Box<dyn DynFs>,
+    index: Path,
+    default_ttl: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    expires: Mutex<BTreeMap<Path, SystemTime>>,
+    journal: AsyncMutex<Journal>,
+}
+
+impl TtlFs {
+    /// Loads the expiry times kept in the directory `index` on `fs`, creating it if missing.
+    pub async fn open<F>(fs: F, index: Path) -> Result<Self, Error>
+    where
+        F: Fs + 'static,
+    {
+        let inner: Box<dyn DynFs> = Box::new(fs);
+        DynFs::create_dir_all(inner.as_ref(), &index).await?;
+        let entries = Self::entries(inner.as_ref(), &index).await?;
+
+        let base = entries
+            .iter()
+            .filter(|(_, snapshot, _)| *snapshot)
+            .map(|(seq, _, _)| *seq)
+            .max();
+        let mut replayed = entries
+            .iter()
+            .filter(|(seq, snapshot, _)| match base {
+                Some(base) => *seq > base || (*seq == base && *snapshot),
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        replayed.sort_by_key(|(seq, snapshot, _)| (*seq, !*snapshot));
+
+        let mut expires = BTreeMap::new();
+        for (_, _, path) in &replayed {
+            let mut file = DynFs::open(inner.as_ref(), path).await?;
+            let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+            result?;
+            apply(&mut expires, path, buf)?;
+        }
+
+        Ok(Self {
+            inner,
+            default_ttl: None,
+            clock: Arc::new(SystemClock),
+            expires: Mutex::new(expires),
+            journal: AsyncMutex::new(Journal {
+                next: entries.iter().map(|(seq, _, _)| seq + 1).max().unwrap_or(1),
+                logged: replayed
+                    .iter()
+                    .filter(|(_, snapshot, _)| !*snapshot)
+                    .count(),
+                replaced: entries.into_iter().map(|(_, _, path)| path).collect(),
+            }),
+            index,
+        })
+    }
+
+    /// Expires every file opened for writing through this layer `ttl` after it was opened.
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// When `path` expires, `None` if it never does.
+    pub fn expires(&self, path: &Path) -> Option<SystemTime> {
+        self.expires.lock().unwrap().get(path).copied()
+    }
+
+    /// Expires `path` `ttl` from now, replacing an earlier time to live.
+    pub async fn set_ttl(&self, path: &Path, ttl: Duration) -> Result<(), Error> {
+        let at = self.clock.now() + ttl;
+        self.change(|expires| {
+            expires.insert(path.clone(), at);
+            format!("{}\t{path}\n", to_millis(at))
+        })
+        .await
+    }
+
+    /// Keeps `path` until it is removed.
+    pub async fn clear_ttl(&self, path: &Path) -> Result<(), Error> {
+        self.change(|expires| match expires.remove(path) {
+            Some(_) => format!("-\t{path}\n"),
+            None => String::new(),
+        })
+        .await
+    }
+
+    /// Removes every expired file and returns how many were removed. Files that are already gone
+    /// are dropped from the index as well.
+    pub async fn reap(&self) -> Result<usize, Error> {
+        let now = self.clock.now();
+        let expired = self
+            .expires
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, expires)| **expires <= now)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        let mut reaped = 0;
+        let mut removed = Vec::new();
+        let mut result = Ok(());
+        for path in expired {
+            match DynFs::remove(self.inner.as_ref(), &path).await {
+                Ok(()) => reaped += 1,
+                Err(e) if not_found(&e) => {}
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+            removed.push(path);
+        }
+        self.change(|expires| {
+            let mut lines = String::new();
+            for path in &removed {
+                // the time to live was extended while removing
+                if expires.get(path).is_some_and(|expires| *expires <= now) {
+                    expires.remove(path);
+                    lines.push_str(&format!("-\t{path}\n"));
+                }
+            }
+            lines
+        })
+        .await?;
+        result.map(|()| reaped)
+    }
+
+    /// Runs [`TtlFs::reap`] in the background of `tasks` every time `ticks` yields, until
+    /// `ticks` ends or the group is shut down. A failed run is retried on the next tick.
+    pub fn spawn_reaper<T>(self: &Arc<Self>, tasks: &TaskGroup, ticks: T)
+    where
+        T: Stream + Unpin + MaybeSend + 'static,
+    {
+        let fs = self.clone();
+        tasks.spawn(move |shutdown| async move {
+            let mut ticks = shutdown.take_until(ticks);
+            while ticks.next().await.is_some() {
+                let _ = fs.reap().await;
+            }
+        });
+    }
+
+    /// The index files in `index` as their sequence number, whether they are a snapshot and
+    /// their path.
+    async fn entries(inner: &dyn DynFs, index: &Path) -> Result<Vec<(u64, bool, Path)>, Error> {
+        let mut listing = match DynFs::list(inner, index).await {
+            Err(e) if not_found(&e) => return Ok(Vec::new()),
+            listing => listing?,
+        };
+        let mut entries = Vec::new();
+        let mut first = true;
+        while let Some(meta) = listing.next().await {
+            // type erased backends report a missing directory as the first item
+            let meta = match meta {
+                Err(e) if first && not_found(&e) => return Ok(Vec::new()),
+                meta => meta?,
+            };
+            first = false;
+            if let Some((seq, snapshot)) = meta.path.filename().and_then(parse_entry) {
+                entries.push((seq, snapshot, meta.path));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn entry(&self, seq: u64, kind: &str) -> Path {
+        self.index.child(format!("{seq:020}.{kind}").as_str())
+    }
+
+    /// Applies `change` to the expiry times and logs the lines it returns, nothing if there are
+    /// none. Changes are applied and logged one after another, so loading the index replays them
+    /// in the same order.
+    async fn change<C>(&self, change: C) -> Result<(), Error>
+    where
+        C: FnOnce(&mut BTreeMap<Path, SystemTime>) -> String,
+    {
+        let mut journal = self.journal.lock().await;
+        let lines = change(&mut self.expires.lock().unwrap());
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let seq = journal.next;
+        journal.next += 1;
+        let log = self.entry(seq, "log");
+        self.publish(&log, lines).await?;
+
+        journal.logged += 1;
+        journal.replaced.push(log);
+        if journal.logged >= COMPACT_AFTER {
+            self.compact(&mut journal, seq).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes every expiry time as the snapshot `seq` and removes the index files it replaces.
+    async fn compact(&self, journal: &mut Journal, seq: u64) -> Result<(), Error> {
+        let content = self
+            .expires
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, expires)| format!("{}\t{path}\n", to_millis(*expires)))
+            .collect::<Vec<_>>()
+            .concat();
+        let snapshot = self.entry(seq, "snapshot");
+        self.publish(&snapshot, content).await?;
+
+        let replaced = std::mem::replace(&mut journal.replaced, vec![snapshot]);
+        journal.logged = 0;
+        // files left behind are skipped by the next load, as they are older than the snapshot
+        let _ = DynFs::remove_batch(self.inner.as_ref(), replaced).await;
+        Ok(())
+    }
+
+    /// Writes `content` to `path` under a temporary name and moves it in place once complete.
+    async fn publish(&self, path: &Path, content: String) -> Result<(), Error> {
+        let staged = temp_path(path)?;
+        let published = async {
+            let mut file = DynFs::open_options(
+                self.inner.as_ref(),
+                &staged,
+                OpenOptions::default().create(true).truncate(true),
+            )
+            .await?;
+            let (result, _) = file.write_all(content.into_bytes()).await;
+            result?;
+            file.close().await?;
+            DynFs::rename_new(self.inner.as_ref(), &staged, path).await
+        };
+        let result = published.await;
+        if result.is_err() {
+            let _ = DynFs::remove(self.inner.as_ref(), &staged).await;
+        }
+        result
+    }
+}
+
+impl Fs for TtlFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        let write = options.write;
+        let file = DynFs::open_options(self.inner.as_ref(), path, options).await?;
+        if let Some(ttl) = self.default_ttl.filter(|_| write) {
+            self.set_ttl(path, ttl).await?;
+        }
+        Ok(file)
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        DynFs::create_dir_all(self.inner.as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list(&self.inner, path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list_options(&self.inner, path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        DynFs::remove(self.inner.as_ref(), path).await?;
+        self.clear_ttl(path).await
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let failed = DynFs::remove_batch(self.inner.as_ref(), paths.clone()).await?;
+        self.change(|expires| {
+            paths
+                .iter()
+                .filter(|path| !failed.iter().any(|(failed, _)| failed == *path))
+                .filter(|path| expires.remove(*path).is_some())
+                .map(|path| format!("-\t{path}\n"))
+                .collect::<Vec<_>>()
+                .concat()
+        })
+        .await?;
+        Ok(failed)
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::copy(self.inner.as_ref(), from, to).await?;
+        match self.default_ttl {
+            Some(ttl) => self.set_ttl(to, ttl).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        DynFs::stat(self.inner.as_ref(), path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        DynFs::create_temp(self.inner.as_ref(), prefix).await
+    }
+
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.inner.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn reaps_expired_files() {
+        use std::{sync::Arc, time::Duration};
+
+        use tempfile::TempDir;
+
+        use super::TtlFs;
+        use crate::{
+            clock::{OffsetClock, SystemClock},
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+        let clock = Arc::new(OffsetClock::new(SystemClock));
+        let fs = TtlFs::open(TokioFs, path("ttl-index"))
+            .await
+            .unwrap()
+            .with_default_ttl(Duration::from_secs(60))
+            .with_clock(clock.clone());
+
+        for name in ["scratch", "kept", "extended"] {
+            fs.open_options(&path(name), OpenOptions::default().create(true))
+                .await
+                .unwrap();
+        }
+        fs.clear_ttl(&path("kept")).await.unwrap();
+        fs.set_ttl(&path("extended"), Duration::from_secs(600))
+            .await
+            .unwrap();
+        assert_eq!(fs.reap().await.unwrap(), 0);
+
+        clock.set_offset_millis(120_000);
+        assert_eq!(fs.reap().await.unwrap(), 1);
+        assert!(!dir.path().join("scratch").exists());
+        assert!(dir.path().join("kept").exists());
+        assert!(dir.path().join("extended").exists());
+
+        // the index survives a restart
+        let expires = fs.expires(&path("extended")).unwrap();
+        drop(fs);
+        let fs = TtlFs::open(TokioFs, path("ttl-index")).await.unwrap();
+        assert_eq!(fs.expires(&path("scratch")), None);
+        let reloaded = fs.expires(&path("extended")).unwrap();
+        assert!(expires.duration_since(reloaded).unwrap() < Duration::from_millis(1));
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn compacts_logged_changes() {
+        use std::time::Duration;
+
+        use tempfile::TempDir;
+
+        use super::{TtlFs, COMPACT_AFTER};
+        use crate::{disk::TokioFs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        let index = Path::from_absolute_path(dir.path().join("ttl-index")).unwrap();
+        let path = |n: usize| Path::from_absolute_path(dir.path().join(n.to_string())).unwrap();
+        let fs = TtlFs::open(TokioFs, index.clone()).await.unwrap();
+
+        for n in 0..COMPACT_AFTER + 5 {
+            fs.set_ttl(&path(n), Duration::from_secs(60)).await.unwrap();
+        }
+        fs.clear_ttl(&path(0)).await.unwrap();
+        // one snapshot and the changes logged after it
+        let files = std::fs::read_dir(dir.path().join("ttl-index"))
+            .unwrap()
+            .count();
+        assert_eq!(files, 7);
+        // a write that crashed before it was published is not part of the index
+        std::fs::write(
+            dir.path()
+                .join("ttl-index")
+                .join("00000000000000000999.log-crashed"),
+            b"torn",
+        )
+        .unwrap();
+
+        let expires = (0..COMPACT_AFTER + 5)
+            .map(|n| fs.expires(&path(n)))
+            .collect::<Vec<_>>();
+        drop(fs);
+        let fs = TtlFs::open(TokioFs, index).await.unwrap();
+        assert_eq!(fs.expires(&path(0)), None);
+        for (n, expires) in expires.into_iter().enumerate() {
+            assert_eq!(
+                fs.expires(&path(n)).map(super::to_millis),
+                expires.map(super::to_millis)
+            );
+        }
+    }
+}
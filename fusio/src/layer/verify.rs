@@ -0,0 +1,378 @@
+use std::sync::Arc;
+
+use futures_core::Stream;
+
+use super::FsLayer;
+use crate::{
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    util::crc32c,
+    DynFs, Error, IoBuf, IoBufMut, Read, Write,
+};
+
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Reads back what was written to a file before [`Write::close`] reports success, for
+/// deployments that cannot trust their storage to return what it acknowledged.
+///
+/// Files opened through it compute the CRC-32C checksum of everything written to them. Once the
+/// backend closed a file, it is opened again and the written bytes are read back: the whole file
+/// when it was truncated or created new, its end otherwise, as appends land there. Closing fails
+/// with [`Error::ChecksumMismatch`] when they differ, or [`Error::UnexpectedEof`] when the stored
+/// file is shorter, and the file is left as stored so it can be inspected.
+///
+/// Reads are served by the layers below, so place it right above the backend: a cache in between
+/// would answer with the data just written. Local files are read back from the page cache,
+/// which verifies everything up to the kernel but not the disk itself.
+pub struct VerifyFs {
+    inner: Arc<Box<dyn DynFs>>,
+}
+
+impl VerifyFs {
+    pub fn new<F>(fs: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Arc::new(Box::new(fs)),
+        }
+    }
+
+    fn wrap(&self, file: Box<dyn DynFile>, path: &Path, whole: bool) -> Box<dyn DynFile> {
+        Box::new(VerifyFile {
+            inner: file,
+            fs: self.inner.clone(),
+            path: path.clone(),
+            whole,
+            written: 0,
+            crc32c: 0,
+        })
+    }
+}
+
+impl Fs for VerifyFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        let fresh = options.truncate || options.create_new;
+        let file = DynFs::open_options(self.inner.as_ref().as_ref(), path, options).await?;
+        Ok(self.wrap(file, path, fresh))
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        DynFs::create_dir_all(self.inner.as_ref().as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list(self.inner.as_ref(), path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list_options(self.inner.as_ref(), path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        DynFs::remove(self.inner.as_ref().as_ref(), path).await
+    }
+
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        DynFs::remove_batch(self.inner.as_ref().as_ref(), paths).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::copy(self.inner.as_ref().as_ref(), from, to).await
+    }
+
+    async fn rename_new(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        DynFs::rename_new(self.inner.as_ref().as_ref(), from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        DynFs::stat(self.inner.as_ref().as_ref(), path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let (file, guard) = DynFs::create_temp(self.inner.as_ref().as_ref(), prefix).await?;
+        let file = self.wrap(file, guard.path(), true);
+        Ok((file, guard))
+    }
+
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.inner.as_ref().as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref().as_ref()).await
+    }
+}
+
+/// A file opened through a [`VerifyFs`], checksumming the writes to verify them on close.
+struct VerifyFile {
+    inner: Box<dyn DynFile>,
+    fs: Arc<Box<dyn DynFs>>,
+    path: Path,
+    /// Whether the written bytes are the whole file rather than appended to it.
+    whole: bool,
+    written: u64,
+    crc32c: u32,
+}
+
+impl VerifyFile {
+    fn update(&mut self, buf: &[u8]) {
+        self.written += buf.len() as u64;
+        self.crc32c = crc32c(self.crc32c, buf);
+    }
+
+    /// Reads the written bytes back from the stored file and compares their checksum.
+    async fn verify(&self) -> Result<(), Error> {
+        let mut stored = DynFs::open(self.fs.as_ref().as_ref(), &self.path).await?;
+        let size = stored.size().await?;
+        if size < self.written {
+            return Err(Error::UnexpectedEof {
+                requested: self.written,
+                available: size,
+            });
+        }
+
+        let start = match self.whole {
+            true => 0,
+            false => size - self.written,
+        };
+        let mut crc = 0;
+        let mut buf = Vec::new();
+        let mut pos = start;
+        while pos < size {
+            buf.resize((size - pos).min(CHUNK_SIZE) as usize, 0);
+            let (result, chunk) = stored.read_exact_at(buf, pos).await;
+            result?;
+            crc = crc32c(crc, &chunk);
+            pos += chunk.len() as u64;
+            buf = chunk;
+        }
+        if crc != self.crc32c {
+            return Err(Error::ChecksumMismatch {
+                expected: self.crc32c,
+                actual: crc,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Read for VerifyFile {
+    async fn read_exact_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<(), Error>, B) {
+        self.inner.read_exact_at(buf, pos).await
+    }
+
+    async fn read_to_end_at(&mut self, buf: Vec<u8>, pos: u64) -> (Result<(), Error>, Vec<u8>) {
+        self.inner.read_to_end_at(buf, pos).await
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        self.inner.size().await
+    }
+
+    async fn read_at<B: IoBufMut>(&mut self, buf: B, pos: u64) -> (Result<usize, Error>, B) {
+        self.inner.read_at(buf, pos).await
+    }
+
+    async fn read_batch(&mut self, ranges: Vec<(u64, usize)>) -> Result<Vec<Vec<u8>>, Error> {
+        self.inner.read_batch(ranges).await
+    }
+}
+
+impl Write for VerifyFile {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+        let (result, buf) = self.inner.write_all(buf).await;
+        if result.is_ok() {
+            self.update(buf.as_slice());
+        }
+        (result, buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush().await
+    }
+
+    /// Closes the file and verifies the data written since it was opened, or since it was last
+    /// closed.
+    async fn close(&mut self) -> Result<(), Error> {
+        self.inner.close().await?;
+        if self.written == 0 && !self.whole {
+            return Ok(());
+        }
+        let verified = self.verify().await;
+        // a file closed again only has to hold what was written after
+        self.whole = false;
+        self.written = 0;
+        self.crc32c = 0;
+        verified
+    }
+
+    async fn write_batch<B: IoBuf>(&mut self, bufs: Vec<B>) -> (Result<(), Error>, Vec<B>) {
+        let (result, bufs) = self.inner.write_batch(bufs).await;
+        if result.is_ok() {
+            for buf in &bufs {
+                self.update(buf.as_slice());
+            }
+        }
+        (result, bufs)
+    }
+
+    async fn barrier(&mut self) -> Result<(), Error> {
+        self.inner.barrier().await
+    }
+}
+
+/// Wraps a stack in a [`VerifyFs`], see [`super::Stack::layer_dyn`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VerifyLayer;
+
+impl FsLayer for VerifyLayer {
+    fn layer(&self, inner: Box<dyn DynFs>) -> Box<dyn DynFs> {
+        Box::new(VerifyFs::new(inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn detects_corrupted_writes() {
+        use futures_core::Stream;
+        use tempfile::TempDir;
+
+        use super::VerifyFs;
+        use crate::{
+            disk::TokioFs,
+            dynamic::DynFile,
+            fs::{FileMeta, Fs, OpenOptions},
+            path::Path,
+            Error, IoBuf, IoBufMut, Read, Write,
+        };
+
+        /// Stores every byte written to a file named `corrupt` flipped.
+        struct Corrupting;
+
+        struct CorruptingFile {
+            inner: Box<dyn DynFile>,
+            flip: bool,
+        }
+
+        impl Read for CorruptingFile {
+            async fn read_exact_at<B: IoBufMut>(
+                &mut self,
+                buf: B,
+                pos: u64,
+            ) -> (Result<(), Error>, B) {
+                self.inner.read_exact_at(buf, pos).await
+            }
+
+            async fn read_to_end_at(
+                &mut self,
+                buf: Vec<u8>,
+                pos: u64,
+            ) -> (Result<(), Error>, Vec<u8>) {
+                self.inner.read_to_end_at(buf, pos).await
+            }
+
+            async fn size(&self) -> Result<u64, Error> {
+                self.inner.size().await
+            }
+        }
+
+        impl Write for CorruptingFile {
+            async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+                let mut stored = buf.as_slice().to_vec();
+                if self.flip {
+                    stored.iter_mut().for_each(|byte| *byte = !*byte);
+                }
+                let (result, _) = self.inner.write_all(stored).await;
+                (result, buf)
+            }
+
+            async fn flush(&mut self) -> Result<(), Error> {
+                self.inner.flush().await
+            }
+
+            async fn close(&mut self) -> Result<(), Error> {
+                self.inner.close().await
+            }
+        }
+
+        impl Fs for Corrupting {
+            type File = CorruptingFile;
+
+            async fn open_options(
+                &self,
+                path: &Path,
+                options: OpenOptions,
+            ) -> Result<Self::File, Error> {
+                Ok(CorruptingFile {
+                    inner: Box::new(TokioFs.open_options(path, options).await?),
+                    flip: path.filename() == Some("corrupt"),
+                })
+            }
+
+            async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+                TokioFs.create_dir_all(path).await
+            }
+
+            async fn list(
+                &self,
+                path: &Path,
+            ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+                TokioFs.list(path).await
+            }
+
+            async fn remove(&self, path: &Path) -> Result<(), Error> {
+                TokioFs.remove(path).await
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+        let fs = VerifyFs::new(Corrupting);
+        let options = OpenOptions::default().create(true).truncate(true);
+
+        let mut file = fs
+            .open_options(&path("intact"), options.clone())
+            .await
+            .unwrap();
+        let (result, _) = file.write_all(&b"hello"[..]).await;
+        result.unwrap();
+        file.close().await.unwrap();
+        // appends are verified at the end of the file
+        let mut file = fs
+            .open_options(&path("intact"), OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        let (result, _) = file.write_all(&b" world"[..]).await;
+        result.unwrap();
+        file.close().await.unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("intact")).unwrap(),
+            b"hello world"
+        );
+
+        let mut file = fs.open_options(&path("corrupt"), options).await.unwrap();
+        let (result, _) = file.write_all(&b"hello"[..]).await;
+        result.unwrap();
+        assert!(matches!(
+            file.close().await,
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+}
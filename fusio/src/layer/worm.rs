@@ -0,0 +1,271 @@
+use std::{collections::HashSet, io, sync::Mutex};
+
+use futures_core::Stream;
+
+use super::FsLayer;
+use crate::{
+    dynamic::DynFile,
+    fs::{FileMeta, Fs, ListOptions, OpenOptions, TempGuard, WatchEvent, WatchOptions},
+    path::Path,
+    util::bulk::copy,
+    DynFs, Error,
+};
+
+/// Makes files immutable once written ("write once, read many"), e.g. for append-only audit or
+/// compliance archives.
+///
+/// Opening an existing file for writing fails with [`io::ErrorKind::AlreadyExists`] and
+/// removing a file fails with [`io::ErrorKind::PermissionDenied`]. Files are opened for writing
+/// with [`OpenOptions::create_new`], so the backend refuses to overwrite them even when two
+/// processes create the same path at the same time. Backends that only store a file when it is
+/// closed, like S3, report an existing file from [`crate::Write::close`] as
+/// [`Error::AlreadyExists`] instead. Paths opened for writing through this layer are never opened
+/// for writing again, even before they were closed. Temporary files created through this layer
+/// may be removed.
+pub struct WormFs {
+    inner: Box<dyn DynFs>,
+    written: Mutex<HashSet<Path>>,
+    temps: Mutex<HashSet<Path>>,
+}
+
+impl WormFs {
+    pub fn new<F>(fs: F) -> Self
+    where
+        F: Fs + 'static,
+    {
+        Self {
+            inner: Box::new(fs),
+            written: Mutex::new(HashSet::new()),
+            temps: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+fn cannot_remove(path: &Path) -> Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("\"{path}\" is immutable and cannot be removed"),
+    )
+    .into()
+}
+
+impl Fs for WormFs {
+    type File = Box<dyn DynFile>;
+
+    async fn open_options(&self, path: &Path, options: OpenOptions) -> Result<Self::File, Error> {
+        if !options.write {
+            return DynFs::open_options(self.inner.as_ref(), path, options).await;
+        }
+        if !self.written.lock().unwrap().insert(path.clone()) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("\"{path}\" is already being written"),
+            )
+            .into());
+        }
+        let file = DynFs::open_options(self.inner.as_ref(), path, options.create_new(true)).await;
+        if file.is_err() {
+            self.written.lock().unwrap().remove(path);
+        }
+        match file {
+            Err(Error::AlreadyExists { .. }) => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("\"{path}\" is immutable and cannot be overwritten"),
+            )
+            .into()),
+            file => file,
+        }
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        DynFs::create_dir_all(self.inner.as_ref(), path).await
+    }
+
+    async fn list(
+        &self,
+        path: &Path,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list(&self.inner, path).await
+    }
+
+    async fn list_options(
+        &self,
+        path: &Path,
+        options: ListOptions,
+    ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+        Fs::list_options(&self.inner, path, options).await
+    }
+
+    async fn remove(&self, path: &Path) -> Result<(), Error> {
+        if !self.temps.lock().unwrap().remove(path) {
+            return Err(cannot_remove(path));
+        }
+        DynFs::remove(self.inner.as_ref(), path).await
+    }
+
+    /// Removes the temporary files in one batch, every other path is reported as failed.
+    async fn remove_batch(&self, paths: Vec<Path>) -> Result<Vec<(Path, Error)>, Error> {
+        let (temps, immutable): (Vec<_>, Vec<_>) = {
+            let mut temps = self.temps.lock().unwrap();
+            paths.into_iter().partition(|path| temps.remove(path))
+        };
+        let mut failed = immutable
+            .into_iter()
+            .map(|path| {
+                let error = cannot_remove(&path);
+                (path, error)
+            })
+            .collect::<Vec<_>>();
+        failed.extend(DynFs::remove_batch(self.inner.as_ref(), temps).await?);
+        Ok(failed)
+    }
+
+    /// Copies through [`Fs::open_options`] of this layer, so `to` is created like a new file and
+    /// an existing file is never overwritten. Backends cannot copy conditionally on the server.
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        copy(&self.inner, self, from, to).await
+    }
+
+    async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+        DynFs::stat(self.inner.as_ref(), path).await
+    }
+
+    async fn create_temp(&self, prefix: &Path) -> Result<(Self::File, TempGuard), Error> {
+        let (file, guard) = DynFs::create_temp(self.inner.as_ref(), prefix).await?;
+        self.temps.lock().unwrap().insert(guard.path().clone());
+        Ok((file, guard))
+    }
+
+    async fn watch(
+        &self,
+        path: Path,
+        options: WatchOptions,
+    ) -> Result<impl Stream<Item = Result<WatchEvent, Error>>, Error> {
+        DynFs::watch(self.inner.as_ref(), path, options).await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        DynFs::shutdown(self.inner.as_ref()).await
+    }
+}
+
+/// Wraps a stack in a [`WormFs`], see [`super::Stack::layer_dyn`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WormLayer;
+
+impl FsLayer for WormLayer {
+    fn layer(&self, inner: Box<dyn DynFs>) -> Box<dyn DynFs> {
+        Box::new(WormFs::new(inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn rejects_overwrites_and_removals() {
+        use std::io;
+
+        use tempfile::TempDir;
+
+        use super::WormFs;
+        use crate::{
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+            Error, Write,
+        };
+
+        fn kind<T>(result: Result<T, Error>) -> Option<io::ErrorKind> {
+            match result {
+                Err(Error::Io(e)) => Some(e.kind()),
+                _ => None,
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("existing"), b"fusio").unwrap();
+        let fs = WormFs::new(TokioFs);
+        let existing = Path::from_absolute_path(dir.path().join("existing")).unwrap();
+        let new = Path::from_absolute_path(dir.path().join("new")).unwrap();
+        let options = OpenOptions::default().create(true).truncate(true);
+
+        let mut file = fs.open_options(&new, options.clone()).await.unwrap();
+        assert_eq!(
+            kind(fs.open_options(&new, options.clone()).await),
+            Some(io::ErrorKind::AlreadyExists)
+        );
+        file.write_all(&b"archived"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+
+        assert_eq!(
+            kind(fs.open_options(&existing, options).await),
+            Some(io::ErrorKind::AlreadyExists)
+        );
+        assert_eq!(
+            kind(fs.remove(&new).await),
+            Some(io::ErrorKind::PermissionDenied)
+        );
+        assert_eq!(std::fs::read(dir.path().join("new")).unwrap(), b"archived");
+        assert_eq!(
+            std::fs::read(dir.path().join("existing")).unwrap(),
+            b"fusio"
+        );
+        fs.open(&existing).await.unwrap();
+
+        let (_, guard) = fs
+            .create_temp(&Path::from_absolute_path(dir.path().join("spill")).unwrap())
+            .await
+            .unwrap();
+        guard.remove(&fs).await.unwrap();
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn rejects_overwrites_on_s3() {
+        use std::sync::Arc;
+
+        use super::WormFs;
+        use crate::{
+            fs::{Fs, OpenOptions},
+            path::Path,
+            remotes::aws::mock::MockS3,
+            Error, Write,
+        };
+
+        let server = Arc::new(MockS3::default());
+        server.put("existing", "fusio");
+        let fs = WormFs::new(server.fs());
+        let options = OpenOptions::default().create(true).truncate(true);
+
+        let mut file = fs
+            .open_options(&Path::parse("new").unwrap(), options.clone())
+            .await
+            .unwrap();
+        file.write_all(&b"archived"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+        assert_eq!(server.get("new").unwrap(), &b"archived"[..]);
+
+        // S3 opens lazily and refuses the conditional upload on close
+        let mut file = fs
+            .open_options(&Path::parse("existing").unwrap(), options)
+            .await
+            .unwrap();
+        file.write_all(&b"tampered"[..]).await.0.unwrap();
+        assert!(matches!(
+            file.close().await,
+            Err(Error::AlreadyExists { .. })
+        ));
+        assert_eq!(server.get("existing").unwrap(), &b"fusio"[..]);
+
+        let new = Path::parse("new").unwrap();
+        fs.copy(&new, &Path::parse("copy").unwrap()).await.unwrap();
+        assert_eq!(server.get("copy").unwrap(), &b"archived"[..]);
+        server.put("kept", "fusio");
+        assert!(matches!(
+            fs.copy(&new, &Path::parse("kept").unwrap()).await,
+            Err(Error::AlreadyExists { .. })
+        ));
+        assert_eq!(server.get("kept").unwrap(), &b"fusio"[..]);
+    }
+}
@@ -46,167 +46,35 @@
 //! ```
 
 pub mod buf;
+pub mod cache;
+pub mod clock;
+pub mod context;
+pub mod cores;
 #[cfg(feature = "dyn")]
 pub mod dynamic;
 mod error;
 #[cfg(feature = "fs")]
 pub mod fs;
 pub mod impls;
-pub mod path;
-
-use std::future::Future;
+#[cfg(feature = "fs")]
+pub mod layer;
+mod macros;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "dyn")]
+pub mod task;
+#[cfg(feature = "fs")]
+pub mod util;
 
 pub use buf::{IoBuf, IoBufMut};
 #[cfg(all(feature = "dyn", feature = "fs"))]
 pub use dynamic::fs::DynFs;
 #[cfg(feature = "dyn")]
 pub use dynamic::{DynRead, DynWrite};
-pub use error::Error;
+pub use error::{BoxedError, Error};
+pub use fusio_core::{path, MaybeSend, MaybeSync, Read, Write};
 pub use impls::*;
 
-#[cfg(not(feature = "no-send"))]
-pub unsafe trait MaybeSend: Send {
-    //! Considering lots of runtimes does not require [`std::marker::Send`] for
-    //! [`std::future::Future`] and [`futures_core::stream::Stream`], we provide a trait to
-    //! represent the future or stream that may not require [`std::marker::Send`]. Users could
-    //! switch the feature `no-send` at compile-time to disable the [`std::marker::Send`] bound
-    //! for [`std::future::Future`] and [`futures_core::stream::Stream`].
-    //!
-    //! # Safety
-    //! Do not implement it directly.
-}
-
-/// # Safety
-/// Do not implement it directly
-#[cfg(feature = "no-send")]
-pub unsafe trait MaybeSend {}
-
-#[cfg(not(feature = "no-send"))]
-unsafe impl<T: Send> MaybeSend for T {}
-#[cfg(feature = "no-send")]
-unsafe impl<T> MaybeSend for T {}
-
-#[cfg(not(feature = "no-send"))]
-pub unsafe trait MaybeSync: Sync {
-    //! Same as [`MaybeSend`], but for [`std::marker::Sync`].
-    //!
-    //! # Safety
-    //! Do not implement it directly.
-}
-
-#[cfg(feature = "no-send")]
-pub unsafe trait MaybeSync {}
-
-#[cfg(not(feature = "no-send"))]
-unsafe impl<T: Sync> MaybeSync for T {}
-#[cfg(feature = "no-send")]
-unsafe impl<T> MaybeSync for T {}
-
-pub trait Write: MaybeSend {
-    //! The core trait for writing data,
-    //! it is similar to [`std::io::Write`], but it takes the ownership of the buffer,
-    //! because completion-based IO requires the buffer to be pinned and should be safe to
-    //! cancellation.
-    //!
-    //! [`Write`] represents "sequential write all and overwrite" semantics,
-    //! which means each buffer will be written to the file sequentially and overwrite the previous
-    //! file when closed.
-    //!
-    //! Contents are not be garanteed to be written to the file until the [`Write::close`] method is
-    //! called, [`Write::flush`] may be used to flush the data to the file in some
-    //! implementations, but not all implementations will do so.
-    //!
-    //! Whether the operation is successful or not, the buffer will be returned,
-    //! fusio promises that the returned buffer will be the same as the input buffer.
-    //!
-    //! # Dyn Compatibility
-    //! This trait is not dyn compatible.
-    //! If you want to use [`Write`] trait in a dynamic way, you could use [`DynWrite`] trait.
-
-    fn write_all<B: IoBuf>(
-        &mut self,
-        buf: B,
-    ) -> impl Future<Output = (Result<(), Error>, B)> + MaybeSend;
-
-    fn flush(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend;
-
-    fn close(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend;
-}
-
-pub trait Read: MaybeSend + MaybeSync {
-    //! The core trait for reading data,
-    //! it is similar to [`std::io::Read`],
-    //! but it takes the ownership of the buffer,
-    //! because completion-based IO requires the buffer to be pinned and should be safe to
-    //! cancellation.
-    //!
-    //! [`Read`] represents "random exactly read" semantics,
-    //! which means the read operation will start at the specified position, and the buffer will be
-    //! exactly filled with the data read.
-    //!
-    //! The buffer will be returned with the result, whether the operation is successful or not,
-    //! fusio promises that the returned buffer will be the same as the input buffer.
-    //!
-    //! If you want sequential reading, try [`SeqRead`].
-    //!
-    //! # Dyn Compatibility
-    //! This trait is not dyn compatible.
-    //! If you want to use [`Read`] trait in a dynamic way, you could use [`DynRead`] trait.
-
-    fn read_exact_at<B: IoBufMut>(
-        &mut self,
-        buf: B,
-        pos: u64,
-    ) -> impl Future<Output = (Result<(), Error>, B)> + MaybeSend;
-
-    fn read_to_end_at(
-        &mut self,
-        buf: Vec<u8>,
-        pos: u64,
-    ) -> impl Future<Output = (Result<(), Error>, Vec<u8>)> + MaybeSend;
-
-    fn size(&self) -> impl Future<Output = Result<u64, Error>> + MaybeSend;
-}
-
-impl<R: Read> Read for &mut R {
-    fn read_exact_at<B: IoBufMut>(
-        &mut self,
-        buf: B,
-        pos: u64,
-    ) -> impl Future<Output = (Result<(), Error>, B)> + MaybeSend {
-        R::read_exact_at(self, buf, pos)
-    }
-
-    fn read_to_end_at(
-        &mut self,
-        buf: Vec<u8>,
-        pos: u64,
-    ) -> impl Future<Output = (Result<(), Error>, Vec<u8>)> + MaybeSend {
-        R::read_to_end_at(self, buf, pos)
-    }
-
-    fn size(&self) -> impl Future<Output = Result<u64, Error>> + MaybeSend {
-        R::size(self)
-    }
-}
-
-impl<W: Write> Write for &mut W {
-    fn write_all<B: IoBuf>(
-        &mut self,
-        buf: B,
-    ) -> impl Future<Output = (Result<(), Error>, B)> + MaybeSend {
-        W::write_all(self, buf)
-    }
-
-    fn flush(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend {
-        W::flush(self)
-    }
-
-    fn close(&mut self) -> impl Future<Output = Result<(), Error>> + MaybeSend {
-        W::close(self)
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::{Read, Write};
@@ -415,10 +283,167 @@ mod tests {
         result.unwrap();
         assert_eq!(buf.as_slice(), b"hello");
         let (result, _) = file.read_exact_at(vec![0u8; 8], 5).await;
-        assert!(result.is_err());
-        if let Error::Io(e) = result.unwrap_err() {
-            assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
-        }
+        assert!(matches!(
+            result.unwrap_err(),
+            Error::UnexpectedEof {
+                requested: 8,
+                available: 7
+            }
+        ));
+
+        let (result, buf) = file.read_at(vec![0u8; 8], 5).await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(&buf[..7], b", world");
+        let (result, _) = file.read_at(vec![0u8; 8], 12).await;
+        assert_eq!(result.unwrap(), 0);
+    }
+
+    #[cfg(all(feature = "tokio", unix))]
+    #[tokio::test]
+    async fn test_large_sparse_file() {
+        use std::os::unix::fs::FileExt;
+
+        use tempfile::tempfile;
+        use tokio::fs::File;
+
+        const SIZE: u64 = 5 << 30;
+        const POS: u64 = (4 << 30) + 10;
+
+        let file = tempfile().unwrap();
+        file.set_len(SIZE).unwrap();
+        file.write_all_at(b"past 4GiB", POS).unwrap();
+        file.write_all_at(b"end", SIZE - 3).unwrap();
+        let mut file = File::from_std(file);
+
+        assert_eq!(file.size().await.unwrap(), SIZE);
+        let (result, buf) = file.read_exact_at(vec![0u8; 9], POS).await;
+        result.unwrap();
+        assert_eq!(buf, b"past 4GiB");
+        let (result, _) = file.read_exact_at(vec![0u8; 8], SIZE - 3).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            Error::UnexpectedEof {
+                requested: 8,
+                available: 3
+            }
+        ));
+        let (result, buf) = file.read_at(vec![0u8; 8], SIZE - 3).await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(&buf[..3], b"end");
+        let (result, buf) = file.read_to_end_at(Vec::new(), SIZE - 3).await;
+        result.unwrap();
+        assert_eq!(buf, b"end");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_read_vec_past_u32() {
+        let mut data = b"hello".to_vec();
+        let (result, _) = (&mut data)
+            .read_exact_at(vec![0u8; 1], u64::from(u32::MAX) + 1)
+            .await;
+        assert!(matches!(
+            result.unwrap_err(),
+            Error::UnexpectedEof {
+                requested: 1,
+                available: 0
+            }
+        ));
+        let (result, buf) = (&mut data).read_to_end_at(Vec::new(), u64::MAX).await;
+        result.unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_batches() {
+        use std::io::Cursor;
+
+        let mut data = Vec::new();
+        let (result, bufs) = Cursor::new(&mut data)
+            .write_batch(vec![&b"hello"[..], &b", "[..], &b"world"[..]])
+            .await;
+        result.unwrap();
+        assert_eq!(bufs.len(), 3);
+        assert_eq!(data, b"hello, world");
+
+        let bufs = (&mut data)
+            .read_batch(vec![(7, 5), (0, 5), (5, 0)])
+            .await
+            .unwrap();
+        assert_eq!(bufs, vec![b"world".to_vec(), b"hello".to_vec(), Vec::new()]);
+        assert!(matches!(
+            (&mut data).read_batch(vec![(0, 1), (10, 5)]).await,
+            Err(Error::UnexpectedEof {
+                requested: 5,
+                available: 2
+            })
+        ));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_zero_length_file() {
+        use tempfile::TempDir;
+
+        use crate::{disk::TokioFs, fs::OpenOptions, path::Path, DynFs};
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("empty")).unwrap();
+        let fs = TokioFs;
+
+        let mut file = fs
+            .open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+
+        let mut file = fs.open(&path).await.unwrap();
+        assert_eq!(file.size().await.unwrap(), 0);
+        let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+        result.unwrap();
+        assert!(buf.is_empty());
+        let (result, buf) = file.read_exact_at(Vec::new(), 0).await;
+        result.unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn test_barrier() {
+        use tempfile::TempDir;
+
+        use crate::{
+            buffered::BufWriter, disk::TokioFs, dynamic::DynFile, fs::OpenOptions, path::Path,
+            DynFs,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let path = Path::from_absolute_path(dir.path().join("journal")).unwrap();
+        let file: Box<dyn DynFile> = TokioFs
+            .open_options(&path, OpenOptions::default().create(true))
+            .await
+            .unwrap();
+        let mut journal = BufWriter::new(file, 64);
+
+        let (result, _) = journal.write_all(&b"entry;"[..]).await;
+        result.unwrap();
+        assert!(std::fs::read(dir.path().join("journal"))
+            .unwrap()
+            .is_empty());
+        // the buffered entry reaches the file before the commit record is written
+        journal.barrier().await.unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("journal")).unwrap(),
+            b"entry;"
+        );
+        let (result, _) = journal.write_all(&b"commit;"[..]).await;
+        result.unwrap();
+        journal.close().await.unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("journal")).unwrap(),
+            b"entry;commit;"
+        );
     }
 
     #[cfg(feature = "monoio")]
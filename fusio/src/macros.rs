@@ -0,0 +1,130 @@
+/// Implements [`Read`](crate::Read) and [`Write`](crate::Write) for a wrapper by forwarding every
+/// method, including the batched ones, to one of its fields, e.g. a `tokio::fs::File` or any
+/// other file of fusio. The wrapper is then a [`DynFile`](crate::dynamic::DynFile) as well.
+///
+/// Generic wrappers list their parameters with bounds in brackets first.
+///
+/// ```
+/// use fusio::{dynamic::DynFile, impl_file, Read, Write};
+///
+/// struct Tracked {
+///     file: Box<dyn DynFile>,
+///     name: String,
+/// }
+///
+/// impl_file!(Tracked => file);
+///
+/// struct Wrapped<F>(F);
+///
+/// impl_file!([F: Read + Write] Wrapped<F> => 0);
+/// ```
+#[macro_export]
+macro_rules! impl_file {
+    ([$($generics:tt)*] $ty:ty => $field:tt) => {
+        impl<$($generics)*> $crate::Read for $ty {
+            async fn read_exact_at<B: $crate::IoBufMut>(
+                &mut self,
+                buf: B,
+                pos: u64,
+            ) -> (::core::result::Result<(), $crate::Error>, B) {
+                $crate::Read::read_exact_at(&mut self.$field, buf, pos).await
+            }
+
+            async fn read_to_end_at(
+                &mut self,
+                buf: ::std::vec::Vec<u8>,
+                pos: u64,
+            ) -> (
+                ::core::result::Result<(), $crate::Error>,
+                ::std::vec::Vec<u8>,
+            ) {
+                $crate::Read::read_to_end_at(&mut self.$field, buf, pos).await
+            }
+
+            async fn size(&self) -> ::core::result::Result<u64, $crate::Error> {
+                $crate::Read::size(&self.$field).await
+            }
+
+            async fn read_at<B: $crate::IoBufMut>(
+                &mut self,
+                buf: B,
+                pos: u64,
+            ) -> (::core::result::Result<usize, $crate::Error>, B) {
+                $crate::Read::read_at(&mut self.$field, buf, pos).await
+            }
+
+            async fn read_batch(
+                &mut self,
+                ranges: ::std::vec::Vec<(u64, usize)>,
+            ) -> ::core::result::Result<::std::vec::Vec<::std::vec::Vec<u8>>, $crate::Error> {
+                $crate::Read::read_batch(&mut self.$field, ranges).await
+            }
+        }
+
+        impl<$($generics)*> $crate::Write for $ty {
+            async fn write_all<B: $crate::IoBuf>(
+                &mut self,
+                buf: B,
+            ) -> (::core::result::Result<(), $crate::Error>, B) {
+                $crate::Write::write_all(&mut self.$field, buf).await
+            }
+
+            async fn flush(&mut self) -> ::core::result::Result<(), $crate::Error> {
+                $crate::Write::flush(&mut self.$field).await
+            }
+
+            async fn close(&mut self) -> ::core::result::Result<(), $crate::Error> {
+                $crate::Write::close(&mut self.$field).await
+            }
+
+            async fn write_batch<B: $crate::IoBuf>(
+                &mut self,
+                bufs: ::std::vec::Vec<B>,
+            ) -> (
+                ::core::result::Result<(), $crate::Error>,
+                ::std::vec::Vec<B>,
+            ) {
+                $crate::Write::write_batch(&mut self.$field, bufs).await
+            }
+
+            async fn barrier(&mut self) -> ::core::result::Result<(), $crate::Error> {
+                $crate::Write::barrier(&mut self.$field).await
+            }
+        }
+    };
+    ($ty:ty => $field:tt) => {
+        $crate::impl_file!([] $ty => $field);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn forwards_to_field() {
+        use tempfile::tempfile;
+
+        use crate::{dynamic::DynFile, Read, Write};
+
+        struct Tracked {
+            file: tokio::fs::File,
+        }
+
+        impl_file!(Tracked => file);
+
+        struct Wrapped<F>(F);
+
+        impl_file!([F: Read + Write] Wrapped<F> => 0);
+
+        let file = tokio::fs::File::from_std(tempfile().unwrap());
+        let mut file: Box<dyn DynFile> = Box::new(Wrapped(Tracked { file }));
+        let (result, _) = file.write_batch(vec![&b"hello, "[..], &b"fusio"[..]]).await;
+        result.unwrap();
+        file.flush().await.unwrap();
+
+        assert_eq!(file.size().await.unwrap(), 12);
+        let (result, buf) = file.read_exact_at(vec![0; 5], 7).await;
+        result.unwrap();
+        assert_eq!(buf, b"fusio");
+    }
+}
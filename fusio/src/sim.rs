@@ -0,0 +1,361 @@
+//! Deterministic simulation of the environment fusio runs in, for seed-reproducible
+//! failure-injection tests of systems built on it.
+//!
+//! A simulation replaces the three sources of nondeterminism fusio depends on:
+//!
+//! - time, with a [`SimClock`] handed to backends and layers that take a [`Clock`],
+//! - randomness, with [`seed`], which makes the names of temporary files and the hashes of caches
+//!   and filters depend only on the seed for the current thread,
+//! - the network, with a [`SimClient`] that injects faults and latency into the requests of another
+//!   HTTP client, e.g. an in-memory S3 server, as decided by its seed.
+//!
+//! Runs are reproducible when they run on a single thread and poll their tasks in the same
+//! order, e.g. on a current thread runtime without real I/O, and a failing seed can be replayed
+//! as is.
+
+use std::{
+    cell::Cell,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::clock::Clock;
+
+thread_local! {
+    static SEEDED: Cell<Option<SimRng>> = const { Cell::new(None) };
+}
+
+/// Makes the randomness fusio uses on the current thread derive from `seed`.
+pub fn seed(seed: u64) {
+    SEEDED.with(|seeded| seeded.set(Some(SimRng::new(seed))));
+}
+
+/// The next random number of the current thread if it was [`seed`]ed.
+pub(crate) fn seeded_u64() -> Option<u64> {
+    SEEDED.with(|seeded| {
+        let mut rng = seeded.get()?;
+        let random = rng.next_u64();
+        seeded.set(Some(rng));
+        Some(random)
+    })
+}
+
+/// A small, fast and deterministic random number generator (SplitMix64), not suitable for
+/// cryptography.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A duration in `[min, max]`.
+    pub fn duration(&mut self, min: Duration, max: Duration) -> Duration {
+        let span = max.saturating_sub(min).as_nanos() as u64;
+        match span {
+            0 => min,
+            span => min + Duration::from_nanos(self.next_u64() % (span + 1)),
+        }
+    }
+}
+
+/// A clock that only moves when it is told to, starting at the Unix epoch by default.
+#[derive(Debug)]
+pub struct SimClock {
+    now: Mutex<SystemTime>,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self::new(UNIX_EPOCH)
+    }
+}
+
+impl SimClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(feature = "http")]
+pub use client::{Fault, SimClient};
+
+#[cfg(feature = "http")]
+mod client {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+    use http::{Request, Response, StatusCode};
+    use http_body::Body;
+    use http_body_util::{BodyExt, Full};
+
+    use super::{SimClock, SimRng};
+    use crate::{
+        error::BoxedError,
+        remotes::http::{BoxBody, DynHttpClient, HttpClient, HttpError},
+        MaybeSync,
+    };
+
+    /// A failure a [`SimClient`] injected into a request.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Fault {
+        /// The request failed to connect and never reached the server.
+        Refused,
+        /// The server answered `503 Service Unavailable` without handling the request.
+        Unavailable,
+        /// The server handled the request but the response was lost, so the caller cannot tell
+        /// whether it took effect.
+        Lost,
+    }
+
+    struct Rates {
+        refused: f64,
+        unavailable: f64,
+        lost: f64,
+    }
+
+    /// Forwards requests to another client, injecting faults and latency decided by a seeded
+    /// [`SimRng`], e.g. in front of an in-memory S3 server given to `AmazonS3Builder::client`.
+    ///
+    /// Latency is simulated by advancing a [`SimClock`] instead of waiting, so simulated time
+    /// passes as fast as the test runs.
+    pub struct SimClient {
+        inner: Box<dyn DynHttpClient>,
+        rng: Mutex<SimRng>,
+        rates: Rates,
+        latency: Option<(Arc<SimClock>, Duration, Duration)>,
+        faults: Mutex<Vec<(String, Fault)>>,
+    }
+
+    impl SimClient {
+        pub fn new<C: HttpClient + 'static>(client: C, seed: u64) -> Self {
+            Self {
+                inner: Box::new(client),
+                rng: Mutex::new(SimRng::new(seed)),
+                rates: Rates {
+                    refused: 0.0,
+                    unavailable: 0.0,
+                    lost: 0.0,
+                },
+                latency: None,
+                faults: Mutex::new(Vec::new()),
+            }
+        }
+
+        /// Fails this share of requests with [`Fault::Refused`].
+        pub fn with_refused(mut self, rate: f64) -> Self {
+            self.rates.refused = rate;
+            self
+        }
+
+        /// Answers this share of requests with [`Fault::Unavailable`].
+        pub fn with_unavailable(mut self, rate: f64) -> Self {
+            self.rates.unavailable = rate;
+            self
+        }
+
+        /// Loses the response of this share of requests, see [`Fault::Lost`].
+        pub fn with_lost(mut self, rate: f64) -> Self {
+            self.rates.lost = rate;
+            self
+        }
+
+        /// Advances `clock` by a latency between `min` and `max` on every request.
+        pub fn with_latency(mut self, clock: Arc<SimClock>, min: Duration, max: Duration) -> Self {
+            self.latency = Some((clock, min, max));
+            self
+        }
+
+        /// The faults injected so far with the method and URI of their requests, to see what a
+        /// seed did.
+        pub fn faults(&self) -> Vec<(String, Fault)> {
+            self.faults.lock().unwrap().clone()
+        }
+
+        fn decide(&self) -> Option<Fault> {
+            let mut rng = self.rng.lock().unwrap();
+            if let Some((clock, min, max)) = &self.latency {
+                clock.advance(rng.duration(*min, *max));
+            }
+            let roll = rng.next_f64();
+            let Rates {
+                refused,
+                unavailable,
+                lost,
+            } = self.rates;
+            if roll < refused {
+                Some(Fault::Refused)
+            } else if roll < refused + unavailable {
+                Some(Fault::Unavailable)
+            } else if roll < refused + unavailable + lost {
+                Some(Fault::Lost)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn connection_error(kind: io::ErrorKind, message: &str) -> HttpError {
+        HttpError::Other(Box::new(io::Error::new(kind, message.to_string())))
+    }
+
+    impl HttpClient for SimClient {
+        type RespBody = BoxBody;
+
+        async fn send_request<B>(
+            &self,
+            request: Request<B>,
+        ) -> Result<Response<Self::RespBody>, HttpError>
+        where
+            B: Body + Send + MaybeSync + 'static,
+            B::Data: Into<Bytes>,
+            B::Error: Into<BoxedError>,
+        {
+            let fault = self.decide();
+            if let Some(fault) = fault {
+                let request = format!("{} {}", request.method(), request.uri());
+                self.faults.lock().unwrap().push((request, fault));
+            }
+            match fault {
+                Some(Fault::Refused) => Err(connection_error(
+                    io::ErrorKind::ConnectionRefused,
+                    "simulated connection failure",
+                )),
+                Some(Fault::Unavailable) => Ok(Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body(BoxBody::new(
+                        Full::new(Bytes::new()).map_err(|e| match e {}),
+                    ))?),
+                Some(Fault::Lost) => {
+                    self.inner.send_request(request).await?;
+                    Err(connection_error(
+                        io::ErrorKind::ConnectionReset,
+                        "simulated lost response",
+                    ))
+                }
+                None => self.inner.send_request(request).await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "fs")]
+    #[test]
+    fn seeds_randomness() {
+        use super::{seed, seeded_u64, SimRng};
+        use crate::{fs::temp_path, path::Path};
+
+        let prefix = Path::parse("tmp/spill").unwrap();
+        seed(7);
+        let first = (seeded_u64(), temp_path(&prefix).unwrap());
+        seed(7);
+        assert_eq!((seeded_u64(), temp_path(&prefix).unwrap()), first);
+        seed(8);
+        assert_ne!(seeded_u64(), first.0);
+
+        let mut rng = SimRng::new(1);
+        assert!((0..1000)
+            .map(|_| rng.next_f64())
+            .all(|f| (0.0..1.0).contains(&f)));
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn injects_faults_by_seed() {
+        use std::{sync::Arc, time::Duration};
+
+        use bytes::Bytes;
+        use http::{Request, Response};
+        use http_body::Body;
+        use http_body_util::{Empty, Full};
+
+        use super::{Fault, SimClient, SimClock};
+        use crate::{
+            clock::Clock,
+            error::BoxedError,
+            remotes::http::{HttpClient, HttpError},
+            MaybeSync,
+        };
+
+        struct Echo;
+
+        impl HttpClient for Echo {
+            type RespBody = Full<Bytes>;
+
+            async fn send_request<B>(
+                &self,
+                _request: Request<B>,
+            ) -> Result<Response<Self::RespBody>, HttpError>
+            where
+                B: Body + Send + MaybeSync + 'static,
+                B::Data: Into<Bytes>,
+                B::Error: Into<BoxedError>,
+            {
+                Ok(Response::new(Full::new(Bytes::from_static(b"ok"))))
+            }
+        }
+
+        async fn run(seed: u64) -> (Vec<(String, Fault)>, std::time::SystemTime) {
+            let clock = Arc::new(SimClock::default());
+            let client = SimClient::new(Echo, seed)
+                .with_refused(0.1)
+                .with_unavailable(0.1)
+                .with_lost(0.1)
+                .with_latency(
+                    clock.clone(),
+                    Duration::from_millis(1),
+                    Duration::from_millis(100),
+                );
+            for i in 0..100 {
+                let request = Request::get(format!("http://sim/{i}"))
+                    .body(Empty::<Bytes>::new())
+                    .unwrap();
+                let _ = client.send_request(request).await;
+            }
+            (client.faults(), clock.now())
+        }
+
+        let (faults, now) = run(42).await;
+        assert!(faults.iter().any(|(_, fault)| *fault == Fault::Refused));
+        assert!(faults.iter().any(|(_, fault)| *fault == Fault::Unavailable));
+        assert!(faults.iter().any(|(_, fault)| *fault == Fault::Lost));
+        assert!(faults.len() < 60, "{} faults", faults.len());
+        assert!(now > std::time::UNIX_EPOCH + Duration::from_millis(100));
+        assert_eq!(run(42).await, (faults.clone(), now));
+        assert_ne!(run(43).await.0, faults);
+    }
+}
@@ -0,0 +1,263 @@
+//! Background tasks for layers, independent of the async runtime in use.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Poll, Waker},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use futures_util::{future::poll_fn, StreamExt};
+
+use crate::{dynamic::MaybeSendFuture, MaybeSend, MaybeSync};
+
+pub trait Spawner: MaybeSend + MaybeSync {
+    //! Runs futures in the background on an async runtime.
+    //!
+    //! Layers that need background work, like migrations or eviction, take a spawner instead of
+    //! calling the spawn function of a particular runtime, usually through a [`TaskGroup`].
+
+    fn spawn(&self, future: Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>>);
+}
+
+impl<S: Spawner + ?Sized> Spawner for Arc<S> {
+    fn spawn(&self, future: Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>>) {
+        S::spawn(self, future)
+    }
+}
+
+/// Spawns onto the tokio runtime the caller is running in.
+#[cfg(all(feature = "tokio", not(feature = "no-send")))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioSpawner;
+
+#[cfg(all(feature = "tokio", not(feature = "no-send")))]
+impl Spawner for TokioSpawner {
+    fn spawn(&self, future: Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>>) {
+        tokio::spawn(future);
+    }
+}
+
+/// Spawns onto the monoio runtime of the current thread.
+#[cfg(feature = "monoio")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MonoioSpawner;
+
+#[cfg(feature = "monoio")]
+impl Spawner for MonoioSpawner {
+    fn spawn(&self, future: Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>>) {
+        monoio::spawn(future);
+    }
+}
+
+/// Spawns onto the tokio-uring runtime of the current thread.
+#[cfg(all(feature = "tokio-uring", target_os = "linux"))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioUringSpawner;
+
+#[cfg(all(feature = "tokio-uring", target_os = "linux"))]
+impl Spawner for TokioUringSpawner {
+    fn spawn(&self, future: Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>>) {
+        tokio_uring::spawn(future);
+    }
+}
+
+pub trait Timer: MaybeSend + MaybeSync {
+    //! Waits on an async runtime.
+    //!
+    //! Layers that bound how long a call may take, like [`crate::layer::DeadlineFs`], take a timer
+    //! instead of calling the sleep function of a particular runtime.
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>>;
+}
+
+impl<T: Timer + ?Sized> Timer for Arc<T> {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>> {
+        T::sleep(self, duration)
+    }
+}
+
+/// Sleeps with the timer of the tokio runtime the caller is running in.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+#[derive(Default)]
+struct State {
+    shutdown: AtomicBool,
+    running: AtomicUsize,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl State {
+    fn wake_all(&self) {
+        for waker in self.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+/// Tells a background task of a [`TaskGroup`] that it should stop.
+///
+/// Tasks are never cancelled, they stop cooperatively by checking or awaiting this signal.
+#[derive(Clone)]
+pub struct Shutdown {
+    state: Arc<State>,
+}
+
+impl Shutdown {
+    pub fn is_shutdown(&self) -> bool {
+        self.state.shutdown.load(Ordering::Acquire)
+    }
+
+    /// Completes once the group is shut down.
+    pub fn wait(&self) -> impl Future<Output = ()> + MaybeSend + 'static {
+        let state = self.state.clone();
+        poll_fn(move |cx| {
+            state.register(cx.waker());
+            match state.shutdown.load(Ordering::Acquire) {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            }
+        })
+    }
+
+    /// Ends `stream` once the group is shut down, e.g. to stop a loop driven by a timer.
+    pub fn take_until<S: Stream>(&self, stream: S) -> impl Stream<Item = S::Item> {
+        stream.take_until(self.wait())
+    }
+}
+
+/// Owns the background tasks of a layer and stops them together.
+///
+/// [`TaskGroup::shutdown`] signals every task and waits until all of them returned. Dropping the
+/// group only signals them, as waiting needs an async context.
+pub struct TaskGroup {
+    spawner: Arc<dyn Spawner>,
+    state: Arc<State>,
+}
+
+impl TaskGroup {
+    pub fn new(spawner: Arc<dyn Spawner>) -> Self {
+        Self {
+            spawner,
+            state: Arc::new(State::default()),
+        }
+    }
+
+    /// Spawns the future returned by `task`, which is handed the [`Shutdown`] signal to stop on.
+    pub fn spawn<T, Fut>(&self, task: T)
+    where
+        T: FnOnce(Shutdown) -> Fut,
+        Fut: Future<Output = ()> + MaybeSend + 'static,
+    {
+        struct Running(Arc<State>);
+
+        impl Drop for Running {
+            fn drop(&mut self) {
+                self.0.running.fetch_sub(1, Ordering::AcqRel);
+                self.0.wake_all();
+            }
+        }
+
+        self.state.running.fetch_add(1, Ordering::AcqRel);
+        let running = Running(self.state.clone());
+        let future = task(self.shutdown_signal());
+        self.spawner.spawn(Box::pin(async move {
+            let _running = running;
+            future.await;
+        }));
+    }
+
+    pub fn shutdown_signal(&self) -> Shutdown {
+        Shutdown {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Number of spawned tasks that have not returned yet.
+    pub fn running(&self) -> usize {
+        self.state.running.load(Ordering::Acquire)
+    }
+
+    /// Signals every task to stop and waits until all of them returned.
+    pub async fn shutdown(&self) {
+        self.state.shutdown.store(true, Ordering::Release);
+        self.state.wake_all();
+
+        poll_fn(|cx| {
+            self.state.register(cx.waker());
+            match self.state.running.load(Ordering::Acquire) {
+                0 => Poll::Ready(()),
+                _ => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+impl Drop for TaskGroup {
+    fn drop(&mut self) {
+        self.state.shutdown.store(true, Ordering::Release);
+        self.state.wake_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn shutdown_waits_for_tasks() {
+        use std::{
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Arc,
+            },
+            time::Duration,
+        };
+
+        use futures_util::{stream, StreamExt};
+
+        use super::{TaskGroup, TokioSpawner};
+
+        let tasks = TaskGroup::new(Arc::new(TokioSpawner));
+        let stopped = Arc::new(AtomicBool::new(false));
+        tasks.spawn(|shutdown| {
+            let stopped = stopped.clone();
+            async move {
+                let ticks = stream::unfold((), |_| async {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    Some(((), ()))
+                });
+                let mut ticks = Box::pin(shutdown.take_until(ticks));
+                while ticks.next().await.is_some() {}
+                stopped.store(true, Ordering::SeqCst);
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(tasks.running(), 1);
+
+        tasks.shutdown().await;
+        assert_eq!(tasks.running(), 0);
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,200 @@
+use futures_util::{stream, StreamExt, TryStreamExt};
+
+use super::{glob::Glob, walk::walk};
+use crate::{
+    fs::{Fs, OpenOptions},
+    path::Path,
+    Error, Read, Write,
+};
+
+async fn matching<F: Fs>(fs: &F, glob: &Glob) -> Result<Vec<Path>, Error> {
+    let mut paths = Vec::new();
+    walk(fs, &glob.prefix(), |meta| {
+        if glob.matches(&meta.path) {
+            paths.push(meta.path);
+        }
+    })
+    .await?;
+
+    Ok(paths)
+}
+
+/// How many paths [`remove_matching`] hands to [`Fs::remove_batch`] at once, as many as one S3
+/// DeleteObjects request takes.
+const REMOVE_BATCH: usize = 1000;
+
+/// How many bytes [`copy`] reads and writes at once.
+const COPY_CHUNK: usize = 8 * 1024 * 1024;
+
+/// Removes every file matching `glob` with [`Fs::remove_batch`], running up to `concurrency`
+/// batches at once. Returns the number of removed files, or the first error if any file could
+/// not be removed.
+pub async fn remove_matching<F: Fs>(
+    fs: &F,
+    glob: &Glob,
+    concurrency: usize,
+) -> Result<usize, Error> {
+    let paths = matching(fs, glob).await?;
+
+    stream::iter(paths.chunks(REMOVE_BATCH).map(|batch| async move {
+        let failed = fs.remove_batch(batch.to_vec()).await?;
+        match failed.into_iter().next() {
+            Some((_, error)) => Err(error),
+            None => Ok(batch.len()),
+        }
+    }))
+    .buffer_unordered(concurrency.max(1))
+    .try_fold(0, |removed, batch| async move { Ok(removed + batch) })
+    .await
+}
+
+/// Copies every file matching `glob` from `src` into `dst`, keeping the part of the path below
+/// the literal prefix of `glob` and placing it under `to`. Up to `concurrency` files are copied at
+/// once. Returns the number of copied files.
+pub async fn copy_matching<S: Fs, D: Fs>(
+    src: &S,
+    dst: &D,
+    glob: &Glob,
+    to: &Path,
+    concurrency: usize,
+) -> Result<usize, Error> {
+    let prefix = glob.prefix();
+    let paths = matching(src, glob).await?;
+
+    stream::iter(paths.iter().map(|from| {
+        let relative = from.prefix_match(&prefix).into_iter().flatten();
+        let target = Path::from_iter(to.parts().chain(relative));
+        async move { copy(src, dst, from, &target).await }
+    }))
+    .buffer_unordered(concurrency.max(1))
+    .try_fold(0, |copied, _| async move { Ok(copied + 1) })
+    .await
+}
+
+/// Copies `from` on `src` to `to` on `dst`, creating the parent directories of `to`. The content
+/// is streamed in chunks, so large files are never held in memory as a whole.
+pub(crate) async fn copy<S: Fs, D: Fs>(
+    src: &S,
+    dst: &D,
+    from: &Path,
+    to: &Path,
+) -> Result<(), Error> {
+    let mut reader = src.open(from).await?;
+    let size = reader.size().await?;
+
+    let parts = to.parts().collect::<Vec<_>>();
+    if let Some((_, parents)) = parts.split_last() {
+        dst.create_dir_all(&Path::from_iter(parents.iter().cloned()))
+            .await?;
+    }
+    let mut writer = dst
+        .open_options(to, OpenOptions::default().create(true).truncate(true))
+        .await?;
+    let mut buf = Vec::new();
+    let mut pos = 0;
+    while pos < size {
+        buf.resize((size - pos).min(COPY_CHUNK as u64) as usize, 0);
+        let (result, read) = reader.read_exact_at(buf, pos).await;
+        result?;
+        let (result, written) = writer.write_all(read).await;
+        result?;
+        pos += written.len() as u64;
+        buf = written;
+    }
+    writer.close().await
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn remove_and_copy_matching() {
+        use tempfile::TempDir;
+
+        use super::{copy_matching, remove_matching};
+        use crate::{disk::TokioFs, path::Path, util::Glob};
+
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        std::fs::create_dir_all(src.path().join("logs/2024")).unwrap();
+        std::fs::write(src.path().join("logs/a.log"), b"a").unwrap();
+        std::fs::write(src.path().join("logs/2024/b.log"), b"b").unwrap();
+        std::fs::write(src.path().join("logs/2024/c.txt"), b"c").unwrap();
+
+        let src_root = Path::from_absolute_path(src.path()).unwrap();
+        let glob = Glob::new(format!("{src_root}/logs/**/*.log")).unwrap();
+
+        let copied = copy_matching(
+            &TokioFs,
+            &TokioFs,
+            &glob,
+            &Path::from_absolute_path(dst.path()).unwrap(),
+            4,
+        )
+        .await
+        .unwrap();
+        assert_eq!(copied, 2);
+        assert_eq!(std::fs::read(dst.path().join("a.log")).unwrap(), b"a");
+        assert_eq!(std::fs::read(dst.path().join("2024/b.log")).unwrap(), b"b");
+        assert!(!dst.path().join("2024/c.txt").exists());
+
+        let removed = remove_matching(&TokioFs, &glob, 4).await.unwrap();
+        assert_eq!(removed, 2);
+        assert!(!src.path().join("logs/a.log").exists());
+        assert!(src.path().join("logs/2024/c.txt").exists());
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn removes_in_batches() {
+        use std::sync::Arc;
+
+        use http::Method;
+
+        use super::remove_matching;
+        use crate::{remotes::aws::mock::MockS3, util::Glob};
+
+        let server = Arc::new(MockS3::default());
+        for key in ["logs/a.log", "logs/2024/b.log", "logs/2024/c.txt"] {
+            server.put(key, "x");
+        }
+
+        let glob = Glob::new("logs/**/*.log").unwrap();
+        assert_eq!(remove_matching(&server.fs(), &glob, 4).await.unwrap(), 2);
+        assert!(server.get("logs/a.log").is_none());
+        assert!(server.get("logs/2024/b.log").is_none());
+        assert!(server.get("logs/2024/c.txt").is_some());
+        // one DeleteObjects request instead of a DELETE per file
+        let requests = server.requests();
+        assert!(requests.iter().all(|(method, _)| method != Method::DELETE));
+        assert_eq!(
+            requests
+                .iter()
+                .filter(|(method, uri)| method == Method::POST && uri.contains("delete"))
+                .count(),
+            1
+        );
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn copies_in_chunks() {
+        use tempfile::TempDir;
+
+        use super::{copy, COPY_CHUNK};
+        use crate::{disk::TokioFs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        let content = (0..COPY_CHUNK * 2 + 3).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(dir.path().join("from"), &content).unwrap();
+
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+        copy(&TokioFs, &TokioFs, &path("from"), &path("nested/to"))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("nested/to")).unwrap(),
+            content
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{glob::Glob, list_snapshot, SnapshotDiff};
+use crate::{
+    clock::{Clock, SystemClock},
+    fs::{Fs, OpenOptions},
+    path::Path,
+    Error, Read, Write,
+};
+
+fn to_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+/// What a [`Catalog`] knows about a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub size: u64,
+    /// When a refresh first saw the file with its current size. Listings report no modification
+    /// time, so this is the start of the listing that observed the change, not the time of the
+    /// write itself.
+    pub observed: SystemTime,
+}
+
+/// An index of the files under managed prefixes, answering lookups, glob and range queries
+/// without listing the backend again, e.g. S3 where every listing is a paginated request.
+///
+/// The index is kept in a file on any [`Fs`], so it survives restarts and can live next to the
+/// data or on a local disk. It holds one `prefix\t{path}` line per managed prefix followed by one
+/// `file\t{size}\t{observed in milliseconds}\t{path}` line per file, and is rewritten by every
+/// [`Catalog::refresh`]. Queries only see changes up to the last refresh.
+pub struct Catalog<F: Fs> {
+    fs: F,
+    index: Path,
+    prefixes: BTreeSet<Path>,
+    entries: BTreeMap<Path, CatalogEntry>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<F: Fs> Catalog<F> {
+    /// Loads the catalog kept in `index` on `fs`, which is created by the first refresh.
+    pub async fn open(fs: F, index: Path) -> Result<Self, Error> {
+        // object stores open lazily and report a missing index on the first read
+        let read = async {
+            let mut file = fs.open(&index).await?;
+            let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+            result.map(|_| buf)
+        };
+        let content = match read.await {
+            Ok(content) => content,
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("{index} is corrupt"));
+        let content = String::from_utf8(content).map_err(|_| invalid())?;
+        let mut prefixes = BTreeSet::new();
+        let mut entries = BTreeMap::new();
+        for line in content.lines() {
+            match line.split_once('\t').ok_or_else(invalid)? {
+                ("prefix", path) => {
+                    prefixes.insert(Path::parse(path)?);
+                }
+                ("file", entry) => {
+                    let mut fields = entry.splitn(3, '\t');
+                    let mut next = || fields.next().ok_or_else(invalid);
+                    let size = next()?.parse().map_err(|_| invalid())?;
+                    let millis = next()?.parse().map_err(|_| invalid())?;
+                    entries.insert(
+                        Path::parse(next()?)?,
+                        CatalogEntry {
+                            size,
+                            observed: UNIX_EPOCH + Duration::from_millis(millis),
+                        },
+                    );
+                }
+                _ => return Err(invalid().into()),
+            }
+        }
+
+        Ok(Self {
+            fs,
+            index,
+            prefixes,
+            entries,
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// The clock refreshes take the time files were observed from.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Starts managing `prefix`. Its files are indexed by the next refresh.
+    pub fn manage(&mut self, prefix: Path) {
+        self.prefixes.insert(prefix);
+    }
+
+    /// Stops managing `prefix` and drops its files from the index, unless they are below another
+    /// managed prefix. Takes effect in the index file with the next refresh.
+    pub fn unmanage(&mut self, prefix: &Path) {
+        if self.prefixes.remove(prefix) {
+            let prefixes = &self.prefixes;
+            self.entries.retain(|path, _| {
+                !path.prefix_matches(prefix) || prefixes.iter().any(|p| path.prefix_matches(p))
+            });
+        }
+    }
+
+    pub fn prefixes(&self) -> impl Iterator<Item = &Path> {
+        self.prefixes.iter()
+    }
+
+    /// Lists every managed prefix on `source` once, updates the index with what changed and
+    /// persists it. Returns the changes, sizes changing count as changed.
+    pub async fn refresh<S: Fs>(&mut self, source: &S) -> Result<SnapshotDiff, Error> {
+        let mut diff = SnapshotDiff::default();
+        for prefix in &self.prefixes {
+            let snapshot = list_snapshot(source, prefix, self.clock.as_ref()).await?;
+            // at the precision of the index, so entries compare equal once loaded again
+            let (observed, _) = snapshot.taken();
+            let observed = UNIX_EPOCH + Duration::from_millis(to_millis(observed) as u64);
+
+            let indexed = self
+                .entries
+                .range::<Path, _>((Bound::Included(prefix), Bound::Unbounded))
+                .take_while(|(path, _)| path.as_ref().starts_with(prefix.as_ref()))
+                .filter(|(path, _)| path.prefix_matches(prefix))
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>();
+            for path in indexed {
+                if snapshot.get(&path).is_none() {
+                    self.entries.remove(&path);
+                    diff.removed.push(path);
+                }
+            }
+            for meta in snapshot.iter() {
+                let entry = CatalogEntry {
+                    size: meta.size,
+                    observed,
+                };
+                match self.entries.get(&meta.path) {
+                    None => diff.added.push(meta.path.clone()),
+                    Some(old) if old.size != meta.size => diff.changed.push(meta.path.clone()),
+                    Some(_) => continue,
+                }
+                self.entries.insert(meta.path.clone(), entry);
+            }
+        }
+
+        self.persist().await?;
+        Ok(diff)
+    }
+
+    async fn persist(&self) -> Result<(), Error> {
+        let prefixes = self.prefixes.iter().map(|path| format!("prefix\t{path}\n"));
+        let files = self.entries.iter().map(|(path, entry)| {
+            format!(
+                "file\t{}\t{}\t{path}\n",
+                entry.size,
+                to_millis(entry.observed)
+            )
+        });
+        let content = prefixes.chain(files).collect::<Vec<_>>().concat();
+
+        let mut file = self
+            .fs
+            .open_options(
+                &self.index,
+                OpenOptions::default().create(true).truncate(true),
+            )
+            .await?;
+        let (result, _) = file.write_all(content.into_bytes()).await;
+        result?;
+        file.close().await
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&CatalogEntry> {
+        self.entries.get(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The files whose paths fall into `range`, in lexicographic order.
+    pub fn range<R>(&self, range: R) -> impl Iterator<Item = (&Path, &CatalogEntry)>
+    where
+        R: RangeBounds<Path>,
+    {
+        self.entries.range(range)
+    }
+
+    /// The files matching `glob`, in lexicographic order. Only the files below the literal
+    /// prefix of `glob` are looked at.
+    pub fn glob<'a>(
+        &'a self,
+        glob: &'a Glob,
+    ) -> impl Iterator<Item = (&'a Path, &'a CatalogEntry)> {
+        let prefix = glob.prefix();
+        self.entries
+            .range::<Path, _>((Bound::Included(&prefix), Bound::Unbounded))
+            .take_while(move |(path, _)| path.as_ref().starts_with(prefix.as_ref()))
+            .filter(move |(path, _)| glob.matches(path))
+    }
+
+    /// The files observed to change since `since`, e.g. to find what a consumer has not seen yet.
+    pub fn changed_since(&self, since: SystemTime) -> impl Iterator<Item = (&Path, &CatalogEntry)> {
+        self.entries
+            .iter()
+            .filter(move |(_, entry)| entry.observed >= since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn refresh_and_query() {
+        use std::{
+            sync::Arc,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        use tempfile::TempDir;
+
+        use super::Catalog;
+        use crate::{clock::FixedClock, disk::TokioFs, path::Path, util::Glob};
+
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("data/a")).unwrap();
+        std::fs::create_dir_all(dir.path().join("other")).unwrap();
+        std::fs::write(dir.path().join("data/a/1.sst"), [0; 10]).unwrap();
+        std::fs::write(dir.path().join("data/a/2.log"), [0; 20]).unwrap();
+        std::fs::write(dir.path().join("data/3.sst"), [0; 30]).unwrap();
+        std::fs::write(dir.path().join("other/4.sst"), [0; 40]).unwrap();
+        let path = |name: &str| Path::from_absolute_path(dir.path().join(name)).unwrap();
+
+        let observed = UNIX_EPOCH + Duration::from_secs(1);
+        let mut catalog = Catalog::open(TokioFs, path("catalog"))
+            .await
+            .unwrap()
+            .with_clock(Arc::new(FixedClock::new(observed)));
+        catalog.manage(path("data"));
+        let diff = catalog.refresh(&TokioFs).await.unwrap();
+        assert_eq!(diff.added.len(), 3);
+        assert_eq!(catalog.get(&path("data/3.sst")).unwrap().size, 30);
+        assert_eq!(catalog.get(&path("data/3.sst")).unwrap().observed, observed);
+        assert!(catalog.get(&path("other/4.sst")).is_none());
+
+        let glob = Glob::new(format!("{}/**/*.sst", path("data"))).unwrap();
+        assert_eq!(
+            catalog
+                .glob(&glob)
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>(),
+            vec![path("data/3.sst"), path("data/a/1.sst")]
+        );
+        assert_eq!(catalog.range(path("data/a")..path("data/b")).count(), 2);
+
+        std::fs::remove_file(dir.path().join("data/a/2.log")).unwrap();
+        std::fs::write(dir.path().join("data/3.sst"), [0; 5]).unwrap();
+        let diff = catalog.refresh(&TokioFs).await.unwrap();
+        assert_eq!(diff.removed, vec![path("data/a/2.log")]);
+        assert_eq!(diff.changed, vec![path("data/3.sst")]);
+
+        let reopened = Catalog::open(TokioFs, path("catalog")).await.unwrap();
+        assert_eq!(reopened.prefixes().collect::<Vec<_>>(), vec![&path("data")]);
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(
+            reopened.get(&path("data/3.sst")),
+            catalog.get(&path("data/3.sst"))
+        );
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn starts_empty_on_s3() {
+        use std::sync::Arc;
+
+        use super::Catalog;
+        use crate::{path::Path, remotes::aws::mock::MockS3};
+
+        let server = Arc::new(MockS3::default());
+        server.put("data/1.sst", "fusio");
+        let index = Path::parse("catalog").unwrap();
+
+        let mut catalog = Catalog::open(server.fs(), index.clone()).await.unwrap();
+        assert_eq!(catalog.prefixes().count(), 0);
+        catalog.manage(Path::parse("data").unwrap());
+        let diff = catalog.refresh(&server.fs()).await.unwrap();
+        assert_eq!(diff.added.len(), 1);
+        assert!(server.get("catalog").is_some());
+
+        let catalog = Catalog::open(server.fs(), index).await.unwrap();
+        let entry = catalog.get(&Path::parse("data/1.sst").unwrap()).unwrap();
+        assert_eq!(entry.size, 5);
+    }
+}
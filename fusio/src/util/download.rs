@@ -0,0 +1,574 @@
+use std::io;
+
+use futures_core::Stream;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use tokio::fs::File;
+
+use super::{crc32c, verify::next_chunk, Verified};
+use crate::{
+    disk::TokioFs,
+    fs::{FileMeta, Fs, OpenOptions},
+    path::{path_to_local, Path},
+    Error, Read, Write,
+};
+
+const RANGE_SIZE: u64 = 8 << 20;
+
+/// Fetches `remote_path` from `offset` to `size` in ranges of `RANGE_SIZE`, up to `concurrency`
+/// at once, yielding them in order.
+///
+/// With an `etag`, every range is read with [`OpenOptions::if_match`], so ranges of two versions
+/// of the file are never mixed: once the file is replaced the fetch fails with
+/// [`Error::PreconditionFailed`].
+fn fetch_ranges<'a, R: Fs>(
+    remote: &'a R,
+    remote_path: &'a Path,
+    etag: Option<&str>,
+    offset: u64,
+    size: u64,
+    concurrency: usize,
+) -> impl Stream<Item = Result<Vec<u8>, Error>> + 'a {
+    let options = match etag {
+        Some(etag) => OpenOptions::default().if_match(etag),
+        None => OpenOptions::default(),
+    };
+    stream::iter(
+        (offset..size)
+            .step_by(RANGE_SIZE as usize)
+            .map(move |offset| {
+                let options = options.clone();
+                async move {
+                    let len = RANGE_SIZE.min(size - offset) as usize;
+                    let mut reader = remote.open_options(remote_path, options).await?;
+                    let (result, buf) = reader.read_exact_at(vec![0; len], offset).await;
+                    result.map(|_| buf)
+                }
+            }),
+    )
+    .buffered(concurrency.max(1))
+}
+
+/// Appends `buf` to `file` and adds it to `verified`.
+async fn append(file: &mut File, buf: Vec<u8>, verified: &mut Verified) -> Result<(), Error> {
+    verified.size += buf.len() as u64;
+    verified.crc32c = crc32c(verified.crc32c, &buf);
+    let (result, _) = file.write_all(buf).await;
+    result
+}
+
+async fn checksum(local: &TokioFs, path: &Path) -> Result<Verified, Error> {
+    let mut file = local.open(path).await?;
+    let mut verified = Verified { size: 0, crc32c: 0 };
+    let mut buf = Vec::new();
+    while let Some(chunk) = next_chunk(&mut file, buf, &mut verified).await? {
+        buf = chunk;
+    }
+    Ok(verified)
+}
+
+/// Reads the synced download at `temp` back and, when it matches `expected` and the checksum
+/// `remote` reports for the whole file, renames it to `local_path` and syncs the directory. On a
+/// mismatch `temp` is left for the caller to remove.
+///
+/// `expected` is computed while downloading and only catches corruption on the local disk, the
+/// checksum of the remote file also catches corruption in transfer. Backends that report no
+/// checksum, like S3 for multipart uploads, are trusted.
+async fn finalize(
+    local: &TokioFs,
+    temp: &Path,
+    local_path: &Path,
+    expected: Verified,
+    remote: &FileMeta,
+) -> Result<(), Error> {
+    let actual = checksum(local, temp).await?;
+    if actual != expected {
+        return Err(Error::ChecksumMismatch {
+            expected: expected.crc32c,
+            actual: actual.crc32c,
+        });
+    }
+    if let Some(crc32c) = remote.crc32c.filter(|crc32c| *crc32c != actual.crc32c) {
+        return Err(Error::ChecksumMismatch {
+            expected: crc32c,
+            actual: actual.crc32c,
+        });
+    }
+
+    tokio::fs::rename(path_to_local(temp)?, path_to_local(local_path)?).await?;
+    local.sync_parent_dir(local_path).await
+}
+
+/// Downloads `remote_path` from `remote` to `local_path`, fetching up to `concurrency` ranges of
+/// the file at once, and returns the size and CRC-32C checksum of the download.
+///
+/// The ranges are pinned to the ETag [`Fs::stat`] reports, so a file replaced while downloading
+/// fails the download with [`Error::PreconditionFailed`] instead of mixing two versions. They are
+/// written in order to a temporary file next to `local_path`, which is synced and read back. Only
+/// when the stored copy matches the checksum computed while downloading and the CRC-32C checksum
+/// the remote reports in [`FileMeta::crc32c`], it is renamed into place and its directory synced,
+/// so `local_path` is either missing, the previous file or the complete download, also after a
+/// crash. On a mismatch the temporary file is removed and [`Error::ChecksumMismatch`] is returned.
+pub async fn download_to_file<R: Fs>(
+    remote: &R,
+    remote_path: &Path,
+    local: &TokioFs,
+    local_path: &Path,
+    concurrency: usize,
+) -> Result<Verified, Error> {
+    let meta = remote.stat(remote_path).await?;
+    let (mut file, mut guard) = local.create_temp(local_path).await?;
+
+    let mut ranges = fetch_ranges(
+        remote,
+        remote_path,
+        meta.etag.as_deref(),
+        0,
+        meta.size,
+        concurrency,
+    );
+    let mut expected = Verified { size: 0, crc32c: 0 };
+    while let Some(buf) = ranges.try_next().await? {
+        append(&mut file, buf, &mut expected).await?;
+    }
+    file.flush().await?;
+    file.sync_all().await?;
+    drop(file);
+    guard.link()?;
+
+    match finalize(local, guard.path(), local_path, expected, &meta).await {
+        Ok(()) => {
+            guard.keep()?;
+            Ok(expected)
+        }
+        Err(e) => {
+            guard.remove(local).await?;
+            Err(e)
+        }
+    }
+}
+
+/// Returns the progress recorded in the sidecar at `path` for the download of the remote file
+/// of `size` bytes identified by `etag`, or `None` when there is none or it belongs to another
+/// version of the file.
+///
+/// The sidecar starts with a `{size}\t{etag}` line followed by one `{end}\t{crc32c}` line per
+/// synced range, holding the size and checksum of the downloaded prefix. A torn last line is
+/// ignored.
+async fn read_progress(
+    local: &TokioFs,
+    path: &Path,
+    etag: &str,
+    size: u64,
+) -> Result<Option<Verified>, Error> {
+    let mut file = match local.open(path).await {
+        Ok(file) => file,
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+    result?;
+
+    let content = String::from_utf8_lossy(&buf);
+    let mut lines = content
+        .split_inclusive('\n')
+        .map_while(|line| line.strip_suffix('\n'));
+    if lines.next() != Some(format!("{size}\t{etag}").as_str()) {
+        return Ok(None);
+    }
+    Ok(lines
+        .map_while(|line| {
+            let (end, crc) = line.split_once('\t')?;
+            Some(Verified {
+                size: end.parse().ok()?,
+                crc32c: crc.parse().ok()?,
+            })
+        })
+        .last()
+        .or(Some(Verified { size: 0, crc32c: 0 })))
+}
+
+/// Returns the metadata of `remote_path`, failing with [`Error::PreconditionFailed`] when its ETag
+/// is no longer `etag`. Backends that report no ETag are trusted to hold the version of `etag`.
+async fn check_version<R: Fs>(
+    remote: &R,
+    remote_path: &Path,
+    etag: &str,
+) -> Result<FileMeta, Error> {
+    let meta = remote.stat(remote_path).await?;
+    match &meta.etag {
+        Some(current) if current.trim_matches('"') != etag.trim_matches('"') => {
+            Err(Error::PreconditionFailed {
+                path: remote_path.clone(),
+            })
+        }
+        _ => Ok(meta),
+    }
+}
+
+/// Downloads `remote_path` like [`download_to_file`], resuming an earlier download that was
+/// interrupted instead of starting over.
+///
+/// The download is staged in `{local_path}.part`, and every range that reached the disk is
+/// recorded in the sidecar `{local_path}.part.progress`. A later call for the same `local_path`
+/// keeps the recorded prefix of the staged file, after checking it against the recorded
+/// checksum, and only fetches the rest. `etag` pins the version of the remote file, e.g. the
+/// `ETag` reported by S3: progress recorded for another `etag` or size is discarded and the
+/// download restarts. Both files are removed once the download is in place.
+///
+/// The ETag reported by [`Fs::stat`] is compared with `etag` before the download starts, and every
+/// range is fetched with [`OpenOptions::if_match`] on it. If the remote file was replaced, the
+/// download fails with [`Error::PreconditionFailed`] instead of mixing two versions, and the
+/// staged files are removed once ranges were fetched. Like [`download_to_file`], the download is
+/// only put in place when it matches the checksum the remote reports.
+pub async fn resume_download<R: Fs>(
+    remote: &R,
+    remote_path: &Path,
+    etag: &str,
+    local: &TokioFs,
+    local_path: &Path,
+    concurrency: usize,
+) -> Result<Verified, Error> {
+    let meta = check_version(remote, remote_path, etag).await?;
+    let size = meta.size;
+    let part = Path::parse(format!("{local_path}.part"))?;
+    let progress = Path::parse(format!("{local_path}.part.progress"))?;
+
+    let mut done = Verified { size: 0, crc32c: 0 };
+    if let Some(recorded) = read_progress(local, &progress, etag, size).await? {
+        match tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(path_to_local(&part)?)
+            .await
+        {
+            Ok(file) => {
+                // data written after the last recorded range may be incomplete
+                file.set_len(recorded.size).await?;
+                if checksum(local, &part).await? == recorded {
+                    done = recorded;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let mut file = local
+        .open_options(
+            &part,
+            OpenOptions::default().create(true).truncate(done.size == 0),
+        )
+        .await?;
+    let mut sidecar = local
+        .open_options(
+            &progress,
+            OpenOptions::default().create(true).truncate(done.size == 0),
+        )
+        .await?;
+    if done.size == 0 {
+        let (result, _) = sidecar
+            .write_all(format!("{size}\t{etag}\n").into_bytes())
+            .await;
+        result?;
+    }
+
+    let mut ranges = fetch_ranges(
+        remote,
+        remote_path,
+        meta.etag.as_deref(),
+        done.size,
+        size,
+        concurrency,
+    );
+    loop {
+        let buf = match ranges.try_next().await {
+            Ok(Some(buf)) => buf,
+            Ok(None) => break,
+            Err(e) => {
+                if let Error::PreconditionFailed { .. } = e {
+                    drop((file, sidecar));
+                    local.remove(&part).await?;
+                    local.remove(&progress).await?;
+                }
+                return Err(e);
+            }
+        };
+        append(&mut file, buf, &mut done).await?;
+        file.flush().await?;
+        file.sync_data().await?;
+        let line = format!("{}\t{}\n", done.size, done.crc32c);
+        let (result, _) = sidecar.write_all(line.into_bytes()).await;
+        result?;
+        sidecar.flush().await?;
+    }
+    file.sync_all().await?;
+    drop((file, sidecar));
+
+    if let Err(e) = finalize(local, &part, local_path, done, &meta).await {
+        local.remove(&part).await?;
+        local.remove(&progress).await?;
+        return Err(e);
+    }
+    local.remove(&progress).await?;
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "completion-based"))]
+    #[tokio::test]
+    async fn downloads_in_ranges() {
+        use tempfile::TempDir;
+
+        use super::download_to_file;
+        use crate::{
+            disk::TokioFs,
+            path::Path,
+            util::{crc32c, Verified},
+        };
+
+        let dir = TempDir::new().unwrap();
+        let content = (0..(17 << 20) + 5).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(dir.path().join("remote"), &content).unwrap();
+        std::fs::write(dir.path().join("local"), b"stale").unwrap();
+
+        let verified = download_to_file(
+            &TokioFs,
+            &Path::from_absolute_path(dir.path().join("remote")).unwrap(),
+            &TokioFs,
+            &Path::from_absolute_path(dir.path().join("local")).unwrap(),
+            4,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            verified,
+            Verified {
+                size: content.len() as u64,
+                crc32c: crc32c(0, &content),
+            }
+        );
+        assert!(std::fs::read(dir.path().join("local")).unwrap() == content);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[cfg(not(feature = "completion-based"))]
+    #[tokio::test]
+    async fn resumes_recorded_prefix() {
+        use tempfile::TempDir;
+
+        use super::resume_download;
+        use crate::{
+            disk::TokioFs,
+            path::Path,
+            util::{crc32c, Verified},
+        };
+
+        let dir = TempDir::new().unwrap();
+        let content = (0..(17 << 20) + 5).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(dir.path().join("remote"), &content).unwrap();
+
+        // an interrupted download: one recorded range, followed by a torn write and a torn line
+        // of progress; the recorded range is marked so it is observable that it was kept
+        let mut part = content[..8 << 20].to_vec();
+        part[0] = !part[0];
+        let recorded = crc32c(0, &part);
+        part.extend_from_slice(b"torn");
+        std::fs::write(dir.path().join("local.part"), &part).unwrap();
+        std::fs::write(
+            dir.path().join("local.part.progress"),
+            format!(
+                "{}\tv1\n{}\t{recorded}\n{}\t",
+                content.len(),
+                8 << 20,
+                16 << 20
+            ),
+        )
+        .unwrap();
+
+        let remote = Path::from_absolute_path(dir.path().join("remote")).unwrap();
+        let local = Path::from_absolute_path(dir.path().join("local")).unwrap();
+        let verified = resume_download(&TokioFs, &remote, "v1", &TokioFs, &local, 4)
+            .await
+            .unwrap();
+
+        let mut expected = content.clone();
+        expected[0] = !expected[0];
+        assert_eq!(
+            verified,
+            Verified {
+                size: expected.len() as u64,
+                crc32c: crc32c(0, &expected),
+            }
+        );
+        assert!(std::fs::read(dir.path().join("local")).unwrap() == expected);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        // progress of another version of the remote file is discarded
+        std::fs::write(dir.path().join("local.part"), &part).unwrap();
+        std::fs::write(
+            dir.path().join("local.part.progress"),
+            format!("{}\tv1\n{}\t{recorded}\n", content.len(), 8 << 20),
+        )
+        .unwrap();
+        resume_download(&TokioFs, &remote, "v2", &TokioFs, &local, 4)
+            .await
+            .unwrap();
+        assert!(std::fs::read(dir.path().join("local")).unwrap() == content);
+    }
+
+    #[cfg(all(feature = "aws", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn refuses_a_replaced_remote_file() {
+        use std::sync::Arc;
+
+        use tempfile::TempDir;
+
+        use super::resume_download;
+        use crate::{
+            disk::TokioFs, fs::Fs, path::Path, remotes::aws::mock::MockS3, util::crc32c, Error,
+        };
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let remote = Path::parse("remote").unwrap();
+        server.put("remote", "version one");
+        let v1 = s3.stat(&remote).await.unwrap().etag.unwrap();
+
+        // interrupted while downloading the first version, which was replaced since
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("local.part"), b"version").unwrap();
+        std::fs::write(
+            dir.path().join("local.part.progress"),
+            format!("11\t{v1}\n7\t{}\n", crc32c(0, b"version")),
+        )
+        .unwrap();
+        server.put("remote", "version two");
+
+        let local = Path::from_absolute_path(dir.path().join("local")).unwrap();
+        let result = resume_download(&s3, &remote, &v1, &TokioFs, &local, 4).await;
+        assert!(matches!(result, Err(Error::PreconditionFailed { .. })));
+        assert!(!dir.path().join("local").exists());
+
+        let v2 = s3.stat(&remote).await.unwrap().etag.unwrap();
+        resume_download(&s3, &remote, &v2, &TokioFs, &local, 4)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("local")).unwrap(),
+            b"version two"
+        );
+    }
+
+    #[cfg(all(feature = "aws", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn checks_the_remote_checksum() {
+        use std::sync::Arc;
+
+        use tempfile::TempDir;
+
+        use super::download_to_file;
+        use crate::{disk::TokioFs, path::Path, remotes::aws::mock::MockS3, Error};
+
+        let server = Arc::new(MockS3::default());
+        let s3 = server.fs();
+        let dir = TempDir::new().unwrap();
+        let remote = Path::parse("remote").unwrap();
+        let local = Path::from_absolute_path(dir.path().join("local")).unwrap();
+
+        server.put_with_crc32c("remote", "version one");
+        download_to_file(&s3, &remote, &TokioFs, &local, 4)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("local")).unwrap(),
+            b"version one"
+        );
+
+        // the stored checksum no longer matches what is served
+        server.put("remote", "version 0ne");
+        let result = download_to_file(&s3, &remote, &TokioFs, &local, 4).await;
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+        assert_eq!(
+            std::fs::read(dir.path().join("local")).unwrap(),
+            b"version one"
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(all(feature = "aws", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn pins_the_version_across_ranges() {
+        use std::sync::Arc;
+
+        use futures_core::Stream;
+        use tempfile::TempDir;
+
+        use super::download_to_file;
+        use crate::{
+            disk::TokioFs,
+            fs::{FileMeta, Fs, OpenOptions},
+            path::Path,
+            remotes::aws::{fs::AmazonS3, mock::MockS3},
+            Error,
+        };
+
+        /// Replaces the object after it was looked up, before its ranges are read.
+        struct Replacing {
+            server: Arc<MockS3>,
+            s3: AmazonS3,
+        }
+
+        impl Fs for Replacing {
+            type File = <AmazonS3 as Fs>::File;
+
+            async fn open_options(
+                &self,
+                path: &Path,
+                options: OpenOptions,
+            ) -> Result<Self::File, Error> {
+                self.server.put("remote", "version two");
+                self.s3.open_options(path, options).await
+            }
+
+            async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+                self.s3.create_dir_all(path).await
+            }
+
+            async fn list(
+                &self,
+                path: &Path,
+            ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+                self.s3.list(path).await
+            }
+
+            async fn remove(&self, path: &Path) -> Result<(), Error> {
+                self.s3.remove(path).await
+            }
+
+            async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+                self.s3.stat(path).await
+            }
+        }
+
+        let server = Arc::new(MockS3::default());
+        server.put("remote", "version one");
+        let remote = Replacing {
+            s3: server.fs(),
+            server,
+        };
+        let dir = TempDir::new().unwrap();
+        let local = Path::from_absolute_path(dir.path().join("local")).unwrap();
+
+        let result = download_to_file(
+            &remote,
+            &Path::parse("remote").unwrap(),
+            &TokioFs,
+            &local,
+            4,
+        )
+        .await;
+        assert!(matches!(result, Err(Error::PreconditionFailed { .. })));
+        assert!(!dir.path().join("local").exists());
+    }
+}
@@ -0,0 +1,53 @@
+use super::walk::walk;
+use crate::{fs::Fs, path::Path, Error};
+
+/// The result of [`du`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// Number of files, directories are not counted.
+    pub objects: u64,
+    pub bytes: u64,
+}
+
+/// Counts the files under `prefix` and sums up their sizes.
+///
+/// The listing is consumed as a stream, so memory use does not grow with the number of objects.
+pub async fn du<F: Fs>(fs: &F, prefix: &Path) -> Result<DiskUsage, Error> {
+    let mut usage = DiskUsage::default();
+    walk(fs, prefix, |meta| {
+        usage.objects += 1;
+        usage.bytes += meta.size;
+    })
+    .await?;
+
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn du_local() {
+        use tempfile::TempDir;
+
+        use super::{du, DiskUsage};
+        use crate::{disk::TokioFs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a"), [0; 10]).unwrap();
+        std::fs::create_dir_all(dir.path().join("sub/nested")).unwrap();
+        std::fs::write(dir.path().join("sub/b"), [0; 20]).unwrap();
+        std::fs::write(dir.path().join("sub/nested/c"), [0; 30]).unwrap();
+
+        let usage = du(&TokioFs, &Path::from_absolute_path(dir.path()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            usage,
+            DiskUsage {
+                objects: 3,
+                bytes: 60
+            }
+        );
+    }
+}
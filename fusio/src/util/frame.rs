@@ -0,0 +1,255 @@
+use super::crc32c;
+#[cfg(feature = "tokio")]
+use crate::{disk::TokioFs, fs::Fs, path::Path};
+use crate::{Error, Read, Write};
+
+/// The length and checksum in front of every frame.
+const HEADER_LEN: usize = 8;
+
+/// The length of a frame with `payload` bytes, padded to whole sectors.
+fn frame_len(payload: usize, sector_size: usize) -> usize {
+    (HEADER_LEN + payload).div_ceil(sector_size) * sector_size
+}
+
+/// The checksum of a frame at `offset`, which covers the offset too, so a valid frame left from an
+/// earlier use of the file at another offset is not mistaken for one written since.
+fn frame_checksum(offset: u64, len: [u8; 4], payload: &[u8]) -> u32 {
+    let crc = crc32c(0, &offset.to_le_bytes());
+    crc32c(crc32c(crc, &len), payload)
+}
+
+/// Appends payloads to a file as sector-aligned, checksummed frames, so a crash in the middle of
+/// an append is detected and cut off by [`FrameReader`] instead of returning a torn record.
+///
+/// A frame is the length of its payload and its CRC-32C as little endian `u32`s followed by the
+/// payload, zero padded to a multiple of the sector size. Frames start at sector boundaries, so a
+/// torn write never damages an earlier frame on disks that write sectors atomically. Use the
+/// sector size of the disk, typically 512 or 4096 bytes.
+///
+/// Appends are not durable until the file is synced, e.g. with [`Write::barrier`] through
+/// [`FrameWriter::get_mut`]. After a failed append the file may end in a partial frame, recover
+/// it before appending again.
+pub struct FrameWriter<W> {
+    file: W,
+    sector_size: usize,
+    offset: u64,
+}
+
+impl<W: Write> FrameWriter<W> {
+    /// Appends frames to `file`, whose valid frames end at `offset`: `0` for a new file, or the
+    /// [`RecoveredFrames::len`] of an existing one.
+    ///
+    /// # Panics
+    ///
+    /// When `sector_size` is smaller than a frame header, 8 bytes.
+    pub fn new(file: W, sector_size: usize, offset: u64) -> Self {
+        assert!(sector_size >= HEADER_LEN, "sectors hold at least a header");
+        Self {
+            file,
+            sector_size,
+            offset,
+        }
+    }
+
+    /// Appends `payload` as one frame.
+    pub async fn append(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let len = u32::try_from(payload.len())
+            .map_err(|_| Error::Other("frame payloads are at most 4 GiB".into()))?
+            .to_le_bytes();
+        let frame_len = frame_len(payload.len(), self.sector_size);
+        let mut frame = Vec::with_capacity(frame_len);
+        frame.extend_from_slice(&len);
+        frame.extend_from_slice(&frame_checksum(self.offset, len, payload).to_le_bytes());
+        frame.extend_from_slice(payload);
+        frame.resize(frame_len, 0);
+
+        let (result, _) = self.file.write_all(frame).await;
+        result?;
+        self.offset += frame_len as u64;
+        Ok(())
+    }
+
+    /// Where the next frame starts.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.file
+    }
+
+    pub fn into_inner(self) -> W {
+        self.file
+    }
+}
+
+/// Reads the frames a [`FrameWriter`] appended, in order, stopping at the first one that is
+/// incomplete or fails its checksum, as left by a torn write.
+pub struct FrameReader<R> {
+    file: R,
+    sector_size: usize,
+    offset: u64,
+    size: u64,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub async fn new(file: R, sector_size: usize) -> Result<Self, Error> {
+        assert!(sector_size >= HEADER_LEN, "sectors hold at least a header");
+        let size = file.size().await?;
+        Ok(Self {
+            file,
+            sector_size,
+            offset: 0,
+            size,
+        })
+    }
+
+    /// The payload of the next frame, or `None` once the valid frames are exhausted.
+    pub async fn next(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        if self.size - self.offset < HEADER_LEN as u64 {
+            return Ok(None);
+        }
+        let (result, header) = self
+            .file
+            .read_exact_at(vec![0; HEADER_LEN], self.offset)
+            .await;
+        result?;
+        let len = <[u8; 4]>::try_from(&header[..4]).unwrap();
+        let checksum = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let payload_len = u32::from_le_bytes(len) as usize;
+        let frame_len = frame_len(payload_len, self.sector_size) as u64;
+        if self.size - self.offset < frame_len {
+            return Ok(None);
+        }
+
+        let (result, payload) = self
+            .file
+            .read_exact_at(vec![0; payload_len], self.offset + HEADER_LEN as u64)
+            .await;
+        result?;
+        if frame_checksum(self.offset, len, &payload) != checksum {
+            return Ok(None);
+        }
+        self.offset += frame_len;
+        Ok(Some(payload))
+    }
+
+    /// Where the frames read so far end, which is where the valid frames end once
+    /// [`FrameReader::next`] returned `None`.
+    pub fn valid_len(&self) -> u64 {
+        self.offset
+    }
+
+    /// The size of the file, larger than [`FrameReader::valid_len`] when it ends in a torn or
+    /// corrupted frame.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// The valid frames of a file, see [`recover_frames`].
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveredFrames {
+    pub frames: Vec<Vec<u8>>,
+    /// Where the valid frames end, and the file ends now.
+    pub len: u64,
+    /// The bytes cut off after the valid frames.
+    pub discarded: u64,
+}
+
+/// Reads the valid frames of the local file at `path` and truncates the file after them, so a
+/// [`FrameWriter`] can continue at [`RecoveredFrames::len`] after a crash. The truncation is
+/// synced before returning.
+#[cfg(feature = "tokio")]
+pub async fn recover_frames(
+    local: &TokioFs,
+    path: &Path,
+    sector_size: usize,
+) -> Result<RecoveredFrames, Error> {
+    let mut reader = FrameReader::new(local.open(path).await?, sector_size).await?;
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.next().await? {
+        frames.push(frame);
+    }
+
+    let len = reader.valid_len();
+    let discarded = reader.size() - len;
+    if discarded > 0 {
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(crate::path::path_to_local(path)?)
+            .await?;
+        file.set_len(len).await?;
+        file.sync_all().await?;
+    }
+    Ok(RecoveredFrames {
+        frames,
+        len,
+        discarded,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn cuts_off_torn_frames() {
+        use tempfile::TempDir;
+
+        use super::{recover_frames, FrameWriter};
+        use crate::{
+            disk::TokioFs,
+            fs::{Fs, OpenOptions},
+            path::Path,
+            Write,
+        };
+
+        const SECTOR: usize = 512;
+
+        let dir = TempDir::new().unwrap();
+        let local = dir.path().join("00.log");
+        let path = Path::from_absolute_path(&local).unwrap();
+        let options = OpenOptions::default().create(true);
+
+        let mut writer = FrameWriter::new(
+            TokioFs.open_options(&path, options.clone()).await.unwrap(),
+            SECTOR,
+            0,
+        );
+        writer.append(b"first").await.unwrap();
+        writer.append(&[7; 600]).await.unwrap();
+        writer.append(b"").await.unwrap();
+        assert_eq!(writer.offset(), 4 * SECTOR as u64);
+        // a crash in the middle of the next append leaves part of its frame
+        let mut file = writer.into_inner();
+        let (result, _) = file.write_all(vec![9; 300]).await;
+        result.unwrap();
+        file.close().await.unwrap();
+
+        let recovered = recover_frames(&TokioFs, &path, SECTOR).await.unwrap();
+        assert_eq!(
+            recovered.frames,
+            vec![b"first".to_vec(), vec![7; 600], Vec::new()]
+        );
+        assert_eq!(recovered.len, 4 * SECTOR as u64);
+        assert_eq!(recovered.discarded, 300);
+        assert_eq!(std::fs::metadata(&local).unwrap().len(), recovered.len);
+
+        let mut writer = FrameWriter::new(
+            TokioFs.open_options(&path, options).await.unwrap(),
+            SECTOR,
+            recovered.len,
+        );
+        writer.append(b"after").await.unwrap();
+        writer.into_inner().close().await.unwrap();
+
+        // a corrupted frame ends the valid frames, like a torn one
+        let mut data = std::fs::read(&local).unwrap();
+        data[SECTOR + 100] ^= 1;
+        std::fs::write(&local, data).unwrap();
+        let recovered = recover_frames(&TokioFs, &path, SECTOR).await.unwrap();
+        assert_eq!(recovered.frames, vec![b"first".to_vec()]);
+        assert_eq!(recovered.discarded, 4 * SECTOR as u64);
+    }
+}
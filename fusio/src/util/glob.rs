@@ -0,0 +1,190 @@
+use thiserror::Error;
+
+use crate::path::Path;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum GlobError {
+    #[error("unclosed character class in glob \"{pattern}\"")]
+    UnclosedClass { pattern: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    /// `?`, any character but `/`.
+    Any,
+    /// `*`, any run of characters without `/`.
+    Star,
+    /// `**/`, zero or more whole path segments.
+    Segments,
+    /// `**` not followed by `/`, anything including `/`.
+    DoubleStar,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// A glob pattern matched against [`Path`]s.
+///
+/// Supports `?`, `*` (within a segment), `**` (across segments, `a/**/b` also matches `a/b`) and
+/// character classes like `[a-z]` or `[!0-9]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    pub fn new(pattern: impl Into<String>) -> Result<Self, GlobError> {
+        let pattern = pattern.into();
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::Any,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        Token::Segments
+                    } else {
+                        Token::DoubleStar
+                    }
+                }
+                '*' => Token::Star,
+                '[' => {
+                    let negated = chars.next_if(|c| *c == '!' || *c == '^').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let start = match chars.next() {
+                            Some(']') if !ranges.is_empty() => break,
+                            Some(c) => c,
+                            None => {
+                                return Err(GlobError::UnclosedClass {
+                                    pattern: pattern.clone(),
+                                })
+                            }
+                        };
+                        let end = match chars.next_if_eq(&'-') {
+                            Some(_) => match chars.next() {
+                                Some(']') | None => {
+                                    return Err(GlobError::UnclosedClass {
+                                        pattern: pattern.clone(),
+                                    })
+                                }
+                                Some(end) => end,
+                            },
+                            None => start,
+                        };
+                        ranges.push((start, end));
+                    }
+                    Token::Class { negated, ranges }
+                }
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+        }
+
+        Ok(Self { pattern, tokens })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The directory made of the leading segments without wildcards, the path to list from.
+    pub fn prefix(&self) -> Path {
+        let literal = self
+            .tokens
+            .iter()
+            .map_while(|token| match token {
+                Token::Literal(c) => Some(*c),
+                _ => None,
+            })
+            .collect::<String>();
+        let literal = literal.rsplit_once('/').map_or("", |(prefix, _)| prefix);
+
+        // a prefix that can not be parsed can not be matched either, fall back to listing all
+        Path::parse(literal).unwrap_or_default()
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let text = path.as_ref().chars().collect::<Vec<_>>();
+        matches(&self.tokens, &text)
+    }
+}
+
+fn matches(tokens: &[Token], text: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+
+    match token {
+        Token::Literal(c) => text.first() == Some(c) && matches(rest, &text[1..]),
+        Token::Any => text.first().is_some_and(|c| *c != '/') && matches(rest, &text[1..]),
+        Token::Class { negated, ranges } => {
+            text.first().is_some_and(|c| {
+                *c != '/'
+                    && ranges
+                        .iter()
+                        .any(|(start, end)| (*start..=*end).contains(c))
+                        != *negated
+            }) && matches(rest, &text[1..])
+        }
+        Token::Star => {
+            let segment = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+            (0..=segment).any(|skip| matches(rest, &text[skip..]))
+        }
+        Token::Segments => {
+            matches(rest, text)
+                || (1..=text.len())
+                    .any(|skip| text[skip - 1] == '/' && matches(rest, &text[skip..]))
+        }
+        Token::DoubleStar => (0..=text.len()).any(|skip| matches(rest, &text[skip..])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Glob;
+    use crate::path::Path;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern)
+            .unwrap()
+            .matches(&Path::parse(path).unwrap())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("logs/*.log", "logs/a.log"));
+        assert!(!matches("logs/*.log", "logs/2024/a.log"));
+        assert!(matches("logs/**/*.log", "logs/a.log"));
+        assert!(matches("logs/**/*.log", "logs/2024/01/a.log"));
+        assert!(matches("logs/**", "logs/2024/01/a.log"));
+        assert!(matches("data/part-?.parquet", "data/part-1.parquet"));
+        assert!(!matches("data/part-?.parquet", "data/part-10.parquet"));
+        assert!(matches("data/[a-c]*", "data/b1"));
+        assert!(!matches("data/[!a-c]*", "data/b1"));
+        assert!(matches("data/[!a-c]*", "data/x1"));
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(
+            Glob::new("logs/2024-*/x").unwrap().prefix().as_ref(),
+            "logs"
+        );
+        assert_eq!(Glob::new("logs/a.log").unwrap().prefix().as_ref(), "logs");
+        assert_eq!(Glob::new("*.log").unwrap().prefix().as_ref(), "");
+        assert_eq!(Glob::new("a/b/**").unwrap().prefix().as_ref(), "a/b");
+    }
+
+    #[test]
+    fn unclosed_class() {
+        assert!(Glob::new("data/[abc").is_err());
+    }
+}
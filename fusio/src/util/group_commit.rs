@@ -0,0 +1,194 @@
+use std::{
+    mem,
+    sync::Mutex,
+    task::{Poll, Waker},
+};
+
+use futures_util::{future::poll_fn, lock::Mutex as AsyncMutex};
+
+use crate::{Error, IoBuf, Write};
+
+#[derive(Default)]
+struct State {
+    /// Writes completed so far.
+    written: u64,
+    /// Writes covered by a successful sync.
+    synced: u64,
+    syncing: bool,
+    syncs: u64,
+    waiting: Vec<Waker>,
+}
+
+/// Shares a file between concurrent writers and batches their syncs, so a write-ahead log with
+/// many producers pays for one sync per batch of commits instead of one per commit.
+///
+/// [`SyncCoordinator::sync`] returns once every write that completed before it was called is
+/// durable. Only one sync runs at a time: callers arriving while one is running wait for it, and
+/// the first of them whose writes it does not cover runs the next one for everyone that is
+/// waiting. Syncs use [`Write::barrier`], so local files sync their data like `fdatasync`.
+///
+/// Writes wait while a sync runs, as both need the file. When a sync fails, only its caller gets
+/// the error, the callers waiting on it run a sync of their own.
+pub struct SyncCoordinator<W> {
+    file: AsyncMutex<W>,
+    state: Mutex<State>,
+}
+
+impl<W: Write> SyncCoordinator<W> {
+    pub fn new(file: W) -> Self {
+        Self {
+            file: AsyncMutex::new(file),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Appends `buf` to the file, after the writes that completed before.
+    pub async fn write_all<B: IoBuf>(&self, buf: B) -> (Result<(), Error>, B) {
+        let mut file = self.file.lock().await;
+        let (result, buf) = file.write_all(buf).await;
+        if result.is_ok() {
+            self.state.lock().unwrap().written += 1;
+        }
+        (result, buf)
+    }
+
+    /// Makes every write that completed before this call durable, sharing the sync with the
+    /// other callers waiting at the same time.
+    pub async fn sync(&self) -> Result<(), Error> {
+        let target = self.state.lock().unwrap().written;
+        let leading = poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+            if state.synced >= target {
+                Poll::Ready(false)
+            } else if !state.syncing {
+                state.syncing = true;
+                Poll::Ready(true)
+            } else {
+                state.waiting.push(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await;
+        if !leading {
+            return Ok(());
+        }
+
+        let mut leader = Leader {
+            state: &self.state,
+            synced: None,
+        };
+        // let the writers that are ready run first, so their writes join this sync instead of
+        // waiting for the file and starting the next one
+        let mut yielded = false;
+        poll_fn(|cx| match mem::replace(&mut yielded, true) {
+            true => Poll::Ready(()),
+            false => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await;
+        let mut file = self.file.lock().await;
+        // no write is running while the file is held, so this covers every completed write
+        let written = self.state.lock().unwrap().written;
+        file.barrier().await?;
+        leader.synced = Some(written);
+        Ok(())
+    }
+
+    /// The number of syncs issued so far.
+    pub fn syncs(&self) -> u64 {
+        self.state.lock().unwrap().syncs
+    }
+
+    pub fn into_inner(self) -> W {
+        self.file.into_inner()
+    }
+}
+
+/// Hands the sync on to the waiting callers once the running one finished, failed or was
+/// cancelled.
+struct Leader<'a> {
+    state: &'a Mutex<State>,
+    synced: Option<u64>,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        let waiting = {
+            let mut state = self.state.lock().unwrap();
+            state.syncing = false;
+            if let Some(written) = self.synced {
+                state.synced = state.synced.max(written);
+                state.syncs += 1;
+            }
+            mem::take(&mut state.waiting)
+        };
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn batches_concurrent_syncs() {
+        use futures_util::future::join_all;
+
+        use super::SyncCoordinator;
+        use crate::{Error, IoBuf, Write};
+
+        #[derive(Default)]
+        struct Journal {
+            data: Vec<u8>,
+            durable: usize,
+            barriers: usize,
+        }
+
+        impl Write for Journal {
+            async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), Error>, B) {
+                self.data.extend_from_slice(buf.as_slice());
+                (Ok(()), buf)
+            }
+
+            async fn flush(&mut self) -> Result<(), Error> {
+                Ok(())
+            }
+
+            async fn close(&mut self) -> Result<(), Error> {
+                Ok(())
+            }
+
+            async fn barrier(&mut self) -> Result<(), Error> {
+                for _ in 0..3 {
+                    tokio::task::yield_now().await;
+                }
+                self.durable = self.data.len();
+                self.barriers += 1;
+                Ok(())
+            }
+        }
+
+        let journal = SyncCoordinator::new(Journal::default());
+        join_all((0..8u8).map(|i| {
+            let journal = &journal;
+            async move {
+                let (result, _) = journal.write_all(vec![i]).await;
+                result.unwrap();
+                journal.sync().await.unwrap();
+                // every record is durable once its sync returned
+                let file = journal.file.lock().await;
+                assert!(file.data[..file.durable].contains(&i));
+            }
+        }))
+        .await;
+
+        let syncs = journal.syncs();
+        assert!(syncs < 8, "{syncs} syncs");
+        let journal = journal.into_inner();
+        assert_eq!(journal.barriers as u64, syncs);
+        assert_eq!(journal.durable, 8);
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    io,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+
+use super::ListSnapshot;
+use crate::{
+    clock::Clock,
+    compression::{Decompressed, Decompression},
+    fs::{EntryKind, FileMeta, Fs},
+    path::Path,
+    Error, Read,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    file_format: String,
+    file_schema: String,
+    /// Milliseconds since the Unix epoch.
+    creation_timestamp: Option<String>,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Deserialize)]
+struct ManifestFile {
+    key: String,
+}
+
+fn invalid(message: &str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+/// Reads the S3 Inventory report described by `manifest`, the `manifest.json` of a delivery, into
+/// a snapshot of the files below `prefix`, so a full scan of a bucket with millions of objects
+/// costs a handful of reads instead of a LIST call per thousand keys.
+///
+/// `fs` is the bucket the report was delivered to; the data files listed by the manifest are
+/// read from it by their keys. Only the CSV format is supported, its `Key` and `Size` columns are
+/// required. The snapshot is stamped with the creation time of the report, so it misses every
+/// change made since, see [`InventoryFs`](crate::layer::InventoryFs) for serving listings from
+/// it. Reports without a creation time are stamped with the time `clock` tells.
+pub async fn load_s3_inventory<F: Fs>(
+    fs: &F,
+    manifest: &Path,
+    prefix: &Path,
+    clock: &dyn Clock,
+) -> Result<ListSnapshot, Error> {
+    let mut file = fs.open(manifest).await?;
+    let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+    result?;
+    let manifest: Manifest = serde_json::from_slice(&buf).map_err(|e| Error::Other(Box::new(e)))?;
+    if manifest.file_format != "CSV" {
+        return Err(Error::Unsupported {
+            message: format!("S3 Inventory format {}", manifest.file_format),
+        });
+    }
+
+    let columns = manifest
+        .file_schema
+        .split(',')
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    let column = |name| {
+        columns
+            .iter()
+            .position(|column| *column == name)
+            .ok_or_else(|| invalid("S3 Inventory schema has no Key or Size column"))
+    };
+    let (key_column, size_column) = (column("Key")?, column("Size")?);
+
+    let mut files = Vec::new();
+    for data in &manifest.files {
+        let path = Path::parse(&data.key)?;
+        let mut file =
+            Decompressed::open(fs.open(&path).await?, &path, Decompression::Auto).await?;
+        let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+        result?;
+
+        for record in csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(buf.as_slice())
+            .records()
+        {
+            let record = record.map_err(|e| Error::Other(Box::new(e)))?;
+            let (Some(key), Some(size)) = (record.get(key_column), record.get(size_column)) else {
+                return Err(invalid("S3 Inventory record misses a column"));
+            };
+            // keys are URL-encoded, with spaces encoded as `+`
+            let key = key.replace('+', " ");
+            let key = percent_decode_str(&key)
+                .decode_utf8()
+                .map_err(|_| invalid("S3 Inventory key is not UTF-8"))?;
+            let kind = match key.ends_with('/') {
+                true => EntryKind::Directory,
+                false => EntryKind::File,
+            };
+            // delete markers of versioned buckets have no size
+            let size = size.parse().unwrap_or(0);
+            files.push(FileMeta::new(Path::parse(key.trim_end_matches('/'))?, size).kind(kind));
+        }
+    }
+
+    let taken = manifest
+        .creation_timestamp
+        .and_then(|millis| millis.parse().ok())
+        .map_or_else(
+            || clock.now(),
+            |millis| UNIX_EPOCH + Duration::from_millis(millis),
+        );
+    Ok(ListSnapshot::from_files(prefix.clone(), taken, files))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn loads_csv_inventory() {
+        use std::{
+            io::Write,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        use flate2::{write::GzEncoder, Compression};
+        use tempfile::TempDir;
+
+        use super::load_s3_inventory;
+        use crate::{clock::SystemClock, disk::TokioFs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        let root = Path::from_absolute_path(dir.path()).unwrap();
+        std::fs::create_dir_all(dir.path().join("inventory/data")).unwrap();
+
+        let key = |name: &str| format!("{}/{name}", root.as_ref());
+        let data = format!(
+            "\"bucket\",\"{}\",\"10\"\n\"bucket\",\"{}\",\"20\"\n\"bucket\",\"{}\",\"30\"\n",
+            key("logs/a.log"),
+            key("logs/with+space.log"),
+            key("other/b.log"),
+        );
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data.as_bytes()).unwrap();
+        std::fs::write(
+            dir.path().join("inventory/data/1.csv.gz"),
+            encoder.finish().unwrap(),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("inventory/manifest.json"),
+            format!(
+                r#"{{
+                    "sourceBucket": "bucket",
+                    "fileFormat": "CSV",
+                    "fileSchema": "Bucket, Key, Size",
+                    "creationTimestamp": "1000",
+                    "files": [{{"key": "{}", "size": 1, "MD5checksum": ""}}]
+                }}"#,
+                key("inventory/data/1.csv.gz")
+            ),
+        )
+        .unwrap();
+
+        let snapshot = load_s3_inventory(
+            &TokioFs,
+            &root.child("inventory").child("manifest.json"),
+            &root.child("logs"),
+            &SystemClock,
+        )
+        .await
+        .unwrap();
+        assert_eq!(snapshot.taken().0, UNIX_EPOCH + Duration::from_millis(1000));
+        assert_eq!(
+            snapshot
+                .iter()
+                .map(|meta| (meta.path.clone(), meta.size))
+                .collect::<Vec<_>>(),
+            vec![
+                (root.child("logs").child("a.log"), 10),
+                (root.child("logs").child("with space.log"), 20),
+            ]
+        );
+    }
+}
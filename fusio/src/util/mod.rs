@@ -0,0 +1,42 @@
+//! Helpers built on top of [`crate::fs::Fs`] that work with every backend.
+
+pub(crate) mod bulk;
+mod catalog;
+mod checksum;
+#[cfg(feature = "tokio")]
+mod download;
+mod du;
+mod frame;
+mod glob;
+mod group_commit;
+#[cfg(feature = "inventory")]
+mod inventory;
+mod pack;
+mod rolling;
+mod scrub;
+mod snapshot;
+mod txn;
+mod upload;
+mod verify;
+mod wal;
+pub(crate) mod walk;
+
+pub use bulk::*;
+pub use catalog::*;
+pub use checksum::*;
+#[cfg(feature = "tokio")]
+pub use download::*;
+pub use du::*;
+pub use frame::*;
+pub use glob::*;
+pub use group_commit::*;
+#[cfg(feature = "inventory")]
+pub use inventory::*;
+pub use pack::*;
+pub use rolling::*;
+pub use scrub::*;
+pub use snapshot::*;
+pub use txn::*;
+pub use upload::*;
+pub use verify::*;
+pub use wal::*;
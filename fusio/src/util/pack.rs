@@ -0,0 +1,314 @@
+use std::{
+    io,
+    pin::pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures_util::StreamExt;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    fs::{Fs, OpenOptions},
+    path::Path,
+    Error, IoBuf, Read, Write,
+};
+
+const DATA_EXTENSION: &str = "pack";
+const INDEX_EXTENSION: &str = "index";
+
+fn pack_name(seq: u64, extension: &str) -> String {
+    format!("{seq:020}.{extension}")
+}
+
+fn parse_index(path: &Path) -> Option<u64> {
+    let (seq, extension) = path.filename()?.split_once('.')?;
+    match extension == INDEX_EXTENSION && seq.len() == 20 {
+        true => seq.parse().ok(),
+        false => None,
+    }
+}
+
+/// A record stored in a [`Pack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackEntry {
+    pub name: String,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// A data object holding many records, written by [`PackWriter`], and its index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pack {
+    pub data: Path,
+    pub index: Path,
+    /// The records in the order they were added.
+    pub entries: Vec<PackEntry>,
+}
+
+impl Pack {
+    /// Reads the index at `index`. The index has one `{offset}\t{len}\t{name}` line per record.
+    pub async fn load<F: Fs>(fs: &F, index: &Path) -> Result<Self, Error> {
+        let invalid = || {
+            Error::from(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid pack index",
+            ))
+        };
+        let seq = parse_index(index).ok_or_else(invalid)?;
+        let parent = Path::from_iter(index.parts().take(index.parts().count() - 1));
+
+        let mut file = fs.open(index).await?;
+        let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+        result?;
+        let content = String::from_utf8(buf).map_err(|_| invalid())?;
+        let entries = content
+            .lines()
+            .map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                Some(PackEntry {
+                    offset: fields.next()?.parse().ok()?,
+                    len: fields.next()?.parse().ok()?,
+                    name: fields.next()?.to_string(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+
+        Ok(Self {
+            data: parent.child(pack_name(seq, DATA_EXTENSION).as_str()),
+            index: index.clone(),
+            entries,
+        })
+    }
+
+    /// The last record added under `name`.
+    pub fn get(&self, name: &str) -> Option<&PackEntry> {
+        self.entries.iter().rev().find(|entry| entry.name == name)
+    }
+
+    /// Reads `entry` with a single ranged read of the data object.
+    pub async fn read<F: Fs>(&self, fs: &F, entry: &PackEntry) -> Result<Vec<u8>, Error> {
+        let len = usize::try_from(entry.len).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "record does not fit in memory")
+        })?;
+        let mut file = fs.open(&self.data).await?;
+        let (result, buf) = file.read_exact_at(vec![0; len], entry.offset).await;
+        result.map(|_| buf)
+    }
+}
+
+/// Lists the complete packs in `dir`, oldest first.
+pub async fn list_packs<F: Fs>(fs: &F, dir: &Path) -> Result<Vec<Path>, Error> {
+    let mut indexes = Vec::new();
+    let mut listing = pin!(fs.list(dir).await?);
+    while let Some(meta) = listing.next().await {
+        let path = meta?.path;
+        if let Some(seq) = parse_index(&path) {
+            indexes.push((seq, path));
+        }
+    }
+    indexes.sort_unstable_by_key(|(seq, _)| *seq);
+    Ok(indexes.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Buffers many small records in memory and writes them to a directory on any [`Fs`] as one
+/// data object plus an index, so object storage sees few large objects instead of many tiny
+/// ones. Records are read back through [`Pack`].
+///
+/// Buffered records are written once they reach the maximum size, once the oldest of them
+/// reaches the maximum age when a record is added, or by [`PackWriter::flush`]. The data object
+/// is written before the index, so a pack only counts, e.g. for [`list_packs`], once it is
+/// complete. Packs are named by a sequence number that continues from the packs already in the
+/// directory.
+pub struct PackWriter<F: Fs> {
+    fs: Arc<F>,
+    dir: Path,
+    max_size: u64,
+    max_age: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    data: Vec<u8>,
+    entries: Vec<PackEntry>,
+    started: Option<SystemTime>,
+    next_seq: u64,
+}
+
+impl<F: Fs> PackWriter<F> {
+    /// Writes packs of up to `max_size` bytes of records to `dir`.
+    pub async fn open(fs: Arc<F>, dir: Path, max_size: u64) -> Result<Self, Error> {
+        fs.create_dir_all(&dir).await?;
+        let next_seq = match list_packs(fs.as_ref(), &dir).await?.last() {
+            Some(index) => parse_index(index).expect("listed packs are parsed") + 1,
+            None => 0,
+        };
+        Ok(Self {
+            fs,
+            dir,
+            max_size,
+            max_age: None,
+            clock: Arc::new(SystemClock),
+            data: Vec::new(),
+            entries: Vec::new(),
+            started: None,
+            next_seq,
+        })
+    }
+
+    /// Writes the buffered records once the oldest of them was added `max_age` ago.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The number of bytes of the buffered records.
+    pub fn buffered(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// Buffers `record` under `name`, which must not contain a newline, and writes the buffered
+    /// records if they reached a limit. Returns the written pack, if any.
+    pub async fn add<B: IoBuf>(&mut self, name: &str, record: B) -> Result<Option<Pack>, Error> {
+        if name.contains(['\n', '\r']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "record name contains a newline",
+            )
+            .into());
+        }
+        let now = self.clock.now();
+        self.entries.push(PackEntry {
+            name: name.to_string(),
+            offset: self.data.len() as u64,
+            len: record.bytes_init() as u64,
+        });
+        self.data.extend_from_slice(record.as_slice());
+        let started = *self.started.get_or_insert(now);
+
+        let expired = self
+            .max_age
+            .is_some_and(|max_age| now.duration_since(started).unwrap_or_default() >= max_age);
+        if self.buffered() >= self.max_size || expired {
+            return self.flush().await;
+        }
+        Ok(None)
+    }
+
+    /// Writes the buffered records as a pack and returns it, or `None` if there are none. On
+    /// failure the records stay buffered for the next attempt.
+    pub async fn flush(&mut self) -> Result<Option<Pack>, Error> {
+        if self.entries.is_empty() {
+            return Ok(None);
+        }
+        let seq = self.next_seq;
+        let data = self.dir.child(pack_name(seq, DATA_EXTENSION).as_str());
+        let index = self.dir.child(pack_name(seq, INDEX_EXTENSION).as_str());
+
+        let mut file = self
+            .fs
+            .open_options(&data, OpenOptions::default().create(true).truncate(true))
+            .await?;
+        let (result, buf) = file.write_all(std::mem::take(&mut self.data)).await;
+        self.data = buf;
+        result?;
+        file.close().await?;
+
+        let mut lines = String::new();
+        for entry in &self.entries {
+            lines.push_str(&format!(
+                "{}\t{}\t{}\n",
+                entry.offset, entry.len, entry.name
+            ));
+        }
+        let mut file = self
+            .fs
+            .open_options(&index, OpenOptions::default().create(true).truncate(true))
+            .await?;
+        let (result, _) = file.write_all(lines.into_bytes()).await;
+        result?;
+        file.close().await?;
+
+        self.next_seq += 1;
+        self.data.clear();
+        self.started = None;
+        Ok(Some(Pack {
+            data,
+            index,
+            entries: std::mem::take(&mut self.entries),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn packs_small_records() {
+        use std::{
+            sync::Arc,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        use tempfile::TempDir;
+
+        use super::{list_packs, Pack, PackWriter};
+        use crate::{
+            clock::{FixedClock, OffsetClock},
+            disk::TokioFs,
+            path::Path,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let root = Path::from_absolute_path(dir.path().join("packs")).unwrap();
+        let clock = Arc::new(OffsetClock::new(FixedClock::new(
+            UNIX_EPOCH + Duration::from_secs(1),
+        )));
+        let mut writer = PackWriter::open(Arc::new(TokioFs), root.clone(), 16)
+            .await
+            .unwrap()
+            .max_age(Duration::from_secs(10))
+            .with_clock(clock.clone());
+
+        assert_eq!(writer.add("a.json", &b"{}"[..]).await.unwrap(), None);
+        assert_eq!(writer.add("b.json", &b"[1, 2]"[..]).await.unwrap(), None);
+        let pack = writer
+            .add("c.json", &b"\"sixteen\""[..])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pack.entries.len(), 3);
+        assert_eq!(writer.buffered(), 0);
+
+        writer.add("d.json", &b"null"[..]).await.unwrap();
+        clock.set_offset_millis(10_000);
+        let aged = writer
+            .add("a.json", &b"{\"v\": 2}"[..])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(writer.flush().await.unwrap(), None);
+
+        let packs = list_packs(&TokioFs, &root).await.unwrap();
+        assert_eq!(packs, vec![pack.index.clone(), aged.index.clone()]);
+        let loaded = Pack::load(&TokioFs, &packs[0]).await.unwrap();
+        assert_eq!(loaded, pack);
+        let entry = loaded.get("b.json").unwrap();
+        assert_eq!(loaded.read(&TokioFs, entry).await.unwrap(), b"[1, 2]");
+
+        let loaded = Pack::load(&TokioFs, &packs[1]).await.unwrap();
+        let entry = loaded.get("a.json").unwrap();
+        assert_eq!(loaded.read(&TokioFs, entry).await.unwrap(), b"{\"v\": 2}");
+
+        // numbering continues after a restart
+        let mut writer = PackWriter::open(Arc::new(TokioFs), root.clone(), 16)
+            .await
+            .unwrap();
+        writer.add("e.json", &b"true"[..]).await.unwrap();
+        let pack = writer.flush().await.unwrap().unwrap();
+        assert_eq!(pack.index.filename(), Some("00000000000000000002.index"));
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::compression::{Codec, Encoder};
+use crate::{
+    clock::{Clock, SystemClock},
+    fs::{Fs, OpenOptions},
+    path::Path,
+    Error, IoBuf, Write,
+};
+
+struct Segment<F> {
+    file: F,
+    path: Path,
+    opened: SystemTime,
+    size: u64,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    encoder: Option<Encoder>,
+}
+
+/// Writes records to a series of files on any [`Fs`], starting a new file once the current one
+/// reached a size or an age, e.g. for exporters of events or metrics writing straight to S3.
+///
+/// Files are named by a template in which `{seq}` is replaced by the sequence number of the file,
+/// padded to 20 digits so the names sort in the order the files were written, and `{timestamp}`
+/// by the time the file was started in milliseconds since the Unix epoch. The sequence restarts at
+/// `0` with every writer, so templates of writers that outlive a restart should include
+/// `{timestamp}`. A record is never split across files, and a file is only sealed when a record
+/// is written, [`RollingWriter::rotate`] seals it on demand, e.g. from a timer.
+pub struct RollingWriter<F: Fs> {
+    fs: Arc<F>,
+    dir: Path,
+    template: String,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    codec: Option<Codec>,
+    clock: Arc<dyn Clock>,
+    next_seq: u64,
+    current: Option<Segment<F::File>>,
+}
+
+impl<F: Fs> RollingWriter<F> {
+    /// Writes files named by `template` into `dir`, see [`RollingWriter`]. Without limits
+    /// every record goes to the same file until it is rotated by hand.
+    pub fn new(fs: Arc<F>, dir: Path, template: impl Into<String>) -> Self {
+        Self {
+            fs,
+            dir,
+            template: template.into(),
+            max_size: None,
+            max_age: None,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            codec: None,
+            clock: Arc::new(SystemClock),
+            next_seq: 0,
+            current: None,
+        }
+    }
+
+    /// Seals a file once this many bytes of records were written to it.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Seals a file before writing a record more than `max_age` after it was started.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Compresses the files with `codec` while writing them and appends its extension to their
+    /// names. [`RollingWriter::max_size`] still counts the bytes of the records.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub fn compress(mut self, codec: Codec) -> Self {
+        self.codec = Some(codec);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn file_name(&self, seq: u64, opened: SystemTime) -> String {
+        let timestamp = opened.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let name = self
+            .template
+            .replace("{seq}", &format!("{seq:020}"))
+            .replace("{timestamp}", &timestamp.as_millis().to_string());
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(codec) = self.codec {
+            return format!("{name}.{}", codec.extension());
+        }
+        name
+    }
+
+    /// Appends `record` to the current file, first sealing it if it is older than the maximum
+    /// age and starting a new one if there is none, and seals the file once it reached the
+    /// maximum size.
+    pub async fn write<B: IoBuf>(&mut self, record: B) -> Result<(), Error> {
+        let now = self.clock.now();
+        let expired = match (&self.current, self.max_age) {
+            (Some(segment), Some(max_age)) => {
+                now.duration_since(segment.opened).unwrap_or_default() >= max_age
+            }
+            _ => false,
+        };
+        if expired {
+            self.rotate().await?;
+        }
+
+        if self.current.is_none() {
+            let path = self.dir.child(self.file_name(self.next_seq, now).as_str());
+            let file = self
+                .fs
+                .open_options(&path, OpenOptions::default().create(true).truncate(true))
+                .await?;
+            self.next_seq += 1;
+            self.current = Some(Segment {
+                file,
+                path,
+                opened: now,
+                size: 0,
+                #[cfg(any(feature = "gzip", feature = "zstd"))]
+                encoder: self.codec.map(Codec::encoder).transpose()?,
+            });
+        }
+        let segment = self.current.as_mut().expect("segment was opened above");
+
+        let len = record.bytes_init() as u64;
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(encoder) = segment.encoder.as_mut() {
+            let output = encoder.encode(record.as_slice())?;
+            if !output.is_empty() {
+                let (result, _) = segment.file.write_all(output).await;
+                result?;
+            }
+            segment.size += len;
+            return self.seal_if_full().await;
+        }
+        let (result, _) = segment.file.write_all(record).await;
+        result?;
+        segment.size += len;
+        self.seal_if_full().await
+    }
+
+    async fn seal_if_full(&mut self) -> Result<(), Error> {
+        let full = match (&self.current, self.max_size) {
+            (Some(segment), Some(max_size)) => segment.size >= max_size,
+            _ => false,
+        };
+        if full {
+            self.rotate().await?;
+        }
+        Ok(())
+    }
+
+    /// The file records are currently written to, if any.
+    pub fn current(&self) -> Option<&Path> {
+        self.current.as_ref().map(|segment| &segment.path)
+    }
+
+    /// Flushes the current file. Compressed files are only complete once they are sealed.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        match self.current.as_mut() {
+            Some(segment) => segment.file.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Seals the current file and returns its path. The next record starts a new file.
+    pub async fn rotate(&mut self) -> Result<Option<Path>, Error> {
+        let Some(mut segment) = self.current.take() else {
+            return Ok(None);
+        };
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(encoder) = segment.encoder.take() {
+            let (result, _) = segment.file.write_all(encoder.finish()?).await;
+            result?;
+        }
+        segment.file.close().await?;
+        Ok(Some(segment.path))
+    }
+
+    /// Seals the current file, see [`RollingWriter::rotate`].
+    pub async fn close(&mut self) -> Result<Option<Path>, Error> {
+        self.rotate().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn rotates_by_size_and_age() {
+        use std::{
+            sync::Arc,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        use tempfile::TempDir;
+
+        use super::RollingWriter;
+        use crate::{
+            clock::{FixedClock, OffsetClock},
+            disk::TokioFs,
+            path::Path,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let clock = Arc::new(OffsetClock::new(FixedClock::new(
+            UNIX_EPOCH + Duration::from_secs(1),
+        )));
+        let mut writer = RollingWriter::new(
+            Arc::new(TokioFs),
+            Path::from_absolute_path(dir.path()).unwrap(),
+            "events-{timestamp}-{seq}.log",
+        )
+        .max_size(8)
+        .max_age(Duration::from_secs(60))
+        .with_clock(clock.clone());
+        let name = |millis: u64, seq: u64| format!("events-{millis}-{seq:020}.log");
+
+        writer.write(&b"hello"[..]).await.unwrap();
+        writer.write(&b"world"[..]).await.unwrap();
+        assert_eq!(writer.current(), None);
+        writer.write(&b"!"[..]).await.unwrap();
+        clock.set_offset_millis(60_000);
+        writer.write(&b"later"[..]).await.unwrap();
+        writer.close().await.unwrap();
+
+        assert_eq!(
+            std::fs::read(dir.path().join(name(1_000, 0))).unwrap(),
+            b"helloworld"
+        );
+        assert_eq!(
+            std::fs::read(dir.path().join(name(1_000, 1))).unwrap(),
+            b"!"
+        );
+        assert_eq!(
+            std::fs::read(dir.path().join(name(61_000, 2))).unwrap(),
+            b"later"
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[cfg(all(feature = "tokio", feature = "gzip", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn compresses_sealed_files() {
+        use std::sync::Arc;
+
+        use tempfile::TempDir;
+
+        use super::RollingWriter;
+        use crate::{compression::Codec, disk::TokioFs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        let mut writer = RollingWriter::new(
+            Arc::new(TokioFs),
+            Path::from_absolute_path(dir.path()).unwrap(),
+            "{seq}.log",
+        )
+        .compress(Codec::Gzip);
+        writer.write(&b"first\n"[..]).await.unwrap();
+        writer.write(&b"second\n"[..]).await.unwrap();
+        let sealed = writer.rotate().await.unwrap().unwrap();
+        assert_eq!(sealed.filename(), Some("00000000000000000000.log.gz"));
+
+        let compressed = std::fs::read(dir.path().join(sealed.filename().unwrap())).unwrap();
+        assert_eq!(Codec::Gzip.decode(&compressed).unwrap(), b"first\nsecond\n");
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    collections::HashSet,
+    future::Future,
+    io,
+    time::{Duration, SystemTime},
+};
+
+use super::{bulk::copy, verify::next_chunk, walk::walk, Verified};
+use crate::{
+    clock::Clock,
+    fs::{Fs, OpenOptions},
+    path::Path,
+    Error, Read, Write,
+};
+
+/// Appended to the path of a file to name the file holding its checksum, see [`store_checksum`].
+pub const CHECKSUM_SUFFIX: &str = ".crc32c";
+
+fn checksum_path(path: &Path) -> Result<Path, Error> {
+    Ok(Path::parse(format!("{path}{CHECKSUM_SUFFIX}"))?)
+}
+
+/// Stores the size and CRC-32C checksum of the file at `path` next to it, so [`scrub`] can verify
+/// the file later. Pass the [`Verified`] returned by [`super::put_verified`].
+pub async fn store_checksum<F: Fs>(fs: &F, path: &Path, verified: Verified) -> Result<(), Error> {
+    let mut file = fs
+        .open_options(
+            &checksum_path(path)?,
+            OpenOptions::default().create(true).truncate(true),
+        )
+        .await?;
+    let (result, _) = file
+        .write_all(format!("{} {:08x}\n", verified.size, verified.crc32c).into_bytes())
+        .await;
+    result?;
+    file.close().await
+}
+
+async fn load_checksum<F: Fs>(fs: &F, path: &Path) -> Result<Verified, Error> {
+    let checksum_path = checksum_path(path)?;
+    let mut file = fs.open(&checksum_path).await?;
+    let (result, content) = file.read_to_end_at(Vec::new(), 0).await;
+    result?;
+    let invalid = || Error::Other(format!("invalid checksum file {checksum_path}").into());
+    let content = String::from_utf8(content).map_err(|_| invalid())?;
+    let (size, crc32c) = content.trim_end().split_once(' ').ok_or_else(invalid)?;
+    Ok(Verified {
+        size: size.parse().map_err(|_| invalid())?,
+        crc32c: u32::from_str_radix(crc32c, 16).map_err(|_| invalid())?,
+    })
+}
+
+/// What [`scrub`] found.
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    /// Files whose content matched their checksum.
+    pub verified: u64,
+    /// Files without a checksum file or a checksum kept by the backend, which were not read.
+    pub unchecked: u64,
+    /// Bytes read from the scrubbed files, and from the mirror when repairing.
+    pub bytes: u64,
+    /// Files whose content no longer matches their checksum, repaired ones included.
+    pub corrupt: Vec<Path>,
+    /// The corrupt files restored from the mirror.
+    pub repaired: Vec<Path>,
+    /// Files that could not be checked or repaired, e.g. because their checksum file is invalid
+    /// or reading them failed, with the error.
+    pub failed: Vec<(Path, Error)>,
+}
+
+/// Keeps reads under `rate_limit` bytes per second on average since `start` as told by `clock`,
+/// waiting with `sleep`.
+struct Pacer<'a, S> {
+    clock: &'a dyn Clock,
+    start: SystemTime,
+    rate_limit: u64,
+    read: u64,
+    sleep: S,
+}
+
+impl<S, Fut> Pacer<'_, S>
+where
+    S: Fn(Duration) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    async fn consume(&mut self, bytes: u64) {
+        self.read += bytes;
+        if self.rate_limit == 0 {
+            return;
+        }
+        let due = Duration::from_secs_f64(self.read as f64 / self.rate_limit as f64);
+        let elapsed = self
+            .clock
+            .now()
+            .duration_since(self.start)
+            .unwrap_or_default();
+        if due > elapsed {
+            (self.sleep)(due - elapsed).await;
+        }
+    }
+}
+
+async fn checksum<F, S, Fut>(
+    fs: &F,
+    path: &Path,
+    pacer: &mut Pacer<'_, S>,
+) -> Result<Verified, Error>
+where
+    F: Fs,
+    S: Fn(Duration) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut file = fs.open(path).await?;
+    let mut verified = Verified { size: 0, crc32c: 0 };
+    let mut buf = Vec::new();
+    while let Some(chunk) = next_chunk(&mut file, buf, &mut verified).await? {
+        pacer.consume(chunk.len() as u64).await;
+        buf = chunk;
+    }
+    Ok(verified)
+}
+
+/// Reads every file under `prefix` that has a checksum and reports the ones whose content no
+/// longer matches it, so silent corruption is found while an intact copy still exists.
+///
+/// Checksums are taken from the files stored by [`store_checksum`], or else from the CRC-32C
+/// checksum the backend keeps for the file, like S3 does for objects uploaded with that checksum
+/// algorithm, see [`FileMeta::crc32c`](crate::fs::FileMeta::crc32c). Looking the latter up costs
+/// an [`Fs::stat`] per file without a checksum file, a HEAD request on S3.
+///
+/// Reads are paced to `rate_limit` bytes per second, `0` for no limit, so a scrub can run in the
+/// background without starving the foreground of bandwidth. The pace is measured with `clock`,
+/// and `sleep` waits for the given duration on the runtime of the caller, e.g.
+/// `tokio::time::sleep`, and is only called to keep the rate limit. Simulations pass a simulated
+/// clock and a `sleep` that advances it. Files without any checksum are counted, but not read.
+/// Files are read in chunks and never held in memory as a whole.
+///
+/// A file that cannot be checked, e.g. because its checksum file is invalid, is reported in
+/// [`ScrubReport::failed`] and the scrub goes on with the next file. Files removed after they were
+/// listed are skipped. Only failing to list `prefix` fails the scrub.
+pub async fn scrub<F, S, Fut>(
+    fs: &F,
+    prefix: &Path,
+    rate_limit: u64,
+    clock: &dyn Clock,
+    sleep: S,
+) -> Result<ScrubReport, Error>
+where
+    F: Fs,
+    S: Fn(Duration) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    scrub_files(fs, None::<&F>, prefix, rate_limit, clock, sleep).await
+}
+
+/// Like [`scrub`], but restores corrupt files from `mirror`, which holds a copy of the files of
+/// `fs` under the same paths, e.g. a replica. The copy is only restored when it matches the
+/// stored checksum, a corrupt file without an intact copy is only reported.
+pub async fn scrub_and_repair<F, M, S, Fut>(
+    fs: &F,
+    mirror: &M,
+    prefix: &Path,
+    rate_limit: u64,
+    clock: &dyn Clock,
+    sleep: S,
+) -> Result<ScrubReport, Error>
+where
+    F: Fs,
+    M: Fs,
+    S: Fn(Duration) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    scrub_files(fs, Some(mirror), prefix, rate_limit, clock, sleep).await
+}
+
+async fn scrub_files<F, M, S, Fut>(
+    fs: &F,
+    mirror: Option<&M>,
+    prefix: &Path,
+    rate_limit: u64,
+    clock: &dyn Clock,
+    sleep: S,
+) -> Result<ScrubReport, Error>
+where
+    F: Fs,
+    M: Fs,
+    S: Fn(Duration) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut files = Vec::new();
+    let mut checksums = HashSet::new();
+    walk(fs, prefix, |meta| {
+        match meta.path.as_ref().strip_suffix(CHECKSUM_SUFFIX) {
+            Some(file) => {
+                checksums.insert(file.to_string());
+            }
+            None => files.push(meta.path),
+        }
+    })
+    .await?;
+
+    let mut report = ScrubReport::default();
+    let mut pacer = Pacer {
+        clock,
+        start: clock.now(),
+        rate_limit,
+        read: 0,
+        sleep,
+    };
+    for path in files {
+        let has_checksum_file = checksums.contains(path.as_ref());
+        match scrub_file(
+            fs,
+            mirror,
+            &path,
+            has_checksum_file,
+            &mut pacer,
+            &mut report,
+        )
+        .await
+        {
+            Ok(()) => {}
+            // removed since it was listed
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => report.failed.push((path, e)),
+        }
+    }
+    report.bytes = pacer.read;
+    Ok(report)
+}
+
+async fn scrub_file<F, M, S, Fut>(
+    fs: &F,
+    mirror: Option<&M>,
+    path: &Path,
+    has_checksum_file: bool,
+    pacer: &mut Pacer<'_, S>,
+    report: &mut ScrubReport,
+) -> Result<(), Error>
+where
+    F: Fs,
+    M: Fs,
+    S: Fn(Duration) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let expected = match has_checksum_file {
+        true => load_checksum(fs, path).await?,
+        false => {
+            let meta = fs.stat(path).await?;
+            let Some(crc32c) = meta.crc32c else {
+                report.unchecked += 1;
+                return Ok(());
+            };
+            Verified {
+                size: meta.size,
+                crc32c,
+            }
+        }
+    };
+    if checksum(fs, path, pacer).await? == expected {
+        report.verified += 1;
+        return Ok(());
+    }
+
+    report.corrupt.push(path.clone());
+    let Some(mirror) = mirror else {
+        return Ok(());
+    };
+    match checksum(mirror, path, pacer).await {
+        Ok(copy) if copy == expected => {}
+        // no intact copy to restore
+        Ok(_) => return Ok(()),
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    }
+    copy(mirror, fs, path, path).await?;
+    report.repaired.push(path.clone());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn repairs_corrupt_files_from_mirror() {
+        use std::{
+            future::{ready, Ready},
+            sync::Arc,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        use super::{scrub, scrub_and_repair, store_checksum};
+        use crate::{
+            clock::{FixedClock, OffsetClock},
+            path::Path,
+            remotes::aws::mock::MockS3,
+            util::{crc32c, Verified},
+        };
+
+        let primary = Arc::new(MockS3::default());
+        let mirror = Arc::new(MockS3::default());
+        let (fs, mirror_fs) = (primary.fs(), mirror.fs());
+        let prefix = Path::parse("sst").unwrap();
+        for (name, content) in [
+            ("a", &b"alpha"[..]),
+            ("b", b"bravo"),
+            ("c", b"charlie"),
+            ("d", b"delta"),
+        ] {
+            let key = format!("sst/{name}");
+            primary.put(&key, content.to_vec());
+            if name != "d" {
+                mirror.put(&key, content.to_vec());
+            }
+            let verified = Verified {
+                size: content.len() as u64,
+                crc32c: crc32c(0, content),
+            };
+            store_checksum(&fs, &Path::parse(&key).unwrap(), verified)
+                .await
+                .unwrap();
+        }
+        primary.put("sst/unchecked", "?");
+        // checksummed by S3 on upload, and rotten since
+        primary.put_with_crc32c("sst/s3", "s3 checksum");
+        primary.put_with_crc32c("sst/rotten", "s3 checksum");
+        primary.put("sst/rotten", "s3 checksun");
+        primary.put("sst/invalid", "?");
+        primary.put("sst/invalid.crc32c", "not a checksum");
+        // bit rot on the primary, and on the mirror as well for `c`, `d` has no mirrored copy
+        primary.put("sst/b", "brave");
+        primary.put("sst/c", "charlee");
+        primary.put("sst/d", "delta!");
+        mirror.put("sst/c", "charlik");
+
+        // simulated time, advanced by sleeping
+        let clock = OffsetClock::new(FixedClock::new(UNIX_EPOCH));
+        let sleep = |duration: Duration| -> Ready<()> {
+            clock.set_offset_millis(clock.offset_millis() + duration.as_millis() as i64);
+            ready(())
+        };
+
+        let report = scrub(&fs, &prefix, 400, &clock, sleep).await.unwrap();
+        // 45 bytes at 400 bytes per second
+        assert_eq!(clock.offset_millis(), 112);
+        let path = |name: &str| Path::parse(format!("sst/{name}")).unwrap();
+        let corrupt = vec![path("b"), path("c"), path("d"), path("rotten")];
+        assert_eq!(report.verified, 2);
+        assert_eq!(report.unchecked, 1);
+        assert_eq!(report.bytes, 45);
+        assert_eq!(report.corrupt, corrupt);
+        assert!(report.repaired.is_empty());
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, path("invalid"));
+
+        let report = scrub_and_repair(&fs, &mirror_fs, &prefix, 0, &clock, sleep)
+            .await
+            .unwrap();
+        assert_eq!(report.corrupt, corrupt);
+        assert_eq!(report.repaired, vec![path("b")]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(primary.get("sst/b").unwrap(), &b"bravo"[..]);
+        assert_eq!(primary.get("sst/c").unwrap(), &b"charlee"[..]);
+
+        let report = scrub(&fs, &prefix, 0, &clock, sleep).await.unwrap();
+        assert_eq!(report.verified, 3);
+        assert_eq!(report.corrupt, vec![path("c"), path("d"), path("rotten")]);
+    }
+}
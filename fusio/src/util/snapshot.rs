@@ -0,0 +1,227 @@
+use std::{
+    collections::{btree_map, BTreeMap},
+    io,
+    ops::Bound,
+    time::SystemTime,
+};
+
+use super::walk::walk;
+use crate::{
+    clock::Clock,
+    fs::{FileMeta, Fs},
+    path::Path,
+    Error, Read,
+};
+
+/// A materialized listing of the files under a prefix, see [`list_snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListSnapshot {
+    prefix: Path,
+    started: SystemTime,
+    finished: SystemTime,
+    files: BTreeMap<Path, FileMeta>,
+}
+
+/// How the files of a [`ListSnapshot`] differ from an older one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub added: Vec<Path>,
+    pub removed: Vec<Path>,
+    /// Files whose size changed.
+    pub changed: Vec<Path>,
+}
+
+impl ListSnapshot {
+    /// A snapshot of the files below `prefix` that were listed by other means at `taken`, e.g.
+    /// by an inventory report. Files outside of `prefix` are left out.
+    pub fn from_files(
+        prefix: Path,
+        taken: SystemTime,
+        files: impl IntoIterator<Item = FileMeta>,
+    ) -> Self {
+        let files = files
+            .into_iter()
+            .filter(|meta| meta.path.prefix_matches(&prefix))
+            .map(|meta| (meta.path.clone(), meta))
+            .collect();
+        Self {
+            prefix,
+            started: taken,
+            finished: taken,
+            files,
+        }
+    }
+
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// When listing started and finished. Every entry reflects its file at some point in between.
+    pub fn taken(&self) -> (SystemTime, SystemTime) {
+        (self.started, self.finished)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&FileMeta> {
+        self.files.get(path)
+    }
+
+    /// Every file in lexicographic order of the paths.
+    pub fn iter(&self) -> impl Iterator<Item = &FileMeta> {
+        self.files.values()
+    }
+
+    /// The files below `prefix`, in lexicographic order, without listing again.
+    pub fn below<'a>(&'a self, prefix: &'a Path) -> impl Iterator<Item = &'a FileMeta> + 'a {
+        self.files
+            .range::<Path, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |(path, _)| path.as_ref().starts_with(prefix.as_ref()))
+            .filter(move |(path, _)| path.prefix_matches(prefix))
+            .map(|(_, meta)| meta)
+    }
+
+    /// The total size of every file.
+    pub fn total_size(&self) -> u64 {
+        self.files.values().map(|meta| meta.size).sum()
+    }
+
+    /// What changed between `older` and this snapshot.
+    pub fn diff(&self, older: &ListSnapshot) -> SnapshotDiff {
+        let mut diff = SnapshotDiff::default();
+        for (path, meta) in &self.files {
+            match older.files.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(old) if old.size != meta.size => diff.changed.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = older
+            .files
+            .keys()
+            .filter(|path| !self.files.contains_key(*path))
+            .cloned()
+            .collect();
+        diff
+    }
+}
+
+impl<'a> IntoIterator for &'a ListSnapshot {
+    type Item = &'a FileMeta;
+    type IntoIter = btree_map::Values<'a, Path, FileMeta>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.files.values()
+    }
+}
+
+/// Lists every file under `prefix` into a [`ListSnapshot`] that can be queried without listing
+/// again.
+///
+/// Listings of object stores are paginated and not isolated from concurrent writes, so the
+/// snapshot is not atomic: every entry reflects its file at the moment its page was listed.
+/// Files created while listing show up if they sort after the page being listed, files removed
+/// while listing show up if they sort before it. Files reported twice, e.g. by a page that was
+/// fetched again after a retry, are kept once with the latest metadata. Files that were neither
+/// created nor removed during [`ListSnapshot::taken`] are always reported correctly. The times
+/// of [`ListSnapshot::taken`] are read from `clock`.
+pub async fn list_snapshot<F: Fs>(
+    fs: &F,
+    prefix: &Path,
+    clock: &dyn Clock,
+) -> Result<ListSnapshot, Error> {
+    let started = clock.now();
+    let mut files = BTreeMap::new();
+    walk(fs, prefix, |meta| {
+        files.insert(meta.path.clone(), meta);
+    })
+    .await?;
+
+    Ok(ListSnapshot {
+        prefix: prefix.clone(),
+        started,
+        finished: clock.now(),
+        files,
+    })
+}
+
+/// Reads a listing of the files below `prefix` from `listing`, a file with one `{size}\t{path}`
+/// line per file, e.g. exported by another tool, so a full scan of a large bucket does not need
+/// to list it. The snapshot is stamped with the time `clock` tells when it was loaded, the listing
+/// may be older.
+pub async fn load_listing<F: Fs>(
+    fs: &F,
+    listing: &Path,
+    prefix: &Path,
+    clock: &dyn Clock,
+) -> Result<ListSnapshot, Error> {
+    let invalid = || {
+        Error::from(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid listing",
+        ))
+    };
+    let mut file = fs.open(listing).await?;
+    let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+    result?;
+    let content = String::from_utf8(buf).map_err(|_| invalid())?;
+
+    let files = content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (size, path) = line.split_once('\t').ok_or_else(invalid)?;
+            Ok(FileMeta::new(
+                Path::parse(path)?,
+                size.parse().map_err(|_| invalid())?,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(ListSnapshot::from_files(prefix.clone(), clock.now(), files))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn snapshots_and_diffs() {
+        use tempfile::TempDir;
+
+        use super::list_snapshot;
+        use crate::{clock::SystemClock, disk::TokioFs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/nested")).unwrap();
+        std::fs::create_dir_all(dir.path().join("ab")).unwrap();
+        std::fs::write(dir.path().join("a/1"), [0; 10]).unwrap();
+        std::fs::write(dir.path().join("a/nested/2"), [0; 20]).unwrap();
+        std::fs::write(dir.path().join("ab/3"), [0; 30]).unwrap();
+        let root = Path::from_absolute_path(dir.path()).unwrap();
+
+        let before = list_snapshot(&TokioFs, &root, &SystemClock).await.unwrap();
+        assert_eq!(before.len(), 3);
+        assert_eq!(before.total_size(), 60);
+        let a = root.child("a");
+        assert_eq!(
+            before.below(&a).map(|meta| meta.size).collect::<Vec<_>>(),
+            vec![10, 20]
+        );
+        assert_eq!(before.get(&root.child("ab").child("3")).unwrap().size, 30);
+
+        std::fs::remove_file(dir.path().join("a/1")).unwrap();
+        std::fs::write(dir.path().join("a/nested/2"), [0; 5]).unwrap();
+        std::fs::write(dir.path().join("ab/4"), [0; 40]).unwrap();
+        let after = list_snapshot(&TokioFs, &root, &SystemClock).await.unwrap();
+
+        let diff = after.diff(&before);
+        assert_eq!(diff.added, vec![root.child("ab").child("4")]);
+        assert_eq!(diff.removed, vec![a.child("1")]);
+        assert_eq!(diff.changed, vec![a.child("nested").child("2")]);
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    pin::pin,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use futures_util::StreamExt;
+
+use crate::{
+    fs::{temp_path, Fs, OpenOptions},
+    path::Path,
+    Error, Read, Write,
+};
+
+const MANIFEST_DIR: &str = "_manifest";
+const DATA_DIR: &str = "data";
+
+fn not_found(err: &Error) -> bool {
+    matches!(err, Error::Io(e) if e.kind() == io::ErrorKind::NotFound)
+}
+
+/// The files of a [`TransactionalFs`] as of one committed version.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    version: u64,
+    /// Logical paths and the data files holding their content.
+    files: BTreeMap<Path, Path>,
+}
+
+impl Snapshot {
+    /// The committed version, `0` before the first commit.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The logical paths of the snapshot in lexicographic order.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys()
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn encode(&self) -> String {
+        self.files
+            .iter()
+            .map(|(path, location)| format!("{location}\t{path}\n"))
+            .collect::<Vec<_>>()
+            .concat()
+    }
+
+    fn decode(version: u64, manifest: &[u8]) -> Result<Self, Error> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("manifest of version {version} is corrupt"),
+            )
+        };
+        let manifest = std::str::from_utf8(manifest).map_err(|_| invalid())?;
+        let files = manifest
+            .lines()
+            .map(|line| {
+                let (location, path) = line.split_once('\t').ok_or_else(invalid)?;
+                Ok((Path::parse(path)?, Path::parse(location)?))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self { version, files })
+    }
+}
+
+/// Groups writes to several files into transactions that become visible all at once, like the
+/// commits of a table format on an object store.
+///
+/// Files are written to uniquely named data files under `{root}/data`, and a commit publishes a
+/// manifest `{root}/_manifest/{version}` that maps the logical paths to their data files. Readers
+/// open files through a [`Snapshot`], so they keep seeing one version while newer ones are
+/// committed. Data files of older versions are never removed.
+///
+/// A commit fails with [`io::ErrorKind::AlreadyExists`] when another transaction committed the
+/// same version first, the caller retries on a fresh snapshot. Manifests are written under a
+/// temporary name and published with [`Fs::rename_new`], a hard link on local disks and a
+/// conditional PUT on S3, so readers never see a manifest half written and of two processes
+/// committing the same version at the same moment only one succeeds. The backend has to support
+/// it.
+pub struct TransactionalFs<F> {
+    fs: F,
+    root: Path,
+    /// The latest version seen, versions up to it are not looked up again.
+    latest: AtomicU64,
+}
+
+impl<F: Fs> TransactionalFs<F> {
+    pub fn new(fs: F, root: Path) -> Self {
+        Self {
+            fs,
+            root,
+            latest: AtomicU64::new(0),
+        }
+    }
+
+    fn manifest(&self, version: u64) -> Path {
+        self.root
+            .child(MANIFEST_DIR)
+            .child(format!("{version:020}").as_str())
+    }
+
+    /// The latest committed version, `0` before the first commit.
+    ///
+    /// Versions are committed one after another, so once a version was seen only the manifests
+    /// after it are looked up instead of listing every manifest again.
+    pub async fn latest_version(&self) -> Result<u64, Error> {
+        let mut latest = match self.latest.load(Ordering::Acquire) {
+            0 => self.list_latest_version().await?,
+            known => known,
+        };
+        loop {
+            match self.fs.stat(&self.manifest(latest + 1)).await {
+                Ok(_) => latest += 1,
+                Err(e) if not_found(&e) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        self.latest.fetch_max(latest, Ordering::AcqRel);
+        Ok(latest)
+    }
+
+    async fn list_latest_version(&self) -> Result<u64, Error> {
+        let dir = self.root.child(MANIFEST_DIR);
+        let listing = match self.fs.list(&dir).await {
+            Err(e) if not_found(&e) => return Ok(0),
+            listing => listing?,
+        };
+        let mut listing = pin!(listing);
+        let mut latest = 0;
+        let mut first = true;
+        while let Some(meta) = listing.next().await {
+            // type erased backends report a missing directory as the first item
+            let meta = match meta {
+                Err(e) if first && not_found(&e) => return Ok(0),
+                meta => meta?,
+            };
+            first = false;
+            if let Some(version) = meta.path.filename().and_then(|name| name.parse().ok()) {
+                latest = latest.max(version);
+            }
+        }
+        Ok(latest)
+    }
+
+    /// The files as of `version`.
+    pub async fn snapshot_at(&self, version: u64) -> Result<Snapshot, Error> {
+        if version == 0 {
+            return Ok(Snapshot::default());
+        }
+        let mut manifest = self.fs.open(&self.manifest(version)).await?;
+        let (result, buf) = manifest.read_to_end_at(Vec::new(), 0).await;
+        result?;
+        Snapshot::decode(version, &buf)
+    }
+
+    /// The files as of the latest committed version.
+    pub async fn snapshot(&self) -> Result<Snapshot, Error> {
+        self.snapshot_at(self.latest_version().await?).await
+    }
+
+    /// Opens `path` as of `snapshot` for reading.
+    pub async fn open(&self, snapshot: &Snapshot, path: &Path) -> Result<F::File, Error> {
+        match snapshot.files.get(path) {
+            Some(location) => self.fs.open(location).await,
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("\"{path}\" is not part of version {}", snapshot.version),
+            )
+            .into()),
+        }
+    }
+
+    /// Starts a transaction on top of the latest committed version.
+    pub async fn begin(&self) -> Result<Transaction<'_, F>, Error> {
+        let base = self.snapshot().await?;
+        self.fs.create_dir_all(&self.root.child(DATA_DIR)).await?;
+        self.fs
+            .create_dir_all(&self.root.child(MANIFEST_DIR))
+            .await?;
+        Ok(Transaction {
+            fs: self,
+            files: base.files.clone(),
+            base,
+            written: Vec::new(),
+        })
+    }
+}
+
+/// Writes and removals that are committed together, see [`TransactionalFs::begin`].
+pub struct Transaction<'a, F> {
+    fs: &'a TransactionalFs<F>,
+    base: Snapshot,
+    files: BTreeMap<Path, Path>,
+    written: Vec<Path>,
+}
+
+impl<F: Fs> Transaction<'_, F> {
+    /// The version the transaction started from.
+    pub fn base(&self) -> &Snapshot {
+        &self.base
+    }
+
+    /// Creates a new file for `path` that replaces its current content once committed. Close it
+    /// before committing.
+    pub async fn create(&mut self, path: &Path) -> Result<F::File, Error> {
+        if path.as_ref().contains(['\t', '\n', '\r']) {
+            return Err(Error::Unsupported {
+                message: format!("transactional paths cannot contain line breaks or tabs: {path}"),
+            });
+        }
+        let location = temp_path(&self.fs.root.child(DATA_DIR).child("part"))?;
+        let file = self
+            .fs
+            .fs
+            .open_options(
+                &location,
+                OpenOptions::default().create(true).truncate(true),
+            )
+            .await?;
+        self.written.push(location.clone());
+        self.files.insert(path.clone(), location);
+        Ok(file)
+    }
+
+    /// Removes `path` once committed.
+    pub fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    /// Publishes the transaction as the version after its base and returns that version.
+    ///
+    /// On conflict the data files written by the transaction are removed again.
+    pub async fn commit(self) -> Result<u64, Error> {
+        let snapshot = Snapshot {
+            version: self.base.version + 1,
+            files: self.files,
+        };
+        let manifest = self.fs.manifest(snapshot.version);
+
+        let staged = temp_path(&manifest)?;
+        let published = async {
+            let mut file = self
+                .fs
+                .fs
+                .open_options(&staged, OpenOptions::default().create(true).truncate(true))
+                .await?;
+            let (result, _) = file.write_all(snapshot.encode().into_bytes()).await;
+            result?;
+            file.close().await?;
+            self.fs.fs.rename_new(&staged, &manifest).await
+        };
+        let result = published.await;
+        if result.is_err() {
+            let _ = self.fs.fs.remove(&staged).await;
+        }
+        match result {
+            Ok(()) => {
+                self.fs.latest.fetch_max(snapshot.version, Ordering::AcqRel);
+                Ok(snapshot.version)
+            }
+            Err(Error::AlreadyExists { .. }) => {
+                for location in &self.written {
+                    let _ = self.fs.fs.remove(location).await;
+                }
+                Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "version {} was committed by another transaction",
+                        snapshot.version
+                    ),
+                )
+                .into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn commits_atomically() {
+        use std::io;
+
+        use tempfile::TempDir;
+
+        use super::{Snapshot, TransactionalFs};
+        use crate::{disk::TokioFs, path::Path, Error, Read, Write};
+
+        async fn read(fs: &TransactionalFs<TokioFs>, snapshot: &Snapshot, path: &Path) -> Vec<u8> {
+            let mut file = fs.open(snapshot, path).await.unwrap();
+            let (result, buf) = file.read_to_end_at(Vec::new(), 0).await;
+            result.unwrap();
+            buf
+        }
+
+        let dir = TempDir::new().unwrap();
+        let root = Path::from_absolute_path(dir.path()).unwrap();
+        let fs = TransactionalFs::new(TokioFs, root.clone());
+        let other = TransactionalFs::new(TokioFs, root);
+        let (a, b) = (Path::parse("a").unwrap(), Path::parse("b").unwrap());
+
+        let mut txn = fs.begin().await.unwrap();
+        for (path, content) in [(&a, "a1"), (&b, "b1")] {
+            let mut file = txn.create(path).await.unwrap();
+            file.write_all(content.as_bytes()).await.0.unwrap();
+            file.close().await.unwrap();
+        }
+        assert_eq!(fs.snapshot().await.unwrap().paths().count(), 0);
+        assert_eq!(txn.commit().await.unwrap(), 1);
+
+        let v1 = fs.snapshot().await.unwrap();
+        assert_eq!(v1.paths().collect::<Vec<_>>(), vec![&a, &b]);
+        assert_eq!(other.latest_version().await.unwrap(), 1);
+
+        let mut first = fs.begin().await.unwrap();
+        let mut second = fs.begin().await.unwrap();
+        first.remove(&b);
+        let mut file = first.create(&a).await.unwrap();
+        file.write_all(&b"a2"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+        second.remove(&a);
+        assert_eq!(first.commit().await.unwrap(), 2);
+        let err = second.commit().await;
+        assert!(matches!(err, Err(Error::Io(e)) if e.kind() == io::ErrorKind::AlreadyExists));
+
+        let v2 = fs.snapshot().await.unwrap();
+        assert_eq!(v2.version(), 2);
+        assert!(!v2.contains(&b));
+        assert_eq!(read(&fs, &v2, &a).await, b"a2");
+        assert_eq!(read(&fs, &v1, &a).await, b"a1");
+        assert_eq!(fs.snapshot_at(1).await.unwrap(), v1);
+        // the version seen before is not listed again, the new one is still found
+        assert_eq!(other.latest_version().await.unwrap(), 2);
+        // manifests are staged under a temporary name, the one of the losing commit is gone
+        let manifests = std::fs::read_dir(dir.path().join("_manifest")).unwrap();
+        assert_eq!(manifests.count(), 2);
+    }
+
+    #[cfg(feature = "aws")]
+    #[tokio::test]
+    async fn commits_conditionally_on_s3() {
+        use std::{io, sync::Arc};
+
+        use futures_util::StreamExt;
+
+        use super::TransactionalFs;
+        use crate::{fs::Fs, path::Path, remotes::aws::mock::MockS3, Error, Write};
+
+        let server = Arc::new(MockS3::default());
+        let fs = TransactionalFs::new(server.fs(), Path::parse("table").unwrap());
+        let a = Path::parse("a").unwrap();
+
+        let mut txn = fs.begin().await.unwrap();
+        let mut file = txn.create(&a).await.unwrap();
+        file.write_all(&b"a1"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+        assert_eq!(txn.commit().await.unwrap(), 1);
+
+        let mut first = fs.begin().await.unwrap();
+        let mut second = fs.begin().await.unwrap();
+        first.remove(&a);
+        let mut file = second.create(&a).await.unwrap();
+        file.write_all(&b"a2"[..]).await.0.unwrap();
+        file.close().await.unwrap();
+        assert_eq!(first.commit().await.unwrap(), 2);
+        let err = second.commit().await;
+        assert!(matches!(err, Err(Error::Io(e)) if e.kind() == io::ErrorKind::AlreadyExists));
+
+        let latest = fs.snapshot().await.unwrap();
+        assert_eq!(latest.version(), 2);
+        assert!(!latest.contains(&a));
+        // the data file of the losing transaction was removed again, only the one of version 1
+        // is left
+        let (s3, data) = (server.fs(), Path::parse("table/data").unwrap());
+        assert_eq!(s3.list(&data).await.unwrap().count().await, 1);
+    }
+
+    #[cfg(all(feature = "tokio", feature = "dyn", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn empty_type_erased_store() {
+        use tempfile::TempDir;
+
+        use super::TransactionalFs;
+        use crate::{disk::TokioFs, dynamic::DynFs, path::Path};
+
+        let dir = TempDir::new().unwrap();
+        let fs: Box<dyn DynFs> = Box::new(TokioFs);
+        let fs = TransactionalFs::new(fs, Path::from_absolute_path(dir.path()).unwrap());
+
+        assert_eq!(fs.latest_version().await.unwrap(), 0);
+        assert_eq!(fs.snapshot().await.unwrap().version(), 0);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{
+    buf::BufferPool,
+    fs::{Fs, OpenOptions},
+    path::Path,
+    Error, Read, Write,
+};
+
+/// Size of the chunks [`put_from_file`] reads, matching the largest buffer a default
+/// [`BufferPool`] keeps, so every chunk is reused. Remote writers upload data beyond their part
+/// size as multipart parts while it is streamed.
+const CHUNK_SIZE: usize = 8 << 20;
+
+/// Streams the file at `from` on `local` into `to` on `remote`, replacing `to`, and returns the
+/// number of bytes uploaded.
+///
+/// The file is read in chunks into buffers taken from `pool` and handed back once written, so
+/// uploading in a steady state neither allocates nor holds the file in memory. Remote writers
+/// like S3 turn the stream into a multipart upload on their own.
+pub async fn put_from_file<L, R>(
+    local: &L,
+    from: &Path,
+    remote: &R,
+    to: &Path,
+    pool: &BufferPool,
+) -> Result<u64, Error>
+where
+    L: Fs,
+    R: Fs,
+{
+    let mut reader = local.open(from).await?;
+    let size = reader.size().await?;
+    let mut writer = remote
+        .open_options(to, OpenOptions::default().create(true).truncate(true))
+        .await?;
+
+    let mut buf = pool.get(CHUNK_SIZE);
+    let mut offset = 0;
+    while offset < size {
+        let len = CHUNK_SIZE.min((size - offset) as usize);
+        buf.resize(len, 0);
+        let (result, chunk) = reader.read_exact_at(buf, offset).await;
+        if let Err(e) = result {
+            pool.put(chunk);
+            return Err(e);
+        }
+        let (result, chunk) = writer.write_all(chunk).await;
+        buf = chunk;
+        if let Err(e) = result {
+            pool.put(buf);
+            return Err(e);
+        }
+        offset += len as u64;
+    }
+    pool.put(buf);
+    writer.close().await?;
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn uploads_in_chunks() {
+        use tempfile::TempDir;
+
+        use super::put_from_file;
+        use crate::{
+            buf::{BufferPool, PoolOptions},
+            disk::TokioFs,
+            path::Path,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let content = (0..(17 << 20) + 5).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(dir.path().join("local"), &content).unwrap();
+        std::fs::write(dir.path().join("remote"), b"stale").unwrap();
+
+        let pool = BufferPool::new(PoolOptions::default());
+        let uploaded = put_from_file(
+            &TokioFs,
+            &Path::from_absolute_path(dir.path().join("local")).unwrap(),
+            &TokioFs,
+            &Path::from_absolute_path(dir.path().join("remote")).unwrap(),
+            &pool,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(uploaded, content.len() as u64);
+        assert!(std::fs::read(dir.path().join("remote")).unwrap() == content);
+        assert_eq!(pool.retained(), 8 << 20);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    fs::{Fs, OpenOptions},
+    path::Path,
+    util::crc32c,
+    Error, Read, Write,
+};
+
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Size and CRC-32C checksum of a file uploaded by [`put_verified`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verified {
+    pub size: u64,
+    pub crc32c: u32,
+}
+
+/// Reads the next chunk of up to `CHUNK_SIZE` bytes into `buf` and adds it to `verified`,
+/// returning `None` at the end of `reader`.
+pub(super) async fn next_chunk<R: Read>(
+    reader: &mut R,
+    mut buf: Vec<u8>,
+    verified: &mut Verified,
+) -> Result<Option<Vec<u8>>, Error> {
+    buf.resize(CHUNK_SIZE, 0);
+    let (result, mut buf) = reader.read_at(buf, verified.size).await;
+    let len = result?;
+    if len == 0 {
+        return Ok(None);
+    }
+    buf.truncate(len);
+    verified.size += len as u64;
+    verified.crc32c = crc32c(verified.crc32c, &buf);
+    Ok(Some(buf))
+}
+
+/// Uploads everything `reader` contains to `path`, then compares the size and CRC-32C checksum
+/// [`Fs::stat`] reports for the stored file with the ones computed while uploading.
+///
+/// Success is only reported once the stored copy is verified, which suits immutable files like
+/// SSTs that are referenced right after the upload. On a mismatch the stored file is removed and
+/// [`Error::ChecksumMismatch`] is returned, so a corrupt file is never left behind. The data is
+/// streamed in chunks and never held in memory as a whole, and the stored file is not read back.
+///
+/// Backends that report no checksum in [`crate::fs::FileMeta::crc32c`], like the local ones, are
+/// only checked by size. S3 verifies uploads itself when `AmazonS3Builder::checksum` is enabled.
+pub async fn put_verified<F, R>(fs: &F, path: &Path, mut reader: R) -> Result<Verified, Error>
+where
+    F: Fs,
+    R: Read,
+{
+    let mut writer = fs
+        .open_options(path, OpenOptions::default().create(true).truncate(true))
+        .await?;
+    let mut expected = Verified { size: 0, crc32c: 0 };
+    let mut buf = Vec::with_capacity(CHUNK_SIZE);
+    while let Some(chunk) = next_chunk(&mut reader, buf, &mut expected).await? {
+        let (result, chunk) = writer.write_all(chunk).await;
+        result?;
+        buf = chunk;
+    }
+    writer.close().await?;
+
+    let stored = fs.stat(path).await?;
+    let actual = stored.crc32c.unwrap_or(expected.crc32c);
+    if stored.size != expected.size || actual != expected.crc32c {
+        fs.remove(path).await?;
+        return Err(Error::ChecksumMismatch {
+            expected: expected.crc32c,
+            actual,
+        });
+    }
+    Ok(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn verifies_upload() {
+        use tempfile::TempDir;
+
+        use super::{put_verified, Verified};
+        use crate::{disk::TokioFs, fs::Fs, path::Path, util::crc32c};
+
+        let dir = TempDir::new().unwrap();
+        let content = (0..3 << 20).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(dir.path().join("local"), &content).unwrap();
+
+        let local = TokioFs
+            .open(&Path::from_absolute_path(dir.path().join("local")).unwrap())
+            .await
+            .unwrap();
+        let remote = Path::from_absolute_path(dir.path().join("remote")).unwrap();
+        let verified = put_verified(&TokioFs, &remote, local).await.unwrap();
+
+        assert_eq!(
+            verified,
+            Verified {
+                size: content.len() as u64,
+                crc32c: crc32c(0, &content),
+            }
+        );
+        assert_eq!(std::fs::read(dir.path().join("remote")).unwrap(), content);
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn removes_corrupt_upload() {
+        use futures_core::Stream;
+        use tempfile::TempDir;
+
+        use super::put_verified;
+        use crate::{
+            disk::TokioFs,
+            fs::{FileMeta, Fs, OpenOptions},
+            path::Path,
+            util::crc32c,
+            Error,
+        };
+
+        // reports the checksum of `corrupt` for every file, as if the stored copy was damaged
+        struct Corrupting {
+            corrupt: &'static [u8],
+        }
+
+        impl Fs for Corrupting {
+            type File = <TokioFs as Fs>::File;
+
+            async fn open_options(
+                &self,
+                path: &Path,
+                options: OpenOptions,
+            ) -> Result<Self::File, Error> {
+                TokioFs.open_options(path, options).await
+            }
+
+            async fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+                TokioFs.create_dir_all(path).await
+            }
+
+            async fn list(
+                &self,
+                path: &Path,
+            ) -> Result<impl Stream<Item = Result<FileMeta, Error>>, Error> {
+                TokioFs.list(path).await
+            }
+
+            async fn remove(&self, path: &Path) -> Result<(), Error> {
+                TokioFs.remove(path).await
+            }
+
+            async fn stat(&self, path: &Path) -> Result<FileMeta, Error> {
+                let mut meta = TokioFs.stat(path).await?;
+                meta.crc32c = Some(crc32c(0, self.corrupt));
+                Ok(meta)
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("local"), b"fusio").unwrap();
+        let local = Path::from_absolute_path(dir.path().join("local")).unwrap();
+        let remote = Path::from_absolute_path(dir.path().join("remote")).unwrap();
+
+        let fs = Corrupting { corrupt: b"fusio" };
+        put_verified(&fs, &remote, TokioFs.open(&local).await.unwrap())
+            .await
+            .unwrap();
+
+        let fs = Corrupting { corrupt: b"fusiO" };
+        let result = put_verified(&fs, &remote, TokioFs.open(&local).await.unwrap()).await;
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+        assert!(!dir.path().join("remote").exists());
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    collections::VecDeque,
+    io,
+    pin::pin,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::StreamExt;
+
+use super::bulk::copy;
+use crate::{
+    fs::{Fs, OpenOptions},
+    path::Path,
+    Error, IoBuf, Write,
+};
+
+const SEGMENT_EXTENSION: &str = "wal";
+
+/// Where [`open_wal`] keeps segments and when it seals them.
+#[derive(Debug, Clone)]
+pub struct WalOptions {
+    pub local_dir: Path,
+    pub remote_dir: Path,
+    /// A segment is sealed once it reaches this many bytes.
+    pub segment_size: u64,
+}
+
+fn segment_name(seq: u64) -> String {
+    format!("{seq:020}.{SEGMENT_EXTENSION}")
+}
+
+fn parse_segment(path: &Path) -> Option<u64> {
+    let (seq, extension) = path.filename()?.split_once('.')?;
+    match extension == SEGMENT_EXTENSION && seq.len() == 20 {
+        true => seq.parse().ok(),
+        false => None,
+    }
+}
+
+fn not_found(err: &Error) -> bool {
+    matches!(err, Error::Io(e) if e.kind() == io::ErrorKind::NotFound)
+}
+
+/// The sequence numbers of the segments in `dir`, none if it does not exist.
+async fn segments<F: Fs>(fs: &F, dir: &Path) -> Result<Vec<u64>, Error> {
+    let listing = match fs.list(dir).await {
+        Err(e) if not_found(&e) => return Ok(Vec::new()),
+        listing => listing?,
+    };
+    let mut listing = pin!(listing);
+    let mut segments = Vec::new();
+    let mut first = true;
+    while let Some(meta) = listing.next().await {
+        // type erased backends report a missing directory as the first item
+        let meta = match meta {
+            Err(e) if first && not_found(&e) => return Ok(Vec::new()),
+            meta => meta?,
+        };
+        first = false;
+        segments.extend(parse_segment(&meta.path));
+    }
+    Ok(segments)
+}
+
+struct Segment<F> {
+    file: F,
+    seq: u64,
+    size: u64,
+}
+
+/// Appends records to segment files on the local [`Fs`], see [`open_wal`].
+pub struct WalWriter<L: Fs> {
+    local: Arc<L>,
+    options: WalOptions,
+    current: Option<Segment<L::File>>,
+    next_seq: u64,
+    sealed: Arc<Mutex<VecDeque<u64>>>,
+}
+
+impl<L: Fs> WalWriter<L> {
+    /// Appends `record` to the current segment, starting a new one if needed, and seals the
+    /// segment once it reaches the configured size.
+    pub async fn append<B: IoBuf>(&mut self, record: B) -> Result<(), Error> {
+        if self.current.is_none() {
+            let seq = self.next_seq;
+            let path = self.options.local_dir.child(segment_name(seq).as_str());
+            let file = self
+                .local
+                .open_options(&path, OpenOptions::default().create(true).truncate(true))
+                .await?;
+            self.next_seq += 1;
+            self.current = Some(Segment { file, seq, size: 0 });
+        }
+        let segment = self.current.as_mut().expect("segment was opened above");
+
+        let len = record.bytes_init() as u64;
+        let (result, _) = segment.file.write_all(record).await;
+        result?;
+        segment.size += len;
+
+        if segment.size >= self.options.segment_size {
+            self.seal().await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the current segment to the local backend.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        match self.current.as_mut() {
+            Some(segment) => segment.file.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Closes the current segment and queues it for upload. The next append starts a new
+    /// segment.
+    pub async fn seal(&mut self) -> Result<(), Error> {
+        if let Some(mut segment) = self.current.take() {
+            segment.file.close().await?;
+            self.sealed.lock().unwrap().push_back(segment.seq);
+        }
+        Ok(())
+    }
+}
+
+/// Uploads sealed segments to the remote [`Fs`] and removes the local copies, see [`open_wal`].
+pub struct WalUploader<L, R> {
+    local: Arc<L>,
+    remote: Arc<R>,
+    options: WalOptions,
+    sealed: Arc<Mutex<VecDeque<u64>>>,
+}
+
+impl<L: Fs, R: Fs> WalUploader<L, R> {
+    /// Segments sealed but not uploaded yet, oldest first.
+    pub fn pending(&self) -> Vec<u64> {
+        self.sealed.lock().unwrap().iter().copied().collect()
+    }
+
+    /// Uploads the sealed segments in order and returns how many were uploaded. A local segment
+    /// is only removed after its upload completed, so a failed upload is retried by the next
+    /// call.
+    pub async fn upload_sealed(&mut self) -> Result<usize, Error> {
+        let mut uploaded = 0;
+        loop {
+            let Some(seq) = self.sealed.lock().unwrap().front().copied() else {
+                return Ok(uploaded);
+            };
+            let local = self.options.local_dir.child(segment_name(seq).as_str());
+            let remote = self.options.remote_dir.child(segment_name(seq).as_str());
+
+            copy(self.local.as_ref(), self.remote.as_ref(), &local, &remote).await?;
+            self.local.remove(&local).await?;
+            self.sealed.lock().unwrap().pop_front();
+            uploaded += 1;
+        }
+    }
+}
+
+/// Opens a write-ahead log that appends to segment files in a local directory for low latency
+/// and archives sealed segments to a remote directory, e.g. on S3.
+///
+/// The [`WalWriter`] appends records and the [`WalUploader`] uploads sealed segments, typically
+/// from a background task. Segments left in the local directory by a previous run are treated as
+/// sealed and uploaded first, new records go to a new segment. Segments are named by a sequence
+/// number, so they sort in the order they were written. New segments continue after the highest
+/// number found locally or remotely, so segments uploaded before a restart are never
+/// overwritten.
+pub async fn open_wal<L: Fs, R: Fs>(
+    local: Arc<L>,
+    remote: Arc<R>,
+    options: WalOptions,
+) -> Result<(WalWriter<L>, WalUploader<L, R>), Error> {
+    local.create_dir_all(&options.local_dir).await?;
+
+    let mut existing = segments(local.as_ref(), &options.local_dir).await?;
+    existing.sort_unstable();
+    let archived = segments(remote.as_ref(), &options.remote_dir).await?;
+
+    let next_seq = existing
+        .iter()
+        .chain(&archived)
+        .max()
+        .map_or(0, |seq| seq + 1);
+    let sealed = Arc::new(Mutex::new(VecDeque::from(existing)));
+
+    Ok((
+        WalWriter {
+            local: local.clone(),
+            options: options.clone(),
+            current: None,
+            next_seq,
+            sealed: sealed.clone(),
+        },
+        WalUploader {
+            local,
+            remote,
+            options,
+            sealed,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn archives_sealed_segments() {
+        use std::sync::Arc;
+
+        use tempfile::TempDir;
+
+        use super::{open_wal, WalOptions};
+        use crate::{disk::TokioFs, path::Path};
+
+        let local = TempDir::new().unwrap();
+        let remote = TempDir::new().unwrap();
+        let options = WalOptions {
+            local_dir: Path::from_absolute_path(local.path().join("wal")).unwrap(),
+            remote_dir: Path::from_absolute_path(remote.path().join("archive")).unwrap(),
+            segment_size: 8,
+        };
+        let segment = |seq: u64| format!("{seq:020}.wal");
+
+        let (mut writer, mut uploader) =
+            open_wal(Arc::new(TokioFs), Arc::new(TokioFs), options.clone())
+                .await
+                .unwrap();
+        writer.append(&b"hello"[..]).await.unwrap();
+        writer.append(&b"world"[..]).await.unwrap();
+        writer.append(&b"!"[..]).await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(uploader.pending(), vec![0]);
+
+        assert_eq!(uploader.upload_sealed().await.unwrap(), 1);
+        assert_eq!(
+            std::fs::read(remote.path().join("archive").join(segment(0))).unwrap(),
+            b"helloworld"
+        );
+        assert!(!local.path().join("wal").join(segment(0)).exists());
+        assert!(local.path().join("wal").join(segment(1)).exists());
+
+        // the unsealed segment of a previous run is archived after a restart
+        drop((writer, uploader));
+        let (mut writer, mut uploader) = open_wal(Arc::new(TokioFs), Arc::new(TokioFs), options)
+            .await
+            .unwrap();
+        assert_eq!(uploader.pending(), vec![1]);
+        writer.append(&b"again"[..]).await.unwrap();
+        writer.seal().await.unwrap();
+
+        assert_eq!(uploader.upload_sealed().await.unwrap(), 2);
+        assert_eq!(
+            std::fs::read(remote.path().join("archive").join(segment(1))).unwrap(),
+            b"!"
+        );
+        assert_eq!(
+            std::fs::read(remote.path().join("archive").join(segment(2))).unwrap(),
+            b"again"
+        );
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "completion-based")))]
+    #[tokio::test]
+    async fn continues_after_archived_segments() {
+        use std::sync::Arc;
+
+        use tempfile::TempDir;
+
+        use super::{open_wal, WalOptions};
+        use crate::{disk::TokioFs, path::Path};
+
+        let local = TempDir::new().unwrap();
+        let remote = TempDir::new().unwrap();
+        let options = WalOptions {
+            local_dir: Path::from_absolute_path(local.path().join("wal")).unwrap(),
+            remote_dir: Path::from_absolute_path(remote.path().join("archive")).unwrap(),
+            segment_size: 4,
+        };
+        let archived = |seq: u64| remote.path().join("archive").join(format!("{seq:020}.wal"));
+
+        let (mut writer, mut uploader) =
+            open_wal(Arc::new(TokioFs), Arc::new(TokioFs), options.clone())
+                .await
+                .unwrap();
+        writer.append(&b"first"[..]).await.unwrap();
+        writer.append(&b"second"[..]).await.unwrap();
+        assert_eq!(uploader.upload_sealed().await.unwrap(), 2);
+        assert_eq!(
+            std::fs::read_dir(local.path().join("wal")).unwrap().count(),
+            0
+        );
+
+        // nothing is left locally, the restart continues after the archived segments
+        drop((writer, uploader));
+        let (mut writer, mut uploader) = open_wal(Arc::new(TokioFs), Arc::new(TokioFs), options)
+            .await
+            .unwrap();
+        writer.append(&b"third"[..]).await.unwrap();
+        assert_eq!(uploader.pending(), vec![2]);
+        assert_eq!(uploader.upload_sealed().await.unwrap(), 1);
+
+        assert_eq!(std::fs::read(archived(0)).unwrap(), b"first");
+        assert_eq!(std::fs::read(archived(1)).unwrap(), b"second");
+        assert_eq!(std::fs::read(archived(2)).unwrap(), b"third");
+    }
+}
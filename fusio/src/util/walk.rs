@@ -0,0 +1,64 @@
+use std::pin::pin;
+
+use futures_util::StreamExt;
+
+use crate::{
+    fs::{FileMeta, Fs},
+    path::Path,
+    Error,
+};
+
+/// Calls `visit` for every file under `prefix`.
+///
+/// Directories are descended into on backends that only list direct children (local), while
+/// backends whose listing is already recursive (object stores) are listed once.
+pub(crate) async fn walk<F, V>(fs: &F, prefix: &Path, mut visit: V) -> Result<(), Error>
+where
+    F: Fs,
+    V: FnMut(FileMeta),
+{
+    walk_entries(fs, prefix, |meta| {
+        if !meta.is_dir() {
+            visit(meta)
+        }
+    })
+    .await
+}
+
+/// Calls `visit` for every file and directory under `prefix` like [`walk`], directories before
+/// the entries below them. Object stores only report directories they list as such, like common
+/// prefixes or `key/` markers.
+pub(crate) async fn walk_entries<F, V>(fs: &F, prefix: &Path, mut visit: V) -> Result<(), Error>
+where
+    F: Fs,
+    V: FnMut(FileMeta),
+{
+    let mut pending = vec![prefix.clone()];
+
+    while let Some(dir) = pending.pop() {
+        let mut listing = pin!(fs.list(&dir).await?);
+        let mut recursive = false;
+        let mut children = Vec::new();
+
+        while let Some(meta) = listing.next().await {
+            let meta = meta?;
+            if meta.path == dir {
+                continue;
+            }
+            recursive |= meta
+                .path
+                .prefix_match(&dir)
+                .is_some_and(|mut parts| parts.nth(1).is_some());
+
+            if meta.is_dir() {
+                children.push(meta.path.clone());
+            }
+            visit(meta);
+        }
+        if !recursive {
+            pending.extend(children);
+        }
+    }
+
+    Ok(())
+}